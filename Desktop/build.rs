@@ -1,4 +1,31 @@
+/// Frontend files the binary embeds: (name, path from this directory)
+const EMBEDDED_ASSETS: &[(&str, &str)] = &[
+    ("index.html", "../Distribution/index.html"),
+    ("emoji.json", "../Library/Shared/emoji.json"),
+];
+
+/// Hash the embedded frontend files into `asset_manifest.rs` for `src/assets.rs`,
+/// which checks the binary's copies against it at startup and derives ETags from it
+fn write_asset_manifest() {
+    use sha2::{Digest, Sha256};
+
+    let mut manifest = String::from("pub const MANIFEST: &[(&str, &str)] = &[\n");
+    for (name, path) in EMBEDDED_ASSETS {
+        println!("cargo:rerun-if-changed={}", path);
+        // Debug builds don't embed the frontend; it may not be built
+        let Ok(bytes) = std::fs::read(path) else { continue };
+        let hash: String = Sha256::digest(&bytes).iter().map(|b| format!("{:02x}", b)).collect();
+        manifest.push_str(&format!("    ({:?}, {:?}),\n", name, hash));
+    }
+    manifest.push_str("];\n");
+
+    let out_dir = std::env::var("OUT_DIR").expect("OUT_DIR is set for build scripts");
+    std::fs::write(std::path::Path::new(&out_dir).join("asset_manifest.rs"), manifest).expect("writing the asset manifest");
+}
+
 fn main() {
+    write_asset_manifest();
+
     // Only run winres on Windows
     #[cfg(windows)]
     {
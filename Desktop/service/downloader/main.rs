@@ -1,13 +1,33 @@
 use std::env;
-use std::fs::File;
+use std::fs::{File, OpenOptions};
 use std::io::{self, Write, BufWriter};
 use std::path::Path;
+use std::sync::OnceLock;
 use std::time::{Instant, Duration};
 use serde_json::json;
 use reqwest;
 use tokio;
 use futures_util::StreamExt;
 
+#[allow(dead_code)]
+#[path = "../../src/logging.rs"]
+mod logging;
+#[allow(dead_code)]
+#[path = "../../src/paths.rs"]
+mod paths;
+
+/// `--download-id`, echoed in every line of output so the app can tell
+/// concurrent downloads apart
+static DOWNLOAD_ID: OnceLock<String> = OnceLock::new();
+
+/// Add the download id to a line of output, when there is one
+fn with_download_id(mut line: serde_json::Value) -> serde_json::Value {
+    if let Some(id) = DOWNLOAD_ID.get() {
+        line["download_id"] = json!(id);
+    }
+    line
+}
+
 #[derive(Debug, Clone)]
 struct DownloadProgress {
     url: String,
@@ -24,7 +44,7 @@ struct DownloadProgress {
 
 impl DownloadProgress {
     fn to_json(&self) -> serde_json::Value {
-        json!({
+        with_download_id(json!({
             "url": self.url,
             "filename": self.filename,
             "total_size": self.total_size,
@@ -43,7 +63,7 @@ impl DownloadProgress {
             "eta_human": self.eta_seconds.map(|s| format_duration(s)),
             "status": self.status,
             "error": self.error
-        })
+        }))
     }
 
     fn print_json(&self) {
@@ -80,7 +100,7 @@ fn format_duration(seconds: u64) -> String {
     }
 }
 
-async fn download_file_multiconnection(url: &str, output_path: &str, headers: Vec<(String, String)>) -> Result<(), Box<dyn std::error::Error>> {
+async fn download_file_multiconnection(url: &str, output_path: &str, headers: Vec<(String, String)>, resume: bool) -> Result<(), Box<dyn std::error::Error>> {
     // Determine the actual output file path
     let final_output_path = if output_path.ends_with('/') || output_path.ends_with('\\') || output_path == "." || output_path == "./" {
         let url_filename = url.split('/').last().unwrap_or("downloaded_file");
@@ -99,6 +119,13 @@ async fn download_file_multiconnection(url: &str, output_path: &str, headers: Ve
         .to_string_lossy()
         .to_string();
 
+    // Continue a partial file left by an earlier attempt
+    let existing = if resume {
+        std::fs::metadata(&final_output_path).map(|m| m.len()).unwrap_or(0)
+    } else {
+        0
+    };
+
     // Skip HEAD request to avoid 405 errors with some servers
     // Use GET request directly and check headers from the response
     let client = reqwest::Client::new();
@@ -108,13 +135,28 @@ async fn download_file_multiconnection(url: &str, output_path: &str, headers: Ve
     for (key, value) in &headers {
         request = request.header(key, value);
     }
+    if existing > 0 {
+        request = request.header(reqwest::header::RANGE, format!("bytes={}-", existing));
+    }
     let response = request.send().await?;
+
+    // The partial file already holds everything the server has
+    if existing > 0 && response.status() == reqwest::StatusCode::RANGE_NOT_SATISFIABLE {
+        tracing::info!("Nothing left to resume, {} bytes already present", existing);
+        return Ok(());
+    }
     
     if !response.status().is_success() {
         return Err(format!("HTTP error: {}", response.status()).into());
     }
 
-    let total_size = response.content_length().unwrap_or(0);
+    // Servers that ignore Range answer 200 with the whole file
+    let resumed_from = if response.status() == reqwest::StatusCode::PARTIAL_CONTENT { existing } else { 0 };
+    if resumed_from > 0 {
+        tracing::info!("Resuming download at byte {}", resumed_from);
+    }
+
+    let total_size = response.content_length().map(|len| len + resumed_from).unwrap_or(0);
 
     // For simplicity, always use single connection to avoid range request issues
     let connections = 1;
@@ -123,7 +165,7 @@ async fn download_file_multiconnection(url: &str, output_path: &str, headers: Ve
         url: url.to_string(),
         filename: filename.clone(),
         total_size,
-        downloaded: 0,
+        downloaded: resumed_from,
         chunk_size: 0,
         download_speed_bps: 0.0,
         eta_seconds: None,
@@ -148,11 +190,15 @@ async fn download_file_single_with_response(
     progress.status = "connecting".to_string();
     progress.broadcast();
 
-    progress.total_size = response.content_length().unwrap_or(0);
+    progress.total_size = response.content_length().map(|len| len + progress.downloaded).unwrap_or(0);
     progress.status = "downloading".to_string();
     progress.broadcast();
 
-    let file = File::create(output_path)?;
+    let file = if progress.downloaded > 0 {
+        OpenOptions::new().append(true).open(output_path)?
+    } else {
+        File::create(output_path)?
+    };
     let mut writer = BufWriter::new(file);
     
     let mut stream = response.bytes_stream();
@@ -205,9 +251,11 @@ async fn download_file_single_with_response(
 // Update main function to use multi-connection download
 #[tokio::main]
 async fn main() {
+    // stdout carries the JSON progress protocol, so diagnostics only go to the log file
+    let log_guard = logging::init("downloader");
     let args: Vec<String> = env::args().collect();
 
-    // Parse arguments: URL OUTPUT_PATH [-H "Header: Value"]...
+    // Parse arguments: URL OUTPUT_PATH [-H "Header: Value"]... [--resume] [--download-id ID]
     if args.len() < 3 {
         print_usage();
         std::process::exit(1);
@@ -216,10 +264,25 @@ async fn main() {
     let mut url: Option<String> = None;
     let mut output_path: Option<String> = None;
     let mut headers: Vec<(String, String)> = Vec::new();
+    let mut resume = false;
     
     let mut i = 1;
     while i < args.len() {
-        if args[i] == "-H" || args[i] == "--header" {
+        if args[i] == "--resume" {
+            resume = true;
+            i += 1;
+        } else if args[i] == "--download-id" {
+            match args.get(i + 1) {
+                Some(id) => {
+                    let _ = DOWNLOAD_ID.set(id.clone());
+                    i += 2;
+                }
+                None => {
+                    print_error("Missing id after --download-id");
+                    std::process::exit(1);
+                }
+            }
+        } else if args[i] == "-H" || args[i] == "--header" {
             if i + 1 < args.len() {
                 let header_str = &args[i + 1];
                 if let Some(colon_pos) = header_str.find(':') {
@@ -284,18 +347,29 @@ async fn main() {
         }
     }
 
-    match download_file_multiconnection(&url, &output_path, headers).await {
+    tracing::info!(
+        url = %logging::redact_url(&url),
+        output = %final_output_path,
+        headers = ?headers.iter().map(|(k, v)| format!("{}: {}", k, logging::redact_header(k, v))).collect::<Vec<_>>(),
+        "Starting download"
+    );
+    let started = Instant::now();
+
+    match download_file_multiconnection(&url, &output_path, headers, resume).await {
         Ok(()) => {
-            let success = json!({
+            tracing::info!(elapsed_ms = started.elapsed().as_millis() as u64, "Download completed: {}", final_output_path);
+            drop(log_guard);
+            let success = with_download_id(json!({
                 "status": "success",
                 "message": "Download completed successfully",
                 "output_path": final_output_path
-            });
+            }));
             println!("{}", success);
             std::process::exit(0);
         }
         Err(e) => {
             print_error(&format!("Download failed: {}", e));
+            drop(log_guard);
             std::process::exit(1);
         }
     }
@@ -304,16 +378,17 @@ async fn main() {
 fn print_usage() {
     let usage = json!({
         "status": "error",
-        "error": "Usage: downloaderservice.exe <URL> <OUTPUT_PATH> [-H \"Header: Value\"]",
+        "error": "Usage: downloaderservice.exe <URL> <OUTPUT_PATH> [-H \"Header: Value\"] [--resume] [--download-id ID]",
         "example": "downloaderservice.exe https://example.com/file.zip ./downloads/file.zip -H \"Authorization: Bearer token123\""
     });
     println!("{}", usage);
 }
 
 fn print_error(message: &str) {
-    let error = json!({
+    tracing::error!("{}", message);
+    let error = with_download_id(json!({
         "status": "error",
         "error": message
-    });
+    }));
     println!("{}", error);
 }
@@ -0,0 +1,53 @@
+//! High contrast mode.
+//!
+//! The dark window frame and menu colors the app applies on Windows become
+//! unreadable under a contrast theme, so they are left to the system while
+//! one is on. Switching themes while the app runs re-themes the window (via
+//! [`take_window_update`]) and tells the page, which has its own styles for
+//! it, with `high-contrast-changed {enabled}`.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use lazy_static::lazy_static;
+use serde_json::json;
+use tracing::info;
+
+lazy_static! {
+    static ref HIGH_CONTRAST: AtomicBool = AtomicBool::new(query());
+}
+
+/// Set when high contrast was switched; the event loop takes it to re-theme
+static WINDOW_DIRTY: AtomicBool = AtomicBool::new(false);
+
+fn query() -> bool {
+    #[cfg(target_os = "windows")]
+    {
+        crate::platform::win::utils::high_contrast()
+    }
+    #[cfg(not(target_os = "windows"))]
+    {
+        false
+    }
+}
+
+pub fn high_contrast() -> bool {
+    HIGH_CONTRAST.load(Ordering::SeqCst)
+}
+
+/// The system's accessibility settings changed; check high contrast again
+pub fn settings_changed() {
+    let enabled = query();
+    if HIGH_CONTRAST.swap(enabled, Ordering::SeqCst) == enabled {
+        return;
+    }
+    info!("High contrast turned {}", if enabled { "on" } else { "off" });
+    WINDOW_DIRTY.store(true, Ordering::SeqCst);
+    if let Some(webview) = crate::ipc::handle() {
+        webview.emit("high-contrast-changed", &json!({ "enabled": enabled }));
+    }
+    crate::ipc::wake();
+}
+
+/// The new high contrast state, if the window needs re-theming
+pub fn take_window_update() -> Option<bool> {
+    WINDOW_DIRTY.swap(false, Ordering::SeqCst).then(high_contrast)
+}
@@ -0,0 +1,648 @@
+//! What the page can ask of the app: every IPC action, handled the same way
+//! on each platform.
+//!
+//! Messages get here through [`dispatch`] once [`crate::ipc::parse_message`]
+//! has checked them. Where an action needs the OS (dialogs, the downloader,
+//! the window, the spell checker) it goes through the [`Platform`] the
+//! platform layer passes in. Answers go out with [`crate::ipc::respond`];
+//! anything that may block runs on a thread of its own so the page's next
+//! message isn't held up.
+
+use std::path::PathBuf;
+use serde_json::{json, Value};
+use tracing::warn;
+
+use crate::core::downloads::{DownloadTarget, FileDrag};
+use crate::ipc::{respond, respond_after};
+use crate::print::PrintJob;
+
+/// Run a download as job `id` (called on a thread of its own)
+pub type StartDownload = fn(id: String, url: String, filename: String, headers: Vec<(String, String)>, target: DownloadTarget);
+
+/// The platform's side of the actions
+pub struct Platform {
+    pub start_download: StartDownload,
+    pub fetch_attachment: crate::attachments::Fetch,
+    /// Reveal a downloaded file (`path`, or a legacy `filename`) in the file manager
+    pub show_in_folder: fn(reference: &str),
+    /// Folder picker titled `title`; `None` when cancelled
+    pub choose_folder: fn(title: &str) -> Option<PathBuf>,
+    /// Save dialog for a PDF, suggesting `default_name`; `None` when cancelled
+    pub choose_pdf_path: fn(default_name: &str) -> Option<PathBuf>,
+    /// Start dragging a downloaded file out of the window
+    pub begin_file_drag: fn(FileDrag),
+    /// Show a downloaded file in the preview window (or its app)
+    pub preview_file: fn(PathBuf),
+    /// Handle `show_context_menu`, where there is a native one
+    pub show_context_menu: Option<fn(message: &Value)>,
+    /// Handle `snap_window`; answers `window-snapped`
+    pub snap_window: fn(preset: String, request_id: Option<String>),
+    /// Handle `save_window_layout`; answers `window-layout-saved`
+    pub save_window_layout: fn(name: Option<String>, request_id: Option<String>),
+    pub print: fn(PrintJob),
+    pub autostart_status: fn() -> Result<bool, String>,
+    pub set_autostart: fn(enabled: bool) -> Result<(), String>,
+    /// Ask where to save the diagnostics bundle and write it; the `logs-exported` answer
+    pub export_diagnostics: fn() -> Value,
+    /// Ask where to save the thread and export it; the `export-finished` answer
+    pub export_thread: fn(crate::export::ExportRequest) -> Value,
+    pub learn_words: crate::spellcheck::LearnWords,
+    /// The spell checker may only be used on the thread IPC messages arrive
+    /// on (NSSpellChecker belongs to the main thread)
+    pub spellcheck_on_ipc_thread: bool,
+    /// Spellcheck languages take effect at the next start (WebView2 reads
+    /// them when it starts); otherwise never, the engine following the system
+    pub languages_at_restart: bool,
+    /// Download and start the update installer; the `update-installed` answer
+    pub install_update: fn() -> Value,
+    /// Ask for screen recording permission, where the OS has a prompt for it
+    pub request_screen_capture: Option<fn()>,
+}
+
+/// Handle one message from the page
+pub fn dispatch(message: &Value, platform: &'static Platform) {
+    let Some(action) = message["type"].as_str() else { return };
+    match action {
+        "start_download" => {
+            if let (Some(url), Some(filename)) = (message["url"].as_str(), message["filename"].as_str()) {
+                let mut headers = Vec::new();
+                if let Some(h) = message["headers"].as_object() {
+                    for (k, v) in h { if let Some(vs) = v.as_str() { headers.push((k.clone(), vs.to_string())); } }
+                }
+                let (u, f) = (crate::protocol::absolute_url(url), filename.to_string());
+                let target = DownloadTarget::from_message(message);
+                // Progress events, transfer controls and toasts all go by this id
+                let id = crate::core::downloads::new_job_id();
+                respond(message["requestId"].as_str(), "download-accepted", &json!({ "success": true, "downloadId": id }));
+                std::thread::spawn(move || (platform.start_download)(id, u, f, headers, target));
+            }
+        }
+        "download_attachments" => {
+            let message = message.clone();
+            // May ask about a metered connection first
+            std::thread::spawn(move || {
+                let result = match crate::attachments::start(&message, platform.fetch_attachment) {
+                    Ok(id) => json!({ "success": true, "downloadId": id }),
+                    Err(e) => json!({ "success": false, "error": e }),
+                };
+                respond(message["requestId"].as_str(), "attachments-accepted", &result);
+            });
+        }
+        "show_in_folder" => {
+            // `path` (from download history) is preferred; `filename` is the legacy form
+            if let Some(reference) = message["path"].as_str().or_else(|| message["filename"].as_str()) {
+                let reference = reference.to_string();
+                std::thread::spawn(move || (platform.show_in_folder)(&reference));
+            }
+        }
+        "choose_download_directory" => {
+            let request_id = message["requestId"].as_str().map(|s| s.to_string());
+            std::thread::spawn(move || {
+                let result = match (platform.choose_folder)("Choose a download folder") {
+                    Some(dir) => download_directory_result(crate::core::downloads::set_download_dir(Some(dir))),
+                    None => json!({ "success": false, "cancelled": true }),
+                };
+                respond(request_id.as_deref(), "download-directory-changed", &result);
+            });
+        }
+        "set_download_directory" => {
+            // `path: null` goes back to the system Downloads folder
+            let request_id = message["requestId"].as_str().map(|s| s.to_string());
+            let dir = message["path"].as_str().filter(|s| !s.is_empty()).map(PathBuf::from);
+            std::thread::spawn(move || {
+                let result = download_directory_result(crate::core::downloads::set_download_dir(dir));
+                respond(request_id.as_deref(), "download-directory-changed", &result);
+            });
+        }
+        "begin_file_drag" => {
+            // Sent from the page's dragstart handler, which cancels the HTML drag
+            match FileDrag::from_message(message) {
+                Ok(request) => (platform.begin_file_drag)(request),
+                Err(e) => {
+                    warn!("Refusing file drag: {}", e);
+                    let result = json!({ "success": false, "error": e });
+                    respond(message["requestId"].as_str(), "file-drag-finished", &result);
+                }
+            }
+        }
+        "preview_file" => match crate::preview::resolve(message) {
+            Ok(path) => (platform.preview_file)(path),
+            Err(e) => warn!("Refusing preview: {}", e),
+        },
+        "show_notification" => {
+            if let Ok(noti_data) = <crate::hooks::noti::NotificationData as serde::Deserialize>::deserialize(message) {
+                std::thread::spawn(move || { let _ = crate::hooks::show_notification(noti_data); });
+            }
+        }
+        "list_notification_sounds" => {
+            respond(message["requestId"].as_str(), "notification-sounds", &crate::notification_sounds::list());
+        }
+        "get_quiet_hours" => {
+            respond(message["requestId"].as_str(), "quiet-hours", &crate::quiet_hours::snapshot());
+        }
+        "set_quiet_hours" => {
+            let result = match crate::quiet_hours::set(message) {
+                Ok(hours) => json!({ "success": true, "quietHours": hours }),
+                Err(e) => json!({ "success": false, "error": e }),
+            };
+            respond(message["requestId"].as_str(), "quiet-hours-changed", &result);
+        }
+        "preview_notification_sound" | "set_notification_sound" => {
+            let name = message["name"].as_str().unwrap_or_default();
+            let (result, event) = if action == "set_notification_sound" {
+                (crate::notification_sounds::set(name), "notification-sound-changed")
+            } else {
+                (crate::notification_sounds::preview(name), "notification-sound-previewed")
+            };
+            let result = match result {
+                Ok(()) => json!({ "success": true, "sounds": crate::notification_sounds::list() }),
+                Err(e) => json!({ "success": false, "error": e }),
+            };
+            respond(message["requestId"].as_str(), event, &result);
+        }
+        "set_log_level" => {
+            if let Some(level) = message["level"].as_str() {
+                match crate::logging::set_level(level) {
+                    Ok(()) => {
+                        let level = level.to_string();
+                        if let Err(e) = crate::core::settings::update(|s| s.log_level = Some(level)) {
+                            warn!("Failed to save log level: {}", e);
+                        }
+                    }
+                    Err(e) => warn!("Failed to change log level: {}", e),
+                }
+            }
+        }
+        "subscribe" => {
+            if let Some(topic) = message["topic"].as_str() {
+                if let Err(e) = crate::core::sync::subscribe(topic) {
+                    warn!("{}", e);
+                }
+            }
+        }
+        "unsubscribe" => {
+            if let Some(topic) = message["topic"].as_str() {
+                crate::core::sync::unsubscribe(topic);
+            }
+        }
+        "sync_reset" => {
+            crate::core::sync::reset();
+            crate::renderer::page_loaded();
+        }
+        "save_draft" => {
+            if let Some(thread_id) = message["threadId"].as_str() {
+                if let Err(e) = crate::core::drafts::save(thread_id, message["text"].as_str().unwrap_or("")) {
+                    warn!("Failed to save draft: {}", e);
+                }
+            }
+        }
+        "get_drafts" => {
+            respond(message["requestId"].as_str(), "drafts", &crate::core::drafts::snapshot());
+        }
+        "get_shortcuts" => {
+            respond(message["requestId"].as_str(), "shortcuts", &crate::shortcuts::snapshot());
+        }
+        "set_shortcut" => {
+            let action = message["action"].as_str().unwrap_or_default();
+            // null restores the default, "" unbinds
+            let result = match crate::shortcuts::set(action, message["accel"].as_str()) {
+                Ok(shortcuts) => json!({ "success": true, "action": action, "shortcuts": shortcuts }),
+                Err(e) => json!({ "success": false, "action": action, "error": e }),
+            };
+            respond(message["requestId"].as_str(), "shortcut-changed", &result);
+        }
+        "auth_changed" => {
+            crate::core::state::set_auth(crate::core::state::AuthSnapshot {
+                signed_in: message["signedIn"].as_bool().unwrap_or(false),
+                user_id: message["userId"].as_str().map(|s| s.to_string()),
+                user_name: message["userName"].as_str().map(|s| s.to_string()),
+                api_base: message["baseUrl"].as_str().map(|s| s.to_string()),
+                token: message["token"].as_str().map(|s| s.to_string()),
+            });
+        }
+        "set_presence" => {
+            // `status: null` goes back to automatic away/active
+            crate::presence::set_manual(message["status"].as_str());
+        }
+        "send_ephemeral" => {
+            let kind = message["kind"].as_str().unwrap_or_default();
+            let thread_id = message["threadId"].as_str().unwrap_or_default();
+            if let Err(e) = crate::ephemeral::send(kind, thread_id, message["payload"].clone()) {
+                warn!("{}", e);
+            }
+        }
+        "watch_presence" => crate::thread_presence::watch(&thread_ids(message)),
+        "socket_event" => crate::thread_presence::socket_event(&message["data"]),
+        "socket_state" => crate::thread_presence::socket_state(message["connected"].as_bool().unwrap_or(false)),
+        "set_session_state" => {
+            let thread_id = message["threadId"].as_str().unwrap_or_default();
+            let result = if thread_id.is_empty() {
+                Err("threadId is required".to_string())
+            } else {
+                crate::core::session_state::save(thread_id, message["scrollAnchor"].as_str())
+            };
+            if let Err(e) = result {
+                warn!("{}", e);
+            }
+        }
+        "clear_session_state" => crate::core::session_state::clear(None),
+        "mark_read" => {
+            if let Some(thread_id) = message["threadId"].as_str() {
+                crate::core::unread::mark_read(thread_id);
+            }
+        }
+        "mark_threads_read" => {
+            let request_id = message["requestId"].as_str().map(|s| s.to_string());
+            let thread_ids = thread_ids(message);
+            std::thread::spawn(move || {
+                let (result, upstream) = crate::mark_read::mark(&thread_ids);
+                respond_after(request_id.as_deref(), "threads-marked-read", &result, upstream);
+            });
+        }
+        "set_visible_threads" => crate::mark_read::set_visible(thread_ids(message)),
+        "format_datetime" | "format_datetimes" | "format_number" => {
+            let request_id = message["requestId"].as_str().map(|s| s.to_string());
+            let format: fn(&Value) -> Value = match action {
+                "format_datetime" => crate::formatting::format_datetime,
+                "format_datetimes" => crate::formatting::format_datetimes,
+                _ => crate::formatting::format_number,
+            };
+            let message = message.clone();
+            std::thread::spawn(move || {
+                respond(request_id.as_deref(), "formatted", &format(&message));
+            });
+        }
+        "set_active_thread" => crate::core::unread::set_active_thread(message["threadId"].as_str()),
+        "message_received" => {
+            if let Some(thread_id) = message["threadId"].as_str() {
+                crate::core::unread::message_received(thread_id);
+            }
+        }
+        "thread_deleted" => {
+            if let Some(thread_id) = message["threadId"].as_str() {
+                crate::core::unread::thread_deleted(thread_id);
+            }
+        }
+        "log_event" => crate::console::log_event(message),
+        "get_recent_logs" => {
+            let source = message["source"].as_str().unwrap_or("desktop").to_string();
+            let lines = message["lines"].as_u64();
+            let request_id = message["requestId"].as_str().map(|s| s.to_string());
+            std::thread::spawn(move || {
+                let result = match crate::diagnostics::recent_logs(&source, lines) {
+                    Ok(lines) => json!({ "success": true, "source": source, "lines": lines }),
+                    Err(e) => json!({ "success": false, "source": source, "error": e }),
+                };
+                respond(request_id.as_deref(), "recent-logs", &result);
+            });
+        }
+        "get_autostart" => {
+            let request_id = message["requestId"].as_str().map(|s| s.to_string());
+            std::thread::spawn(move || {
+                respond(request_id.as_deref(), "autostart", &crate::autostart::result((platform.autostart_status)()));
+            });
+        }
+        "set_autostart" => {
+            let request_id = message["requestId"].as_str().map(|s| s.to_string());
+            let enabled = message["enabled"].as_bool().unwrap_or(false);
+            std::thread::spawn(move || {
+                let result = crate::autostart::apply(enabled, platform.set_autostart, platform.autostart_status);
+                respond(request_id.as_deref(), "autostart", &result);
+            });
+        }
+        "get_system_info" => {
+            let request_id = message["requestId"].as_str().map(|s| s.to_string());
+            std::thread::spawn(move || {
+                respond(request_id.as_deref(), "system-info", &crate::diagnostics::system_info());
+            });
+        }
+        "export_logs" => {
+            let request_id = message["requestId"].as_str().map(|s| s.to_string());
+            std::thread::spawn(move || {
+                respond(request_id.as_deref(), "logs-exported", &(platform.export_diagnostics)());
+            });
+        }
+        "set_spellcheck" => {
+            let request_id = message["requestId"].as_str().map(|s| s.to_string());
+            let enabled = message["enabled"].as_bool();
+            let languages: Option<Vec<String>> = message["languages"]
+                .as_array()
+                .map(|tags| tags.iter().filter_map(|t| t.as_str().map(|s| s.to_string())).collect());
+            let languages_changed = languages.as_ref().is_some_and(|l| *l != crate::spellcheck::current().languages);
+            with_spellchecker(platform, move || {
+                let result = match crate::spellcheck::set(enabled, languages, platform.learn_words) {
+                    Ok(spellcheck) if platform.languages_at_restart => {
+                        json!({ "success": true, "spellcheck": spellcheck, "restartRequired": languages_changed })
+                    }
+                    // The languages are only remembered
+                    Ok(spellcheck) => json!({ "success": true, "spellcheck": spellcheck, "languagesApplied": false }),
+                    Err(e) => json!({ "success": false, "error": e }),
+                };
+                respond(request_id.as_deref(), "spellcheck-changed", &result);
+            });
+        }
+        #[cfg(any(target_os = "windows", target_os = "macos"))]
+        "start_audio_recording" => {
+            let request_id = message["requestId"].as_str().map(|s| s.to_string());
+            std::thread::spawn(move || {
+                respond(request_id.as_deref(), "recording-started", &crate::recording::start());
+            });
+        }
+        #[cfg(any(target_os = "windows", target_os = "macos"))]
+        "stop_audio_recording" => {
+            let request_id = message["requestId"].as_str().map(|s| s.to_string());
+            std::thread::spawn(move || {
+                respond(request_id.as_deref(), "recording-stopped", &crate::recording::stop());
+            });
+        }
+        "set_block_capture" => {
+            let result = match message["enabled"].as_bool() {
+                Some(enabled) => match crate::privacy::set_block_capture(enabled) {
+                    Ok(blocked) => json!({ "success": true, "blockCapture": blocked }),
+                    Err(e) => json!({ "success": false, "error": e }),
+                },
+                None => json!({ "success": false, "error": "enabled must be a boolean" }),
+            };
+            respond(message["requestId"].as_str(), "privacy-changed", &result);
+        }
+        "add_dictionary_word" => {
+            let request_id = message["requestId"].as_str().map(|s| s.to_string());
+            let word = message["word"].as_str().unwrap_or_default().to_string();
+            with_spellchecker(platform, move || {
+                let result = match crate::spellcheck::add_word(&word, platform.learn_words) {
+                    Ok(()) => json!({ "success": true, "word": word }),
+                    Err(e) => json!({ "success": false, "word": word, "error": e }),
+                };
+                respond(request_id.as_deref(), "dictionary-word-added", &result);
+            });
+        }
+        "show_context_menu" => {
+            if let Some(show_context_menu) = platform.show_context_menu {
+                show_context_menu(message);
+            }
+        }
+        "snap_window" => {
+            let preset = message["preset"].as_str().unwrap_or_default().to_string();
+            (platform.snap_window)(preset, message["requestId"].as_str().map(|s| s.to_string()));
+        }
+        "save_window_layout" => {
+            let name = message["name"].as_str().map(|s| s.to_string());
+            (platform.save_window_layout)(name, message["requestId"].as_str().map(|s| s.to_string()));
+        }
+        "list_window_layouts" => {
+            respond(message["requestId"].as_str(), "window-layouts", &crate::window_layout::list());
+        }
+        "print_page" => (platform.print)(PrintJob::Dialog),
+        "print_to_pdf" => {
+            let request_id = message["requestId"].as_str().map(|s| s.to_string());
+            match crate::print::save_path(message) {
+                Ok(save_path) => {
+                    std::thread::spawn(move || {
+                        let Some(path) = save_path.or_else(|| (platform.choose_pdf_path)(&crate::print::default_pdf_name())) else {
+                            let result = json!({ "success": false, "cancelled": true });
+                            crate::print::pdf_rejected(request_id.as_deref(), result);
+                            return;
+                        };
+                        (platform.print)(PrintJob::Pdf { path, request_id });
+                    });
+                }
+                Err(e) => {
+                    let result = json!({ "success": false, "error": e });
+                    crate::print::pdf_rejected(request_id.as_deref(), result);
+                }
+            }
+        }
+        "export_thread" => {
+            let request_id = message["requestId"].as_str().map(|s| s.to_string());
+            let format = message["format"].as_str().and_then(crate::export::ExportFormat::parse);
+            match (message["threadId"].as_str(), format) {
+                (Some(thread_id), Some(format)) => {
+                    let request = crate::export::ExportRequest::new(thread_id.to_string(), format);
+                    std::thread::spawn(move || {
+                        respond(request_id.as_deref(), "export-finished", &(platform.export_thread)(request));
+                    });
+                }
+                _ => {
+                    let result = json!({ "success": false, "error": "export_thread needs threadId and format (json or csv)" });
+                    respond(request_id.as_deref(), "export-finished", &result);
+                }
+            }
+        }
+        "clear_thread_history" => {
+            let request_id = message["requestId"].as_str().map(|s| s.to_string());
+            match message["threadId"].as_str().map(|s| s.to_string()) {
+                Some(thread_id) => {
+                    std::thread::spawn(move || {
+                        let result = crate::history::clear_thread(&thread_id);
+                        respond(request_id.as_deref(), "clear-history-finished", &result);
+                    });
+                }
+                None => {
+                    let result = json!({ "success": false, "error": "clear_thread_history needs a threadId" });
+                    respond(request_id.as_deref(), "clear-history-finished", &result);
+                }
+            }
+        }
+        "cancel_history_clear" => {
+            if let Some(thread_id) = message["threadId"].as_str() {
+                if !crate::history::cancel(thread_id) {
+                    warn!("No history clear running for thread {}", thread_id);
+                }
+            }
+        }
+        "search_emoji" => {
+            let query = message["query"].as_str().unwrap_or_default();
+            respond(message["requestId"].as_str(), "emoji-results", &crate::emoji::search(query));
+        }
+        "clear_download_history" => crate::core::downloads::clear_history(),
+        "get_inflight_requests" => {
+            respond(message["requestId"].as_str(), "inflight-requests", &crate::inflight::list());
+        }
+        "cancel_inflight_request" => {
+            let result = match message["id"].as_u64() {
+                Some(id) => crate::inflight::cancel(id),
+                None => json!({ "success": false, "error": "id must be a number" }),
+            };
+            respond(message["requestId"].as_str(), "inflight-cancelled", &result);
+        }
+        "get_prefetch_stats" => {
+            respond(message["requestId"].as_str(), "prefetch-stats", &crate::prefetch::stats());
+        }
+        "cancel_prefetch" => crate::prefetch::cancel(),
+        "run_cleanup" => {
+            let request_id = message["requestId"].as_str().map(|s| s.to_string());
+            std::thread::spawn(move || {
+                respond(request_id.as_deref(), "cleanup-finished", &crate::maintenance::run());
+            });
+        }
+        "get_recordings" => {
+            respond(message["requestId"].as_str(), "recordings", &crate::recordings::list());
+        }
+        "replay_recording" => {
+            let request_id = message["requestId"].as_str().map(|s| s.to_string());
+            match message["id"].as_u64() {
+                // Waits for the server
+                Some(id) => {
+                    std::thread::spawn(move || {
+                        respond(request_id.as_deref(), "recording-replayed", &crate::recordings::replay(id));
+                    });
+                }
+                None => {
+                    let result = json!({ "success": false, "error": "id must be a number" });
+                    respond(request_id.as_deref(), "recording-replayed", &result);
+                }
+            }
+        }
+        "get_history_clears" => {
+            respond(message["requestId"].as_str(), "history-clears", &crate::history::unfinished());
+        }
+        "cancel_export" => {
+            if let Some(thread_id) = message["threadId"].as_str() {
+                if !crate::export::cancel(thread_id) {
+                    warn!("No export running for thread {}", thread_id);
+                }
+            }
+        }
+        "upload_file" => {
+            let request_id = message["requestId"].as_str().map(|s| s.to_string());
+            match (message["threadId"].as_str(), message["path"].as_str()) {
+                (Some(thread_id), Some(path)) => {
+                    let request = crate::upload::UploadRequest::new(
+                        message["id"].as_str().map(|s| s.to_string()).unwrap_or_else(crate::upload::new_upload_id),
+                        thread_id.to_string(),
+                        PathBuf::from(path),
+                    );
+                    std::thread::spawn(move || {
+                        let result = crate::upload::upload(&request);
+                        respond(request_id.as_deref(), "upload-finished", &result);
+                    });
+                }
+                _ => {
+                    let result = json!({ "success": false, "error": "upload_file needs threadId and path" });
+                    respond(request_id.as_deref(), "upload-finished", &result);
+                }
+            }
+        }
+        "paste" => {
+            let request_id = message["requestId"].as_str().map(|s| s.to_string());
+            // Encoding a large screenshot takes a moment
+            std::thread::spawn(move || {
+                respond(request_id.as_deref(), "clipboard-paste", &crate::clipboard::paste());
+            });
+        }
+        "stage_media" => {
+            let request_id = message["requestId"].as_str().map(|s| s.to_string());
+            let message = message.clone();
+            std::thread::spawn(move || {
+                let result = match crate::temp_media::stage(&message) {
+                    Ok(mut media) => {
+                        media["success"] = true.into();
+                        media
+                    }
+                    Err(e) => json!({ "success": false, "error": e }),
+                };
+                respond(request_id.as_deref(), "media-staged", &result);
+            });
+        }
+        "check_permissions" => {
+            respond(message["requestId"].as_str(), "permissions", &crate::permissions::check());
+        }
+        "request_permission" => {
+            let request_id = message["requestId"].as_str().map(|s| s.to_string());
+            let kind = message["kind"].as_str().unwrap_or_default().to_string();
+            // Waits for the user to answer the prompt
+            std::thread::spawn(move || {
+                let result = match crate::permissions::request(&kind) {
+                    Ok(mut result) => {
+                        result["success"] = true.into();
+                        result
+                    }
+                    Err(e) => json!({ "success": false, "kind": kind, "error": e }),
+                };
+                respond(request_id.as_deref(), "permission-result", &result);
+            });
+        }
+        "release_media" => {
+            let id = message["id"].as_str().unwrap_or_default();
+            let released = crate::temp_media::release(id);
+            respond(message["requestId"].as_str(), "media-released", &json!({ "id": id, "released": released }));
+        }
+        "cancel_upload" => {
+            if let Some(id) = message["id"].as_str() {
+                // A stopped chunked upload is dropped instead
+                if !crate::upload::cancel(id) && !crate::upload::discard(id) {
+                    warn!("No upload running with id {}", id);
+                }
+            }
+        }
+        "resume_upload" => {
+            let request_id = message["requestId"].as_str().map(|s| s.to_string());
+            let id = message["id"].as_str().unwrap_or_default();
+            if let Err(e) = crate::upload::resume(id, request_id.clone()) {
+                let result = json!({ "success": false, "id": id, "error": e });
+                respond(request_id.as_deref(), "upload-finished", &result);
+            }
+        }
+        "pause_transfer" | "resume_transfer" | "cancel_transfer" | "retry_transfer" => {
+            let result = crate::core::transfers::control(action, message["id"].as_str().unwrap_or_default());
+            respond(message["requestId"].as_str(), "transfer-controlled", &result);
+        }
+        "check_updates" => {
+            let request_id = message["requestId"].as_str().map(|s| s.to_string());
+            std::thread::spawn(move || {
+                let result = match crate::updates::check() {
+                    Ok(manifest) => json!({ "success": true, "update": manifest }),
+                    Err(e) => json!({ "success": false, "error": e }),
+                };
+                respond(request_id.as_deref(), "update-checked", &result);
+            });
+        }
+        "download_update" => {
+            let request_id = message["requestId"].as_str().map(|s| s.to_string());
+            std::thread::spawn(move || {
+                respond(request_id.as_deref(), "update-installed", &(platform.install_update)());
+            });
+        }
+        "screen_capture_access" => {
+            if let Some(request) = platform.request_screen_capture {
+                if crate::media::screen_share_enabled() {
+                    std::thread::spawn(request);
+                }
+            }
+        }
+        "remind_update_later" => {
+            if let Some(version) = message["version"].as_str() {
+                crate::updates::remind_later(version);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// The string ids in `threadIds`
+fn thread_ids(message: &Value) -> Vec<String> {
+    message["threadIds"]
+        .as_array()
+        .map(|ids| ids.iter().filter_map(|id| id.as_str().map(|s| s.to_string())).collect())
+        .unwrap_or_default()
+}
+
+/// Run `work` where the platform's spell checker may be used: right away,
+/// or on a thread of its own since adding words can take a moment
+fn with_spellchecker(platform: &Platform, work: impl FnOnce() + Send + 'static) {
+    if platform.spellcheck_on_ipc_thread {
+        work();
+    } else {
+        std::thread::spawn(work);
+    }
+}
+
+fn download_directory_result(result: Result<PathBuf, String>) -> Value {
+    match result {
+        Ok(dir) => json!({ "success": true, "path": dir.to_string_lossy() }),
+        Err(e) => {
+            warn!("Download directory not changed: {}", e);
+            json!({ "success": false, "error": e })
+        }
+    }
+}
@@ -0,0 +1,105 @@
+//! The frontend files compiled into the binary.
+//!
+//! `build.rs` hashes them into a manifest (SHA-256 per file) that is
+//! embedded next to them. Release builds check the embedded copies against
+//! it at startup ([`verify_at_startup`]): a damaged bundle gets a dialog
+//! naming the files and offering to check for updates, instead of a blank
+//! window. The same hashes are the files' ETags. Debug builds load the page
+//! from the dev server and skip the check.
+
+use std::borrow::Cow;
+use http::{Request, Response, StatusCode};
+use sha2::{Digest, Sha256};
+use tracing::{error, info};
+
+include!(concat!(env!("OUT_DIR"), "/asset_manifest.rs"));
+
+#[cfg(not(debug_assertions))]
+pub const INDEX_HTML: &[u8] = include_bytes!("../../Distribution/index.html");
+#[cfg(debug_assertions)]
+pub const INDEX_HTML: &[u8] = &[];
+pub const EMOJI_JSON: &[u8] = include_bytes!("../../Library/Shared/emoji.json");
+
+/// Embedded files by manifest name
+fn embedded() -> [(&'static str, &'static [u8]); 2] {
+    [("index.html", INDEX_HTML), ("emoji.json", EMOJI_JSON)]
+}
+
+fn manifest_hash(name: &str) -> Option<&'static str> {
+    MANIFEST.iter().find(|(entry, _)| *entry == name).map(|(_, hash)| *hash)
+}
+
+/// Names of the embedded files that don't match the manifest
+pub fn damaged() -> Vec<&'static str> {
+    embedded()
+        .into_iter()
+        .filter(|(name, bytes)| {
+            let hash: String = Sha256::digest(bytes).iter().map(|b| format!("{:02x}", b)).collect();
+            manifest_hash(name) != Some(hash.as_str())
+        })
+        .map(|(name, _)| name)
+        .collect()
+}
+
+/// Quoted ETag of the embedded file `name`
+pub fn etag(name: &str) -> String {
+    format!("\"{}\"", manifest_hash(name).map_or("", |hash| &hash[..16]))
+}
+
+/// Answer `request` with the embedded file `name`, or 304 when the page's
+/// copy is current. `cache_control` is sent with both.
+pub fn serve(request: &Request<Vec<u8>>, name: &str, content_type: &str, cache_control: &str) -> Response<Cow<'static, [u8]>> {
+    let etag = etag(name);
+    let bytes = embedded().into_iter().find(|(entry, _)| *entry == name).map_or(&[][..], |(_, bytes)| bytes);
+    let builder = Response::builder().header("ETag", &etag).header("Cache-Control", cache_control);
+    let cached = request
+        .headers()
+        .get(http::header::IF_NONE_MATCH)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.split(',').any(|tag| tag.trim() == etag));
+    if cached {
+        return builder.status(StatusCode::NOT_MODIFIED).body(Cow::Borrowed(&[][..])).unwrap();
+    }
+    builder.header("Content-Type", content_type).body(Cow::Borrowed(bytes)).unwrap()
+}
+
+/// Check the bundle once before the window opens (release builds)
+pub fn verify_at_startup() {
+    if cfg!(debug_assertions) {
+        return;
+    }
+    let damaged = damaged();
+    if damaged.is_empty() {
+        info!("Embedded frontend verified ({} files)", MANIFEST.len());
+        return;
+    }
+    error!("Embedded frontend files don't match the build manifest: {}", damaged.join(", "));
+    let message = format!(
+        "Workspace is damaged and may show a blank window. These files don't match the build:\n\n{}\n\nCheck for updates now?",
+        damaged.iter().map(|name| format!("• {}", name)).collect::<Vec<_>>().join("\n")
+    );
+    offer_update(&message);
+}
+
+#[cfg(target_os = "windows")]
+fn offer_update(message: &str) {
+    use crate::platform::win::utils;
+
+    if utils::confirm("Workspace", message) {
+        utils::check_for_updates(true);
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn offer_update(message: &str) {
+    use crate::platform::mac::utils;
+
+    if utils::confirm("Workspace", message, "Check for Updates", "Continue") {
+        utils::check_for_updates(true);
+    }
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+fn offer_update(message: &str) {
+    eprintln!("{}", message);
+}
@@ -0,0 +1,336 @@
+//! "Download all" for a message's or thread's attachments.
+//!
+//! `download_attachments {items: [{url, filename}], subdir?, headers?}`
+//! downloads the files one after another as a single entry in the Transfers
+//! panel, counting finished files, with every file's state in its `items`.
+//! With `subdir` (typically the thread's name) they go into that folder of
+//! the download directory (or of `targetDir`). A file that fails doesn't stop
+//! the others; cancelling skips the ones not started yet, and retrying the
+//! entry downloads only what didn't arrive.
+//!
+//! The end is reported as `attachments-downloaded {downloadId, folder,
+//! saved, failed}` and with one notification whose "Show in folder" opens
+//! the folder. Each file goes through the platform's downloader, so its
+//! `download-progress` events carry `{downloadId}-{n}`.
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use lazy_static::lazy_static;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tracing::{error, info, warn};
+
+use crate::core::downloads::{self, DownloadJob, DownloadTarget};
+use crate::core::path_checks;
+use crate::core::transfers::{self, TransferControl, TransferItem, TransferKind, TransferState, TransferUnit};
+
+/// Largest number of files one request may carry
+const MAX_ITEMS: usize = 200;
+
+/// The platform's downloader: fetch `url` into the staging file for `filename`
+/// with `headers`, stopping when the job's flag is set
+pub type Fetch = fn(url: &str, staging: &Path, filename: &str, headers: Vec<(String, String)>, job: &DownloadJob) -> Result<(), String>;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Item {
+    pub url: String,
+    pub filename: String,
+}
+
+struct Group {
+    items: Vec<Item>,
+    detail: Vec<TransferItem>,
+    target: DownloadTarget,
+    folder: PathBuf,
+    headers: Vec<(String, String)>,
+    fetch: Fetch,
+    stop: Arc<AtomicBool>,
+    /// Downloading, or waiting to
+    running: bool,
+    /// Held back on a metered connection
+    waiting: bool,
+}
+
+lazy_static! {
+    /// Grouped downloads listed in the transfers, by transfer id
+    static ref GROUPS: Mutex<HashMap<String, Group>> = Mutex::new(HashMap::new());
+}
+
+/// Thread names may hold anything; a folder name may not
+fn folder_name(name: &str) -> String {
+    let cleaned: String = name
+        .chars()
+        .map(|c| if c.is_control() || r#"<>:"/\|?*"#.contains(c) { '_' } else { c })
+        .collect();
+    cleaned.trim().trim_end_matches('.').to_string()
+}
+
+/// `report.pdf`, `report (2).pdf`, … so files of the same name don't overwrite each other
+fn unique_name(filename: &str, taken: &mut HashSet<String>) -> String {
+    let path = Path::new(filename);
+    let stem = path.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+    let extension = path.extension().map(|e| format!(".{}", e.to_string_lossy())).unwrap_or_default();
+    let mut name = filename.to_string();
+    let mut n = 1;
+    while !taken.insert(name.to_lowercase()) {
+        n += 1;
+        name = format!("{} ({}){}", stem, n, extension);
+    }
+    name
+}
+
+/// Handle `download_attachments`; returns the transfer id the group is listed under
+pub fn start(message: &Value, fetch: Fetch) -> Result<String, String> {
+    let items: Vec<Item> = serde_json::from_value(message["items"].clone()).map_err(|e| format!("invalid items: {}", e))?;
+    if items.is_empty() {
+        return Err("no attachments to download".to_string());
+    }
+    if items.len() > MAX_ITEMS {
+        return Err(format!("at most {} attachments at once", MAX_ITEMS));
+    }
+    let mut taken = HashSet::new();
+    let items: Vec<Item> = items
+        .into_iter()
+        .map(|item| Item { url: crate::protocol::absolute_url(&item.url), filename: unique_name(&item.filename, &mut taken) })
+        .collect();
+
+    let mut target = DownloadTarget::from_message(message);
+    target.save_path = None;
+    let base = target.target_dir.clone().unwrap_or_else(downloads::download_dir);
+    let folder = match message["subdir"].as_str().map(folder_name).filter(|name| !name.is_empty()) {
+        Some(name) => {
+            let folder = path_checks::sanitize_target_path(&base, &name).map_err(|e| e.to_string())?;
+            std::fs::create_dir_all(&folder).map_err(|e| format!("cannot create {}: {}", folder.display(), e))?;
+            target.target_dir = Some(folder.clone());
+            folder
+        }
+        None => base,
+    };
+
+    let mut headers = Vec::new();
+    if let Some(h) = message["headers"].as_object() {
+        for (k, v) in h {
+            if let Some(vs) = v.as_str() {
+                headers.push((k.clone(), vs.to_string()));
+            }
+        }
+    }
+
+    let id = format!("attachments-{}", uuid::Uuid::new_v4().simple());
+    let detail = items.iter().map(|item| TransferItem { name: item.filename.clone(), state: None, path: None, error: None }).collect();
+    let label = format!("{} attachments", items.len());
+    let waiting = !crate::metered::allow(&format!("Downloading {}", label));
+    let stop = Arc::new(AtomicBool::new(false));
+    let group = Group { items, detail, target, folder, headers, fetch, stop, running: true, waiting };
+    GROUPS.lock().unwrap().insert(id.clone(), group);
+
+    if waiting {
+        transfers::waiting(&id, TransferKind::DownloadGroup, &label, TransferUnit::Files);
+        publish(&id);
+        let waiting_id = id.clone();
+        crate::metered::when_unmetered(move || {
+            if start_waiting(&waiting_id) {
+                run(&waiting_id);
+            }
+        });
+        return Ok(id);
+    }
+    let run_id = id.clone();
+    std::thread::spawn(move || run(&run_id));
+    Ok(id)
+}
+
+fn publish(id: &str) {
+    let groups = GROUPS.lock().unwrap();
+    let Some(group) = groups.get(id) else { return };
+    let done = group.detail.iter().filter(|item| item.state == Some(TransferState::Completed)).count() as u64;
+    let (detail, folder) = (group.detail.clone(), group.folder.clone());
+    drop(groups);
+    transfers::progress(id, done, Some(detail.len() as u64));
+    transfers::set_items(id, detail);
+    transfers::set_path(id, &folder);
+}
+
+fn set_item(id: &str, index: usize, state: TransferState, path: Option<PathBuf>, error: Option<String>) {
+    if let Some(item) = GROUPS.lock().unwrap().get_mut(id).and_then(|group| group.detail.get_mut(index)) {
+        *item = TransferItem { name: item.name.clone(), state: Some(state), path, error };
+    }
+    publish(id);
+}
+
+/// Take group `id` off the waiting list; false if it isn't waiting (any more)
+fn start_waiting(id: &str) -> bool {
+    GROUPS.lock().unwrap().get_mut(id).is_some_and(|group| std::mem::replace(&mut group.waiting, false))
+}
+
+/// Download the files of group `id` that haven't arrived yet
+fn run(id: &str) {
+    let (pending, label): (Vec<(usize, Item)>, String) = {
+        let mut groups = GROUPS.lock().unwrap();
+        let Some(group) = groups.get_mut(id) else { return };
+        let mut pending = Vec::new();
+        for (index, (item, detail)) in group.items.iter().zip(group.detail.iter_mut()).enumerate() {
+            if detail.state != Some(TransferState::Completed) {
+                *detail = TransferItem { name: detail.name.clone(), state: None, path: None, error: None };
+                pending.push((index, item.clone()));
+            }
+        }
+        (pending, format!("{} attachments", group.items.len()))
+    };
+    transfers::started(id, TransferKind::DownloadGroup, &label, TransferUnit::Files);
+    publish(id);
+    info!("Downloading {} attachment(s) as {}", pending.len(), id);
+
+    for (index, item) in pending {
+        let (target, headers, fetch, stop) = {
+            let groups = GROUPS.lock().unwrap();
+            let Some(group) = groups.get(id) else { return };
+            (group.target.clone(), group.headers.clone(), group.fetch, group.stop.clone())
+        };
+        if stop.load(Ordering::SeqCst) {
+            set_item(id, index, TransferState::Cancelled, None, None);
+            continue;
+        }
+        set_item(id, index, TransferState::Running, None, None);
+        // Not listed in the transfers on its own; shares the group's stop flag
+        let job = DownloadJob { id: format!("{}-{}", id, index), stop };
+        match download(&item, &target, headers, fetch, &job) {
+            Ok(destination) => {
+                downloads::record(&item.filename, &destination);
+                set_item(id, index, TransferState::Completed, Some(destination), None);
+            }
+            Err(_) if job.stop.load(Ordering::SeqCst) => set_item(id, index, TransferState::Cancelled, None, None),
+            Err(e) => {
+                warn!("Attachment {} failed: {}", item.filename, e);
+                set_item(id, index, TransferState::Failed, None, Some(e));
+            }
+        }
+    }
+    finish(id);
+}
+
+fn download(item: &Item, target: &DownloadTarget, headers: Vec<(String, String)>, fetch: Fetch, job: &DownloadJob) -> Result<PathBuf, String> {
+    let destination = downloads::resolve_destination(&item.filename, target)?;
+    let staging = downloads::prepare_staging(&destination).map_err(|e| e.to_string())?;
+    let result = fetch(&item.url, &staging, &item.filename, headers, job).and_then(|()| {
+        downloads::move_into_place(&staging, &destination)
+            .map_err(|e| format!("could not move the download to {}: {}", destination.display(), e))
+    });
+    if result.is_err() {
+        let _ = std::fs::remove_file(&staging);
+    }
+    result.map(|()| destination)
+}
+
+/// Report how group `id` ended: the event, the notification and its entry's state
+fn finish(id: &str) {
+    let (items, detail, folder, cancelled) = {
+        let mut groups = GROUPS.lock().unwrap();
+        let Some(group) = groups.get_mut(id) else { return };
+        group.running = false;
+        (group.items.clone(), group.detail.clone(), group.folder.clone(), group.stop.swap(false, Ordering::SeqCst))
+    };
+    let saved: Vec<Value> = detail
+        .iter()
+        .filter(|item| item.state == Some(TransferState::Completed))
+        .map(|item| json!({ "filename": item.name, "path": item.path }))
+        .collect();
+    let failed: Vec<Value> = items
+        .iter()
+        .zip(&detail)
+        .filter(|(_, item)| item.state != Some(TransferState::Completed))
+        .map(|(item, detail)| json!({ "url": item.url, "filename": item.filename, "error": detail.error }))
+        .collect();
+
+    let (state, error) = if cancelled {
+        (TransferState::Cancelled, None)
+    } else if failed.is_empty() {
+        (TransferState::Completed, None)
+    } else {
+        (TransferState::Failed, Some(format!("{} of {} files failed", failed.len(), items.len())))
+    };
+    info!("Attachments {} ended {:?}: {} saved, {} not", id, state, saved.len(), failed.len());
+    transfers::stopped(id, state, error);
+    if state == TransferState::Completed {
+        GROUPS.lock().unwrap().remove(id);
+    }
+
+    let result = json!({
+        "downloadId": id,
+        "success": failed.is_empty(),
+        "cancelled": cancelled,
+        "folder": folder,
+        "saved": saved,
+        "failed": failed,
+    });
+    crate::ipc::respond(None, "attachments-downloaded", &result);
+    if !cancelled {
+        notify(&folder, detail.iter().find_map(|item| item.path.clone()), saved.len(), failed.len());
+    }
+}
+
+fn notify(folder: &Path, first_saved: Option<PathBuf>, saved: usize, failed: usize) {
+    if crate::quiet_hours::silenced() {
+        return;
+    }
+    let (title, message) = match failed {
+        0 => ("Attachments downloaded", format!("{} files saved to {}", saved, folder.display())),
+        _ => ("Some attachments failed", format!("{} saved to {}, {} failed", saved, folder.display(), failed)),
+    };
+    let toast = crate::hooks::NotificationData {
+        title: title.to_string(),
+        message,
+        icon: None,
+        chat_uuid: None,
+        // "Show in folder" opens the folder the file is in
+        reveal_path: first_saved.map(|path| path.to_string_lossy().into_owned()),
+        tag: None,
+    };
+    if let Err(e) = crate::hooks::show_notification(toast) {
+        error!("Failed to show attachments notification: {}", e);
+    }
+}
+
+/// Grouped downloads as listed in [`transfers`]
+pub struct Groups;
+
+impl TransferControl for Groups {
+    fn resume(&self, id: &str) -> Result<(), String> {
+        // Download anyway
+        if !start_waiting(id) {
+            return Err(format!("attachments {} can't be resumed", id));
+        }
+        let id = id.to_string();
+        std::thread::spawn(move || run(&id));
+        Ok(())
+    }
+
+    fn cancel(&self, id: &str) -> Result<(), String> {
+        if start_waiting(id) {
+            if let Some(group) = GROUPS.lock().unwrap().get_mut(id) {
+                group.running = false;
+            }
+            transfers::stopped(id, TransferState::Cancelled, None);
+            return Ok(());
+        }
+        let groups = GROUPS.lock().unwrap();
+        let group = groups.get(id).filter(|group| group.running).ok_or_else(|| format!("attachments {} are not downloading", id))?;
+        group.stop.store(true, Ordering::SeqCst);
+        Ok(())
+    }
+
+    fn retry(&self, id: &str) -> Result<(), String> {
+        {
+            let mut groups = GROUPS.lock().unwrap();
+            let group = groups.get_mut(id).filter(|group| !group.running).ok_or_else(|| format!("attachments {} can't be retried", id))?;
+            // Until `run` takes over; keeps a double click from starting two
+            group.running = true;
+            group.stop.store(false, Ordering::SeqCst);
+        }
+        let id = id.to_string();
+        std::thread::spawn(move || run(&id));
+        Ok(())
+    }
+}
@@ -0,0 +1,66 @@
+//! "Launch Workspace when I sign in", behind `get_autostart` and
+//! `set_autostart {enabled}`.
+//!
+//! The platform layer registers the app with the OS (the `Run` key on
+//! Windows, a LaunchAgent on macOS) and reads the registration back, so the
+//! answer reflects what the OS will actually do even after the user turned
+//! the entry off elsewhere. The `autostart` setting only remembers the last
+//! choice made here. Builds running out of a cargo `target` directory refuse
+//! to register: the entry would point at a binary that is rebuilt or deleted.
+
+use std::path::PathBuf;
+use serde_json::{json, Value};
+use tracing::{info, warn};
+
+/// Name of the OS entry; a `--profile` gets its own
+pub fn entry_name() -> String {
+    match crate::paths::profile() {
+        Some(profile) => format!("MikoWorkspace ({})", profile),
+        None => "MikoWorkspace".to_string(),
+    }
+}
+
+/// Executable and arguments the OS should start at sign-in
+pub fn launch_command() -> Result<(PathBuf, Vec<String>), String> {
+    let exe = std::env::current_exe().map_err(|e| e.to_string())?;
+    if cfg!(debug_assertions) || exe.components().any(|c| c.as_os_str() == "target") {
+        return Err(format!("{} is a development build; install the app to start it at sign-in", exe.display()));
+    }
+
+    let mut args = vec!["--start-hidden".to_string()];
+    if let Some(profile) = crate::paths::profile() {
+        args.extend(["--profile".to_string(), profile]);
+    }
+    if crate::paths::portable() {
+        args.push("--portable".to_string());
+    }
+    Ok((exe, args))
+}
+
+/// The `autostart` reply: the OS state, or why it couldn't be read or changed
+pub fn result(enabled: Result<bool, String>) -> Value {
+    match enabled {
+        Ok(enabled) => json!({ "success": true, "enabled": enabled }),
+        Err(e) => {
+            warn!("Autostart: {}", e);
+            json!({ "success": false, "error": e })
+        }
+    }
+}
+
+/// Handle `set_autostart` with the platform's `status` and `set` functions;
+/// answers with the state read back afterwards
+pub fn apply(
+    enabled: bool,
+    set: fn(bool) -> Result<(), String>,
+    status: fn() -> Result<bool, String>,
+) -> Value {
+    if let Err(e) = set(enabled) {
+        return result(Err(e));
+    }
+    info!("Autostart {}", if enabled { "enabled" } else { "disabled" });
+    if let Err(e) = crate::core::settings::update(|s| s.autostart = enabled) {
+        warn!("Failed to save the autostart setting: {}", e);
+    }
+    result(status())
+}
@@ -0,0 +1,78 @@
+//! Command-line flags of the desktop app.
+//!
+//! Mostly for IT deployments: `--start-hidden` for autostart entries,
+//! `--profile <name>` to run a test install next to the production one,
+//! `--portable` to keep everything in `data` next to the executable,
+//! `--safe-mode` when a bad setting or cache keeps the app from starting, and
+//! `--dev-server <url>` for debug builds. The app has no console, so usage
+//! and errors are shown in a message box.
+
+use clap::Parser;
+
+#[derive(Parser, Debug, Clone, Default)]
+#[command(name = "workspace", version, about = "Workspace desktop app")]
+pub struct Args {
+    /// Start in the tray without showing the window
+    #[arg(long)]
+    pub start_hidden: bool,
+
+    /// Keep settings, logs, history and the browser profile apart under this name
+    #[arg(long, env = crate::paths::PROFILE_ENV, value_parser = parse_profile)]
+    pub profile: Option<String>,
+
+    /// Keep settings, logs, history and the browser profile in `data` next to
+    /// the executable (also turned on by a `portable.marker` file there)
+    #[arg(long, env = crate::paths::PORTABLE_ENV)]
+    pub portable: bool,
+
+    /// Start with default settings and a fresh page, and offer to reset
+    /// (same as holding Shift while the app starts)
+    #[arg(long)]
+    pub safe_mode: bool,
+
+    /// Page to load instead of http://localhost:5173 (debug builds only)
+    #[arg(long, value_name = "URL")]
+    pub dev_server: Option<String>,
+}
+
+fn parse_profile(name: &str) -> Result<String, String> {
+    let valid = !name.is_empty()
+        && name.len() <= 64
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if valid {
+        Ok(name.to_string())
+    } else {
+        Err("use up to 64 letters, digits, '-' or '_'".to_string())
+    }
+}
+
+/// Parse the command line; `--help`, `--version` and bad flags are shown in a
+/// message box and end the process
+pub fn parse() -> Args {
+    let args = match Args::try_parse() {
+        Ok(args) => args,
+        Err(e) => {
+            let code = e.exit_code();
+            show_usage(&e.render().to_string());
+            std::process::exit(code);
+        }
+    };
+
+    // Inherited by the downloader service, so its logs follow the profile too
+    if let Some(profile) = &args.profile {
+        std::env::set_var(crate::paths::PROFILE_ENV, profile);
+    }
+    if args.portable {
+        std::env::set_var(crate::paths::PORTABLE_ENV, "1");
+    }
+    args
+}
+
+fn show_usage(text: &str) {
+    #[cfg(target_os = "windows")]
+    crate::platform::win::utils::show_message("Workspace", text);
+    #[cfg(target_os = "macos")]
+    crate::platform::mac::utils::show_message("Workspace", text);
+    #[cfg(not(any(target_os = "windows", target_os = "macos")))]
+    eprintln!("{}", text);
+}
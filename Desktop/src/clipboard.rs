@@ -0,0 +1,103 @@
+//! Native clipboard paste (`paste`), for what the webview's clipboard can't
+//! hand to the page: images, e.g. a screenshot pasted into the composer.
+//!
+//! An image is written as a PNG under `clipboard` in the data directory and
+//! answered with its path (for `upload_file`), a `miko://downloads/` URL the
+//! page can show as a preview, and its size. Text is answered as text. The
+//! PNGs are removed once uploaded and when the app exits; leftovers of a run
+//! that crashed go at the next start (see `maintenance`).
+
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+use serde_json::{json, Value};
+use tracing::{debug, info, warn};
+
+/// Larger images are refused rather than encoded: a 500 MP scan would
+/// freeze the app for minutes
+pub const MAX_PIXELS: usize = 50_000_000;
+
+pub fn temp_dir() -> PathBuf {
+    crate::paths::data_dir().join("clipboard")
+}
+
+/// A PNG written by [`paste`]
+pub fn is_temp_file(path: &Path) -> bool {
+    path.parent() == Some(temp_dir().as_path())
+}
+
+/// Handle `paste`
+pub fn paste() -> Value {
+    let mut clipboard = match arboard::Clipboard::new() {
+        Ok(clipboard) => clipboard,
+        Err(e) => return json!({ "success": false, "error": format!("clipboard unavailable: {}", e) }),
+    };
+    match clipboard.get_image() {
+        Ok(image) => save_image(image),
+        Err(arboard::Error::ContentNotAvailable) => match clipboard.get_text() {
+            Ok(text) => json!({ "success": true, "kind": "text", "text": text }),
+            Err(arboard::Error::ContentNotAvailable) => json!({ "success": true, "kind": "empty" }),
+            Err(e) => json!({ "success": false, "error": e.to_string() }),
+        },
+        Err(e) => json!({ "success": false, "error": e.to_string() }),
+    }
+}
+
+fn save_image(image: arboard::ImageData) -> Value {
+    let (width, height) = (image.width, image.height);
+    if width.saturating_mul(height) > MAX_PIXELS {
+        warn!("Refusing to paste a {}x{} image", width, height);
+        return json!({
+            "success": false,
+            "error": format!("image is too large to paste ({}x{}, at most {} megapixels)", width, height, MAX_PIXELS / 1_000_000),
+        });
+    }
+
+    let started = Instant::now();
+    let path = temp_dir().join(format!("pasted-{}.png", chrono::Local::now().format("%Y%m%d-%H%M%S-%3f")));
+    if let Err(e) = write_png(&path, &image) {
+        warn!("Failed to save the pasted image: {}", e);
+        let _ = std::fs::remove_file(&path);
+        return json!({ "success": false, "error": format!("failed to save the pasted image: {}", e) });
+    }
+    let size = std::fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+    debug!("Saved a pasted {}x{} image in {:?}", width, height, started.elapsed());
+    json!({
+        "success": true,
+        "kind": "image",
+        "path": path,
+        "url": crate::preview::register(&path),
+        "width": width,
+        "height": height,
+        "size": size,
+    })
+}
+
+fn write_png(path: &Path, image: &arboard::ImageData) -> Result<(), Box<dyn std::error::Error>> {
+    std::fs::create_dir_all(temp_dir())?;
+    let file = std::io::BufWriter::new(std::fs::File::create(path)?);
+    let mut encoder = png::Encoder::new(file, image.width as u32, image.height as u32);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    // Screenshots are large and mostly flat; fast compression is plenty
+    encoder.set_compression(png::Compression::Fast);
+    encoder.write_header()?.write_image_data(&image.bytes)?;
+    Ok(())
+}
+
+/// Remove a pasted image once it was uploaded
+pub fn uploaded(path: &Path) {
+    if is_temp_file(path) {
+        if let Err(e) = std::fs::remove_file(path) {
+            warn!("Failed to remove {}: {}", path.display(), e);
+        }
+    }
+}
+
+/// Remove every pasted image (startup and exit)
+pub fn cleanup() {
+    match std::fs::remove_dir_all(temp_dir()) {
+        Ok(()) => info!("Removed pasted images"),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => warn!("Failed to remove pasted images: {}", e),
+    }
+}
@@ -0,0 +1,173 @@
+//! Whether this build can work with the ERP it talks to, as the `compat`
+//! state topic.
+//!
+//! Once the ERP is reachable (at startup and after every reconnect) its
+//! `GET /api/version` is compared with the API range compiled in here: the
+//! same major as [`MIN_API`] and no older than it. A minor newer than
+//! [`KNOWN_MINOR`] still works, but the page may lack what came
+//! with it. The topic is `{status, apiVersion, clientVersion, checkedAt}`,
+//! `status` one of `unknown` (no answer, or an ERP without the endpoint),
+//! `compatible`, `frontend_update_recommended` and `blocked`.
+//!
+//! When blocked, a native dialog offers Check for Updates once per ERP
+//! version instead of leaving the page to fail with 400s. Every forwarded
+//! request also carries `X-Miko-Client-Version` so the ERP can refuse old
+//! clients itself.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use chrono::{DateTime, Utc};
+use lazy_static::lazy_static;
+use serde::Serialize;
+use serde_json::Value;
+use tracing::{debug, info, warn};
+
+use crate::core::sync;
+
+const VERSION_PATH: &str = "/api/version";
+/// Oldest ERP API (major, minor) with everything this build uses; another
+/// major is never compatible
+const MIN_API: (u64, u64) = (1, 0);
+/// Newest minor this build's page knows about
+const KNOWN_MINOR: u64 = 0;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Compat {
+    #[default]
+    Unknown,
+    Compatible,
+    FrontendUpdateRecommended,
+    Blocked,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CompatState {
+    status: Compat,
+    api_version: Option<String>,
+    client_version: &'static str,
+    checked_at: Option<DateTime<Utc>>,
+}
+
+lazy_static! {
+    static ref STATE: Mutex<CompatState> =
+        Mutex::new(CompatState { client_version: env!("CARGO_PKG_VERSION"), ..Default::default() });
+    /// ERP version the blocked dialog was last shown for
+    static ref ASKED_FOR: Mutex<Option<String>> = Mutex::new(None);
+}
+
+static CHECKING: AtomicBool = AtomicBool::new(false);
+
+/// `1.4.2` -> `(1, 4)`
+fn major_minor(version: &str) -> Option<(u64, u64)> {
+    let mut parts = version.trim().trim_start_matches('v').split(['.', '-', '+']);
+    let major = parts.next()?.parse().ok()?;
+    let minor = parts.next().and_then(|minor| minor.parse().ok()).unwrap_or(0);
+    Some((major, minor))
+}
+
+fn classify(version: &str) -> Compat {
+    let Some((major, minor)) = major_minor(version) else { return Compat::Unknown };
+    if major != MIN_API.0 || (major, minor) < MIN_API {
+        Compat::Blocked
+    } else if minor > KNOWN_MINOR {
+        Compat::FrontendUpdateRecommended
+    } else {
+        Compat::Compatible
+    }
+}
+
+/// Ask the ERP for its API version and publish the verdict; on a background
+/// thread, as it waits for the answer
+pub fn check() {
+    if CHECKING.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    let api_version = fetch_version();
+    CHECKING.store(false, Ordering::SeqCst);
+
+    let status = api_version.as_deref().map_or(Compat::Unknown, classify);
+    let changed = {
+        let mut state = STATE.lock().unwrap();
+        let changed = state.status != status || state.api_version != api_version;
+        state.status = status;
+        state.api_version = api_version.clone();
+        state.checked_at = Some(Utc::now());
+        changed
+    };
+    if changed {
+        info!("ERP API {:?}: {:?}", api_version, status);
+    }
+    sync::publish(sync::TOPIC_COMPAT);
+
+    if status == Compat::Blocked {
+        let version = api_version.unwrap_or_default();
+        let mut asked = ASKED_FOR.lock().unwrap();
+        if asked.as_deref() != Some(version.as_str()) {
+            *asked = Some(version.clone());
+            drop(asked);
+            offer_update(&version);
+        }
+    }
+}
+
+fn fetch_version() -> Option<String> {
+    let response = crate::protocol::get(VERSION_PATH);
+    if !response.status().is_success() {
+        debug!("No ERP API version: {}", response.status());
+        return None;
+    }
+    let body: Value = serde_json::from_slice(response.body()).unwrap_or(Value::Null);
+    let version = ["apiVersion", "version"]
+        .iter()
+        .find_map(|field| body["data"][*field].as_str().or_else(|| body[*field].as_str()));
+    if version.is_none() {
+        warn!("ERP answered {} without a version", VERSION_PATH);
+    }
+    version.map(|v| v.to_string())
+}
+
+fn offer_update(api_version: &str) {
+    let message = format!(
+        "The server was updated (API {}) and this version of Workspace ({}) can no longer work with it. Check for updates now?",
+        api_version,
+        env!("CARGO_PKG_VERSION"),
+    );
+    if ask_update(&message) {
+        check_for_updates();
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn ask_update(message: &str) -> bool {
+    // Yes checks for updates
+    crate::platform::win::utils::confirm("Update required", message)
+}
+
+#[cfg(target_os = "windows")]
+fn check_for_updates() {
+    crate::platform::win::utils::check_for_updates(true);
+}
+
+#[cfg(target_os = "macos")]
+fn ask_update(message: &str) -> bool {
+    crate::platform::mac::utils::confirm("Update required", message, "Check for Updates", "Later")
+}
+
+#[cfg(target_os = "macos")]
+fn check_for_updates() {
+    crate::platform::mac::utils::check_for_updates(true);
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+fn ask_update(_message: &str) -> bool {
+    false
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+fn check_for_updates() {}
+
+pub fn snapshot() -> Value {
+    serde_json::to_value(&*STATE.lock().unwrap()).unwrap_or(Value::Null)
+}
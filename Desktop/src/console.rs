@@ -0,0 +1,135 @@
+//! The page's console errors and uncaught exceptions, in the native log.
+//!
+//! Release builds have no visible DevTools, so [`INIT_SCRIPT`] forwards
+//! `console.error`/`console.warn`, `window.onerror` and unhandled promise
+//! rejections as `log_event` messages, and they are written to the rotating
+//! log under the `webview` target. A page stuck in an error loop would drown
+//! the log: identical repeats are counted instead of written, and no more
+//! than [`MAX_PER_SECOND`] events are written per second.
+
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use lazy_static::lazy_static;
+use serde_json::Value;
+use tracing::{error, warn};
+
+const MAX_PER_SECOND: u32 = 20;
+/// Longer messages and stacks are cut
+const MAX_FIELD_CHARS: usize = 4096;
+
+/// Installed with `with_initialization_script`
+pub const INIT_SCRIPT: &str = r#"
+(function () {
+    if (window.__mikoConsoleHooked) return;
+    window.__mikoConsoleHooked = true;
+    let sending = false;
+    const text = (value) => {
+        if (value instanceof Error) return value.stack || String(value);
+        if (typeof value === 'string') return value;
+        try { return JSON.stringify(value); } catch (e) { return String(value); }
+    };
+    const send = (event) => {
+        if (sending || !window.ipc) return;
+        sending = true;
+        try { window.ipc.postMessage(JSON.stringify(Object.assign({ type: 'log_event' }, event))); } catch (e) {}
+        sending = false;
+    };
+    ['error', 'warn'].forEach((level) => {
+        const original = console[level];
+        console[level] = function (...args) {
+            const error = args.find((arg) => arg instanceof Error);
+            send({ level, message: args.map(text).join(' '), stack: error && error.stack, url: location.href });
+            return original.apply(this, args);
+        };
+    });
+    window.addEventListener('error', (e) => {
+        send({ level: 'error', message: e.message, stack: e.error && e.error.stack, url: e.filename, line: e.lineno, column: e.colno });
+    });
+    window.addEventListener('unhandledrejection', (e) => {
+        const reason = e.reason;
+        send({ level: 'error', message: 'Unhandled rejection: ' + text(reason && reason.message || reason), stack: reason && reason.stack, url: location.href });
+    });
+})();
+"#;
+
+struct Limiter {
+    window_start: Instant,
+    written: u32,
+    dropped: u32,
+    last: Option<String>,
+    repeats: u32,
+}
+
+lazy_static! {
+    static ref LIMITER: Mutex<Limiter> =
+        Mutex::new(Limiter { window_start: Instant::now(), written: 0, dropped: 0, last: None, repeats: 0 });
+}
+
+fn field(message: &Value, key: &str) -> String {
+    let text = message[key].as_str().unwrap_or_default();
+    match text.char_indices().nth(MAX_FIELD_CHARS) {
+        Some((cut, _)) => format!("{}…", &text[..cut]),
+        None => text.to_string(),
+    }
+}
+
+/// Handle `log_event` from the page
+pub fn log_event(message: &Value) {
+    let level = message["level"].as_str().unwrap_or("error");
+    let text = field(message, "message");
+    let stack = field(message, "stack");
+    let url = crate::logging::redact_url(&field(message, "url"));
+    let line = message["line"].as_u64();
+    let column = message["column"].as_u64();
+    let key = format!("{}|{}|{}|{:?}", level, text, url, line);
+
+    let mut limiter = LIMITER.lock().unwrap();
+    let new_window = limiter.window_start.elapsed() >= Duration::from_secs(1);
+    if limiter.last.as_deref() == Some(key.as_str()) {
+        limiter.repeats += 1;
+        // A page stuck in a loop still shows up about once a second
+        if new_window {
+            flush_repeats(&mut limiter);
+            roll_window(&mut limiter);
+        }
+        return;
+    }
+    flush_repeats(&mut limiter);
+    limiter.last = Some(key);
+    if new_window {
+        roll_window(&mut limiter);
+    }
+    if limiter.written >= MAX_PER_SECOND {
+        limiter.dropped += 1;
+        return;
+    }
+    limiter.written += 1;
+    drop(limiter);
+
+    let location = match (line, column) {
+        (Some(line), Some(column)) => format!("{}:{}:{}", url, line, column),
+        (Some(line), None) => format!("{}:{}", url, line),
+        _ => url,
+    };
+    if level == "warn" {
+        warn!(target: "webview", location = %location, stack = %stack, "{}", text);
+    } else {
+        error!(target: "webview", location = %location, stack = %stack, "{}", text);
+    }
+}
+
+fn roll_window(limiter: &mut Limiter) {
+    if limiter.dropped > 0 {
+        warn!(target: "webview", "Dropped {} page log events over the rate limit", limiter.dropped);
+    }
+    limiter.window_start = Instant::now();
+    limiter.written = 0;
+    limiter.dropped = 0;
+}
+
+fn flush_repeats(limiter: &mut Limiter) {
+    if limiter.repeats > 0 {
+        warn!(target: "webview", "Previous page log event repeated {} more times", limiter.repeats);
+        limiter.repeats = 0;
+    }
+}
@@ -1,3 +1,6 @@
+//! Window theming, and the native menu for right-clicks in the page
+//! (`show_context_menu`), with spelling suggestions when the page has them.
+
 #[cfg(windows)]
 use windows::{
     core::*,
@@ -7,8 +10,6 @@ use windows::{
     },
 };
 
-
-
 #[cfg(windows)]
 pub fn init_window_theme(hwnd: HWND) -> Result<()> {
     setup_window_theme(hwnd)?;
@@ -38,4 +39,178 @@ fn setup_window_theme(hwnd: HWND) -> Result<()> {
         
         Ok(())
     }
-}
\ No newline at end of file
+}
+/// Spelling suggestions shown at most, like the engines' own menus
+const MAX_SUGGESTIONS: usize = 5;
+
+/// A `show_context_menu` request: where the page was right-clicked and what
+/// is under the pointer. The page reads `spell` from the engine's marking of
+/// the misspelled word.
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ContextMenuRequest {
+    /// Position in CSS pixels within the page; the cursor position when left out
+    pub x: Option<f64>,
+    pub y: Option<f64>,
+    #[serde(default)]
+    pub is_editable: bool,
+    #[serde(default)]
+    pub has_selection: bool,
+    pub spell: Option<SpellContext>,
+    #[serde(skip)]
+    pub request_id: Option<String>,
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct SpellContext {
+    pub word: String,
+    #[serde(default)]
+    pub suggestions: Vec<String>,
+}
+
+/// What the user picked
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Choice {
+    /// Replace the word with this
+    Suggestion(String),
+    AddToDictionary,
+    Cut,
+    Copy,
+    Paste,
+    SelectAll,
+    EmojiPicker,
+}
+
+impl Choice {
+    pub fn action(&self) -> &'static str {
+        match self {
+            Self::Suggestion(_) => "suggestion",
+            Self::AddToDictionary => "add_to_dictionary",
+            Self::Cut => "cut",
+            Self::Copy => "copy",
+            Self::Paste => "paste",
+            Self::SelectAll => "select_all",
+            Self::EmojiPicker => "emoji_picker",
+        }
+    }
+}
+
+pub enum Entry {
+    Item { label: String, choice: Option<Choice>, enabled: bool },
+    Separator,
+}
+
+fn item(label: &str, choice: Choice, enabled: bool) -> Entry {
+    Entry::Item { label: label.to_string(), choice: Some(choice), enabled }
+}
+
+/// The menu for `request`: spelling suggestions first, then editing
+pub fn entries(request: &ContextMenuRequest) -> Vec<Entry> {
+    let mut entries = Vec::new();
+    if let Some(spell) = request.spell.as_ref().filter(|_| request.is_editable) {
+        let suggestions: Vec<&String> = spell.suggestions.iter().filter(|s| !s.is_empty()).take(MAX_SUGGESTIONS).collect();
+        if suggestions.is_empty() {
+            entries.push(Entry::Item { label: "No spelling suggestions".to_string(), choice: None, enabled: false });
+        }
+        for suggestion in suggestions {
+            // `&` would mark a mnemonic
+            entries.push(item(&suggestion.replace('&', "&&"), Choice::Suggestion(suggestion.clone()), true));
+        }
+        entries.push(item(&format!("Add \"{}\" to Dictionary", spell.word.replace('&', "&&")), Choice::AddToDictionary, true));
+        entries.push(Entry::Separator);
+    }
+    if request.is_editable {
+        entries.push(item("Cut\tCtrl+X", Choice::Cut, request.has_selection));
+    }
+    entries.push(item("Copy\tCtrl+C", Choice::Copy, request.has_selection));
+    if request.is_editable {
+        entries.push(item("Paste\tCtrl+V", Choice::Paste, true));
+    }
+    entries.push(Entry::Separator);
+    entries.push(item("Select All\tCtrl+A", Choice::SelectAll, true));
+    if request.is_editable {
+        entries.push(Entry::Separator);
+        entries.push(item("Emoji Picker", Choice::EmojiPicker, true));
+    }
+    entries
+}
+
+/// Show the menu for `request` over `hwnd` and wait for a pick; `scale` is
+/// the window's scale factor, to place it at the page's CSS position
+#[cfg(windows)]
+pub fn show(hwnd: HWND, request: &ContextMenuRequest, scale: f64) -> Option<Choice> {
+    use windows::Win32::Graphics::Gdi::ClientToScreen;
+    use windows::Win32::UI::WindowsAndMessaging::{
+        AppendMenuW, CreatePopupMenu, DestroyMenu, GetCursorPos, TrackPopupMenu, MF_GRAYED, MF_SEPARATOR, MF_STRING, TPM_RETURNCMD,
+        TPM_RIGHTBUTTON,
+    };
+
+    let entries = entries(request);
+    unsafe {
+        let menu = CreatePopupMenu().ok()?;
+        // Command ids are the entry's index + 1; 0 means nothing was picked
+        for (index, entry) in entries.iter().enumerate() {
+            let appended = match entry {
+                Entry::Item { label, enabled, .. } => {
+                    let wide: Vec<u16> = label.encode_utf16().chain(std::iter::once(0)).collect();
+                    let flags = if *enabled { MF_STRING } else { MF_STRING | MF_GRAYED };
+                    AppendMenuW(menu, flags, index + 1, PCWSTR(wide.as_ptr()))
+                }
+                Entry::Separator => AppendMenuW(menu, MF_SEPARATOR, 0, PCWSTR::null()),
+            };
+            if let Err(e) = appended {
+                tracing::warn!("Failed to build the context menu: {}", e);
+            }
+        }
+
+        let mut point = POINT::default();
+        match (request.x, request.y) {
+            (Some(x), Some(y)) => {
+                point = POINT { x: (x * scale).round() as i32, y: (y * scale).round() as i32 };
+                let _ = ClientToScreen(hwnd, &mut point);
+            }
+            _ => {
+                let _ = GetCursorPos(&mut point);
+            }
+        }
+        let picked = TrackPopupMenu(menu, TPM_RETURNCMD | TPM_RIGHTBUTTON, point.x, point.y, 0, hwnd, None).0 as usize;
+        let _ = DestroyMenu(menu);
+
+        match entries.into_iter().nth(picked.checked_sub(1)?)? {
+            Entry::Item { choice, .. } => choice,
+            Entry::Separator => None,
+        }
+    }
+}
+
+/// Answer the page with what was picked (`None` when the menu was dismissed)
+/// as `context-menu-selected {action, suggestion?, word?}`. Adding to the
+/// dictionary is done here; the emoji picker is also announced with
+/// `open-emoji-picker {at: "caret"}`. Everything else is for the page to do
+/// on its selection.
+pub fn selected(request: &ContextMenuRequest, choice: Option<Choice>, learn: crate::spellcheck::LearnWords) {
+    let word = request.spell.as_ref().map(|spell| spell.word.clone());
+    match &choice {
+        Some(Choice::AddToDictionary) => {
+            if let Some(word) = word.clone() {
+                std::thread::spawn(move || {
+                    if let Err(e) = crate::spellcheck::add_word(&word, learn) {
+                        tracing::warn!("Failed to add {:?} to the dictionary: {}", word, e);
+                    }
+                });
+            }
+        }
+        Some(Choice::EmojiPicker) => crate::ipc::respond(None, "open-emoji-picker", &serde_json::json!({ "at": "caret" })),
+        _ => {}
+    }
+    let suggestion = match &choice {
+        Some(Choice::Suggestion(suggestion)) => Some(suggestion.clone()),
+        _ => None,
+    };
+    let result = serde_json::json!({
+        "action": choice.as_ref().map(Choice::action),
+        "suggestion": suggestion,
+        "word": word,
+    });
+    crate::ipc::respond(request.request_id.as_deref(), "context-menu-selected", &result);
+}
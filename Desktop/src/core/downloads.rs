@@ -0,0 +1,840 @@
+//! Download destinations and history.
+//!
+//! Downloads are written to a staging file in the app data directory and moved
+//! to their destination once complete, so a half-written file never shows up
+//! where the user expects the finished one. The destination is the download
+//! directory (`downloads.directory` in the settings, else the system Downloads
+//! folder) unless `start_download` carries a `targetDir` or `savePath`. Every
+//! finished download is recorded in `download_history.json` so "Show in
+//! folder" can find it wherever it went.
+//!
+//! Unfinished downloads (waiting, running with their byte offset, paused) are
+//! journaled to `download_journal.json` on every change, so a crash of the app
+//! itself doesn't lose them. [`restore`] reconciles the journal with the
+//! staging files at the next start: what was running or waiting starts again
+//! once the user is signed in, paused ones are listed as paused, and ones whose
+//! partial file is gone are listed as failed, ready to retry from scratch.
+
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::{info, warn};
+
+use super::path_checks;
+use super::transfers::{self, TransferControl, TransferKind, TransferState, TransferUnit};
+
+/// Oldest entries are dropped beyond this
+const MAX_HISTORY: usize = 500;
+
+/// The history changed since the tray menu was last built
+static TRAY_DIRTY: AtomicBool = AtomicBool::new(false);
+
+/// Where the page asked for a download to be saved
+#[derive(Debug, Clone, Default)]
+pub struct DownloadTarget {
+    /// Directory to save into under the download's own filename
+    pub target_dir: Option<PathBuf>,
+    /// Exact file to create, typically from the native save dialog
+    pub save_path: Option<PathBuf>,
+}
+
+impl DownloadTarget {
+    /// Read `targetDir` / `savePath` from a `start_download` message
+    pub fn from_message(message: &Value) -> Self {
+        let path = |key: &str| message[key].as_str().filter(|s| !s.is_empty()).map(PathBuf::from);
+        Self { target_dir: path("targetDir"), save_path: path("savePath") }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DownloadRecord {
+    pub filename: String,
+    pub path: PathBuf,
+    pub completed_at: chrono::DateTime<chrono::Utc>,
+}
+
+lazy_static! {
+    static ref HISTORY: Mutex<Vec<DownloadRecord>> = Mutex::new(load());
+    /// Downloads listed in the transfers, by transfer id; kept after a pause
+    /// or failure so they can be resumed or retried
+    static ref JOBS: Mutex<HashMap<String, Job>> = Mutex::new(HashMap::new());
+    /// Downloads waiting for an unmetered connection, by transfer id
+    static ref DEFERRED: Mutex<HashMap<String, Restart>> = Mutex::new(HashMap::new());
+    /// Unfinished downloads by transfer id, as last saved
+    static ref JOURNAL: Mutex<BTreeMap<String, JournalEntry>> = Mutex::new(load_journal());
+    /// Restored downloads to start again once the user is signed in
+    static ref RESTART_ON_SIGN_IN: Mutex<Vec<String>> = Mutex::new(Vec::new());
+}
+
+fn history_path() -> PathBuf {
+    crate::paths::data_dir().join("download_history.json")
+}
+
+fn load() -> Vec<DownloadRecord> {
+    std::fs::read(history_path())
+        .ok()
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .unwrap_or_default()
+}
+
+fn system_download_dir() -> PathBuf {
+    dirs::download_dir().unwrap_or_else(|| std::env::current_dir().unwrap().join("Downloads"))
+}
+
+/// Where downloads go by default; every caller reads it from here
+pub fn download_dir() -> PathBuf {
+    super::settings::get()
+        .downloads
+        .directory
+        .unwrap_or_else(system_download_dir)
+}
+
+/// Change the download directory (`None` goes back to the system Downloads
+/// folder). Already downloaded files stay where they are; the history keeps
+/// their absolute paths.
+pub fn set_download_dir(directory: Option<PathBuf>) -> Result<PathBuf, String> {
+    let directory = directory.map(|dir| super::checked_path(&dir)).transpose()?;
+    if let Some(dir) = &directory {
+        check_writable(dir)?;
+    }
+    super::settings::update(|s| s.downloads.directory = directory)
+        .map_err(|e| format!("failed to save download directory: {}", e))?;
+    let dir = download_dir();
+    info!("Download directory set to {}", dir.display());
+    Ok(dir)
+}
+
+/// Final path for `filename` given the page's `target`, checked to be writable
+pub fn resolve_destination(filename: &str, target: &DownloadTarget) -> Result<PathBuf, String> {
+    let destination = match (&target.save_path, &target.target_dir) {
+        (Some(path), _) => {
+            path_checks::check_path(path).map_err(|e| e.to_string())?;
+            path.clone()
+        }
+        (None, Some(dir)) => {
+            path_checks::check_path(dir).map_err(|e| e.to_string())?;
+            path_checks::sanitize_target_path(dir, filename).map_err(|e| e.to_string())?
+        }
+        // Only a name inside the download directory: the page must not smuggle in `..\` via it
+        (None, None) => path_checks::sanitize_target_path(&download_dir(), filename).map_err(|e| e.to_string())?,
+    };
+    let destination = super::checked_path(&destination)?;
+
+    let dir = destination.parent().ok_or("destination has no parent directory")?;
+    if target.save_path.is_none() && target.target_dir.is_none() {
+        std::fs::create_dir_all(dir).map_err(|e| format!("cannot create {}: {}", dir.display(), e))?;
+    }
+    check_writable(dir)?;
+    Ok(destination)
+}
+
+/// Metadata can't tell about ACLs or read-only media; creating a file can
+fn check_writable(dir: &Path) -> Result<(), String> {
+    if !dir.is_dir() {
+        return Err(format!("folder does not exist: {}", dir.display()));
+    }
+    let probe = dir.join(format!(".miko-write-test-{}", uuid::Uuid::new_v4().simple()));
+    std::fs::File::create(&probe).map_err(|e| format!("cannot write to {}: {}", dir.display(), e))?;
+    let _ = std::fs::remove_file(&probe);
+    Ok(())
+}
+
+/// Where staged downloads are kept
+pub fn staging_dir() -> PathBuf {
+    crate::paths::data_dir().join("downloads")
+}
+
+/// Where the downloader writes before the file is moved to `destination`.
+/// The name is derived from the destination, so a download interrupted by
+/// quitting continues from the same file when it is started again.
+pub fn prepare_staging(destination: &Path) -> io::Result<PathBuf> {
+    use sha2::{Digest, Sha256};
+
+    let dir = staging_dir();
+    std::fs::create_dir_all(&dir)?;
+    let name = destination.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+    let digest = Sha256::digest(destination.to_string_lossy().as_bytes());
+    let key: String = digest[..8].iter().map(|b| format!("{:02x}", b)).collect();
+    Ok(dir.join(format!("{}-{}.part", key, name)))
+}
+
+/// Move a finished download into place. A rename across volumes fails, so
+/// that falls back to copying next to the destination, renaming there and
+/// deleting the staged file.
+pub fn move_into_place(staged: &Path, destination: &Path) -> io::Result<()> {
+    if std::fs::rename(staged, destination).is_ok() {
+        return Ok(());
+    }
+
+    let mut partial = destination.as_os_str().to_owned();
+    partial.push(".part");
+    let partial = PathBuf::from(partial);
+    let result = std::fs::copy(staged, &partial).and_then(|_| std::fs::rename(&partial, destination));
+    if result.is_err() {
+        let _ = std::fs::remove_file(&partial);
+    }
+    result?;
+    info!("Copied download across volumes to {}", destination.display());
+    if let Err(e) = std::fs::remove_file(staged) {
+        warn!("Failed to remove staged download {}: {}", staged.display(), e);
+    }
+    Ok(())
+}
+
+/// Remember where a finished download went
+pub fn record(filename: &str, path: &Path) {
+    let mut history = HISTORY.lock().unwrap();
+    history.push(DownloadRecord {
+        filename: filename.to_string(),
+        path: path.to_path_buf(),
+        completed_at: chrono::Utc::now(),
+    });
+    let excess = history.len().saturating_sub(MAX_HISTORY);
+    history.drain(..excess);
+    save_history(&history);
+}
+
+/// Forget every finished download (`clear_download_history`)
+pub fn clear_history() {
+    let mut history = HISTORY.lock().unwrap();
+    history.clear();
+    save_history(&history);
+    info!("Download history cleared");
+}
+
+fn save_history(history: &[DownloadRecord]) {
+    match serde_json::to_vec(history) {
+        Ok(bytes) => {
+            if let Err(e) = super::write_atomic(&history_path(), &bytes) {
+                warn!("Failed to save download history: {}", e);
+            }
+        }
+        Err(e) => warn!("Failed to serialize download history: {}", e),
+    }
+    TRAY_DIRTY.store(true, Ordering::SeqCst);
+    crate::ipc::wake();
+}
+
+/// The `count` newest finished downloads, newest first
+pub fn recent(count: usize) -> Vec<DownloadRecord> {
+    HISTORY.lock().unwrap().iter().rev().take(count).cloned().collect()
+}
+
+/// True if the history changed since the last call (the tray lists the newest)
+pub fn take_tray_update() -> bool {
+    TRAY_DIRTY.swap(false, Ordering::SeqCst)
+}
+
+/// `filename` cut to `max_chars` with an ellipsis in the middle, so the
+/// extension stays visible
+pub fn shortened_name(filename: &str, max_chars: usize) -> String {
+    let chars: Vec<char> = filename.chars().collect();
+    if chars.len() <= max_chars || max_chars < 5 {
+        return filename.to_string();
+    }
+    let extension = filename.rfind('.').map_or(0, |dot| filename[dot..].chars().count());
+    // At least the extension and a few characters before it stay at the end
+    let tail = (extension + 4).min(max_chars / 2).max((max_chars - 1) / 3);
+    let head = max_chars - 1 - tail;
+    let mut name: String = chars[..head].iter().collect();
+    name.push('…');
+    name.extend(&chars[chars.len() - tail..]);
+    name
+}
+
+/// Where the most recent download named `filename` was saved
+pub fn recorded_path(filename: &str) -> Option<PathBuf> {
+    HISTORY
+        .lock()
+        .unwrap()
+        .iter()
+        .rev()
+        .find(|record| record.filename == filename)
+        .map(|record| record.path.clone())
+}
+
+/// A download in the history was saved at exactly `path`
+fn is_recorded(path: &Path) -> bool {
+    path.is_absolute() && HISTORY.lock().unwrap().iter().any(|record| record.path == path)
+}
+
+/// Resolve a `show_in_folder` reference: a full path (preferred) or a bare
+/// filename from older pages, looked up in the history and then the download
+/// directory. `Ok` is the canonical path of the existing file, `Err` the folder
+/// it should have been in.
+pub fn locate(reference: &str) -> Result<PathBuf, PathBuf> {
+    let given = Path::new(reference);
+    let candidate = if is_recorded(given) {
+        given.to_path_buf()
+    } else {
+        match path_checks::sanitize_target_path(&download_dir(), reference) {
+            Ok(path) if given.is_absolute() => path,
+            Ok(path) => path
+                .file_name()
+                .and_then(|name| recorded_path(&name.to_string_lossy()))
+                .unwrap_or(path),
+            Err(e) => {
+                warn!("Refusing to show {}: {}", reference, e);
+                return Err(download_dir());
+            }
+        }
+    };
+
+    match std::fs::canonicalize(&candidate) {
+        Ok(path) if path.is_file() => Ok(path),
+        _ => Err(candidate
+            .parent()
+            .filter(|dir| dir.is_dir())
+            .map(|dir| dir.to_path_buf())
+            .unwrap_or_else(download_dir)),
+    }
+}
+
+/// Canonical path of a file the app downloaded: recorded in the history or in
+/// the download directory. The page may only hand these to native code.
+pub fn downloaded_file(path: &str) -> Result<PathBuf, String> {
+    // Resolving a network path already contacts the server; only saved downloads may be there
+    if path_checks::is_unc(path) && !is_recorded(Path::new(path)) {
+        return Err(path_checks::PathError::Unc(path.to_string()).to_string());
+    }
+    let path = std::fs::canonicalize(path).map_err(|e| format!("{}: {}", path, e))?;
+    if !path.is_file() {
+        return Err(format!("not a file: {}", path.display()));
+    }
+
+    let in_downloads = std::fs::canonicalize(download_dir()).is_ok_and(|dir| path.starts_with(dir));
+    let recorded = HISTORY
+        .lock()
+        .unwrap()
+        .iter()
+        .any(|record| std::fs::canonicalize(&record.path).is_ok_and(|p| p == path));
+    if !in_downloads && !recorded {
+        return Err(format!("not a downloaded file: {}", path.display()));
+    }
+    Ok(path)
+}
+
+/// A native drag-out of a downloaded file, requested by `begin_file_drag`
+#[derive(Debug)]
+pub struct FileDrag {
+    pub path: PathBuf,
+    /// PNG from the page's `iconDataUrl`, shown under the cursor where supported
+    pub icon_png: Option<Vec<u8>>,
+    pub request_id: Option<String>,
+}
+
+impl FileDrag {
+    /// Build from a `begin_file_drag` message; only files the app downloaded
+    /// (recorded in the history or in the download directory) may be dragged
+    pub fn from_message(message: &Value) -> Result<Self, String> {
+        let path = message["path"].as_str().ok_or("begin_file_drag needs a path")?;
+        let path = downloaded_file(path)?;
+
+        Ok(Self {
+            path,
+            icon_png: message["iconDataUrl"].as_str().and_then(decode_png_data_url),
+            request_id: message["requestId"].as_str().map(|s| s.to_string()),
+        })
+    }
+
+    /// Tell the page how the drag ended (`dropped` is false when cancelled)
+    pub fn finished(&self, result: Result<bool, String>) {
+        let path = self.path.to_string_lossy();
+        let value = match result {
+            Ok(dropped) => serde_json::json!({ "success": true, "path": path, "dropped": dropped }),
+            Err(e) => {
+                warn!("File drag of {} failed: {}", path, e);
+                serde_json::json!({ "success": false, "path": path, "error": e })
+            }
+        };
+        crate::ipc::respond(self.request_id.as_deref(), "file-drag-finished", &value);
+    }
+}
+
+fn decode_png_data_url(url: &str) -> Option<Vec<u8>> {
+    use base64::Engine;
+    let data = url.strip_prefix("data:image/png;base64,")?;
+    base64::engine::general_purpose::STANDARD.decode(data).ok()
+}
+
+/// Why a running download was asked to stop
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    /// Keep the staging file so the download can resume
+    Pause,
+    Cancel,
+}
+
+/// Starts the download again under the given transfer id; the platform
+/// layer's downloader resumes from the staging file if one is left
+pub type Restart = Arc<dyn Fn(String) + Send + Sync>;
+
+struct Job {
+    stop: Arc<AtomicBool>,
+    reason: Option<StopReason>,
+    running: bool,
+    /// `None` for a restored download that never got as far as creating one
+    staging: Option<PathBuf>,
+    restart: Restart,
+}
+
+/// What it takes to start a download again, also after a restart
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DownloadSpec {
+    pub url: String,
+    pub filename: String,
+    /// The page's headers minus credentials, which aren't written to disk
+    headers: Vec<(String, String)>,
+    target_dir: Option<PathBuf>,
+    save_path: Option<PathBuf>,
+}
+
+impl DownloadSpec {
+    pub fn new(url: &str, filename: &str, headers: &[(String, String)], target: &DownloadTarget) -> Self {
+        Self {
+            url: url.to_string(),
+            filename: filename.to_string(),
+            headers: headers.iter().filter(|(name, _)| !crate::logging::is_credential_header(name)).cloned().collect(),
+            target_dir: target.target_dir.clone(),
+            save_path: target.save_path.clone(),
+        }
+    }
+
+    /// The saved headers plus the signed-in user's token
+    pub fn headers(&self) -> Vec<(String, String)> {
+        let mut headers = self.headers.clone();
+        if let Some(token) = super::state::runtime().auth.token.clone() {
+            headers.push(("Authorization".to_string(), format!("Bearer {}", token)));
+        }
+        headers
+    }
+
+    pub fn target(&self) -> DownloadTarget {
+        DownloadTarget { target_dir: self.target_dir.clone(), save_path: self.save_path.clone() }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum JournalState {
+    /// Deferred on a metered connection; nothing downloaded yet
+    Waiting,
+    /// Running, or interrupted by quitting or a crash
+    Active,
+    /// Paused by the user
+    Paused,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JournalEntry {
+    spec: DownloadSpec,
+    state: JournalState,
+    staging: Option<PathBuf>,
+    destination: Option<PathBuf>,
+    /// Bytes in the staging file when the state last changed
+    offset: u64,
+}
+
+/// What becomes of a journaled download at the next start
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Recovery {
+    /// Start again (resuming from the staging file, if any) once signed in
+    Restart,
+    /// Leave it to the user to resume
+    Paused { offset: u64 },
+    /// Bytes were downloaded but the staging file is gone
+    Vanished,
+}
+
+/// `staged` is the size of the entry's staging file, if there is one
+fn recover(entry: &JournalEntry, staged: Option<u64>) -> Recovery {
+    match (entry.state, staged) {
+        (JournalState::Waiting, _) | (JournalState::Active, Some(_)) => Recovery::Restart,
+        (JournalState::Paused, Some(offset)) => Recovery::Paused { offset },
+        // Nothing was downloaded yet, so starting over loses nothing
+        (_, None) if entry.offset == 0 => Recovery::Restart,
+        (_, None) => Recovery::Vanished,
+    }
+}
+
+fn journal_path() -> PathBuf {
+    crate::paths::data_dir().join("download_journal.json")
+}
+
+fn load_journal() -> BTreeMap<String, JournalEntry> {
+    std::fs::read(journal_path())
+        .ok()
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .unwrap_or_default()
+}
+
+/// Change the journal and save it right away
+fn journal(change: impl FnOnce(&mut BTreeMap<String, JournalEntry>)) {
+    let mut journal = JOURNAL.lock().unwrap();
+    change(&mut journal);
+    match serde_json::to_vec(&*journal) {
+        Ok(bytes) => {
+            if let Err(e) = super::write_atomic(&journal_path(), &bytes) {
+                warn!("Failed to save the download journal: {}", e);
+            }
+        }
+        Err(e) => warn!("Failed to serialize the download journal: {}", e),
+    }
+}
+
+fn staged_size(staging: Option<&Path>) -> Option<u64> {
+    std::fs::metadata(staging?).ok().filter(|m| m.is_file()).map(|m| m.len())
+}
+
+/// List the downloads the last run left unfinished (see the module docs).
+/// `restart` makes the platform's [`Restart`] for a download.
+pub fn restore(restart: impl Fn(&DownloadSpec) -> Restart) {
+    let entries = JOURNAL.lock().unwrap().clone();
+    if entries.is_empty() {
+        return;
+    }
+    let mut vanished = Vec::new();
+    for (id, entry) in entries {
+        let recovery = recover(&entry, staged_size(entry.staging.as_deref()));
+        info!("Restoring download {} ({:?}): {:?}", id, entry.state, recovery);
+        let job = Job {
+            stop: Arc::new(AtomicBool::new(false)),
+            reason: None,
+            running: false,
+            staging: entry.staging.clone(),
+            restart: restart(&entry.spec),
+        };
+        JOBS.lock().unwrap().insert(id.clone(), job);
+        transfers::started(&id, TransferKind::Download, &entry.spec.filename, TransferUnit::Bytes);
+        if let Some(destination) = &entry.destination {
+            transfers::set_path(&id, destination);
+        }
+        match recovery {
+            Recovery::Restart => {
+                transfers::progress(&id, staged_size(entry.staging.as_deref()).unwrap_or(0), None);
+                transfers::stopped(&id, TransferState::Paused, None);
+                RESTART_ON_SIGN_IN.lock().unwrap().push(id);
+            }
+            Recovery::Paused { offset } => {
+                transfers::progress(&id, offset, None);
+                transfers::stopped(&id, TransferState::Paused, None);
+            }
+            Recovery::Vanished => {
+                transfers::stopped(&id, TransferState::Failed, Some("The partly downloaded file is gone".to_string()));
+                vanished.push(id);
+            }
+        }
+    }
+    // Retrying starts them over, which journals them again
+    journal(|journal| journal.retain(|id, _| !vanished.contains(id)));
+}
+
+/// Start the restored downloads that were running or waiting, once
+pub fn signed_in() {
+    let ids = std::mem::take(&mut *RESTART_ON_SIGN_IN.lock().unwrap());
+    let resumed = ids.iter().filter(|id| Downloads::restart(id, &[TransferState::Paused]).is_ok()).count();
+    if resumed > 0 {
+        info!("Resumed {} interrupted download(s)", resumed);
+        let message = match resumed {
+            1 => "Resumed 1 interrupted download".to_string(),
+            n => format!("Resumed {} interrupted downloads", n),
+        };
+        let _ = crate::hooks::show_simple_notification("Downloads", &message);
+    }
+}
+
+/// A running download as the platform downloader sees it
+pub struct DownloadJob {
+    pub id: String,
+    /// Set to kill the downloader process
+    pub stop: Arc<AtomicBool>,
+}
+
+pub fn new_job_id() -> String {
+    format!("download-{}", uuid::Uuid::new_v4().simple())
+}
+
+/// Register download `id` as running and list it in the transfers
+pub fn begin_job(id: &str, spec: &DownloadSpec, staging: &Path, destination: &Path, restart: Restart) -> DownloadJob {
+    let stop = Arc::new(AtomicBool::new(false));
+    let job = Job { stop: stop.clone(), reason: None, running: true, staging: Some(staging.to_path_buf()), restart };
+    JOBS.lock().unwrap().insert(id.to_string(), job);
+    journal(|journal| {
+        let entry = JournalEntry {
+            spec: spec.clone(),
+            state: JournalState::Active,
+            staging: Some(staging.to_path_buf()),
+            destination: Some(destination.to_path_buf()),
+            offset: staged_size(Some(staging)).unwrap_or(0),
+        };
+        journal.insert(id.to_string(), entry);
+    });
+    transfers::started(id, TransferKind::Download, &spec.filename, TransferUnit::Bytes);
+    transfers::set_path(id, destination);
+    DownloadJob { id: id.to_string(), stop }
+}
+
+/// Record how the download `id` ended. Returns the reason if it was stopped
+/// on request, which decides whether the staging file is kept.
+pub fn end_job(id: &str, result: &Result<(), String>) -> Option<StopReason> {
+    let mut jobs = JOBS.lock().unwrap();
+    let reason = jobs.get_mut(id).and_then(|job| {
+        job.running = false;
+        job.reason.take()
+    });
+    let (state, error) = match (result, reason) {
+        (Ok(()), _) => (TransferState::Completed, None),
+        (Err(_), Some(StopReason::Pause)) => (TransferState::Paused, None),
+        (Err(_), Some(StopReason::Cancel)) => (TransferState::Cancelled, None),
+        // The staging file is kept for the next start
+        (Err(_), None) if super::shutdown::is_quitting() => (TransferState::Paused, None),
+        (Err(e), None) => (TransferState::Failed, Some(e.clone())),
+    };
+    if state == TransferState::Completed {
+        jobs.remove(id);
+    }
+    drop(jobs);
+    match state {
+        TransferState::Paused if reason == Some(StopReason::Pause) => journal(|journal| {
+            if let Some(entry) = journal.get_mut(id) {
+                entry.state = JournalState::Paused;
+                entry.offset = staged_size(entry.staging.as_deref()).unwrap_or(0);
+            }
+        }),
+        // Interrupted by quitting: stays active, so it resumes at the next start
+        TransferState::Paused => {}
+        _ => journal(|journal| {
+            journal.remove(id);
+        }),
+    }
+    transfers::stopped(id, state, error);
+    reason
+}
+
+/// List download `id` as waiting for an unmetered connection; `start` runs
+/// it then, or when it's resumed from the Transfers panel
+pub fn defer(id: &str, spec: &DownloadSpec, start: Restart) {
+    DEFERRED.lock().unwrap().insert(id.to_string(), start);
+    journal(|journal| {
+        let entry = JournalEntry { spec: spec.clone(), state: JournalState::Waiting, staging: None, destination: None, offset: 0 };
+        journal.insert(id.to_string(), entry);
+    });
+    transfers::waiting(id, TransferKind::Download, &spec.filename, TransferUnit::Bytes);
+    let id = id.to_string();
+    crate::metered::when_unmetered(move || {
+        start_deferred(&id);
+    });
+}
+
+/// Start the deferred download `id`; false if it isn't waiting (any more)
+fn start_deferred(id: &str) -> bool {
+    let Some(start) = DEFERRED.lock().unwrap().remove(id) else { return false };
+    start(id.to_string());
+    true
+}
+
+/// Staging files of the downloads this run knows about (running, paused or failed)
+pub fn staging_files() -> Vec<PathBuf> {
+    JOBS.lock().unwrap().values().filter_map(|job| job.staging.clone()).collect()
+}
+
+/// Downloads as listed in [`transfers`]
+pub struct Downloads;
+
+impl Downloads {
+    fn stop(id: &str, reason: StopReason) -> Result<(), String> {
+        let mut jobs = JOBS.lock().unwrap();
+        let job = jobs.get_mut(id).filter(|job| job.running).ok_or_else(|| format!("download {} is not running", id))?;
+        job.reason = Some(reason);
+        job.stop.store(true, Ordering::SeqCst);
+        Ok(())
+    }
+
+    /// Start the stopped download `id` again if it is in one of `states`
+    fn restart(id: &str, states: &[TransferState]) -> Result<(), String> {
+        let restart = {
+            let mut jobs = JOBS.lock().unwrap();
+            let job = jobs.get_mut(id).filter(|job| !job.running).ok_or_else(|| format!("download {} can't be restarted", id))?;
+            if !transfers::state_of(id).is_some_and(|state| states.contains(&state)) {
+                return Err(format!("download {} can't be restarted", id));
+            }
+            // Until the downloader registers again; keeps a double click from starting two
+            job.running = true;
+            job.restart.clone()
+        };
+        restart(id.to_string());
+        Ok(())
+    }
+}
+
+impl TransferControl for Downloads {
+    fn pause(&self, id: &str) -> Result<(), String> {
+        Self::stop(id, StopReason::Pause)
+    }
+
+    fn resume(&self, id: &str) -> Result<(), String> {
+        // Download anyway
+        if start_deferred(id) {
+            return Ok(());
+        }
+        Self::restart(id, &[TransferState::Paused])
+    }
+
+    fn cancel(&self, id: &str) -> Result<(), String> {
+        if Self::stop(id, StopReason::Cancel).is_ok() {
+            return Ok(());
+        }
+        if DEFERRED.lock().unwrap().remove(id).is_some() {
+            journal(|journal| {
+                journal.remove(id);
+            });
+            transfers::stopped(id, TransferState::Cancelled, None);
+            return Ok(());
+        }
+        // A paused download only has its staging file left to clean up
+        let staging = match JOBS.lock().unwrap().get(id) {
+            Some(job) if !job.running && transfers::state_of(id) == Some(TransferState::Paused) => job.staging.clone(),
+            _ => return Err(format!("download {} is not running", id)),
+        };
+        if let Some(staging) = staging.filter(|staging| staging.exists()) {
+            if let Err(e) = std::fs::remove_file(&staging) {
+                warn!("Failed to remove staged download {}: {}", staging.display(), e);
+            }
+        }
+        journal(|journal| {
+            journal.remove(id);
+        });
+        transfers::stopped(id, TransferState::Cancelled, None);
+        Ok(())
+    }
+
+    fn retry(&self, id: &str) -> Result<(), String> {
+        Self::restart(id, &[TransferState::Failed, TransferState::Cancelled])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Staging files of a synthetic crashed run, removed on drop
+    struct Fixture {
+        root: PathBuf,
+    }
+
+    impl Fixture {
+        fn new() -> Self {
+            let root = std::env::temp_dir().join(format!("miko-downloads-{}", uuid::Uuid::new_v4().simple()));
+            std::fs::create_dir_all(&root).unwrap();
+            Self { root }
+        }
+
+        /// A journal entry for `name`, its staging file `staged` bytes long
+        /// (`None`: not there)
+        fn entry(&self, name: &str, state: JournalState, offset: u64, staged: Option<usize>) -> JournalEntry {
+            let staging = self.root.join(format!("{}.part", name));
+            if let Some(len) = staged {
+                std::fs::write(&staging, vec![0u8; len]).unwrap();
+            }
+            let spec = DownloadSpec::new(&format!("https://erp.example.com/files/{}", name), name, &[], &DownloadTarget::default());
+            JournalEntry { spec, state, staging: Some(staging), destination: Some(self.root.join(name)), offset }
+        }
+    }
+
+    impl Drop for Fixture {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.root);
+        }
+    }
+
+    #[test]
+    fn the_journal_is_reconciled_with_what_is_on_disk() {
+        use JournalState::{Active, Paused, Waiting};
+
+        let fixture = Fixture::new();
+        let cases = [
+            // (journaled, offset, staging file, outcome)
+            (Waiting, 0, None, Recovery::Restart),
+            (Active, 4096, Some(4096), Recovery::Restart),
+            // The file grew after the last journal write; it is what resumes
+            (Active, 1024, Some(8192), Recovery::Restart),
+            (Active, 0, None, Recovery::Restart),
+            (Active, 4096, None, Recovery::Vanished),
+            (Paused, 4096, Some(4096), Recovery::Paused { offset: 4096 }),
+            // Shorter than journaled (a disk that lost writes): the file wins
+            (Paused, 4096, Some(100), Recovery::Paused { offset: 100 }),
+            (Paused, 0, None, Recovery::Restart),
+            (Paused, 4096, None, Recovery::Vanished),
+        ];
+        for (n, (state, offset, staged, expected)) in cases.into_iter().enumerate() {
+            let entry = fixture.entry(&format!("case-{}", n), state, offset, staged);
+            let recovery = recover(&entry, staged_size(entry.staging.as_deref()));
+            assert_eq!(recovery, expected, "{:?} at {} with {:?} bytes staged", state, offset, staged);
+        }
+
+        let directory = fixture.entry("directory", Active, 4096, None);
+        std::fs::create_dir(directory.staging.as_ref().unwrap()).unwrap();
+        assert_eq!(recover(&directory, staged_size(directory.staging.as_deref())), Recovery::Vanished);
+    }
+
+    #[test]
+    fn a_restart_lists_what_the_crashed_run_left() {
+        let fixture = Fixture::new();
+        let ids = ["restore-running", "restore-paused", "restore-vanished", "restore-waiting"].map(String::from);
+        let entries = [
+            fixture.entry("running.bin", JournalState::Active, 1000, Some(3000)),
+            fixture.entry("paused.bin", JournalState::Paused, 2000, Some(2000)),
+            fixture.entry("vanished.bin", JournalState::Active, 5000, None),
+            JournalEntry { staging: None, destination: None, ..fixture.entry("waiting.bin", JournalState::Waiting, 0, None) },
+        ];
+        journal(|journal| journal.extend(ids.iter().cloned().zip(entries)));
+        // What the next run reads back
+        let reloaded = load_journal();
+        assert!(ids.iter().all(|id| reloaded.contains_key(id)));
+        *JOURNAL.lock().unwrap() = reloaded;
+
+        let specs = Mutex::new(Vec::new());
+        restore(|spec| {
+            specs.lock().unwrap().push(spec.filename.clone());
+            Arc::new(|_| {})
+        });
+
+        let transfers = transfers::snapshot();
+        let listed = |id: &str| (transfers[id]["state"].clone(), transfers[id]["done"].clone());
+        assert_eq!(listed("restore-running"), (Value::from("paused"), Value::from(3000)));
+        assert_eq!(listed("restore-paused"), (Value::from("paused"), Value::from(2000)));
+        assert_eq!(listed("restore-vanished").0, "failed");
+        assert_eq!(transfers["restore-vanished"]["error"], "The partly downloaded file is gone");
+        assert_eq!(listed("restore-waiting"), (Value::from("paused"), Value::from(0)));
+        assert!(specs.lock().unwrap().contains(&"vanished.bin".to_string()));
+
+        let restarting = RESTART_ON_SIGN_IN.lock().unwrap().clone();
+        assert!(restarting.contains(&ids[0]) && restarting.contains(&ids[3]));
+        assert!(!restarting.contains(&ids[1]) && !restarting.contains(&ids[2]));
+        // Failed ones leave the journal; retrying journals them again
+        let journaled = load_journal();
+        assert!(!journaled.contains_key(&ids[2]));
+        assert!([&ids[0], &ids[1], &ids[3]].iter().all(|id| journaled.contains_key(*id)));
+
+        RESTART_ON_SIGN_IN.lock().unwrap().retain(|id| !ids.contains(id));
+        journal(|journal| journal.retain(|id, _| !ids.contains(id)));
+    }
+
+    #[test]
+    fn credentials_stay_out_of_the_journal() {
+        let headers = [("Authorization", "Bearer secret"), ("Cookie", "session=secret"), ("Accept", "*/*")]
+            .map(|(name, value)| (name.to_string(), value.to_string()));
+        let spec = DownloadSpec::new("https://erp.example.com/files/a", "a.bin", &headers, &DownloadTarget::default());
+        let saved = serde_json::to_string(&spec).unwrap();
+        assert!(!saved.contains("secret"), "{}", saved);
+        assert!(saved.contains("Accept"));
+    }
+}
@@ -0,0 +1,58 @@
+//! Composer drafts kept by the host so they survive webview reloads and
+//! renderer crashes.
+//!
+//! The page sends `save_draft` (debounced) while the user types and asks for
+//! `get_drafts` after it loads. Drafts live only in this process, keyed by the
+//! signed-in user so a shared machine never shows one account's text to
+//! another; signing out drops the departing account's drafts.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use lazy_static::lazy_static;
+use serde_json::{json, Value};
+use tracing::{debug, info};
+
+/// Longer drafts are refused rather than truncated
+const MAX_DRAFT_BYTES: usize = 64 * 1024;
+
+lazy_static! {
+    /// user id -> thread id -> text
+    static ref DRAFTS: Mutex<HashMap<String, HashMap<String, String>>> = Mutex::new(HashMap::new());
+}
+
+fn current_account() -> Option<String> {
+    let state = super::state::runtime();
+    state.auth.signed_in.then(|| state.auth.user_id.clone()).flatten()
+}
+
+/// Handle `save_draft`; empty text removes the draft
+pub fn save(thread_id: &str, text: &str) -> Result<(), String> {
+    if text.len() > MAX_DRAFT_BYTES {
+        return Err(format!("draft for {} is too long ({} bytes)", thread_id, text.len()));
+    }
+    let account = current_account().ok_or("not signed in")?;
+    let mut drafts = DRAFTS.lock().unwrap();
+    let threads = drafts.entry(account).or_default();
+    if text.trim().is_empty() {
+        threads.remove(thread_id);
+    } else {
+        threads.insert(thread_id.to_string(), text.to_string());
+    }
+    debug!("Saved draft for {} ({} bytes)", thread_id, text.len());
+    Ok(())
+}
+
+/// Result of `get_drafts` for the signed-in account
+pub fn snapshot() -> Value {
+    let drafts = current_account()
+        .and_then(|account| DRAFTS.lock().unwrap().get(&account).cloned())
+        .unwrap_or_default();
+    json!({ "success": true, "drafts": drafts })
+}
+
+/// Forget the drafts of `user_id`, e.g. on logout
+pub fn clear_account(user_id: &str) {
+    if let Some(threads) = DRAFTS.lock().unwrap().remove(user_id) {
+        info!("Dropped {} draft(s) of the signed-out account", threads.len());
+    }
+}
@@ -0,0 +1,283 @@
+//! ERP latency polling behind the `connection-quality` state topic.
+//!
+//! The connectivity monitor only tells reachable from unreachable; the status
+//! dot also wants to know how quickly the ERP answers. A background thread
+//! times a request to the ERP every `health.poll_interval_secs` (default 30 s)
+//! and keeps the round trips of the last [`WINDOW`]. Each wait gets up to
+//! [`MAX_JITTER`] added so desktops started together don't poll in step. While
+//! the server doesn't answer, polls start again after [`DOWN_RETRY_MIN`] and
+//! back off exponentially up to the normal interval, so a recovery shows up
+//! quickly without hammering a server that is coming back. [`poll_now`]
+//! skips the wait and the backoff, e.g. after the machine wakes up. During
+//! the ERP's maintenance the poller waits for its advertised end instead.
+//!
+//! The failure count is kept in `erp-health.json` in the data directory, so
+//! a fleet relaunched during an outage doesn't all hit the dead server at
+//! once. A count less than [`RESTORE_FOR`] old is taken up again: the ERP
+//! starts out as unreachable (writes are refused and cached threads served
+//! until the connectivity monitor's first probe says otherwise) and the
+//! first poll comes at a random point within the backoff it had reached.
+//! Otherwise the first poll still waits up to [`STARTUP_DELAY`]. Whether the
+//! state was restored is part of `GET /admin/health`.
+
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use chrono::{DateTime, Utc};
+use lazy_static::lazy_static;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tracing::{debug, info, warn};
+
+use super::state::{self, ConnectionQuality, Connectivity};
+
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+const MIN_POLL_INTERVAL: Duration = Duration::from_secs(5);
+/// Latencies older than this don't count towards the average
+pub const WINDOW: Duration = Duration::from_secs(5 * 60);
+/// Fraction of the wait added at random
+const MAX_JITTER: f64 = 0.1;
+const DOWN_RETRY_MIN: Duration = Duration::from_secs(2);
+/// Saved failures older than this are forgotten at startup
+const RESTORE_FOR: Duration = Duration::from_secs(10 * 60);
+/// Longest random wait before the first poll after startup
+const STARTUP_DELAY: Duration = Duration::from_secs(5);
+
+static STARTED: AtomicBool = AtomicBool::new(false);
+
+/// The poller's state as kept across restarts
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Saved {
+    failures: u32,
+    checked_at: Option<DateTime<Utc>>,
+    /// First failed poll of the current outage
+    down_since: Option<DateTime<Utc>>,
+}
+
+lazy_static! {
+    static ref POLL_NOW: Mutex<Option<Sender<()>>> = Mutex::new(None);
+    /// What was taken up from the last run, for `/admin/health`
+    static ref RESTORED: Mutex<Option<Saved>> = Mutex::new(None);
+}
+
+fn saved_path() -> std::path::PathBuf {
+    crate::paths::data_dir().join("erp-health.json")
+}
+
+/// The last run's failures, unless there were none or they are too old
+fn load() -> Option<Saved> {
+    let saved: Saved = serde_json::from_slice(&std::fs::read(saved_path()).ok()?).ok()?;
+    let age = Utc::now().signed_duration_since(saved.checked_at?).to_std().ok()?;
+    (saved.failures > 0 && age < RESTORE_FOR).then_some(saved)
+}
+
+fn save(saved: &Saved) {
+    let result = serde_json::to_vec(saved)
+        .map_err(std::io::Error::other)
+        .and_then(|bytes| super::write_atomic(&saved_path(), &bytes));
+    if let Err(e) = result {
+        warn!("Failed to save the ERP health state: {}", e);
+    }
+}
+
+/// For `GET /admin/health`
+pub fn restored() -> Value {
+    match RESTORED.lock().unwrap().as_ref() {
+        Some(saved) => json!({ "restored": true, "failures": saved.failures, "downSince": saved.down_since }),
+        None => json!({ "restored": false }),
+    }
+}
+
+/// Start the poller thread (once)
+pub fn start_poller() {
+    if STARTED.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    let (tx, rx) = mpsc::channel();
+    *POLL_NOW.lock().unwrap() = Some(tx);
+
+    let restored = load();
+    if let Some(saved) = &restored {
+        info!("ERP was failing at the last run ({} failed polls), resuming the backoff", saved.failures);
+        *RESTORED.lock().unwrap() = Some(saved.clone());
+        state::set_connectivity(Connectivity::ErpUnreachable);
+    }
+    let first_wait = first_wait(restored.as_ref());
+    let mut saved = restored.unwrap_or_default();
+
+    std::thread::spawn(move || {
+        let client = match reqwest::blocking::Client::builder().timeout(PROBE_TIMEOUT).build() {
+            Ok(client) => client,
+            Err(e) => {
+                warn!("ERP health polling disabled: {}", e);
+                return;
+            }
+        };
+
+        let mut samples: VecDeque<(Instant, Duration)> = VecDeque::new();
+        let mut failures = saved.failures;
+        if rx.recv_timeout(first_wait).is_ok() {
+            failures = 0;
+        }
+        loop {
+            let started = Instant::now();
+            let answered = client.get(super::network::ERP_PROBE_URL).send().is_ok();
+            let latency = started.elapsed();
+
+            let now = Instant::now();
+            samples.retain(|(at, _)| now.duration_since(*at) < WINDOW);
+            if answered {
+                if failures > 0 {
+                    info!("ERP answering again after {} failed polls", failures);
+                }
+                failures = 0;
+                samples.push_back((now, latency));
+                debug!("ERP answered in {} ms", latency.as_millis());
+            } else {
+                if failures == 0 {
+                    warn!("ERP health poll failed");
+                }
+                failures += 1;
+            }
+            state::set_connection_quality(quality(answered.then_some(latency), &samples, failures));
+            if let Some(changed) = after_poll(&saved, failures, Utc::now()) {
+                saved = changed;
+                save(&saved);
+            }
+
+            // `POLL_NOW` keeps a sender alive, so this is a wait-or-nudge
+            let wait = crate::erp_maintenance::probe_wait().unwrap_or_else(|| next_wait(failures));
+            if rx.recv_timeout(with_jitter(wait)).is_ok() {
+                failures = 0;
+            }
+        }
+    });
+}
+
+/// A random point within the backoff `restored` had reached, or within
+/// [`STARTUP_DELAY`]
+fn first_wait(restored: Option<&Saved>) -> Duration {
+    let within = restored.map_or(STARTUP_DELAY, |saved| next_wait(saved.failures));
+    within.mul_f64(rand::thread_rng().gen_range(0.0..1.0))
+}
+
+/// What to save after a poll that left `failures`, if that changed
+fn after_poll(saved: &Saved, failures: u32, now: DateTime<Utc>) -> Option<Saved> {
+    (failures != saved.failures).then(|| Saved {
+        failures,
+        checked_at: Some(now),
+        down_since: (failures > 0).then(|| saved.down_since.unwrap_or(now)),
+    })
+}
+
+/// Poll right away and forget the failures so far
+pub fn poll_now() {
+    if let Some(tx) = POLL_NOW.lock().unwrap().as_ref() {
+        let _ = tx.send(());
+    }
+}
+
+fn poll_interval() -> Duration {
+    Duration::from_secs(super::settings::get().health.poll_interval_secs).max(MIN_POLL_INTERVAL)
+}
+
+/// The configured interval while the ERP answers; while it doesn't,
+/// [`DOWN_RETRY_MIN`] doubled per failure, capped at the interval
+fn next_wait(failures: u32) -> Duration {
+    let interval = poll_interval();
+    if failures == 0 {
+        return interval;
+    }
+    DOWN_RETRY_MIN.saturating_mul(1 << (failures - 1).min(16)).min(interval)
+}
+
+fn with_jitter(wait: Duration) -> Duration {
+    wait.mul_f64(1.0 + rand::thread_rng().gen_range(0.0..MAX_JITTER))
+}
+
+fn quality(latency: Option<Duration>, samples: &VecDeque<(Instant, Duration)>, failures: u32) -> ConnectionQuality {
+    let average = (!samples.is_empty())
+        .then(|| samples.iter().map(|(_, latency)| latency.as_millis() as u64).sum::<u64>() / samples.len() as u64);
+    ConnectionQuality {
+        reachable: latency.is_some(),
+        latency_ms: latency.map(|latency| latency.as_millis() as u64),
+        average_ms: average,
+        window_secs: WINDOW.as_secs(),
+        failures,
+        last_checked: Some(chrono::Utc::now()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// When one desktop polls a dead ERP in its first `span` after starting
+    /// with `restored`; the clock is simulated
+    fn polls_after_start(restored: Option<&Saved>, span: Duration) -> Vec<Duration> {
+        let mut failures = restored.map_or(0, |saved| saved.failures);
+        let mut at = first_wait(restored);
+        let mut polls = Vec::new();
+        while at < span {
+            polls.push(at);
+            failures += 1;
+            at += with_jitter(next_wait(failures));
+        }
+        polls
+    }
+
+    #[test]
+    fn an_outage_is_taken_up_again_only_while_recent() {
+        let _ = std::fs::remove_file(saved_path());
+        assert!(load().is_none());
+
+        let now = Utc::now();
+        let mut saved = Saved::default();
+        let first = now - chrono::Duration::minutes(2);
+        for (minutes_ago, failures) in [(2, 1), (1, 2), (0, 3)] {
+            saved = after_poll(&saved, failures, now - chrono::Duration::minutes(minutes_ago)).unwrap();
+        }
+        assert!(after_poll(&saved, 3, now).is_none());
+        assert_eq!(saved.down_since, Some(first));
+        save(&saved);
+        let restored = load().expect("a recent outage is restored");
+        assert_eq!((restored.failures, restored.down_since), (3, Some(first)));
+
+        let stale = Saved { checked_at: Some(now - chrono::Duration::from_std(RESTORE_FOR).unwrap()), ..saved.clone() };
+        save(&stale);
+        assert!(load().is_none());
+        save(&after_poll(&saved, 0, now).unwrap());
+        assert!(load().is_none());
+        std::fs::write(saved_path(), b"{\"failures\":").unwrap();
+        assert!(load().is_none());
+        let _ = std::fs::remove_file(saved_path());
+    }
+
+    #[test]
+    fn a_fleet_restarted_during_an_outage_spreads_its_polls_out() {
+        const FLEET: usize = 200;
+        let span = Duration::from_secs(60);
+        let interval = poll_interval();
+        let outage = Saved { failures: 6, checked_at: Some(Utc::now()), down_since: Some(Utc::now()) };
+
+        let restored: Vec<Vec<Duration>> = (0..FLEET).map(|_| polls_after_start(Some(&outage), span)).collect();
+        // A random start within the interval, then never closer than it
+        let most = span.as_secs().div_ceil(interval.as_secs()) as usize;
+        assert!(restored.iter().all(|polls| polls.len() <= most), "{:?}", restored.iter().map(Vec::len).max());
+        let mut per_second = vec![0; span.as_secs() as usize];
+        for at in restored.iter().flatten() {
+            per_second[at.as_secs() as usize] += 1;
+        }
+        // About FLEET / 15 expected in each
+        assert!(per_second.iter().all(|polls| *polls < FLEET / 8), "{:?}", per_second);
+
+        // Without it, every desktop would poll within the startup delay and
+        // back off from scratch
+        let fresh: Vec<Vec<Duration>> = (0..FLEET).map(|_| polls_after_start(None, span)).collect();
+        assert!(fresh.iter().all(|polls| polls[0] < STARTUP_DELAY && polls.len() > most));
+    }
+}
@@ -0,0 +1,59 @@
+//! Application state shared by the platform layers.
+//!
+//! `state` holds what only lives for the current process, `settings` holds
+//! what the user expects to survive a restart.
+
+pub mod downloads;
+pub mod drafts;
+pub mod health;
+pub mod network;
+pub mod offline_cache;
+pub mod path_checks;
+pub mod power;
+pub mod session_state;
+pub mod settings;
+pub mod shutdown;
+pub mod state;
+pub mod sync;
+pub mod transfer_status;
+pub mod transfers;
+pub mod unread;
+
+use std::io::Write;
+use std::path::{Component, Path, PathBuf};
+
+/// Write through a temporary file + rename so readers never see a torn file.
+/// The temporary file is synced before the rename, so after a crash the path
+/// holds either the old or the new contents.
+pub fn write_atomic(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let mut file = std::fs::File::create(&tmp)?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    drop(file);
+    std::fs::rename(&tmp, path)
+}
+
+/// Longest path accepted from the page
+const MAX_PATH_CHARS: usize = 4096;
+
+/// Check a path that came from the page: absolute, no `..`, no NUL, bounded
+/// length. Returns it normalized (`.` and repeated separators dropped); the
+/// target need not exist yet.
+pub fn checked_path(path: &Path) -> Result<PathBuf, String> {
+    let text = path.to_string_lossy();
+    if text.chars().count() > MAX_PATH_CHARS || text.contains('\0') {
+        return Err("invalid path".to_string());
+    }
+    if !path.is_absolute() {
+        return Err(format!("not an absolute path: {}", path.display()));
+    }
+    if path.components().any(|c| c == Component::ParentDir) {
+        return Err(format!("path must not contain '..': {}", path.display()));
+    }
+    Ok(path.components().collect())
+}
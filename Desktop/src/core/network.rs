@@ -0,0 +1,214 @@
+//! Connectivity monitor behind the `connectivity` state topic and the tray
+//! icon variant.
+//!
+//! A background thread re-assesses reachability whenever the OS reports an
+//! address change (Windows) or on a timer, probing both the internet (which
+//! also reveals captive portals) and the ERP server. A new assessment must hold
+//! for [`DEBOUNCE`] before it is published, so a flapping link doesn't spam
+//! the page. During a grace period ([`allow_grace`], after a wake from sleep)
+//! only a recovery is published, since the link and the VPN are still coming
+//! up. Each time the ERP is found reachable its API version is checked again
+//! (see [`crate::compat`]); while it is in maintenance (see
+//! [`crate::erp_maintenance`]) it counts as `erp_maintenance`. Whether the connection is metered is read from the
+//! OS with every assessment and published right away (see [`crate::metered`]).
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use lazy_static::lazy_static;
+use tracing::{debug, info, warn};
+
+use super::state::{self, Connectivity, ProxyStatus};
+
+/// Well-known page with a fixed body; portals answer it with their login page
+const INTERNET_PROBE_URL: &str = "http://www.msftconnecttest.com/connecttest.txt";
+const INTERNET_PROBE_BODY: &str = "Microsoft Connect Test";
+pub(super) const ERP_PROBE_URL: &str = "http://10.10.60.8:1669";
+
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+const POLL_INTERVAL: Duration = Duration::from_secs(30);
+/// How long a changed assessment has to persist before it is published
+const DEBOUNCE: Duration = Duration::from_secs(5);
+const RECHECK_INTERVAL: Duration = Duration::from_secs(2);
+
+lazy_static! {
+    static ref RECHECK: Mutex<Option<Sender<()>>> = Mutex::new(None);
+    /// End of the current grace period
+    static ref GRACE_UNTIL: Mutex<Option<Instant>> = Mutex::new(None);
+}
+
+/// Set when the published assessment changes; the event loop takes it for the tray
+static TRAY_DIRTY: AtomicBool = AtomicBool::new(false);
+
+/// Start the monitor thread (once)
+pub fn start_monitor() {
+    let (tx, rx) = mpsc::channel();
+    {
+        let mut recheck = RECHECK.lock().unwrap();
+        if recheck.is_some() {
+            return;
+        }
+        *recheck = Some(tx.clone());
+    }
+
+    #[cfg(windows)]
+    watch_address_changes(tx);
+
+    std::thread::spawn(move || {
+        let client = match reqwest::blocking::Client::builder()
+            .timeout(PROBE_TIMEOUT)
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+        {
+            Ok(client) => client,
+            Err(e) => {
+                warn!("Connectivity monitor disabled: {}", e);
+                return;
+            }
+        };
+
+        // Before the first probes, so prefetch at sign-in already knows
+        update_metered();
+        let mut current = assess(&client);
+        info!("Connectivity: {:?}", current);
+        publish(current);
+
+        let mut pending: Option<(Connectivity, Instant)> = None;
+        loop {
+            let wait = if pending.is_some() { RECHECK_INTERVAL } else { POLL_INTERVAL };
+            // `RECHECK` keeps a sender alive, so this is a wait-or-nudge
+            let _ = rx.recv_timeout(wait);
+
+            update_metered();
+            let observed = assess(&client);
+            pending = match pending {
+                _ if observed == current => None,
+                Some((candidate, since)) if candidate == observed => {
+                    if since.elapsed() >= DEBOUNCE && (candidate == Connectivity::Online || !in_grace()) {
+                        info!("Connectivity changed: {:?} -> {:?}", current, observed);
+                        current = observed;
+                        publish(current);
+                        None
+                    } else {
+                        Some((candidate, since))
+                    }
+                }
+                _ => {
+                    debug!("Connectivity looks like {:?}, confirming", observed);
+                    Some((observed, Instant::now()))
+                }
+            };
+        }
+    });
+}
+
+/// Re-assess right away, e.g. after resume or a failed request
+pub fn recheck() {
+    if let Some(tx) = RECHECK.lock().unwrap().as_ref() {
+        let _ = tx.send(());
+    }
+}
+
+/// Don't publish anything but `Online` for `period`
+pub fn allow_grace(period: Duration) {
+    *GRACE_UNTIL.lock().unwrap() = Some(Instant::now() + period);
+}
+
+fn in_grace() -> bool {
+    GRACE_UNTIL.lock().unwrap().is_some_and(|until| Instant::now() < until)
+}
+
+/// Returns the new assessment if it changed since the last call
+pub fn take_tray_update() -> Option<Connectivity> {
+    TRAY_DIRTY
+        .swap(false, Ordering::SeqCst)
+        .then(|| state::runtime().connectivity)
+}
+
+fn publish(connectivity: Connectivity) {
+    state::set_connectivity(connectivity);
+    TRAY_DIRTY.store(true, Ordering::SeqCst);
+    crate::ipc::wake();
+    // The ERP may have been updated while it was out of reach
+    if connectivity == Connectivity::Online {
+        std::thread::spawn(crate::compat::check);
+    }
+}
+
+fn update_metered() {
+    let metered = connection_metered();
+    if state::set_metered(metered) {
+        info!("Connection {}", if metered { "metered" } else { "no longer metered" });
+        crate::metered::changed(metered);
+    }
+}
+
+/// The OS rates the internet connection as costing per byte; only Windows tells
+fn connection_metered() -> bool {
+    #[cfg(target_os = "windows")]
+    {
+        crate::platform::win::utils::metered_connection()
+    }
+    #[cfg(not(target_os = "windows"))]
+    {
+        false
+    }
+}
+
+fn assess(client: &reqwest::blocking::Client) -> Connectivity {
+    let internet = probe_internet(client);
+    let proxy_failed = matches!(state::runtime().proxy, ProxyStatus::Failed { .. });
+    let erp = !proxy_failed && probe_erp(client);
+
+    match (internet, erp) {
+        // It answers, but only with its maintenance page
+        (_, true) if crate::erp_maintenance::active() => Connectivity::ErpMaintenance,
+        (_, true) => Connectivity::Online,
+        (InternetProbe::Captive, false) => Connectivity::Captive,
+        (InternetProbe::Reachable, false) => Connectivity::ErpUnreachable,
+        (InternetProbe::Unreachable, false) => Connectivity::Offline,
+    }
+}
+
+enum InternetProbe {
+    Reachable,
+    Captive,
+    Unreachable,
+}
+
+fn probe_internet(client: &reqwest::blocking::Client) -> InternetProbe {
+    match client.get(INTERNET_PROBE_URL).send() {
+        Ok(response) if response.status().is_success() => match response.text() {
+            Ok(body) if body.trim() == INTERNET_PROBE_BODY => InternetProbe::Reachable,
+            _ => InternetProbe::Captive,
+        },
+        // Portals typically redirect the probe to their login page
+        Ok(_) => InternetProbe::Captive,
+        Err(_) => InternetProbe::Unreachable,
+    }
+}
+
+/// Any HTTP answer counts; only transport failures mean unreachable
+fn probe_erp(client: &reqwest::blocking::Client) -> bool {
+    client.get(ERP_PROBE_URL).send().is_ok()
+}
+
+/// Nudge the monitor whenever an IP address is added or removed
+#[cfg(windows)]
+fn watch_address_changes(tx: Sender<()>) {
+    use windows::Win32::NetworkManagement::IpHelper::NotifyAddrChange;
+
+    std::thread::spawn(move || loop {
+        // Without a handle/overlapped the call blocks until the next change
+        let result = unsafe { NotifyAddrChange(std::ptr::null_mut(), std::ptr::null()) };
+        if result != 0 {
+            warn!("NotifyAddrChange failed ({}), falling back to polling", result);
+            return;
+        }
+        debug!("Network address change notified");
+        if tx.send(()).is_err() {
+            return;
+        }
+    });
+}
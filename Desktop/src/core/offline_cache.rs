@@ -0,0 +1,112 @@
+//! Recent threads readable without a connection.
+//!
+//! Every successful page `GET` of the thread list (`/api/chats`) or of a
+//! thread's messages (`/api/chats/{uuid}/messages`) is kept on disk under
+//! `offline` in the data directory, one folder per account and at most
+//! [`MAX_ACCOUNT_BYTES`] per folder (oldest dropped first). While the ERP is
+//! unreachable those requests are answered from here, marked
+//! `X-Miko-Offline: true` and `X-Miko-Cached-At` so the page can say how old
+//! the data is. Signing out deletes the account's folder.
+
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use tracing::{debug, info, warn};
+
+const MAX_ACCOUNT_BYTES: u64 = 32 * 1024 * 1024;
+/// Larger answers aren't kept
+const MAX_ENTRY_BYTES: usize = 4 * 1024 * 1024;
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Entry {
+    path: String,
+    fetched_at: chrono::DateTime<chrono::Utc>,
+    body: Value,
+}
+
+/// Answered from here when offline: the thread list and thread messages
+pub fn cacheable(path: &str) -> bool {
+    let segments: Vec<&str> = path.trim_end_matches('/').split('/').collect();
+    matches!(segments.as_slice(), ["", "api", "chats"]) || matches!(segments.as_slice(), ["", "api", "chats", chat, "messages"] if !chat.is_empty())
+}
+
+fn short_hash(text: &str) -> String {
+    Sha256::digest(text.as_bytes())[..8].iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn account_dir(user_id: &str) -> PathBuf {
+    crate::paths::data_dir().join("offline").join(short_hash(user_id))
+}
+
+fn entry_path(user_id: &str, path: &str) -> PathBuf {
+    account_dir(user_id).join(format!("{}.json", short_hash(path)))
+}
+
+fn current_account() -> Option<String> {
+    let state = super::state::runtime();
+    state.auth.signed_in.then(|| state.auth.user_id.clone()).flatten()
+}
+
+/// Keep a successful answer to `path` for the signed-in account
+pub fn store(path: &str, body: &[u8]) {
+    let Some(user_id) = current_account() else { return };
+    if body.len() > MAX_ENTRY_BYTES {
+        return;
+    }
+    let Ok(body) = serde_json::from_slice::<Value>(body) else { return };
+    let entry = Entry { path: path.to_string(), fetched_at: chrono::Utc::now(), body };
+    let result = serde_json::to_vec(&entry)
+        .map_err(std::io::Error::other)
+        .and_then(|bytes| super::write_atomic(&entry_path(&user_id, path), &bytes));
+    match result {
+        Ok(()) => enforce_cap(&account_dir(&user_id)),
+        Err(e) => warn!("Failed to cache {} for offline use: {}", path, e),
+    }
+}
+
+/// The kept answer to `path` and when it was fetched
+pub fn load(path: &str) -> Option<(Vec<u8>, chrono::DateTime<chrono::Utc>)> {
+    let user_id = current_account()?;
+    let bytes = std::fs::read(entry_path(&user_id, path)).ok()?;
+    match serde_json::from_slice::<Entry>(&bytes) {
+        // A hash collision would be a different path
+        Ok(entry) if entry.path == path => {
+            debug!("Serving {} from the offline cache ({})", path, entry.fetched_at);
+            Some((serde_json::to_vec(&entry.body).ok()?, entry.fetched_at))
+        }
+        _ => None,
+    }
+}
+
+/// Drop the oldest entries until the folder fits [`MAX_ACCOUNT_BYTES`]
+fn enforce_cap(dir: &Path) {
+    let Ok(entries) = std::fs::read_dir(dir) else { return };
+    let mut files: Vec<(std::time::SystemTime, u64, PathBuf)> = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let metadata = entry.metadata().ok()?;
+            Some((metadata.modified().ok()?, metadata.len(), entry.path()))
+        })
+        .collect();
+    let mut total: u64 = files.iter().map(|(_, len, _)| len).sum();
+    files.sort();
+    for (_, len, path) in files {
+        if total <= MAX_ACCOUNT_BYTES {
+            break;
+        }
+        if std::fs::remove_file(&path).is_ok() {
+            total -= len;
+        }
+    }
+}
+
+/// Forget everything kept for `user_id` (signing out)
+pub fn clear_account(user_id: &str) {
+    match std::fs::remove_dir_all(account_dir(user_id)) {
+        Ok(()) => info!("Cleared the offline cache of the signed-out account"),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => warn!("Failed to clear the offline cache: {}", e),
+    }
+}
@@ -0,0 +1,310 @@
+//! Checks for file names and paths the page hands to native code.
+//!
+//! Everything that joins a name from an IPC message onto a folder, or passes
+//! a path from one to Explorer, Finder or the downloader, goes through
+//! [`sanitize_target_path`]. Both `\` and `/` count as separators whatever
+//! the platform, so a Windows-style payload is just as harmless on macOS.
+//! UNC and device paths (`\\server\share`, `\\?\`, `\\.\`) are refused
+//! before anything touches them: even resolving one makes Windows
+//! authenticate against the named server.
+
+use std::fmt;
+use std::path::{Component, Path, PathBuf};
+
+use super::MAX_PATH_CHARS;
+
+/// Names Windows reserves for devices in every folder, with or without an extension
+const RESERVED_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "CONIN$", "CONOUT$", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+/// Not allowed in Windows file names; `:` would also name an alternate data stream
+const INVALID_CHARS: &[char] = &['<', '>', ':', '"', '|', '?', '*'];
+
+/// Why a name or path from the page was refused
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PathError {
+    Empty,
+    TooLong,
+    /// NUL, control characters or characters Windows doesn't allow in names
+    InvalidCharacters(String),
+    /// `\\server\share`, `\\?\` and `\\.\` paths
+    Unc(String),
+    /// `CON`, `NUL`, `COM1`, ... (also as `nul.txt`)
+    Reserved(String),
+    /// `.`, `..` or a name that is only dots and spaces
+    InvalidName(String),
+    Relative(String),
+    /// Resolves to somewhere outside the folder it must stay in
+    Outside(PathBuf),
+}
+
+impl fmt::Display for PathError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Empty => write!(f, "empty file name"),
+            Self::TooLong => write!(f, "path is longer than {} characters", MAX_PATH_CHARS),
+            Self::InvalidCharacters(name) => write!(f, "invalid characters in {:?}", name),
+            Self::Unc(path) => write!(f, "network and device paths are not allowed: {}", path),
+            Self::Reserved(name) => write!(f, "{:?} is a reserved device name", name),
+            Self::InvalidName(name) => write!(f, "invalid file name: {:?}", name),
+            Self::Relative(path) => write!(f, "not an absolute path: {}", path),
+            Self::Outside(path) => write!(f, "outside the allowed folder: {}", path.display()),
+        }
+    }
+}
+
+impl std::error::Error for PathError {}
+
+fn is_separator(c: char) -> bool {
+    c == '\\' || c == '/'
+}
+
+/// `\\server\share`, `//server/share`, `\\?\C:\x`, `\\.\PhysicalDrive0`
+pub fn is_unc(input: &str) -> bool {
+    let mut chars = input.chars();
+    matches!((chars.next(), chars.next()), (Some(a), Some(b)) if is_separator(a) && is_separator(b))
+}
+
+/// `C:`, `C:\x` or `C:x`
+fn has_drive_prefix(input: &str) -> bool {
+    let bytes = input.as_bytes();
+    bytes.len() >= 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':'
+}
+
+/// Check a full path from the page without resolving it: no UNC or device
+/// path, no `..`, no reserved or invalid names
+pub fn check_path(path: &Path) -> Result<(), PathError> {
+    let text = path.to_string_lossy();
+    if is_unc(&text) {
+        return Err(PathError::Unc(text.into_owned()));
+    }
+    for component in path.components() {
+        match component {
+            Component::Normal(name) => check_name(&name.to_string_lossy())?,
+            Component::ParentDir => return Err(PathError::InvalidName("..".to_string())),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Check one path component as a file name: not reserved, nothing Windows refuses
+pub fn check_name(name: &str) -> Result<(), PathError> {
+    if name.chars().any(|c| c.is_control() || INVALID_CHARS.contains(&c)) {
+        return Err(PathError::InvalidCharacters(name.to_string()));
+    }
+    // Windows drops trailing dots and spaces, so `..  ` is `..` and `nul.` is `nul`
+    let trimmed = name.trim_end_matches(['.', ' ']);
+    if trimmed.is_empty() {
+        return Err(PathError::InvalidName(name.to_string()));
+    }
+    let stem = trimmed.split('.').next().unwrap_or_default().trim_end();
+    if RESERVED_NAMES.iter().any(|reserved| stem.eq_ignore_ascii_case(reserved)) {
+        return Err(PathError::Reserved(name.to_string()));
+    }
+    Ok(())
+}
+
+/// The deepest part of `path` that exists, resolved, with the rest appended;
+/// the target of a download need not exist yet
+fn resolve_existing(path: &Path) -> Option<PathBuf> {
+    let mut existing = path;
+    let mut rest = Vec::new();
+    loop {
+        if let Ok(resolved) = std::fs::canonicalize(existing) {
+            return Some(rest.iter().rev().fold(resolved, |path, name| path.join(name)));
+        }
+        rest.push(existing.file_name()?);
+        existing = existing.parent()?;
+    }
+}
+
+/// Turn `user_input` from the page into a path inside `base_dir`.
+///
+/// A plain name, or a relative path whose folders are dropped, is joined
+/// onto `base_dir`. A full path must resolve (following links, for the part
+/// that exists) to somewhere inside `base_dir`. Either way every component
+/// is checked with [`check_name`], and UNC and device paths are refused.
+pub fn sanitize_target_path(base_dir: &Path, user_input: &str) -> Result<PathBuf, PathError> {
+    if user_input.trim().is_empty() {
+        return Err(PathError::Empty);
+    }
+    if user_input.chars().count() > MAX_PATH_CHARS {
+        return Err(PathError::TooLong);
+    }
+    if user_input.contains('\0') {
+        return Err(PathError::InvalidCharacters(user_input.to_string()));
+    }
+    if is_unc(user_input) {
+        return Err(PathError::Unc(user_input.to_string()));
+    }
+
+    let absolute = Path::new(user_input).is_absolute() || has_drive_prefix(user_input) || user_input.starts_with(is_separator);
+    if !absolute {
+        // Only the last component: `..\..\Windows\x` is `x`
+        let name = user_input.rsplit(is_separator).next().unwrap_or_default();
+        check_name(name)?;
+        return Ok(base_dir.join(name));
+    }
+    // `C:\x` on macOS, or `/x` on Windows (the current drive's root), isn't what the page meant
+    if !Path::new(user_input).is_absolute() {
+        return Err(PathError::Relative(user_input.to_string()));
+    }
+
+    let path = Path::new(user_input);
+    check_path(path)?;
+    let base = std::fs::canonicalize(base_dir).map_err(|_| PathError::Outside(path.to_path_buf()))?;
+    let resolved = resolve_existing(path).ok_or_else(|| PathError::Outside(path.to_path_buf()))?;
+    if !resolved.starts_with(&base) {
+        return Err(PathError::Outside(path.to_path_buf()));
+    }
+    Ok(resolved)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fresh folder to resolve against, removed when dropped
+    struct Base(PathBuf);
+
+    impl Base {
+        fn new() -> Self {
+            let dir = std::env::temp_dir().join(format!("miko-paths-{}", uuid::Uuid::new_v4().simple()));
+            std::fs::create_dir_all(dir.join("sub")).unwrap();
+            Self(std::fs::canonicalize(dir).unwrap())
+        }
+    }
+
+    impl Drop for Base {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn kind(result: &Result<PathBuf, PathError>) -> &'static str {
+        match result {
+            Ok(_) => "ok",
+            Err(PathError::Empty) => "empty",
+            Err(PathError::TooLong) => "too long",
+            Err(PathError::InvalidCharacters(_)) => "invalid characters",
+            Err(PathError::Unc(_)) => "unc",
+            Err(PathError::Reserved(_)) => "reserved",
+            Err(PathError::InvalidName(_)) => "invalid name",
+            Err(PathError::Relative(_)) => "relative",
+            Err(PathError::Outside(_)) => "outside",
+        }
+    }
+
+    #[test]
+    fn hostile_inputs_are_refused() {
+        let base = Base::new();
+        let table = [
+            ("", "empty"),
+            ("   ", "empty"),
+            ("a\0b", "invalid characters"),
+            (r"\\server\share", "unc"),
+            (r"\\server\share\file.txt", "unc"),
+            ("//server", "unc"),
+            ("//server/share/file.txt", "unc"),
+            (r"\/server\share", "unc"),
+            (r"\\?\C:\Windows\System32", "unc"),
+            (r"\\?\UNC\server\share", "unc"),
+            (r"\\.\PhysicalDrive0", "unc"),
+            (r"\\.\pipe\name", "unc"),
+            ("CON", "reserved"),
+            ("con", "reserved"),
+            ("nul.txt", "reserved"),
+            ("NUL.tar.gz", "reserved"),
+            ("con.", "reserved"),
+            ("aux ", "reserved"),
+            ("COM1 .txt", "reserved"),
+            ("lpt9", "reserved"),
+            (r"..\..\CONIN$", "reserved"),
+            ("report.txt:secret", "invalid characters"),
+            ("file::$DATA", "invalid characters"),
+            ("a<b", "invalid characters"),
+            ("a|b", "invalid characters"),
+            ("what?.txt", "invalid characters"),
+            ("tab\there", "invalid characters"),
+            ("..", "invalid name"),
+            (".", "invalid name"),
+            (r"folder\..", "invalid name"),
+            ("... ", "invalid name"),
+        ];
+        for (input, expected) in table {
+            assert_eq!(kind(&sanitize_target_path(&base.0, input)), expected, "{:?}", input);
+        }
+        assert_eq!(kind(&sanitize_target_path(&base.0, &"a".repeat(MAX_PATH_CHARS + 1))), "too long");
+    }
+
+    #[test]
+    fn relative_inputs_keep_only_the_name() {
+        let base = Base::new();
+        let table = [
+            (r"..\..\Windows\System32\drivers\etc\hosts", "hosts"),
+            ("../../etc/passwd", "passwd"),
+            (r"sub\..\..\report.pdf", "report.pdf"),
+            ("folder/report.pdf", "report.pdf"),
+            ("report.pdf", "report.pdf"),
+            ("console.log", "console.log"),
+            ("CONFIG.SYS", "CONFIG.SYS"),
+        ];
+        for (input, name) in table {
+            let path = sanitize_target_path(&base.0, input).unwrap_or_else(|e| panic!("{:?}: {}", input, e));
+            assert_eq!(path, base.0.join(name), "{:?}", input);
+            assert!(path.starts_with(&base.0));
+        }
+    }
+
+    #[test]
+    fn full_paths_must_stay_inside_the_base() {
+        let base = Base::new();
+        let inside = base.0.join("sub").join("new.txt");
+        assert_eq!(sanitize_target_path(&base.0, &inside.to_string_lossy()), Ok(inside.clone()));
+        let escaping = format!("{}{}..{}x.txt", base.0.join("sub").display(), std::path::MAIN_SEPARATOR, std::path::MAIN_SEPARATOR);
+        assert_eq!(kind(&sanitize_target_path(&base.0, &escaping)), "invalid name");
+
+        let outside = std::env::temp_dir().join("elsewhere.txt");
+        assert_eq!(kind(&sanitize_target_path(&base.0, &outside.to_string_lossy())), "outside");
+        // A sibling whose name starts with the base's
+        let sibling = format!("{}-other{}x.txt", base.0.display(), std::path::MAIN_SEPARATOR);
+        assert_eq!(kind(&sanitize_target_path(&base.0, &sibling)), "outside");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn unix_full_paths() {
+        let base = Base::new();
+        assert_eq!(kind(&sanitize_target_path(&base.0, "/etc/passwd")), "outside");
+        // A drive path means nothing here
+        assert_eq!(kind(&sanitize_target_path(&base.0, r"C:\Windows\System32\drivers\etc\hosts")), "relative");
+        assert_eq!(kind(&sanitize_target_path(&base.0, "C:relative.txt")), "relative");
+
+        // A link inside the base pointing out of it
+        std::os::unix::fs::symlink("/etc", base.0.join("link")).unwrap();
+        let through_link = base.0.join("link").join("passwd");
+        assert_eq!(kind(&sanitize_target_path(&base.0, &through_link.to_string_lossy())), "outside");
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn windows_full_paths() {
+        let base = Base::new();
+        assert_eq!(kind(&sanitize_target_path(&base.0, r"C:\Windows\System32\drivers\etc\hosts")), "outside");
+        // The current drive's root, and a drive-relative path
+        assert_eq!(kind(&sanitize_target_path(&base.0, r"\Windows\win.ini")), "relative");
+        assert_eq!(kind(&sanitize_target_path(&base.0, "C:relative.txt")), "relative");
+    }
+
+    #[test]
+    fn unc_detection_ignores_the_platform() {
+        for input in [r"\\server", "//server", r"\/server", r"/\server", r"\\?\", r"\\.\"] {
+            assert!(is_unc(input), "{:?}", input);
+        }
+        for input in [r"\server", "/server", r"C:\x", "server"] {
+            assert!(!is_unc(input), "{:?}", input);
+        }
+    }
+}
@@ -0,0 +1,68 @@
+//! Catching up after the machine wakes from sleep.
+//!
+//! The platform layers call [`resumed`] on the OS wake notification. The
+//! connectivity monitor and the ERP health poller check again at once, but
+//! for [`GRACE`] the monitor doesn't report the ERP as down, because the link
+//! and the VPN are usually still coming up. The ERP is probed every [`RETRY`]
+//! until it answers or the grace period ends. Then the page gets
+//! `system-resumed` and re-fetches its threads and session.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use serde_json::json;
+use tracing::{debug, info, warn};
+
+/// How long the network gets to come back before it counts as down
+const GRACE: Duration = Duration::from_secs(30);
+const RETRY: Duration = Duration::from_secs(2);
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A wake is being handled; notifications arriving meanwhile are dropped
+static HANDLING: AtomicBool = AtomicBool::new(false);
+
+pub fn resumed() {
+    if HANDLING.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    info!("System resumed from sleep");
+    super::network::allow_grace(GRACE);
+    super::network::recheck();
+    super::health::poll_now();
+
+    std::thread::spawn(|| {
+        let started = Instant::now();
+        let reachable = wait_for_erp(started);
+        if reachable {
+            info!("ERP reachable {} ms after resume", started.elapsed().as_millis());
+        } else {
+            warn!("ERP still unreachable {} s after resume", GRACE.as_secs());
+        }
+        HANDLING.store(false, Ordering::SeqCst);
+        if let Some(webview) = crate::ipc::handle() {
+            webview.emit(
+                "system-resumed",
+                &json!({ "erpReachable": reachable, "waitedMs": started.elapsed().as_millis() as u64 }),
+            );
+        }
+    });
+}
+
+fn wait_for_erp(started: Instant) -> bool {
+    let client = match reqwest::blocking::Client::builder().timeout(PROBE_TIMEOUT).build() {
+        Ok(client) => client,
+        Err(e) => {
+            warn!("Can't probe the ERP after resume: {}", e);
+            return false;
+        }
+    };
+    loop {
+        match client.get(super::network::ERP_PROBE_URL).send() {
+            Ok(_) => return true,
+            Err(e) => debug!("ERP not reachable yet after resume: {}", e),
+        }
+        if started.elapsed() + RETRY >= GRACE {
+            return false;
+        }
+        std::thread::sleep(RETRY);
+    }
+}
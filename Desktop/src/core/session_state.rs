@@ -0,0 +1,106 @@
+//! The thread and scroll position to come back to after a restart.
+//!
+//! The page reports where it is with `set_session_state {threadId,
+//! scrollAnchor}` (debounced on its side), kept per account in the settings.
+//! The first time an account is confirmed signed in after startup, its saved
+//! place is emitted as `restore-session-state` so the page can open it instead
+//! of the thread list. Signing out and `clear_session_state` (sent when web
+//! data is cleared) forget it. An entry that doesn't parse is treated as none.
+
+use std::collections::HashSet;
+use std::sync::Mutex;
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::{debug, info, warn};
+
+use super::settings;
+
+const MAX_ANCHOR_CHARS: usize = 256;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionState {
+    pub thread_id: String,
+    /// Opaque to the host: whatever the page needs to scroll back (a message id)
+    #[serde(default)]
+    pub scroll_anchor: Option<String>,
+}
+
+lazy_static! {
+    /// Accounts whose saved place went out already in this process
+    static ref RESTORED: Mutex<HashSet<String>> = Mutex::new(HashSet::new());
+}
+
+fn current_account() -> Option<String> {
+    let state = super::state::runtime();
+    state.auth.signed_in.then(|| state.auth.user_id.clone()).flatten()
+}
+
+/// Handle `set_session_state`
+pub fn save(thread_id: &str, scroll_anchor: Option<&str>) -> Result<(), String> {
+    if scroll_anchor.is_some_and(|a| a.chars().count() > MAX_ANCHOR_CHARS) {
+        return Err("scroll anchor too long".to_string());
+    }
+    let account = current_account().ok_or("not signed in")?;
+    let state = SessionState { thread_id: thread_id.to_string(), scroll_anchor: scroll_anchor.map(|a| a.to_string()) };
+    let value = serde_json::to_value(&state).map_err(|e| e.to_string())?;
+    if settings::get().session_state.get(&account) == Some(&value) {
+        return Ok(());
+    }
+    settings::update(|s| {
+        s.session_state.insert(account, value);
+    })
+    .map_err(|e| format!("failed to save session state: {}", e))?;
+    debug!("Saved session state ({})", thread_id);
+    Ok(())
+}
+
+/// The saved place of `user_id`, if any and readable
+fn saved(user_id: &str) -> Option<SessionState> {
+    let value = settings::get().session_state.get(user_id).cloned()?;
+    match serde_json::from_value::<SessionState>(value) {
+        Ok(state) if !state.thread_id.is_empty() => Some(state),
+        _ => {
+            warn!("Ignoring unreadable session state");
+            None
+        }
+    }
+}
+
+/// Called when the page reports who is signed in: emits
+/// `restore-session-state` once per account and process
+pub fn signed_in(user_id: &str) {
+    if crate::safe_mode::active() {
+        return;
+    }
+    if !RESTORED.lock().unwrap().insert(user_id.to_string()) {
+        return;
+    }
+    let Some(state) = saved(user_id) else { return };
+    info!("Restoring session state ({})", state.thread_id);
+    if let Some(webview) = crate::ipc::handle() {
+        webview.emit("restore-session-state", &json!({ "threadId": state.thread_id, "scrollAnchor": state.scroll_anchor }));
+    }
+}
+
+/// Forget the saved place of `user_id` (signing out), or of every account
+pub fn clear(user_id: Option<&str>) {
+    let saved = settings::get().session_state;
+    let present = match user_id {
+        Some(user_id) => saved.contains_key(user_id),
+        None => !saved.is_empty(),
+    };
+    if !present {
+        return;
+    }
+    let result = settings::update(|s| match user_id {
+        Some(user_id) => {
+            s.session_state.remove(user_id);
+        }
+        None => s.session_state.clear(),
+    });
+    if let Err(e) = result {
+        warn!("Failed to clear session state: {}", e);
+    }
+}
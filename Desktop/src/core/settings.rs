@@ -0,0 +1,502 @@
+//! User settings persisted as `settings.json` in the app data directory.
+//!
+//! The document carries a schema `version`; older files are upgraded by the
+//! functions in [`MIGRATIONS`] before being deserialized and written back;
+//! files from a newer version are read as far as understood but not
+//! rewritten. Every write goes through [`super::write_atomic`], so a crash
+//! never leaves a torn file.
+//! In safe mode the file is neither read nor written.
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::RwLock;
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::{info, warn};
+
+pub const SETTINGS_VERSION: u64 = 1;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub version: u64,
+    /// Log filter restored at startup (overridden by `MIKO_LOG`)
+    pub log_level: Option<String>,
+    /// BCP 47 tag dates and numbers are formatted for (see `crate::formatting`);
+    /// the OS locale when unset
+    pub language: Option<String>,
+    /// Largest attachment accepted by the native uploader, in MiB
+    pub max_upload_mb: u64,
+    pub uploads: UploadSettings,
+    /// Largest message the page may post over IPC, in KiB (applies at startup)
+    pub max_ipc_message_kb: u64,
+    /// Share of IPC requests, 0 to 1, whose result carries `_timings` for
+    /// the devtools Performance panel; 0 measures none
+    pub ipc_timing_sample_rate: f64,
+    /// Origins besides the app's own (`https://erp.example.com`) whose pages
+    /// may post IPC messages; never the sensitive ones (`ipc::SENSITIVE_ACTIONS`)
+    pub ipc_extra_origins: Vec<String>,
+    /// "Remind me later" on an update prompt
+    pub update_snooze: Option<UpdateSnooze>,
+    /// Last choice made with `set_autostart`; the OS entry is what counts
+    pub autostart: bool,
+    /// Transfer progress in the window title and tray tooltip (see `core::transfer_status`)
+    pub show_transfer_progress: bool,
+    /// What downloads and updates do on a metered connection (see `metered`)
+    pub metered_transfers: MeteredPolicy,
+    pub media: MediaSettings,
+    pub privacy: PrivacySettings,
+    pub presence: PresenceSettings,
+    pub spellcheck: SpellcheckSettings,
+    pub downloads: DownloadSettings,
+    pub health: HealthSettings,
+    pub recording: RecordingSettings,
+    /// Command -> accelerator overrides of the default shortcuts; an empty
+    /// string unbinds the command
+    pub shortcuts: BTreeMap<String, String>,
+    /// WebView2 profile location; defaults to `WebView2` in the data directory,
+    /// which is also used in portable mode regardless
+    pub webview2_user_data_dir: Option<std::path::PathBuf>,
+    /// Chat API the `miko://` protocol forwards `/api/*` to; defaults to the
+    /// frontend build's `VITE_API_URL`
+    pub api_base_url: Option<String>,
+    pub forwarding: ForwardingSettings,
+    pub ephemeral: EphemeralSettings,
+    pub read: ReadSettings,
+    pub prefetch: PrefetchSettings,
+    pub maintenance: MaintenanceSettings,
+    pub notifications: NotificationSettings,
+    pub rendering: RenderingSettings,
+    pub window_layouts: WindowLayoutSettings,
+    /// Permissions `request_permission` asked for (see `permissions`); macOS
+    /// only prompts once, so these are denied rather than undecided when
+    /// the OS can't tell
+    pub permissions_requested: Vec<String>,
+    /// User id -> where that account was in the app (see `core::session_state`);
+    /// kept as raw JSON so one bad entry can't make the whole file unreadable
+    pub session_state: BTreeMap<String, Value>,
+    /// Keep a hidden webview warm so the preview window opens instantly
+    /// (Windows; costs the memory of one idle webview)
+    pub warm_standby_webview: bool,
+    /// Debugging only: start WebView2 with `--disable-web-security`
+    pub dangerous_disable_web_security: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MediaSettings {
+    /// Locked-down deployments turn `getDisplayMedia` off entirely
+    pub screen_share_enabled: bool,
+}
+
+impl Default for MediaSettings {
+    fn default() -> Self {
+        Self { screen_share_enabled: true }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PrivacySettings {
+    /// Keep app windows out of screenshots, recordings and screen shares
+    pub block_capture: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PresenceSettings {
+    /// Report away/active to the presence API
+    pub enabled: bool,
+    /// Idle time after which the user is reported away
+    pub idle_threshold_secs: u64,
+}
+
+impl Default for PresenceSettings {
+    fn default() -> Self {
+        Self { enabled: true, idle_threshold_secs: 300 }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SpellcheckSettings {
+    pub enabled: bool,
+    /// BCP 47 tags, e.g. `en-US`; empty follows the system languages
+    pub languages: Vec<String>,
+    /// Words added with `add_dictionary_word`, re-added when languages change
+    pub custom_words: Vec<String>,
+}
+
+impl Default for SpellcheckSettings {
+    fn default() -> Self {
+        Self { enabled: true, languages: Vec::new(), custom_words: Vec::new() }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MeteredPolicy {
+    /// Download anyway, or wait for Wi-Fi?
+    #[default]
+    Ask,
+    /// Wait for an unmetered connection without asking
+    Wait,
+    /// Treat metered connections like any other
+    Ignore,
+}
+
+/// Sounds are `none`, `default` (the OS notification sound) or the name of
+/// one bundled with the app (see `notification_sounds`)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NotificationSettings {
+    /// For accounts that haven't picked one
+    pub sound: String,
+    /// User id -> that account's pick
+    pub account_sounds: BTreeMap<String, String>,
+    pub quiet_hours: QuietHoursSettings,
+}
+
+impl Default for NotificationSettings {
+    fn default() -> Self {
+        Self { sound: "default".to_string(), account_sounds: BTreeMap::new(), quiet_hours: QuietHoursSettings::default() }
+    }
+}
+
+/// When notifications are silenced on a schedule (see `quiet_hours`)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct QuietHoursSettings {
+    pub enabled: bool,
+    /// Local time, `HH:MM`; `end` before `start` runs past midnight
+    pub start: String,
+    pub end: String,
+    /// `mon`…`sun` the quiet hours start on; empty means every day
+    pub days: Vec<String>,
+}
+
+impl Default for QuietHoursSettings {
+    fn default() -> Self {
+        Self { enabled: false, start: "22:00".to_string(), end: "07:00".to_string(), days: Vec::new() }
+    }
+}
+
+/// How the webview draws (see `rendering`); applies after a relaunch
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RenderingSettings {
+    /// Render in software, for GPU drivers that break video or the window
+    pub disable_gpu: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DownloadSettings {
+    /// Where downloads go without an explicit target; the system Downloads
+    /// folder when unset
+    pub directory: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HealthSettings {
+    /// Seconds between ERP latency polls while the server answers
+    pub poll_interval_secs: u64,
+}
+
+impl Default for HealthSettings {
+    fn default() -> Self {
+        Self { poll_interval_secs: 30 }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RecordingSettings {
+    /// Voice memos stop by themselves after this long
+    pub max_duration_secs: u64,
+}
+
+impl Default for RecordingSettings {
+    fn default() -> Self {
+        Self { max_duration_secs: 300 }
+    }
+}
+
+/// Connection handling of the `miko://` protocol's forwarding client; applies
+/// at startup, except the timeouts, which apply to the next request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ForwardingSettings {
+    /// Talk HTTP/2 to a plain `http://` API without negotiating it first.
+    /// `https://` APIs negotiate HTTP/2 on their own.
+    pub http2_prior_knowledge: bool,
+    /// Idle connections kept open to the API
+    pub pool_max_idle_per_host: usize,
+    /// How long an idle connection is kept; should stay below the server's own timeout
+    pub pool_idle_timeout_secs: u64,
+    pub tcp_keepalive_secs: u64,
+    /// Debugging only: keep the last requests for inspection and replay
+    /// (see `crate::recordings`)
+    pub record_requests: bool,
+    /// Seconds a forwarded request may take, from sending it to the end of the answer
+    pub timeout_secs: u64,
+    /// Path prefix -> seconds, instead of `timeout_secs` for requests under
+    /// it; the longest matching prefix wins (see `crate::protocol::timeout_for`)
+    pub route_timeouts: BTreeMap<String, u64>,
+}
+
+impl Default for ForwardingSettings {
+    fn default() -> Self {
+        Self {
+            http2_prior_knowledge: false,
+            pool_max_idle_per_host: 16,
+            pool_idle_timeout_secs: 55,
+            tcp_keepalive_secs: 30,
+            record_requests: false,
+            timeout_secs: 30,
+            // File transfers the page makes through the protocol
+            route_timeouts: BTreeMap::from([
+                ("/api/files".to_string(), 300),
+                ("/api/fileupload".to_string(), 300),
+                ("/api/imageupload".to_string(), 300),
+                ("/api/chunked-upload".to_string(), 300),
+            ]),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EphemeralSettings {
+    /// The ERP accepts `POST /api/ephemeral/batch`; otherwise each typing
+    /// or read signal is its own request
+    pub batch_endpoint: bool,
+}
+
+/// Marking threads read (see `crate::mark_read`)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReadSettings {
+    /// The ERP accepts `POST /api/chats/read {threadIds}`; otherwise each
+    /// thread is its own request
+    pub batch_endpoint: bool,
+    /// Mark the visible unread threads read when the window gets focus back
+    pub mark_visible_on_focus: bool,
+}
+
+impl Default for ReadSettings {
+    fn default() -> Self {
+        Self { batch_endpoint: false, mark_visible_on_focus: true }
+    }
+}
+
+/// Thread list warm-up after signing in (see `crate::prefetch`)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PrefetchSettings {
+    pub enabled: bool,
+    /// Threads at the top of the list whose messages are fetched too
+    pub threads: usize,
+}
+
+impl Default for PrefetchSettings {
+    fn default() -> Self {
+        Self { enabled: true, threads: 5 }
+    }
+}
+
+/// Resumable uploads of large attachments (see `crate::upload`)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UploadSettings {
+    /// Files from this size on (MiB) go up in chunks; smaller ones as one request
+    pub chunked_threshold_mb: u64,
+    pub chunk_size_mb: u64,
+}
+
+impl Default for UploadSettings {
+    fn default() -> Self {
+        Self { chunked_threshold_mb: 100, chunk_size_mb: 8 }
+    }
+}
+
+/// What the scheduled cleanup removes (see `crate::maintenance`)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MaintenanceSettings {
+    pub clean_logs: bool,
+    pub log_retention_days: u32,
+    pub clean_crash_reports: bool,
+    pub crash_retention_days: u32,
+    /// Staged downloads nothing refers to any more
+    pub clean_part_files: bool,
+    /// Pasted images and voice memos of earlier sessions
+    pub clean_temp_files: bool,
+}
+
+impl Default for MaintenanceSettings {
+    fn default() -> Self {
+        Self {
+            clean_logs: true,
+            log_retention_days: 14,
+            clean_crash_reports: true,
+            crash_retention_days: 30,
+            clean_part_files: true,
+            clean_temp_files: true,
+        }
+    }
+}
+
+/// Window geometries saved with `save_window_layout` (see `window_layout`)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WindowLayoutSettings {
+    pub layouts: BTreeMap<String, SavedLayout>,
+    /// The one `last-saved` snaps to
+    pub last_saved: Option<String>,
+}
+
+/// Position and size as fractions of the monitor's work area
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedLayout {
+    /// The monitor's name, to find it again
+    pub monitor: Option<String>,
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UpdateSnooze {
+    pub version: String,
+    pub until: chrono::DateTime<chrono::Utc>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            version: SETTINGS_VERSION,
+            log_level: None,
+            language: None,
+            max_upload_mb: 3072,
+            uploads: UploadSettings::default(),
+            max_ipc_message_kb: 1024,
+            ipc_timing_sample_rate: 0.0,
+            ipc_extra_origins: Vec::new(),
+            update_snooze: None,
+            autostart: false,
+            show_transfer_progress: true,
+            metered_transfers: MeteredPolicy::default(),
+            media: MediaSettings::default(),
+            privacy: PrivacySettings::default(),
+            presence: PresenceSettings::default(),
+            spellcheck: SpellcheckSettings::default(),
+            downloads: DownloadSettings::default(),
+            health: HealthSettings::default(),
+            recording: RecordingSettings::default(),
+            forwarding: ForwardingSettings::default(),
+            ephemeral: EphemeralSettings::default(),
+            read: ReadSettings::default(),
+            prefetch: PrefetchSettings::default(),
+            maintenance: MaintenanceSettings::default(),
+            notifications: NotificationSettings::default(),
+            rendering: RenderingSettings::default(),
+            window_layouts: WindowLayoutSettings::default(),
+            permissions_requested: Vec::new(),
+            session_state: BTreeMap::new(),
+            shortcuts: BTreeMap::new(),
+            webview2_user_data_dir: None,
+            api_base_url: None,
+            warm_standby_webview: true,
+            dangerous_disable_web_security: false,
+        }
+    }
+}
+
+/// `MIGRATIONS[n]` upgrades a version `n` document to version `n + 1`
+const MIGRATIONS: &[fn(Value) -> Value] = &[migrate_v0_to_v1];
+
+/// Files written before versioning have no `version` field
+fn migrate_v0_to_v1(mut doc: Value) -> Value {
+    doc["version"] = Value::from(1u64);
+    doc
+}
+
+fn migrate(mut doc: Value) -> Value {
+    let mut version = doc["version"].as_u64().unwrap_or(0);
+    while let Some(step) = MIGRATIONS.get(version as usize) {
+        doc = step(doc);
+        version += 1;
+        info!("Migrated settings to version {}", version);
+    }
+    doc
+}
+
+pub fn settings_path() -> PathBuf {
+    crate::paths::data_dir().join("settings.json")
+}
+
+fn load() -> Settings {
+    if crate::safe_mode::active() {
+        return Settings::default();
+    }
+    let path = settings_path();
+    let Ok(text) = std::fs::read_to_string(&path) else {
+        return Settings::default();
+    };
+
+    let doc = match serde_json::from_str::<Value>(&text) {
+        Ok(doc) if doc.is_object() => doc,
+        _ => {
+            warn!("Ignoring unreadable settings file {}", path.display());
+            return Settings::default();
+        }
+    };
+
+    // A file from a newer build is left alone: saving it here would drop the
+    // fields this one doesn't know
+    let migrated = doc["version"].as_u64().unwrap_or(0) < SETTINGS_VERSION;
+    match serde_json::from_value::<Settings>(migrate(doc)) {
+        Ok(settings) => {
+            if migrated {
+                if let Err(e) = save(&settings) {
+                    warn!("Failed to write migrated settings: {}", e);
+                }
+            }
+            settings
+        }
+        Err(e) => {
+            warn!("Invalid settings file {}: {}", path.display(), e);
+            Settings::default()
+        }
+    }
+}
+
+fn save(settings: &Settings) -> Result<(), Box<dyn std::error::Error>> {
+    // The defaults in use must not replace the file the user may want back
+    if crate::safe_mode::active() {
+        return Ok(());
+    }
+    super::write_atomic(&settings_path(), &serde_json::to_vec_pretty(settings)?)?;
+    Ok(())
+}
+
+lazy_static! {
+    static ref SETTINGS: RwLock<Settings> = RwLock::new(load());
+}
+
+/// Snapshot of the current settings
+pub fn get() -> Settings {
+    SETTINGS.read().unwrap_or_else(|poisoned| poisoned.into_inner()).clone()
+}
+
+/// Apply `change` and persist the result
+pub fn update(change: impl FnOnce(&mut Settings)) -> Result<(), Box<dyn std::error::Error>> {
+    let mut settings = SETTINGS.write().unwrap_or_else(|poisoned| poisoned.into_inner());
+    change(&mut settings);
+    settings.version = SETTINGS_VERSION;
+    save(&settings)
+}
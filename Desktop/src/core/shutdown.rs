@@ -0,0 +1,80 @@
+//! Quitting while downloads or uploads are running.
+//!
+//! The platform layers ask before a user-initiated quit when [`InFlight`]
+//! isn't empty. "Quit anyway" marks the process as quitting so interrupted
+//! downloads keep their staging files for a resume; "finish in background"
+//! hides the window and quits once the transfers have drained.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use tracing::info;
+
+const IDLE_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+static QUITTING: AtomicBool = AtomicBool::new(false);
+static EXIT_WHEN_IDLE: AtomicBool = AtomicBool::new(false);
+
+/// What the user chose in the quit prompt
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QuitChoice {
+    Quit,
+    FinishInBackground,
+    Stay,
+}
+
+/// Transfers that a quit would interrupt
+#[derive(Debug, Clone, Copy)]
+pub struct InFlight {
+    pub downloads: usize,
+    pub uploads: usize,
+}
+
+impl InFlight {
+    pub fn current() -> Self {
+        Self {
+            downloads: super::state::runtime().downloads.len(),
+            uploads: crate::upload::active_count(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.downloads == 0 && self.uploads == 0
+    }
+
+    /// e.g. "2 downloads and 1 upload are in progress."
+    pub fn describe(&self) -> String {
+        let count = |n: usize, noun: &str| format!("{} {}{}", n, noun, if n == 1 { "" } else { "s" });
+        let parts: Vec<String> = [(self.downloads, "download"), (self.uploads, "upload")]
+            .into_iter()
+            .filter(|(n, _)| *n > 0)
+            .map(|(n, noun)| count(n, noun))
+            .collect();
+        let verb = if self.downloads + self.uploads == 1 { "is" } else { "are" };
+        format!("{} {} in progress.", parts.join(" and "), verb)
+    }
+}
+
+/// The user confirmed quitting; interrupted work should leave resumable state
+pub fn begin_quit() {
+    QUITTING.store(true, Ordering::SeqCst);
+}
+
+pub fn is_quitting() -> bool {
+    QUITTING.load(Ordering::SeqCst)
+}
+
+/// Call `quit` once nothing is in flight any more (at most one watcher runs)
+pub fn exit_when_idle(quit: impl FnOnce() + Send + 'static) {
+    if EXIT_WHEN_IDLE.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    info!("Finishing transfers in the background before quitting");
+    std::thread::spawn(move || {
+        while !InFlight::current().is_empty() {
+            std::thread::sleep(IDLE_POLL_INTERVAL);
+        }
+        info!("Background transfers finished, quitting");
+        begin_quit();
+        quit();
+    });
+}
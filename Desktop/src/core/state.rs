@@ -0,0 +1,224 @@
+//! Process-lifetime state: init flags, proxy status, connectivity and ERP
+//! latency, active downloads, auth and renderer health.
+
+use std::collections::HashMap;
+use std::sync::{Mutex, MutexGuard};
+use lazy_static::lazy_static;
+use serde::Serialize;
+
+use super::sync;
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum ProxyStatus {
+    #[default]
+    Stopped,
+    Starting,
+    Ready { port: u16 },
+    Failed { error: String },
+}
+
+/// What the network monitor currently believes about reachability
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Connectivity {
+    #[default]
+    Online,
+    /// Traffic is intercepted by a captive portal (hotel/guest Wi-Fi)
+    Captive,
+    /// The network works but the ERP server doesn't answer
+    ErpUnreachable,
+    /// The ERP answers that it is down for scheduled maintenance
+    ErpMaintenance,
+    Offline,
+}
+
+impl Connectivity {
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Online => "Online",
+            Self::Captive => "Sign-in required (captive portal)",
+            Self::ErpUnreachable => "ERP server unreachable",
+            Self::ErpMaintenance => "ERP server maintenance",
+            Self::Offline => "Offline",
+        }
+    }
+}
+
+/// Health of the webview's renderer process, for the `renderer` topic
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RendererState {
+    #[default]
+    Running,
+    /// The renderer failed and the page is about to be reloaded
+    Recovering,
+    /// It kept failing; reloading was given up
+    Failed,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RendererStatus {
+    pub state: RendererState,
+    /// Failures in the current counting window
+    pub failures: u32,
+    pub last_failure: Option<String>,
+}
+
+/// ERP latency for the status indicator, from [`super::health`]
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ConnectionQuality {
+    pub reachable: bool,
+    /// Latest round trip; `None` while unreachable
+    pub latency_ms: Option<u64>,
+    /// Mean of the successful polls within `window_secs`
+    pub average_ms: Option<u64>,
+    pub window_secs: u64,
+    /// Failed polls in a row
+    pub failures: u32,
+    pub last_checked: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ActiveDownload {
+    pub filename: String,
+    pub progress_percent: f64,
+    pub speed: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct AuthSnapshot {
+    pub signed_in: bool,
+    pub user_id: Option<String>,
+    pub user_name: Option<String>,
+    /// ERP API base and bearer token for native requests; never published
+    #[serde(skip)]
+    pub api_base: Option<String>,
+    #[serde(skip)]
+    pub token: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct UpdateStatus {
+    pub checking: bool,
+    pub available_version: Option<String>,
+    pub downloading: bool,
+}
+
+#[derive(Debug, Default)]
+pub struct RuntimeState {
+    pub initialization_complete: bool,
+    pub webview_ready: bool,
+    pub proxy: ProxyStatus,
+    /// By download id, the one in the `download-progress` events
+    pub downloads: HashMap<String, ActiveDownload>,
+    pub auth: AuthSnapshot,
+    pub updates: UpdateStatus,
+    pub connectivity: Connectivity,
+    /// The OS says the connection costs per byte (see `crate::metered`)
+    pub metered: bool,
+    pub connection_quality: ConnectionQuality,
+    pub renderer: RendererStatus,
+}
+
+lazy_static! {
+    static ref RUNTIME: Mutex<RuntimeState> = Mutex::new(RuntimeState::default());
+}
+
+/// Lock the runtime state; keep the guard short-lived
+pub fn runtime() -> MutexGuard<'static, RuntimeState> {
+    RUNTIME.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+pub fn is_initialized() -> bool {
+    runtime().initialization_complete
+}
+
+pub fn set_initialized() {
+    runtime().initialization_complete = true;
+}
+
+pub fn set_webview_ready(ready: bool) {
+    runtime().webview_ready = ready;
+}
+
+pub fn set_proxy_status(status: ProxyStatus) {
+    runtime().proxy = status;
+    sync::publish(sync::TOPIC_PROXY);
+}
+
+pub fn set_auth(auth: AuthSnapshot) {
+    let signed_out = !auth.signed_in;
+    let signed_in_as = auth.user_id.clone().filter(|_| auth.signed_in);
+    let previous = std::mem::replace(&mut runtime().auth, auth);
+    if signed_out {
+        super::unread::clear();
+        crate::presence::signed_out();
+        crate::thread_presence::clear();
+        crate::prefetch::signed_out();
+        if let Some(user_id) = previous.user_id.filter(|_| previous.signed_in) {
+            super::drafts::clear_account(&user_id);
+            super::offline_cache::clear_account(&user_id);
+            super::session_state::clear(Some(&user_id));
+        }
+    } else if let Some(user_id) = signed_in_as {
+        // Switched accounts without signing out
+        if let Some(previous_id) = previous.user_id.filter(|id| previous.signed_in && *id != user_id) {
+            super::session_state::clear(Some(&previous_id));
+        }
+        super::session_state::signed_in(&user_id);
+        crate::prefetch::signed_in(&user_id);
+        super::downloads::signed_in();
+    }
+    sync::publish(sync::TOPIC_AUTH);
+}
+
+pub fn set_update_status(status: UpdateStatus) {
+    runtime().updates = status;
+    sync::publish(sync::TOPIC_UPDATES);
+}
+
+pub fn set_connectivity(connectivity: Connectivity) {
+    runtime().connectivity = connectivity;
+    sync::publish(sync::TOPIC_CONNECTIVITY);
+}
+
+/// Returns whether it changed
+pub fn set_metered(metered: bool) -> bool {
+    let changed = std::mem::replace(&mut runtime().metered, metered) != metered;
+    if changed {
+        sync::publish(sync::TOPIC_CONNECTIVITY);
+    }
+    changed
+}
+
+pub fn set_connection_quality(quality: ConnectionQuality) {
+    runtime().connection_quality = quality;
+    sync::publish(sync::TOPIC_CONNECTION_QUALITY);
+}
+
+pub fn set_renderer_status(status: RendererStatus) {
+    runtime().renderer = status;
+    sync::publish(sync::TOPIC_RENDERER);
+}
+
+pub fn download_started(id: &str, filename: &str) {
+    runtime().downloads.insert(
+        id.to_string(),
+        ActiveDownload { filename: filename.to_string(), progress_percent: 0.0, speed: None },
+    );
+    sync::publish(sync::TOPIC_DOWNLOADS);
+}
+
+pub fn download_progress(id: &str, progress_percent: f64, speed: Option<&str>) {
+    if let Some(download) = runtime().downloads.get_mut(id) {
+        download.progress_percent = progress_percent;
+        download.speed = speed.map(|s| s.to_string());
+    }
+    sync::publish(sync::TOPIC_DOWNLOADS);
+}
+
+pub fn download_finished(id: &str) {
+    runtime().downloads.remove(id);
+    sync::publish(sync::TOPIC_DOWNLOADS);
+}
@@ -0,0 +1,243 @@
+//! Subscription-style state sync from Rust to the webview.
+//!
+//! The page subscribes with `window.__miko.subscribe(topic, cb)`, which sends a
+//! `subscribe` IPC message the first time a topic gets a listener. From then on
+//! every change to the topic pushes a JSON merge patch (RFC 7386) against the
+//! last snapshot the page received. Topics nobody subscribed to are never
+//! serialized. The bootstrap script sends `sync_reset` on every page load so a
+//! reloaded page always gets full snapshots again.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+use lazy_static::lazy_static;
+use serde_json::{Map, Value};
+
+use super::state;
+
+pub const TOPIC_PROXY: &str = "proxy";
+pub const TOPIC_DOWNLOADS: &str = "downloads";
+pub const TOPIC_AUTH: &str = "auth";
+pub const TOPIC_UPDATES: &str = "updates";
+pub const TOPIC_UNREAD: &str = "unread";
+pub const TOPIC_CONNECTIVITY: &str = "connectivity";
+pub const TOPIC_CONNECTION_QUALITY: &str = "connection-quality";
+pub const TOPIC_RENDERER: &str = "renderer";
+pub const TOPIC_TRANSFERS: &str = "transfers";
+pub const TOPIC_PRIVACY: &str = "privacy";
+pub const TOPIC_PRESENCE: &str = "presence";
+pub const TOPIC_COMPAT: &str = "compat";
+pub const TOPIC_QUIET_HOURS: &str = "quiet-hours";
+pub const TOPICS: &[&str] = &[
+    TOPIC_PROXY,
+    TOPIC_DOWNLOADS,
+    TOPIC_AUTH,
+    TOPIC_UPDATES,
+    TOPIC_UNREAD,
+    TOPIC_CONNECTIVITY,
+    TOPIC_CONNECTION_QUALITY,
+    TOPIC_RENDERER,
+    TOPIC_TRANSFERS,
+    TOPIC_PRIVACY,
+    TOPIC_PRESENCE,
+    TOPIC_COMPAT,
+    TOPIC_QUIET_HOURS,
+];
+
+/// Installed with `with_initialization_script`; owns the page-side dispatcher
+pub const BOOTSTRAP_SCRIPT: &str = r#"
+(function () {
+    if (window.__miko && window.__miko.subscribe) return;
+    const listeners = {};
+    const snapshots = {};
+    const post = (message) => window.ipc && window.ipc.postMessage(JSON.stringify(message));
+    const merge = (target, patch) => {
+        if (patch === null || typeof patch !== 'object' || Array.isArray(patch)) return patch;
+        const result = (target && typeof target === 'object' && !Array.isArray(target)) ? { ...target } : {};
+        for (const [key, value] of Object.entries(patch)) {
+            if (value === null) delete result[key];
+            else result[key] = merge(result[key], value);
+        }
+        return result;
+    };
+    window.__miko = Object.assign(window.__miko || {}, {
+        subscribe(topic, callback) {
+            const set = listeners[topic] || (listeners[topic] = new Set());
+            set.add(callback);
+            if (set.size === 1) post({ type: 'subscribe', topic });
+            else if (topic in snapshots) callback(snapshots[topic]);
+            return () => {
+                set.delete(callback);
+                if (set.size === 0) {
+                    delete snapshots[topic];
+                    post({ type: 'unsubscribe', topic });
+                }
+            };
+        },
+        getSnapshot(topic) {
+            return snapshots[topic];
+        },
+        __timed(requestId, result) {
+            const timings = { requestId, ...result._timings, deliveredAt: performance.timeOrigin + performance.now() };
+            window.__miko.lastTimings = timings;
+            const measure = (name, start, end) => {
+                if (typeof start !== 'number' || typeof end !== 'number') return;
+                try {
+                    performance.measure(`ipc ${timings.type} ${name}`, {
+                        start: start - performance.timeOrigin,
+                        end: end - performance.timeOrigin,
+                        detail: timings,
+                    });
+                } catch (e) {}
+            };
+            measure('round trip', timings.sentAt, timings.deliveredAt);
+            measure('native', timings.receivedAt, timings.respondedAt);
+            measure('upstream', timings.upstreamSent, timings.upstreamReceived);
+            measure('delivery', timings.respondedAt, timings.deliveredAt);
+        },
+        __receiveState(topic, payload, full) {
+            snapshots[topic] = full ? payload : merge(snapshots[topic], payload);
+            (listeners[topic] || []).forEach((callback) => {
+                try { callback(snapshots[topic]); } catch (e) { console.error('State listener failed:', e); }
+            });
+        },
+    });
+    post({ type: 'sync_reset' });
+})();
+"#;
+
+lazy_static! {
+    static ref SUBSCRIBED: Mutex<HashSet<String>> = Mutex::new(HashSet::new());
+    static ref LAST_SENT: Mutex<HashMap<String, Value>> = Mutex::new(HashMap::new());
+}
+
+fn snapshot(topic: &str) -> Value {
+    if topic == TOPIC_UNREAD {
+        return super::unread::snapshot();
+    }
+    if topic == TOPIC_TRANSFERS {
+        return super::transfers::snapshot();
+    }
+    if topic == TOPIC_PRESENCE {
+        return crate::thread_presence::snapshot();
+    }
+    if topic == TOPIC_COMPAT {
+        return crate::compat::snapshot();
+    }
+    if topic == TOPIC_QUIET_HOURS {
+        return crate::quiet_hours::snapshot();
+    }
+    if topic == TOPIC_PRIVACY {
+        return serde_json::to_value(super::settings::get().privacy).unwrap_or(Value::Null);
+    }
+
+    let runtime = state::runtime();
+    let value = match topic {
+        TOPIC_PROXY => serde_json::to_value(&runtime.proxy),
+        TOPIC_DOWNLOADS => serde_json::to_value(&runtime.downloads),
+        TOPIC_AUTH => serde_json::to_value(&runtime.auth),
+        TOPIC_UPDATES => serde_json::to_value(&runtime.updates),
+        TOPIC_CONNECTIVITY => Ok(serde_json::json!({
+            "status": runtime.connectivity,
+            "isMetered": runtime.metered,
+            "maintenanceUntil": crate::erp_maintenance::until().map(|until| until.to_rfc3339()),
+        })),
+        TOPIC_CONNECTION_QUALITY => serde_json::to_value(&runtime.connection_quality),
+        TOPIC_RENDERER => serde_json::to_value(&runtime.renderer),
+        _ => Ok(Value::Null),
+    };
+    value.unwrap_or(Value::Null)
+}
+
+/// Merge patch turning `old` into `new`, or `None` when nothing changed
+fn diff(old: &Value, new: &Value) -> Option<Value> {
+    if old == new {
+        return None;
+    }
+    let (Value::Object(old), Value::Object(new)) = (old, new) else {
+        return Some(new.clone());
+    };
+
+    let mut patch = Map::new();
+    for (key, value) in new {
+        match old.get(key) {
+            Some(previous) if value.is_object() => {
+                if let Some(nested) = diff(previous, value) {
+                    patch.insert(key.clone(), nested);
+                }
+            }
+            Some(previous) if previous == value => {}
+            _ => {
+                patch.insert(key.clone(), value.clone());
+            }
+        }
+    }
+    for key in old.keys().filter(|key| !new.contains_key(*key)) {
+        patch.insert(key.clone(), Value::Null);
+    }
+    Some(Value::Object(patch))
+}
+
+/// Script handing a patch to the page; `payload` is a JS expression
+pub fn receive_script(topic: &str, payload: &str, full: bool) -> String {
+    format!(
+        "window.__miko && window.__miko.__receiveState({}, {}, {});",
+        crate::ipc::js_literal(&topic),
+        payload,
+        full
+    )
+}
+
+pub fn subscribe(topic: &str) -> Result<(), String> {
+    if !TOPICS.contains(&topic) {
+        return Err(format!("Unknown state topic: {}", topic));
+    }
+    SUBSCRIBED.lock().unwrap().insert(topic.to_string());
+    // Nothing sent yet, so the next push is a full snapshot
+    LAST_SENT.lock().unwrap().remove(topic);
+    crate::ipc::queue_state(topic);
+    Ok(())
+}
+
+pub fn unsubscribe(topic: &str) {
+    SUBSCRIBED.lock().unwrap().remove(topic);
+    LAST_SENT.lock().unwrap().remove(topic);
+}
+
+/// Forget every subscription; sent by the page when it (re)loads
+pub fn reset() {
+    SUBSCRIBED.lock().unwrap().clear();
+    LAST_SENT.lock().unwrap().clear();
+}
+
+/// Push the changes of `topic` to the page if it is subscribed. The patch is
+/// computed when the webview queue is delivered, so bursts of changes
+/// collapse into one update.
+pub fn publish(topic: &str) {
+    if SUBSCRIBED.lock().unwrap().contains(topic) {
+        crate::ipc::queue_state(topic);
+    }
+}
+
+/// Send `topic` as a full snapshot next time instead of a patch, for state
+/// the page may hold stale parts of
+pub fn resend(topic: &str) {
+    LAST_SENT.lock().unwrap().remove(topic);
+    publish(topic);
+}
+
+/// The changes of `topic` since the last push (everything after a
+/// subscribe), if any, and whether they are a full snapshot
+pub fn patch(topic: &str) -> Option<(Value, bool)> {
+    if !SUBSCRIBED.lock().unwrap().contains(topic) {
+        return None;
+    }
+
+    let current = snapshot(topic);
+    let mut last_sent = LAST_SENT.lock().unwrap();
+    let patch = match last_sent.get(topic) {
+        Some(previous) => (diff(previous, &current)?, false),
+        None => (current.clone(), true),
+    };
+    last_sent.insert(topic.to_string(), current);
+    Some(patch)
+}
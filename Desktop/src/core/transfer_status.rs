@@ -0,0 +1,122 @@
+//! Transfer progress outside the page, for when the window is minimized:
+//! "↓ 42% 5.1 MB/s" in the window title and the tray tooltip while
+//! downloads or uploads run, the plain title again once none does.
+//!
+//! Worked out from [`super::transfers`] at most once a second by a thread
+//! that only runs while something transfers; the event loop picks changes up
+//! with [`take_update`] and applies them itself. `show_transfer_progress` in
+//! the settings turns it off.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+use lazy_static::lazy_static;
+
+use super::transfers::{self, TransferKind, TransferUnit};
+
+const INTERVAL: Duration = Duration::from_secs(1);
+
+lazy_static! {
+    /// What the title and tooltip show now
+    static ref CURRENT: Mutex<Option<String>> = Mutex::new(None);
+}
+
+static WATCHING: AtomicBool = AtomicBool::new(false);
+/// Set when [`CURRENT`] changed; the event loop takes it to update the window
+static DIRTY: AtomicBool = AtomicBool::new(false);
+
+fn enabled() -> bool {
+    super::settings::get().show_transfer_progress
+}
+
+/// A transfer started: follow the transfers until none is running
+pub fn watch() {
+    if !enabled() || WATCHING.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    std::thread::spawn(|| loop {
+        let line = if enabled() { status_line() } else { None };
+        publish(line.clone());
+        if line.is_none() {
+            WATCHING.store(false, Ordering::SeqCst);
+            // One may have started after the line was worked out
+            if status_line().is_none() || WATCHING.swap(true, Ordering::SeqCst) {
+                return;
+            }
+        }
+        std::thread::sleep(INTERVAL);
+    });
+}
+
+fn publish(line: Option<String>) {
+    let mut current = CURRENT.lock().unwrap();
+    if *current != line {
+        *current = line;
+        DIRTY.store(true, Ordering::SeqCst);
+        crate::ipc::wake();
+    }
+}
+
+/// Direction, share done and speed of the running byte transfers
+fn status_line() -> Option<String> {
+    let running: Vec<_> = transfers::running()
+        .into_iter()
+        .filter(|t| t.unit == TransferUnit::Bytes)
+        .collect();
+    if running.is_empty() {
+        return None;
+    }
+
+    let downloading = running.iter().any(|t| t.kind == TransferKind::Download);
+    let uploading = running.iter().any(|t| t.kind == TransferKind::Upload);
+    let arrows = match (downloading, uploading) {
+        (true, true) => "↓↑",
+        (true, false) => "↓",
+        _ => "↑",
+    };
+    let mut line = arrows.to_string();
+    // Only meaningful when every size is known
+    let totals: Option<Vec<u64>> = running.iter().map(|t| t.total).collect();
+    if let Some(total) = totals.map(|totals| totals.iter().sum::<u64>()).filter(|total| *total > 0) {
+        let done: u64 = running.iter().map(|t| t.done).sum();
+        line.push_str(&format!(" {}%", done.min(total) * 100 / total));
+    }
+    let speed: f64 = running.iter().filter_map(|t| t.speed).sum();
+    if speed > 0.0 {
+        line.push_str(&format!(" {}/s", format_bytes(speed)));
+    }
+    Some(line)
+}
+
+fn format_bytes(bytes: f64) -> String {
+    const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+    let mut value = bytes;
+    let mut unit = 0;
+    while value >= 1000.0 && unit < UNITS.len() - 1 {
+        value /= 1000.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{:.0} {}", value, UNITS[unit])
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+/// What the title and tooltip should show now
+pub fn current() -> Option<String> {
+    CURRENT.lock().unwrap().clone()
+}
+
+/// The new status line (`None`: idle, plain title) if it changed since the last call
+pub fn take_update() -> Option<Option<String>> {
+    DIRTY.swap(false, Ordering::SeqCst).then(current)
+}
+
+/// The main window's title for `status`
+pub fn window_title(status: Option<&str>) -> String {
+    match status {
+        Some(status) => format!("Workspace — {}", status),
+        None => "Workspace".to_string(),
+    }
+}
@@ -0,0 +1,279 @@
+//! One list of the downloads, uploads and exports, for the Transfers panel.
+//!
+//! Each manager records its transfers here as they start, progress and end,
+//! and implements [`TransferControl`] so `pause_transfer`, `resume_transfer`,
+//! `cancel_transfer` and `retry_transfer {id}` reach the manager that owns the
+//! transfer. The list is published as the `transfers` state topic, whose
+//! updates the IPC layer coalesces. Finished transfers stay listed (the newest
+//! [`MAX_FINISHED`]) so failed ones can be retried. Downloads held back on a
+//! metered connection are listed as `waiting_for_unmetered` until they start.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Instant;
+use lazy_static::lazy_static;
+use serde::Serialize;
+use serde_json::{json, Value};
+use tracing::{info, warn};
+
+use super::sync;
+
+const MAX_FINISHED: usize = 50;
+/// Weight of the newest sample in the smoothed speed
+const SPEED_SMOOTHING: f64 = 0.3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TransferKind {
+    Download,
+    /// Several attachments downloaded as one job (see `crate::attachments`)
+    DownloadGroup,
+    Upload,
+    Export,
+}
+
+/// What `done` and `total` count
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TransferUnit {
+    Bytes,
+    /// Exports know how many messages they wrote, not how large the file gets
+    Messages,
+    /// Grouped downloads count the files they finished
+    Files,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TransferState {
+    Running,
+    /// Held back until the connection is no longer metered (see `crate::metered`)
+    WaitingForUnmetered,
+    Paused,
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+impl TransferState {
+    pub fn is_finished(self) -> bool {
+        matches!(self, Self::Completed | Self::Failed | Self::Cancelled)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Transfer {
+    pub id: String,
+    pub kind: TransferKind,
+    pub label: String,
+    pub unit: TransferUnit,
+    pub done: u64,
+    pub total: Option<u64>,
+    /// Units per second, smoothed; `None` until two samples arrived
+    pub speed: Option<f64>,
+    pub state: TransferState,
+    pub path: Option<PathBuf>,
+    pub error: Option<String>,
+    pub started_at: chrono::DateTime<chrono::Utc>,
+    /// Per-file detail of a grouped transfer
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub items: Vec<TransferItem>,
+    #[serde(skip)]
+    last_sample: Option<(Instant, u64)>,
+    #[serde(skip)]
+    finished_at: Option<Instant>,
+}
+
+/// One file of a grouped transfer
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransferItem {
+    pub name: String,
+    /// `None` while queued
+    pub state: Option<TransferState>,
+    pub path: Option<PathBuf>,
+    pub error: Option<String>,
+}
+
+/// The pause/resume/cancel/retry actions a manager supports for its transfers
+pub trait TransferControl {
+    fn pause(&self, _id: &str) -> Result<(), String> {
+        Err("this transfer can't be paused".to_string())
+    }
+
+    fn resume(&self, _id: &str) -> Result<(), String> {
+        Err("this transfer can't be resumed".to_string())
+    }
+
+    fn cancel(&self, id: &str) -> Result<(), String>;
+
+    /// Start a failed or cancelled transfer again under the same id
+    fn retry(&self, id: &str) -> Result<(), String>;
+}
+
+lazy_static! {
+    static ref TRANSFERS: Mutex<HashMap<String, Transfer>> = Mutex::new(HashMap::new());
+}
+
+fn manager(kind: TransferKind) -> &'static dyn TransferControl {
+    match kind {
+        TransferKind::Download => &super::downloads::Downloads,
+        TransferKind::DownloadGroup => &crate::attachments::Groups,
+        TransferKind::Upload => &crate::upload::Uploads,
+        TransferKind::Export => &crate::export::Exports,
+    }
+}
+
+/// Record a transfer as running; a retried or resumed one replaces its old entry
+pub fn started(id: &str, kind: TransferKind, label: &str, unit: TransferUnit) {
+    insert(id, kind, label, unit, TransferState::Running);
+    super::transfer_status::watch();
+}
+
+/// Record a transfer that waits for an unmetered connection before it starts
+pub fn waiting(id: &str, kind: TransferKind, label: &str, unit: TransferUnit) {
+    insert(id, kind, label, unit, TransferState::WaitingForUnmetered);
+}
+
+fn insert(id: &str, kind: TransferKind, label: &str, unit: TransferUnit, state: TransferState) {
+    let mut transfers = TRANSFERS.lock().unwrap();
+    let previous = transfers.remove(id);
+    transfers.insert(
+        id.to_string(),
+        Transfer {
+            id: id.to_string(),
+            kind,
+            label: label.to_string(),
+            unit,
+            // A resumed download continues from where it stopped
+            done: previous.as_ref().map_or(0, |t| t.done),
+            total: previous.as_ref().and_then(|t| t.total),
+            speed: None,
+            state,
+            path: previous.as_ref().and_then(|t| t.path.clone()),
+            error: None,
+            started_at: chrono::Utc::now(),
+            items: previous.map(|t| t.items).unwrap_or_default(),
+            last_sample: None,
+            finished_at: None,
+        },
+    );
+    drop(transfers);
+    sync::publish(sync::TOPIC_TRANSFERS);
+}
+
+pub fn progress(id: &str, done: u64, total: Option<u64>) {
+    {
+        let mut transfers = TRANSFERS.lock().unwrap();
+        let Some(transfer) = transfers.get_mut(id) else { return };
+        let now = Instant::now();
+        if let Some((at, previous)) = transfer.last_sample {
+            let elapsed = now.duration_since(at).as_secs_f64();
+            if elapsed > 0.0 && done >= previous {
+                let sample = (done - previous) as f64 / elapsed;
+                transfer.speed = Some(match transfer.speed {
+                    Some(speed) => speed + SPEED_SMOOTHING * (sample - speed),
+                    None => sample,
+                });
+            }
+        }
+        transfer.last_sample = Some((now, done));
+        transfer.done = done;
+        transfer.total = total.or(transfer.total);
+    }
+    sync::publish(sync::TOPIC_TRANSFERS);
+}
+
+/// Where the transfer's file is (or will be) on disk
+pub fn set_path(id: &str, path: &Path) {
+    if let Some(transfer) = TRANSFERS.lock().unwrap().get_mut(id) {
+        transfer.path = Some(path.to_path_buf());
+    }
+    sync::publish(sync::TOPIC_TRANSFERS);
+}
+
+/// Replace the per-file detail of a grouped transfer
+pub fn set_items(id: &str, items: Vec<TransferItem>) {
+    if let Some(transfer) = TRANSFERS.lock().unwrap().get_mut(id) {
+        transfer.items = items;
+    }
+    sync::publish(sync::TOPIC_TRANSFERS);
+}
+
+/// Record that the transfer stopped running, and why
+pub fn stopped(id: &str, state: TransferState, error: Option<String>) {
+    {
+        let mut transfers = TRANSFERS.lock().unwrap();
+        let Some(transfer) = transfers.get_mut(id) else { return };
+        transfer.state = state;
+        transfer.error = error;
+        transfer.speed = None;
+        transfer.last_sample = None;
+        if state == TransferState::Completed {
+            if let Some(total) = transfer.total {
+                transfer.done = total;
+            }
+        }
+        transfer.finished_at = state.is_finished().then(Instant::now);
+        prune(&mut transfers);
+    }
+    sync::publish(sync::TOPIC_TRANSFERS);
+}
+
+pub fn state_of(id: &str) -> Option<TransferState> {
+    TRANSFERS.lock().unwrap().get(id).map(|t| t.state)
+}
+
+/// The transfers in the `Running` state
+pub fn running() -> Vec<Transfer> {
+    TRANSFERS.lock().unwrap().values().filter(|t| t.state == TransferState::Running).cloned().collect()
+}
+
+fn prune(transfers: &mut HashMap<String, Transfer>) {
+    let mut finished: Vec<(Instant, String)> =
+        transfers.values().filter_map(|t| t.finished_at.map(|at| (at, t.id.clone()))).collect();
+    if finished.len() <= MAX_FINISHED {
+        return;
+    }
+    finished.sort();
+    for (_, id) in &finished[..finished.len() - MAX_FINISHED] {
+        transfers.remove(id);
+    }
+}
+
+/// The `transfers` topic: every transfer keyed by id
+pub fn snapshot() -> Value {
+    serde_json::to_value(&*TRANSFERS.lock().unwrap()).unwrap_or(Value::Null)
+}
+
+/// Handle `pause_transfer`, `resume_transfer`, `cancel_transfer` and
+/// `retry_transfer`; returns the IPC result payload
+pub fn control(message_type: &str, id: &str) -> Value {
+    let kind = TRANSFERS.lock().unwrap().get(id).map(|t| t.kind);
+    let result = match kind {
+        None => Err(format!("unknown transfer: {}", id)),
+        Some(kind) => {
+            let manager = manager(kind);
+            match message_type {
+                "pause_transfer" => manager.pause(id),
+                "resume_transfer" => manager.resume(id),
+                "cancel_transfer" => manager.cancel(id),
+                "retry_transfer" => manager.retry(id),
+                other => Err(format!("unknown transfer action: {}", other)),
+            }
+        }
+    };
+
+    match result {
+        Ok(()) => {
+            info!("{} {}", message_type, id);
+            json!({ "success": true, "id": id, "action": message_type })
+        }
+        Err(e) => {
+            warn!("{} {} failed: {}", message_type, id, e);
+            json!({ "success": false, "id": id, "action": message_type, "error": e })
+        }
+    }
+}
@@ -0,0 +1,149 @@
+//! Per-thread unread counts, kept natively so the badge and tray stay correct
+//! while the webview is hidden or reloading.
+//!
+//! Counts are bumped by new-message reports for threads other than the active
+//! one and cleared by `mark_read`. They are persisted to `unread.json` and
+//! published on the `unread` state topic.
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use super::sync;
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct UnreadStore {
+    threads: BTreeMap<String, u32>,
+    #[serde(skip)]
+    active_thread: Option<String>,
+}
+
+lazy_static! {
+    static ref STORE: Mutex<UnreadStore> = Mutex::new(load());
+}
+
+/// Set whenever the total changes; the event loop takes it to refresh badge/tray
+static BADGE_DIRTY: AtomicBool = AtomicBool::new(true);
+
+fn unread_path() -> PathBuf {
+    crate::paths::data_dir().join("unread.json")
+}
+
+fn load() -> UnreadStore {
+    std::fs::read(unread_path())
+        .ok()
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .unwrap_or_default()
+}
+
+/// Persist, publish and flag the badge after a change
+fn changed(store: &UnreadStore) {
+    match serde_json::to_vec(store) {
+        Ok(bytes) => {
+            if let Err(e) = super::write_atomic(&unread_path(), &bytes) {
+                warn!("Failed to save unread counts: {}", e);
+            }
+        }
+        Err(e) => warn!("Failed to serialize unread counts: {}", e),
+    }
+    BADGE_DIRTY.store(true, Ordering::SeqCst);
+    crate::ipc::wake();
+}
+
+fn with_store(update: impl FnOnce(&mut UnreadStore) -> bool) {
+    {
+        let mut store = STORE.lock().unwrap();
+        if !update(&mut store) {
+            return;
+        }
+        changed(&store);
+    }
+    sync::publish(sync::TOPIC_UNREAD);
+}
+
+/// A message arrived for `thread_id`; ignored for the thread being viewed
+pub fn message_received(thread_id: &str) {
+    with_store(|store| {
+        if store.active_thread.as_deref() == Some(thread_id) {
+            return false;
+        }
+        let count = store.threads.entry(thread_id.to_string()).or_insert(0);
+        *count = count.saturating_add(1);
+        true
+    });
+}
+
+pub fn mark_read(thread_id: &str) {
+    with_store(|store| store.threads.remove(thread_id).is_some());
+}
+
+/// The thread currently open in the UI (reading it clears its count)
+pub fn set_active_thread(thread_id: Option<&str>) {
+    {
+        STORE.lock().unwrap().active_thread = thread_id.map(|id| id.to_string());
+    }
+    if let Some(id) = thread_id {
+        mark_read(id);
+    }
+}
+
+pub fn active_thread() -> Option<String> {
+    STORE.lock().unwrap().active_thread.clone()
+}
+
+/// Drop counts for threads that no longer exist
+pub fn thread_deleted(thread_id: &str) {
+    with_store(|store| {
+        if store.active_thread.as_deref() == Some(thread_id) {
+            store.active_thread = None;
+        }
+        store.threads.remove(thread_id).is_some()
+    });
+}
+
+/// Forget everything, e.g. on logout
+pub fn clear() {
+    with_store(|store| {
+        store.active_thread = None;
+        if store.threads.is_empty() {
+            return false;
+        }
+        store.threads.clear();
+        true
+    });
+}
+
+pub fn count(thread_id: &str) -> u32 {
+    STORE.lock().unwrap().threads.get(thread_id).copied().unwrap_or(0)
+}
+
+pub fn total() -> u32 {
+    STORE.lock().unwrap().threads.values().sum()
+}
+
+/// Snapshot for the `unread` state topic
+pub fn snapshot() -> serde_json::Value {
+    let store = STORE.lock().unwrap();
+    serde_json::json!({
+        "total": store.threads.values().sum::<u32>(),
+        "threads": store.threads,
+    })
+}
+
+/// Returns the new total if it changed since the last call
+pub fn take_badge_update() -> Option<u32> {
+    BADGE_DIRTY.swap(false, Ordering::SeqCst).then(total)
+}
+
+/// Text shown for the badge/tray, `None` when there is nothing unread
+pub fn badge_label(total: u32) -> Option<String> {
+    match total {
+        0 => None,
+        1..=99 => Some(total.to_string()),
+        _ => Some("99+".to_string()),
+    }
+}
@@ -0,0 +1,110 @@
+//! Diagnostics helpers backing the in-app log viewer and "Export diagnostic logs…".
+
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use serde_json::{json, Value};
+use tracing::info;
+
+pub const LOG_SOURCES: &[&str] = &["desktop", "proxy", "downloader"];
+const DEFAULT_TAIL_LINES: usize = 200;
+const MAX_TAIL_LINES: usize = 5000;
+
+/// Rotated log files for `source`, oldest first (the date suffix sorts lexically)
+fn log_files(source: &str) -> Vec<PathBuf> {
+    let prefix = format!("{}.", source);
+    let mut files: Vec<PathBuf> = std::fs::read_dir(crate::paths::log_dir())
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok().map(|e| e.path()))
+                .filter(|path| {
+                    path.file_name()
+                        .and_then(|n| n.to_str())
+                        .map(|n| n.starts_with(&prefix) && n.ends_with(".log"))
+                        .unwrap_or(false)
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    files.sort();
+    files
+}
+
+/// Return the last `lines` log lines of `source`, reading across rotation
+/// boundaries when today's file is shorter than requested.
+pub fn recent_logs(source: &str, lines: Option<u64>) -> Result<Vec<String>, String> {
+    if !LOG_SOURCES.contains(&source) {
+        return Err(format!("Unknown log source: {}", source));
+    }
+    let wanted = lines.map(|n| n as usize).unwrap_or(DEFAULT_TAIL_LINES).clamp(1, MAX_TAIL_LINES);
+
+    let mut collected: Vec<String> = Vec::new();
+    for path in log_files(source).iter().rev() {
+        let file = File::open(path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+        let mut file_lines: Vec<String> = BufReader::new(file).lines().map_while(Result::ok).collect();
+
+        let take = (wanted - collected.len()).min(file_lines.len());
+        let mut tail = file_lines.split_off(file_lines.len() - take);
+        tail.append(&mut collected);
+        collected = tail;
+
+        if collected.len() >= wanted {
+            break;
+        }
+    }
+
+    Ok(collected)
+}
+
+/// Environment details for support, as returned by the `get_system_info` IPC
+/// and written to `system.txt` in exports
+pub fn system_info() -> Value {
+    json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "os": format!("{} ({})", std::env::consts::OS, std::env::consts::ARCH),
+        "connectivity": crate::core::state::runtime().connectivity.label(),
+        "profile": crate::paths::profile(),
+        "portable": crate::paths::portable(),
+        "dataDir": crate::paths::data_dir().to_string_lossy(),
+        "downloadDir": crate::core::downloads::download_dir().to_string_lossy(),
+        // Only WebView2 keeps its profile where we tell it to
+        "webviewProfile": cfg!(windows).then(|| crate::paths::webview_user_data_dir(crate::core::settings::get().webview2_user_data_dir.as_deref()).to_string_lossy().into_owned()),
+        "requestTimeouts": crate::protocol::timeout_table(),
+        "rendering": crate::rendering::info(),
+    })
+}
+
+/// Default file name offered by the save dialog
+pub fn default_export_name() -> String {
+    format!("miko-diagnostics-{}.zip", chrono::Local::now().format("%Y%m%d-%H%M%S"))
+}
+
+/// Zip every log file and crash report into `destination` and return its path
+pub fn export_logs(destination: &Path) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let file = File::create(destination)?;
+    let mut zip = zip::ZipWriter::new(file);
+    let options = zip::write::SimpleFileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+
+    let mut count = 0;
+    for (folder, dir) in [("logs", crate::paths::log_dir()), ("crashes", crate::paths::crash_dir())] {
+        let Ok(entries) = std::fs::read_dir(&dir) else { continue };
+        for path in entries.filter_map(|e| e.ok().map(|e| e.path())).filter(|p| p.is_file()) {
+            let Some(name) = path.file_name().and_then(|n| n.to_str()) else { continue };
+            zip.start_file(format!("{}/{}", folder, name), options)?;
+            zip.write_all(&std::fs::read(&path)?)?;
+            count += 1;
+        }
+    }
+
+    zip.start_file("system.txt", options)?;
+    if let Value::Object(info) = system_info() {
+        for (key, value) in info.into_iter().filter(|(_, value)| !value.is_null()) {
+            writeln!(zip, "{}: {}", key, value.as_str().map(|s| s.to_string()).unwrap_or_else(|| value.to_string()))?;
+        }
+    }
+    writeln!(zip, "exported: {}", chrono::Local::now().to_rfc3339())?;
+
+    zip.finish()?;
+    info!("Exported {} diagnostic files to {}", count, destination.display());
+    Ok(destination.to_path_buf())
+}
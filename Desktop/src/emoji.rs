@@ -0,0 +1,86 @@
+//! Emoji picker data, served from the binary.
+//!
+//! The dataset (`Library/Shared/emoji.json`, shared with the web build) is
+//! embedded (see [`crate::assets`]) and answered at
+//! `miko://app/assets/emoji.json` with an ETag, so
+//! the picker never waits on the network or re-downloads it. `search_emoji
+//! {query}` runs the search natively against an index built on a worker at
+//! startup ([`build_index`]); until it is ready the first search builds it.
+
+use std::borrow::Cow;
+use std::sync::OnceLock;
+use http::{Request, Response};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tracing::{info, warn};
+
+pub const ROUTE: &str = "/assets/emoji.json";
+/// Matches returned by one search
+const MAX_RESULTS: usize = 24;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Emoji {
+    pub emoji: String,
+    pub name: String,
+    pub category: String,
+    #[serde(default)]
+    pub keywords: Vec<String>,
+}
+
+static INDEX: OnceLock<Vec<Emoji>> = OnceLock::new();
+
+/// Parse the embedded dataset; called once on a worker at startup
+pub fn build_index() {
+    index();
+}
+
+fn index() -> &'static [Emoji] {
+    INDEX.get_or_init(|| match serde_json::from_slice::<Vec<Emoji>>(crate::assets::EMOJI_JSON) {
+        Ok(emoji) => {
+            info!("Emoji index ready ({} entries)", emoji.len());
+            emoji
+        }
+        Err(e) => {
+            warn!("Embedded emoji data is invalid: {}", e);
+            Vec::new()
+        }
+    })
+}
+
+/// Answer `GET /assets/emoji.json`, with 304 when the page's copy is current
+pub fn serve(request: &Request<Vec<u8>>) -> Response<Cow<'static, [u8]>> {
+    crate::assets::serve(request, "emoji.json", "application/json; charset=utf-8", "public, max-age=31536000, immutable")
+}
+
+/// Lower is better; `None` when `emoji` doesn't match `query` at all
+fn rank(emoji: &Emoji, query: &str) -> Option<u8> {
+    if emoji.name.starts_with(query) {
+        Some(0)
+    } else if emoji.name.split(' ').any(|word| word.starts_with(query)) {
+        Some(1)
+    } else if emoji.keywords.iter().any(|keyword| keyword.starts_with(query)) {
+        Some(2)
+    } else if emoji.name.contains(query) {
+        Some(3)
+    } else {
+        None
+    }
+}
+
+/// Handle `search_emoji {query}`; returns the IPC result payload
+pub fn search(query: &str) -> Value {
+    let query = query.trim().to_lowercase();
+    if query.is_empty() {
+        return json!({ "success": true, "query": query, "results": [] });
+    }
+
+    let mut matches: Vec<(u8, usize, &Emoji)> = index()
+        .iter()
+        .enumerate()
+        .filter_map(|(position, emoji)| rank(emoji, &query).map(|rank| (rank, position, emoji)))
+        .collect();
+    // Ties keep the dataset's (picker) order
+    matches.sort_by_key(|(rank, position, _)| (*rank, *position));
+    let results: Vec<&Emoji> = matches.into_iter().take(MAX_RESULTS).map(|(_, _, emoji)| emoji).collect();
+    json!({ "success": true, "query": query, "results": results })
+}
@@ -0,0 +1,194 @@
+//! Typing indicators and read receipts (`send_ephemeral {kind, threadId,
+//! payload}`).
+//!
+//! These are small, frequent and only matter while fresh, so they skip the
+//! `miko://` forwarding pipeline. Signals arriving within [`BATCH_WINDOW`] are
+//! collected, the latest per kind and thread winning, and then sent together:
+//! as one `POST /api/ephemeral/batch` when `ephemeral.batch_endpoint` says the
+//! ERP has it, otherwise as one `POST /api/ephemeral/{kind}` per signal.
+//! While the connection isn't online they are dropped, not queued: a late
+//! "typing" is worse than none.
+
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::time::Duration;
+use lazy_static::lazy_static;
+use serde_json::{json, Value};
+use tracing::{debug, warn};
+
+use crate::core::state::{self, Connectivity};
+
+const BATCH_WINDOW: Duration = Duration::from_millis(250);
+const TIMEOUT: Duration = Duration::from_secs(5);
+const MAX_KIND_CHARS: usize = 32;
+/// Largest `payload`, serialized
+const MAX_PAYLOAD_BYTES: usize = 4096;
+
+#[derive(Default)]
+struct Pending {
+    /// (kind, thread id) -> payload
+    signals: BTreeMap<(String, String), Value>,
+    /// A flush is scheduled; later signals ride along
+    scheduled: bool,
+}
+
+lazy_static! {
+    static ref PENDING: Mutex<Pending> = Mutex::new(Pending::default());
+    static ref CLIENT: Option<reqwest::blocking::Client> = reqwest::blocking::Client::builder()
+        .timeout(TIMEOUT)
+        .build()
+        .map_err(|e| warn!("Ephemeral signals disabled: {}", e))
+        .ok();
+}
+
+/// Queue one signal for the next batch
+pub fn send(kind: &str, thread_id: &str, payload: Value) -> Result<(), String> {
+    if kind.is_empty() || kind.len() > MAX_KIND_CHARS || !kind.chars().all(|c| c.is_ascii_lowercase() || c == '_') {
+        return Err(format!("invalid ephemeral kind: {:?}", kind));
+    }
+    if payload.to_string().len() > MAX_PAYLOAD_BYTES {
+        return Err(format!("{} payload too large", kind));
+    }
+
+    let mut pending = PENDING.lock().unwrap();
+    pending.signals.insert((kind.to_string(), thread_id.to_string()), payload);
+    if !pending.scheduled {
+        pending.scheduled = true;
+        std::thread::spawn(|| {
+            std::thread::sleep(BATCH_WINDOW);
+            flush();
+        });
+    }
+    Ok(())
+}
+
+fn flush() {
+    let signals = {
+        let mut pending = PENDING.lock().unwrap();
+        pending.scheduled = false;
+        std::mem::take(&mut pending.signals)
+    };
+    let auth = state::runtime().auth.clone();
+    let connectivity = state::runtime().connectivity;
+    if connectivity != Connectivity::Online || !auth.signed_in {
+        debug!("Dropped {} ephemeral signal(s) ({:?})", signals.len(), connectivity);
+        return;
+    }
+    let Some(client) = CLIENT.as_ref() else { return };
+    let base_url = auth.api_base.unwrap_or_else(crate::protocol::api_base);
+    let base_url = base_url.trim_end_matches('/');
+
+    let requests: Vec<(String, Value)> = if crate::core::settings::get().ephemeral.batch_endpoint {
+        let events: Vec<Value> = signals
+            .into_iter()
+            .map(|((kind, thread_id), payload)| json!({ "kind": kind, "threadId": thread_id, "payload": payload }))
+            .collect();
+        vec![(format!("{}/api/ephemeral/batch", base_url), json!({ "events": events }))]
+    } else {
+        signals
+            .into_iter()
+            .map(|((kind, thread_id), payload)| {
+                (format!("{}/api/ephemeral/{}", base_url, kind), json!({ "threadId": thread_id, "payload": payload }))
+            })
+            .collect()
+    };
+
+    for (url, body) in requests {
+        let mut builder = client.post(&url).json(&body);
+        if let Some(token) = &auth.token {
+            builder = builder.bearer_auth(token);
+        }
+        // Not retried; the next signal supersedes this one anyway
+        if let Err(e) = builder.send().and_then(|response| response.error_for_status()) {
+            debug!("Ephemeral signal to {} failed: {}", url, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::time::Instant;
+
+    /// Wait for the flush of what was just sent: `count` requests, or none
+    /// for a whole window when `count` is 0
+    fn posted(seen: &Mutex<Vec<(String, Value)>>, count: usize) -> Vec<(String, Value)> {
+        let deadline = Instant::now() + BATCH_WINDOW * if count == 0 { 3 } else { 20 };
+        while Instant::now() < deadline && seen.lock().unwrap().len() < count.max(1) {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        // Give stragglers a moment so extra requests show up as a failure
+        std::thread::sleep(BATCH_WINDOW / 2);
+        let mut posted = std::mem::take(&mut *seen.lock().unwrap());
+        posted.sort_by(|a, b| (&a.0, a.1.to_string()).cmp(&(&b.0, b.1.to_string())));
+        posted
+    }
+
+    #[test]
+    fn signals_in_one_window_are_sent_together() {
+        crate::testing::upstream();
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let record = seen.clone();
+        crate::testing::route("/api/ephemeral/", move |request| {
+            let body = serde_json::from_slice(&request.body).unwrap();
+            record.lock().unwrap().push((request.path.clone(), body));
+            crate::testing::Reply::json(200, json!({ "success": true }))
+        });
+        let _session = crate::testing::session(true);
+        state::runtime().connectivity = Connectivity::Online;
+        let burst = || {
+            send("typing", "t1", json!({ "typing": true })).unwrap();
+            send("read", "t1", json!({ "messageId": "m1" })).unwrap();
+            send("typing", "t2", json!({ "typing": true })).unwrap();
+            // The latest per kind and thread wins
+            send("typing", "t1", json!({ "typing": false })).unwrap();
+        };
+
+        crate::core::settings::update(|s| s.ephemeral.batch_endpoint = true).unwrap();
+        burst();
+        let batch = posted(&seen, 1);
+        assert_eq!(batch.len(), 1, "{:?}", batch);
+        assert_eq!(batch[0].0, "/api/ephemeral/batch");
+        assert_eq!(
+            batch[0].1,
+            json!({ "events": [
+                { "kind": "read", "threadId": "t1", "payload": { "messageId": "m1" } },
+                { "kind": "typing", "threadId": "t1", "payload": { "typing": false } },
+                { "kind": "typing", "threadId": "t2", "payload": { "typing": true } },
+            ] })
+        );
+
+        crate::core::settings::update(|s| s.ephemeral.batch_endpoint = false).unwrap();
+        burst();
+        assert_eq!(
+            posted(&seen, 3),
+            [
+                ("/api/ephemeral/read".to_string(), json!({ "threadId": "t1", "payload": { "messageId": "m1" } })),
+                ("/api/ephemeral/typing".to_string(), json!({ "threadId": "t1", "payload": { "typing": false } })),
+                ("/api/ephemeral/typing".to_string(), json!({ "threadId": "t2", "payload": { "typing": true } })),
+            ]
+        );
+
+        // Dropped, not queued for later
+        state::runtime().connectivity = Connectivity::Offline;
+        burst();
+        assert!(posted(&seen, 0).is_empty());
+        state::runtime().connectivity = Connectivity::Online;
+        state::runtime().auth.signed_in = false;
+        burst();
+        assert!(posted(&seen, 0).is_empty());
+        state::runtime().auth.signed_in = true;
+        send("typing", "t3", json!({})).unwrap();
+        assert_eq!(posted(&seen, 1), [("/api/ephemeral/typing".to_string(), json!({ "threadId": "t3", "payload": {} }))]);
+    }
+
+    #[test]
+    fn malformed_signals_are_refused() {
+        for kind in ["", "Typing", "typing-indicator", "read/../x", &"k".repeat(MAX_KIND_CHARS + 1)] {
+            assert!(send(kind, "t1", json!({})).is_err(), "{:?}", kind);
+        }
+        let payload = json!({ "text": "x".repeat(MAX_PAYLOAD_BYTES) });
+        assert_eq!(send("typing", "t1", payload), Err("typing payload too large".to_string()));
+    }
+}
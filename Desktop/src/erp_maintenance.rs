@@ -0,0 +1,204 @@
+//! The ERP's nightly maintenance window, told apart from an ERP failure.
+//!
+//! During maintenance the ERP answers 503 with an HTML page. A 503 carrying
+//! one of [`HEADERS`] or [`BODY_MARKERS`] is answered to the page as the
+//! `erp_maintenance` code with `until` when the ERP says when it's back
+//! (`X-Erp-Maintenance-Until`, `Retry-After` or a `data-maintenance-until`
+//! attribute in the page). The first one starts the window: the page gets
+//! `erp-maintenance {active, until?, message}` once, to show a banner
+//! instead of an error per request, and the network monitor reports the
+//! `erp_maintenance` connectivity state (with `maintenanceUntil`), so cached
+//! threads are read offline and writes are refused with the same code.
+//!
+//! The health poller waits until the advertised end instead of polling.
+//! From then (or every [`RECHECK`] when no end was given) `GET /api/version`
+//! checks whether the ERP is back; once it is, the session is checked again
+//! and the page gets `erp-maintenance {active: false}` and `maintenance-ended
+//! {sessionValid, lastedMs}` to re-fetch its threads, as after a resume.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use chrono::{DateTime, Local, NaiveDateTime, NaiveTime};
+use http::{HeaderMap, StatusCode};
+use lazy_static::lazy_static;
+use serde_json::{json, Value};
+use tracing::{debug, info};
+
+/// Headers the ERP's maintenance page is sent with
+const HEADERS: &[&str] = &["x-erp-maintenance", "x-maintenance-mode"];
+const UNTIL_HEADER: &str = "x-erp-maintenance-until";
+/// Found (case-insensitively) in the body of the maintenance page
+const BODY_MARKERS: &[&str] = &["erp-maintenance", "scheduled maintenance"];
+const UNTIL_ATTRIBUTE: &str = "data-maintenance-until=\"";
+/// How much of the body is searched for a marker
+const MAX_SCAN: usize = 64 * 1024;
+/// Between checks when no end was advertised, or it has passed
+const RECHECK: Duration = Duration::from_secs(60);
+const PROBE_PATH: &str = "/api/version";
+
+#[derive(Debug, Clone)]
+struct Window {
+    started: Instant,
+    until: Option<DateTime<Local>>,
+}
+
+lazy_static! {
+    static ref WINDOW: Mutex<Option<Window>> = Mutex::new(None);
+}
+
+/// The end-of-maintenance watcher is running
+static WATCHING: AtomicBool = AtomicBool::new(false);
+
+/// `02:00` (the next one), an RFC 3339 time or an HTTP date
+fn parse_until(text: &str, now: DateTime<Local>) -> Option<DateTime<Local>> {
+    let text = text.trim();
+    if let Ok(at) = DateTime::parse_from_rfc3339(text).or_else(|_| DateTime::parse_from_rfc2822(text)) {
+        return Some(at.with_timezone(&Local));
+    }
+    let time = NaiveTime::parse_from_str(text, "%H:%M").ok()?;
+    let today = NaiveDateTime::new(now.date_naive(), time).and_local_timezone(Local).earliest()?;
+    Some(if today > now { today } else { today + chrono::Duration::days(1) })
+}
+
+/// When a maintenance answer says the ERP is back
+fn advertised_end(headers: &HeaderMap, body: &str, now: DateTime<Local>) -> Option<DateTime<Local>> {
+    let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok());
+    if let Some(until) = header(UNTIL_HEADER).and_then(|v| parse_until(v, now)) {
+        return Some(until);
+    }
+    if let Some(retry_after) = header(http::header::RETRY_AFTER.as_str()) {
+        return match retry_after.trim().parse::<i64>() {
+            Ok(secs) => Some(now + chrono::Duration::seconds(secs)),
+            Err(_) => parse_until(retry_after, now),
+        };
+    }
+    let start = body.find(UNTIL_ATTRIBUTE)? + UNTIL_ATTRIBUTE.len();
+    let end = body[start..].find('"')?;
+    parse_until(&body[start..start + end], now)
+}
+
+/// `Some(until)` when `status`, `headers` and `body` are the ERP's
+/// maintenance page
+pub fn detect(status: StatusCode, headers: &HeaderMap, body: &[u8]) -> Option<Option<DateTime<Local>>> {
+    if status != StatusCode::SERVICE_UNAVAILABLE {
+        return None;
+    }
+    let text = String::from_utf8_lossy(&body[..body.len().min(MAX_SCAN)]);
+    let marked = HEADERS.iter().any(|name| headers.contains_key(*name)) || {
+        let lower = text.to_lowercase();
+        BODY_MARKERS.iter().any(|marker| lower.contains(marker))
+    };
+    marked.then(|| advertised_end(headers, &text, Local::now()))
+}
+
+pub fn active() -> bool {
+    WINDOW.lock().unwrap().is_some()
+}
+
+/// The advertised end of the current window
+pub fn until() -> Option<DateTime<Local>> {
+    WINDOW.lock().unwrap().as_ref().and_then(|window| window.until)
+}
+
+/// How long the health poller should wait instead of its interval; `None`
+/// outside maintenance
+pub fn probe_wait() -> Option<Duration> {
+    let window = WINDOW.lock().unwrap().clone()?;
+    let remaining = window.until.and_then(|until| (until - Local::now()).to_std().ok());
+    Some(remaining.unwrap_or(RECHECK).max(RECHECK))
+}
+
+fn message(until: Option<DateTime<Local>>) -> String {
+    match until {
+        Some(until) => format!("Server maintenance until {}", until.format("%H:%M")),
+        None => "Server maintenance in progress".to_string(),
+    }
+}
+
+/// From the proxy on a maintenance answer; starts the window, or moves its end
+pub fn seen(until: Option<DateTime<Local>>) {
+    let started = {
+        let mut window = WINDOW.lock().unwrap();
+        match window.as_mut() {
+            Some(window) => {
+                if until.is_none() || window.until == until {
+                    return;
+                }
+                window.until = until;
+                false
+            }
+            None => {
+                *window = Some(Window { started: Instant::now(), until });
+                true
+            }
+        }
+    };
+    if started {
+        info!("ERP in maintenance until {:?}", until);
+        crate::core::network::recheck();
+    } else {
+        info!("ERP maintenance now until {:?}", until);
+    }
+    crate::core::sync::publish(crate::core::sync::TOPIC_CONNECTIVITY);
+    emit("erp-maintenance", banner());
+    if !WATCHING.swap(true, Ordering::SeqCst) {
+        std::thread::spawn(watch);
+    }
+}
+
+/// The `erp-maintenance` payload for the current state
+pub fn banner() -> Value {
+    let window = WINDOW.lock().unwrap().clone();
+    match window {
+        Some(window) => json!({
+            "active": true,
+            "until": window.until.map(|until| until.to_rfc3339()),
+            "message": message(window.until),
+        }),
+        None => json!({ "active": false }),
+    }
+}
+
+/// Wait for the end of the window, then check until the ERP is back
+fn watch() {
+    while let Some(wait) = probe_wait() {
+        std::thread::sleep(wait);
+        let response = crate::protocol::get(PROBE_PATH);
+        // Still in maintenance: `seen` already took any new end from it
+        if is_maintenance(&response) {
+            debug!("ERP still in maintenance");
+            continue;
+        }
+        if response.status().is_server_error() {
+            debug!("ERP not back from maintenance yet: {}", response.status());
+            continue;
+        }
+        ended();
+        break;
+    }
+    WATCHING.store(false, Ordering::SeqCst);
+}
+
+fn is_maintenance(response: &http::Response<std::borrow::Cow<'static, [u8]>>) -> bool {
+    serde_json::from_slice::<Value>(response.body()).is_ok_and(|body| body["error"]["code"] == "erp_maintenance")
+}
+
+fn ended() {
+    let Some(window) = WINDOW.lock().unwrap().take() else { return };
+    let lasted = window.started.elapsed();
+    info!("ERP maintenance over after {} min", lasted.as_secs() / 60);
+    crate::core::network::recheck();
+    crate::core::health::poll_now();
+    crate::core::sync::publish(crate::core::sync::TOPIC_CONNECTIVITY);
+    let session_valid = crate::session_takeover::verify();
+    emit("erp-maintenance", banner());
+    emit("maintenance-ended", json!({ "sessionValid": session_valid, "lastedMs": lasted.as_millis() as u64 }));
+}
+
+fn emit(event: &str, payload: Value) {
+    if let Some(webview) = crate::ipc::handle() {
+        webview.emit(event, &payload);
+    }
+    crate::ipc::wake();
+}
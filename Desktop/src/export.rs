@@ -0,0 +1,462 @@
+//! Full thread export (JSON Lines or CSV) for compliance requests.
+//!
+//! Messages are fetched page by page from `/api/chats/{uuid}/messages` and
+//! written out as each page arrives, so only one page is ever held in memory.
+//! The server hands out a `nextCursor` with every page except the last.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use lazy_static::lazy_static;
+use serde_json::{json, Value};
+use tracing::{debug, info, warn};
+
+use crate::core::transfers::{self, TransferControl, TransferKind, TransferState, TransferUnit};
+
+const PAGE_SIZE: usize = 500;
+/// Attempts per page when rate limited or on server errors
+const MAX_ATTEMPTS: u32 = 6;
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+const CSV_COLUMNS: &[&str] = &["id", "messageId", "createdAt", "userId", "userName", "userRole", "content", "attachments", "isEdited", "editedAt"];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExportFormat {
+    JsonLines,
+    Csv,
+}
+
+impl ExportFormat {
+    pub fn parse(format: &str) -> Option<Self> {
+        match format.to_ascii_lowercase().as_str() {
+            "json" | "jsonl" | "ndjson" => Some(Self::JsonLines),
+            "csv" => Some(Self::Csv),
+            _ => None,
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::JsonLines => "jsonl",
+            Self::Csv => "csv",
+        }
+    }
+
+    pub fn filter_name(self) -> &'static str {
+        match self {
+            Self::JsonLines => "JSON Lines",
+            Self::Csv => "CSV file",
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct ExportRequest {
+    pub thread_id: String,
+    pub format: ExportFormat,
+    /// ERP API base; never taken from the page (see [`ExportRequest::new`])
+    base_url: String,
+    token: Option<String>,
+}
+
+impl ExportRequest {
+    /// Export `thread_id` with the app's API base and the signed-in user's
+    /// token, whatever the page sent along
+    pub fn new(thread_id: String, format: ExportFormat) -> Self {
+        Self {
+            thread_id,
+            format,
+            base_url: crate::protocol::api_base(),
+            token: crate::core::state::runtime().auth.token.clone(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ExportError {
+    Cancelled,
+    Failed(String),
+}
+
+impl std::fmt::Display for ExportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Cancelled => write!(f, "export cancelled"),
+            Self::Failed(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl<E: std::error::Error> From<E> for ExportError {
+    fn from(e: E) -> Self {
+        Self::Failed(e.to_string())
+    }
+}
+
+lazy_static! {
+    /// Cancellation flags of the exports in progress, keyed by thread id
+    static ref RUNNING: Mutex<HashMap<String, Arc<AtomicBool>>> = Mutex::new(HashMap::new());
+    /// Failed and cancelled exports with their file, kept for `retry_transfer`
+    static ref STOPPED: Mutex<HashMap<String, (ExportRequest, PathBuf)>> = Mutex::new(HashMap::new());
+}
+
+/// Exports as listed in [`crate::core::transfers`], as `export-<thread id>`
+pub struct Exports;
+
+fn transfer_id(thread_id: &str) -> String {
+    format!("export-{}", thread_id)
+}
+
+impl TransferControl for Exports {
+    fn cancel(&self, id: &str) -> Result<(), String> {
+        let thread_id = id.strip_prefix("export-").unwrap_or(id);
+        cancel(thread_id).then_some(()).ok_or_else(|| format!("export {} is not running", id))
+    }
+
+    fn retry(&self, id: &str) -> Result<(), String> {
+        let (request, path) = STOPPED.lock().unwrap().remove(id).ok_or_else(|| format!("export {} can't be retried", id))?;
+        std::thread::spawn(move || {
+            let result = export_to(&request, &path);
+            crate::ipc::respond(None, "export-finished", &result);
+        });
+        Ok(())
+    }
+}
+
+/// Ask a running export of `thread_id` to stop; returns false if none is running
+pub fn cancel(thread_id: &str) -> bool {
+    match RUNNING.lock().unwrap().get(thread_id) {
+        Some(flag) => {
+            flag.store(true, Ordering::SeqCst);
+            true
+        }
+        None => false,
+    }
+}
+
+pub fn default_file_name(thread_id: &str, format: ExportFormat) -> String {
+    let safe_id: String = thread_id
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+        .collect();
+    format!("thread-{}-{}.{}", safe_id, chrono::Local::now().format("%Y%m%d-%H%M%S"), format.extension())
+}
+
+/// Export the thread to `path`, emitting `export-progress` to the webview and
+/// finishing with a notification. Returns the IPC result payload.
+pub fn export_to(request: &ExportRequest, path: &Path) -> Value {
+    // Checked again in `run`; this keeps the running export's transfer entry intact
+    if RUNNING.lock().unwrap().contains_key(&request.thread_id) {
+        return json!({ "success": false, "threadId": request.thread_id, "error": "an export of this thread is already running" });
+    }
+    let webview = crate::ipc::handle();
+    let id = transfer_id(&request.thread_id);
+    transfers::started(&id, TransferKind::Export, &format!("Thread {} ({})", request.thread_id, request.format.extension()), TransferUnit::Messages);
+    transfers::set_path(&id, path);
+    let result = run(request, path, |done, total| {
+        if let Some(webview) = &webview {
+            webview.emit_latest("export-progress", &request.thread_id, &json!({ "threadId": request.thread_id, "done": done, "total": total }));
+        }
+        transfers::progress(&id, done, total);
+    });
+
+    match &result {
+        Ok(_) => transfers::stopped(&id, TransferState::Completed, None),
+        Err(ExportError::Cancelled) => transfers::stopped(&id, TransferState::Cancelled, None),
+        Err(e) => transfers::stopped(&id, TransferState::Failed, Some(e.to_string())),
+    }
+    if result.is_err() {
+        STOPPED.lock().unwrap().insert(id, (request.clone(), path.to_path_buf()));
+    }
+
+    match result {
+        Ok(count) => {
+            let _ = crate::hooks::show_notification(crate::hooks::noti::NotificationData {
+                title: "Thread exported".to_string(),
+                message: format!("{} messages saved to {}", count, path.display()),
+                icon: None,
+                chat_uuid: None,
+                reveal_path: Some(path.to_string_lossy().into_owned()),
+                tag: None,
+            });
+            json!({ "success": true, "threadId": request.thread_id, "path": path.to_string_lossy(), "count": count })
+        }
+        Err(ExportError::Cancelled) => json!({ "success": false, "threadId": request.thread_id, "cancelled": true }),
+        Err(e) => json!({ "success": false, "threadId": request.thread_id, "error": e.to_string() }),
+    }
+}
+
+/// Export the whole thread to `path`, calling `on_progress(done, total)` after
+/// every page. The partial file is removed if the export fails or is cancelled.
+fn run(request: &ExportRequest, path: &Path, on_progress: impl FnMut(u64, Option<u64>)) -> Result<u64, ExportError> {
+    let cancelled = Arc::new(AtomicBool::new(false));
+    {
+        let mut running = RUNNING.lock().unwrap();
+        if running.contains_key(&request.thread_id) {
+            return Err(ExportError::Failed("an export of this thread is already running".to_string()));
+        }
+        running.insert(request.thread_id.clone(), cancelled.clone());
+    }
+
+    let result = write_export(request, path, &cancelled, on_progress);
+    RUNNING.lock().unwrap().remove(&request.thread_id);
+
+    match &result {
+        Ok(count) => info!("Exported {} messages of thread {} to {}", count, request.thread_id, path.display()),
+        Err(e) => {
+            warn!("Export of thread {} stopped: {}", request.thread_id, e);
+            if let Err(e) = std::fs::remove_file(path) {
+                warn!("Failed to remove partial export {}: {}", path.display(), e);
+            }
+        }
+    }
+    result
+}
+
+fn write_export(
+    request: &ExportRequest,
+    path: &Path,
+    cancelled: &AtomicBool,
+    mut on_progress: impl FnMut(u64, Option<u64>),
+) -> Result<u64, ExportError> {
+    let client = reqwest::blocking::Client::builder()
+        .timeout(Duration::from_secs(60))
+        .build()?;
+    let mut out = BufWriter::new(File::create(path)?);
+    if request.format == ExportFormat::Csv {
+        // BOM so Excel picks UTF-8
+        out.write_all(b"\xEF\xBB\xBF")?;
+        write_csv_row(&mut out, CSV_COLUMNS.iter().map(|c| c.to_string()))?;
+    }
+
+    let mut cursor: Option<String> = None;
+    let mut done = 0u64;
+    loop {
+        let page = fetch_page(&client, &request.base_url, &request.thread_id, request.token.as_deref(), cursor.as_deref(), cancelled)?;
+        let messages = page
+            .get("messages")
+            .or_else(|| page.get("data"))
+            .and_then(|m| m.as_array())
+            .ok_or_else(|| ExportError::Failed("unexpected response: no messages array".to_string()))?;
+
+        for message in messages {
+            match request.format {
+                ExportFormat::JsonLines => {
+                    serde_json::to_writer(&mut out, message)?;
+                    out.write_all(b"\n")?;
+                }
+                ExportFormat::Csv => write_csv_row(&mut out, CSV_COLUMNS.iter().map(|c| csv_field(&message[*c])))?,
+            }
+        }
+        done += messages.len() as u64;
+        on_progress(done, page["total"].as_u64());
+
+        let next = page["nextCursor"].as_str().or_else(|| page["next_cursor"].as_str());
+        match next {
+            // A server that repeats its cursor would otherwise loop forever
+            Some(next) if !messages.is_empty() && cursor.as_deref() != Some(next) => cursor = Some(next.to_string()),
+            _ => break,
+        }
+    }
+
+    out.flush()?;
+    out.into_inner().map_err(|e| ExportError::Failed(e.to_string()))?.sync_all()?;
+    Ok(done)
+}
+
+/// One page of the thread's messages, retried while rate limited; shared with
+/// [`crate::history`], which lists the messages it deletes the same way
+pub(crate) fn fetch_page(
+    client: &reqwest::blocking::Client,
+    base_url: &str,
+    thread_id: &str,
+    token: Option<&str>,
+    cursor: Option<&str>,
+    cancelled: &AtomicBool,
+) -> Result<Value, ExportError> {
+    let url = api_url(base_url, &["api", "chats", thread_id, "messages"])?;
+    let mut query = vec![("limit", PAGE_SIZE.to_string())];
+    if let Some(cursor) = cursor {
+        query.push(("cursor", cursor.to_string()));
+    }
+
+    let mut backoff = Duration::from_secs(1);
+    for attempt in 1..=MAX_ATTEMPTS {
+        if cancelled.load(Ordering::SeqCst) {
+            return Err(ExportError::Cancelled);
+        }
+
+        let mut builder = client.get(url.clone()).query(&query);
+        if let Some(token) = token {
+            builder = builder.bearer_auth(token);
+        }
+
+        let retry_after = match builder.send() {
+            Ok(response) if response.status().is_success() => return Ok(response.json()?),
+            Ok(response) if response.status() == reqwest::StatusCode::TOO_MANY_REQUESTS || response.status().is_server_error() => {
+                debug!("Export page request got {} (attempt {})", response.status(), attempt);
+                response
+                    .headers()
+                    .get(reqwest::header::RETRY_AFTER)
+                    .and_then(|v| v.to_str().ok())
+                    .and_then(|v| v.trim().parse::<u64>().ok())
+                    .map(Duration::from_secs)
+            }
+            Ok(response) => {
+                let status = response.status();
+                let body = response.text().unwrap_or_default();
+                return Err(ExportError::Failed(format!("server returned {}: {}", status, body.trim())));
+            }
+            Err(e) if e.is_timeout() || e.is_connect() => {
+                debug!("Export page request failed (attempt {}): {}", attempt, e);
+                None
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        let wait = retry_after.unwrap_or(backoff).min(MAX_BACKOFF);
+        sleep_unless_cancelled(wait, cancelled)?;
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+    }
+
+    Err(ExportError::Failed(format!("giving up after {} attempts", MAX_ATTEMPTS)))
+}
+
+/// `base_url` with `segments` appended, each escaped as a single segment;
+/// shared with [`crate::history`]
+pub(crate) fn api_url(base_url: &str, segments: &[&str]) -> Result<reqwest::Url, ExportError> {
+    // Pushed as is, these would name a different path
+    if let Some(segment) = segments.iter().find(|segment| matches!(**segment, "" | "." | "..")) {
+        return Err(ExportError::Failed(format!("invalid id {:?}", segment)));
+    }
+    let mut url = reqwest::Url::parse(base_url)?;
+    url.path_segments_mut()
+        .map_err(|()| ExportError::Failed(format!("not an API base: {}", base_url)))?
+        .pop_if_empty()
+        .extend(segments);
+    Ok(url)
+}
+
+pub(crate) fn sleep_unless_cancelled(duration: Duration, cancelled: &AtomicBool) -> Result<(), ExportError> {
+    let step = Duration::from_millis(100);
+    let mut slept = Duration::ZERO;
+    while slept < duration {
+        if cancelled.load(Ordering::SeqCst) {
+            return Err(ExportError::Cancelled);
+        }
+        std::thread::sleep(step);
+        slept += step;
+    }
+    Ok(())
+}
+
+fn csv_field(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) => s.clone(),
+        Value::Array(items) => items
+            .iter()
+            .map(|item| item.as_str().map(|s| s.to_string()).unwrap_or_else(|| item.to_string()))
+            .collect::<Vec<_>>()
+            .join("; "),
+        // sql.NullString / NullTime come through as {String|Time, Valid}
+        Value::Object(map) if map.get("Valid").is_some() => match map.get("Valid") {
+            Some(Value::Bool(true)) => map
+                .iter()
+                .find(|(key, _)| key.as_str() != "Valid")
+                .map(|(_, v)| csv_field(v))
+                .unwrap_or_default(),
+            _ => String::new(),
+        },
+        other => other.to_string(),
+    }
+}
+
+fn write_csv_row(out: &mut impl Write, fields: impl Iterator<Item = String>) -> std::io::Result<()> {
+    let mut first = true;
+    for field in fields {
+        if !first {
+            out.write_all(b",")?;
+        }
+        first = false;
+        if field.contains([',', '"', '\n', '\r']) {
+            write!(out, "\"{}\"", field.replace('"', "\"\""))?;
+        } else {
+            out.write_all(field.as_bytes())?;
+        }
+    }
+    out.write_all(b"\r\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn thread_ids_stay_one_segment() {
+        let url = api_url("https://erp.example/", &["api", "chats", "0b6f3c1e-uuid", "messages"]).unwrap();
+        assert_eq!(url.as_str(), "https://erp.example/api/chats/0b6f3c1e-uuid/messages");
+
+        let url = api_url("https://erp.example/erp/", &["api", "chats", "../a/b?c#d", "messages"]).unwrap();
+        assert_eq!(url.as_str(), "https://erp.example/erp/api/chats/..%2Fa%2Fb%3Fc%23d/messages");
+        assert_eq!(url.query(), None);
+
+    }
+
+    #[test]
+    fn unusable_bases_and_ids_fail() {
+        assert!(api_url("not a url", &["api"]).is_err());
+        assert!(api_url("mailto:ops@example.com", &["api"]).is_err());
+        for id in ["", ".", ".."] {
+            assert!(api_url("https://erp.example/", &["api", "chats", id, "messages"]).is_err(), "{:?}", id);
+        }
+    }
+
+    #[test]
+    fn cursors_go_back_as_they_came() {
+        let base = crate::testing::upstream();
+        crate::testing::route("/api/chats/cursor-echo/", |request| {
+            crate::testing::Reply::json(200, json!({ "messages": [], "path": request.path }))
+        });
+
+        let client = reqwest::blocking::Client::new();
+        for cursor in ["eyJpZCI6MX0=", "a&b=c", "กี่ 😀", "x+y /..?#"] {
+            let page = fetch_page(&client, base, "cursor-echo", None, Some(cursor), &AtomicBool::new(false)).unwrap();
+            let url = reqwest::Url::parse(&format!("{}{}", base, page["path"].as_str().unwrap())).unwrap();
+            let query: Vec<(String, String)> = url.query_pairs().into_owned().collect();
+            assert_eq!(query, [("limit".to_string(), PAGE_SIZE.to_string()), ("cursor".to_string(), cursor.to_string())]);
+        }
+    }
+
+    #[test]
+    fn long_threads_are_paged_through_and_retried() {
+        use crate::testing::mock_erp::{chat_uuid, Faults};
+
+        let session = crate::testing::session(true);
+        let mock = session.use_mock_erp();
+        let chat = chat_uuid(3);
+        mock.add_messages(&chat, PAGE_SIZE * 2);
+        let total = mock.messages(&chat).len();
+        // The first page only comes on the second try
+        mock.set_faults(Faults { path_prefix: Some(format!("/api/chats/{}/", chat)), status: Some(503), count: Some(1), ..Faults::default() });
+
+        let path = std::env::temp_dir().join(format!("miko-export-{}.jsonl", uuid::Uuid::new_v4().simple()));
+        let mut progress = Vec::new();
+        let count = run(&ExportRequest::new(chat.clone(), ExportFormat::JsonLines), &path, |done, _| progress.push(done));
+        let lines = std::fs::read_to_string(&path);
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(count.unwrap(), total as u64);
+        let ids: Vec<u64> = lines.unwrap().lines().map(|line| serde_json::from_str::<Value>(line).unwrap()["id"].as_u64().unwrap()).collect();
+        assert_eq!(ids, (1..=total as u64).collect::<Vec<_>>());
+        assert_eq!(progress, [500, 1000, total as u64]);
+
+        let requests: Vec<String> = mock.take_requests().into_iter().map(|r| r.path).collect();
+        assert_eq!(requests.len(), 4, "{:?}", requests);
+        assert_eq!(requests[0], requests[1]);
+        assert!(requests[3].ends_with("cursor=1000"), "{:?}", requests);
+    }
+}
@@ -0,0 +1,227 @@
+//! Dates and numbers formatted natively for the page, so it doesn't depend
+//! on the webview's `Intl` data (older WebView2 runtimes got Thai
+//! Buddhist-calendar dates wrong).
+//!
+//! `format_datetime {epoch_ms, style, tz?}`, `format_datetimes {epoch_ms:
+//! [..], style, tz?}` for a whole list of message timestamps in one call, and
+//! `format_number {value, style}`. The locale is the `language` setting, or
+//! the OS locale; Thai gets the Buddhist calendar, as CLDR has it. `tz` is an
+//! IANA zone name; without one the OS zone is used.
+
+use chrono::{Datelike, Local, NaiveDateTime, TimeZone, Timelike};
+use fixed_decimal::FixedDecimal;
+use icu::calendar::DateTime;
+use icu::datetime::options::length;
+use icu::datetime::DateTimeFormatter;
+use icu::decimal::FixedDecimalFormatter;
+use icu::locid::Locale;
+use serde_json::{json, Value};
+
+/// Timestamps per `format_datetimes`; a page of messages is far fewer
+const MAX_BULK: usize = 2000;
+const FALLBACK_LOCALE: &str = "en-US";
+
+/// The locale to format for: the `language` setting, else the OS locale
+pub fn locale() -> Locale {
+    let tag = crate::core::settings::get().language.or_else(sys_locale::get_locale).unwrap_or_default();
+    // macOS can report `th_TH`
+    tag.replace('_', "-").parse().unwrap_or_else(|_| FALLBACK_LOCALE.parse().unwrap())
+}
+
+fn datetime_options(style: &str) -> Option<length::Bag> {
+    use length::{Date, Time};
+
+    Some(match style {
+        "full_date" => length::Bag::from_date_style(Date::Full),
+        "long_date" => length::Bag::from_date_style(Date::Long),
+        "medium_date" => length::Bag::from_date_style(Date::Medium),
+        "short_date" => length::Bag::from_date_style(Date::Short),
+        "medium_time" => length::Bag::from_time_style(Time::Medium),
+        "short_time" => length::Bag::from_time_style(Time::Short),
+        "long_datetime" => length::Bag::from_date_time_style(Date::Long, Time::Short),
+        "medium_datetime" => length::Bag::from_date_time_style(Date::Medium, Time::Short),
+        "short_datetime" => length::Bag::from_date_time_style(Date::Short, Time::Short),
+        _ => return None,
+    })
+}
+
+/// Wall-clock time of `epoch_ms` in `tz`, or in the OS zone
+fn local_time(epoch_ms: i64, tz: Option<&chrono_tz::Tz>) -> Option<NaiveDateTime> {
+    match tz {
+        Some(tz) => tz.timestamp_millis_opt(epoch_ms).single().map(|t| t.naive_local()),
+        None => Local.timestamp_millis_opt(epoch_ms).single().map(|t| t.naive_local()),
+    }
+}
+
+/// Formats timestamps with one formatter; building it is the expensive part
+struct DateTimes {
+    formatter: DateTimeFormatter,
+    tz: Option<chrono_tz::Tz>,
+}
+
+impl DateTimes {
+    fn new(locale: &Locale, style: &str, tz: Option<&str>) -> Result<Self, String> {
+        let options = datetime_options(style).ok_or_else(|| format!("unknown date style: {}", style))?;
+        let tz = tz.map(|tz| tz.parse::<chrono_tz::Tz>().map_err(|_| format!("unknown time zone: {}", tz))).transpose()?;
+        let formatter = DateTimeFormatter::try_new(&locale.into(), options.into()).map_err(|e| e.to_string())?;
+        Ok(Self { formatter, tz })
+    }
+
+    fn format(&self, epoch_ms: i64) -> Option<String> {
+        let t = local_time(epoch_ms, self.tz.as_ref())?;
+        let datetime =
+            DateTime::try_new_iso_datetime(t.year(), t.month() as u8, t.day() as u8, t.hour() as u8, t.minute() as u8, t.second() as u8)
+                .ok()?;
+        self.formatter.format_to_string(&datetime.to_any()).ok()
+    }
+}
+
+/// `format_datetime`
+pub fn format_datetime(message: &Value) -> Value {
+    datetime_in(&locale(), message)
+}
+
+fn datetime_in(locale: &Locale, message: &Value) -> Value {
+    let result = DateTimes::new(locale, message["style"].as_str().unwrap_or("medium_datetime"), message["tz"].as_str())
+        .and_then(|formatter| {
+            let epoch_ms = message["epoch_ms"].as_i64().ok_or("epoch_ms must be an integer")?;
+            formatter.format(epoch_ms).ok_or_else(|| format!("timestamp out of range: {}", epoch_ms))
+        });
+    match result {
+        Ok(value) => json!({ "success": true, "value": value, "locale": locale.to_string() }),
+        Err(error) => json!({ "success": false, "error": error }),
+    }
+}
+
+/// `format_datetimes`: one string per timestamp, `null` for ones that aren't
+pub fn format_datetimes(message: &Value) -> Value {
+    datetimes_in(&locale(), message)
+}
+
+fn datetimes_in(locale: &Locale, message: &Value) -> Value {
+    let Some(timestamps) = message["epoch_ms"].as_array() else {
+        return json!({ "success": false, "error": "epoch_ms must be an array" });
+    };
+    if timestamps.len() > MAX_BULK {
+        return json!({ "success": false, "error": format!("more than {} timestamps", MAX_BULK) });
+    }
+    match DateTimes::new(locale, message["style"].as_str().unwrap_or("medium_datetime"), message["tz"].as_str()) {
+        Ok(formatter) => {
+            let values: Vec<Option<String>> =
+                timestamps.iter().map(|epoch_ms| epoch_ms.as_i64().and_then(|ms| formatter.format(ms))).collect();
+            json!({ "success": true, "values": values, "locale": locale.to_string() })
+        }
+        Err(error) => json!({ "success": false, "error": error }),
+    }
+}
+
+/// `format_number`: `decimal` (up to 3 fraction digits), `integer` or `percent` (of a 0..1 value)
+pub fn format_number(message: &Value) -> Value {
+    number_in(&locale(), message)
+}
+
+fn number_in(locale: &Locale, message: &Value) -> Value {
+    let style = message["style"].as_str().unwrap_or("decimal");
+    let result = message["value"].as_f64().filter(|v| v.is_finite()).ok_or_else(|| "value must be a number".to_string()).and_then(|value| {
+        let (value, digits, suffix) = match style {
+            "decimal" => (value, -3, ""),
+            "integer" => (value, 0, ""),
+            "percent" => (value * 100.0, 0, "%"),
+            _ => return Err(format!("unknown number style: {}", style)),
+        };
+        // `Display` for f64 never uses an exponent, so this always parses
+        let mut decimal: FixedDecimal = value.to_string().parse().map_err(|_| format!("value out of range: {}", value))?;
+        decimal.half_even(digits);
+        decimal.trim_end();
+        let formatter = FixedDecimalFormatter::try_new(&locale.into(), Default::default()).map_err(|e| e.to_string())?;
+        Ok(format!("{}{}", formatter.format_to_string(&decimal), suffix))
+    });
+    match result {
+        Ok(value) => json!({ "success": true, "value": value, "locale": locale.to_string() }),
+        Err(error) => json!({ "success": false, "error": error }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 2023-11-15 14:05:09 in Bangkok
+    const NOV_15: i64 = 1_700_031_909_000;
+
+    fn datetime(locale: &str, style: &str, tz: &str) -> Value {
+        datetime_in(&locale.parse().unwrap(), &json!({ "epoch_ms": NOV_15, "style": style, "tz": tz }))["value"].clone()
+    }
+
+    fn number(locale: &str, value: f64, style: &str) -> Value {
+        number_in(&locale.parse().unwrap(), &json!({ "value": value, "style": style }))["value"].clone()
+    }
+
+    #[test]
+    fn dates_match_cldr_for_thai_and_english() {
+        let golden = [
+            ("full_date", "วันพุธที่ 15 พฤศจิกายน พ.ศ. 2566", "Wednesday, November 15, 2023"),
+            ("long_date", "15 พฤศจิกายน 2566", "November 15, 2023"),
+            ("medium_date", "15 พ.ย. 2566", "Nov 15, 2023"),
+            ("short_date", "15/11/66", "11/15/23"),
+            ("medium_time", "14:05:09", "2:05:09\u{202f}PM"),
+            ("short_time", "14:05", "2:05\u{202f}PM"),
+            ("long_datetime", "15 พฤศจิกายน 2566 14:05", "November 15, 2023, 2:05\u{202f}PM"),
+            ("medium_datetime", "15 พ.ย. 2566 14:05", "Nov 15, 2023, 2:05\u{202f}PM"),
+            ("short_datetime", "15/11/66 14:05", "11/15/23, 2:05\u{202f}PM"),
+        ];
+        for (style, thai, english) in golden {
+            assert_eq!(datetime("th-TH", style, "Asia/Bangkok"), thai, "th-TH {}", style);
+            assert_eq!(datetime("en-US", style, "Asia/Bangkok"), english, "en-US {}", style);
+        }
+    }
+
+    #[test]
+    fn the_zone_decides_the_wall_clock() {
+        assert_eq!(datetime("en-US", "short_datetime", "America/New_York"), "11/15/23, 2:05\u{202f}AM");
+        assert_eq!(datetime("th-TH", "short_datetime", "UTC"), "15/11/66 07:05");
+        // Midnight UTC on New Year's Day is still the old year in New York
+        let new_year = json!({ "epoch_ms": 1_704_067_200_000_i64, "style": "short_date", "tz": "America/New_York" });
+        assert_eq!(datetime_in(&"th-TH".parse().unwrap(), &new_year)["value"], "31/12/66");
+    }
+
+    #[test]
+    fn numbers_match_cldr_for_thai_and_english() {
+        for locale in ["th-TH", "en-US"] {
+            assert_eq!(number(locale, 1_234_567.891, "decimal"), "1,234,567.891");
+            assert_eq!(number(locale, 0.12345, "decimal"), "0.123");
+            assert_eq!(number(locale, -1234.5, "decimal"), "-1,234.5");
+            assert_eq!(number(locale, 2.5, "integer"), "2");
+            assert_eq!(number(locale, 3.5, "integer"), "4");
+            assert_eq!(number(locale, 0.256, "percent"), "26%");
+        }
+    }
+
+    #[test]
+    fn bulk_formatting_keeps_places_for_bad_timestamps() {
+        let thai: Locale = "th-TH".parse().unwrap();
+        let message = json!({ "epoch_ms": [NOV_15, "soon", i64::MAX, NOV_15 + 60_000], "style": "short_time", "tz": "Asia/Bangkok" });
+        let result = datetimes_in(&thai, &message);
+        assert_eq!(result["values"], json!(["14:05", null, null, "14:06"]));
+        assert_eq!(result["locale"], "th-TH");
+
+        let too_many = json!({ "epoch_ms": vec![NOV_15; MAX_BULK + 1] });
+        assert_eq!(datetimes_in(&thai, &too_many)["success"], false);
+        assert_eq!(datetimes_in(&thai, &json!({ "epoch_ms": NOV_15 }))["error"], "epoch_ms must be an array");
+    }
+
+    #[test]
+    fn bad_requests_say_what_is_wrong() {
+        let english: Locale = "en-US".parse().unwrap();
+        let cases = [
+            (datetime_in(&english, &json!({ "epoch_ms": NOV_15, "style": "fancy" })), "unknown date style: fancy"),
+            (datetime_in(&english, &json!({ "epoch_ms": NOV_15, "tz": "Mars/Olympus" })), "unknown time zone: Mars/Olympus"),
+            (datetime_in(&english, &json!({ "epoch_ms": "yesterday" })), "epoch_ms must be an integer"),
+            (number_in(&english, &json!({ "value": "12" })), "value must be a number"),
+            (number_in(&english, &json!({ "value": 12, "style": "roman" })), "unknown number style: roman"),
+        ];
+        for (result, error) in cases {
+            assert_eq!(result, json!({ "success": false, "error": error }));
+        }
+    }
+}
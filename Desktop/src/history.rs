@@ -0,0 +1,360 @@
+//! Clearing a thread's history (`clear_thread_history {threadId}`, Tools →
+//! Clear Chat History).
+//!
+//! The ERP only deletes messages one at a time, so the thread is listed page
+//! by page from `/api/chats/{uuid}/messages` (as the export does) and every
+//! message is deleted with its own `DELETE /api/messages/{id}`, [`WORKERS`]
+//! at a time and retried while rate limited. Both go to the app's API base
+//! with the signed-in user's token, never to one the page names. Progress goes out as `history-clear-progress`, the outcome as
+//! `history-cleared`. A clear that stops with messages left (failures, a
+//! cancel, a lost connection) is remembered on disk and listed by
+//! `get_history_clears`; running it again picks up whatever is still there.
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tracing::{debug, info, warn};
+
+use crate::export::ExportError;
+
+/// Deletes in flight at once
+const WORKERS: usize = 4;
+/// Attempts per message when rate limited or on server errors
+const MAX_ATTEMPTS: u32 = 6;
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+/// Failed message ids kept per unfinished clear
+const MAX_FAILED_IDS: usize = 100;
+
+/// A clear that stopped with messages left
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UnfinishedClear {
+    pub thread_id: String,
+    pub deleted: u64,
+    pub failed: u64,
+    pub failed_ids: Vec<String>,
+    pub error: Option<String>,
+    pub stopped_at: chrono::DateTime<chrono::Utc>,
+}
+
+lazy_static! {
+    /// Cancellation flags of the clears in progress, keyed by thread id
+    static ref RUNNING: Mutex<HashMap<String, Arc<AtomicBool>>> = Mutex::new(HashMap::new());
+    static ref UNFINISHED: Mutex<BTreeMap<String, UnfinishedClear>> = Mutex::new(load());
+}
+
+fn unfinished_path() -> PathBuf {
+    crate::paths::data_dir().join("history_clears.json")
+}
+
+fn load() -> BTreeMap<String, UnfinishedClear> {
+    std::fs::read(unfinished_path())
+        .ok()
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .unwrap_or_default()
+}
+
+fn save(unfinished: &BTreeMap<String, UnfinishedClear>) {
+    let result = serde_json::to_vec_pretty(unfinished)
+        .map_err(|e| e.to_string())
+        .and_then(|bytes| crate::core::write_atomic(&unfinished_path(), &bytes).map_err(|e| e.to_string()));
+    if let Err(e) = result {
+        warn!("Failed to save unfinished history clears: {}", e);
+    }
+}
+
+/// `get_history_clears`: clears that stopped with messages left
+pub fn unfinished() -> Value {
+    json!({ "success": true, "clears": UNFINISHED.lock().unwrap().values().collect::<Vec<_>>() })
+}
+
+/// Ask a running clear of `thread_id` to stop; returns false if none is running
+pub fn cancel(thread_id: &str) -> bool {
+    match RUNNING.lock().unwrap().get(thread_id) {
+        Some(flag) => {
+            flag.store(true, Ordering::SeqCst);
+            true
+        }
+        None => false,
+    }
+}
+
+struct Outcome {
+    deleted: u64,
+    failed_ids: Vec<String>,
+    failed: u64,
+    /// Why it stopped before listing everything
+    error: Option<ExportError>,
+}
+
+/// Delete every message of `thread_id` with the signed-in user's session.
+/// Returns the `history-cleared` payload, which is also emitted.
+pub fn clear_thread(thread_id: &str) -> Value {
+    let cancelled = Arc::new(AtomicBool::new(false));
+    {
+        let mut running = RUNNING.lock().unwrap();
+        if running.contains_key(thread_id) {
+            return json!({ "success": false, "threadId": thread_id, "error": "this thread is already being cleared" });
+        }
+        running.insert(thread_id.to_string(), cancelled.clone());
+    }
+
+    // Never the page's API base: the token must only go to the app's own
+    let base_url = crate::protocol::api_base();
+    let token = crate::core::state::runtime().auth.token.clone();
+    info!("Clearing history of thread {}", thread_id);
+    let outcome = run(thread_id, &base_url, token.as_deref(), &cancelled);
+    RUNNING.lock().unwrap().remove(thread_id);
+
+    let finished = outcome.error.is_none() && outcome.failed == 0;
+    let error = outcome.error.as_ref().map(|e| e.to_string());
+    {
+        let mut unfinished = UNFINISHED.lock().unwrap();
+        let changed = if finished {
+            unfinished.remove(thread_id).is_some()
+        } else {
+            unfinished.insert(
+                thread_id.to_string(),
+                UnfinishedClear {
+                    thread_id: thread_id.to_string(),
+                    deleted: outcome.deleted,
+                    failed: outcome.failed,
+                    failed_ids: outcome.failed_ids.clone(),
+                    error: error.clone(),
+                    stopped_at: chrono::Utc::now(),
+                },
+            );
+            true
+        };
+        if changed {
+            save(&unfinished);
+        }
+    }
+
+    if finished {
+        info!("Cleared {} messages of thread {}", outcome.deleted, thread_id);
+    } else {
+        warn!(
+            "Clearing thread {} stopped: {} deleted, {} failed{}",
+            thread_id,
+            outcome.deleted,
+            outcome.failed,
+            error.as_deref().map(|e| format!(" ({})", e)).unwrap_or_default()
+        );
+    }
+    let result = json!({
+        "success": finished,
+        "threadId": thread_id,
+        "deleted": outcome.deleted,
+        "failed": outcome.failed,
+        "failedIds": outcome.failed_ids,
+        "cancelled": matches!(outcome.error, Some(ExportError::Cancelled)),
+        "error": error,
+        // Running it again continues with what is left
+        "resumable": !finished,
+    });
+    if let Some(webview) = crate::ipc::handle() {
+        webview.emit("history-cleared", &result);
+    }
+    result
+}
+
+fn run(thread_id: &str, base_url: &str, token: Option<&str>, cancelled: &AtomicBool) -> Outcome {
+    let mut outcome = Outcome { deleted: 0, failed_ids: Vec::new(), failed: 0, error: None };
+    let client = match reqwest::blocking::Client::builder().timeout(Duration::from_secs(60)).build() {
+        Ok(client) => client,
+        Err(e) => {
+            outcome.error = Some(e.into());
+            return outcome;
+        }
+    };
+
+    // Listed up front: deleting while paging would shift the cursor
+    let ids = match list_message_ids(&client, base_url, thread_id, token, cancelled) {
+        Ok(ids) => ids,
+        Err(e) => {
+            outcome.error = Some(e);
+            return outcome;
+        }
+    };
+    let total = ids.len() as u64;
+    let queue = Mutex::new(ids.into_iter().collect::<VecDeque<_>>());
+    let deleted = AtomicU64::new(0);
+    let failed = Mutex::new(Vec::new());
+    let webview = crate::ipc::handle();
+    let report = |deleted: u64, failed: u64| {
+        if let Some(webview) = &webview {
+            webview.emit_latest(
+                "history-clear-progress",
+                thread_id,
+                &json!({ "threadId": thread_id, "deleted": deleted, "failed": failed, "total": total }),
+            );
+        }
+    };
+
+    std::thread::scope(|scope| {
+        for _ in 0..WORKERS {
+            scope.spawn(|| loop {
+                if cancelled.load(Ordering::SeqCst) {
+                    return;
+                }
+                let Some(id) = queue.lock().unwrap().pop_front() else { return };
+                match delete_message(&client, base_url, &id, token, cancelled) {
+                    Ok(()) => {
+                        let done = deleted.fetch_add(1, Ordering::SeqCst) + 1;
+                        report(done, failed.lock().unwrap().len() as u64);
+                    }
+                    Err(ExportError::Cancelled) => return,
+                    Err(e) => {
+                        debug!("Failed to delete message {}: {}", id, e);
+                        let mut failed = failed.lock().unwrap();
+                        failed.push(id);
+                        report(deleted.load(Ordering::SeqCst), failed.len() as u64);
+                    }
+                }
+            });
+        }
+    });
+
+    let mut failed_ids = failed.into_inner().unwrap();
+    outcome.deleted = deleted.into_inner();
+    outcome.failed = failed_ids.len() as u64;
+    failed_ids.truncate(MAX_FAILED_IDS);
+    outcome.failed_ids = failed_ids;
+    if cancelled.load(Ordering::SeqCst) {
+        outcome.error = Some(ExportError::Cancelled);
+    }
+    outcome
+}
+
+fn list_message_ids(
+    client: &reqwest::blocking::Client,
+    base_url: &str,
+    thread_id: &str,
+    token: Option<&str>,
+    cancelled: &AtomicBool,
+) -> Result<Vec<String>, ExportError> {
+    let mut ids = Vec::new();
+    let mut cursor: Option<String> = None;
+    loop {
+        let page = crate::export::fetch_page(client, base_url, thread_id, token, cursor.as_deref(), cancelled)?;
+        let messages = page
+            .get("messages")
+            .or_else(|| page.get("data"))
+            .and_then(|m| m.as_array())
+            .ok_or_else(|| ExportError::Failed("unexpected response: no messages array".to_string()))?;
+        ids.extend(messages.iter().filter_map(|message| {
+            let id = message.get("messageId").or_else(|| message.get("id"))?;
+            id.as_str().map(|s| s.to_string()).or_else(|| id.as_u64().map(|n| n.to_string()))
+        }));
+
+        let next = page["nextCursor"].as_str().or_else(|| page["next_cursor"].as_str());
+        match next {
+            Some(next) if !messages.is_empty() && cursor.as_deref() != Some(next) => cursor = Some(next.to_string()),
+            _ => return Ok(ids),
+        }
+    }
+}
+
+fn delete_message(
+    client: &reqwest::blocking::Client,
+    base_url: &str,
+    id: &str,
+    token: Option<&str>,
+    cancelled: &AtomicBool,
+) -> Result<(), ExportError> {
+    let url = crate::export::api_url(base_url, &["api", "messages", id])?;
+    let mut backoff = Duration::from_secs(1);
+    for attempt in 1..=MAX_ATTEMPTS {
+        if cancelled.load(Ordering::SeqCst) {
+            return Err(ExportError::Cancelled);
+        }
+
+        let mut builder = client.delete(url.clone());
+        if let Some(token) = token {
+            builder = builder.bearer_auth(token);
+        }
+
+        let retry_after = match builder.send() {
+            // Already gone counts as deleted
+            Ok(response) if response.status().is_success() || response.status() == reqwest::StatusCode::NOT_FOUND => {
+                return Ok(())
+            }
+            Ok(response) if response.status() == reqwest::StatusCode::TOO_MANY_REQUESTS || response.status().is_server_error() => {
+                debug!("Delete of message {} got {} (attempt {})", id, response.status(), attempt);
+                response
+                    .headers()
+                    .get(reqwest::header::RETRY_AFTER)
+                    .and_then(|v| v.to_str().ok())
+                    .and_then(|v| v.trim().parse::<u64>().ok())
+                    .map(Duration::from_secs)
+            }
+            Ok(response) => {
+                let status = response.status();
+                let body = response.text().unwrap_or_default();
+                return Err(ExportError::Failed(format!("server returned {}: {}", status, body.trim())));
+            }
+            Err(e) if e.is_timeout() || e.is_connect() => {
+                debug!("Delete of message {} failed (attempt {}): {}", id, attempt, e);
+                None
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        let wait = retry_after.unwrap_or(backoff).min(MAX_BACKOFF);
+        crate::export::sleep_unless_cancelled(wait, cancelled)?;
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+    }
+
+    Err(ExportError::Failed(format!("giving up after {} attempts", MAX_ATTEMPTS)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lists_from_chats_and_deletes_each_message() {
+        let base = crate::testing::upstream();
+        let deleted = Arc::new(Mutex::new(Vec::new()));
+        let seen = deleted.clone();
+        crate::testing::route("/api/chats/history-1/messages", |request| {
+            let page = match request.path.contains("cursor=") {
+                false => json!({ "messages": [{ "messageId": "m1" }, { "id": 2 }], "nextCursor": "p2" }),
+                true => json!({ "data": [{ "messageId": "a/b?c" }] }),
+            };
+            crate::testing::Reply::json(200, page)
+        });
+        crate::testing::route("/api/messages/", move |request| {
+            seen.lock().unwrap().push(request.path.clone());
+            crate::testing::Reply::json(200, json!({ "success": true }))
+        });
+
+        let outcome = run("history-1", base, Some("token"), &AtomicBool::new(false));
+        assert!(outcome.error.is_none(), "{:?}", outcome.error);
+        assert_eq!((outcome.deleted, outcome.failed), (3, 0));
+        let mut deleted = deleted.lock().unwrap().clone();
+        deleted.sort();
+        assert_eq!(deleted, ["/api/messages/2", "/api/messages/a%2Fb%3Fc", "/api/messages/m1"]);
+    }
+
+    #[test]
+    fn clearing_empties_the_thread_on_the_mock_erp() {
+        let session = crate::testing::session(true);
+        let mock = session.use_mock_erp();
+        let chat = crate::testing::mock_erp::chat_uuid(4);
+        mock.add_messages(&chat, 40);
+
+        let result = clear_thread(&chat);
+        assert_eq!(result["success"], true, "{}", result);
+        assert_eq!(result["deleted"], 43);
+        assert!(mock.messages(&chat).is_empty());
+        // Nothing left, nothing to resume
+        assert_eq!(clear_thread(&chat)["deleted"], 0);
+    }
+}
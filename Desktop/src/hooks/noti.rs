@@ -6,6 +6,8 @@ use windows::UI::Notifications::{
 };
 #[cfg(target_os = "windows")]
 use windows::Data::Xml::Dom::XmlDocument;
+#[cfg(target_os = "windows")]
+use crate::notification_sounds::Sound;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct NotificationData {
@@ -13,23 +15,33 @@ pub struct NotificationData {
     pub message: String,
     pub icon: Option<String>,
     pub chat_uuid: Option<String>,
+    /// File to offer a "Show in folder" action for; only set natively, never from the page
+    #[serde(skip)]
+    pub reveal_path: Option<String>,
+    /// Replaces the toast shown earlier with this tag (see [`progress_tag`])
+    #[serde(skip)]
+    pub tag: Option<String>,
 }
 
+/// Group of the toasts tagged by [`progress_tag`]
+#[cfg(target_os = "windows")]
+const PROGRESS_GROUP: &str = "progress";
+
 /// Initialize notification system
 pub fn init_notifications() -> Result<(), Box<dyn std::error::Error>> {
     #[cfg(target_os = "windows")]
     {
-        println!("✅ Windows notification system initialized");
+        tracing::info!("Windows notification system initialized");
     }
     
     #[cfg(target_os = "macos")]
     {
-        println!("✅ macOS notification system initialized (osascript)");
+        tracing::info!("macOS notification system initialized (osascript)");
     }
     
     #[cfg(not(any(target_os = "windows", target_os = "macos")))]
     {
-        println!("⚠️ Notifications not yet implemented for this platform");
+        tracing::warn!("Notifications not yet implemented for this platform");
     }
     
     Ok(())
@@ -49,7 +61,7 @@ pub fn show_notification(data: NotificationData) -> Result<(), Box<dyn std::erro
     
     #[cfg(not(any(target_os = "windows", target_os = "macos")))]
     {
-        println!("📢 Notification (Fallback): {} - {}", data.title, data.message);
+        tracing::info!("Notification (Fallback): {} - {}", data.title, data.message);
         Ok(())
     }
 }
@@ -59,21 +71,50 @@ pub fn show_notification(data: NotificationData) -> Result<(), Box<dyn std::erro
 fn show_windows_notification(data: NotificationData) -> Result<(), Box<dyn std::error::Error>> {
     use windows::core::HSTRING;
     
-    println!("📢 Showing Windows notification: {} - {}", data.title, data.message);
+    tracing::info!("Showing Windows notification: {} - {}", data.title, data.message);
     
+    // Protocol activation opens the containing folder without needing a COM
+    // activator, from the button and from a click on the toast itself
+    let folder = data
+        .reveal_path
+        .as_deref()
+        .and_then(|path| std::path::Path::new(path).parent())
+        .and_then(|folder| url::Url::from_directory_path(folder).ok());
+    let (launch, actions) = match &folder {
+        Some(folder) => (
+            format!(r#" activationType="protocol" launch="{}""#, escape_xml(folder.as_str())),
+            format!(
+                r#"<actions><action content="Show in folder" activationType="protocol" arguments="{}"/></actions>"#,
+                escape_xml(folder.as_str())
+            ),
+        ),
+        None => (String::new(), String::new()),
+    };
+
+    // Windows plays its own sound; bundled ones are played alongside a silent toast
+    let sound = crate::notification_sounds::for_notification();
+    let audio = match sound {
+        Sound::System => r#"<audio src="ms-winsoundevent:Notification.Default"/>"#,
+        _ => r#"<audio silent="true"/>"#,
+    };
+
     // Create XML template for toast notification
     let xml_template = format!(
-        r#"<toast>
+        r#"<toast{}>
             <visual>
                 <binding template="ToastGeneric">
                     <text>{}</text>
                     <text>{}</text>
                 </binding>
             </visual>
-            <audio src="ms-winsoundevent:Notification.Default"/>
+            {}
+            {}
         </toast>"#,
+        launch,
         escape_xml(&data.title),
-        escape_xml(&data.message)
+        escape_xml(&data.message),
+        actions,
+        audio
     );
     
     // Create XML document
@@ -82,22 +123,95 @@ fn show_windows_notification(data: NotificationData) -> Result<(), Box<dyn std::
     
     // Create toast notification
     let toast = ToastNotification::CreateToastNotification(&xml_doc)?;
-    
-    // Get toast notifier
-    let app_id = HSTRING::from("MikoWorkspace");
-    let notifier = ToastNotificationManager::CreateToastNotifierWithId(&app_id)?;
+    if let Some(tag) = &data.tag {
+        toast.SetTag(&HSTRING::from(tag))?;
+        toast.SetGroup(&HSTRING::from(PROGRESS_GROUP))?;
+    }
+    // Straight to the Action Center while silenced
+    if crate::quiet_hours::silenced() {
+        toast.SetSuppressPopup(true)?;
+    }
     
     // Show the notification
-    notifier.Show(&toast)?;
+    toast_notifier()?.Show(&toast)?;
+    if let Sound::Bundled(_) = sound {
+        crate::notification_sounds::play(sound);
+    }
     
-    println!("✅ Windows notification shown successfully");
+    tracing::info!("Windows notification shown successfully");
+    Ok(())
+}
+
+#[cfg(target_os = "windows")]
+fn toast_notifier() -> windows::core::Result<windows::UI::Notifications::ToastNotifier> {
+    ToastNotificationManager::CreateToastNotifierWithId(&windows::core::HSTRING::from("MikoWorkspace"))
+}
+
+/// Tag for the single toast tracking one long-running job (a download).
+/// Older Windows 10 builds limit tags to 16 characters, hence the hash.
+pub fn progress_tag(key: &str) -> String {
+    use sha2::{Digest, Sha256};
+    Sha256::digest(key.as_bytes())[..8].iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Show a silent toast with a progress bar that [`update_progress_toast`]
+/// keeps current; a later [`show_notification`] with the same tag replaces it
+#[cfg(target_os = "windows")]
+pub fn show_progress_toast(tag: &str, title: &str, subject: &str) -> Result<(), Box<dyn std::error::Error>> {
+    use windows::core::HSTRING;
+    use windows::UI::Notifications::NotificationData as ToastData;
+
+    let xml_template = format!(
+        r#"<toast>
+            <visual>
+                <binding template="ToastGeneric">
+                    <text>{}</text>
+                    <progress title="{}" value="{{progressValue}}" valueStringOverride="{{progressLabel}}" status="{{progressStatus}}"/>
+                </binding>
+            </visual>
+            <audio silent="true"/>
+        </toast>"#,
+        escape_xml(title),
+        escape_xml(subject)
+    );
+    let xml_doc = XmlDocument::new()?;
+    xml_doc.LoadXml(&HSTRING::from(&xml_template))?;
+
+    let data = ToastData::new()?;
+    data.Values()?.Insert(&HSTRING::from("progressValue"), &HSTRING::from("0"))?;
+    data.Values()?.Insert(&HSTRING::from("progressLabel"), &HSTRING::from(""))?;
+    data.Values()?.Insert(&HSTRING::from("progressStatus"), &HSTRING::from(""))?;
+    data.SetSequenceNumber(0)?;
+
+    let toast = ToastNotification::CreateToastNotification(&xml_doc)?;
+    toast.SetTag(&HSTRING::from(tag))?;
+    toast.SetGroup(&HSTRING::from(PROGRESS_GROUP))?;
+    toast.SetData(&data)?;
+    toast_notifier()?.Show(&toast)?;
+    Ok(())
+}
+
+/// Move the progress bar of the toast tagged `tag`. `sequence` must grow with
+/// every update so Windows drops updates arriving out of order.
+#[cfg(target_os = "windows")]
+pub fn update_progress_toast(tag: &str, fraction: f64, status: &str, sequence: u32) -> Result<(), Box<dyn std::error::Error>> {
+    use windows::core::HSTRING;
+    use windows::UI::Notifications::NotificationData as ToastData;
+
+    let fraction = fraction.clamp(0.0, 1.0);
+    let data = ToastData::new()?;
+    data.Values()?.Insert(&HSTRING::from("progressValue"), &HSTRING::from(format!("{:.3}", fraction)))?;
+    data.Values()?.Insert(&HSTRING::from("progressLabel"), &HSTRING::from(format!("{:.0}%", fraction * 100.0)))?;
+    data.Values()?.Insert(&HSTRING::from("progressStatus"), &HSTRING::from(status))?;
+    data.SetSequenceNumber(sequence)?;
+    toast_notifier()?.UpdateWithTagAndGroup(&data, &HSTRING::from(tag), &HSTRING::from(PROGRESS_GROUP))?;
     Ok(())
 }
 
 /// Show a macOS notification using osascript
 #[cfg(target_os = "macos")]
 fn show_macos_notification(data: NotificationData) -> Result<(), Box<dyn std::error::Error>> {
-    println!("📢 Showing macOS notification: {} - {}", data.title, data.message);
+    tracing::info!("Showing macOS notification: {} - {}", data.title, data.message);
     
     // Use macOS osascript to show notification
     let script = format!(
@@ -105,17 +219,19 @@ fn show_macos_notification(data: NotificationData) -> Result<(), Box<dyn std::er
         data.message.replace("\"", "\\\""),
         data.title.replace("\"", "\\\"")
     );
+    // Played natively, so bundled and system sounds behave the same
+    crate::notification_sounds::play(crate::notification_sounds::for_notification());
     
     let mut command = std::process::Command::new("osascript");
     command.arg("-e").arg(&script);
     
     match command.spawn() {
         Ok(_) => {
-            println!("✅ macOS notification sent");
+            tracing::info!("macOS notification sent");
             Ok(())
         }
         Err(e) => {
-            println!("❌ Failed to send macOS notification: {}", e);
+            tracing::error!("Failed to send macOS notification: {}", e);
             Err(e.into())
         }
     }
@@ -145,5 +261,7 @@ pub fn show_simple_notification(title: &str, message: &str) -> Result<(), Box<dy
         message: message.to_string(),
         icon: None,
         chat_uuid: None,
+        reveal_path: None,
+        tag: None,
     })
 }
@@ -0,0 +1,257 @@
+//! The embedded application icon, decoded once per requested size.
+//!
+//! Windows ships `icon.ico` and macOS `icon.icns`. ICO entries are decoded by
+//! the `ico` crate; ICNS is a flat list of `(type, length, data)` chunks whose
+//! modern entries (`ic07`..`ic14`) are plain PNG files, which is all we read.
+
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::sync::{Arc, Mutex};
+use lazy_static::lazy_static;
+use tracing::{debug, warn};
+
+#[cfg(target_os = "macos")]
+const APP_ICON_BYTES: &[u8] = include_bytes!("../../Library/Shared/Icons/icon.icns");
+
+#[cfg(not(target_os = "macos"))]
+const APP_ICON_BYTES: &[u8] = include_bytes!("../../Library/Shared/Icons/icon.ico");
+
+/// Window icons are never shown larger than this
+pub const WINDOW_ICON_SIZE: u32 = 256;
+/// Menu bar / notification area icons
+pub const TRAY_ICON_SIZE: u32 = 64;
+
+pub struct RgbaIcon {
+    pub rgba: Vec<u8>,
+    pub width: u32,
+    pub height: u32,
+}
+
+lazy_static! {
+    static ref CACHE: Mutex<HashMap<u32, Arc<RgbaIcon>>> = Mutex::new(HashMap::new());
+}
+
+/// The app icon entry best suited to `max_size` pixels, decoded to RGBA.
+/// Falls back to a generated placeholder when the embedded asset can't be read.
+pub fn load_app_icon(max_size: u32) -> Arc<RgbaIcon> {
+    if let Some(icon) = CACHE.lock().unwrap().get(&max_size) {
+        return icon.clone();
+    }
+
+    let icon = Arc::new(decode(APP_ICON_BYTES, max_size).unwrap_or_else(|e| {
+        warn!("Failed to decode embedded app icon: {}", e);
+        fallback_icon(32)
+    }));
+    CACHE.lock().unwrap().insert(max_size, icon.clone());
+    icon
+}
+
+/// Decode the window and tray sizes ahead of time (startup runs this on a worker)
+pub fn preload() {
+    load_app_icon(WINDOW_ICON_SIZE);
+    load_app_icon(TRAY_ICON_SIZE);
+}
+
+fn decode(bytes: &[u8], max_size: u32) -> Result<RgbaIcon, Box<dyn std::error::Error>> {
+    let icon = if bytes.starts_with(b"icns") {
+        decode_icns(bytes, max_size)?
+    } else {
+        decode_ico(bytes, max_size)?
+    };
+    debug!("App icon decoded: {}x{} (requested <= {})", icon.width, icon.height, max_size);
+    Ok(icon)
+}
+
+/// Largest size that fits in `max_size`, otherwise the smallest one available
+fn pick_size<T>(candidates: Vec<(u32, T)>, max_size: u32) -> Option<T> {
+    let (fitting, larger): (Vec<_>, Vec<_>) = candidates.into_iter().partition(|(size, _)| *size <= max_size);
+    fitting
+        .into_iter()
+        .max_by_key(|(size, _)| *size)
+        .or_else(|| larger.into_iter().min_by_key(|(size, _)| *size))
+        .map(|(_, item)| item)
+}
+
+fn decode_ico(bytes: &[u8], max_size: u32) -> Result<RgbaIcon, Box<dyn std::error::Error>> {
+    let icon_dir = ico::IconDir::read(Cursor::new(bytes))?;
+    let candidates = icon_dir
+        .entries()
+        .iter()
+        .map(|entry| (entry.width().max(entry.height()), entry))
+        .collect();
+    let entry = pick_size(candidates, max_size).ok_or("ICO file has no entries")?;
+    let image = entry.decode()?;
+    Ok(RgbaIcon {
+        rgba: image.rgba_data().to_vec(),
+        width: image.width(),
+        height: image.height(),
+    })
+}
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+fn decode_icns(bytes: &[u8], max_size: u32) -> Result<RgbaIcon, Box<dyn std::error::Error>> {
+    let read_u32 = |at: usize| -> Option<u32> {
+        bytes.get(at..at + 4).map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    };
+
+    let total = (read_u32(4).ok_or("truncated ICNS header")? as usize).min(bytes.len());
+    let mut candidates = Vec::new();
+    let mut offset = 8;
+    while offset + 8 <= total {
+        let length = read_u32(offset + 4).ok_or("truncated ICNS entry")? as usize;
+        if length < 8 || offset + length > total {
+            return Err("corrupt ICNS entry length".into());
+        }
+        let data = &bytes[offset + 8..offset + length];
+        // The IHDR chunk starts right after the signature; width is its first field
+        if data.starts_with(PNG_SIGNATURE) && data.len() >= 24 {
+            let width = u32::from_be_bytes([data[16], data[17], data[18], data[19]]);
+            candidates.push((width, data));
+        }
+        offset += length;
+    }
+
+    let png = pick_size(candidates, max_size).ok_or("ICNS file has no PNG entries")?;
+    decode_png(png)
+}
+
+fn decode_png(data: &[u8]) -> Result<RgbaIcon, Box<dyn std::error::Error>> {
+    let mut decoder = png::Decoder::new(Cursor::new(data));
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info()?;
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let frame = reader.next_frame(&mut buf)?;
+    buf.truncate(frame.buffer_size());
+
+    let rgba = match frame.color_type {
+        png::ColorType::Rgba => buf,
+        png::ColorType::Rgb => buf.chunks_exact(3).flat_map(|p| [p[0], p[1], p[2], 255]).collect(),
+        png::ColorType::GrayscaleAlpha => buf.chunks_exact(2).flat_map(|p| [p[0], p[0], p[0], p[1]]).collect(),
+        png::ColorType::Grayscale => buf.iter().flat_map(|&g| [g, g, g, 255]).collect(),
+        png::ColorType::Indexed => return Err("unexpanded indexed PNG".into()),
+    };
+
+    Ok(RgbaIcon { rgba, width: frame.width, height: frame.height })
+}
+
+/// Greyed-out, semi-transparent copy used for the tray while disconnected
+pub fn dimmed(icon: &RgbaIcon) -> RgbaIcon {
+    let rgba = icon
+        .rgba
+        .chunks_exact(4)
+        .flat_map(|p| {
+            let grey = ((p[0] as u32 * 30 + p[1] as u32 * 59 + p[2] as u32 * 11) / 100) as u8;
+            [grey, grey, grey, p[3] / 2]
+        })
+        .collect();
+    RgbaIcon { rgba, width: icon.width, height: icon.height }
+}
+
+/// Simple gradient used when the embedded icon can't be decoded
+pub fn fallback_icon(size: u32) -> RgbaIcon {
+    let mut rgba = Vec::with_capacity((size * size * 4) as usize);
+    for y in 0..size {
+        for x in 0..size {
+            let r = ((x as f32 / size as f32) * 255.0) as u8;
+            let g = ((y as f32 / size as f32) * 255.0) as u8;
+            rgba.extend_from_slice(&[r, g, 128, 255]);
+        }
+    }
+    RgbaIcon { rgba, width: size, height: size }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ICO: &[u8] = include_bytes!("../../Library/Shared/Icons/icon.ico");
+    const ICNS: &[u8] = include_bytes!("../../Library/Shared/Icons/icon.icns");
+
+    fn size(icon: &RgbaIcon) -> u32 {
+        assert_eq!(icon.width, icon.height);
+        assert_eq!(icon.rgba.len(), (icon.width * icon.height * 4) as usize);
+        icon.width
+    }
+
+    fn error(result: Result<RgbaIcon, Box<dyn std::error::Error>>) -> String {
+        result.err().expect("decoded").to_string()
+    }
+
+    /// An ICNS file holding `entries` as `(type, data)` chunks
+    fn icns(entries: &[(&[u8; 4], &[u8])]) -> Vec<u8> {
+        let mut body = Vec::new();
+        for (kind, data) in entries {
+            body.extend_from_slice(*kind);
+            body.extend_from_slice(&(data.len() as u32 + 8).to_be_bytes());
+            body.extend_from_slice(data);
+        }
+        let mut file = b"icns".to_vec();
+        file.extend_from_slice(&(body.len() as u32 + 8).to_be_bytes());
+        file.extend(body);
+        file
+    }
+
+    #[test]
+    fn embedded_ico_decodes() {
+        assert_eq!(size(&decode(ICO, WINDOW_ICON_SIZE).unwrap()), 256);
+        assert_eq!(size(&decode(ICO, TRAY_ICON_SIZE).unwrap()), 64);
+        assert_eq!(size(&decode_ico(ICO, 100).unwrap()), 64);
+        assert_eq!(size(&decode_ico(ICO, 8).unwrap()), 16);
+    }
+
+    #[test]
+    fn embedded_icns_decodes() {
+        assert_eq!(size(&decode(ICNS, WINDOW_ICON_SIZE).unwrap()), 256);
+        assert_eq!(size(&decode(ICNS, TRAY_ICON_SIZE).unwrap()), 64);
+        assert_eq!(size(&decode_icns(ICNS, 100).unwrap()), 64);
+        // Smaller than any PNG entry, larger than all of them
+        assert_eq!(size(&decode_icns(ICNS, 16).unwrap()), 32);
+        assert_eq!(size(&decode_icns(ICNS, 4096).unwrap()), 1024);
+    }
+
+    #[test]
+    fn picks_the_largest_fitting_size() {
+        let candidates = || vec![(32, "32"), (256, "256"), (64, "64")];
+        assert_eq!(pick_size(candidates(), 64), Some("64"));
+        assert_eq!(pick_size(candidates(), 255), Some("64"));
+        assert_eq!(pick_size(candidates(), 16), Some("32"));
+        assert_eq!(pick_size(Vec::<(u32, ())>::new(), 64), None);
+    }
+
+    #[test]
+    fn truncated_icns_is_refused() {
+        assert_eq!(error(decode_icns(&ICNS[..6], 64)), "truncated ICNS header");
+        // The header promises more than there is: the first entry runs past the end
+        assert_eq!(error(decode_icns(&ICNS[..100], 64)), "corrupt ICNS entry length");
+        // Cut inside the first entry's header
+        assert_eq!(error(decode_icns(&ICNS[..12], 64)), "ICNS file has no PNG entries");
+        assert_eq!(error(decode_icns(b"icns", 64)), "truncated ICNS header");
+    }
+
+    #[test]
+    fn bad_icns_lengths_are_refused() {
+        let png_prefix = &ICNS[16..16 + 24];
+        for length in [0u32, 7, 0x7fff_ffff] {
+            let mut file = icns(&[(b"ic07", png_prefix)]);
+            file[12..16].copy_from_slice(&length.to_be_bytes());
+            assert_eq!(error(decode_icns(&file, 64)), "corrupt ICNS entry length", "length {}", length);
+        }
+        // A file length past the end is clamped to what is there
+        let mut file = icns(&[(b"info", b"plist")]);
+        file[4..8].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert_eq!(error(decode_icns(&file, 64)), "ICNS file has no PNG entries");
+    }
+
+    #[test]
+    fn corrupt_entries_fail_without_panicking() {
+        // A PNG signature and IHDR start followed by garbage
+        let mut broken = ICNS[16..16 + 24].to_vec();
+        broken.extend_from_slice(&[0xff; 64]);
+        assert!(decode_icns(&icns(&[(b"ic07", &broken)]), 64).is_err());
+        // Only legacy (non-PNG) entries
+        assert_eq!(error(decode_icns(&icns(&[(b"ic04", &[0; 32])]), 64)), "ICNS file has no PNG entries");
+        assert!(decode_ico(b"\0\0\x01\0\x01\0garbage", 64).is_err());
+        assert!(decode(b"", 64).is_err());
+    }
+}
@@ -0,0 +1,114 @@
+//! Requests the `miko://` protocol is currently forwarding, so a runaway one
+//! (a huge export nobody meant to start) can be found and stopped.
+//!
+//! Listed by `GET miko://app/admin/inflight` and the `get_inflight_requests`
+//! IPC; `POST /admin/inflight/{id}/cancel` and `cancel_inflight_request {id}`
+//! stop one. The request runs under the [`AbortRegistration`] [`track`] hands
+//! out, so a cancel drops it at once, whether it is still connecting, waiting
+//! for the server's headers or halfway through the body, and the connection
+//! is closed. The page then gets the `cancelled` envelope with status 499
+//! instead of a network error.
+
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use futures_util::future::{AbortHandle, AbortRegistration};
+use lazy_static::lazy_static;
+use serde::Serialize;
+use serde_json::{json, Value};
+use tracing::info;
+
+pub const ROUTE: &str = "/admin/inflight";
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InFlight {
+    pub id: u64,
+    pub method: String,
+    /// Path and query, with secrets in the query redacted
+    pub path: String,
+    /// The `x-request-id` sent upstream
+    pub request_id: String,
+    /// Signed-in user the request was sent for
+    pub user_id: Option<String>,
+    pub started_at: chrono::DateTime<chrono::Utc>,
+    /// Response body received so far
+    pub bytes: AtomicU64,
+    #[serde(skip)]
+    abort: AbortHandle,
+}
+
+impl InFlight {
+    pub fn add_bytes(&self, count: usize) {
+        self.bytes.fetch_add(count as u64, Ordering::Relaxed);
+    }
+}
+
+/// Keeps a request listed until dropped
+pub struct Tracked(Arc<InFlight>);
+
+impl std::ops::Deref for Tracked {
+    type Target = InFlight;
+
+    fn deref(&self) -> &InFlight {
+        &self.0
+    }
+}
+
+impl Drop for Tracked {
+    fn drop(&mut self) {
+        REQUESTS.lock().unwrap().remove(&self.0.id);
+    }
+}
+
+lazy_static! {
+    static ref REQUESTS: Mutex<BTreeMap<u64, Arc<InFlight>>> = Mutex::new(BTreeMap::new());
+}
+
+/// List a request about to be forwarded; cancelling it aborts the future run
+/// under the returned registration
+pub fn track(method: &str, path: &str, request_id: &str) -> (Tracked, AbortRegistration) {
+    static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+    let (abort, registration) = AbortHandle::new_pair();
+    let request = Arc::new(InFlight {
+        id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+        method: method.to_string(),
+        path: crate::logging::redact_url(path),
+        request_id: request_id.to_string(),
+        user_id: crate::core::state::runtime().auth.user_id.clone(),
+        started_at: chrono::Utc::now(),
+        bytes: AtomicU64::new(0),
+        abort,
+    });
+    REQUESTS.lock().unwrap().insert(request.id, request.clone());
+    (Tracked(request), registration)
+}
+
+/// `get_inflight_requests` / `GET /admin/inflight`: oldest first
+pub fn list() -> Value {
+    let requests = REQUESTS.lock().unwrap();
+    json!({ "success": true, "requests": requests.values().map(|r| &**r).collect::<Vec<_>>() })
+}
+
+/// Requests being forwarded right now
+pub fn count() -> usize {
+    REQUESTS.lock().unwrap().len()
+}
+
+/// `cancel_inflight_request {id}` / `POST /admin/inflight/{id}/cancel`
+pub fn cancel(id: u64) -> Value {
+    match REQUESTS.lock().unwrap().get(&id) {
+        Some(request) => {
+            request.abort.abort();
+            info!("Cancelled forwarded request {} {} [{}]", request.method, request.path, request.request_id);
+            json!({ "success": true, "id": id })
+        }
+        None => json!({ "success": false, "id": id, "error": "no such request in flight" }),
+    }
+}
+
+/// The request id of a `/admin/inflight/{id}/cancel` path
+pub fn cancel_route(path: &str) -> Option<u64> {
+    path.strip_prefix(ROUTE)?.strip_prefix('/')?.strip_suffix("/cancel")?.parse().ok()
+}
@@ -0,0 +1,985 @@
+//! Delivery of native data into the webview.
+//!
+//! Payloads are always embedded as JSON object literals produced by
+//! [`js_literal`], never spliced into hand-escaped JS strings. Messages from
+//! the page go through [`parse_message`] before any handler sees them; it
+//! also refuses messages from pages other than the app's own (see
+//! [`allow_origin`]), and [`SENSITIVE_ACTIONS`] from anything but the app.
+//!
+//! IPC handlers and other background threads have no access to the webview,
+//! so they go through a [`WebviewHandle`]; the platform event loop evaluates
+//! what was queued. Deliveries are at least [`FLUSH_INTERVAL`] apart and batch
+//! everything queued, so a flood of events (a busy thread, bot integrations)
+//! can't saturate the UI thread. Progress-style events and state
+//! topics are merged while queued, latest wins; plain events are never merged.
+//!
+//! No single `evaluate_script` is larger than [`MAX_SCRIPT_BYTES`]: a delivery
+//! is split into several, and a payload too large on its own is sent in
+//! pieces that the page joins and parses before dispatching it.
+//!
+//! A share of requests (`ipc_timing_sample_rate`) is timed: the result then
+//! carries `_timings {type, sentAt?, receivedAt, upstreamSent?,
+//! upstreamReceived?, respondedAt}` in epoch milliseconds, which the page keeps as
+//! `window.__miko.lastTimings` and shows as `performance.measure`s. The
+//! upstream span is the [`Upstream`] a handler that asked the API passes to
+//! [`respond_after`].
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use rand::Rng;
+use lazy_static::lazy_static;
+use serde::Serialize;
+use serde_json::Value;
+use tracing::warn;
+
+/// Serialize `value` into a JS expression that is safe to splice into a script.
+///
+/// JSON is valid JS except that U+2028/U+2029 used to be line terminators, and
+/// `<` is escaped too so a `</script>` inside a string can never end a script
+/// block. All three can only occur inside JSON strings, where `\uXXXX` is
+/// equivalent.
+pub fn js_literal(value: &impl Serialize) -> String {
+    let json = serde_json::to_string(value).unwrap_or_else(|_| "null".to_string());
+    let mut out = String::with_capacity(json.len());
+    for c in json.chars() {
+        match c {
+            '<' => out.push_str("\\u003c"),
+            '\u{2028}' => out.push_str("\\u2028"),
+            '\u{2029}' => out.push_str("\\u2029"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// Script dispatching `event_name` on `window` with `payload` as `detail`
+pub fn emit_script(event_name: &str, payload: &impl Serialize) -> String {
+    format!(
+        "window.dispatchEvent(new CustomEvent({}, {{ detail: {} }}));",
+        js_literal(&event_name),
+        js_literal(payload)
+    )
+}
+
+/// Dispatch a `CustomEvent` named `event_name` carrying `payload` in the page
+pub fn webview_emit(webview: &wry::WebView, event_name: &str, payload: &impl Serialize) -> Result<(), String> {
+    webview.evaluate_script(&emit_script(event_name, payload)).map_err(|e| e.to_string())
+}
+
+/// Most items the webview queue holds before the oldest ones are dropped
+const MAX_QUEUED: usize = 1024;
+/// Longest script passed to one `evaluate_script`
+const MAX_SCRIPT_BYTES: usize = 1024 * 1024;
+/// JSON text per piece of a chunked payload; escaping can grow it up to 6x
+const CHUNK_BYTES: usize = MAX_SCRIPT_BYTES / 8;
+
+/// Minimum gap between two deliveries to the page
+pub const FLUSH_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Something to run in the page once the event loop picks it up
+pub enum EmitEvent {
+    /// Dispatch a `CustomEvent` with `payload` as `detail`
+    Event { name: String, payload: Value },
+    /// Like `Event`, but replaces a still-queued one with the same `name` and `key`
+    Latest { name: String, key: String, payload: Value },
+    /// Push the changes of a state topic; computed at delivery, so however
+    /// often the topic changed in between only its latest state goes out
+    State(String),
+    /// Set `window.ipcResult_<request_id>` to `value`
+    Result { request_id: String, value: Value },
+    /// Evaluate raw JavaScript
+    Script(String),
+}
+
+impl EmitEvent {
+    /// Whether `self`, queued earlier, is superseded by `newer`
+    fn superseded_by(&self, newer: &EmitEvent) -> bool {
+        match (self, newer) {
+            (EmitEvent::Latest { name, key, .. }, EmitEvent::Latest { name: newer_name, key: newer_key, .. }) => {
+                name == newer_name && key == newer_key
+            }
+            (EmitEvent::State(topic), EmitEvent::State(newer_topic)) => topic == newer_topic,
+            _ => false,
+        }
+    }
+
+    /// The scripts delivering this item, in order; more than one when its
+    /// payload has to be chunked
+    fn into_scripts(self) -> Vec<String> {
+        match self {
+            EmitEvent::Event { name, payload } | EmitEvent::Latest { name, payload, .. } => with_payload(&payload, |detail| {
+                format!("window.dispatchEvent(new CustomEvent({}, {{ detail: {} }}));", js_literal(&name), detail)
+            }),
+            EmitEvent::State(topic) => match crate::core::sync::patch(&topic) {
+                Some((payload, full)) => {
+                    with_payload(&payload, |patch| crate::core::sync::receive_script(&topic, patch, full))
+                }
+                None => Vec::new(),
+            },
+            EmitEvent::Result { request_id, value } if value.get("_timings").is_some() => with_payload(&value, |value| {
+                format!("{{ const result = {}; window.__miko && window.__miko.__timed && window.__miko.__timed({}, result); window['ipcResult_{}'] = result; }}", value, js_literal(&request_id), request_id)
+            }),
+            EmitEvent::Result { request_id, value } => {
+                with_payload(&value, |value| format!("window['ipcResult_{}'] = {};", request_id, value))
+            }
+            EmitEvent::Script(script) if script.len() > MAX_SCRIPT_BYTES => {
+                warn!("Dropped a {} byte script over the {} byte limit", script.len(), MAX_SCRIPT_BYTES);
+                Vec::new()
+            }
+            EmitEvent::Script(script) => vec![script],
+        }
+    }
+}
+
+/// Script(s) running `finish` with a JS expression evaluating to `payload`.
+/// Small payloads are inlined; larger ones are appended to a page-side buffer
+/// one piece per script and parsed by the last one.
+fn with_payload(payload: &Value, finish: impl Fn(&str) -> String) -> Vec<String> {
+    static NEXT_CHUNKED: AtomicU64 = AtomicU64::new(0);
+
+    let literal = js_literal(payload);
+    if literal.len() <= MAX_SCRIPT_BYTES - 1024 {
+        return vec![finish(&literal)];
+    }
+
+    let json = serde_json::to_string(payload).unwrap_or_else(|_| "null".to_string());
+    let slot = format!("window.__mikoChunks[{}]", NEXT_CHUNKED.fetch_add(1, Ordering::Relaxed));
+    let mut scripts = Vec::new();
+    let mut rest = json.as_str();
+    while !rest.is_empty() {
+        let mut cut = rest.len().min(CHUNK_BYTES);
+        while !rest.is_char_boundary(cut) {
+            cut -= 1;
+        }
+        let (piece, tail) = rest.split_at(cut);
+        scripts.push(format!(
+            "window.__mikoChunks = window.__mikoChunks || {{}}; {slot} = ({slot} || '') + {};",
+            js_literal(&piece),
+        ));
+        rest = tail;
+    }
+    scripts.push(format!(
+        "{{ const payload = JSON.parse({slot}); delete {slot}; {} }}",
+        finish("payload")
+    ));
+    scripts
+}
+
+#[derive(Default)]
+struct Queue {
+    items: VecDeque<EmitEvent>,
+    dropped: usize,
+    /// A wake-up is on its way; later items ride along
+    wake_pending: bool,
+    last_flush: Option<Instant>,
+}
+
+/// Cloneable, thread-safe way to reach the webview from background threads.
+///
+/// Items are queued and the event loop is woken; the actual
+/// `evaluate_script` happens on the main thread in `user_event()`. The queue is
+/// bounded: when a stuck webview lets it fill up, the oldest items are dropped.
+#[derive(Clone)]
+pub struct WebviewHandle {
+    queue: Arc<Mutex<Queue>>,
+    waker: Arc<dyn Fn() + Send + Sync>,
+}
+
+impl WebviewHandle {
+    fn push(&self, item: EmitEvent) {
+        let wake_in = {
+            let mut queue = self.queue.lock().unwrap();
+            if let Some(slot) = queue.items.iter_mut().find(|queued| queued.superseded_by(&item)) {
+                *slot = item;
+                return;
+            }
+            if queue.items.len() >= MAX_QUEUED {
+                queue.items.pop_front();
+                if queue.dropped == 0 {
+                    warn!("Webview event queue full, dropping oldest events");
+                }
+                queue.dropped += 1;
+            }
+            queue.items.push_back(item);
+
+            if queue.wake_pending {
+                return;
+            }
+            queue.wake_pending = true;
+            queue.last_flush.map_or(Duration::ZERO, |at| FLUSH_INTERVAL.saturating_sub(at.elapsed()))
+        };
+
+        if wake_in.is_zero() {
+            (self.waker)();
+        } else {
+            let waker = self.waker.clone();
+            std::thread::spawn(move || {
+                std::thread::sleep(wake_in);
+                waker();
+            });
+        }
+    }
+
+    pub fn emit(&self, name: &str, payload: &impl Serialize) {
+        let payload = serde_json::to_value(payload).unwrap_or(Value::Null);
+        self.push(EmitEvent::Event { name: name.to_string(), payload });
+    }
+
+    /// Emit an event where only the newest one per `key` matters (progress,
+    /// status); one still waiting for delivery is replaced
+    pub fn emit_latest(&self, name: &str, key: &str, payload: &impl Serialize) {
+        let payload = serde_json::to_value(payload).unwrap_or(Value::Null);
+        self.push(EmitEvent::Latest { name: name.to_string(), key: key.to_string(), payload });
+    }
+
+    pub fn eval(&self, script: String) {
+        self.push(EmitEvent::Script(script));
+    }
+
+    /// Queue a push of state `topic`'s changes
+    pub fn state(&self, topic: &str) {
+        self.push(EmitEvent::State(topic.to_string()));
+    }
+
+    /// Take everything queued so far; called by the event loop
+    pub fn drain(&self) -> Vec<EmitEvent> {
+        let mut queue = self.queue.lock().unwrap();
+        if queue.dropped > 0 {
+            warn!("Dropped {} webview events while the queue was full", queue.dropped);
+            queue.dropped = 0;
+        }
+        queue.wake_pending = false;
+        queue.last_flush = Some(Instant::now());
+        queue.items.drain(..).collect()
+    }
+
+    /// Run the drained items against the real webview (main thread only),
+    /// batched into as few `evaluate_script` calls as [`MAX_SCRIPT_BYTES`]
+    /// allows. Each item is guarded so one that throws doesn't stop the rest.
+    pub fn deliver(&self, webview: &wry::WebView) {
+        for (batch, count) in batches(self.drain()) {
+            if let Err(e) = webview.evaluate_script(&batch) {
+                warn!("Failed to deliver {} webview script(s): {}", count, e);
+            }
+        }
+    }
+}
+
+/// The scripts delivering `items`, joined into as few batches of at most
+/// [`MAX_SCRIPT_BYTES`] as possible, with how many scripts each holds
+fn batches(items: Vec<EmitEvent>) -> Vec<(String, usize)> {
+    let scripts = items
+        .into_iter()
+        .flat_map(EmitEvent::into_scripts)
+        .map(|script| format!("try {{ {} }} catch (e) {{ console.error(e); }}", script));
+
+    let mut batches = Vec::new();
+    let mut batch = String::new();
+    let mut count = 0;
+    for script in scripts {
+        if !batch.is_empty() && batch.len() + script.len() > MAX_SCRIPT_BYTES {
+            batches.push((std::mem::take(&mut batch), count));
+            count = 0;
+        }
+        batch.push_str(&script);
+        batch.push('\n');
+        count += 1;
+    }
+    if count > 0 {
+        batches.push((batch, count));
+    }
+    batches
+}
+
+lazy_static! {
+    static ref HANDLE: Mutex<Option<WebviewHandle>> = Mutex::new(None);
+    /// `max_ipc_message_kb`, read once at startup
+    static ref MAX_MESSAGE_BYTES: usize = crate::core::settings::get().max_ipc_message_kb as usize * 1024;
+    /// Origins accepted as the app's own besides [`APP_ORIGIN`] (see [`allow_origin`])
+    static ref APP_ORIGINS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+    /// Sampled requests still waiting for their answer -> when they arrived
+    static ref RECEIVED_AT: Mutex<HashMap<String, Value>> = Mutex::new(HashMap::new());
+}
+
+/// Create the webview handle; `waker` must make the event loop call
+/// [`WebviewHandle::deliver`]
+pub fn install(waker: impl Fn() + Send + Sync + 'static) -> WebviewHandle {
+    let handle = WebviewHandle {
+        queue: Arc::new(Mutex::new(Queue::default())),
+        waker: Arc::new(waker),
+    };
+    *HANDLE.lock().unwrap() = Some(handle.clone());
+    handle
+}
+
+/// The installed handle, for background work to take at spawn time
+pub fn handle() -> Option<WebviewHandle> {
+    HANDLE.lock().unwrap().clone()
+}
+
+/// Ask the event loop to apply pending native-side changes
+pub fn wake() {
+    if let Some(handle) = handle() {
+        (handle.waker)();
+    }
+}
+
+/// Longest message `type`
+const MAX_TYPE_CHARS: usize = 64;
+/// Longest `requestId`, `threadId`, `id` or `topic`
+const MAX_ID_CHARS: usize = 128;
+
+fn is_message_type(name: &str) -> bool {
+    !name.is_empty() && name.len() <= MAX_TYPE_CHARS && name.chars().all(|c| c.is_ascii_lowercase() || c == '_')
+}
+
+/// `requestId`s end up in a property name of `window`, so they are restricted
+fn is_request_id(id: &str) -> bool {
+    !id.is_empty() && id.len() <= MAX_ID_CHARS && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Thread ids end up in API paths and file names, so they are restricted to
+/// what the ERP hands out (uuids, numbers)
+fn is_thread_id(id: &str) -> bool {
+    !id.is_empty() && id.len() <= MAX_ID_CHARS && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Why a message from the page was rejected, as `code` of the `ipc-error` event
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RejectCode {
+    /// Over `max_ipc_message_kb`; the body is not parsed at all
+    PayloadTooLarge,
+    InvalidJson,
+    /// `type`, `requestId` or an identifier field failed validation
+    InvalidField,
+    /// Posted by a page the action isn't allowed from
+    ForbiddenOrigin,
+}
+
+/// Where the release build loads the app from
+const APP_ORIGIN: &str = "miko://app";
+/// The same on Windows, where WebView2 serves custom protocols over http(s)
+const APP_ORIGINS_MAPPED: &[&str] = &["http://miko.app", "https://miko.app"];
+
+/// Actions only the app itself may post: they open file dialogs, read or
+/// touch local files (logs, drafts, recordings), the clipboard or the
+/// microphone, change settings or the signed-in session. Origins from
+/// `ipc_extra_origins` can't.
+pub const SENSITIVE_ACTIONS: &[&str] = &[
+    "start_download",
+    "download_attachments",
+    "show_in_folder",
+    "choose_download_directory",
+    "set_download_directory",
+    "begin_file_drag",
+    "preview_file",
+    "upload_file",
+    "resume_upload",
+    "paste",
+    "stage_media",
+    "request_permission",
+    "export_logs",
+    "export_thread",
+    "print_to_pdf",
+    "start_audio_recording",
+    "set_log_level",
+    "set_shortcut",
+    "auth_changed",
+    "set_session_state",
+    "clear_session_state",
+    "save_draft",
+    "get_drafts",
+    "get_recent_logs",
+    "get_system_info",
+    "set_autostart",
+    "set_spellcheck",
+    "add_dictionary_word",
+    "set_block_capture",
+    "set_notification_sound",
+    "set_quiet_hours",
+    "save_window_layout",
+    "clear_download_history",
+    "clear_thread_history",
+    "run_cleanup",
+    "get_recordings",
+    "replay_recording",
+    "download_update",
+];
+
+/// `scheme://host[:port]` of `uri`, lowercased
+fn origin_of(uri: &http::Uri) -> Option<String> {
+    Some(format!("{}://{}", uri.scheme_str()?, uri.authority()?).to_ascii_lowercase())
+}
+
+/// Also accept messages from `url`'s origin as the app's own; for the dev
+/// server in debug builds
+pub fn allow_origin(url: &str) {
+    match url.parse::<http::Uri>().ok().as_ref().and_then(origin_of) {
+        Some(origin) => {
+            let mut origins = APP_ORIGINS.lock().unwrap();
+            if !origins.contains(&origin) {
+                origins.push(origin);
+            }
+        }
+        None => warn!("Not an origin IPC messages can come from: {}", url),
+    }
+}
+
+/// Whether a page at `origin` may post `action`, `extra_origins` being
+/// `ipc_extra_origins`
+fn origin_allowed(origin: &str, action: &str, extra_origins: &[String]) -> bool {
+    let own = origin == APP_ORIGIN || APP_ORIGINS_MAPPED.contains(&origin) || APP_ORIGINS.lock().unwrap().iter().any(|o| o == origin);
+    own || (!SENSITIVE_ACTIONS.contains(&action)
+        && extra_origins.iter().any(|o| o.trim_end_matches('/').eq_ignore_ascii_case(origin)))
+}
+
+/// Parse an IPC message from the page at `source` and check the fields every
+/// handler relies on: `type` is `[a-z_]+` and allowed from that page,
+/// `requestId` is alphanumeric, identifiers are short and free of control
+/// characters. A violation is logged and answered with an `ipc-error` event
+/// (and the request's result, when its id is usable) instead of reaching a
+/// handler. The body is only borrowed.
+pub fn parse_message(source: &http::Uri, body: &str) -> Option<Value> {
+    let received_at = now_ms();
+    match check_message(source, body, &crate::core::settings::get().ipc_extra_origins) {
+        Ok(message) => {
+            if let Some(request_id) = message["requestId"].as_str() {
+                start_timing(request_id, &message, received_at);
+            }
+            Some(message)
+        }
+        Err((request_id, code, error)) => {
+            warn!(code = ?code, "Rejected IPC message: {}", error);
+            let mut result = serde_json::json!({ "success": false, "code": code, "error": error });
+            if code == RejectCode::PayloadTooLarge {
+                result["size"] = body.len().into();
+                result["limit"] = (*MAX_MESSAGE_BYTES).into();
+            }
+            respond(request_id.as_deref(), "ipc-error", &result);
+            None
+        }
+    }
+}
+
+type Rejection = (Option<String>, RejectCode, String);
+
+fn check_message(source: &http::Uri, body: &str, extra_origins: &[String]) -> Result<Value, Rejection> {
+    if body.len() > *MAX_MESSAGE_BYTES {
+        return Err((None, RejectCode::PayloadTooLarge, format!("message too large ({} bytes)", body.len())));
+    }
+    let message: Value =
+        serde_json::from_str(body).map_err(|e| (None, RejectCode::InvalidJson, format!("invalid JSON: {}", e)))?;
+    let request_id = message["requestId"].as_str().filter(|id| is_request_id(id)).map(|id| id.to_string());
+    let fail = |error: String| Err((request_id.clone(), RejectCode::InvalidField, error));
+    let shown = |value: &Value| value.to_string().chars().take(80).collect::<String>();
+
+    if !message["requestId"].is_null() && request_id.is_none() {
+        return fail(format!("invalid requestId: {}", shown(&message["requestId"])));
+    }
+    let action = match message["type"].as_str() {
+        Some(name) if is_message_type(name) => name,
+        _ => return fail(format!("invalid message type: {}", shown(&message["type"]))),
+    };
+    let origin = origin_of(source).unwrap_or_else(|| "null".to_string());
+    if !origin_allowed(&origin, action, extra_origins) {
+        return Err((request_id, RejectCode::ForbiddenOrigin, format!("{} is not allowed from {}", action, origin)));
+    }
+    let thread_id = |value: &Value| match value {
+        Value::Number(_) => true,
+        Value::String(id) => is_thread_id(id),
+        _ => false,
+    };
+    if !message["threadId"].is_null() && !thread_id(&message["threadId"]) {
+        return fail(format!("invalid threadId: {}", shown(&message["threadId"])));
+    }
+    let thread_ids_valid = match &message["threadIds"] {
+        Value::Null => true,
+        Value::Array(ids) => ids.iter().all(thread_id),
+        _ => false,
+    };
+    if !thread_ids_valid {
+        return fail(format!("invalid threadIds: {}", shown(&message["threadIds"])));
+    }
+    for key in ["id", "topic"] {
+        let valid = match &message[key] {
+            Value::Null | Value::Number(_) => true,
+            Value::String(s) => s.chars().count() <= MAX_ID_CHARS && !s.chars().any(char::is_control),
+            _ => false,
+        };
+        if !valid {
+            return fail(format!("invalid {}: {}", key, shown(&message[key])));
+        }
+    }
+    Ok(message)
+}
+
+/// Publish `value` as `window.ipcResult_<requestId>` (polled by the frontend)
+/// and as a `event` CustomEvent on `window`.
+pub fn respond(request_id: Option<&str>, event: &str, value: &Value) {
+    respond_after(request_id, event, value, None);
+}
+
+/// [`respond`] for an answer that took the API calls spanning `upstream`
+pub fn respond_after(request_id: Option<&str>, event: &str, value: &Value, upstream: Option<Upstream>) {
+    let Some(handle) = handle() else { return };
+    let Some(id) = request_id.filter(|id| is_request_id(id)) else {
+        handle.emit(event, value);
+        return;
+    };
+    let mut value = value.clone();
+    if let Some(timings) = finish_timing(id, upstream) {
+        if let Some(object) = value.as_object_mut() {
+            object.insert("_timings".to_string(), timings);
+        }
+    }
+    handle.push(EmitEvent::Result { request_id: id.to_string(), value: value.clone() });
+    handle.emit(event, &value);
+}
+
+/// Most timed requests waiting for an answer; ones never answered are dropped beyond it
+const MAX_TIMED: usize = 256;
+
+/// Wall-clock milliseconds, comparable with `performance.timeOrigin` in the page
+pub fn now_ms() -> f64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0.0, |elapsed| elapsed.as_secs_f64() * 1000.0)
+}
+
+/// Time `message` when it falls into `ipc_timing_sample_rate`; a `sentAt`
+/// the page put in is passed back with the rest
+fn start_timing(request_id: &str, message: &Value, received_at: f64) {
+    let rate = crate::core::settings::get().ipc_timing_sample_rate;
+    if rate <= 0.0 || !rand::thread_rng().gen_bool(rate.min(1.0)) {
+        return;
+    }
+    let mut timings = serde_json::json!({ "type": message["type"], "receivedAt": received_at });
+    if let Some(sent_at) = message["sentAt"].as_f64() {
+        timings["sentAt"] = sent_at.into();
+    }
+    let mut received = RECEIVED_AT.lock().unwrap();
+    if received.len() >= MAX_TIMED {
+        received.clear();
+    }
+    received.insert(request_id.to_string(), timings);
+}
+
+/// The `_timings` of a sampled request being answered now, `upstream` being
+/// the API calls that went into the answer
+fn finish_timing(request_id: &str, upstream: Option<Upstream>) -> Option<Value> {
+    let mut timings = RECEIVED_AT.lock().unwrap().remove(request_id)?;
+    timings["respondedAt"] = now_ms().into();
+    if let Some(upstream) = upstream {
+        timings["upstreamSent"] = upstream.sent_at.into();
+        timings["upstreamReceived"] = upstream.received_at.into();
+    }
+    Some(timings)
+}
+
+/// When a forwarded request went out and its whole answer was in, in
+/// [`now_ms`] milliseconds. [`crate::protocol`] puts it in the extensions of
+/// the responses that came from the API.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Upstream {
+    pub sent_at: f64,
+    pub received_at: f64,
+}
+
+impl Upstream {
+    /// The span of a forwarded `response`, if it reached the API
+    pub fn of<B>(response: &http::Response<B>) -> Option<Upstream> {
+        response.extensions().get().copied()
+    }
+
+    /// From the first of the two going out to the last coming back
+    pub fn cover(self, other: Upstream) -> Upstream {
+        Upstream { sent_at: self.sent_at.min(other.sent_at), received_at: self.received_at.max(other.received_at) }
+    }
+}
+
+/// Queue a push of state `topic`'s changes to the page
+pub fn queue_state(topic: &str) {
+    if let Some(handle) = handle() {
+        handle.state(topic);
+    }
+}
+
+/// Queue raw JavaScript for evaluation in the webview
+pub fn queue_script(script: String) {
+    if let Some(handle) = handle() {
+        handle.eval(script);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ERP: &str = "https://erp.example.com";
+
+    fn message(source: &str, body: &Value, extra_origins: &[&str]) -> Result<Value, Rejection> {
+        let extra: Vec<String> = extra_origins.iter().map(|o| o.to_string()).collect();
+        check_message(&source.parse().unwrap(), &body.to_string(), &extra)
+    }
+
+    fn code(result: Result<Value, Rejection>) -> Option<RejectCode> {
+        result.err().map(|(_, code, _)| code)
+    }
+
+    #[test]
+    fn app_origins_may_post_anything() {
+        for source in ["miko://app/index.html", "http://miko.app/", "https://miko.app/threads"] {
+            for &action in SENSITIVE_ACTIONS.iter().chain(["mark_read"].iter()) {
+                let body = serde_json::json!({ "type": action, "requestId": "r1" });
+                assert!(message(source, &body, &[]).is_ok(), "{} from {}", action, source);
+            }
+        }
+    }
+
+    #[test]
+    fn foreign_origins_are_rejected() {
+        for source in ["https://evil.example.com/", "http://miko.app.evil.com/", "https://miko.app:8443/", "miko://other/", "http://localhost:5173/"] {
+            let body = serde_json::json!({ "type": "mark_read", "requestId": "r1", "threadId": "t1" });
+            let rejected = message(source, &body, &[ERP]);
+            assert_eq!(code(rejected.clone()), Some(RejectCode::ForbiddenOrigin), "{}", source);
+            // Answered to the request it came with
+            assert_eq!(rejected.err().and_then(|(id, _, _)| id).as_deref(), Some("r1"));
+        }
+    }
+
+    #[test]
+    fn extra_origins_may_post_only_plain_actions() {
+        let plain = serde_json::json!({ "type": "mark_read", "threadId": "t1" });
+        assert!(message("https://erp.example.com/page", &plain, &[ERP]).is_ok());
+        assert!(message("https://ERP.example.com/page", &plain, &["https://erp.example.com/"]).is_ok());
+        for &action in SENSITIVE_ACTIONS {
+            let body = serde_json::json!({ "type": action });
+            assert_eq!(code(message("https://erp.example.com/page", &body, &[ERP])), Some(RejectCode::ForbiddenOrigin), "{}", action);
+        }
+    }
+
+    #[test]
+    fn session_and_local_data_actions_are_sensitive() {
+        for action in ["auth_changed", "set_session_state", "clear_session_state", "get_recent_logs", "get_recordings", "replay_recording", "upload_file"] {
+            assert!(SENSITIVE_ACTIONS.contains(&action), "{}", action);
+        }
+    }
+
+    #[test]
+    fn malformed_messages_are_rejected_before_the_origin_check() {
+        let source = "https://evil.example.com/";
+        assert_eq!(code(check_message(&source.parse().unwrap(), "{not json", &[])), Some(RejectCode::InvalidJson));
+        let bad_type = serde_json::json!({ "type": "Auth-Changed" });
+        assert_eq!(code(message(source, &bad_type, &[])), Some(RejectCode::InvalidField));
+        let bad_id = serde_json::json!({ "type": "auth_changed", "requestId": "x');alert(1)//" });
+        assert_eq!(code(message("miko://app/", &bad_id, &[])), Some(RejectCode::InvalidField));
+    }
+
+    #[test]
+    fn identifiers_are_checked() {
+        let long = "a".repeat(MAX_ID_CHARS + 1);
+        for body in [
+            serde_json::json!({ "type": "mark_read", "threadId": long }),
+            serde_json::json!({ "type": "mark_read", "threadId": "a\nb" }),
+            serde_json::json!({ "type": "mark_read", "id": ["a"] }),
+            serde_json::json!({ "type": "subscribe", "topic": long }),
+        ] {
+            assert_eq!(code(message("miko://app/", &body, &[])), Some(RejectCode::InvalidField), "{}", body);
+        }
+    }
+
+    #[test]
+    fn thread_ids_cannot_leave_their_path_segment() {
+        for thread_id in ["a/b", "../admin", "..", ".", "a?limit=1", "a#b", "a%2Fb", "a\\b", "a b", "", "กี่", "a;b", "a&b=c"] {
+            let body = serde_json::json!({ "type": "export_thread", "threadId": thread_id, "format": "csv" });
+            assert_eq!(code(message("miko://app/", &body, &[])), Some(RejectCode::InvalidField), "{:?}", thread_id);
+            let body = serde_json::json!({ "type": "mark_threads_read", "threadIds": ["ok", thread_id] });
+            assert_eq!(code(message("miko://app/", &body, &[])), Some(RejectCode::InvalidField), "{:?}", thread_id);
+        }
+        for thread_id in [serde_json::json!("0b6f3c1e-8d2a-4f5e-9c3b-7a1d2e4f6a8b"), serde_json::json!("thread_42"), serde_json::json!(42)] {
+            let body = serde_json::json!({ "type": "mark_read", "threadId": thread_id, "threadIds": [thread_id] });
+            assert!(message("miko://app/", &body, &[]).is_ok(), "{}", thread_id);
+        }
+    }
+
+    #[test]
+    fn hostile_messages_are_rejected() {
+        let deep = format!("{}{}", "[".repeat(10_000), "]".repeat(10_000));
+        let oversized = serde_json::json!({ "type": "save_draft", "text": "x".repeat(*MAX_MESSAGE_BYTES) }).to_string();
+        let table: Vec<(String, RejectCode)> = vec![
+            (oversized, RejectCode::PayloadTooLarge),
+            (deep, RejectCode::InvalidJson),
+            ("{\"type\": \"mark_read\"".to_string(), RejectCode::InvalidJson),
+            ("".to_string(), RejectCode::InvalidJson),
+            // Not objects
+            ("[]".to_string(), RejectCode::InvalidField),
+            ("[{\"type\": \"mark_read\"}]".to_string(), RejectCode::InvalidField),
+            ("\"mark_read\"".to_string(), RejectCode::InvalidField),
+            ("42".to_string(), RejectCode::InvalidField),
+            ("null".to_string(), RejectCode::InvalidField),
+        ];
+        for (body, expected) in table {
+            let shown: String = body.chars().take(40).collect();
+            assert_eq!(code(check_message(&"miko://app/".parse().unwrap(), &body, &[])), Some(expected), "{}", shown);
+        }
+
+        // Wrong types
+        for body in [
+            serde_json::json!({ "type": 5 }),
+            serde_json::json!({ "type": ["mark_read"] }),
+            serde_json::json!({ "type": "Mark_Read" }),
+            serde_json::json!({ "type": "mark_read", "requestId": {} }),
+            serde_json::json!({ "type": "mark_read", "requestId": 7 }),
+            serde_json::json!({ "type": "mark_read", "threadId": true }),
+            serde_json::json!({ "type": "mark_read", "threadId": { "id": "a" } }),
+            serde_json::json!({ "type": "mark_threads_read", "threadIds": "a" }),
+            serde_json::json!({ "type": "mark_threads_read", "threadIds": [null] }),
+            serde_json::json!({ "type": "mark_threads_read", "threadIds": [["a"]] }),
+            serde_json::json!({ "type": "cancel_upload", "id": {} }),
+            serde_json::json!({ "type": "subscribe", "topic": ["downloads"] }),
+        ] {
+            assert_eq!(code(message("miko://app/", &body, &[])), Some(RejectCode::InvalidField), "{}", body);
+        }
+    }
+
+    /// Strings that end a script block, a line or a string literal when spliced in raw
+    const NASTY: &[&str] = &[
+        "</script><script>alert(1)</script>",
+        "<!-- <![CDATA[ ]]> -->",
+        "line\u{2028}separator\u{2029}paragraph",
+        "\"double\" 'single' `back${tick}`",
+        "back\\slash \\\" \\u0041 \\",
+        "\n\r\t\u{0}",
+    ];
+
+    #[test]
+    fn literals_escape_what_json_leaves_raw() {
+        for &text in NASTY {
+            let value = serde_json::json!({ "text": text, "nested": [text], text: 1 });
+            let literal = js_literal(&value);
+            for raw in ['<', '\u{2028}', '\u{2029}', '\n', '\r'] {
+                assert!(!literal.contains(raw), "{:?} in {}", raw, literal);
+            }
+            // Still JSON, and the same value once parsed
+            assert_eq!(serde_json::from_str::<Value>(&literal).unwrap(), value);
+        }
+    }
+
+    #[test]
+    fn event_scripts_keep_name_and_payload_inside_their_literals() {
+        for &text in NASTY {
+            let script = emit_script(text, &serde_json::json!({ "text": text }));
+            let inner = script
+                .strip_prefix("window.dispatchEvent(new CustomEvent(")
+                .and_then(|rest| rest.strip_suffix(" }));"))
+                .unwrap_or_else(|| panic!("{}", script));
+
+            // The name's literal ends exactly where the script goes on
+            let mut values = serde_json::Deserializer::from_str(inner).into_iter::<Value>();
+            assert_eq!(values.next().unwrap().unwrap(), text);
+            let detail = inner[values.byte_offset()..].strip_prefix(", { detail: ").unwrap();
+            assert_eq!(serde_json::from_str::<Value>(detail).unwrap(), serde_json::json!({ "text": text }));
+        }
+    }
+
+    #[test]
+    fn lone_surrogates_never_reach_a_literal() {
+        // JSON from the page can't carry one...
+        let body = r#"{"type": "save_draft", "text": "\ud800"}"#;
+        assert_eq!(code(check_message(&"miko://app/".parse().unwrap(), body, &[])), Some(RejectCode::InvalidJson));
+        // ...and native text with one was replaced while converting
+        let lossy = String::from_utf16_lossy(&[0x61, 0xD800, 0x62]);
+        assert_eq!(serde_json::from_str::<Value>(&js_literal(&lossy)).unwrap(), "a\u{FFFD}b");
+    }
+
+    #[test]
+    fn rejected_messages_never_reach_a_handler() {
+        let body = serde_json::json!({ "type": "auth_changed", "token": "stolen" }).to_string();
+        assert!(parse_message(&"https://evil.example.com/".parse().unwrap(), &body).is_none());
+        assert!(parse_message(&"miko://app/".parse().unwrap(), &body).is_some());
+    }
+
+    /// A handle of its own, counting wake-ups instead of waking an event loop
+    fn test_handle() -> (WebviewHandle, Arc<AtomicU64>) {
+        let wakes = Arc::new(AtomicU64::new(0));
+        let counter = wakes.clone();
+        let handle = WebviewHandle {
+            queue: Arc::new(Mutex::new(Queue::default())),
+            waker: Arc::new(move || {
+                counter.fetch_add(1, Ordering::SeqCst);
+            }),
+        };
+        (handle, wakes)
+    }
+
+    fn payloads(items: &[EmitEvent]) -> Vec<Value> {
+        items
+            .iter()
+            .map(|item| match item {
+                EmitEvent::Event { payload, .. } | EmitEvent::Latest { payload, .. } => payload.clone(),
+                EmitEvent::State(topic) => Value::from(topic.as_str()),
+                _ => Value::Null,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn floods_of_latest_and_state_coalesce() {
+        let (handle, wakes) = test_handle();
+        for i in 0..10_000 {
+            handle.emit_latest("download-progress", &format!("job-{}", i % 50), &serde_json::json!({ "i": i }));
+            handle.state(&format!("topic-{}", i % 10));
+        }
+        // One wake-up; everything after it rides along
+        assert_eq!(wakes.load(Ordering::SeqCst), 1);
+
+        let items = handle.drain();
+        assert_eq!(items.len(), 60);
+        for item in &items {
+            match item {
+                // Each slot holds the newest payload for its key
+                EmitEvent::Latest { key, payload, .. } => {
+                    let job: u64 = key.trim_start_matches("job-").parse().unwrap();
+                    assert_eq!(payload["i"], 10_000 - 50 + job, "{}", key);
+                }
+                EmitEvent::State(_) => {}
+                _ => panic!("only latest and state items were queued"),
+            }
+        }
+        // First-queued order is kept
+        let payloads = payloads(&items);
+        assert_eq!(payloads[..4], [serde_json::json!({ "i": 9950 }), Value::from("topic-0"), serde_json::json!({ "i": 9951 }), Value::from("topic-1")]);
+        assert_eq!(payloads.iter().filter(|p| p.is_string()).count(), 10);
+        assert!(handle.drain().is_empty());
+    }
+
+    #[test]
+    fn plain_events_drop_the_oldest_past_the_limit() {
+        let (handle, _) = test_handle();
+        for i in 0..10_000u64 {
+            handle.emit("message", &i);
+        }
+        assert_eq!(handle.queue.lock().unwrap().dropped, 10_000 - MAX_QUEUED);
+
+        let items = handle.drain();
+        assert_eq!(handle.queue.lock().unwrap().dropped, 0);
+        // The newest survive, in order
+        let expected: Vec<Value> = (10_000 - MAX_QUEUED as u64..10_000).map(Value::from).collect();
+        assert_eq!(payloads(&items), expected);
+    }
+
+    #[test]
+    fn deliveries_stay_under_the_script_limit() {
+        let (handle, _) = test_handle();
+        let text = "ข้อความ <b>".repeat(400);
+        for i in 0..MAX_QUEUED {
+            handle.emit("message", &serde_json::json!({ "i": i, "text": text }));
+        }
+        let batches = batches(handle.drain());
+        assert!(batches.len() > 1);
+        assert!(batches.iter().all(|(batch, _)| batch.len() <= MAX_SCRIPT_BYTES));
+        assert_eq!(batches.iter().map(|(_, count)| count).sum::<usize>(), MAX_QUEUED);
+
+        // Every event arrives once, in order
+        let joined: String = batches.iter().map(|(batch, _)| batch.as_str()).collect();
+        let mut from = 0;
+        for i in 0..MAX_QUEUED {
+            let at = joined[from..].find(&format!("{{\"i\":{},", i)).unwrap_or_else(|| panic!("event {} missing", i));
+            from += at + 1;
+        }
+    }
+
+    #[test]
+    fn oversized_payloads_are_chunked() {
+        let (handle, _) = test_handle();
+        let text = "กี่😀\u{2028}</script>".repeat(200_000);
+        handle.emit("big", &serde_json::json!({ "text": text }));
+        handle.emit("small", &1);
+
+        let batches = batches(handle.drain());
+        assert!(batches.iter().all(|(batch, _)| batch.len() <= MAX_SCRIPT_BYTES));
+        let scripts: Vec<&str> = batches.iter().flat_map(|(batch, _)| batch.lines()).collect();
+        assert!(scripts.len() > 3);
+        assert!(scripts.last().unwrap().contains("\"small\""));
+        assert!(!scripts.iter().any(|script| script.contains("</script>")));
+
+        // The pieces join back into the payload's JSON
+        let mut json = String::new();
+        for script in &scripts[..scripts.len() - 2] {
+            let literal = &script[script.find("|| '') + ").unwrap() + 9..script.rfind("; } catch").unwrap()];
+            json.push_str(&serde_json::from_str::<String>(literal).expect("one literal per piece"));
+        }
+        let payload: Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(payload["text"], text.as_str());
+    }
+
+    #[test]
+    fn floods_of_large_messages_leave_memory_steady() {
+        use crate::testing::{live_bytes, peak_bytes, reset_peak};
+
+        let source: http::Uri = "miko://app/".parse().unwrap();
+        let limit = *MAX_MESSAGE_BYTES;
+        let fits = serde_json::json!({ "type": "save_draft", "threadId": "t", "text": "x".repeat(limit - 100) }).to_string();
+        let too_large = "x".repeat(limit * 4);
+        let soak = || {
+            for _ in 0..50 {
+                assert!(parse_message(&source, &fits).is_some());
+                assert!(parse_message(&source, &too_large).is_none());
+            }
+        };
+
+        soak();
+        let before = live_bytes();
+        reset_peak();
+        soak();
+        assert!(live_bytes() - before < 64 * 1024, "{} bytes kept", live_bytes() - before);
+        // One parsed copy of a message at a time; rejected ones are never copied
+        assert!(peak_bytes() - before < 2 * limit as isize, "peak {} bytes over", peak_bytes() - before);
+    }
+
+    #[test]
+    fn floods_of_large_deliveries_leave_memory_steady() {
+        use crate::testing::{live_bytes, peak_bytes, reset_peak};
+
+        let (handle, _) = test_handle();
+        let text = "x".repeat(MAX_SCRIPT_BYTES + MAX_SCRIPT_BYTES / 4);
+        let soak = || {
+            for i in 0..5 {
+                for _ in 0..MAX_QUEUED / 64 {
+                    handle.emit("message", &i);
+                }
+                handle.emit_latest("big", "only", &serde_json::json!({ "text": text }));
+                let batches = batches(handle.drain());
+                assert!(batches.iter().all(|(batch, _)| batch.len() <= MAX_SCRIPT_BYTES));
+            }
+        };
+
+        soak();
+        let before = live_bytes();
+        reset_peak();
+        soak();
+        assert!(live_bytes() - before < 64 * 1024, "{} bytes kept", live_bytes() - before);
+        // A few copies of the payload while it is escaped and chunked, none per delivery
+        assert!(peak_bytes() - before < 8 * text.len() as isize, "peak {} bytes over", peak_bytes() - before);
+    }
+
+    #[test]
+    fn timed_answers_report_the_upstream_span_they_were_given() {
+        crate::testing::emitted("timed-answer");
+        let span = Upstream { sent_at: 20.0, received_at: 30.0 };
+        for (id, upstream) in [("timedWithSpan", Some(span)), ("timedWithout", None)] {
+            RECEIVED_AT.lock().unwrap().insert(id.to_string(), serde_json::json!({ "type": "mark_threads_read", "receivedAt": 10.0 }));
+            respond_after(Some(id), "timed-answer", &serde_json::json!({ "success": true }), upstream);
+        }
+
+        let timings: Vec<Value> = crate::testing::emitted("timed-answer").into_iter().map(|answer| answer["_timings"].clone()).collect();
+        assert_eq!(timings.len(), 2);
+        assert_eq!((timings[0]["upstreamSent"].as_f64(), timings[0]["upstreamReceived"].as_f64()), (Some(20.0), Some(30.0)));
+        assert!(timings[1].get("upstreamSent").is_none() && timings[1]["respondedAt"].as_f64() > Some(10.0));
+        assert!(RECEIVED_AT.lock().unwrap().get("timedWithSpan").is_none());
+
+        let later = Upstream { sent_at: 25.0, received_at: 40.0 };
+        assert_eq!(span.cover(later), Upstream { sent_at: 20.0, received_at: 40.0 });
+    }
+}
@@ -0,0 +1,147 @@
+//! Structured logging shared by the desktop app and the downloader service.
+//!
+//! Logs go to a daily-rotated file in the per-app data directory
+//! (`<app>.YYYY-MM-DD.log`), and additionally to stderr in debug builds.
+//! The level is taken from `MIKO_LOG` (env-filter syntax) and can be changed
+//! at runtime with [`set_level`]. Where the files go is up to
+//! [`crate::paths`].
+
+use std::sync::OnceLock;
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_appender::rolling::{Builder, Rotation};
+use tracing_subscriber::{fmt, prelude::*, reload, EnvFilter, Registry};
+
+pub const LOG_ENV: &str = "MIKO_LOG";
+const DEFAULT_FILTER: &str = "info";
+const MAX_LOG_FILES: usize = 14;
+
+static FILTER_HANDLE: OnceLock<reload::Handle<EnvFilter, Registry>> = OnceLock::new();
+
+/// Initialize logging for `app_name` (used as the log file prefix).
+///
+/// The returned guard flushes the background writer on drop and must be kept
+/// alive for the lifetime of the process.
+pub fn init(app_name: &str) -> Option<WorkerGuard> {
+    let filter = EnvFilter::try_from_env(LOG_ENV).unwrap_or_else(|_| EnvFilter::new(DEFAULT_FILTER));
+    let (filter, handle) = reload::Layer::new(filter);
+
+    let dir = crate::paths::log_dir();
+    let appender = std::fs::create_dir_all(&dir)
+        .map_err(|e| e.to_string())
+        .and_then(|_| {
+            Builder::new()
+                .rotation(Rotation::DAILY)
+                .filename_prefix(app_name)
+                .filename_suffix("log")
+                .max_log_files(MAX_LOG_FILES)
+                .build(&dir)
+                .map_err(|e| e.to_string())
+        });
+
+    let (file_layer, guard) = match appender {
+        Ok(appender) => {
+            let (writer, guard) = tracing_appender::non_blocking(appender);
+            (Some(fmt::layer().with_writer(writer).with_ansi(false)), Some(guard))
+        }
+        Err(e) => {
+            eprintln!("Failed to open log directory {}: {}", dir.display(), e);
+            (None, None)
+        }
+    };
+
+    // stdout is reserved for the downloader's JSON protocol, so the console copy goes to stderr
+    let console_layer = cfg!(debug_assertions).then(|| fmt::layer().with_writer(std::io::stderr));
+
+    if tracing_subscriber::registry()
+        .with(filter)
+        .with(file_layer)
+        .with(console_layer)
+        .try_init()
+        .is_err()
+    {
+        return guard;
+    }
+
+    let _ = FILTER_HANDLE.set(handle);
+
+    let app = app_name.to_string();
+    std::panic::set_hook(Box::new(move |panic_info| {
+        tracing::error!("panic: {}", panic_info);
+        write_crash_report(&app, &panic_info.to_string());
+    }));
+
+    tracing::info!(
+        version = env!("CARGO_PKG_VERSION"),
+        log_dir = %dir.display(),
+        "{} logging initialized",
+        app_name
+    );
+
+    guard
+}
+
+fn write_crash_report(app_name: &str, message: &str) {
+    let dir = crate::paths::crash_dir();
+    if std::fs::create_dir_all(&dir).is_err() {
+        return;
+    }
+    let now = chrono::Local::now();
+    let report = format!(
+        "app: {}\nversion: {}\ntime: {}\nos: {} ({})\n\n{}\n\n{}\n",
+        app_name,
+        env!("CARGO_PKG_VERSION"),
+        now.to_rfc3339(),
+        std::env::consts::OS,
+        std::env::consts::ARCH,
+        message,
+        std::backtrace::Backtrace::force_capture()
+    );
+    let _ = std::fs::write(dir.join(format!("{}-{}.txt", app_name, now.format("%Y%m%d-%H%M%S"))), report);
+}
+
+/// Replace the active filter, e.g. `"debug"` or `"info,workspace::platform=trace"`
+pub fn set_level(directives: &str) -> Result<(), String> {
+    let filter = EnvFilter::try_new(directives).map_err(|e| format!("Invalid log filter '{}': {}", directives, e))?;
+    FILTER_HANDLE
+        .get()
+        .ok_or("Logging is not initialized")?
+        .reload(filter)
+        .map_err(|e| e.to_string())?;
+
+    tracing::info!(filter = directives, "Log level changed");
+    Ok(())
+}
+
+const SENSITIVE_KEYS: &[&str] = &["token", "access_token", "password", "session", "sessionid", "key", "secret"];
+
+/// Mask the values of credential-like query parameters before a URL is logged
+pub fn redact_url(url: &str) -> String {
+    let Some((base, query)) = url.split_once('?') else {
+        return url.to_string();
+    };
+
+    let query = query
+        .split('&')
+        .map(|pair| match pair.split_once('=') {
+            Some((key, _)) if SENSITIVE_KEYS.contains(&key.to_ascii_lowercase().as_str()) => format!("{}=***", key),
+            _ => pair.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("&");
+
+    format!("{}?{}", base, query)
+}
+
+/// Headers carrying credentials, which are never logged or written to disk
+pub fn is_credential_header(name: &str) -> bool {
+    matches!(name.to_ascii_lowercase().as_str(), "authorization" | "cookie" | "set-cookie" | "x-session-id")
+}
+
+/// Mask the value of authentication headers before they are logged
+pub fn redact_header(name: &str, value: &str) -> String {
+    if is_credential_header(name) {
+        "***".to_string()
+    } else {
+        value.to_string()
+    }
+}
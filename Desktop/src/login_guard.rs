@@ -0,0 +1,168 @@
+//! Sign-in attempt limits for `POST /api/login` through the `miko://`
+//! protocol, against password guessing on a shared machine.
+//!
+//! Rejected sign-ins (401/403) are counted per account (`uid` in the body,
+//! kept hashed) and for the whole app. After [`FREE_ATTEMPTS`] the rejection
+//! is answered late, one second more each time up to [`MAX_DELAY`]; after
+//! [`LOCKOUT_AFTER`] (or [`APP_LOCKOUT_AFTER`] across accounts) sign-ins are
+//! refused with 429 and `retry_after` (seconds) for a lockout that doubles
+//! each time, up to [`MAX_LOCKOUT`]. A successful sign-in clears the
+//! account's count and the app's. Counts are kept in `login-attempts.json` in
+//! the data directory so quitting doesn't reset them, and are forgotten
+//! after [`FORGET_AFTER`] without a failure.
+//!
+//! Every answer to a sign-in says how many tries are left before the lockout
+//! (`attempts_remaining`); captcha fields the server sends (`captcha*`) reach
+//! the page even when its error body had to be wrapped in the envelope.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+use chrono::{DateTime, Utc};
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use tracing::{info, warn};
+
+pub const LOGIN_PATH: &str = "/api/login";
+const FREE_ATTEMPTS: u32 = 5;
+const LOCKOUT_AFTER: u32 = 10;
+const APP_LOCKOUT_AFTER: u32 = 20;
+const MAX_DELAY: Duration = Duration::from_secs(8);
+const FIRST_LOCKOUT: Duration = Duration::from_secs(60);
+const MAX_LOCKOUT: Duration = Duration::from_secs(60 * 60);
+const FORGET_AFTER: Duration = Duration::from_secs(24 * 60 * 60);
+/// Key of the app-wide count
+const APP_KEY: &str = "*";
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Attempts {
+    failures: u32,
+    last_failure: Option<DateTime<Utc>>,
+    locked_until: Option<DateTime<Utc>>,
+}
+
+impl Attempts {
+    fn current(mut self, now: DateTime<Utc>) -> Self {
+        let forgotten = self.last_failure.is_none_or(|at| now - at > chrono::Duration::from_std(FORGET_AFTER).unwrap());
+        if forgotten && self.locked_until.is_none_or(|until| until <= now) {
+            self = Self::default();
+        }
+        self
+    }
+}
+
+lazy_static! {
+    static ref ATTEMPTS: Mutex<HashMap<String, Attempts>> = Mutex::new(load());
+}
+
+fn path() -> std::path::PathBuf {
+    crate::paths::data_dir().join("login-attempts.json")
+}
+
+fn load() -> HashMap<String, Attempts> {
+    std::fs::read(path())
+        .ok()
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .unwrap_or_default()
+}
+
+fn save(attempts: &HashMap<String, Attempts>) {
+    let result = serde_json::to_vec(attempts)
+        .map_err(std::io::Error::other)
+        .and_then(|bytes| crate::core::write_atomic(&path(), &bytes));
+    if let Err(e) = result {
+        warn!("Failed to save sign-in attempts: {}", e);
+    }
+}
+
+/// The account a sign-in request is for, hashed; the app-wide key if the body names none
+pub fn account_key(body: &[u8]) -> String {
+    let body: Value = serde_json::from_slice(body).unwrap_or(Value::Null);
+    match ["uid", "username", "email"].iter().find_map(|field| body[*field].as_str()) {
+        Some(account) => Sha256::digest(account.trim().to_lowercase().as_bytes())[..8].iter().map(|b| format!("{:02x}", b)).collect(),
+        None => APP_KEY.to_string(),
+    }
+}
+
+/// Seconds until `account` may try again, while it or the app is locked out
+pub fn locked(account: &str) -> Option<u64> {
+    let now = Utc::now();
+    let attempts = ATTEMPTS.lock().unwrap();
+    [account, APP_KEY]
+        .iter()
+        .filter_map(|key| attempts.get(*key)?.locked_until)
+        .filter(|until| *until > now)
+        .max()
+        .map(|until| (until - now).num_seconds().max(1) as u64)
+}
+
+/// What a sign-in's answer means for the counts
+pub struct Outcome {
+    /// Hold the answer back this long
+    pub delay: Duration,
+    pub attempts_remaining: u32,
+}
+
+/// Count the answer `status` to a sign-in for `account`
+pub fn record(account: &str, status: http::StatusCode) -> Outcome {
+    let now = Utc::now();
+    let mut attempts = ATTEMPTS.lock().unwrap();
+    for key in [account, APP_KEY] {
+        let entry = attempts.remove(key).unwrap_or_default().current(now);
+        attempts.insert(key.to_string(), entry);
+    }
+
+    if status.is_success() {
+        attempts.remove(account);
+        attempts.remove(APP_KEY);
+        save(&attempts);
+        return Outcome { delay: Duration::ZERO, attempts_remaining: LOCKOUT_AFTER };
+    }
+    if !matches!(status, http::StatusCode::UNAUTHORIZED | http::StatusCode::FORBIDDEN) {
+        let failures = attempts[account].failures;
+        return Outcome { delay: Duration::ZERO, attempts_remaining: LOCKOUT_AFTER.saturating_sub(failures) };
+    }
+
+    let mut remaining = u32::MAX;
+    let mut delay = Duration::ZERO;
+    for (key, limit) in [(account, LOCKOUT_AFTER), (APP_KEY, APP_LOCKOUT_AFTER)] {
+        let entry = attempts.get_mut(key).unwrap();
+        entry.failures += 1;
+        entry.last_failure = Some(now);
+        remaining = remaining.min(limit.saturating_sub(entry.failures));
+        if entry.failures > FREE_ATTEMPTS {
+            delay = delay.max(Duration::from_secs(1 << (entry.failures - FREE_ATTEMPTS - 1).min(3)).min(MAX_DELAY));
+        }
+        if entry.failures >= limit {
+            let lockout = FIRST_LOCKOUT.saturating_mul(1 << (entry.failures - limit).min(6)).min(MAX_LOCKOUT);
+            entry.locked_until = Some(now + chrono::Duration::from_std(lockout).unwrap());
+            warn!("Sign-in locked for {:?} after {} failed attempts ({})", lockout, entry.failures, if key == APP_KEY { "app" } else { "account" });
+        }
+    }
+    save(&attempts);
+    if remaining > 0 && remaining <= 3 {
+        info!("Sign-in rejected; {} attempts left before the lockout", remaining);
+    }
+    Outcome { delay, attempts_remaining: remaining }
+}
+
+/// Add `attempts_remaining`, and `captcha*` fields from a wrapped server
+/// body, to a sign-in's JSON answer
+pub fn annotate(body: &[u8], attempts_remaining: u32) -> Option<Vec<u8>> {
+    let Ok(Value::Object(mut object)) = serde_json::from_slice::<Value>(body) else { return None };
+    // The envelope carries the server's text in `error.message`
+    let wrapped = object
+        .get("error")
+        .and_then(|error| error["message"].as_str())
+        .and_then(|message| serde_json::from_str::<Value>(message).ok());
+    if let Some(Value::Object(server)) = wrapped {
+        for (key, value) in server.into_iter().filter(|(key, _)| key.starts_with("captcha")) {
+            object.entry(key).or_insert(value);
+        }
+    }
+    object.insert("attempts_remaining".to_string(), attempts_remaining.into());
+    serde_json::to_vec(&object).ok()
+}
@@ -8,27 +8,110 @@
 mod context_menu;
 #[cfg(target_os = "windows")]
 mod menubar;
+mod accessibility;
+mod actions;
+mod assets;
+mod attachments;
+mod autostart;
+mod cli;
+mod clipboard;
+mod compat;
+mod console;
+mod core;
 mod hooks;
+mod icons;
+mod logging;
+mod login_guard;
+mod maintenance;
+mod metered;
+mod notification_sounds;
+mod mark_read;
+mod diagnostics;
+mod emoji;
+mod ephemeral;
+mod erp_maintenance;
+mod export;
+mod formatting;
+mod history;
+mod inflight;
+mod ipc;
+mod media;
+mod paths;
+mod presence;
+mod preview;
+mod prefetch;
+mod print;
+mod privacy;
+mod protocol;
+mod permissions;
+mod quiet_hours;
+mod recordings;
+#[cfg(any(target_os = "windows", target_os = "macos"))]
+mod recording;
+mod renderer;
+mod rendering;
+mod safe_mode;
+mod session_takeover;
+mod shortcuts;
+mod spellcheck;
+mod startup;
+mod temp_media;
+mod thread_presence;
+mod updates;
+mod upload;
+mod window_layout;
+#[cfg(test)]
+mod testing;
 
 // Platform-specific conditional compilation
 mod platform;
 
 // Main function that calls the platform-specific implementation
 fn main() -> Result<(), Box<dyn std::error::Error>> {
+    startup::begin();
+    // First: `--profile` and `--portable` decide where settings and logs are read from
+    let args = cli::parse();
+    // Before anything reads the settings
+    let safe_mode = args.safe_mode || safe_mode::requested_by_keyboard();
+    if safe_mode {
+        safe_mode::enable();
+    }
+    // Decoded off the UI thread; the window and tray pick the results from the cache
+    std::thread::spawn(icons::preload);
+    std::thread::spawn(emoji::build_index);
+
+    // Keep the guard alive so buffered log lines are flushed on exit
+    let _log_guard = logging::init("desktop");
+    if std::env::var_os(logging::LOG_ENV).is_none() {
+        if let Some(level) = crate::core::settings::get().log_level {
+            let _ = logging::set_level(&level);
+        }
+    }
+    if safe_mode {
+        safe_mode::run_startup_dialog();
+    }
+    assets::verify_at_startup();
+    // Old logs and what earlier runs left behind; again once a day
+    maintenance::start();
+    // Chunked uploads a quit interrupted show up as paused in the Transfers panel
+    upload::restore_interrupted();
+
     #[cfg(target_os = "windows")]
     {
-        platform::win::main()
+        platform::win::main(args)
     }
     #[cfg(target_os = "macos")]
     {
-        platform::mac::main()
+        platform::mac::main(args)
     }
     #[cfg(target_os = "linux")]
     {
+        let _ = args;
         platform::linux::main()
     }
     #[cfg(not(any(target_os = "windows", target_os = "macos", target_os = "linux")))]
     {
+        let _ = args;
         eprintln!("❌ Unsupported platform. This application supports Windows, macOS, and Linux only.");
         std::process::exit(1);
     }
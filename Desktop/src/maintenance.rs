@@ -0,0 +1,277 @@
+//! Housekeeping of the app data directory, so a machine that never
+//! reinstalls doesn't fill up with old files.
+//!
+//! Runs at startup, then daily, and on `run_cleanup`. Each category can be
+//! turned off under `maintenance` in the settings:
+//! - logs of every binary older than `log_retention_days`
+//! - crash reports older than `crash_retention_days`
+//! - staged downloads (`.part`) no download refers to any more, once they are
+//!   [`PART_FILE_GRACE`] old (until then the page may start them again)
+//! - pasted images and voice memos left by earlier sessions, and staged
+//!   images no draft refers to any more
+//!
+//! What was removed is logged and answered as `cleanup-finished`.
+
+use std::path::Path;
+use std::sync::Once;
+use std::time::{Duration, SystemTime};
+use lazy_static::lazy_static;
+use serde::Serialize;
+use serde_json::{json, Value};
+use tracing::{info, warn};
+
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+const PART_FILE_GRACE: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+/// How `recording` names voice memos in the temp directory
+const VOICE_MEMO_PREFIX: &str = "miko-voice-memo-";
+
+lazy_static! {
+    /// Files older than this come from an earlier session
+    static ref SESSION_START: SystemTime = SystemTime::now();
+}
+
+#[derive(Debug, Default, Clone, Copy, Serialize)]
+pub struct Reclaimed {
+    pub files: u64,
+    pub bytes: u64,
+}
+
+impl Reclaimed {
+    fn add(&mut self, other: Reclaimed) {
+        self.files += other.files;
+        self.bytes += other.bytes;
+    }
+}
+
+/// Delete the files directly in `dir` that `expired` picks
+fn remove_files(dir: &Path, expired: impl Fn(&Path, &std::fs::Metadata) -> bool) -> Reclaimed {
+    let mut reclaimed = Reclaimed::default();
+    let Ok(entries) = std::fs::read_dir(dir) else { return reclaimed };
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        let path = entry.path();
+        let Ok(metadata) = entry.metadata() else { continue };
+        if !metadata.is_file() || !expired(&path, &metadata) {
+            continue;
+        }
+        match std::fs::remove_file(&path) {
+            Ok(()) => reclaimed.add(Reclaimed { files: 1, bytes: metadata.len() }),
+            Err(e) => warn!("Failed to remove {}: {}", path.display(), e),
+        }
+    }
+    reclaimed
+}
+
+fn modified_before(metadata: &std::fs::Metadata, cutoff: SystemTime) -> bool {
+    metadata.modified().is_ok_and(|modified| modified < cutoff)
+}
+
+fn has_extension(path: &Path, extension: &str) -> bool {
+    path.extension().is_some_and(|e| e.eq_ignore_ascii_case(extension))
+}
+
+/// `*.log` in `dir` last written before `now - retention`
+pub fn clean_logs(dir: &Path, retention: Duration, now: SystemTime) -> Reclaimed {
+    let cutoff = now - retention;
+    remove_files(dir, |path, metadata| has_extension(path, "log") && modified_before(metadata, cutoff))
+}
+
+/// Crash reports in `dir` written before `now - retention`
+pub fn clean_crash_reports(dir: &Path, retention: Duration, now: SystemTime) -> Reclaimed {
+    let cutoff = now - retention;
+    remove_files(dir, |_, metadata| modified_before(metadata, cutoff))
+}
+
+/// `.part` files in `dir` older than [`PART_FILE_GRACE`] that aren't in `in_use`
+pub fn clean_part_files(dir: &Path, in_use: &[std::path::PathBuf], now: SystemTime) -> Reclaimed {
+    let cutoff = now - PART_FILE_GRACE;
+    remove_files(dir, |path, metadata| {
+        has_extension(path, "part") && modified_before(metadata, cutoff) && !in_use.iter().any(|used| used == path)
+    })
+}
+
+/// Pasted images in `paste_dir` and voice memos in `temp_dir` written before `session_start`
+pub fn clean_temp_files(paste_dir: &Path, temp_dir: &Path, session_start: SystemTime) -> Reclaimed {
+    let mut reclaimed = remove_files(paste_dir, |_, metadata| modified_before(metadata, session_start));
+    reclaimed.add(remove_files(temp_dir, |path, metadata| {
+        let memo = path.file_name().and_then(|n| n.to_str()).is_some_and(|n| n.starts_with(VOICE_MEMO_PREFIX));
+        memo && has_extension(path, "wav") && modified_before(metadata, session_start)
+    }));
+    reclaimed
+}
+
+/// Staged images in `dir` that aren't in `in_use`, once they are a minute
+/// old (one being staged isn't in `in_use` yet)
+pub fn clean_temp_media(dir: &Path, in_use: &[std::path::PathBuf], now: SystemTime) -> Reclaimed {
+    let cutoff = now - Duration::from_secs(60);
+    remove_files(dir, |path, metadata| modified_before(metadata, cutoff) && !in_use.iter().any(|used| used == path))
+}
+
+/// Handle `run_cleanup`; also what the schedule runs
+pub fn run() -> Value {
+    let settings = crate::core::settings::get().maintenance;
+    let now = SystemTime::now();
+    let mut categories = serde_json::Map::new();
+    let mut total = Reclaimed::default();
+    let mut record = |name: &str, reclaimed: Reclaimed| {
+        total.add(reclaimed);
+        categories.insert(name.to_string(), json!(reclaimed));
+    };
+
+    if settings.clean_logs {
+        record("logs", clean_logs(&crate::paths::log_dir(), DAY * settings.log_retention_days.max(1), now));
+    }
+    if settings.clean_crash_reports {
+        record("crashReports", clean_crash_reports(&crate::paths::crash_dir(), DAY * settings.crash_retention_days.max(1), now));
+    }
+    if settings.clean_part_files {
+        let in_use = crate::core::downloads::staging_files();
+        record("partFiles", clean_part_files(&crate::core::downloads::staging_dir(), &in_use, now));
+    }
+    if settings.clean_temp_files {
+        record("tempFiles", clean_temp_files(&crate::clipboard::temp_dir(), &crate::paths::temp_dir(), *SESSION_START));
+        record("tempMedia", clean_temp_media(&crate::temp_media::dir(), &crate::temp_media::in_use(), now));
+    }
+
+    info!("Cleanup removed {} file(s), {} KiB", total.files, total.bytes / 1024);
+    json!({ "success": true, "files": total.files, "bytes": total.bytes, "categories": categories })
+}
+
+/// Clean up now and then once a day (once per process)
+pub fn start() {
+    static STARTED: Once = Once::new();
+    STARTED.call_once(|| {
+        lazy_static::initialize(&SESSION_START);
+        std::thread::spawn(|| loop {
+            run();
+            std::thread::sleep(DAY);
+        });
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// A directory tree of files with chosen ages, removed on drop
+    struct Fixture {
+        root: PathBuf,
+        now: SystemTime,
+    }
+
+    impl Fixture {
+        fn new() -> Self {
+            let root = std::env::temp_dir().join(format!("miko-maintenance-{}", uuid::Uuid::new_v4().simple()));
+            Self { root, now: SystemTime::now() }
+        }
+
+        /// `name` under the root, `len` bytes long and last written `age` ago
+        fn file(&self, name: &str, len: usize, age: Duration) -> PathBuf {
+            let path = self.root.join(name);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            let file = std::fs::File::create(&path).unwrap();
+            file.set_len(len as u64).unwrap();
+            file.set_modified(self.now - age).unwrap();
+            path
+        }
+
+        /// What is left in `dir`, sorted
+        fn left(&self, dir: &str) -> Vec<String> {
+            let mut names: Vec<String> = std::fs::read_dir(self.root.join(dir))
+                .unwrap()
+                .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+                .collect();
+            names.sort();
+            names
+        }
+    }
+
+    impl Drop for Fixture {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.root);
+        }
+    }
+
+    fn reclaimed(reclaimed: Reclaimed) -> (u64, u64) {
+        (reclaimed.files, reclaimed.bytes)
+    }
+
+    #[test]
+    fn logs_past_retention() {
+        let fixture = Fixture::new();
+        fixture.file("logs/desktop.2026-09-01.log", 100, DAY * 20);
+        fixture.file("logs/CLI.LOG", 10, DAY * 15);
+        fixture.file("logs/desktop.log", 1000, DAY);
+        fixture.file("logs/notes.txt", 1000, DAY * 20);
+        fixture.file("logs/archive/old.log", 1000, DAY * 20);
+
+        let removed = clean_logs(&fixture.root.join("logs"), DAY * 14, fixture.now);
+        assert_eq!(reclaimed(removed), (2, 110));
+        assert_eq!(fixture.left("logs"), ["archive", "desktop.log", "notes.txt"]);
+        assert_eq!(fixture.left("logs/archive"), ["old.log"], "subdirectories are left alone");
+    }
+
+    #[test]
+    fn crash_reports_past_retention() {
+        let fixture = Fixture::new();
+        fixture.file("crashes/2026-08-01.dmp", 4096, DAY * 40);
+        fixture.file("crashes/2026-08-20.txt", 50, DAY * 31);
+        fixture.file("crashes/2026-10-01.dmp", 4096, DAY * 5);
+
+        let removed = clean_crash_reports(&fixture.root.join("crashes"), DAY * 30, fixture.now);
+        assert_eq!(reclaimed(removed), (2, 4146));
+        assert_eq!(fixture.left("crashes"), ["2026-10-01.dmp"]);
+    }
+
+    #[test]
+    fn part_files_nobody_resumes() {
+        let fixture = Fixture::new();
+        fixture.file("staging/abandoned.zip.part", 300, PART_FILE_GRACE + DAY);
+        let resumed = fixture.file("staging/resumed.pdf.part", 300, PART_FILE_GRACE + DAY);
+        fixture.file("staging/recent.mp4.part", 300, DAY);
+        fixture.file("staging/finished.zip", 300, PART_FILE_GRACE + DAY);
+
+        let removed = clean_part_files(&fixture.root.join("staging"), &[resumed], fixture.now);
+        assert_eq!(reclaimed(removed), (1, 300));
+        assert_eq!(fixture.left("staging"), ["finished.zip", "recent.mp4.part", "resumed.pdf.part"]);
+    }
+
+    #[test]
+    fn temp_files_of_earlier_sessions() {
+        let fixture = Fixture::new();
+        let session_start = fixture.now - Duration::from_secs(3600);
+        let before = Duration::from_secs(7200);
+        let during = Duration::from_secs(60);
+        fixture.file("paste/paste-1.png", 20, before);
+        fixture.file("paste/paste-2.png", 20, during);
+        fixture.file("tmp/miko-voice-memo-1.wav", 30, before);
+        fixture.file("tmp/miko-voice-memo-2.wav", 30, during);
+        fixture.file("tmp/miko-voice-memo-3.txt", 30, before);
+        fixture.file("tmp/someone-else.wav", 30, before);
+
+        let removed = clean_temp_files(&fixture.root.join("paste"), &fixture.root.join("tmp"), session_start);
+        assert_eq!(reclaimed(removed), (2, 50));
+        assert_eq!(fixture.left("paste"), ["paste-2.png"]);
+        assert_eq!(fixture.left("tmp"), ["miko-voice-memo-2.wav", "miko-voice-memo-3.txt", "someone-else.wav"]);
+    }
+
+    #[test]
+    fn temp_media_no_draft_refers_to() {
+        let fixture = Fixture::new();
+        fixture.file("media/dropped.jpg", 500, Duration::from_secs(120));
+        let attached = fixture.file("media/attached.jpg", 500, Duration::from_secs(120));
+        fixture.file("media/staging.jpg", 500, Duration::from_secs(5));
+
+        let removed = clean_temp_media(&fixture.root.join("media"), &[attached], fixture.now);
+        assert_eq!(reclaimed(removed), (1, 500));
+        assert_eq!(fixture.left("media"), ["attached.jpg", "staging.jpg"]);
+    }
+
+    #[test]
+    fn missing_directories_reclaim_nothing() {
+        let fixture = Fixture::new();
+        let missing = fixture.root.join("missing");
+        assert_eq!(reclaimed(clean_logs(&missing, DAY, fixture.now)), (0, 0));
+        assert_eq!(reclaimed(clean_temp_files(&missing, &missing, fixture.now)), (0, 0));
+    }
+}
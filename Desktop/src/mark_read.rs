@@ -0,0 +1,216 @@
+//! Marking many threads read at once (`mark_threads_read {threadIds}`),
+//! instead of one request per thread from the page.
+//!
+//! With `read.batch_endpoint` the threads go to the ERP as one `POST
+//! /api/chats/read {threadIds}`; otherwise (or when the ERP turns out not to
+//! have it) as `PATCH /api/chats/{id}/read`, at most [`MAX_IN_FLIGHT`] at a
+//! time. Both go through the `miko://` forwarding. The answer says per thread
+//! whether it worked, and the native unread counts of those that did are
+//! cleared.
+//!
+//! When the window gets focus back, the threads the page reported visible
+//! (`set_visible_threads {threadIds}`) that still count unread are marked
+//! the same way, and `threads-marked-read` is emitted. Focus has to stay for
+//! [`FOCUS_SETTLE`] first, so alt-tabbing through the window sends nothing.
+
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+use http::Response;
+use lazy_static::lazy_static;
+use serde_json::{json, Value};
+use tracing::{debug, info, warn};
+
+use crate::ipc::Upstream;
+
+const BATCH_PATH: &str = "/api/chats/read";
+const MAX_IN_FLIGHT: usize = 4;
+/// Threads per call; the page never shows more
+const MAX_THREADS: usize = 500;
+const FOCUS_SETTLE: Duration = Duration::from_millis(1500);
+
+lazy_static! {
+    /// Threads the page shows, in its order
+    static ref VISIBLE: Mutex<Vec<String>> = Mutex::new(Vec::new());
+}
+
+/// Bumped by every focus change; a pending focus run only goes ahead if it didn't move
+static FOCUS_GENERATION: AtomicU64 = AtomicU64::new(0);
+/// The ERP answered the batch call with 404/405 this session
+static BATCH_UNSUPPORTED: AtomicBool = AtomicBool::new(false);
+
+fn valid_id(id: &str) -> bool {
+    !id.is_empty() && id.len() <= 64 && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// The threads the page shows now
+pub fn set_visible(thread_ids: Vec<String>) {
+    *VISIBLE.lock().unwrap() = thread_ids;
+}
+
+/// Mark `thread_ids` read on the ERP; returns the IPC result payload with a
+/// per-thread success map, and the span of the calls that made it
+pub fn mark(thread_ids: &[String]) -> (Value, Option<Upstream>) {
+    let upstream = Mutex::new(None);
+    let mut ids = BTreeSet::new();
+    let mut results: BTreeMap<String, Result<(), String>> = BTreeMap::new();
+    for id in thread_ids.iter().take(MAX_THREADS) {
+        if valid_id(id) {
+            ids.insert(id.clone());
+        } else {
+            results.insert(id.clone(), Err("invalid thread id".to_string()));
+        }
+    }
+    let ids: Vec<String> = ids.into_iter().collect();
+
+    let batched = !ids.is_empty()
+        && crate::core::settings::get().read.batch_endpoint
+        && !BATCH_UNSUPPORTED.load(Ordering::SeqCst)
+        && match mark_batch(&ids, &upstream) {
+            Some(result) => {
+                results.extend(ids.iter().map(|id| (id.clone(), result.clone())));
+                true
+            }
+            None => false,
+        };
+    if !batched {
+        results.extend(fan_out(&ids, &upstream));
+    }
+
+    let marked: Vec<&String> = results.iter().filter(|(_, r)| r.is_ok()).map(|(id, _)| id).collect();
+    for id in &marked {
+        crate::core::unread::mark_read(id);
+    }
+    info!("Marked {}/{} thread(s) read{}", marked.len(), results.len(), if batched { " in one call" } else { "" });
+
+    let threads: serde_json::Map<String, Value> = results
+        .iter()
+        .map(|(id, result)| {
+            let value = match result {
+                Ok(()) => json!({ "success": true }),
+                Err(e) => json!({ "success": false, "error": e }),
+            };
+            (id.clone(), value)
+        })
+        .collect();
+    let result = json!({
+        "success": results.values().all(|r| r.is_ok()),
+        "batched": batched,
+        "threads": threads,
+    });
+    (result, upstream.into_inner().unwrap())
+}
+
+/// One call for all of `ids`; `None` when the ERP has no batch endpoint
+fn mark_batch(ids: &[String], upstream: &Mutex<Option<Upstream>>) -> Option<Result<(), String>> {
+    let response = send(http::Method::POST, BATCH_PATH, &json!({ "threadIds": ids }), upstream);
+    let status = response.status();
+    if matches!(status, http::StatusCode::NOT_FOUND | http::StatusCode::METHOD_NOT_ALLOWED) {
+        info!("The ERP has no batch read endpoint ({}); marking threads one by one", status);
+        BATCH_UNSUPPORTED.store(true, Ordering::SeqCst);
+        return None;
+    }
+    Some(answer(&response))
+}
+
+/// `PATCH` each thread, [`MAX_IN_FLIGHT`] at a time
+fn fan_out(ids: &[String], upstream: &Mutex<Option<Upstream>>) -> BTreeMap<String, Result<(), String>> {
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new(BTreeMap::new());
+    std::thread::scope(|scope| {
+        for _ in 0..MAX_IN_FLIGHT.min(ids.len()) {
+            scope.spawn(|| {
+                while let Some(id) = ids.get(next.fetch_add(1, Ordering::SeqCst)) {
+                    let path = format!("/api/chats/{}/read", id);
+                    let result = answer(&send(http::Method::PATCH, &path, &json!({}), upstream));
+                    if let Err(e) = &result {
+                        debug!("Marking thread {} read failed: {}", id, e);
+                    }
+                    results.lock().unwrap().insert(id.clone(), result);
+                }
+            });
+        }
+    });
+    results.into_inner().unwrap()
+}
+
+/// Forward one call, widening `upstream` to cover it
+fn send(method: http::Method, path: &str, body: &Value, upstream: &Mutex<Option<Upstream>>) -> Response<Cow<'static, [u8]>> {
+    let response = crate::protocol::send_json(method, path, body);
+    if let Some(span) = Upstream::of(&response) {
+        let mut upstream = upstream.lock().unwrap();
+        *upstream = Some(upstream.map_or(span, |covered| covered.cover(span)));
+    }
+    response
+}
+
+fn answer(response: &Response<Cow<'static, [u8]>>) -> Result<(), String> {
+    if response.status().is_success() {
+        return Ok(());
+    }
+    let body: Value = serde_json::from_slice(response.body()).unwrap_or(Value::Null);
+    // The forwarding envelope, or the ERP's own body
+    let message = body["error"]["message"].as_str().or_else(|| body["error"].as_str());
+    Err(message.map(|m| m.to_string()).unwrap_or_else(|| response.status().to_string()))
+}
+
+/// The main window gained or lost focus
+pub fn focus_changed(focused: bool) {
+    let generation = FOCUS_GENERATION.fetch_add(1, Ordering::SeqCst) + 1;
+    if !focused || !crate::core::settings::get().read.mark_visible_on_focus {
+        return;
+    }
+    std::thread::spawn(move || {
+        std::thread::sleep(FOCUS_SETTLE);
+        if FOCUS_GENERATION.load(Ordering::SeqCst) != generation || !crate::core::state::runtime().auth.signed_in {
+            return;
+        }
+        let unread: Vec<String> = VISIBLE
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|id| crate::core::unread::count(id) > 0)
+            .cloned()
+            .collect();
+        if unread.is_empty() {
+            return;
+        }
+        let (result, _) = mark(&unread);
+        if !result["success"].as_bool().unwrap_or(false) {
+            warn!("Some visible threads could not be marked read");
+        }
+        if let Some(webview) = crate::ipc::handle() {
+            webview.emit("threads-marked-read", &result);
+        }
+        crate::ipc::wake();
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_answer_spans_every_call_it_took() {
+        let _session = crate::testing::session(true);
+        crate::testing::upstream();
+        crate::testing::route("/api/chats/span-", |request| match request.path.as_str() {
+            "/api/chats/span-3/read" => crate::testing::Reply::json(404, json!({ "error": "no such thread" })),
+            _ => crate::testing::Reply::json(200, json!({ "success": true })),
+        });
+
+        let ids: Vec<String> = (1..=3).map(|n| format!("span-{}", n)).collect();
+        let before = crate::ipc::now_ms();
+        let (result, upstream) = mark(&ids);
+        let after = crate::ipc::now_ms();
+        assert_eq!(result["threads"]["span-1"]["success"], true);
+        assert_eq!(result["threads"]["span-3"]["error"], "no such thread");
+        let span = upstream.expect("the calls went out");
+        assert!(before <= span.sent_at && span.sent_at <= span.received_at && span.received_at <= after, "{:?}", span);
+
+        let (_, upstream) = mark(&["not a thread".to_string()]);
+        assert_eq!(upstream, None);
+    }
+}
@@ -0,0 +1,44 @@
+//! Screen sharing (`navigator.mediaDevices.getDisplayMedia`) policy.
+//!
+//! The engines show their own source picker; the platform layers only decide
+//! whether a capture may start. When `media.screen_share_enabled` is off the
+//! page-side API is also replaced so callers fail fast with `NotAllowedError`
+//! instead of waiting on a capture that will be cancelled.
+
+pub fn screen_share_enabled() -> bool {
+    crate::core::settings::get().media.screen_share_enabled
+}
+
+const DISABLED_SCRIPT: &str = r#"
+(function () {
+    if (!navigator.mediaDevices) return;
+    navigator.mediaDevices.getDisplayMedia = function () {
+        return Promise.reject(new DOMException('Screen sharing is disabled by your administrator', 'NotAllowedError'));
+    };
+})();
+"#;
+
+/// Asks the host for OS-level capture access (the macOS TCC prompt) the first
+/// time the page starts a capture, then continues with the real call
+#[cfg(target_os = "macos")]
+const ACCESS_SCRIPT: &str = r#"
+(function () {
+    if (!navigator.mediaDevices || !navigator.mediaDevices.getDisplayMedia) return;
+    const original = navigator.mediaDevices.getDisplayMedia.bind(navigator.mediaDevices);
+    navigator.mediaDevices.getDisplayMedia = function (constraints) {
+        window.ipc && window.ipc.postMessage(JSON.stringify({ type: 'screen_capture_access' }));
+        return original(constraints);
+    };
+})();
+"#;
+
+/// Initialization script matching the current setting, if one is needed
+pub fn init_script() -> Option<&'static str> {
+    if !screen_share_enabled() {
+        return Some(DISABLED_SCRIPT);
+    }
+    #[cfg(target_os = "macos")]
+    return Some(ACCESS_SCRIPT);
+    #[cfg(not(target_os = "macos"))]
+    None
+}
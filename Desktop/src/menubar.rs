@@ -2,22 +2,24 @@ use windows::Win32::{
     Foundation::HWND,
     UI::WindowsAndMessaging::*,
     Graphics::Dwm::*,
-    System::Com::*,
 };
 use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
 use lazy_static::lazy_static;
+use tracing::{error, info, warn};
 
 // Global storage for menu items and pending commands
 lazy_static! {
     static ref GLOBAL_MENU_ITEMS: Arc<Mutex<HashMap<u16, String>>> = Arc::new(Mutex::new(HashMap::new()));
     static ref PENDING_MENU_COMMAND: Arc<Mutex<Option<u16>>> = Arc::new(Mutex::new(None));
+    /// Raw `HACCEL` built from the shortcut map; 0 until the menu is attached
+    static ref ACCELERATORS: Mutex<isize> = Mutex::new(0);
 }
 
 pub fn store_menu_items_globally(items: HashMap<u16, String>) {
     let mut global_items = GLOBAL_MENU_ITEMS.lock().unwrap();
     *global_items = items;
-    println!("📋 Stored {} menu items globally", global_items.len());
+    info!("Stored {} menu items globally", global_items.len());
 }
 
 pub fn get_menu_action(command_id: u16) -> Option<String> {
@@ -28,7 +30,7 @@ pub fn get_menu_action(command_id: u16) -> Option<String> {
 pub fn set_pending_menu_command(command_id: u16) {
     let mut pending = PENDING_MENU_COMMAND.lock().unwrap();
     *pending = Some(command_id);
-    println!("📋 Set pending menu command: {}", command_id);
+    info!("Set pending menu command: {}", command_id);
 }
 
 pub fn get_and_clear_pending_menu_command() -> Option<u16> {
@@ -81,18 +83,49 @@ impl MenuBar {
             
             // Store the menu items globally for the message handler
             store_menu_items_globally(self.menu_items.clone());
+            install_accelerators(&self.menu_items);
             
             Ok(())
         }
     }
 
+    /// Relabel the shortcut items and rebuild the accelerator table after
+    /// the shortcut map changed
+    pub fn apply_shortcuts(&self) {
+        for (id, action) in self.menu_items.iter().filter(|(_, a)| crate::shortcuts::DEFAULTS.iter().any(|(d, _, _)| *d == a.as_str())) {
+            let text_wide: Vec<u16> = crate::shortcuts::menu_text(action).encode_utf16().chain(std::iter::once(0)).collect();
+            unsafe {
+                if let Err(e) = ModifyMenuW(
+                    self.menu_handle,
+                    *id as u32,
+                    MF_BYCOMMAND | MF_STRING,
+                    *id as usize,
+                    windows::core::PCWSTR(text_wide.as_ptr()),
+                ) {
+                    warn!("Failed to relabel menu item {}: {}", action, e);
+                }
+            }
+        }
+        install_accelerators(&self.menu_items);
+    }
+
+    /// Show or clear the checkmark of every item bound to `action`
+    pub fn set_checked(&self, action: &str, checked: bool) {
+        let state = if checked { MF_CHECKED } else { MF_UNCHECKED };
+        for (id, _) in self.menu_items.iter().filter(|(_, a)| a.as_str() == action) {
+            unsafe {
+                CheckMenuItem(self.menu_handle, *id as u32, (MF_BYCOMMAND | state).0);
+            }
+        }
+    }
+
     pub fn get_menu_items(&self) -> &HashMap<u16, String> {
         &self.menu_items
     }
 
     pub fn handle_menu_command(&self, command_id: u16, hwnd: HWND) -> Result<(), Box<dyn std::error::Error>> {
         if let Some(action) = self.menu_items.get(&command_id) {
-            println!("📋 Menu action triggered: {}", action);
+            info!("Menu action triggered: {}", action);
             
             match action.as_str() {
                 "check_updates" => {
@@ -107,7 +140,7 @@ impl MenuBar {
                     }
                 }
                 _ => {
-                    println!("⚠️ Unhandled menu action: {}", action);
+                    warn!("Unhandled menu action: {}", action);
                 }
             }
         }
@@ -141,6 +174,11 @@ impl<'a> SubMenu<'a> {
         }
     }
 
+    /// Add a rebindable command, labelled with its current accelerator
+    pub fn add_shortcut_item(&mut self, action: &str) -> Result<u16, Box<dyn std::error::Error>> {
+        self.add_item(&crate::shortcuts::menu_text(action), action)
+    }
+
     pub fn add_separator(&mut self) -> Result<(), Box<dyn std::error::Error>> {
         unsafe {
             AppendMenuW(self.handle, MF_SEPARATOR, 0, windows::core::PCWSTR::null())?;
@@ -210,7 +248,7 @@ pub fn apply_modern_menu_theme(hwnd: HWND) -> Result<(), Box<dyn std::error::Err
             SWP_NOMOVE | SWP_NOSIZE | SWP_NOZORDER | SWP_FRAMECHANGED,
         );
 
-        println!("✅ Modern dark mode styling applied with window animations enabled");
+        info!("Modern dark mode styling applied with window animations enabled");
         Ok(())
     }
 }
@@ -227,7 +265,7 @@ fn enable_dark_mode_for_app() -> Result<(), Box<dyn std::error::Error>> {
     // The actual implementation may vary based on Windows version
     
     // For now, we'll rely on DWM attributes which are documented
-    println!("Dark mode preference set for application");
+    info!("Dark mode preference set for application");
     Ok(())
 }
 
@@ -274,13 +312,35 @@ pub fn is_system_dark_mode() -> bool {
     }
 }
 
+/// Hand the frame and menu colors back to the system (high contrast)
+pub fn clear_menu_colors(hwnd: HWND) -> Result<(), Box<dyn std::error::Error>> {
+    unsafe {
+        let dark_mode_value: i32 = 0; // FALSE
+        let _ = DwmSetWindowAttribute(
+            hwnd,
+            DWMWA_USE_IMMERSIVE_DARK_MODE,
+            &dark_mode_value as *const _ as *const std::ffi::c_void,
+            std::mem::size_of::<i32>() as u32,
+        );
+        let _ = SetWindowPos(
+            hwnd,
+            HWND::default(),
+            0, 0, 0, 0,
+            SWP_NOMOVE | SWP_NOSIZE | SWP_NOZORDER | SWP_FRAMECHANGED,
+        );
+        let _ = DrawMenuBar(hwnd);
+        info!("High contrast: system frame and menu colors restored");
+        Ok(())
+    }
+}
+
 // Apply theme-aware colors to menu items using modern DWM (with window effects)
 pub fn apply_menu_colors(hwnd: HWND) -> Result<(), Box<dyn std::error::Error>> {
     unsafe {
         let is_dark = is_system_dark_mode();
         
         if is_dark {
-            println!("System is using dark mode - applying modern dark theme");
+            info!("System is using dark mode - applying modern dark theme");
             
             // Enable dark mode for this specific window using modern DWM API
             let dark_mode_value: i32 = 1; // TRUE
@@ -291,7 +351,7 @@ pub fn apply_menu_colors(hwnd: HWND) -> Result<(), Box<dyn std::error::Error>> {
                 std::mem::size_of::<i32>() as u32,
             );
         } else {
-            println!("System is using light mode - applying modern light theme");
+            info!("System is using light mode - applying modern light theme");
             
             // Disable dark mode for light theme
             let light_mode_value: i32 = 0; // FALSE
@@ -324,22 +384,129 @@ pub fn apply_menu_colors(hwnd: HWND) -> Result<(), Box<dyn std::error::Error>> {
     }
 }
 
+/// Build the accelerator table for the menu commands in the shortcut map,
+/// replacing the previous one
+fn install_accelerators(menu_items: &HashMap<u16, String>) {
+    let mut accels = Vec::new();
+    for shortcut in crate::shortcuts::current() {
+        let Some(text) = &shortcut.accelerator else { continue };
+        let Some(accelerator) = crate::shortcuts::Accelerator::parse(text).ok() else { continue };
+        let Some(key) = virtual_key(&accelerator.key) else {
+            warn!("No virtual key for {}", text);
+            continue;
+        };
+        let mut flags = FVIRTKEY;
+        if accelerator.ctrl {
+            flags |= FCONTROL;
+        }
+        if accelerator.alt {
+            flags |= FALT;
+        }
+        if accelerator.shift {
+            flags |= FSHIFT;
+        }
+        for (id, _) in menu_items.iter().filter(|(_, a)| **a == shortcut.action) {
+            accels.push(ACCEL { fVirt: flags, key, cmd: *id });
+        }
+    }
+
+    let table = match unsafe { CreateAcceleratorTableW(&accels) } {
+        Ok(table) => table.0 as isize,
+        Err(e) => {
+            error!("Failed to create accelerator table: {}", e);
+            0
+        }
+    };
+    let previous = std::mem::replace(&mut *ACCELERATORS.lock().unwrap(), table);
+    if previous != 0 {
+        unsafe {
+            let _ = DestroyAcceleratorTable(HACCEL(previous as *mut std::ffi::c_void));
+        }
+    }
+    info!("Installed {} keyboard accelerators", accels.len());
+}
+
+/// Run a key press through the accelerator table. A match sends `WM_COMMAND`
+/// to `hwnd`, which the menu hook picks up like a menu click.
+pub fn translate_accelerator(hwnd: HWND, message: u32, virtual_key: u32, lparam: isize) -> bool {
+    let table = *ACCELERATORS.lock().unwrap();
+    if table == 0 {
+        return false;
+    }
+    let msg = MSG {
+        hwnd,
+        message,
+        wParam: windows::Win32::Foundation::WPARAM(virtual_key as usize),
+        lParam: windows::Win32::Foundation::LPARAM(lparam),
+        ..Default::default()
+    };
+    unsafe { TranslateAcceleratorW(hwnd, HACCEL(table as *mut std::ffi::c_void), &msg) != 0 }
+}
+
+/// Win32 virtual-key code for a normalized [`crate::shortcuts::Accelerator`] key
+fn virtual_key(key: &str) -> Option<u16> {
+    use windows::Win32::UI::Input::KeyboardAndMouse::*;
+
+    let mut chars = key.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        let vk = match c {
+            'A'..='Z' | '0'..='9' => return Some(c as u16),
+            ',' => VK_OEM_COMMA,
+            '-' => VK_OEM_MINUS,
+            '+' => VK_OEM_PLUS,
+            '.' => VK_OEM_PERIOD,
+            '/' => VK_OEM_2,
+            ';' => VK_OEM_1,
+            '\'' => VK_OEM_7,
+            '[' => VK_OEM_4,
+            ']' => VK_OEM_6,
+            '\\' => VK_OEM_5,
+            '`' => VK_OEM_3,
+            _ => return None,
+        };
+        return Some(vk.0);
+    }
+    if let Some(n) = key.strip_prefix('F').and_then(|n| n.parse::<u16>().ok()) {
+        return Some(VK_F1.0 + n - 1);
+    }
+    let vk = match key {
+        "Enter" => VK_RETURN,
+        "Tab" => VK_TAB,
+        "Space" => VK_SPACE,
+        "Backspace" => VK_BACK,
+        "Delete" => VK_DELETE,
+        "Insert" => VK_INSERT,
+        "Home" => VK_HOME,
+        "End" => VK_END,
+        "PageUp" => VK_PRIOR,
+        "PageDown" => VK_NEXT,
+        "Up" => VK_UP,
+        "Down" => VK_DOWN,
+        "Left" => VK_LEFT,
+        "Right" => VK_RIGHT,
+        _ => return None,
+    };
+    Some(vk.0)
+}
+
 // Enhanced menu creation with modern items and dark mode support
 pub fn create_app_menubar() -> Result<MenuBar, Box<dyn std::error::Error>> {
     let mut menubar = MenuBar::new()?;
 
     // File Menu
     let mut file_menu = menubar.add_menu("File")?;
-    file_menu.add_item("New Chat\tCtrl+N", "new_chat")?;
-    file_menu.add_item("New Window\tCtrl+Shift+N", "new_window")?;
+    file_menu.add_shortcut_item("new_chat")?;
+    file_menu.add_shortcut_item("new_window")?;
     file_menu.add_separator()?;
-    file_menu.add_item("Open Workspace\tCtrl+O", "open_workspace")?;
+    file_menu.add_shortcut_item("open_workspace")?;
     file_menu.add_item("Recent Workspaces", "recent_workspaces")?;
     file_menu.add_separator()?;
     file_menu.add_item("Import Chat History", "import_history")?;
     file_menu.add_item("Export Chat History", "export_history")?;
     file_menu.add_separator()?;
-    file_menu.add_item("Settings\tCtrl+,", "settings")?;
+    file_menu.add_shortcut_item("print_page")?;
+    file_menu.add_separator()?;
+    file_menu.add_shortcut_item("settings")?;
     file_menu.add_separator()?;
     file_menu.add_item("Exit\tAlt+F4", "exit")?;
 
@@ -355,11 +522,20 @@ pub fn create_app_menubar() -> Result<MenuBar, Box<dyn std::error::Error>> {
     edit_menu.add_separator()?;
     edit_menu.add_item("Find\tCtrl+F", "find")?;
     edit_menu.add_item("Find and Replace\tCtrl+H", "find_replace")?;
+    edit_menu.add_separator()?;
+
+    // Spelling submenu; checkmarks are kept in sync by `update_spelling_menu`
+    let mut spelling_menu = edit_menu.add_submenu("Spelling")?;
+    spelling_menu.add_item("Check Spelling While Typing", "spelling_toggle")?;
+    spelling_menu.add_separator()?;
+    for (tag, name) in crate::spellcheck::MENU_LANGUAGES {
+        spelling_menu.add_item(name, &format!("spelling_lang:{}", tag))?;
+    }
 
     // View Menu
     let mut view_menu = menubar.add_menu("View")?;
-    view_menu.add_item("Toggle Sidebar\tCtrl+B", "toggle_sidebar")?;
-    view_menu.add_item("Toggle Chat List\tCtrl+1", "toggle_chat_list")?;
+    view_menu.add_shortcut_item("toggle_sidebar")?;
+    view_menu.add_shortcut_item("toggle_chat_list")?;
     view_menu.add_item("Toggle DevTools\tF12", "toggle_devtools")?;
     view_menu.add_separator()?;
     
@@ -370,27 +546,37 @@ pub fn create_app_menubar() -> Result<MenuBar, Box<dyn std::error::Error>> {
     zoom_menu.add_item("Reset Zoom\tCtrl+0", "reset_zoom")?;
     
     view_menu.add_separator()?;
-    view_menu.add_item("Full Screen\tF11", "fullscreen")?;
+    view_menu.add_shortcut_item("fullscreen")?;
     view_menu.add_item("Always on Top", "always_on_top")?;
 
+    let mut layout_menu = view_menu.add_submenu("Window Layout")?;
+    for (preset, _) in crate::window_layout::PRESETS {
+        layout_menu.add_shortcut_item(&format!("snap:{}", preset))?;
+    }
+    layout_menu.add_separator()?;
+    layout_menu.add_item("Save Current Layout", "save_window_layout")?;
+
     // Tools Menu
     let mut tools_menu = menubar.add_menu("Tools")?;
     tools_menu.add_item("Clear Chat History", "clear_history")?;
     tools_menu.add_item("Reset Application", "reset_app")?;
     tools_menu.add_separator()?;
+    tools_menu.add_item("Block Screen Capture", "block_capture")?;
+    tools_menu.add_separator()?;
     tools_menu.add_item("Network Diagnostics", "network_diagnostics")?;
     tools_menu.add_item("Performance Monitor", "performance_monitor")?;
 
     // Help Menu
     let mut help_menu = menubar.add_menu("Help")?;
     help_menu.add_item("Getting Started", "getting_started")?;
-    help_menu.add_item("Keyboard Shortcuts\tCtrl+/", "shortcuts")?;
+    help_menu.add_shortcut_item("shortcuts")?;
     help_menu.add_separator()?;
     help_menu.add_item("Documentation", "documentation")?;
     help_menu.add_item("Community Forum", "community")?;
     help_menu.add_separator()?;
     help_menu.add_item("Report Issue", "report_issue")?;
     help_menu.add_item("Send Feedback", "send_feedback")?;
+    help_menu.add_item("Export Diagnostic Logs…", "export_logs")?;
     help_menu.add_separator()?;
     help_menu.add_item("Check for Updates", "check_updates")?;
     help_menu.add_item("About Workspace", "about")?;
@@ -398,6 +584,14 @@ pub fn create_app_menubar() -> Result<MenuBar, Box<dyn std::error::Error>> {
     Ok(menubar)
 }
 
+/// Reflect the spellcheck settings in the Edit > Spelling checkmarks
+pub fn update_spelling_menu(menubar: &MenuBar, spellcheck: &crate::core::settings::SpellcheckSettings) {
+    menubar.set_checked("spelling_toggle", spellcheck.enabled);
+    for (tag, _) in crate::spellcheck::MENU_LANGUAGES {
+        menubar.set_checked(&format!("spelling_lang:{}", tag), spellcheck.languages.iter().any(|l| l == tag));
+    }
+}
+
 // Enable window animations and effects
 pub fn enable_window_animations(hwnd: HWND) -> Result<(), Box<dyn std::error::Error>> {
     unsafe {
@@ -412,75 +606,53 @@ pub fn enable_window_animations(hwnd: HWND) -> Result<(), Box<dyn std::error::Er
 
         // Enable window animations using AnimateWindow for show/hide
         // This will be called when showing/hiding the window
-        println!("✅ Window animations and transitions enabled");
+        info!("Window animations and transitions enabled");
         Ok(())
     }
 }
 
-// Windows Task Dialog for Check Updates
-pub fn show_check_updates_dialog(hwnd: HWND) -> Result<(), Box<dyn std::error::Error>> {
+// Network diagnostics dialog
+pub fn show_network_diagnostics_dialog(hwnd: HWND) -> Result<(), Box<dyn std::error::Error>> {
+    let connectivity = crate::core::state::runtime().connectivity;
+    crate::core::network::recheck();
+
     unsafe {
-        // Initialize COM for task dialogs
-        let _ = CoInitializeEx(None, COINIT_APARTMENTTHREADED);
-        
-        // Create task dialog configuration
-        let title = "Check for Updates";
-        let main_instruction = "Checking for updates...";
-        let content = "Miko Workspace is checking for available updates.\n\nCurrent Version: 0.1.0\n\nThis feature will connect to the update server to check for the latest version.";
-        
-        // Convert strings to wide strings
-        let title_wide: Vec<u16> = title.encode_utf16().chain(std::iter::once(0)).collect();
-        let instruction_wide: Vec<u16> = main_instruction.encode_utf16().chain(std::iter::once(0)).collect();
-        let content_wide: Vec<u16> = content.encode_utf16().chain(std::iter::once(0)).collect();
-        
-        let button_text = "Check Now";
-        let button_text_wide: Vec<u16> = button_text.encode_utf16().chain(std::iter::once(0)).collect();
-        
-        // Show message box as task dialog alternative (simpler approach)
+        let title = "Network Diagnostics";
         let message = format!(
-            "{}\n\n{}\n\nWould you like to check for updates now?",
-            main_instruction, content
+            "Current assessment: {}\n\nThe connection is checked again whenever the network changes and every 30 seconds.",
+            connectivity.label()
         );
+
+        let title_wide: Vec<u16> = title.encode_utf16().chain(std::iter::once(0)).collect();
         let message_wide: Vec<u16> = message.encode_utf16().chain(std::iter::once(0)).collect();
-        
-        let result = MessageBoxW(
+
+        MessageBoxW(
             hwnd,
             windows::core::PCWSTR(message_wide.as_ptr()),
             windows::core::PCWSTR(title_wide.as_ptr()),
-            MB_YESNO | MB_ICONINFORMATION,
+            MB_OK | MB_ICONINFORMATION | MB_SETFOREGROUND,
         );
-        
-        if result == IDYES {
-            println!("✅ User clicked 'Yes' to check for updates");
-            
-            // Show checking dialog immediately
-            let checking_message = "Checking for updates...\n\nPlease wait while we connect to the update server.";
-            let checking_message_wide: Vec<u16> = checking_message.encode_utf16().chain(std::iter::once(0)).collect();
-            let checking_title = "Update Check";
-            let checking_title_wide: Vec<u16> = checking_title.encode_utf16().chain(std::iter::once(0)).collect();
-            
-            // Simulate checking (in real app, this would be async)
-            std::thread::sleep(std::time::Duration::from_millis(500));
-            
-            // Show result dialog
-            let result_message = "You are using the latest version of Miko Workspace.\n\nVersion: 0.1.0\n\nNo updates are available at this time.";
-            let result_message_wide: Vec<u16> = result_message.encode_utf16().chain(std::iter::once(0)).collect();
-            let result_title = "Update Check Complete";
-            let result_title_wide: Vec<u16> = result_title.encode_utf16().chain(std::iter::once(0)).collect();
-            
-            MessageBoxW(
-                hwnd,
-                windows::core::PCWSTR(result_message_wide.as_ptr()),
-                windows::core::PCWSTR(result_title_wide.as_ptr()),
-                MB_OK | MB_ICONINFORMATION,
-            );
-            
-            println!("✅ Update check completed");
-        } else {
-            println!("❌ User cancelled update check");
-        }
-        
-        CoUninitialize();
+
+        Ok(())
+    }
+}
+
+// Keyboard shortcuts dialog
+pub fn show_shortcuts_dialog(hwnd: HWND) -> Result<(), Box<dyn std::error::Error>> {
+    unsafe {
+        let title = "Keyboard Shortcuts";
+        let message = crate::shortcuts::describe();
+
+        let title_wide: Vec<u16> = title.encode_utf16().chain(std::iter::once(0)).collect();
+        let message_wide: Vec<u16> = message.encode_utf16().chain(std::iter::once(0)).collect();
+
+        MessageBoxW(
+            hwnd,
+            windows::core::PCWSTR(message_wide.as_ptr()),
+            windows::core::PCWSTR(title_wide.as_ptr()),
+            MB_OK | MB_ICONINFORMATION | MB_SETFOREGROUND,
+        );
+
         Ok(())
     }
 }
@@ -498,7 +670,7 @@ pub fn show_about_dialog(hwnd: HWND) -> Result<(), Box<dyn std::error::Error>> {
             hwnd,
             windows::core::PCWSTR(message_wide.as_ptr()),
             windows::core::PCWSTR(title_wide.as_ptr()),
-            MB_OK | MB_ICONINFORMATION,
+            MB_OK | MB_ICONINFORMATION | MB_SETFOREGROUND,
         );
         
         Ok(())
@@ -0,0 +1,101 @@
+//! Large transfers on metered connections (phone hotspots, capped plans).
+//!
+//! Windows rates each connection's cost; the network monitor keeps it as the
+//! `isMetered` flag of the `connectivity` topic. While it's set, a download
+//! or update asks first: "download anyway" lets everything through until the
+//! connection changes, "wait for Wi-Fi" holds it back until the connection is
+//! no longer metered (downloads are listed as `waiting_for_unmetered` in the
+//! transfers meanwhile, and resuming one starts it anyway). Prefetch is simply
+//! skipped. `metered_transfers` can make them `wait` without asking, or
+//! `ignore` the cost altogether.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use lazy_static::lazy_static;
+use tracing::info;
+
+use crate::core::settings::{self, MeteredPolicy};
+
+type Waiting = Box<dyn FnOnce() + Send>;
+
+lazy_static! {
+    /// Run once the connection is no longer metered
+    static ref WAITING: Mutex<Vec<Waiting>> = Mutex::new(Vec::new());
+}
+
+/// "Download anyway" was chosen on the current metered connection
+static ALLOWED: AtomicBool = AtomicBool::new(false);
+
+pub fn is_metered() -> bool {
+    crate::core::state::runtime().metered
+}
+
+/// Large transfers should hold back right now
+pub fn restricted() -> bool {
+    is_metered() && settings::get().metered_transfers != MeteredPolicy::Ignore
+}
+
+/// Whether `what` ("report.pdf", "The update") may download now; asks the
+/// user under [`MeteredPolicy::Ask`]. When not, the caller hands its start to
+/// [`when_unmetered`].
+pub fn allow(what: &str) -> bool {
+    if !restricted() || ALLOWED.load(Ordering::SeqCst) {
+        return true;
+    }
+    if settings::get().metered_transfers == MeteredPolicy::Wait {
+        info!("Holding back {} on a metered connection", what);
+        return false;
+    }
+    let allowed = ask(what);
+    if allowed {
+        ALLOWED.store(true, Ordering::SeqCst);
+    }
+    info!("{} on a metered connection: {}", what, if allowed { "downloading anyway" } else { "waiting for Wi-Fi" });
+    allowed
+}
+
+/// Run `start` once the connection is no longer metered (right away if it isn't)
+pub fn when_unmetered(start: impl FnOnce() + Send + 'static) {
+    if !is_metered() {
+        std::thread::spawn(start);
+        return;
+    }
+    WAITING.lock().unwrap().push(Box::new(start));
+}
+
+/// From the network monitor when the connection's cost changes
+pub fn changed(metered: bool) {
+    ALLOWED.store(false, Ordering::SeqCst);
+    if metered {
+        return;
+    }
+    let waiting = std::mem::take(&mut *WAITING.lock().unwrap());
+    if !waiting.is_empty() {
+        info!("Starting {} transfer(s) held back on a metered connection", waiting.len());
+    }
+    for start in waiting {
+        std::thread::spawn(start);
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn ask(what: &str) -> bool {
+    let message = format!(
+        "You're on a metered connection (a phone hotspot or capped plan). {} may use a lot of data.\n\n\
+         Yes: download anyway.\n\
+         No: wait for Wi-Fi; it starts once the connection isn't metered.",
+        what
+    );
+    crate::platform::win::utils::confirm("Metered connection", &message)
+}
+
+#[cfg(target_os = "macos")]
+fn ask(what: &str) -> bool {
+    let message = format!("You're on a metered connection. {} may use a lot of data.", what);
+    crate::platform::mac::utils::confirm("Metered connection", &message, "Download Anyway", "Wait for Wi-Fi")
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+fn ask(_what: &str) -> bool {
+    true
+}
@@ -0,0 +1,149 @@
+//! The sound native notifications make, picked per account.
+//!
+//! A sound is `none`, `default` (the OS notification sound, which Windows
+//! toasts play themselves) or one of the short clips bundled in the binary.
+//! The signed-in account's pick is kept under its user id in
+//! `notifications.account_sounds`; without one, `notifications.sound` applies.
+//! It's read for every notification, so a change applies to the next one.
+//! Notifications stay quiet while the user is on do-not-disturb or in quiet
+//! hours; a preview plays regardless, as the user asked for it.
+
+use serde_json::{json, Value};
+
+use crate::core::settings;
+
+pub const NONE: &str = "none";
+pub const DEFAULT: &str = "default";
+
+/// Name, label in the picker, 16-bit PCM WAV
+const BUNDLED: &[(&str, &str, &[u8])] = &[
+    ("chime", "Chime", include_bytes!("../../Library/Shared/Sounds/chime.wav")),
+    ("pop", "Pop", include_bytes!("../../Library/Shared/Sounds/pop.wav")),
+    ("bell", "Bell", include_bytes!("../../Library/Shared/Sounds/bell.wav")),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sound {
+    Silent,
+    System,
+    Bundled(&'static [u8]),
+}
+
+fn resolve(name: &str) -> Option<Sound> {
+    match name {
+        NONE => Some(Sound::Silent),
+        DEFAULT => Some(Sound::System),
+        _ => BUNDLED.iter().find(|(n, _, _)| *n == name).map(|(_, _, wav)| Sound::Bundled(wav)),
+    }
+}
+
+fn current_account() -> Option<String> {
+    let state = crate::core::state::runtime();
+    state.auth.signed_in.then(|| state.auth.user_id.clone()).flatten()
+}
+
+/// The signed-in account's pick, else the app-wide one
+pub fn current() -> String {
+    let notifications = settings::get().notifications;
+    current_account()
+        .and_then(|account| notifications.account_sounds.get(&account).cloned())
+        .unwrap_or(notifications.sound)
+}
+
+/// What a notification shown now should sound like
+pub fn for_notification() -> Sound {
+    if crate::quiet_hours::silenced() {
+        return Sound::Silent;
+    }
+    // A pick from a newer build that's gone now falls back to the OS sound
+    resolve(&current()).unwrap_or(Sound::System)
+}
+
+/// Handle `list_notification_sounds`
+pub fn list() -> Value {
+    let mut sounds = vec![json!({ "name": NONE, "label": "None" }), json!({ "name": DEFAULT, "label": "System default" })];
+    sounds.extend(BUNDLED.iter().map(|(name, label, _)| json!({ "name": name, "label": label })));
+    json!({ "sounds": sounds, "current": current() })
+}
+
+/// Handle `set_notification_sound`: for the signed-in account, or for
+/// everyone while signed out
+pub fn set(name: &str) -> Result<(), String> {
+    if resolve(name).is_none() {
+        return Err(format!("unknown sound: {}", name));
+    }
+    let account = current_account();
+    settings::update(|s| match account {
+        Some(account) => {
+            s.notifications.account_sounds.insert(account, name.to_string());
+        }
+        None => s.notifications.sound = name.to_string(),
+    })
+    .map_err(|e| e.to_string())
+}
+
+/// Handle `preview_notification_sound`
+pub fn preview(name: &str) -> Result<(), String> {
+    let sound = resolve(name).ok_or_else(|| format!("unknown sound: {}", name))?;
+    play(sound);
+    Ok(())
+}
+
+/// Start `sound` without waiting for it to finish
+#[cfg(target_os = "windows")]
+pub fn play(sound: Sound) {
+    use windows::core::{w, PCWSTR};
+    use windows::Win32::Foundation::HMODULE;
+    use windows::Win32::Media::Audio::{PlaySoundW, SND_ALIAS, SND_ASYNC, SND_MEMORY, SND_NODEFAULT};
+
+    // SAFETY: bundled clips are 'static, so they outlive the asynchronous playback
+    let played = unsafe {
+        match sound {
+            Sound::Silent => return,
+            Sound::System => PlaySoundW(w!("Notification.Default"), HMODULE::default(), SND_ALIAS | SND_ASYNC | SND_NODEFAULT),
+            Sound::Bundled(wav) => PlaySoundW(PCWSTR(wav.as_ptr().cast()), HMODULE::default(), SND_MEMORY | SND_ASYNC | SND_NODEFAULT),
+        }
+    };
+    if !played.as_bool() {
+        tracing::warn!("Couldn't play notification sound");
+    }
+}
+
+/// Start `sound` without waiting for it to finish
+#[cfg(target_os = "macos")]
+pub fn play(sound: Sound) {
+    use cocoa::base::{id, nil, BOOL};
+    use cocoa::foundation::{NSAutoreleasePool, NSString};
+    use objc::{class, msg_send, sel, sel_impl};
+    use std::sync::Mutex;
+
+    /// The playing NSSound; it stops once released, so it's kept until the next one
+    static PLAYING: Mutex<usize> = Mutex::new(0);
+
+    unsafe {
+        let sound: id = match sound {
+            Sound::Silent => return,
+            Sound::System => {
+                let named: id = msg_send![class!(NSSound), soundNamed: NSString::alloc(nil).init_str("Glass").autorelease()];
+                msg_send![named, retain]
+            }
+            Sound::Bundled(wav) => {
+                let data: id = msg_send![class!(NSData), dataWithBytes: wav.as_ptr() length: wav.len()];
+                let sound: id = msg_send![class!(NSSound), alloc];
+                msg_send![sound, initWithData: data]
+            }
+        };
+        if sound == nil {
+            tracing::warn!("Couldn't play notification sound");
+            return;
+        }
+        let _: BOOL = msg_send![sound, play];
+        let previous = std::mem::replace(&mut *PLAYING.lock().unwrap(), sound as usize);
+        if previous != 0 {
+            let _: () = msg_send![previous as id, release];
+        }
+    }
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+pub fn play(_sound: Sound) {}
@@ -0,0 +1,255 @@
+//! Where the app keeps things on disk, for every binary and every feature.
+//!
+//! Everything goes in one data directory: under `%LOCALAPPDATA%` (or the
+//! platform's equivalent) normally, or in portable mode (`--portable`, or a
+//! [`PORTABLE_MARKER`] file next to the executable) in `data` next to the
+//! executable, for running from a USB stick or network share on machines
+//! where the profile folder is redirected or slow. A `--profile` gets its
+//! own directory under `profiles/<name>` of either. Features join their
+//! file names onto [`data_dir`]; nothing else decides a location.
+//!
+//! In portable mode scratch files ([`temp_dir`]) and the WebView2 profile
+//! stay in the data directory too, and a configured `webview2_user_data_dir`
+//! is ignored, so nothing is left on the machine. Directories are created by
+//! whoever writes to them first.
+
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+/// Set from `--profile`; child processes inherit it
+pub const PROFILE_ENV: &str = "MIKO_PROFILE";
+/// Set from `--portable`; child processes inherit it
+pub const PORTABLE_ENV: &str = "MIKO_PORTABLE";
+/// Next to the executable, turns on portable mode without the flag
+pub const PORTABLE_MARKER: &str = "portable.marker";
+/// The data directory's name under the platform's local app data
+const APP_DIR: &str = "MikoWorkspace";
+
+/// The data directory and whether it is the portable one
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Locations {
+    root: PathBuf,
+    portable: bool,
+}
+
+impl Locations {
+    /// `exe_dir` is the executable's folder, `flagged` whether `--portable`
+    /// was given, `local_data` the platform's local app data
+    pub fn resolve(exe_dir: Option<&Path>, flagged: bool, profile: Option<&str>, local_data: Option<PathBuf>) -> Self {
+        let portable = exe_dir.filter(|dir| flagged || dir.join(PORTABLE_MARKER).is_file());
+        let (base, portable) = match portable {
+            Some(exe_dir) => (exe_dir.join("data"), true),
+            None => (local_data.unwrap_or_else(std::env::temp_dir).join(APP_DIR), false),
+        };
+        let root = match profile {
+            Some(profile) => base.join("profiles").join(profile),
+            None => base,
+        };
+        Self { root, portable }
+    }
+
+    pub fn data_dir(&self) -> PathBuf {
+        self.root.clone()
+    }
+
+    pub fn log_dir(&self) -> PathBuf {
+        self.root.join("logs")
+    }
+
+    pub fn crash_dir(&self) -> PathBuf {
+        self.root.join("crashes")
+    }
+
+    pub fn temp_dir(&self) -> PathBuf {
+        if self.portable {
+            self.root.join("temp")
+        } else {
+            std::env::temp_dir()
+        }
+    }
+
+    /// `configured` is the `webview2_user_data_dir` setting
+    pub fn webview_user_data_dir(&self, configured: Option<&Path>) -> PathBuf {
+        match configured {
+            Some(dir) if !self.portable => dir.to_path_buf(),
+            _ => self.root.join("WebView2"),
+        }
+    }
+}
+
+/// The locations of this process, decided on first use: after the command
+/// line was parsed (see `cli::parse`)
+pub fn current() -> &'static Locations {
+    static CURRENT: OnceLock<Locations> = OnceLock::new();
+    CURRENT.get_or_init(locate)
+}
+
+#[cfg(not(test))]
+fn locate() -> Locations {
+    let exe = std::env::current_exe().ok();
+    let flagged = std::env::var(PORTABLE_ENV).is_ok_and(|v| !matches!(v.as_str(), "" | "0" | "false"));
+    Locations::resolve(exe.as_deref().and_then(Path::parent), flagged, profile().as_deref(), dirs::data_local_dir())
+}
+
+/// Unit tests keep everything in a scratch directory of their own, never in
+/// the user's data directory
+#[cfg(test)]
+fn locate() -> Locations {
+    let root = std::env::temp_dir().join(format!("miko-test-data-{}", std::process::id()));
+    Locations { root, portable: true }
+}
+
+/// The `--profile` this process runs under
+pub fn profile() -> Option<String> {
+    std::env::var(PROFILE_ENV).ok().filter(|profile| !profile.is_empty())
+}
+
+/// Running in portable mode
+pub fn portable() -> bool {
+    current().portable
+}
+
+/// Directory holding everything the app keeps on disk
+pub fn data_dir() -> PathBuf {
+    current().data_dir()
+}
+
+/// Directory holding the rotating log files of every binary
+pub fn log_dir() -> PathBuf {
+    current().log_dir()
+}
+
+/// Directory holding one report per panic, bundled by the diagnostics export
+pub fn crash_dir() -> PathBuf {
+    current().crash_dir()
+}
+
+/// Scratch files (voice memos, printed PDFs): the system's temp directory,
+/// or `temp` in the data directory in portable mode
+pub fn temp_dir() -> PathBuf {
+    let dir = current().temp_dir();
+    if portable() {
+        let _ = std::fs::create_dir_all(&dir);
+    }
+    dir
+}
+
+/// WebView2 profile (cookies, localStorage), `configured` being the
+/// `webview2_user_data_dir` setting; kept out of %TEMP% so cleanup tools
+/// don't log users out
+pub fn webview_user_data_dir(configured: Option<&Path>) -> PathBuf {
+    current().webview_user_data_dir(configured)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fresh folder standing in for the executable's, removed when dropped
+    struct ExeDir(PathBuf);
+
+    impl ExeDir {
+        fn new(marker: bool) -> Self {
+            let dir = std::env::temp_dir().join(format!("miko-exe-{}", uuid::Uuid::new_v4().simple()));
+            std::fs::create_dir_all(&dir).unwrap();
+            if marker {
+                std::fs::write(dir.join(PORTABLE_MARKER), b"").unwrap();
+            }
+            Self(dir)
+        }
+    }
+
+    impl Drop for ExeDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn every_location(locations: &Locations, configured_webview: Option<&Path>) -> Vec<PathBuf> {
+        vec![
+            locations.data_dir(),
+            locations.log_dir(),
+            locations.crash_dir(),
+            locations.temp_dir(),
+            locations.webview_user_data_dir(configured_webview),
+        ]
+    }
+
+    /// Every file below `dir`
+    fn files(dir: &Path) -> Vec<PathBuf> {
+        let mut found = Vec::new();
+        for entry in std::fs::read_dir(dir).into_iter().flatten().flatten() {
+            let path = entry.path();
+            if path.is_dir() {
+                found.extend(files(&path));
+            } else {
+                found.push(path);
+            }
+        }
+        found
+    }
+
+    #[test]
+    fn marker_or_flag_turns_on_portable_mode() {
+        let local = Some(PathBuf::from("/local"));
+        let marked = ExeDir::new(true);
+        let portable = Locations::resolve(Some(&marked.0), false, None, local.clone());
+        assert!(portable.portable);
+        assert_eq!(portable.data_dir(), marked.0.join("data"));
+
+        let unmarked = ExeDir::new(false);
+        assert_eq!(Locations::resolve(Some(&unmarked.0), true, None, local.clone()).data_dir(), unmarked.0.join("data"));
+        let installed = Locations::resolve(Some(&unmarked.0), false, None, local.clone());
+        assert!(!installed.portable);
+        assert_eq!(installed.data_dir(), Path::new("/local").join(APP_DIR));
+        assert_eq!(installed.temp_dir(), std::env::temp_dir());
+    }
+
+    #[test]
+    fn profiles_stay_inside_the_portable_directory() {
+        let exe = ExeDir::new(true);
+        let locations = Locations::resolve(Some(&exe.0), false, Some("test"), None);
+        assert_eq!(locations.data_dir(), exe.0.join("data").join("profiles").join("test"));
+    }
+
+    #[test]
+    fn portable_locations_stay_inside_the_data_directory() {
+        let exe = ExeDir::new(true);
+        let outside = std::env::temp_dir().join("configured-webview2");
+        for profile in [None, Some("test")] {
+            let locations = Locations::resolve(Some(&exe.0), false, profile, dirs::data_local_dir());
+            for location in every_location(&locations, Some(&outside)) {
+                assert!(location.starts_with(exe.0.join("data")), "{}", location.display());
+            }
+        }
+    }
+
+    #[test]
+    fn portable_writes_do_not_escape() {
+        let exe = ExeDir::new(true);
+        let outside = std::env::temp_dir().join(format!("configured-webview2-{}", uuid::Uuid::new_v4().simple()));
+        let locations = Locations::resolve(Some(&exe.0), true, None, dirs::data_local_dir());
+        let locations = every_location(&locations, Some(&outside));
+        for (n, dir) in locations.iter().enumerate() {
+            std::fs::create_dir_all(dir).unwrap();
+            std::fs::write(dir.join(format!("written-{}.txt", n)), b"x").unwrap();
+        }
+
+        let written = files(&exe.0);
+        let data = std::fs::canonicalize(exe.0.join("data")).unwrap();
+        // The marker and one file per location, all of them but the marker in `data`
+        assert_eq!(written.len(), locations.len() + 1);
+        for file in written.iter().filter(|file| !file.ends_with(PORTABLE_MARKER)) {
+            assert!(std::fs::canonicalize(file).unwrap().starts_with(&data), "{}", file.display());
+        }
+        assert!(!outside.exists());
+    }
+
+    #[test]
+    fn configured_webview_profile_applies_when_installed() {
+        let configured = PathBuf::from("/profiles/webview");
+        let locations = Locations::resolve(None, false, None, Some(PathBuf::from("/local")));
+        assert_eq!(locations.webview_user_data_dir(Some(&configured)), configured);
+        assert_eq!(locations.webview_user_data_dir(None), Path::new("/local").join(APP_DIR).join("WebView2"));
+    }
+}
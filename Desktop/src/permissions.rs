@@ -0,0 +1,169 @@
+//! OS permissions the app needs, asked for when the page chooses.
+//!
+//! On macOS the microphone and screen recording are behind TCC prompts that
+//! otherwise pop up the first time a memo is recorded or a screen is
+//! shared, in the middle of something, and get denied by accident.
+//! `check_permissions` answers `{notifications, microphone, screen}`, each
+//! `{status, canRequest, settingsUrl?, requiresRestart?}`, for the
+//! onboarding and settings pages. `request_permission {kind}` shows the
+//! prompt when macOS still would; after a denial (macOS asks once) it opens
+//! the System Settings pane instead and answers `openedSettings: true`.
+//!
+//! `status` is `granted`, `denied`, `notDetermined`, `restricted` (MDM or
+//! parental controls; only an admin can change it) or `unknown`. macOS
+//! notifications are shown through AppleScript, whose permission can't be
+//! read, so they are `unknown` and a request shows a test notification.
+//! Screen recording only takes effect after a restart. Windows asks for
+//! none of these: everything is `granted`.
+
+use serde::Serialize;
+use serde_json::{json, Value};
+use tracing::info;
+
+use crate::core::settings;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Kind {
+    Notifications,
+    Microphone,
+    Screen,
+}
+
+pub const KINDS: &[Kind] = &[Kind::Notifications, Kind::Microphone, Kind::Screen];
+
+impl Kind {
+    fn parse(kind: &str) -> Result<Self, String> {
+        match kind {
+            "notifications" => Ok(Self::Notifications),
+            "microphone" => Ok(Self::Microphone),
+            "screen" => Ok(Self::Screen),
+            other => Err(format!("unknown permission: {:?}", other)),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Notifications => "notifications",
+            Self::Microphone => "microphone",
+            Self::Screen => "screen",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Status {
+    Granted,
+    Denied,
+    NotDetermined,
+    Restricted,
+    Unknown,
+}
+
+#[cfg(target_os = "macos")]
+fn status(kind: Kind) -> Status {
+    use crate::platform::mac::permissions;
+    match kind {
+        Kind::Notifications => Status::Unknown,
+        Kind::Microphone => permissions::microphone(),
+        // Not granted reads the same before and after a denial
+        Kind::Screen if permissions::screen_granted() => Status::Granted,
+        Kind::Screen if asked(kind) => Status::Denied,
+        Kind::Screen => Status::NotDetermined,
+    }
+}
+
+#[cfg(not(target_os = "macos"))]
+fn status(_kind: Kind) -> Status {
+    Status::Granted
+}
+
+/// The System Settings pane for `kind`
+#[cfg(target_os = "macos")]
+fn settings_url(kind: Kind) -> Option<&'static str> {
+    Some(match kind {
+        Kind::Notifications => "x-apple.systempreferences:com.apple.preference.notifications",
+        Kind::Microphone => "x-apple.systempreferences:com.apple.preference.security?Privacy_Microphone",
+        Kind::Screen => "x-apple.systempreferences:com.apple.preference.security?Privacy_ScreenCapture",
+    })
+}
+
+#[cfg(not(target_os = "macos"))]
+fn settings_url(_kind: Kind) -> Option<&'static str> {
+    None
+}
+
+fn asked(kind: Kind) -> bool {
+    settings::get().permissions_requested.iter().any(|k| k == kind.name())
+}
+
+fn entry(kind: Kind) -> Value {
+    let status = status(kind);
+    let mut entry = json!({
+        "status": status,
+        "canRequest": matches!(status, Status::NotDetermined | Status::Unknown),
+        "settingsUrl": settings_url(kind),
+    });
+    if kind == Kind::Screen && cfg!(target_os = "macos") && status != Status::Granted {
+        entry["requiresRestart"] = true.into();
+    }
+    entry
+}
+
+/// Handle `check_permissions`
+pub fn check() -> Value {
+    let mut permissions = serde_json::Map::new();
+    for &kind in KINDS {
+        permissions.insert(kind.name().to_string(), entry(kind));
+    }
+    Value::Object(permissions)
+}
+
+/// Handle `request_permission`; blocks until the user answered the prompt
+pub fn request(kind: &str) -> Result<Value, String> {
+    let kind = Kind::parse(kind)?;
+    if kind == Kind::Screen && !crate::media::screen_share_enabled() {
+        return Err("screen sharing is turned off".to_string());
+    }
+    let before = status(kind);
+    let opened_settings = match before {
+        Status::Granted | Status::Restricted => false,
+        Status::NotDetermined | Status::Unknown => {
+            prompt(kind);
+            false
+        }
+        Status::Denied => open_settings(kind)?,
+    };
+    if !asked(kind) {
+        settings::update(|s| s.permissions_requested.push(kind.name().to_string())).map_err(|e| e.to_string())?;
+    }
+    let mut result = entry(kind);
+    info!("Permission {} requested: {:?} -> {}", kind.name(), before, result["status"]);
+    result["kind"] = kind.name().into();
+    result["openedSettings"] = opened_settings.into();
+    Ok(result)
+}
+
+#[cfg(target_os = "macos")]
+fn prompt(kind: Kind) {
+    use crate::platform::mac::permissions;
+    match kind {
+        Kind::Notifications => permissions::request_notifications(),
+        Kind::Microphone => permissions::request_microphone(),
+        Kind::Screen => crate::platform::mac::utils::request_screen_capture_access(),
+    }
+}
+
+#[cfg(not(target_os = "macos"))]
+fn prompt(_kind: Kind) {}
+
+/// Open `kind`'s System Settings pane; false where there is none
+fn open_settings(kind: Kind) -> Result<bool, String> {
+    let Some(url) = settings_url(kind) else { return Ok(false) };
+    std::process::Command::new("open")
+        .arg(url)
+        .spawn()
+        .map(|_| true)
+        .map_err(|e| format!("could not open System Settings: {}", e))
+}
@@ -0,0 +1,88 @@
+//! Start at sign-in through a LaunchAgent in `~/Library/LaunchAgents`.
+//!
+//! launchd reads the plist at the next sign-in; `launchctl print-disabled` tells
+//! whether the user disabled the job in System Settings > Login Items.
+
+use std::path::PathBuf;
+
+fn label() -> String {
+    match crate::paths::profile() {
+        Some(profile) => format!("com.miko.workspace.{}", profile),
+        None => "com.miko.workspace".to_string(),
+    }
+}
+
+/// launchd domain of the signed-in user's jobs
+fn gui_domain() -> String {
+    let uid = std::process::Command::new("id")
+        .arg("-u")
+        .output()
+        .map(|out| String::from_utf8_lossy(&out.stdout).trim().to_string())
+        .unwrap_or_default();
+    format!("gui/{}", uid)
+}
+
+fn plist_path() -> Result<PathBuf, String> {
+    let home = dirs::home_dir().ok_or("no home directory")?;
+    Ok(home.join("Library/LaunchAgents").join(format!("{}.plist", label())))
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+/// Whether launchd will start the app at sign-in
+pub fn status() -> Result<bool, String> {
+    if !plist_path()?.is_file() {
+        return Ok(false);
+    }
+
+    // Lists `"<label>" => disabled` for jobs turned off in Login Items
+    let disabled = std::process::Command::new("launchctl")
+        .args(["print-disabled", &gui_domain()])
+        .output()
+        .map(|out| String::from_utf8_lossy(&out.stdout).contains(&format!("\"{}\" => disabled", label())))
+        .unwrap_or(false);
+    Ok(!disabled)
+}
+
+pub fn set(enabled: bool) -> Result<(), String> {
+    let path = plist_path()?;
+    if !enabled {
+        return match std::fs::remove_file(&path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.to_string()),
+            _ => Ok(()),
+        };
+    }
+
+    let (exe, args) = crate::autostart::launch_command()?;
+    let arguments: String = std::iter::once(exe.to_string_lossy().into_owned())
+        .chain(args)
+        .map(|arg| format!("        <string>{}</string>\n", escape(&arg)))
+        .collect();
+    let plist = format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+    <key>Label</key>
+    <string>{}</string>
+    <key>ProgramArguments</key>
+    <array>
+{}    </array>
+    <key>RunAtLoad</key>
+    <true/>
+</dict>
+</plist>
+"#,
+        label(),
+        arguments
+    );
+    crate::core::write_atomic(&path, plist.as_bytes()).map_err(|e| e.to_string())?;
+
+    // Clear an earlier "off" from Login Items
+    let _ = std::process::Command::new("launchctl")
+        .args(["enable", &format!("{}/{}", gui_domain(), label())])
+        .status();
+    Ok(())
+}
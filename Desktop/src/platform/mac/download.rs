@@ -1,159 +1,211 @@
+use std::path::Path;
 use std::process::{Command, Stdio};
-use std::io::{BufRead, BufReader};
 use serde_json;
+use crate::core::downloads::{self, DownloadTarget};
 use crate::platform::mac::utils::show_notification;
+use tracing::{debug, error, info};
 
-pub fn show_file_in_finder(filename: &str) {
-    println!("📂 Showing file in Finder: {}", filename);
-    
-    // Determine the file path in Downloads folder
-    let downloads_dir = dirs::download_dir().unwrap_or_else(|| {
-        std::env::current_dir().unwrap().join("Downloads")
+/// Reveal the file in Finder. `reference` is a full path or, from older
+/// pages, a bare filename (see [`downloads::locate`]).
+pub fn show_file_in_finder(reference: &str) {
+    info!("Showing file in Finder: {}", reference);
+
+    let (args, target): (&[&str], _) = match downloads::locate(reference) {
+        Ok(path) => (&["-R"], path),
+        Err(dir) => {
+            error!("File not found: {}", reference);
+            (&[], dir)
+        }
+    };
+    // Arguments go straight to exec, so spaces, commas and Unicode need no quoting
+    match std::process::Command::new("open").args(args).arg(&target).status() {
+        Ok(status) if status.success() => info!("Revealed {} in Finder", target.display()),
+        Ok(status) => error!("open exited with {} for {}", status, target.display()),
+        Err(e) => error!("Failed to run open: {}", e),
+    }
+}
+
+/// Download under `id`, given out when the page's request was accepted
+pub fn start_download_process(id: String, url: String, filename: String, target: DownloadTarget) {
+    if !crate::metered::allow(&format!("Downloading {}", filename)) {
+        let spec = downloads::DownloadSpec::new(&url, &filename, &[], &target);
+        let start: downloads::Restart = std::sync::Arc::new(move |id| {
+            let (url, filename, target) = (url.clone(), filename.clone(), target.clone());
+            std::thread::spawn(move || run_download_job(id, url, filename, target));
+        });
+        downloads::defer(&id, &spec, start);
+        return;
+    }
+    run_download_job(id, url, filename, target);
+}
+
+/// List the downloads the last run left unfinished (see [`downloads::restore`])
+pub fn restore_downloads() {
+    downloads::restore(|spec| {
+        let spec = spec.clone();
+        std::sync::Arc::new(move |id| {
+            let spec = spec.clone();
+            std::thread::spawn(move || run_download_job(id, spec.url.clone(), spec.filename.clone(), spec.target()));
+        })
     });
-    
-    let file_path = downloads_dir.join(filename);
-    
-    if file_path.exists() {
-        println!("✅ File exists, opening in Finder: {}", file_path.display());
-        
-        // Use macOS 'open' command with -R flag to reveal in Finder
-        let mut command = std::process::Command::new("open");
-        command.arg("-R").arg(&file_path);
-        
-        match command.spawn() {
-            Ok(_) => {
-                println!("✅ Successfully opened file in Finder");
-            }
-            Err(e) => {
-                println!("❌ Failed to open file in Finder: {}", e);
-                
-                // Fallback: just open the Downloads folder
-                let mut fallback_command = std::process::Command::new("open");
-                fallback_command.arg(&downloads_dir);
-                
-                match fallback_command.spawn() {
-                    Ok(_) => {
-                        println!("✅ Opened Downloads folder as fallback");
-                    }
-                    Err(e2) => {
-                        println!("❌ Failed to open Downloads folder: {}", e2);
-                    }
-                }
-            }
+}
+
+/// Download under the transfer `id`; pausing, resuming and retrying from the
+/// Transfers panel run this again with the same arguments
+fn run_download_job(id: String, url: String, filename: String, target: DownloadTarget) {
+    info!("Starting download: {} -> {}", crate::logging::redact_url(&url), filename);
+
+    let prepared = downloads::resolve_destination(&filename, &target)
+        .and_then(|destination| Ok((downloads::prepare_staging(&destination).map_err(|e| e.to_string())?, destination)));
+    let (staging, destination) = match prepared {
+        Ok(paths) => paths,
+        Err(e) => {
+            error!("Cannot save {}: {}", filename, e);
+            downloads::end_job(&id, &Err(e.clone()));
+            show_notification("Download Failed", &format!("Cannot save {}: {}", filename, e));
+            return;
         }
-    } else {
-        println!("❌ File not found: {}", file_path.display());
-        
-        // Just open the Downloads folder
-        let mut command = std::process::Command::new("open");
-        command.arg(&downloads_dir);
-        
-        match command.spawn() {
-            Ok(_) => {
-                println!("✅ Opened Downloads folder (file not found)");
-            }
-            Err(e) => {
-                println!("❌ Failed to open Downloads folder: {}", e);
+    };
+
+    let restart: downloads::Restart = {
+        let (url, filename, target) = (url.clone(), filename.clone(), target.clone());
+        std::sync::Arc::new(move |id| {
+            let (url, filename, target) = (url.clone(), filename.clone(), target.clone());
+            std::thread::spawn(move || run_download_job(id, url, filename, target));
+        })
+    };
+    let spec = downloads::DownloadSpec::new(&url, &filename, &[], &target);
+    let job = downloads::begin_job(&id, &spec, &staging, &destination, restart);
+
+    // A staging file left by a quit or pause mid-download is continued
+    let resume = staging.exists();
+    if resume {
+        info!("Resuming {} from {}", filename, staging.display());
+    }
+    let result = run_downloader(&url, &staging, &filename, resume, Some(&job)).and_then(|()| {
+        downloads::move_into_place(&staging, &destination)
+            .map_err(|e| format!("could not move the download to {}: {}", destination.display(), e))
+    });
+    let stopped = downloads::end_job(&id, &result);
+    match result {
+        Ok(()) => {
+            downloads::record(&filename, &destination);
+            let folder = destination.parent().map(|p| p.display().to_string()).unwrap_or_default();
+            show_notification("Download Complete", &format!("{} saved to {}", filename, folder));
+        }
+        Err(_) if stopped == Some(downloads::StopReason::Pause) => info!("Paused {}", filename),
+        Err(_) if stopped == Some(downloads::StopReason::Cancel) => {
+            info!("Cancelled {}", filename);
+            let _ = std::fs::remove_file(&staging);
+        }
+        Err(e) => {
+            error!("Download of {} failed: {}", filename, e);
+            // Kept when quitting so the next attempt resumes
+            if !crate::core::shutdown::is_quitting() {
+                let _ = std::fs::remove_file(&staging);
             }
+            show_notification("Download Failed", &format!("Failed to download {}", filename));
         }
     }
 }
 
-pub fn start_download_process(url: String, filename: String) {
-    println!("Starting download: {} -> {}", url, filename);
-    
+/// [`crate::attachments::Fetch`]: one file of a grouped download; like
+/// single downloads, without the page's headers
+pub fn fetch_attachment(
+    url: &str,
+    staging: &Path,
+    filename: &str,
+    _headers: Vec<(String, String)>,
+    job: &downloads::DownloadJob,
+) -> Result<(), String> {
+    run_downloader(url, staging, filename, false, Some(job))
+}
+
+/// Run the downloader service for `url` into `output_path`, tracking its
+/// progress in the `downloads` state topic under the `job`'s id (or a new
+/// one), which the downloader also puts in its output. With `resume`, a partial file left
+/// by an earlier attempt is continued instead of restarted. A `job` is
+/// reported to the transfers and can be stopped.
+pub fn run_downloader(
+    url: &str,
+    output_path: &Path,
+    filename: &str,
+    resume: bool,
+    job: Option<&downloads::DownloadJob>,
+) -> Result<(), String> {
     // Get the path to the downloader executable
     let exe_path = std::env::current_exe()
         .ok()
         .and_then(|exe| exe.parent().map(|p| p.join("downloaderservice")))
         .unwrap_or_else(|| std::path::PathBuf::from("./target/debug/downloaderservice"));
     
-    println!("Using downloader executable: {}", exe_path.display());
-    
-    // Determine output path (Downloads folder)
-    let downloads_dir = dirs::download_dir().unwrap_or_else(|| {
-        std::env::current_dir().unwrap().join("Downloads")
-    });
-    
-    // Create downloads directory if it doesn't exist
-    if !downloads_dir.exists() {
-        if let Err(e) = std::fs::create_dir_all(&downloads_dir) {
-            println!("Failed to create downloads directory: {}", e);
-            return;
-        }
-    }
-    
-    let output_path = downloads_dir.join(&filename);
+    info!("Using downloader executable: {}", exe_path.display());
     
     // Start the downloader process
+    let download_id = job.map_or_else(downloads::new_job_id, |job| job.id.clone());
     let mut command = Command::new(&exe_path);
     command
-        .arg(&url)
-        .arg(&output_path)
+        .arg(url)
+        .arg(output_path)
+        .arg("--download-id")
+        .arg(&download_id)
         .stdout(Stdio::piped())
         .stderr(Stdio::piped());
+    if resume {
+        command.arg("--resume");
+    }
     
-    match command.spawn() {
-        Ok(mut child) => {
-            println!("Downloader process started with PID: {}", child.id());
+    let child = match command.spawn() {
+        Ok(child) => child,
+        Err(e) => {
+            error!("Failed to start downloader process: {}", e);
+            return Err(format!("failed to start downloader: {}", e));
+        }
+    };
+    info!("Downloader process started with PID: {}", child.id());
+    crate::core::state::download_started(&download_id, filename);
+    
+    let mut reported_error: Option<String> = None;
+    let stop = job.map(|job| &*job.stop);
+    let outcome = crate::platform::subprocess::supervise(child, stop, |json_line| {
+        debug!("Download progress: {}", json_line);
+        
+        // Parse JSON and emit progress events
+        if let Ok(progress) = serde_json::from_str::<serde_json::Value>(json_line) {
+            let status = progress["status"].as_str().unwrap_or("unknown");
             
-            // Read stdout in a separate thread
-            if let Some(stdout) = child.stdout.take() {
-                let reader = BufReader::new(stdout);
-                
-                for line in reader.lines() {
-                    match line {
-                        Ok(json_line) => {
-                            println!("Download progress: {}", json_line);
-                            
-                            // Parse JSON and emit progress events
-                            if let Ok(progress) = serde_json::from_str::<serde_json::Value>(&json_line) {
-                                let status = progress["status"].as_str().unwrap_or("unknown");
-                                
-                                match status {
-                                    "downloading" => {
-                                        println!("Progress: {}%", progress["progress_percent"].as_f64().unwrap_or(0.0));
-                                    }
-                                    "completed" => {
-                                        println!("Download completed: {}", filename);
-                                        
-                                        // Show macOS notification
-                                        show_notification("Download Complete", &format!("{} saved to Downloads", filename));
-                                        break;
-                                    }
-                                    "error" => {
-                                        println!("Download error: {}", progress["error"].as_str().unwrap_or("Unknown error"));
-                                        show_notification("Download Failed", &format!("Failed to download {}", filename));
-                                        break;
-                                    }
-                                    _ => {}
-                                }
-                            }
-                        }
-                        Err(e) => {
-                            println!("Error reading download output: {}", e);
-                            break;
-                        }
+            match status {
+                "downloading" => {
+                    let percent = progress["progress_percent"].as_f64().unwrap_or(0.0);
+                    debug!("Progress: {}%", percent);
+                    crate::core::state::download_progress(&download_id, percent, progress["download_speed_human"].as_str());
+                    if let Some(job) = job {
+                        let total = progress["total_size"].as_u64().filter(|&total| total > 0);
+                        crate::core::transfers::progress(&job.id, progress["downloaded"].as_u64().unwrap_or(0), total);
                     }
                 }
-            }
-            
-            // Wait for the process to complete
-            match child.wait() {
-                Ok(status) => {
-                    if status.success() {
-                        println!("Download completed successfully");
-                    } else {
-                        println!("Download failed with exit code: {:?}", status.code());
-                    }
+                "completed" => {
+                    info!("Download completed: {}", filename);
                 }
-                Err(e) => {
-                    println!("Error waiting for download process: {}", e);
+                "error" => {
+                    let error_msg = progress["error"].as_str().unwrap_or("Unknown error");
+                    error!("Download error: {}", error_msg);
+                    reported_error = Some(error_msg.to_string());
                 }
+                _ => {}
             }
         }
-        Err(e) => {
-            println!("Failed to start downloader process: {}", e);
-        }
+    });
+    
+    crate::core::state::download_finished(&download_id);
+    if outcome.success() {
+        info!("Download process completed successfully");
+        Ok(())
+    } else if stop.is_some_and(|stop| stop.load(std::sync::atomic::Ordering::SeqCst)) {
+        Err("stopped".to_string())
+    } else {
+        let reason = outcome.error_message();
+        error!("Download process failed: {}", reason);
+        Err(reported_error.unwrap_or(reason))
     }
 }
@@ -0,0 +1,144 @@
+//! Dragging a downloaded file out of the window into Finder, Mail, etc.
+//!
+//! AppKit drags are asynchronous: the session is started on the window's
+//! content view and a small `NSDraggingSource` hears how it ended, at which
+//! point the page gets its `file-drag-finished` result.
+
+use std::sync::{Mutex, Once};
+use cocoa::base::{id, nil};
+use cocoa::foundation::{NSPoint, NSRect, NSSize, NSString};
+use lazy_static::lazy_static;
+use objc::declare::ClassDecl;
+use objc::runtime::{Class, Object, Sel};
+use objc::{class, msg_send, sel, sel_impl};
+use winit::raw_window_handle::{HasWindowHandle, RawWindowHandle};
+use winit::window::Window;
+use tracing::info;
+
+use crate::core::downloads::FileDrag;
+
+const SOURCE_CLASS: &str = "MikoFileDragSource";
+const NS_DRAG_OPERATION_NONE: usize = 0;
+const NS_DRAG_OPERATION_COPY: usize = 1;
+const NS_EVENT_TYPE_LEFT_MOUSE_DRAGGED: usize = 6;
+const ICON_SIZE: f64 = 48.0;
+
+lazy_static! {
+    /// The drag in flight; AppKit runs one session at a time
+    static ref PENDING: Mutex<Option<FileDrag>> = Mutex::new(None);
+}
+
+pub fn begin_file_drag(window: &Window, request: FileDrag) {
+    let mut pending = PENDING.lock().unwrap();
+    if pending.is_some() {
+        drop(pending);
+        request.finished(Err("another file drag is in progress".to_string()));
+        return;
+    }
+    // The session only ends on a later run loop turn, so holding the lock is fine
+    match unsafe { start_session(window, &request) } {
+        Ok(()) => *pending = Some(request),
+        Err(e) => {
+            drop(pending);
+            request.finished(Err(e));
+        }
+    }
+}
+
+/// The IPC round trip means the original mouse-down is gone by now, so the
+/// session starts from a synthesized drag event at the current mouse position
+unsafe fn start_session(window: &Window, request: &FileDrag) -> Result<(), String> {
+    let view: id = match window.window_handle().map_err(|e| e.to_string())?.as_raw() {
+        RawWindowHandle::AppKit(handle) => handle.ns_view.as_ptr() as id,
+        _ => return Err("not an AppKit window".to_string()),
+    };
+    let ns_window: id = msg_send![view, window];
+    let location: NSPoint = msg_send![ns_window, mouseLocationOutsideOfEventStream];
+    let window_number: isize = msg_send![ns_window, windowNumber];
+    let event: id = msg_send![class!(NSEvent),
+        mouseEventWithType: NS_EVENT_TYPE_LEFT_MOUSE_DRAGGED
+        location: location
+        modifierFlags: 0usize
+        timestamp: 0.0f64
+        windowNumber: window_number
+        context: nil
+        eventNumber: 0isize
+        clickCount: 1isize
+        pressure: 1.0f32];
+    if event == nil {
+        return Err("could not create the drag event".to_string());
+    }
+
+    let path = NSString::alloc(nil).init_str(&request.path.to_string_lossy());
+    let _: id = msg_send![path, autorelease];
+    let url: id = msg_send![class!(NSURL), fileURLWithPath: path];
+
+    let point: NSPoint = msg_send![view, convertPoint: location fromView: nil];
+    let frame = NSRect::new(
+        NSPoint::new(point.x - ICON_SIZE / 2.0, point.y - ICON_SIZE / 2.0),
+        NSSize::new(ICON_SIZE, ICON_SIZE),
+    );
+    let item: id = msg_send![class!(NSDraggingItem), alloc];
+    let item: id = msg_send![item, initWithPasteboardWriter: url];
+    let _: id = msg_send![item, autorelease];
+    let _: () = msg_send![item, setDraggingFrame: frame contents: drag_image(request, path)];
+    let items: id = msg_send![class!(NSArray), arrayWithObject: item];
+
+    // Released by `session_ended`
+    let source: id = msg_send![source_class(), new];
+    let session: id = msg_send![view, beginDraggingSessionWithItems: items event: event source: source];
+    if session == nil {
+        let _: () = msg_send![source, release];
+        return Err("AppKit refused to start the drag".to_string());
+    }
+    Ok(())
+}
+
+/// The page's `iconDataUrl` if it decoded, otherwise Finder's icon for the file
+unsafe fn drag_image(request: &FileDrag, path: id) -> id {
+    if let Some(png) = &request.icon_png {
+        let data: id = msg_send![class!(NSData), dataWithBytes: png.as_ptr() length: png.len()];
+        let image: id = msg_send![class!(NSImage), alloc];
+        let image: id = msg_send![image, initWithData: data];
+        if image != nil {
+            let _: id = msg_send![image, autorelease];
+            return image;
+        }
+    }
+    let workspace: id = msg_send![class!(NSWorkspace), sharedWorkspace];
+    msg_send![workspace, iconForFile: path]
+}
+
+fn source_class() -> &'static Class {
+    static REGISTER: Once = Once::new();
+    REGISTER.call_once(|| {
+        let mut decl = ClassDecl::new(SOURCE_CLASS, class!(NSObject)).expect("drag source class registered twice");
+        unsafe {
+            decl.add_method(
+                sel!(draggingSession:sourceOperationMaskForDraggingContext:),
+                operation_mask as extern "C" fn(&Object, Sel, id, isize) -> usize,
+            );
+            decl.add_method(
+                sel!(draggingSession:endedAtPoint:operation:),
+                session_ended as extern "C" fn(&Object, Sel, id, NSPoint, usize),
+            );
+        }
+        decl.register();
+    });
+    Class::get(SOURCE_CLASS).unwrap()
+}
+
+extern "C" fn operation_mask(_this: &Object, _sel: Sel, _session: id, _context: isize) -> usize {
+    NS_DRAG_OPERATION_COPY
+}
+
+extern "C" fn session_ended(this: &Object, _sel: Sel, _session: id, _point: NSPoint, operation: usize) {
+    if let Some(request) = PENDING.lock().unwrap().take() {
+        let dropped = operation != NS_DRAG_OPERATION_NONE;
+        info!("File drag of {} {}", request.path.display(), if dropped { "dropped" } else { "cancelled" });
+        request.finished(Ok(dropped));
+    }
+    unsafe {
+        let _: id = msg_send![this, autorelease];
+    }
+}
@@ -3,7 +3,7 @@
 use wry::WebViewBuilder;
 use winit::{
     event::WindowEvent,
-    event_loop::{ActiveEventLoop, EventLoop},
+    event_loop::{ActiveEventLoop, EventLoop, EventLoopProxy},
     window::{Window, WindowId},
     application::ApplicationHandler,
     dpi::LogicalSize,
@@ -13,90 +13,163 @@ use serde_json;
 use tray_icon::TrayIcon;
 use std::sync::Mutex;
 use lazy_static::lazy_static;
+use tracing::{error, info, warn};
 
 pub mod utils;
+pub mod autostart;
 pub mod download;
+pub mod drag;
+pub mod permissions;
+pub mod power;
+pub mod print;
+pub mod recovery;
 pub mod tray;
 
 use crate::hooks as app_hooks;
-use app_hooks::{init_notifications, show_notification};
+use app_hooks::init_notifications;
 
-// Global flag to ensure only one tray icon is created system-wide
 lazy_static! {
-    pub static ref TRAY_ICON_CREATED: Arc<Mutex<bool>> = Arc::new(Mutex::new(false));
+    static ref EVENT_PROXY: Mutex<Option<EventLoopProxy<AppEvent>>> = Mutex::new(None);
 }
 
-// Include the icon at compile time
-pub const ICON_BYTES: &[u8] = include_bytes!("../../../../Library/Shared/Icons/icon.icns");
+/// Events delivered to the event loop from background threads
+#[derive(Debug)]
+pub enum AppEvent {
+    /// Queued webview events or badge changes are waiting to be applied
+    Wake,
+    /// Leave the event loop as if the user chose Exit (e.g. to run an installer)
+    Quit,
+    /// Start dragging a downloaded file out of the window (UI thread only)
+    BeginFileDrag(crate::core::downloads::FileDrag),
+    /// Print the page or save it as a PDF (needs the webview)
+    Print(crate::print::PrintJob),
+    /// Reload the page after a renderer failure
+    ReloadPage,
+    /// The page finished loading; the window is shown the first time
+    PageLoaded,
+    /// Move the window to a layout preset (`snap_window`)
+    SnapWindow { preset: String, request_id: Option<String> },
+    /// Keep the window's geometry as a named layout (`save_window_layout`)
+    SaveWindowLayout { name: Option<String>, request_id: Option<String> },
+}
+
+/// Wake the event loop with `event`; returns false once the loop has exited
+pub fn send_app_event(event: AppEvent) -> bool {
+    match EVENT_PROXY.lock().unwrap().as_ref() {
+        Some(proxy) => proxy.send_event(event).is_ok(),
+        None => false,
+    }
+}
+
+/// The macOS side of the page's actions (see [`crate::actions`])
+static ACTIONS: crate::actions::Platform = crate::actions::Platform {
+    start_download: |id, url, filename, _headers, target| download::start_download_process(id, url, filename, target),
+    fetch_attachment: download::fetch_attachment,
+    show_in_folder: download::show_file_in_finder,
+    choose_folder: utils::choose_folder,
+    choose_pdf_path: |default_name| utils::choose_save_path(default_name, "Save as PDF"),
+    begin_file_drag: |request| { send_app_event(AppEvent::BeginFileDrag(request)); },
+    preview_file: |path| { std::thread::spawn(move || utils::quick_look(&path)); },
+    show_context_menu: None,
+    snap_window: |preset, request_id| { send_app_event(AppEvent::SnapWindow { preset, request_id }); },
+    save_window_layout: |name, request_id| { send_app_event(AppEvent::SaveWindowLayout { name, request_id }); },
+    print: |job| { send_app_event(AppEvent::Print(job)); },
+    autostart_status: autostart::status,
+    set_autostart: autostart::set,
+    export_diagnostics: utils::export_diagnostics,
+    export_thread: utils::export_thread,
+    learn_words: utils::learn_words,
+    // NSSpellChecker belongs to the main thread, where IPC messages arrive
+    spellcheck_on_ipc_thread: true,
+    // WebKit follows the system languages
+    languages_at_restart: false,
+    install_update: utils::install_update,
+    request_screen_capture: Some(utils::request_screen_capture_access),
+};
 
 #[cfg(debug_assertions)]
 const DEV_SERVER_URL: &str = "http://localhost:5173";
 
-#[cfg(not(debug_assertions))]
-const INDEX_HTML_BYTES: &[u8] = include_bytes!("../../../../Distribution/index.html");
 
 struct App {
     window: Option<Arc<Window>>,
     webview: Option<wry::WebView>,
-    initialization_complete: bool,
-    ready_to_show: bool,
     tray_icon: Option<TrayIcon>,
+    webview_handle: crate::ipc::WebviewHandle,
+    args: crate::cli::Args,
 }
 
 impl App {
-    fn new() -> Self {
+    fn new(args: crate::cli::Args) -> Self {
         Self {
             window: None,
             webview: None,
-            initialization_complete: false,
-            ready_to_show: false,
             tray_icon: None,
+            webview_handle: crate::ipc::install(|| { send_app_event(AppEvent::Wake); }),
+            args,
         }
     }
 }
 
-impl ApplicationHandler for App {
+impl ApplicationHandler<AppEvent> for App {
     fn resumed(&mut self, event_loop: &ActiveEventLoop) {
         let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
-            if self.window.is_none() && !self.initialization_complete {
-                println!("Starting macOS initialization...");
+            if self.window.is_none() && !crate::core::state::is_initialized() {
+                info!("Starting macOS initialization...");
                 
-                let mut window_attributes = Window::default_attributes()
+                // Shown on the first page load (AppEvent::PageLoaded)
+                let window_attributes = Window::default_attributes()
                     .with_title("Workspace")
                     .with_inner_size(LogicalSize::new(1200, 800))
                     .with_visible(false);
                 
-                if let Some(icon) = utils::load_window_icon() {
-                    window_attributes = window_attributes.with_window_icon(Some(icon));
-                }
-                
-                match event_loop.create_window(window_attributes) {
+                let created = {
+                    let _phase = crate::startup::phase("window");
+                    event_loop.create_window(window_attributes)
+                };
+                match created {
                     Ok(window) => {
                         let window = Arc::new(window);
                         self.window = Some(window.clone());
-                        self.create_webview(&window);
+                        utils::set_capture_excluded(&window, crate::privacy::block_capture());
+                        let created = {
+                            let _phase = crate::startup::phase("webview");
+                            self.ensure_webview(&window)
+                        };
+                        if !created {
+                            info!("No webview, exiting");
+                            event_loop.exit();
+                            return;
+                        }
+                        crate::startup::show_after_timeout(|| {
+                            send_app_event(AppEvent::PageLoaded);
+                        });
+                        
+                        // Decoded on a worker since startup began
+                        window.set_window_icon(utils::load_window_icon());
                         
                         // Initialize notifications
                         if let Err(e) = init_notifications() {
-                            println!("⚠️ Failed to initialize notifications: {}", e);
+                            warn!("Failed to initialize notifications: {}", e);
                         }
                         
-                        window.set_visible(true);
-                        
                         if self.tray_icon.is_none() {
+                            let _phase = crate::startup::phase("tray");
                             if let Ok(tray) = tray::create_tray_icon(self.window.clone()) {
                                 self.tray_icon = Some(tray);
+                                self.update_unread_badge(crate::core::unread::total());
                             }
                         }
-                        self.initialization_complete = true;
+                        power::observe_wake();
+                        crate::core::state::set_initialized();
                     }
-                    Err(e) => println!("❌ Failed to create macOS window: {}", e),
+                    Err(e) => error!("Failed to create macOS window: {}", e),
                 }
             }
         }));
         
         if let Err(panic_info) = result {
-            println!("🚨 macOS initialization panic caught: {:?}", panic_info);
+            error!("macOS initialization panic caught: {:?}", panic_info);
         }
     }
 
@@ -108,89 +181,253 @@ impl ApplicationHandler for App {
                         window.set_visible(false);
                     }
                 }
+                WindowEvent::ScaleFactorChanged { scale_factor, .. } => {
+                    // WebKit re-renders at the new density itself; the page may swap bitmaps
+                    info!("Scale factor changed to {}", scale_factor);
+                    self.webview_handle.emit("scale-factor-changed", &serde_json::json!({ "scaleFactor": scale_factor }));
+                }
+                WindowEvent::Focused(focused) => crate::mark_read::focus_changed(focused),
                 WindowEvent::Destroyed => {
                     self.webview = None;
+                    crate::core::state::set_webview_ready(false);
                     self.window = None;
                 }
                 _ => {}
             }
         }));
     }
+
+    fn user_event(&mut self, event_loop: &ActiveEventLoop, event: AppEvent) {
+        match event {
+            AppEvent::Wake => {
+                // Deliver events queued by background threads (kept until the webview exists)
+                if let Some(webview) = &self.webview {
+                    self.webview_handle.deliver(webview);
+                }
+
+                if crate::core::downloads::take_tray_update() {
+                    if let Some(tray) = &self.tray_icon {
+                        tray::refresh_menu(tray);
+                    }
+                }
+
+                if let Some(blocked) = crate::privacy::take_window_update() {
+                    if let Some(window) = &self.window {
+                        utils::set_capture_excluded(window, blocked);
+                    }
+                }
+
+                if let Some(total) = crate::core::unread::take_badge_update() {
+                    self.update_unread_badge(total);
+                }
+
+                if let Some(connectivity) = crate::core::network::take_tray_update() {
+                    self.update_connectivity_icon(connectivity);
+                }
+
+                if crate::quiet_hours::take_tray_update() {
+                    self.update_unread_badge(crate::core::unread::total());
+                }
+
+                if let Some(status) = crate::core::transfer_status::take_update() {
+                    if let Some(window) = &self.window {
+                        window.set_title(&crate::core::transfer_status::window_title(status.as_deref()));
+                    }
+                    self.update_unread_badge(crate::core::unread::total());
+                }
+            }
+            AppEvent::Quit => {
+                info!("Quit requested");
+                event_loop.exit();
+            }
+            AppEvent::BeginFileDrag(request) => match &self.window {
+                Some(window) => drag::begin_file_drag(window, request),
+                None => request.finished(Err("no window".to_string())),
+            },
+            AppEvent::Print(job) => match &self.webview {
+                Some(webview) => print::run(webview, job),
+                None => job.failed("no webview"),
+            },
+            AppEvent::SnapWindow { preset, request_id } => {
+                let result = match &self.window {
+                    Some(window) => crate::window_layout::snap(window, &preset),
+                    None => Err("no window".to_string()),
+                };
+                let reply = match result {
+                    Ok(()) => serde_json::json!({ "success": true, "preset": preset }),
+                    Err(e) => serde_json::json!({ "success": false, "preset": preset, "error": e }),
+                };
+                crate::ipc::respond(request_id.as_deref(), "window-snapped", &reply);
+            }
+            AppEvent::SaveWindowLayout { name, request_id } => {
+                let result = match &self.window {
+                    Some(window) => crate::window_layout::save(window, name.as_deref()),
+                    None => Err("no window".to_string()),
+                };
+                let reply = match result {
+                    Ok(name) => serde_json::json!({ "success": true, "name": name, "layouts": crate::window_layout::list() }),
+                    Err(e) => serde_json::json!({ "success": false, "error": e }),
+                };
+                crate::ipc::respond(request_id.as_deref(), "window-layout-saved", &reply);
+            }
+            AppEvent::PageLoaded => {
+                if let Some(window) = &self.window {
+                    // `--start-hidden` leaves it in the tray until opened from there
+                    if crate::startup::take_first_show() && !self.args.start_hidden {
+                        window.set_visible(true);
+                        window.focus_window();
+                    }
+                }
+            }
+            AppEvent::ReloadPage => {
+                if let Some(webview) = &self.webview {
+                    info!("Reloading the page");
+                    if let Err(e) = webview.reload() {
+                        error!("Failed to reload the page: {}", e);
+                    }
+                }
+            }
+        }
+    }
 }
 
 impl App {
-    fn create_webview(&mut self, window: &Arc<Window>) {
+    /// Reflect the unread total in the Dock badge and the tray tooltip, which
+    /// also shows running transfers
+    fn update_unread_badge(&self, total: u32) {
+        let label = crate::core::unread::badge_label(total);
+        utils::set_dock_badge(label.as_deref());
+        if let Some(tray) = &self.tray_icon {
+            let details: Vec<String> = label
+                .map(|label| format!("{} unread", label))
+                .into_iter()
+                .chain(crate::core::transfer_status::current())
+                .chain(crate::quiet_hours::tooltip())
+                .collect();
+            let tooltip = if details.is_empty() {
+                "Workspace - macOS Desktop Application".to_string()
+            } else {
+                format!("Workspace - {}", details.join(" - "))
+            };
+            let _ = tray.set_tooltip(Some(tooltip));
+        }
+    }
+
+    /// Grey the tray icon out while the network or the ERP is unreachable
+    fn update_connectivity_icon(&self, connectivity: crate::core::state::Connectivity) {
+        let Some(tray) = &self.tray_icon else { return };
+        let icon = crate::icons::load_app_icon(crate::icons::TRAY_ICON_SIZE);
+        let icon = match connectivity {
+            crate::core::state::Connectivity::Online => tray_icon::Icon::from_rgba(icon.rgba.clone(), icon.width, icon.height),
+            _ => {
+                let dimmed = crate::icons::dimmed(&icon);
+                tray_icon::Icon::from_rgba(dimmed.rgba, dimmed.width, dimmed.height)
+            }
+        };
+        match icon {
+            Ok(icon) => {
+                if let Err(e) = tray.set_icon(Some(icon)) {
+                    warn!("Failed to update tray icon: {}", e);
+                }
+            }
+            Err(e) => warn!("Failed to build tray icon: {}", e),
+        }
+    }
+
+    /// Create the webview, offering to retry after a failure; false once the
+    /// user gives up
+    fn ensure_webview(&mut self, window: &Arc<Window>) -> bool {
+        loop {
+            match self.create_webview(window) {
+                Ok(()) => return true,
+                Err(e) => {
+                    error!("Failed to create WebView: {}", e);
+                    let message = format!("Workspace could not open its window:\n\n{}", e);
+                    if !utils::confirm("Workspace", &message, "Try Again", "Quit") {
+                        return false;
+                    }
+                }
+            }
+        }
+    }
+
+    fn create_webview(&mut self, window: &Arc<Window>) -> Result<(), String> {
         let mut webview_builder = WebViewBuilder::new();
 
         #[cfg(debug_assertions)]
-        { webview_builder = webview_builder.with_url(DEV_SERVER_URL); }
-
-        #[cfg(not(debug_assertions))]
         {
-            use std::borrow::Cow;
-            webview_builder = webview_builder.with_custom_protocol("miko".into(), move |_webview, request| {
-                if request.uri() == "miko://app/" || request.uri() == "miko://app/index.html" {
-                    return http::Response::builder().header("Content-Type", "text/html").body(Cow::Borrowed(INDEX_HTML_BYTES)).unwrap();
-                }
-                http::Response::builder().status(404).body(Cow::Borrowed(&[] as &[u8])).unwrap()
-            });
-            webview_builder = webview_builder.with_url("miko://app/");
+            let dev_server = self.args.dev_server.as_deref().unwrap_or(DEV_SERVER_URL);
+            crate::ipc::allow_origin(dev_server);
+            webview_builder = webview_builder.with_url(dev_server);
         }
 
-        webview_builder = webview_builder.with_initialization_script("console.log('🍎 macOS WebKit WebView initialized');");
+        #[cfg(not(debug_assertions))]
+        { webview_builder = webview_builder.with_url("miko://app/"); }
+
+        // Serves the bundled page and forwards same-origin requests to the server
+        webview_builder = webview_builder.with_asynchronous_custom_protocol(crate::protocol::SCHEME.into(), |_webview, request, responder| {
+            crate::protocol::handle(request, responder);
+        });
+
+        webview_builder = webview_builder.with_initialization_script(crate::core::sync::BOOTSTRAP_SCRIPT);
+        // Safe mode keeps only the bridge the page needs to talk to the host
+        if !crate::safe_mode::active() {
+            webview_builder = webview_builder.with_initialization_script("console.log('🍎 macOS WebKit WebView initialized');");
+            webview_builder = webview_builder.with_initialization_script(crate::console::INIT_SCRIPT);
+            webview_builder = webview_builder.with_initialization_script(crate::spellcheck::init_script());
+            if let Some(script) = crate::media::init_script() {
+                webview_builder = webview_builder.with_initialization_script(script);
+            }
+        }
 
         let webview = webview_builder
             .with_devtools(true)
+            .with_on_page_load_handler(|event, _url| {
+                if let wry::PageLoadEvent::Finished = event {
+                    send_app_event(AppEvent::PageLoaded);
+                }
+            })
             .with_ipc_handler(|request| {
-                let body = request.body();
-                if let Ok(message) = serde_json::from_str::<serde_json::Value>(body) {
-                    if let Some(msg_type) = message["type"].as_str() {
-                        match msg_type {
-                            "start_download" => {
-                                if let (Some(url), Some(filename)) = (message["url"].as_str(), message["filename"].as_str()) {
-                                    let (u, f) = (url.to_string(), filename.to_string());
-                                    std::thread::spawn(move || { download::start_download_process(u, f); });
-                                }
-                            }
-                            "show_in_folder" => {
-                                if let Some(filename) = message["filename"].as_str() {
-                                    let f = filename.to_string();
-                                    std::thread::spawn(move || { download::show_file_in_finder(&f); });
-                                }
-                            }
-                            "show_notification" => {
-                                if let Ok(noti_data) = serde_json::from_value::<crate::hooks::noti::NotificationData>(message.clone()) {
-                                    std::thread::spawn(move || { let _ = show_notification(noti_data); });
-                                }
-                            }
-                            _ => {}
-                        }
-                    }
+                // Malformed or oversized messages are answered with `ipc-error` here
+                if let Some(message) = crate::ipc::parse_message(request.uri(), request.body()) {
+                    crate::actions::dispatch(&message, &ACTIONS);
                 }
             })
-            .build(&**window);
+            .build(&**window)
+            .map_err(|e| e.to_string())?;
 
-        if let Ok(wv) = webview {
-            self.webview = Some(wv);
+        recovery::install_process_terminated_handler(&webview);
+        if crate::rendering::gpu_disabled() {
+            utils::disable_accelerated_drawing(&webview);
         }
+        self.webview = Some(webview);
+        crate::core::state::set_webview_ready(true);
+        if let Some(webview) = &self.webview {
+            self.webview_handle.deliver(webview);
+        }
+        Ok(())
     }
 }
 
-impl Drop for App {
-    fn drop(&mut self) {
-        if let Ok(mut created) = TRAY_ICON_CREATED.lock() { *created = false; }
-    }
-}
+pub fn main(args: crate::cli::Args) -> Result<(), Box<dyn std::error::Error>> {
+    info!("Starting Workspace macOS Desktop Application");
 
-pub fn main() -> Result<(), Box<dyn std::error::Error>> {
-    println!("🚀 Starting Workspace macOS Desktop Application");
-    
-    std::panic::set_hook(Box::new(|panic_info| {
-        println!("🚨 macOS app panic caught: {:?}", panic_info);
-    }));
-    
-    let event_loop = EventLoop::new()?;
-    let mut app = App::new();
+    let event_loop = EventLoop::<AppEvent>::with_user_event().build()?;
+    *EVENT_PROXY.lock().unwrap() = Some(event_loop.create_proxy());
+    if !cfg!(debug_assertions) && args.dev_server.is_some() {
+        warn!("--dev-server is ignored in release builds");
+    }
+    let mut app = App::new(args);
+    download::restore_downloads();
+    // First, so a restored outage is in place before the monitor's first probe
+    crate::core::health::start_poller();
+    crate::core::network::start_monitor();
+    crate::presence::start_monitor(utils::idle_time);
+    crate::quiet_hours::start_monitor();
+    std::thread::spawn(|| utils::check_for_updates(false));
     let _ = event_loop.run_app(&mut app);
+    crate::clipboard::cleanup();
+    crate::temp_media::cleanup();
+    crate::rendering::relaunch_if_requested();
     Ok(())
 }
@@ -0,0 +1,66 @@
+//! TCC state behind `crate::permissions`.
+
+use std::time::{Duration, Instant};
+use cocoa::base::id;
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use objc::{class, msg_send, sel, sel_impl};
+use tracing::warn;
+
+use crate::permissions::Status;
+
+#[link(name = "AVFoundation", kind = "framework")]
+extern "C" {
+    static AVMediaTypeAudio: id;
+}
+
+#[link(name = "CoreGraphics", kind = "framework")]
+extern "C" {
+    fn CGPreflightScreenCaptureAccess() -> bool;
+}
+
+/// How long the microphone prompt is waited on
+const PROMPT_TIMEOUT: Duration = Duration::from_secs(120);
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// `AVCaptureDevice authorizationStatusForMediaType:` for audio
+pub fn microphone() -> Status {
+    let status: isize = unsafe { msg_send![class!(AVCaptureDevice), authorizationStatusForMediaType: AVMediaTypeAudio] };
+    match status {
+        0 => Status::NotDetermined,
+        1 => Status::Restricted,
+        2 => Status::Denied,
+        3 => Status::Granted,
+        _ => Status::Unknown,
+    }
+}
+
+pub fn screen_granted() -> bool {
+    unsafe { CGPreflightScreenCaptureAccess() }
+}
+
+/// Open the microphone (which shows the prompt) and keep it open until the
+/// user answered
+pub fn request_microphone() {
+    let stream = cpal::default_host().default_input_device().and_then(|device| {
+        let config = device.default_input_config().ok()?.config();
+        let stream = device
+            .build_input_stream(&config, |_: &[f32], _: &cpal::InputCallbackInfo| {}, |e| warn!("Microphone: {}", e), None)
+            .ok()?;
+        stream.play().ok()?;
+        Some(stream)
+    });
+    if stream.is_none() {
+        warn!("No microphone to ask permission for");
+        return;
+    }
+    let started = Instant::now();
+    while microphone() == Status::NotDetermined && started.elapsed() < PROMPT_TIMEOUT {
+        std::thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// AppleScript notifications can't be asked about; the first one shown is
+/// what asks
+pub fn request_notifications() {
+    super::utils::show_notification("Workspace", "Notifications are on");
+}
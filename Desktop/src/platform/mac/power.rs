@@ -0,0 +1,45 @@
+//! Wake-from-sleep notifications.
+//!
+//! `NSWorkspaceDidWakeNotification` is only posted to the workspace's own
+//! notification center, so a small observer object is registered there and
+//! forwards to [`crate::core::power::resumed`].
+
+use std::sync::Once;
+use cocoa::base::{id, nil};
+use cocoa::foundation::NSString;
+use objc::declare::ClassDecl;
+use objc::runtime::{Class, Object, Sel};
+use objc::{class, msg_send, sel, sel_impl};
+use tracing::info;
+
+const OBSERVER_CLASS: &str = "MikoWakeObserver";
+
+/// Register the observer (once); must be called on the main thread
+pub fn observe_wake() {
+    static REGISTERED: Once = Once::new();
+    REGISTERED.call_once(|| unsafe {
+        let observer: id = msg_send![observer_class(), new];
+        let workspace: id = msg_send![class!(NSWorkspace), sharedWorkspace];
+        let center: id = msg_send![workspace, notificationCenter];
+        let name = NSString::alloc(nil).init_str("NSWorkspaceDidWakeNotification");
+        // The center doesn't retain observers; this one lives as long as the app
+        let _: () = msg_send![center, addObserver: observer selector: sel!(didWake:) name: name object: nil];
+        info!("Watching for wake from sleep");
+    });
+}
+
+fn observer_class() -> &'static Class {
+    static REGISTER: Once = Once::new();
+    REGISTER.call_once(|| {
+        let mut decl = ClassDecl::new(OBSERVER_CLASS, class!(NSObject)).expect("wake observer class registered twice");
+        unsafe {
+            decl.add_method(sel!(didWake:), did_wake as extern "C" fn(&Object, Sel, id));
+        }
+        decl.register();
+    });
+    Class::get(OBSERVER_CLASS).unwrap()
+}
+
+extern "C" fn did_wake(_this: &Object, _sel: Sel, _notification: id) {
+    crate::core::power::resumed();
+}
@@ -0,0 +1,133 @@
+//! WKWebView printing.
+//!
+//! The print dialog is wry's own `printOperationWithPrintInfo:` sheet. PDFs
+//! go through the same operation with a "save" job disposition and no panels,
+//! which paginates like a printout (unlike `createPDF`); a small delegate
+//! hears when the file is written.
+
+use std::ffi::c_void;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, Once};
+use cocoa::base::{id, nil, BOOL, NO};
+use cocoa::foundation::{NSAutoreleasePool, NSRect, NSString};
+use lazy_static::lazy_static;
+use objc::declare::ClassDecl;
+use objc::runtime::{Class, Object, Sel};
+use objc::{class, msg_send, sel, sel_impl};
+use wry::WebViewExtMacOS;
+use tracing::error;
+
+use crate::print::{self, PrintJob};
+
+const DELEGATE_CLASS: &str = "MikoPrintDelegate";
+
+lazy_static! {
+    /// The PDF being written and the request to answer; one at a time
+    static ref PENDING: Mutex<Option<(PathBuf, Option<String>)>> = Mutex::new(None);
+}
+
+pub fn run(webview: &wry::WebView, job: PrintJob) {
+    match job {
+        PrintJob::Dialog => {
+            if let Err(e) = webview.print() {
+                error!("Printing failed: {}", e);
+                report_failure(&format!("The page could not be printed.\n\n{}", e));
+            }
+        }
+        PrintJob::Pdf { path, request_id } => {
+            let busy = {
+                let mut pending = PENDING.lock().unwrap();
+                let busy = pending.is_some();
+                if !busy {
+                    // Stored first: the delegate may be told before the call returns
+                    *pending = Some((path.clone(), request_id.clone()));
+                }
+                busy
+            };
+            let result = if busy {
+                Err("another PDF is still being written".to_string())
+            } else {
+                unsafe { start_pdf(webview, &path) }.inspect_err(|_| {
+                    PENDING.lock().unwrap().take();
+                })
+            };
+            if let Err(e) = result {
+                report_failure(&format!("The PDF could not be saved.\n\n{}", e));
+                print::pdf_finished(request_id.as_deref(), &path, Err(e));
+            }
+        }
+    }
+}
+
+unsafe fn start_pdf(webview: &wry::WebView, path: &Path) -> Result<(), String> {
+    let wk_webview = webview.webview();
+    let view = &*wk_webview as *const _ as id;
+    let can_print: BOOL = msg_send![view, respondsToSelector: sel!(printOperationWithPrintInfo:)];
+    if can_print == NO {
+        return Err("printing web pages needs macOS 11 or later".to_string());
+    }
+
+    let shared: id = msg_send![class!(NSPrintInfo), sharedPrintInfo];
+    let info: id = msg_send![shared, copy];
+    let info = info.autorelease();
+    let _: () = msg_send![info, setJobDisposition: NSString::alloc(nil).init_str("NSPrintSaveJob").autorelease()];
+    let path = NSString::alloc(nil).init_str(&path.to_string_lossy()).autorelease();
+    let url: id = msg_send![class!(NSURL), fileURLWithPath: path];
+    let settings: id = msg_send![info, dictionary];
+    let _: () = msg_send![settings, setObject: url forKey: NSString::alloc(nil).init_str("NSPrintJobSavingURL").autorelease()];
+
+    let operation: id = msg_send![view, printOperationWithPrintInfo: info];
+    if operation == nil {
+        return Err("WebKit did not create a print operation".to_string());
+    }
+    let _: () = msg_send![operation, setShowsPrintPanel: NO];
+    let _: () = msg_send![operation, setShowsProgressPanel: NO];
+    // The operation's view starts with an empty frame and would print blank pages
+    let bounds: NSRect = msg_send![view, bounds];
+    let operation_view: id = msg_send![operation, view];
+    let _: () = msg_send![operation_view, setFrame: bounds];
+
+    let window: id = msg_send![view, window];
+    // Released by `operation_finished`
+    let delegate: id = msg_send![delegate_class(), new];
+    let _: () = msg_send![operation,
+        runOperationModalForWindow: window
+        delegate: delegate
+        didRunSelector: sel!(printOperationDidRun:success:contextInfo:)
+        contextInfo: std::ptr::null_mut::<c_void>()];
+    Ok(())
+}
+
+fn delegate_class() -> &'static Class {
+    static REGISTER: Once = Once::new();
+    REGISTER.call_once(|| {
+        let mut decl = ClassDecl::new(DELEGATE_CLASS, class!(NSObject)).expect("print delegate class registered twice");
+        unsafe {
+            decl.add_method(
+                sel!(printOperationDidRun:success:contextInfo:),
+                operation_finished as extern "C" fn(&Object, Sel, id, BOOL, *mut c_void),
+            );
+        }
+        decl.register();
+    });
+    Class::get(DELEGATE_CLASS).unwrap()
+}
+
+extern "C" fn operation_finished(this: &Object, _sel: Sel, _operation: id, success: BOOL, _context: *mut c_void) {
+    if let Some((path, request_id)) = PENDING.lock().unwrap().take() {
+        let result = if success != NO { Ok(()) } else { Err("the print operation failed".to_string()) };
+        if let Err(e) = &result {
+            report_failure(&format!("The PDF could not be saved.\n\n{}", e));
+        }
+        print::pdf_finished(request_id.as_deref(), &path, result);
+    }
+    unsafe {
+        let _: id = msg_send![this, autorelease];
+    }
+}
+
+/// The AppleScript dialog blocks, so it must not run on the UI thread
+fn report_failure(message: &str) {
+    let message = message.to_string();
+    std::thread::spawn(move || super::utils::show_message("Print", &message));
+}
@@ -0,0 +1,53 @@
+//! WKWebView web content process crash detection.
+//!
+//! WebKit reports a dead content process to the navigation delegate, which
+//! belongs to wry, so `webViewWebContentProcessDidTerminate:` is added to the
+//! delegate's class at runtime. The failure goes to [`crate::renderer`], which
+//! decides between a delayed reload and giving up with a dialog.
+
+use std::ffi::CString;
+use cocoa::base::{id, nil, BOOL, NO};
+use objc::runtime::{class_addMethod, Class, Imp, Object, Sel};
+use objc::{msg_send, sel, sel_impl};
+use wry::WebViewExtMacOS;
+use tracing::{info, warn};
+
+use crate::renderer::{self, Recovery};
+
+pub fn install_process_terminated_handler(webview: &wry::WebView) {
+    unsafe {
+        let wk_webview = webview.webview();
+        let view = &*wk_webview as *const _ as id;
+        let delegate: id = msg_send![view, navigationDelegate];
+        if delegate == nil {
+            warn!("WKWebView has no navigation delegate, renderer crashes won't be detected");
+            return;
+        }
+
+        let selector = sel!(webViewWebContentProcessDidTerminate:);
+        let handled: BOOL = msg_send![delegate, respondsToSelector: selector];
+        if handled != NO {
+            info!("The navigation delegate already handles web content process termination");
+            return;
+        }
+
+        let class: *const Class = msg_send![delegate, class];
+        let types = CString::new("v@:@").unwrap();
+        let imp: Imp = std::mem::transmute(process_terminated as extern "C" fn(&Object, Sel, id));
+        if class_addMethod(class as *mut Class, selector, imp, types.as_ptr()) == NO {
+            warn!("Failed to add the web content process termination handler");
+        }
+    }
+}
+
+extern "C" fn process_terminated(_this: &Object, _sel: Sel, _webview: id) {
+    match renderer::failed("web content process terminated") {
+        Recovery::Reload => renderer::schedule_reload(|| {
+            super::send_app_event(super::AppEvent::ReloadPage);
+        }),
+        // The AppleScript dialog blocks, so it must not run on the UI thread
+        Recovery::GiveUp(message) => {
+            std::thread::spawn(move || super::utils::show_message("Workspace", &message));
+        }
+    }
+}
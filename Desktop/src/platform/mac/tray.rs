@@ -1,18 +1,11 @@
 use tray_icon::{TrayIcon, TrayIconBuilder, menu::{Menu, MenuItem, MenuEvent, PredefinedMenuItem}};
 use std::sync::Arc;
 use winit::window::Window;
-use crate::platform::mac::{ICON_BYTES, TRAY_ICON_CREATED};
+use crate::platform::tray_menu::RecentAction;
+use tracing::{info, warn};
 
-pub fn create_tray_icon(window: Option<Arc<Window>>) -> Result<TrayIcon, Box<dyn std::error::Error>> {
-    // Check global flag to prevent multiple tray icons system-wide
-    {
-        let mut created = TRAY_ICON_CREATED.lock().unwrap();
-        if *created {
-            return Err("Tray icon already exists globally".into());
-        }
-        *created = true;
-    }
-    
+/// The tray menu; rebuilt with [`refresh_menu`] when the download history changes
+fn build_menu() -> Result<Menu, tray_icon::menu::Error> {
     // Create tray menu with explicit IDs to avoid conflicts
     let show_window = MenuItem::with_id("show_window", "Show Window", true, None);
     let hide_window = MenuItem::with_id("hide_window", "Hide Window", true, None);
@@ -32,39 +25,33 @@ pub fn create_tray_icon(window: Option<Arc<Window>>) -> Result<TrayIcon, Box<dyn
     menu.append(&separator1)?;
     menu.append(&open_workspace)?;
     menu.append(&open_downloads)?;
+    crate::platform::tray_menu::append_recent_downloads(&menu)?;
     menu.append(&separator2)?;
     menu.append(&about)?;
     menu.append(&separator3)?;
     menu.append(&exit)?;
-    
-    // Create tray icon using the existing ICON_BYTES
+    Ok(menu)
+}
+
+pub fn refresh_menu(tray: &TrayIcon) {
+    match build_menu() {
+        Ok(menu) => tray.set_menu(Some(Box::new(menu))),
+        Err(e) => warn!("Failed to rebuild the tray menu: {}", e),
+    }
+}
+
+pub fn create_tray_icon(window: Option<Arc<Window>>) -> Result<TrayIcon, Box<dyn std::error::Error>> {
+    // Create tray icon from the shared app icon
     let tray_icon = TrayIconBuilder::new()
-        .with_menu(Box::new(menu))
+        .with_menu(Box::new(build_menu()?))
         .with_tooltip("Workspace - macOS Desktop Application")
         .with_icon({
-            match ico::IconDir::read(std::io::Cursor::new(ICON_BYTES)) {
-                Ok(icon_dir) => {
-                    if let Some(entry) = icon_dir.entries().iter()
-                        .max_by_key(|entry| (entry.width() as u32, entry.height() as u32, entry.bits_per_pixel())) {
-                        match entry.decode() {
-                            Ok(image) => {
-                                let rgba_data = image.rgba_data().to_vec();
-                                let width = image.width();
-                                let height = image.height();
-                                tray_icon::Icon::from_rgba(rgba_data, width, height)?
-                            }
-                            Err(_) => fallback_icon()?
-                        }
-                    } else {
-                        fallback_icon()?
-                    }
-                }
-                Err(_) => fallback_icon()?
-            }
+            let icon = crate::icons::load_app_icon(crate::icons::TRAY_ICON_SIZE);
+            tray_icon::Icon::from_rgba(icon.rgba.clone(), icon.width, icon.height)?
         })
         .build()?;
     
-    println!("✅ macOS tray icon created with ID-based menu items");
+    info!("macOS tray icon created with ID-based menu items");
     
     // Handle menu events
     let menu_channel = MenuEvent::receiver();
@@ -73,7 +60,7 @@ pub fn create_tray_icon(window: Option<Arc<Window>>) -> Result<TrayIcon, Box<dyn
     std::thread::spawn(move || {
         loop {
             if let Ok(event) = menu_channel.recv() {
-                println!("🔔 macOS tray menu event received: {}", event.id.0);
+                info!("macOS tray menu event received: {}", event.id.0);
                 match event.id.0.as_str() {
                     "show_window" => {
                         if let Some(window) = &window_ref {
@@ -90,7 +77,7 @@ pub fn create_tray_icon(window: Option<Arc<Window>>) -> Result<TrayIcon, Box<dyn
                         let _ = std::process::Command::new("open").arg("http://10.10.60.8:1669").spawn();
                     }
                     "open_downloads" => {
-                        let downloads_dir = dirs::download_dir().unwrap_or_else(|| std::env::current_dir().unwrap().join("Downloads"));
+                        let downloads_dir = crate::core::downloads::download_dir();
                         let _ = std::process::Command::new("open").arg(&downloads_dir).spawn();
                     }
                     "about" => {
@@ -98,10 +85,32 @@ pub fn create_tray_icon(window: Option<Arc<Window>>) -> Result<TrayIcon, Box<dyn
                         let _ = std::process::Command::new("osascript").arg("-e").arg(script).spawn();
                     }
                     "exit" => {
-                        if let Ok(mut created) = TRAY_ICON_CREATED.lock() { *created = false; }
-                        std::process::exit(0);
+                        use crate::core::shutdown::{self, InFlight, QuitChoice};
+
+                        // Asked here: the dialog blocks, and this isn't the UI thread
+                        let in_flight = InFlight::current();
+                        let choice = if in_flight.is_empty() { QuitChoice::Quit } else { super::utils::ask_quit(&in_flight.describe()) };
+                        match choice {
+                            QuitChoice::Quit => {
+                                shutdown::begin_quit();
+                                std::process::exit(0);
+                            }
+                            QuitChoice::FinishInBackground => {
+                                if let Some(window) = &window_ref {
+                                    window.set_visible(false);
+                                }
+                                shutdown::exit_when_idle(|| std::process::exit(0));
+                            }
+                            QuitChoice::Stay => {}
+                        }
                     }
-                    _ => {}
+                    id => match crate::platform::tray_menu::recent_action(id) {
+                        Some(RecentAction::Open(path)) => {
+                            let _ = std::process::Command::new("open").arg(&path).spawn();
+                        }
+                        Some(RecentAction::Show(path)) => super::download::show_file_in_finder(&path.to_string_lossy()),
+                        None => warn!("Unknown tray menu action: {}", event.id.0),
+                    },
                 }
             }
         }
@@ -109,9 +118,3 @@ pub fn create_tray_icon(window: Option<Arc<Window>>) -> Result<TrayIcon, Box<dyn
     
     Ok(tray_icon)
 }
-
-fn fallback_icon() -> Result<tray_icon::Icon, Box<dyn std::error::Error>> {
-    let size = 32;
-    let rgba_data = vec![128; (size * size * 4) as usize];
-    Ok(tray_icon::Icon::from_rgba(rgba_data, size, size)?)
-}
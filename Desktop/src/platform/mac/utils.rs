@@ -1,82 +1,23 @@
 use winit::window::Icon;
-use crate::platform::mac::ICON_BYTES;
+use std::time::Duration;
+use tracing::{error, info, warn};
 
 pub fn load_window_icon() -> Option<Icon> {
-    // First try to load from embedded bytes
-    println!("Attempting to load window icon from embedded bytes ({} bytes)", ICON_BYTES.len());
-    
-    match ico::IconDir::read(std::io::Cursor::new(ICON_BYTES)) {
-        Ok(icon_dir) => {
-            println!("ICO file parsed successfully, {} entries found", icon_dir.entries().len());
-            
-            // Find the best icon (largest size, highest bit depth)
-            if let Some(entry) = icon_dir.entries().iter()
-                .max_by_key(|entry| (entry.width() as u32, entry.height() as u32, entry.bits_per_pixel())) {
-                
-                println!("Selected icon entry: {}x{} @ {} bpp", entry.width(), entry.height(), entry.bits_per_pixel());
-                
-                match entry.decode() {
-                    Ok(image) => {
-                        let rgba_data = image.rgba_data().to_vec();
-                        let width = image.width();
-                        let height = image.height();
-                        
-                        println!("Icon decoded to RGBA: {}x{}, {} bytes", width, height, rgba_data.len());
-                        
-                        match Icon::from_rgba(rgba_data, width, height) {
-                            Ok(icon) => {
-                                println!("✅ Window icon loaded successfully ({}x{})", width, height);
-                                return Some(icon);
-                            }
-                            Err(e) => {
-                                println!("⚠️ Failed to create winit icon from RGBA data: {}", e);
-                            }
-                        }
-                    }
-                    Err(e) => {
-                        println!("⚠️ Failed to decode icon entry: {}", e);
-                    }
-                }
-            } else {
-                println!("⚠️ No icon entries found in ICO file");
-            }
+    let icon = crate::icons::load_app_icon(crate::icons::WINDOW_ICON_SIZE);
+    match Icon::from_rgba(icon.rgba.clone(), icon.width, icon.height) {
+        Ok(window_icon) => {
+            info!("Window icon loaded successfully ({}x{})", icon.width, icon.height);
+            Some(window_icon)
         }
         Err(e) => {
-            println!("⚠️ Failed to parse embedded ICO file: {}", e);
-        }
-    }
-    
-    // Fallback: try to create a simple colored icon
-    println!("Creating fallback icon...");
-    let size = 32;
-    let mut rgba_data = Vec::with_capacity((size * size * 4) as usize);
-    
-    for y in 0..size {
-        for x in 0..size {
-            // Create a simple gradient icon
-            let r = ((x as f32 / size as f32) * 255.0) as u8;
-            let g = ((y as f32 / size as f32) * 255.0) as u8;
-            let b = 128;
-            let a = 255;
-            
-            rgba_data.extend_from_slice(&[r, g, b, a]);
-        }
-    }
-    
-    match Icon::from_rgba(rgba_data, size, size) {
-        Ok(icon) => {
-            println!("✅ Fallback icon created successfully ({}x{})", size, size);
-            Some(icon)
-        }
-        Err(e) => {
-            println!("⚠️ Failed to create fallback icon: {}", e);
+            warn!("Failed to create winit icon from RGBA data: {}", e);
             None
         }
     }
 }
 
 pub fn show_notification(title: &str, message: &str) {
-    println!("📢 Notification: {} - {}", title, message);
+    info!("Notification: {} - {}", title, message);
     
     // Use macOS osascript to show notification
     let script = format!(
@@ -90,10 +31,421 @@ pub fn show_notification(title: &str, message: &str) {
     
     match command.spawn() {
         Ok(_) => {
-            println!("✅ macOS notification sent");
+            info!("macOS notification sent");
+        }
+        Err(e) => {
+            error!("Failed to send macOS notification: {}", e);
+        }
+    }
+}
+
+/// Ask for a destination file with the native save panel (via osascript)
+pub fn choose_save_path(default_name: &str, prompt: &str) -> Option<std::path::PathBuf> {
+    let script = format!(
+        r#"POSIX path of (choose file name with prompt "{}" default name "{}")"#,
+        prompt.replace('"', "\\\""),
+        default_name.replace('"', "\\\"")
+    );
+
+    // A cancelled panel makes osascript exit with an error
+    let output = std::process::Command::new("osascript").arg("-e").arg(&script).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+
+    let path = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (!path.is_empty()).then(|| std::path::PathBuf::from(path))
+}
+
+/// Ask for a folder with the native folder panel (via osascript)
+pub fn choose_folder(prompt: &str) -> Option<std::path::PathBuf> {
+    let script = format!(r#"POSIX path of (choose folder with prompt "{}")"#, prompt.replace('"', "\\\""));
+
+    // A cancelled panel makes osascript exit with an error
+    let output = std::process::Command::new("osascript").arg("-e").arg(&script).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+
+    let path = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (!path.is_empty()).then(|| std::path::PathBuf::from(path))
+}
+
+/// Let the user pick a location and write the diagnostics archive there
+pub fn export_diagnostics() -> serde_json::Value {
+    let Some(path) = choose_save_path(&crate::diagnostics::default_export_name(), "Export diagnostic logs") else {
+        return serde_json::json!({ "success": false, "cancelled": true });
+    };
+
+    match crate::diagnostics::export_logs(&path) {
+        Ok(path) => serde_json::json!({ "success": true, "path": path.to_string_lossy() }),
+        Err(e) => {
+            warn!("Failed to export diagnostic logs: {}", e);
+            serde_json::json!({ "success": false, "error": e.to_string() })
+        }
+    }
+}
+
+/// Let the user pick a location and export the thread there
+pub fn export_thread(request: crate::export::ExportRequest) -> serde_json::Value {
+    let default_name = crate::export::default_file_name(&request.thread_id, request.format);
+    let Some(path) = choose_save_path(&default_name, "Export conversation") else {
+        return serde_json::json!({ "success": false, "threadId": request.thread_id, "cancelled": true });
+    };
+    crate::export::export_to(&request, &path)
+}
+
+/// Show `label` on the Dock icon, or clear the badge with `None`.
+/// Must be called on the main thread.
+pub fn set_dock_badge(label: Option<&str>) {
+    use cocoa::base::{id, nil};
+    use cocoa::foundation::NSString;
+    use objc::{msg_send, sel, sel_impl};
+
+    unsafe {
+        let app: id = cocoa::appkit::NSApp();
+        if app == nil {
+            return;
+        }
+        let dock_tile: id = msg_send![app, dockTile];
+        let badge: id = match label {
+            Some(label) => {
+                let label: id = NSString::alloc(nil).init_str(label);
+                msg_send![label, autorelease]
+            }
+            None => nil,
+        };
+        let _: () = msg_send![dock_tile, setBadgeLabel: badge];
+    }
+}
+
+/// Turn off WebKit's accelerated drawing for `webview` (`rendering.disable_gpu`).
+/// The preferences are private, so they're only set where WebKit still has them.
+/// Must be called on the main thread.
+pub fn disable_accelerated_drawing(webview: &wry::WebView) {
+    use cocoa::base::{id, BOOL, NO};
+    use objc::{msg_send, sel, sel_impl};
+    use wry::WebViewExtMacOS;
+
+    unsafe {
+        let wk_webview = webview.webview();
+        let view = &*wk_webview as *const _ as id;
+        let configuration: id = msg_send![view, configuration];
+        let preferences: id = msg_send![configuration, preferences];
+        let drawing: BOOL = msg_send![preferences, respondsToSelector: sel!(_setAcceleratedDrawingEnabled:)];
+        if drawing != NO {
+            let _: () = msg_send![preferences, _setAcceleratedDrawingEnabled: NO];
+        }
+        let canvas: BOOL = msg_send![preferences, respondsToSelector: sel!(_setCanvasUsesAcceleratedDrawing:)];
+        if canvas != NO {
+            let _: () = msg_send![preferences, _setCanvasUsesAcceleratedDrawing: NO];
+        }
+        if drawing == NO && canvas == NO {
+            warn!("This WebKit has no accelerated drawing preferences, GPU rendering stays on");
+        }
+    }
+}
+
+/// Keep `window` out of screenshots, recordings and screen shares
+/// (`privacy.block_capture`) through its `sharingType`. Must be called on the
+/// main thread.
+pub fn set_capture_excluded(window: &winit::window::Window, excluded: bool) {
+    use cocoa::base::id;
+    use objc::{msg_send, sel, sel_impl};
+    use winit::raw_window_handle::{HasWindowHandle, RawWindowHandle};
+
+    /// `NSWindowSharingNone` / `NSWindowSharingReadOnly`
+    const SHARING_NONE: usize = 0;
+    const SHARING_READ_ONLY: usize = 1;
+
+    let Ok(RawWindowHandle::AppKit(handle)) = window.window_handle().map(|h| h.as_raw()) else { return };
+    unsafe {
+        let view = handle.ns_view.as_ptr() as id;
+        let ns_window: id = msg_send![view, window];
+        let sharing = if excluded { SHARING_NONE } else { SHARING_READ_ONLY };
+        let _: () = msg_send![ns_window, setSharingType: sharing];
+    }
+}
+
+/// The part of `monitor` not taken by the Dock and menu bar, in physical
+/// pixels: `visibleFrame`'s insets within `frame`, scaled onto the monitor
+pub fn monitor_work_area(monitor: &winit::monitor::MonitorHandle) -> Option<crate::window_layout::Area> {
+    use cocoa::base::id;
+    use cocoa::foundation::NSRect;
+    use objc::{msg_send, sel, sel_impl};
+    use winit::platform::macos::MonitorHandleExtMacOS;
+
+    let screen = monitor.ns_screen()? as id;
+    let (frame, visible): (NSRect, NSRect) = unsafe { (msg_send![screen, frame], msg_send![screen, visibleFrame]) };
+    let scale = monitor.scale_factor();
+    // AppKit's origin is the bottom left
+    let left = (visible.origin.x - frame.origin.x) * scale;
+    let bottom = (visible.origin.y - frame.origin.y) * scale;
+    let top = (frame.origin.y + frame.size.height - visible.origin.y - visible.size.height) * scale;
+    let (position, size) = (monitor.position(), monitor.size());
+    Some(crate::window_layout::Area {
+        x: position.x + left.round() as i32,
+        y: position.y + top.round() as i32,
+        width: (visible.size.width * scale).round() as u32,
+        height: (size.height as f64 - top - bottom).max(0.0).round() as u32,
+    })
+}
+
+/// Modal two-button question via AppleScript; true when `accept` was clicked
+pub fn confirm(title: &str, message: &str, accept: &str, decline: &str) -> bool {
+    let escape = |s: &str| s.replace('\\', "\\\\").replace('"', "\\\"");
+    let script = format!(
+        r#"button returned of (display dialog "{}" with title "{}" buttons {{"{}", "{}"}} default button "{}")"#,
+        escape(message),
+        escape(title),
+        escape(decline),
+        escape(accept),
+        escape(accept)
+    );
+    match std::process::Command::new("osascript").arg("-e").arg(&script).output() {
+        Ok(output) => output.status.success() && String::from_utf8_lossy(&output.stdout).trim() == accept,
+        Err(e) => {
+            warn!("Failed to show dialog: {}", e);
+            false
+        }
+    }
+}
+
+/// Ask whether to quit while transfers are running
+pub fn ask_quit(in_flight: &str) -> crate::core::shutdown::QuitChoice {
+    use crate::core::shutdown::QuitChoice;
+
+    const QUIT: &str = "Quit Anyway";
+    const BACKGROUND: &str = "Finish in Background";
+    let script = format!(
+        r#"button returned of (display dialog "{} Quit anyway? Interrupted downloads resume next time." with title "Workspace" buttons {{"Cancel", "{}", "{}"}} default button "{}" cancel button "Cancel" with icon caution)"#,
+        in_flight.replace('\\', "\\\\").replace('"', "\\\""),
+        BACKGROUND,
+        QUIT,
+        BACKGROUND
+    );
+    match std::process::Command::new("osascript").arg("-e").arg(&script).output() {
+        Ok(output) if output.status.success() => match String::from_utf8_lossy(&output.stdout).trim() {
+            QUIT => QuitChoice::Quit,
+            BACKGROUND => QuitChoice::FinishInBackground,
+            _ => QuitChoice::Stay,
+        },
+        Ok(_) => QuitChoice::Stay,
+        Err(e) => {
+            warn!("Failed to show dialog: {}", e);
+            QuitChoice::Quit
         }
+    }
+}
+
+/// Shift is held down right now (safe-mode launch); `NSEvent` answers this
+/// before the app has a window or event loop
+pub fn shift_held() -> bool {
+    use objc::{class, msg_send, sel, sel_impl};
+
+    const NS_EVENT_MODIFIER_FLAG_SHIFT: usize = 1 << 17;
+    let flags: usize = unsafe { msg_send![class!(NSEvent), modifierFlags] };
+    flags & NS_EVENT_MODIFIER_FLAG_SHIFT != 0
+}
+
+/// Pick any number of `items`; `None` when the dialog was cancelled
+pub fn choose_from_list(title: &str, prompt: &str, items: &[&str], ok: &str, cancel: &str) -> Option<Vec<String>> {
+    let escape = |s: &str| s.replace('\\', "\\\\").replace('"', "\\\"");
+    let list = items.iter().map(|item| format!("\"{}\"", escape(item))).collect::<Vec<_>>().join(", ");
+    let script = format!(
+        r#"set picked to choose from list {{{}}} with title "{}" with prompt "{}" OK button name "{}" cancel button name "{}" with multiple selections allowed and empty selection allowed
+if picked is false then return "false"
+set AppleScript's text item delimiters to linefeed
+return picked as text"#,
+        list,
+        escape(title),
+        escape(prompt),
+        escape(ok),
+        escape(cancel)
+    );
+    match std::process::Command::new("osascript").arg("-e").arg(&script).output() {
+        Ok(output) if output.status.success() => {
+            let text = String::from_utf8_lossy(&output.stdout).trim_end().to_string();
+            if text == "false" {
+                return None;
+            }
+            Some(text.lines().filter(|line| !line.is_empty()).map(|line| line.to_string()).collect())
+        }
+        Ok(_) => None,
+        Err(e) => {
+            warn!("Failed to show dialog: {}", e);
+            None
+        }
+    }
+}
+
+/// Modal message with an OK button
+pub fn show_message(title: &str, message: &str) {
+    let script = format!(
+        r#"display dialog "{}" with title "{}" buttons {{"OK"}} default button "OK""#,
+        message.replace('"', "\\\""),
+        title.replace('"', "\\\"")
+    );
+    let _ = std::process::Command::new("osascript").arg("-e").arg(&script).status();
+}
+
+/// Show a downloaded file in Quick Look; `qlmanage -p` opens the same panel
+/// Finder's space bar does, and Escape closes it. Blocks until it is closed.
+pub fn quick_look(path: &std::path::Path) {
+    info!("Previewing {}", path.display());
+    match std::process::Command::new("qlmanage")
+        .arg("-p")
+        .arg(path)
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .status()
+    {
+        Ok(_) => {}
         Err(e) => {
-            println!("❌ Failed to send macOS notification: {}", e);
+            warn!("Quick Look failed, opening {} instead: {}", path.display(), e);
+            let _ = std::process::Command::new("open").arg(path).spawn();
+        }
+    }
+}
+
+/// Open the downloaded DMG (mounts it) or pkg (starts Installer)
+pub fn launch_installer(path: &std::path::Path) -> Result<(), String> {
+    let status = std::process::Command::new("open").arg(path).status().map_err(|e| e.to_string())?;
+    if status.success() {
+        info!("Opened installer {}", path.display());
+        Ok(())
+    } else {
+        Err(format!("open exited with {}", status))
+    }
+}
+
+/// Download and verify the available update, then open it after
+/// confirmation, closing the app. Declining snoozes the update.
+pub fn install_update() -> serde_json::Value {
+    if !crate::metered::allow("Downloading the update") {
+        crate::metered::when_unmetered(|| {
+            if let Some(error) = install_update()["error"].as_str() {
+                show_notification("Update Failed", error);
+            }
+        });
+        return serde_json::json!({ "success": true, "installed": false, "deferred": true });
+    }
+    let prepared = crate::updates::prepare_installer(|url, path| {
+        let filename = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+        super::download::run_downloader(url, path, &filename, true, None)
+    });
+    let (manifest, path) = match prepared {
+        Ok(prepared) => prepared,
+        Err(e) => return serde_json::json!({ "success": false, "error": e }),
+    };
+
+    let message = format!(
+        "Workspace {} has been downloaded. Install it now? Workspace will quit so it can be replaced.",
+        manifest.latest_version
+    );
+    if !confirm("Install Update", &message, "Install", "Later") {
+        crate::updates::remind_later(&manifest.latest_version);
+        return serde_json::json!({ "success": true, "installed": false, "version": manifest.latest_version });
+    }
+
+    match launch_installer(&path) {
+        Ok(()) => {
+            super::send_app_event(super::AppEvent::Quit);
+            serde_json::json!({ "success": true, "installed": true, "version": manifest.latest_version })
+        }
+        Err(e) => {
+            warn!("Failed to open installer: {}", e);
+            serde_json::json!({ "success": false, "error": e })
+        }
+    }
+}
+
+/// Check for updates and offer to install one. `interactive` ignores
+/// "remind me later" and also reports when nothing is available.
+pub fn check_for_updates(interactive: bool) {
+    let manifest = match crate::updates::check() {
+        Ok(Some(manifest)) => manifest,
+        Ok(None) => {
+            if interactive {
+                let message = format!("You are using the latest version of Workspace ({}).", env!("CARGO_PKG_VERSION"));
+                show_message("Check for Updates", &message);
+            }
+            return;
+        }
+        Err(e) => {
+            warn!("{}", e);
+            return;
+        }
+    };
+
+    if !interactive && crate::updates::is_snoozed(&manifest.latest_version) {
+        info!("Update {} postponed by the user", manifest.latest_version);
+        return;
+    }
+
+    let message = format!(
+        "Workspace {} is available (you have {}). Download the update now?",
+        manifest.latest_version,
+        env!("CARGO_PKG_VERSION")
+    );
+    if confirm("Update Available", &message, "Download", "Remind Me Later") {
+        let result = install_update();
+        if let Some(error) = result["error"].as_str() {
+            show_notification("Update Failed", error);
+        }
+    } else {
+        crate::updates::remind_later(&manifest.latest_version);
+    }
+}
+
+#[link(name = "CoreGraphics", kind = "framework")]
+extern "C" {
+    fn CGPreflightScreenCaptureAccess() -> bool;
+    fn CGRequestScreenCaptureAccess() -> bool;
+    fn CGEventSourceSecondsSinceLastEventType(state: i32, event_type: u32) -> f64;
+}
+
+/// kCGEventSourceStateCombinedSessionState
+const COMBINED_SESSION_STATE: i32 = 0;
+/// kCGAnyInputEventType
+const ANY_INPUT_EVENT: u32 = !0;
+
+/// Time since the last keyboard or mouse input in this session
+pub fn idle_time() -> Option<Duration> {
+    let seconds = unsafe { CGEventSourceSecondsSinceLastEventType(COMBINED_SESSION_STATE, ANY_INPUT_EVENT) };
+    (seconds.is_finite() && seconds >= 0.0).then(|| Duration::from_secs_f64(seconds))
+}
+
+/// Trigger the Screen Recording (TCC) prompt if access hasn't been granted.
+/// macOS only asks once; afterwards the user has to enable it in System
+/// Settings and restart the app.
+pub fn request_screen_capture_access() {
+    unsafe {
+        if CGPreflightScreenCaptureAccess() {
+            return;
+        }
+        if !CGRequestScreenCaptureAccess() {
+            warn!("Screen recording permission not granted");
+        }
+    }
+}
+
+/// Teach `words` to the system spellchecker WebKit uses. It has one shared
+/// dictionary, so `languages` doesn't matter here.
+pub fn learn_words(words: &[String], _languages: &[String]) -> Result<(), String> {
+    use cocoa::base::{id, nil};
+    use cocoa::foundation::{NSAutoreleasePool, NSString};
+    use objc::{class, msg_send, sel, sel_impl};
+
+    unsafe {
+        let checker: id = msg_send![class!(NSSpellChecker), sharedSpellChecker];
+        if checker == nil {
+            return Err("no system spellchecker".to_string());
+        }
+        for word in words {
+            let word = NSString::alloc(nil).init_str(word).autorelease();
+            let _: () = msg_send![checker, learnWord: word];
         }
     }
+    Ok(())
 }
@@ -1,3 +1,7 @@
+pub mod subprocess;
+#[cfg(any(target_os = "windows", target_os = "macos"))]
+pub mod tray_menu;
+
 #[cfg(target_os = "windows")]
 pub mod win;
 
@@ -0,0 +1,193 @@
+//! Supervision of helper processes (the downloader) that talk JSON lines on stdout.
+//!
+//! stdout and stderr are each drained on their own thread so the child can
+//! never block on a full pipe, whatever the supervising thread is doing.
+
+use std::io::{BufRead, BufReader, Read};
+use std::process::{Child, ExitStatus};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::time::Duration;
+use tracing::{error, warn};
+
+/// Only the tail of stderr is kept for error reports
+const MAX_STDERR_BYTES: usize = 16 * 1024;
+/// How often the stop flag is checked while the child is quiet
+const STOP_POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+pub struct ProcessOutcome {
+    pub status: std::io::Result<ExitStatus>,
+    /// Last part of what the child wrote to stderr
+    pub stderr: String,
+}
+
+impl ProcessOutcome {
+    pub fn success(&self) -> bool {
+        matches!(&self.status, Ok(status) if status.success())
+    }
+
+    /// Human-readable failure reason, preferring the child's own stderr
+    pub fn error_message(&self) -> String {
+        let stderr = self.stderr.trim();
+        match &self.status {
+            Ok(status) if !stderr.is_empty() => format!("exit code {:?}: {}", status.code(), stderr),
+            Ok(status) => format!("exit code {:?}", status.code()),
+            Err(e) => format!("failed to wait for process: {}", e),
+        }
+    }
+}
+
+/// Read `child` to completion, calling `on_line` for every stdout line as it
+/// arrives, then reap it. Reading continues after a final status line so the
+/// child is never killed by a closed pipe while printing its summary. Setting
+/// `stop` kills the child.
+pub fn supervise(mut child: Child, stop: Option<&AtomicBool>, mut on_line: impl FnMut(&str)) -> ProcessOutcome {
+    let stderr_reader = child.stderr.take().map(|stderr| {
+        std::thread::spawn(move || {
+            let mut tail: Vec<u8> = Vec::new();
+            let mut reader = BufReader::new(stderr);
+            let mut buf = [0u8; 4096];
+            loop {
+                match reader.read(&mut buf) {
+                    Ok(0) => break,
+                    Ok(n) => {
+                        tail.extend_from_slice(&buf[..n]);
+                        if tail.len() > MAX_STDERR_BYTES {
+                            tail.drain(..tail.len() - MAX_STDERR_BYTES);
+                        }
+                    }
+                    Err(e) => {
+                        warn!("Error reading child stderr: {}", e);
+                        break;
+                    }
+                }
+            }
+            String::from_utf8_lossy(&tail).into_owned()
+        })
+    });
+
+    let (line_tx, line_rx) = mpsc::channel::<String>();
+    let stdout_reader = child.stdout.take().map(|stdout| {
+        std::thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                match line {
+                    Ok(line) => {
+                        if line_tx.send(line).is_err() {
+                            break;
+                        }
+                    }
+                    Err(e) => {
+                        error!("Error reading child stdout: {}", e);
+                        break;
+                    }
+                }
+            }
+        })
+    });
+
+    // The channel closes once the stdout thread hits EOF
+    let mut killed = false;
+    loop {
+        match line_rx.recv_timeout(STOP_POLL_INTERVAL) {
+            Ok(line) => on_line(&line),
+            Err(mpsc::RecvTimeoutError::Timeout) => {}
+            Err(mpsc::RecvTimeoutError::Disconnected) => break,
+        }
+        if !killed && stop.is_some_and(|stop| stop.load(Ordering::SeqCst)) {
+            killed = true;
+            if let Err(e) = child.kill() {
+                warn!("Failed to stop child process {}: {}", child.id(), e);
+            }
+        }
+    }
+
+    let status = child.wait();
+    if let Some(handle) = stdout_reader {
+        let _ = handle.join();
+    }
+    let stderr = stderr_reader
+        .and_then(|handle| handle.join().ok())
+        .unwrap_or_default();
+
+    ProcessOutcome { status, stderr }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::process::{Command, Stdio};
+    use std::time::Instant;
+
+    /// A fake downloader running `script`
+    fn spawn(script: &str) -> Child {
+        Command::new("sh")
+            .arg("-c")
+            .arg(script)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap()
+    }
+
+    #[test]
+    fn lines_arrive_in_order_with_stderr_interleaved() {
+        let script = r#"
+            i=1
+            while [ $i -le 500 ]; do
+                echo "{\"type\":\"progress\",\"n\":$i}"
+                echo "retrying chunk $i" >&2
+                i=$((i + 1))
+            done
+            echo '{"type":"complete"}'
+            echo 'disk full' >&2
+            exit 3
+        "#;
+        let mut lines = Vec::new();
+        let outcome = supervise(spawn(script), None, |line| lines.push(line.to_string()));
+
+        let expected: Vec<String> = (1..=500)
+            .map(|n| format!(r#"{{"type":"progress","n":{}}}"#, n))
+            .chain([r#"{"type":"complete"}"#.to_string()])
+            .collect();
+        assert_eq!(lines, expected);
+        assert!(!outcome.success());
+        assert_eq!(outcome.status.as_ref().unwrap().code(), Some(3));
+        assert!(outcome.stderr.starts_with("retrying chunk 1\n"));
+        assert_eq!(outcome.error_message(), format!("exit code Some(3): {}", outcome.stderr.trim()));
+        assert!(outcome.error_message().ends_with("retrying chunk 500\ndisk full"));
+    }
+
+    #[test]
+    fn only_the_stderr_tail_is_kept() {
+        // Far more than a pipe buffer on stderr before stdout says anything:
+        // the child would block forever if stderr weren't drained meanwhile
+        let script = r#"
+            head -c 200000 /dev/zero | tr '\0' x >&2
+            echo '{"type":"complete"}'
+            echo ' the end' >&2
+        "#;
+        let mut lines = Vec::new();
+        let outcome = supervise(spawn(script), None, |line| lines.push(line.to_string()));
+
+        assert_eq!(lines, [r#"{"type":"complete"}"#]);
+        assert!(outcome.success());
+        assert_eq!(outcome.stderr.len(), MAX_STDERR_BYTES);
+        assert!(outcome.stderr.ends_with("xxx the end\n"));
+    }
+
+    #[test]
+    fn stop_kills_the_child() {
+        let stop = AtomicBool::new(false);
+        let started = Instant::now();
+        let mut lines = Vec::new();
+        let outcome = supervise(spawn("echo started; exec sleep 30"), Some(&stop), |line| {
+            lines.push(line.to_string());
+            stop.store(true, Ordering::SeqCst);
+        });
+
+        assert_eq!(lines, ["started"]);
+        assert!(started.elapsed() < Duration::from_secs(10), "took {:?}", started.elapsed());
+        assert!(!outcome.success());
+        assert_eq!(outcome.status.as_ref().unwrap().code(), None);
+    }
+}
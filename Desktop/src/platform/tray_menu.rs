@@ -0,0 +1,59 @@
+//! The "recent downloads" section shared by the Windows and macOS tray menus.
+//!
+//! The newest finished downloads are listed by name (click opens the file),
+//! followed by a "Show in Folder" submenu with the same entries. Entries whose
+//! file is gone are grayed out. Menu ids carry the path; it is checked against
+//! the download history again when clicked.
+
+use std::path::PathBuf;
+use tray_icon::menu::{Menu, MenuItem, PredefinedMenuItem, Submenu};
+
+const RECENT_DOWNLOADS: usize = 5;
+/// Longer names are shortened in the middle
+const MAX_NAME_CHARS: usize = 40;
+const OPEN_PREFIX: &str = "recent_open:";
+const SHOW_PREFIX: &str = "recent_show:";
+
+pub enum RecentAction {
+    Open(PathBuf),
+    Show(PathBuf),
+}
+
+/// Append the section (with a leading separator); nothing while the history is empty
+pub fn append_recent_downloads(menu: &Menu) -> Result<(), tray_icon::menu::Error> {
+    let recent = crate::core::downloads::recent(RECENT_DOWNLOADS);
+    if recent.is_empty() {
+        return Ok(());
+    }
+
+    menu.append(&PredefinedMenuItem::separator())?;
+    menu.append(&MenuItem::new("Recent Downloads", false, None))?;
+    let show = Submenu::new("Show in Folder", true);
+    for record in &recent {
+        let exists = record.path.is_file();
+        // `&` would mark a mnemonic
+        let label = crate::core::downloads::shortened_name(&record.filename, MAX_NAME_CHARS).replace('&', "&&");
+        let path = record.path.to_string_lossy();
+        menu.append(&MenuItem::with_id(format!("{}{}", OPEN_PREFIX, path), &label, exists, None))?;
+        show.append(&MenuItem::with_id(format!("{}{}", SHOW_PREFIX, path), &label, exists, None))?;
+    }
+    menu.append(&show)
+}
+
+/// The action behind a clicked menu id, if it is a recent download still in
+/// the history
+pub fn recent_action(id: &str) -> Option<RecentAction> {
+    let (path, open) = match (id.strip_prefix(OPEN_PREFIX), id.strip_prefix(SHOW_PREFIX)) {
+        (Some(path), _) => (path, true),
+        (_, Some(path)) => (path, false),
+        _ => return None,
+    };
+    match crate::core::downloads::downloaded_file(path) {
+        Ok(path) if open => Some(RecentAction::Open(path)),
+        Ok(path) => Some(RecentAction::Show(path)),
+        Err(e) => {
+            tracing::warn!("Recent download unavailable: {}", e);
+            None
+        }
+    }
+}
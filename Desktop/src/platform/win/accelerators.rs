@@ -0,0 +1,56 @@
+//! Keyboard shortcuts while the page has focus.
+//!
+//! Key presses go to WebView2, not to the window's message loop, so the menu
+//! accelerator table is never consulted on its own. `AcceleratorKeyPressed`
+//! hands every key down with a modifier (and the function keys) to the host
+//! first; those matching the shortcut map are translated into the menu
+//! command and kept from the page.
+
+use webview2_com::Microsoft::Web::WebView2::Win32::{
+    ICoreWebView2AcceleratorKeyPressedEventArgs, ICoreWebView2Controller, COREWEBVIEW2_KEY_EVENT_KIND,
+    COREWEBVIEW2_KEY_EVENT_KIND_KEY_DOWN, COREWEBVIEW2_KEY_EVENT_KIND_SYSTEM_KEY_DOWN,
+};
+use webview2_com::AcceleratorKeyPressedEventHandler;
+use windows::Win32::Foundation::HWND;
+use windows::Win32::UI::WindowsAndMessaging::{WM_KEYDOWN, WM_SYSKEYDOWN};
+use wry::WebViewExtWindows;
+use tracing::warn;
+
+pub fn install_accelerator_handler(webview: &wry::WebView, hwnd: HWND) {
+    let controller = webview.controller();
+    // HWND isn't Send; the handler runs on this (the UI) thread anyway
+    let raw_hwnd = hwnd.0 as isize;
+
+    let handler = AcceleratorKeyPressedEventHandler::create(Box::new(
+        move |_sender: Option<ICoreWebView2Controller>, args: Option<ICoreWebView2AcceleratorKeyPressedEventArgs>| {
+            let Some(args) = args else { return Ok(()) };
+            let mut kind = COREWEBVIEW2_KEY_EVENT_KIND::default();
+            unsafe { args.KeyEventKind(&mut kind)? };
+            let message = match kind {
+                COREWEBVIEW2_KEY_EVENT_KIND_KEY_DOWN => WM_KEYDOWN,
+                COREWEBVIEW2_KEY_EVENT_KIND_SYSTEM_KEY_DOWN => WM_SYSKEYDOWN,
+                _ => return Ok(()),
+            };
+
+            let mut virtual_key = 0u32;
+            let mut lparam = 0i32;
+            unsafe {
+                args.VirtualKey(&mut virtual_key)?;
+                args.KeyEventLParam(&mut lparam)?;
+            }
+
+            let hwnd = HWND(raw_hwnd as *mut std::ffi::c_void);
+            if crate::menubar::translate_accelerator(hwnd, message, virtual_key, lparam as isize) {
+                unsafe { args.SetHandled(true)? };
+                // The command is waiting in the menu hook; run it now
+                crate::ipc::wake();
+            }
+            Ok(())
+        },
+    ));
+
+    let mut token = 0i64;
+    if let Err(e) = unsafe { controller.add_AcceleratorKeyPressed(&handler, &mut token) } {
+        warn!("Failed to register AcceleratorKeyPressed handler: {}", e);
+    }
+}
@@ -0,0 +1,66 @@
+//! Start at sign-in through `HKCU\...\CurrentVersion\Run`.
+//!
+//! Task Manager's Startup tab doesn't remove the `Run` value when the user
+//! disables an entry; it flags it under `Explorer\StartupApproved\Run`, where
+//! the first byte of the value is odd while the entry is disabled. Both are
+//! read to tell whether Windows will start the app.
+
+use winreg::enums::{HKEY_CURRENT_USER, KEY_READ, KEY_WRITE, REG_BINARY};
+use winreg::{RegKey, RegValue};
+
+const RUN_KEY: &str = r"Software\Microsoft\Windows\CurrentVersion\Run";
+const APPROVED_KEY: &str = r"Software\Microsoft\Windows\CurrentVersion\Explorer\StartupApproved\Run";
+/// StartupApproved value of an enabled entry
+const APPROVED_ENABLED: [u8; 12] = [2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+
+fn quote(arg: &str) -> String {
+    if arg.contains(' ') { format!("\"{}\"", arg) } else { arg.to_string() }
+}
+
+/// Whether Windows will start the app at sign-in
+pub fn status() -> Result<bool, String> {
+    let hkcu = RegKey::predef(HKEY_CURRENT_USER);
+    let name = crate::autostart::entry_name();
+    let registered = hkcu
+        .open_subkey_with_flags(RUN_KEY, KEY_READ)
+        .and_then(|key| key.get_value::<String, _>(&name))
+        .is_ok();
+    if !registered {
+        return Ok(false);
+    }
+
+    let disabled = hkcu
+        .open_subkey_with_flags(APPROVED_KEY, KEY_READ)
+        .and_then(|key| key.get_raw_value(&name))
+        .is_ok_and(|value| value.bytes.first().is_some_and(|flag| flag % 2 == 1));
+    Ok(!disabled)
+}
+
+pub fn set(enabled: bool) -> Result<(), String> {
+    let hkcu = RegKey::predef(HKEY_CURRENT_USER);
+    let name = crate::autostart::entry_name();
+    let (run, _) = hkcu.create_subkey(RUN_KEY).map_err(|e| e.to_string())?;
+
+    if !enabled {
+        return match run.delete_value(&name) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.to_string()),
+            _ => Ok(()),
+        };
+    }
+
+    let (exe, args) = crate::autostart::launch_command()?;
+    let command = std::iter::once(format!("\"{}\"", exe.display()))
+        .chain(args.iter().map(|arg| quote(arg)))
+        .collect::<Vec<_>>()
+        .join(" ");
+    run.set_value(&name, &command).map_err(|e| e.to_string())?;
+
+    // Turning it on here overrides an earlier "Disabled" from Task Manager
+    if let Ok(approved) = hkcu.open_subkey_with_flags(APPROVED_KEY, KEY_READ | KEY_WRITE) {
+        if approved.get_raw_value(&name).is_ok() {
+            let value = RegValue { bytes: APPROVED_ENABLED.to_vec(), vtype: REG_BINARY };
+            approved.set_raw_value(&name, &value).map_err(|e| e.to_string())?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,40 @@
+//! WebView2 side of screen sharing.
+//!
+//! WebView2 presents its own monitor/window/tab picker for `getDisplayMedia`;
+//! `ScreenCaptureStarting` is where the host gets to veto a capture, which is
+//! how `media.screen_share_enabled` is enforced for every frame.
+
+use webview2_com::Microsoft::Web::WebView2::Win32::{ICoreWebView2_27, ICoreWebView2ScreenCaptureStartingEventArgs};
+use webview2_com::ScreenCaptureStartingEventHandler;
+use windows_core::Interface;
+use wry::WebViewExtWindows;
+use tracing::{info, warn};
+
+pub fn install_screen_capture_handler(webview: &wry::WebView) {
+    let core = match webview.webview().cast::<ICoreWebView2_27>() {
+        Ok(core) => core,
+        Err(e) => {
+            // Runtimes older than 1.0.2903 have no veto; the init script still blocks the API
+            warn!("ScreenCaptureStarting unavailable in this WebView2 runtime: {}", e);
+            return;
+        }
+    };
+
+    let handler = ScreenCaptureStartingEventHandler::create(Box::new(
+        |_sender, args: Option<ICoreWebView2ScreenCaptureStartingEventArgs>| {
+            let Some(args) = args else { return Ok(()) };
+            let allowed = crate::media::screen_share_enabled();
+            info!("Screen capture starting ({})", if allowed { "allowed" } else { "blocked by settings" });
+            unsafe {
+                args.SetCancel(!allowed)?;
+                args.SetHandled(true)?;
+            }
+            Ok(())
+        },
+    ));
+
+    let mut token = 0i64;
+    if let Err(e) = unsafe { core.add_ScreenCaptureStarting(&handler, &mut token) } {
+        warn!("Failed to register ScreenCaptureStarting handler: {}", e);
+    }
+}
@@ -1,123 +1,307 @@
+use std::path::Path;
 use std::process::{Command, Stdio};
-use std::io::{BufRead, BufReader};
 use serde_json;
-use crate::platform::win::PROGRESS_SENDER;
+use std::time::{Duration, Instant};
+use crate::core::downloads::{self, DownloadTarget};
+use crate::ipc::WebviewHandle;
+use tracing::{debug, error, info, warn};
 
-pub fn show_file_in_explorer(filename: &str) {
-    println!("📂 Showing file in Windows Explorer: {}", filename);
-    
-    // Determine the file path in Downloads folder
-    let downloads_dir = dirs::download_dir().unwrap_or_else(|| {
-        std::env::current_dir().unwrap().join("Downloads")
-    });
-    
-    let file_path = downloads_dir.join(filename);
-    
-    if file_path.exists() {
-        println!("✅ File exists, opening in Explorer: {}", file_path.display());
-        
-        // Use Windows Explorer with /select parameter to highlight the file
-        let mut command = std::process::Command::new("explorer");
-        command.arg("/select,").arg(&file_path);
-        
-        // Hide the console window on Windows
-        #[cfg(windows)]
-        {
-            use std::os::windows::process::CommandExt;
-            const CREATE_NO_WINDOW: u32 = 0x08000000;
-            command.creation_flags(CREATE_NO_WINDOW);
-        }
-        
-        match command.spawn() {
-            Ok(_) => {
-                println!("✅ Successfully opened file in Explorer");
-            }
-            Err(e) => {
-                println!("❌ Failed to open file in Explorer: {}", e);
-                
-                // Fallback: just open the Downloads folder
-                let mut fallback_command = std::process::Command::new("explorer");
-                fallback_command.arg(&downloads_dir);
-                
-                #[cfg(windows)]
-                {
-                    use std::os::windows::process::CommandExt;
-                    const CREATE_NO_WINDOW: u32 = 0x08000000;
-                    fallback_command.creation_flags(CREATE_NO_WINDOW);
-                }
-                
-                match fallback_command.spawn() {
-                    Ok(_) => {
-                        println!("✅ Opened Downloads folder as fallback");
-                    }
-                    Err(e2) => {
-                        println!("❌ Failed to open Downloads folder: {}", e2);
-                    }
+/// Open Explorer with the file selected. `reference` is a full path or, from
+/// older pages, a bare filename (see [`downloads::locate`]).
+pub fn show_file_in_explorer(reference: &str) {
+    info!("Showing file in Windows Explorer: {}", reference);
+
+    match downloads::locate(reference) {
+        Ok(path) => {
+            // canonicalize() yields a verbatim path the shell doesn't parse
+            let path = strip_verbatim_prefix(&path);
+            if let Err(e) = select_in_explorer(&path) {
+                error!("Failed to select {} in Explorer: {}", path.display(), e);
+                if let Some(dir) = path.parent() {
+                    open_folder(dir);
                 }
             }
         }
+        Err(dir) => {
+            error!("File not found: {}", reference);
+            open_folder(&dir);
+        }
+    }
+}
+
+/// `\\?\C:\x` -> `C:\x`, `\\?\UNC\server\share` -> `\\server\share`
+pub fn strip_verbatim_prefix(path: &Path) -> std::path::PathBuf {
+    let text = path.to_string_lossy();
+    if let Some(rest) = text.strip_prefix(r"\\?\UNC\") {
+        std::path::PathBuf::from(format!(r"\\{}", rest))
+    } else if let Some(rest) = text.strip_prefix(r"\\?\") {
+        std::path::PathBuf::from(rest)
     } else {
-        println!("❌ File not found: {}", file_path.display());
-        
-        // Just open the Downloads folder
-        let mut command = std::process::Command::new("explorer");
-        command.arg(&downloads_dir);
-        
-        #[cfg(windows)]
-        {
-            use std::os::windows::process::CommandExt;
-            const CREATE_NO_WINDOW: u32 = 0x08000000;
-            command.creation_flags(CREATE_NO_WINDOW);
+        path.to_path_buf()
+    }
+}
+
+/// Uses the shell API rather than `explorer /select,` whose command line
+/// parsing breaks on commas in the path
+fn select_in_explorer(path: &Path) -> windows::core::Result<()> {
+    use windows::core::HSTRING;
+    use windows::Win32::System::Com::{CoInitializeEx, CoTaskMemFree, IBindCtx, COINIT_APARTMENTTHREADED};
+    use windows::Win32::UI::Shell::Common::ITEMIDLIST;
+    use windows::Win32::UI::Shell::{SHOpenFolderAndSelectItems, SHParseDisplayName};
+
+    unsafe {
+        // Called on a worker thread; S_FALSE (already initialized) is fine
+        let _ = CoInitializeEx(None, COINIT_APARTMENTTHREADED);
+        let mut pidl: *mut ITEMIDLIST = std::ptr::null_mut();
+        SHParseDisplayName(&HSTRING::from(path.as_os_str()), None::<&IBindCtx>, &mut pidl, 0, None)?;
+        let result = SHOpenFolderAndSelectItems(pidl, None, 0);
+        CoTaskMemFree(Some(pidl as *const _));
+        result
+    }
+}
+
+fn open_folder(dir: &Path) {
+    use windows::core::{w, HSTRING, PCWSTR};
+    use windows::Win32::Foundation::HWND;
+    use windows::Win32::UI::Shell::ShellExecuteW;
+    use windows::Win32::UI::WindowsAndMessaging::SW_SHOWNORMAL;
+
+    let result = unsafe {
+        ShellExecuteW(HWND::default(), w!("open"), &HSTRING::from(dir.as_os_str()), PCWSTR::null(), PCWSTR::null(), SW_SHOWNORMAL)
+    };
+    if result.0 as isize > 32 {
+        info!("Opened folder {}", dir.display());
+    } else {
+        error!("Failed to open folder {} (code {})", dir.display(), result.0 as isize);
+    }
+}
+
+/// Minimum gap between progress updates forwarded to the webview (~10/s)
+const PROGRESS_FORWARD_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Downloads still running after this get a toast with a progress bar
+const PROGRESS_TOAST_AFTER: Duration = Duration::from_secs(10);
+/// How often the progress toast is updated
+const PROGRESS_TOAST_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Keep one progress toast per long download current, from the `downloads`
+/// state the downloader updates; stops when download `id` leaves it
+fn watch_progress_toast(id: String, filename: String) {
+    std::thread::spawn(move || {
+        std::thread::sleep(PROGRESS_TOAST_AFTER);
+        let running = |id: &str| crate::core::state::runtime().downloads.get(id).cloned();
+        if running(&id).is_none() || crate::quiet_hours::silenced() {
+            return;
         }
-        
-        match command.spawn() {
-            Ok(_) => {
-                println!("✅ Opened Downloads folder (file not found)");
+
+        let tag = crate::hooks::progress_tag(&id);
+        if let Err(e) = crate::hooks::show_progress_toast(&tag, "Downloading", &filename) {
+            warn!("Failed to show download progress toast: {}", e);
+            return;
+        }
+        let mut sequence = 1;
+        while let Some(download) = running(&id) {
+            let status = download.speed.unwrap_or_default();
+            if let Err(e) = crate::hooks::update_progress_toast(&tag, download.progress_percent / 100.0, &status, sequence) {
+                debug!("Progress toast update failed: {}", e);
             }
-            Err(e) => {
-                println!("❌ Failed to open Downloads folder: {}", e);
+            sequence += 1;
+            std::thread::sleep(PROGRESS_TOAST_INTERVAL);
+        }
+    });
+}
+
+/// Toast for finished download `id`, replacing its progress toast; clicking
+/// it shows the file in its folder
+fn notify_finished(id: &str, filename: &str, result: Result<&Path, &str>) {
+    if crate::quiet_hours::silenced() {
+        return;
+    }
+    let (title, message, reveal_path) = match result {
+        Ok(destination) => (
+            "Download complete",
+            format!("{} saved to {}", filename, destination.parent().map(|p| p.display().to_string()).unwrap_or_default()),
+            Some(destination.to_string_lossy().into_owned()),
+        ),
+        Err(error) => ("Download failed", format!("{}: {}", filename, error), None),
+    };
+    let toast = crate::hooks::NotificationData {
+        title: title.to_string(),
+        message,
+        icon: None,
+        chat_uuid: None,
+        reveal_path,
+        tag: Some(crate::hooks::progress_tag(id)),
+    };
+    if let Err(e) = crate::hooks::show_notification(toast) {
+        warn!("Failed to show download notification: {}", e);
+    }
+}
+
+/// Download under `id`, given out when the page's request was accepted
+pub fn start_download_process(
+    id: String,
+    url: String,
+    filename: String,
+    headers: Vec<(String, String)>,
+    target: DownloadTarget,
+    webview: Option<WebviewHandle>,
+) {
+    if !crate::metered::allow(&format!("Downloading {}", filename)) {
+        let spec = downloads::DownloadSpec::new(&url, &filename, &headers, &target);
+        let start: downloads::Restart = std::sync::Arc::new(move |id| {
+            let (url, filename, headers, target, webview) = (url.clone(), filename.clone(), headers.clone(), target.clone(), webview.clone());
+            std::thread::spawn(move || run_download_job(id, url, filename, headers, target, webview));
+        });
+        downloads::defer(&id, &spec, start);
+        return;
+    }
+    run_download_job(id, url, filename, headers, target, webview);
+}
+
+/// List the downloads the last run left unfinished (see [`downloads::restore`]);
+/// restarted ones go with the token of whoever is signed in by then
+pub fn restore_downloads(webview: Option<WebviewHandle>) {
+    downloads::restore(|spec| {
+        let (spec, webview) = (spec.clone(), webview.clone());
+        std::sync::Arc::new(move |id| {
+            let (spec, webview) = (spec.clone(), webview.clone());
+            std::thread::spawn(move || run_download_job(id, spec.url.clone(), spec.filename.clone(), spec.headers(), spec.target(), webview));
+        })
+    });
+}
+
+/// Download under the transfer `id`; pausing, resuming and retrying from the
+/// Transfers panel run this again with the same arguments
+fn run_download_job(
+    id: String,
+    url: String,
+    filename: String,
+    headers: Vec<(String, String)>,
+    target: DownloadTarget,
+    webview: Option<WebviewHandle>,
+) {
+    info!("Starting download: {} -> {}", crate::logging::redact_url(&url), filename);
+    debug!("Real-time progress will be sent to frontend via callback");
+
+    let prepared = downloads::resolve_destination(&filename, &target)
+        .and_then(|destination| Ok((downloads::prepare_staging(&destination).map_err(|e| e.to_string())?, destination)));
+    let (staging, destination) = match prepared {
+        Ok(paths) => paths,
+        Err(e) => {
+            error!("Cannot save {}: {}", filename, e);
+            downloads::end_job(&id, &Err(e.clone()));
+            notify_finished(&id, &filename, Err(&e));
+            if let Some(webview) = &webview {
+                webview.emit(
+                    "download-progress",
+                    &serde_json::json!({ "status": "error", "error": e, "filename": filename, "download_id": id }),
+                );
+            }
+            return;
+        }
+    };
+
+    let restart: downloads::Restart = {
+        let (url, filename, headers, target, webview) = (url.clone(), filename.clone(), headers.clone(), target.clone(), webview.clone());
+        std::sync::Arc::new(move |id| {
+            let (url, filename, headers, target, webview) = (url.clone(), filename.clone(), headers.clone(), target.clone(), webview.clone());
+            std::thread::spawn(move || run_download_job(id, url, filename, headers, target, webview));
+        })
+    };
+    let spec = downloads::DownloadSpec::new(&url, &filename, &headers, &target);
+    let job = downloads::begin_job(&id, &spec, &staging, &destination, restart);
+
+    // A staging file left by a quit or pause mid-download is continued
+    let resume = staging.exists();
+    if resume {
+        info!("Resuming {} from {}", filename, staging.display());
+    }
+    watch_progress_toast(id.clone(), filename.clone());
+    let result = run_downloader(&url, &staging, &filename, headers, resume, webview.clone(), Some(&job)).and_then(|()| {
+        downloads::move_into_place(&staging, &destination)
+            .map_err(|e| format!("could not move the download to {}: {}", destination.display(), e))
+    });
+    let stopped = downloads::end_job(&id, &result);
+    match result {
+        Ok(()) => {
+            info!("Saved {} to {}", filename, destination.display());
+            downloads::record(&filename, &destination);
+            notify_finished(&id, &filename, Ok(&destination));
+            if let Some(webview) = &webview {
+                webview.emit("download-progress", &serde_json::json!({
+                    "status": "saved",
+                    "filename": filename,
+                    "path": destination.to_string_lossy(),
+                    "download_id": id,
+                }));
+            }
+        }
+        Err(_) if stopped == Some(downloads::StopReason::Pause) => info!("Paused {}", filename),
+        Err(_) if stopped == Some(downloads::StopReason::Cancel) => {
+            info!("Cancelled {}", filename);
+            let _ = std::fs::remove_file(&staging);
+        }
+        Err(e) => {
+            error!("Download of {} failed: {}", filename, e);
+            // Kept when quitting so the next attempt resumes
+            if !crate::core::shutdown::is_quitting() {
+                notify_finished(&id, &filename, Err(&e));
+                let _ = std::fs::remove_file(&staging);
             }
         }
     }
 }
 
-pub fn start_download_process(url: String, filename: String, headers: Vec<(String, String)>) {
-    println!("Starting download: {} -> {}", url, filename);
-    println!("📊 Real-time progress will be sent to frontend via callback");
-    
+/// [`crate::attachments::Fetch`]: one file of a grouped download
+pub fn fetch_attachment(
+    url: &str,
+    staging: &Path,
+    filename: &str,
+    headers: Vec<(String, String)>,
+    job: &downloads::DownloadJob,
+) -> Result<(), String> {
+    run_downloader(url, staging, filename, headers, false, crate::ipc::handle(), Some(job))
+}
+
+/// Run the downloader service for `url` into `output_path`, forwarding its
+/// progress as `download-progress` events carrying the `download_id` (the
+/// `job`'s, or a new one). With `resume`, a partial file left by an earlier
+/// attempt is continued instead of restarted. A `job` is reported to the
+/// transfers and can be stopped.
+pub fn run_downloader(
+    url: &str,
+    output_path: &Path,
+    filename: &str,
+    headers: Vec<(String, String)>,
+    resume: bool,
+    webview: Option<WebviewHandle>,
+    job: Option<&downloads::DownloadJob>,
+) -> Result<(), String> {
     // Get the path to the downloader executable
     let exe_path = std::env::current_exe()
         .ok()
         .and_then(|exe| exe.parent().map(|p| p.join("downloaderservice.exe")))
         .unwrap_or_else(|| std::path::PathBuf::from("./target/debug/downloaderservice.exe"));
     
-    println!("Using downloader executable: {}", exe_path.display());
-    
-    // Determine output path (Downloads folder)
-    let downloads_dir = dirs::download_dir().unwrap_or_else(|| {
-        std::env::current_dir().unwrap().join("Downloads")
-    });
-    
-    // Create downloads directory if it doesn't exist
-    if !downloads_dir.exists() {
-        if let Err(e) = std::fs::create_dir_all(&downloads_dir) {
-            println!("Failed to create downloads directory: {}", e);
-            return;
-        }
-    }
-    
-    let output_path = downloads_dir.join(&filename);
+    info!("Using downloader executable: {}", exe_path.display());
     
     // Start the downloader process with hidden window
+    let download_id = job.map_or_else(downloads::new_job_id, |job| job.id.clone());
     let mut command = Command::new(&exe_path);
     command
-        .arg(&url)
-        .arg(&output_path);
+        .arg(url)
+        .arg(output_path)
+        .arg("--download-id")
+        .arg(&download_id);
     
     // Add headers if provided
     for (key, value) in headers {
         command.arg("-H").arg(format!("{}: {}", key, value));
     }
+    if resume {
+        command.arg("--resume");
+    }
     
     command
         .stdout(Stdio::piped())
@@ -131,76 +315,94 @@ pub fn start_download_process(url: String, filename: String, headers: Vec<(Strin
         command.creation_flags(CREATE_NO_WINDOW);
     }
     
-    match command.spawn() {
-        Ok(mut child) => {
-            println!("Downloader process started with PID: {} (hidden window)", child.id());
-            
-            // Read stdout in a separate thread
-            if let Some(stdout) = child.stdout.take() {
-                let reader = BufReader::new(stdout);
-                
-                for line in reader.lines() {
-                    match line {
-                        Ok(json_line) => {
-                            // Real progress from subprocess - output to console
-                            println!("📥 DOWNLOAD_PROGRESS: {}", json_line);
-                            
-                            // Send progress to frontend via global channel
-                            if let Ok(sender_lock) = PROGRESS_SENDER.lock() {
-                                if let Some(sender) = sender_lock.as_ref() {
-                                    if let Err(e) = sender.send(json_line.clone()) {
-                                        println!("⚠️ Failed to send progress to frontend: {}", e);
-                                    }
-                                }
-                            }
-                            
-                            // Parse JSON to check status
-                            if let Ok(progress) = serde_json::from_str::<serde_json::Value>(&json_line) {
-                                let status = progress["status"].as_str().unwrap_or("unknown");
-                                let percent = progress["progress_percent"].as_f64().unwrap_or(0.0);
-                                let speed = progress["download_speed_human"].as_str().unwrap_or("N/A");
-                                
-                                match status {
-                                    "downloading" => {
-                                        println!("  ├─ Progress: {:.1}% @ {}", percent, speed);
-                                    }
-                                    "completed" => {
-                                        println!("  └─ ✅ Download completed: {}", filename);
-                                        break;
-                                    }
-                                    "error" => {
-                                        let error_msg = progress["error"].as_str().unwrap_or("Unknown error");
-                                        println!("  └─ ❌ Download error: {}", error_msg);
-                                        break;
-                                    }
-                                    _ => {}
-                                }
-                            }
-                        }
-                        Err(e) => {
-                            println!("Error reading download output: {}", e);
-                            break;
-                        }
+    let child = match command.spawn() {
+        Ok(child) => child,
+        Err(e) => {
+            error!("Failed to start downloader process: {}", e);
+            return Err(format!("failed to start downloader: {}", e));
+        }
+    };
+    info!("Downloader process started with PID: {} (hidden window)", child.id());
+    crate::core::state::download_started(&download_id, filename);
+    
+    let mut last_forwarded: Option<Instant> = None;
+    let mut reported_error = false;
+
+    let stop = job.map(|job| &*job.stop);
+    let outcome = crate::platform::subprocess::supervise(child, stop, |json_line| {
+        // Real progress from subprocess - output to console
+        debug!("Download progress: {}", json_line);
+        
+        // Parse JSON to check status
+        if let Ok(mut progress) = serde_json::from_str::<serde_json::Value>(json_line) {
+            // An older downloader doesn't know `--download-id`
+            if progress.is_object() {
+                progress["download_id"] = download_id.as_str().into();
+            }
+            let status = progress["status"].as_str().unwrap_or("unknown");
+
+            // Forward to the frontend; "downloading" ticks are throttled so
+            // evaluate_script isn't flooded, state changes always go through
+            let due = last_forwarded.map_or(true, |at| at.elapsed() >= PROGRESS_FORWARD_INTERVAL);
+            if status != "downloading" || due {
+                last_forwarded = Some(Instant::now());
+                if let Some(webview) = &webview {
+                    if status == "downloading" {
+                        webview.emit_latest("download-progress", &download_id, &progress);
+                    } else {
+                        webview.emit("download-progress", &progress);
                     }
                 }
             }
+
+            let percent = progress["progress_percent"].as_f64().unwrap_or(0.0);
+            let speed = progress["download_speed_human"].as_str().unwrap_or("N/A");
             
-            // Wait for the process to complete
-            match child.wait() {
-                Ok(status) => {
-                    if status.success() {
-                        println!("Download process completed successfully");
-                    } else {
-                        println!("Download process failed with exit code: {:?}", status.code());
+            match status {
+                "downloading" => {
+                    debug!("Progress: {:.1}% @ {}", percent, speed);
+                    if due {
+                        crate::core::state::download_progress(&download_id, percent, Some(speed));
+                        if let Some(job) = job {
+                            let total = progress["total_size"].as_u64().filter(|&total| total > 0);
+                            crate::core::transfers::progress(&job.id, progress["downloaded"].as_u64().unwrap_or(0), total);
+                        }
                     }
                 }
-                Err(e) => {
-                    println!("Error waiting for download process: {}", e);
+                "completed" => {
+                    info!("Download completed: {}", filename);
+                }
+                "error" => {
+                    let error_msg = progress["error"].as_str().unwrap_or("Unknown error");
+                    error!("Download error: {}", error_msg);
+                    reported_error = true;
                 }
+                _ => {}
             }
         }
-        Err(e) => {
-            println!("Failed to start downloader process: {}", e);
+    });
+    
+    crate::core::state::download_finished(&download_id);
+    if outcome.success() {
+        info!("Download process completed successfully");
+        Ok(())
+    } else if stop.is_some_and(|stop| stop.load(std::sync::atomic::Ordering::SeqCst)) {
+        Err("stopped".to_string())
+    } else {
+        let reason = outcome.error_message();
+        error!("Download process failed: {}", reason);
+
+        // A crash or bad arguments may never produce an error line on stdout
+        if !reported_error {
+            if let Some(webview) = &webview {
+                webview.emit("download-progress", &serde_json::json!({
+                    "status": "error",
+                    "error": format!("Downloader failed ({})", reason),
+                    "filename": filename,
+                    "download_id": download_id,
+                }));
+            }
         }
+        Err(reason)
     }
 }
@@ -0,0 +1,68 @@
+//! Dragging a downloaded file out of the window into Explorer, Outlook, etc.
+//!
+//! The shell builds the data object (CF_HDROP plus the formats Explorer
+//! itself offers, including its drag image); we only supply the drop source
+//! and run the OLE drag loop, which blocks the UI thread until the drop.
+
+use std::path::Path;
+use windows::core::{implement, HRESULT, HSTRING};
+use windows::Win32::Foundation::{BOOL, DRAGDROP_S_CANCEL, DRAGDROP_S_DROP, DRAGDROP_S_USEDEFAULTCURSORS, S_OK};
+use windows::Win32::System::Com::{IBindCtx, IDataObject};
+use windows::Win32::System::Ole::{DoDragDrop, IDropSource, IDropSource_Impl, OleInitialize, DROPEFFECT, DROPEFFECT_COPY, DROPEFFECT_NONE};
+use windows::Win32::System::SystemServices::{MK_LBUTTON, MODIFIERKEYS_FLAGS};
+use windows::Win32::UI::Shell::{BHID_DataObject, IShellItem, SHCreateItemFromParsingName};
+use winit::window::Window;
+use tracing::info;
+
+use crate::core::downloads::FileDrag;
+
+#[implement(IDropSource)]
+struct DropSource;
+
+impl IDropSource_Impl for DropSource_Impl {
+    fn QueryContinueDrag(&self, escape_pressed: BOOL, key_state: MODIFIERKEYS_FLAGS) -> HRESULT {
+        if escape_pressed.as_bool() {
+            DRAGDROP_S_CANCEL
+        } else if key_state.0 & MK_LBUTTON.0 == 0 {
+            DRAGDROP_S_DROP
+        } else {
+            S_OK
+        }
+    }
+
+    fn GiveFeedback(&self, _effect: DROPEFFECT) -> HRESULT {
+        DRAGDROP_S_USEDEFAULTCURSORS
+    }
+}
+
+/// Run the drag and report to the page whether the file was dropped.
+/// `iconDataUrl` isn't used: the shell data object brings the file's own icon.
+pub fn begin_file_drag(_window: &Window, request: FileDrag) {
+    let result = run_drag(&request.path);
+    if let Ok(dropped) = result {
+        info!("File drag of {} {}", request.path.display(), if dropped { "dropped" } else { "cancelled" });
+    }
+    request.finished(result);
+}
+
+fn run_drag(path: &Path) -> Result<bool, String> {
+    let path = HSTRING::from(super::download::strip_verbatim_prefix(path).as_os_str());
+    unsafe {
+        // winit has usually done this already for its own drop target
+        let _ = OleInitialize(None);
+
+        let item: IShellItem = SHCreateItemFromParsingName(&path, None::<&IBindCtx>).map_err(|e| e.to_string())?;
+        let data: IDataObject = item.BindToHandler(None::<&IBindCtx>, &BHID_DataObject).map_err(|e| e.to_string())?;
+        let source: IDropSource = DropSource.into();
+
+        let mut effect = DROPEFFECT_NONE;
+        let result = DoDragDrop(&data, &source, DROPEFFECT_COPY, &mut effect);
+        if result == DRAGDROP_S_DROP {
+            Ok(effect != DROPEFFECT_NONE)
+        } else if result == DRAGDROP_S_CANCEL {
+            Ok(false)
+        } else {
+            Err(format!("DoDragDrop failed: {:?}", result))
+        }
+    }
+}
@@ -1,3 +1,5 @@
+use tracing::{debug, error, info};
+
 #[cfg(windows)]
 pub fn start_menu_command_handler(_hwnd: windows::Win32::Foundation::HWND) {
     use windows::Win32::{
@@ -6,7 +8,7 @@ pub fn start_menu_command_handler(_hwnd: windows::Win32::Foundation::HWND) {
         System::Threading::GetCurrentThreadId,
     };
     
-    println!("🎯 Installing Windows message hook for menu commands");
+    info!("Installing Windows message hook for menu commands");
     
     unsafe {
         // Install a WH_CALLWNDPROC hook to intercept messages
@@ -19,10 +21,10 @@ pub fn start_menu_command_handler(_hwnd: windows::Win32::Foundation::HWND) {
         
         match hook_result {
             Ok(hook) => {
-                println!("✅ Windows message hook installed successfully: {:?}", hook);
+                info!("Windows message hook installed successfully: {:?}", hook);
             }
             Err(e) => {
-                println!("❌ Failed to install Windows hook: {:?}", e);
+                error!("Failed to install Windows hook: {:?}", e);
             }
         }
     }
@@ -44,12 +46,37 @@ unsafe extern "system" fn menu_hook_proc(
         
         if msg.message == WM_COMMAND {
             let command_id = (msg.wParam.0 & 0xFFFF) as u16;
-            println!("🎯 WM_COMMAND intercepted! Command ID: {}", command_id);
+            debug!("WM_COMMAND intercepted! Command ID: {}", command_id);
             
             // Store the command for processing in the main event loop
             menubar::set_pending_menu_command(command_id);
         }
+
+        // Sent to every top-level window; the automatic resume comes on every wake
+        if msg.message == WM_POWERBROADCAST && msg.wParam.0 == PBT_APMRESUMEAUTOMATIC as usize {
+            crate::core::power::resumed();
+        }
+
+        if msg.message == WM_SETTINGCHANGE && msg.wParam.0 == SPI_SETHIGHCONTRAST.0 as usize {
+            crate::accessibility::settings_changed();
+        }
+
+        if msg.message != 0 && msg.message == taskbar_created_message() {
+            info!("TaskbarCreated received; re-adding the tray icon");
+            super::send_app_event(super::AppEvent::TaskbarCreated);
+        }
     }
     
     CallNextHookEx(HHOOK::default(), code, wparam, lparam)
 }
+
+/// The message Explorer broadcasts to top-level windows once the taskbar is
+/// (re)created, e.g. after Explorer crashed; 0 if it couldn't be registered
+#[cfg(windows)]
+fn taskbar_created_message() -> u32 {
+    use std::sync::OnceLock;
+    use windows::Win32::UI::WindowsAndMessaging::RegisterWindowMessageW;
+
+    static MESSAGE: OnceLock<u32> = OnceLock::new();
+    *MESSAGE.get_or_init(|| unsafe { RegisterWindowMessageW(windows::core::w!("TaskbarCreated")) })
+}
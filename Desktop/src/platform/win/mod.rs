@@ -1,10 +1,7 @@
 #![windows_subsystem = "windows"]
-use wry::WebViewBuilder;
-#[cfg(windows)]
-use wry::WebViewBuilderExtWindows;
 use winit::{
     event::WindowEvent,
-    event_loop::{ActiveEventLoop, EventLoop},
+    event_loop::{ActiveEventLoop, EventLoop, EventLoopProxy},
     window::{Window, WindowId},
     application::ApplicationHandler,
     dpi::LogicalSize,
@@ -14,141 +11,406 @@ use serde_json;
 use tray_icon::TrayIcon;
 use std::sync::Mutex;
 use lazy_static::lazy_static;
-use std::sync::mpsc::{channel, Receiver, Sender};
+use tracing::{error, info, warn};
 
 pub mod utils;
+pub mod autostart;
+pub mod accelerators;
 pub mod download;
+pub mod drag;
+pub mod preview;
+pub mod print;
+pub mod capture;
+pub mod recovery;
 pub mod tray;
 pub mod hooks;
+pub mod webview;
 
-// Global flag to ensure only one tray icon is created system-wide
 lazy_static! {
-    pub static ref TRAY_ICON_CREATED: Arc<Mutex<bool>> = Arc::new(Mutex::new(false));
-    pub static ref PROGRESS_SENDER: Arc<Mutex<Option<Sender<String>>>> = Arc::new(Mutex::new(None));
+    static ref EVENT_PROXY: Mutex<Option<EventLoopProxy<AppEvent>>> = Mutex::new(None);
+}
+
+/// Events delivered to the event loop from background threads
+#[derive(Debug)]
+pub enum AppEvent {
+    /// Queued webview events or badge changes are waiting to be applied
+    Wake,
+    /// Leave the event loop as if the user chose Exit (e.g. to run an installer)
+    Quit,
+    /// The user chose Exit from the tray; asks first while transfers are running
+    ExitRequested,
+    /// Start dragging a downloaded file out of the window (UI thread only)
+    BeginFileDrag(crate::core::downloads::FileDrag),
+    /// Print the page or save it as a PDF (needs the webview)
+    Print(crate::print::PrintJob),
+    /// Reload the page after a renderer failure
+    ReloadPage,
+    /// The page finished loading; the window is shown the first time
+    PageLoaded,
+    /// Show a downloaded file in the preview window (or its app)
+    PreviewFile(std::path::PathBuf),
+    /// Escape was pressed in the preview window
+    ClosePreview,
+    /// Warm a hidden webview for the next preview (see `webview::Standby`)
+    WarmStandby,
+    /// Explorer (re)started and lost the notification area icons
+    TaskbarCreated,
+    /// Move the window to a layout preset (`snap_window`)
+    SnapWindow { preset: String, request_id: Option<String> },
+    /// Keep the window's geometry as a named layout (`save_window_layout`)
+    SaveWindowLayout { name: Option<String>, request_id: Option<String> },
+    /// Show the right-click menu the page asked for (UI thread only)
+    ContextMenu(crate::context_menu::ContextMenuRequest),
+}
+
+/// Wake the event loop with `event`; returns false once the loop has exited
+pub fn send_app_event(event: AppEvent) -> bool {
+    match EVENT_PROXY.lock().unwrap().as_ref() {
+        Some(proxy) => proxy.send_event(event).is_ok(),
+        None => false,
+    }
 }
 
 use crate::{context_menu, menubar, hooks as app_hooks};
 use menubar::{MenuBar, apply_modern_menu_theme, enable_window_animations};
 use app_hooks::{init_notifications, show_notification};
 
-// Include the icon at compile time
-pub const ICON_BYTES: &[u8] = include_bytes!("../../../../Library/Shared/Icons/icon.ico");
+/// The Windows side of the page's actions (see [`crate::actions`])
+static ACTIONS: crate::actions::Platform = crate::actions::Platform {
+    start_download: |id, url, filename, headers, target| {
+        download::start_download_process(id, url, filename, headers, target, crate::ipc::handle())
+    },
+    fetch_attachment: download::fetch_attachment,
+    show_in_folder: download::show_file_in_explorer,
+    choose_folder: utils::choose_folder,
+    choose_pdf_path: |default_name| utils::choose_save_path(default_name, "PDF document", "pdf"),
+    begin_file_drag: |request| { send_app_event(AppEvent::BeginFileDrag(request)); },
+    preview_file: |path| { send_app_event(AppEvent::PreviewFile(path)); },
+    show_context_menu: Some(show_context_menu),
+    snap_window: |preset, request_id| { send_app_event(AppEvent::SnapWindow { preset, request_id }); },
+    save_window_layout: |name, request_id| { send_app_event(AppEvent::SaveWindowLayout { name, request_id }); },
+    print: |job| { send_app_event(AppEvent::Print(job)); },
+    autostart_status: autostart::status,
+    set_autostart: autostart::set,
+    export_diagnostics: utils::export_diagnostics,
+    export_thread: utils::export_thread,
+    learn_words: utils::learn_words,
+    spellcheck_on_ipc_thread: false,
+    // WebView2 reads the languages when the environment is created
+    languages_at_restart: true,
+    install_update: || utils::install_update(crate::ipc::handle()),
+    request_screen_capture: None,
+};
+
+/// `show_context_menu`: the menu itself is shown on the UI thread
+fn show_context_menu(message: &serde_json::Value) {
+    match serde_json::from_value::<context_menu::ContextMenuRequest>(message.clone()) {
+        Ok(mut request) => {
+            request.request_id = message["requestId"].as_str().map(|s| s.to_string());
+            send_app_event(AppEvent::ContextMenu(request));
+        }
+        Err(e) => {
+            let result = serde_json::json!({ "action": null, "error": format!("invalid context menu request: {}", e) });
+            crate::ipc::respond(message["requestId"].as_str(), "context-menu-selected", &result);
+        }
+    }
+}
 
 #[cfg(debug_assertions)]
 const DEV_SERVER_URL: &str = "http://localhost:5173";
 
-#[cfg(not(debug_assertions))]
-const INDEX_HTML_BYTES: &[u8] = include_bytes!("../../../../Distribution/index.html");
+// Progress arrives as a `download-progress` event; forward it to the callback registered for its
+// `download_id` in `downloadProgressCallbacks`, else to the one installed by useDownload
+const DOWNLOAD_PROGRESS_BRIDGE: &str = "window.addEventListener('download-progress', (e) => { const byId = window.downloadProgressCallbacks && window.downloadProgressCallbacks[e.detail.download_id]; const callback = typeof byId === 'function' ? byId : window.downloadProgressCallback; if (typeof callback === 'function') callback(e.detail); });";
+
 
 struct App {
     window: Option<Arc<Window>>,
     webview: Option<wry::WebView>,
-    initialization_complete: bool,
-    ready_to_show: bool,
     native_menubar: Option<MenuBar>,
     tray_icon: Option<TrayIcon>,
-    progress_receiver: Option<Receiver<String>>,
+    preview: Option<preview::PreviewWindow>,
+    standby: Option<webview::Standby>,
+    webview_handle: crate::ipc::WebviewHandle,
+    args: crate::cli::Args,
 }
 
 impl App {
-    fn new() -> Self {
-        // Create channel for download progress
-        let (sender, receiver) = channel();
-        
-        // Store sender globally
-        {
-            let mut global_sender = PROGRESS_SENDER.lock().unwrap();
-            *global_sender = Some(sender);
-        }
-        
+    fn new(args: crate::cli::Args) -> Self {
         Self {
             window: None,
             webview: None,
-            initialization_complete: false,
-            ready_to_show: false,
             native_menubar: None,
             tray_icon: None,
-            progress_receiver: Some(receiver),
+            preview: None,
+            standby: None,
+            webview_handle: crate::ipc::install(|| { send_app_event(AppEvent::Wake); }),
+            args,
         }
     }
 }
 
-impl ApplicationHandler for App {
+impl ApplicationHandler<AppEvent> for App {
     fn resumed(&mut self, event_loop: &ActiveEventLoop) {
-        if self.window.is_none() && !self.initialization_complete {
-            println!("Starting initialization...");
+        if self.window.is_none() && !crate::core::state::is_initialized() {
+            info!("Starting initialization...");
             
-            // Create window but keep it hidden until everything is ready
-            let mut window_attributes = Window::default_attributes()
+            // Create window but keep it hidden until the page has loaded
+            let window_attributes = Window::default_attributes()
                 .with_title("Workspace")
                 .with_inner_size(LogicalSize::new(1200, 800))
-                .with_visible(false); // Keep hidden during preload
-            
-            // Set window icon
-            if let Some(icon) = utils::load_window_icon() {
-                window_attributes = window_attributes.with_window_icon(Some(icon));
-            }
+                .with_visible(false);
             
-            let window = Arc::new(event_loop.create_window(window_attributes).unwrap());
+            let window = {
+                let _phase = crate::startup::phase("window");
+                Arc::new(event_loop.create_window(window_attributes).unwrap())
+            };
             self.window = Some(window.clone());
+            utils::set_capture_excluded(&window, crate::privacy::block_capture());
             
-            // Create WebView2 immediately (but window stays hidden)
-            self.create_webview(&window);
+            // Shown on the first page load (AppEvent::PageLoaded)
+            let created = {
+                let _phase = crate::startup::phase("webview");
+                self.ensure_webview(&window)
+            };
+            if !created {
+                info!("No webview, exiting");
+                event_loop.exit();
+                return;
+            }
+            crate::startup::show_after_timeout(|| {
+                send_app_event(AppEvent::PageLoaded);
+            });
+            
+            // Decoded on a worker since startup began; the default icon shows until now
+            window.set_window_icon(utils::load_window_icon());
             
             // Initialize notifications
             if let Err(e) = init_notifications() {
-                println!("⚠️ Failed to initialize notifications: {}", e);
+                warn!("Failed to initialize notifications: {}", e);
             }
             
-            println!("✅ All initialization complete - showing window");
-            
-            // Show the window immediately
-            window.set_visible(true);
-            
             // Create tray icon
-            if self.tray_icon.is_none() {
-                match tray::create_tray_icon(self.window.clone()) {
-                    Ok(tray) => {
-                        self.tray_icon = Some(tray);
-                        println!("✅ Tray icon created successfully");
+            {
+                let _phase = crate::startup::phase("tray");
+                tray::handle_menu_events(self.window.clone());
+                self.recreate_tray("startup");
+            }
+            
+            crate::core::state::set_initialized();
+        }
+    }
+
+    fn exiting(&mut self, _event_loop: &ActiveEventLoop) {
+        // Webviews go before the event loop does, the unused standby included
+        self.standby = None;
+        self.preview = None;
+    }
+
+    fn window_event(&mut self, event_loop: &ActiveEventLoop, window_id: WindowId, event: WindowEvent) {
+        self.run_menu_command(event_loop);
+
+        if let Some(preview) = self.preview.as_ref().filter(|p| p.id() == window_id) {
+            match event {
+                WindowEvent::CloseRequested => self.preview = None,
+                WindowEvent::Resized(size) => preview.fit(size),
+                _ => {}
+            }
+            return;
+        }
+
+        match event {
+            WindowEvent::CloseRequested => { self.request_quit(event_loop); }
+            WindowEvent::ScaleFactorChanged { scale_factor, .. } => self.scale_factor_changed(scale_factor),
+            WindowEvent::Resized(size) => self.fit_webview(size),
+            WindowEvent::Focused(focused) => crate::mark_read::focus_changed(focused),
+            WindowEvent::KeyboardInput { event, .. } => {
+                if event.state == winit::event::ElementState::Pressed {
+                    if let winit::keyboard::Key::Named(winit::keyboard::NamedKey::F12) = event.logical_key {
+                        // DevTools handled by wry
                     }
-                    Err(e) => println!("⚠️ Failed to create tray icon: {}", e),
                 }
             }
-            
-            self.initialization_complete = true;
+            _ => {}
         }
     }
 
-    fn window_event(&mut self, event_loop: &ActiveEventLoop, _window_id: WindowId, event: WindowEvent) {
-        // Poll for download progress updates
-        if let Some(receiver) = &self.progress_receiver {
-            while let Ok(progress_json) = receiver.try_recv() {
+    fn user_event(&mut self, event_loop: &ActiveEventLoop, event: AppEvent) {
+        match event {
+            AppEvent::Wake => {
+                // Deliver events queued by background threads (kept until the webview exists)
+                if let Some(webview) = &self.webview {
+                    self.webview_handle.deliver(webview);
+                }
+
+                if crate::core::downloads::take_tray_update() {
+                    if let Some(tray) = &self.tray_icon {
+                        tray::refresh_menu(tray);
+                    }
+                }
+
+                if let Some(total) = crate::core::unread::take_badge_update() {
+                    self.update_unread_badge(total);
+                }
+
+                if let Some(connectivity) = crate::core::network::take_tray_update() {
+                    self.update_connectivity_icon(connectivity);
+                }
+
+                if crate::quiet_hours::take_tray_update() {
+                    self.update_unread_badge(crate::core::unread::total());
+                }
+
+                if let Some(status) = crate::core::transfer_status::take_update() {
+                    if let Some(window) = &self.window {
+                        window.set_title(&crate::core::transfer_status::window_title(status.as_deref()));
+                    }
+                    self.update_unread_badge(crate::core::unread::total());
+                }
+
+                if let (Some(spellcheck), Some(menu)) = (crate::spellcheck::take_menu_update(), &self.native_menubar) {
+                    menubar::update_spelling_menu(menu, &spellcheck);
+                }
+
+                if let Some(blocked) = crate::privacy::take_window_update() {
+                    let preview = self.preview.as_ref().map(|p| p.window());
+                    for window in self.window.as_deref().into_iter().chain(preview) {
+                        utils::set_capture_excluded(window, blocked);
+                    }
+                    if let Some(menu) = &self.native_menubar {
+                        menu.set_checked("block_capture", blocked);
+                    }
+                }
+
+                #[cfg(windows)]
+                if let Some(high_contrast) = crate::accessibility::take_window_update() {
+                    let hwnd = utils::main_hwnd();
+                    if high_contrast {
+                        let _ = menubar::clear_menu_colors(hwnd);
+                    } else {
+                        let _ = context_menu::init_window_theme(hwnd);
+                        let _ = apply_modern_menu_theme(hwnd);
+                        let _ = menubar::apply_menu_colors(hwnd);
+                    }
+                }
+
+                if crate::shortcuts::take_menu_update() {
+                    if let Some(menu) = &self.native_menubar {
+                        menu.apply_shortcuts();
+                    }
+                }
+
+                self.run_menu_command(event_loop);
+            }
+            AppEvent::Quit => {
+                info!("Quit requested");
+                event_loop.exit();
+            }
+            AppEvent::ExitRequested => self.request_quit(event_loop),
+            AppEvent::TaskbarCreated => self.recreate_tray("Explorer restarted"),
+            AppEvent::BeginFileDrag(request) => match &self.window {
+                Some(window) => drag::begin_file_drag(window, request),
+                None => request.finished(Err("no window".to_string())),
+            },
+            AppEvent::Print(job) => match &self.webview {
+                Some(webview) => print::run(webview, job),
+                None => job.failed("no webview"),
+            },
+            AppEvent::ContextMenu(request) => {
+                use winit::raw_window_handle::{HasWindowHandle, RawWindowHandle};
+
+                let choice = self.window.as_ref().and_then(|window| match window.window_handle().map(|h| h.as_raw()) {
+                    Ok(RawWindowHandle::Win32(handle)) => {
+                        let hwnd = windows::Win32::Foundation::HWND(handle.hwnd.get() as *mut std::ffi::c_void);
+                        context_menu::show(hwnd, &request, window.scale_factor())
+                    }
+                    _ => None,
+                });
+                context_menu::selected(&request, choice, utils::learn_words);
+            }
+            AppEvent::SnapWindow { preset, request_id } => {
+                let result = match &self.window {
+                    Some(window) => crate::window_layout::snap(window, &preset),
+                    None => Err("no window".to_string()),
+                };
+                let reply = match result {
+                    Ok(()) => serde_json::json!({ "success": true, "preset": preset }),
+                    Err(e) => serde_json::json!({ "success": false, "preset": preset, "error": e }),
+                };
+                crate::ipc::respond(request_id.as_deref(), "window-snapped", &reply);
+            }
+            AppEvent::SaveWindowLayout { name, request_id } => {
+                let result = match &self.window {
+                    Some(window) => crate::window_layout::save(window, name.as_deref()),
+                    None => Err("no window".to_string()),
+                };
+                let reply = match result {
+                    Ok(name) => serde_json::json!({ "success": true, "name": name, "layouts": crate::window_layout::list() }),
+                    Err(e) => serde_json::json!({ "success": false, "error": e }),
+                };
+                crate::ipc::respond(request_id.as_deref(), "window-layout-saved", &reply);
+            }
+            AppEvent::PageLoaded => {
+                if let Some(window) = &self.window {
+                    if crate::startup::take_first_show() {
+                        // `--start-hidden` leaves it in the tray until opened from there
+                        if !self.args.start_hidden {
+                            window.set_visible(true);
+                            window.focus_window();
+                        }
+                        webview::schedule_standby();
+                    }
+                }
+            }
+            AppEvent::PreviewFile(path) => {
+                // One preview at a time; a new one replaces it
+                self.preview = None;
+                if crate::preview::kind(&path).is_none() {
+                    preview::open_with_default_app(&path);
+                    return;
+                }
+                let standby = self.standby.take();
+                let adopted = standby.is_some();
+                match preview::PreviewWindow::open(event_loop, &path, standby) {
+                    Ok(window) => {
+                        self.preview = Some(window);
+                        if adopted {
+                            webview::schedule_standby();
+                        }
+                    }
+                    Err(e) => {
+                        warn!("Preview window failed, opening {} instead: {}", path.display(), e);
+                        preview::open_with_default_app(&path);
+                    }
+                }
+            }
+            AppEvent::ClosePreview => self.preview = None,
+            AppEvent::WarmStandby => {
+                if self.standby.is_none() && webview::standby_due() {
+                    match preview::standby(event_loop) {
+                        Ok(standby) => {
+                            info!("Standby webview ready");
+                            self.standby = Some(standby);
+                        }
+                        Err(e) => warn!("Failed to warm the standby webview: {}", e),
+                    }
+                }
+            }
+            AppEvent::ReloadPage => {
                 if let Some(webview) = &self.webview {
-                    let escaped_json = progress_json.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n");
-                    let script = format!(
-                        r#"
-                        (function() {{
-                            try {{
-                                const progressData = JSON.parse("{}");
-                                if (typeof window.downloadProgressCallback === 'function') {{
-                                    window.downloadProgressCallback(progressData);
-                                }} else {{
-                                    window.dispatchEvent(new CustomEvent('download-progress', {{ detail: progressData }}));
-                                }}
-                            }} catch (e) {{
-                                console.error('❌ Error processing download progress:', e);
-                            }}
-                        }})();
-                        "#,
-                        escaped_json
-                    );
-                    let _ = webview.evaluate_script(&script);
+                    info!("Reloading the page");
+                    if let Err(e) = webview.reload() {
+                        error!("Failed to reload the page: {}", e);
+                    }
                 }
             }
         }
-        
-        // Check for pending menu commands
+    }
+}
+
+impl App {
+    /// Run the command a menu click or keyboard shortcut left for the event loop
+    fn run_menu_command(&self, event_loop: &ActiveEventLoop) {
         if let Some(command_id) = menubar::get_and_clear_pending_menu_command() {
             if let Some(action) = menubar::get_menu_action(command_id) {
                 #[cfg(windows)]
@@ -161,10 +423,77 @@ impl ApplicationHandler for App {
                             RawWindowHandle::Win32(handle) => {
                                 let hwnd = HWND(handle.hwnd.get() as *mut std::ffi::c_void);
                                 match action.as_str() {
-                                    "check_updates" => { let _ = menubar::show_check_updates_dialog(hwnd); }
+                                    "check_updates" => { std::thread::spawn(|| utils::check_for_updates(true)); }
+                                    "spelling_toggle" => {
+                                        let enabled = !crate::spellcheck::current().enabled;
+                                        std::thread::spawn(move || {
+                                            if let Err(e) = crate::spellcheck::set(Some(enabled), None, utils::learn_words) {
+                                                warn!("{}", e);
+                                            }
+                                        });
+                                    }
+                                    "block_capture" => {
+                                        if let Err(e) = crate::privacy::set_block_capture(!crate::privacy::block_capture()) {
+                                            warn!("{}", e);
+                                        }
+                                    }
+                                    other if other.starts_with("spelling_lang:") => {
+                                        let tag = other.trim_start_matches("spelling_lang:").to_string();
+                                        std::thread::spawn(move || {
+                                            if let Err(e) = crate::spellcheck::toggle_language(&tag, utils::learn_words) {
+                                                warn!("{}", e);
+                                            }
+                                        });
+                                    }
+                                    "print_page" => match &self.webview {
+                                        Some(webview) => print::run(webview, crate::print::PrintJob::Dialog),
+                                        None => warn!("Print requested before the webview exists"),
+                                    },
                                     "about" => { let _ = menubar::show_about_dialog(hwnd); }
-                                    "exit" => { event_loop.exit(); }
-                                    _ => {}
+                                    "network_diagnostics" => { let _ = menubar::show_network_diagnostics_dialog(hwnd); }
+                                    "export_logs" => {
+                                        std::thread::spawn(|| {
+                                            let result = utils::export_diagnostics();
+                                            if let Some(path) = result["path"].as_str() {
+                                                let _ = show_notification(crate::hooks::noti::NotificationData {
+                                                    title: "Diagnostic logs exported".to_string(),
+                                                    message: path.to_string(),
+                                                    icon: None,
+                                                    chat_uuid: None,
+                                                    reveal_path: Some(path.to_string()),
+                                                    tag: None,
+                                                });
+                                            }
+                                            crate::ipc::respond(None, "logs-exported", &result);
+                                        });
+                                    }
+                                    "clear_history" => match crate::core::unread::active_thread() {
+                                        Some(thread_id) => {
+                                            let question = "Delete every message in this chat for everyone?\n\nThis can't be undone.";
+                                            if utils::confirm("Clear Chat History", question) {
+                                                std::thread::spawn(move || { crate::history::clear_thread(&thread_id); });
+                                            }
+                                        }
+                                        None => utils::show_message("Clear Chat History", "Open the chat to clear first."),
+                                    },
+                                    "shortcuts" => { let _ = menubar::show_shortcuts_dialog(hwnd); }
+                                    "fullscreen" => {
+                                        let fullscreen = window.fullscreen().is_none().then_some(winit::window::Fullscreen::Borderless(None));
+                                        window.set_fullscreen(fullscreen);
+                                    }
+                                    other if other.starts_with("snap:") => {
+                                        if let Err(e) = crate::window_layout::snap(window, other.trim_start_matches("snap:")) {
+                                            utils::show_message("Window Layout", &e);
+                                        }
+                                    }
+                                    "save_window_layout" => {
+                                        if let Err(e) = crate::window_layout::save(window, None) {
+                                            warn!("Failed to save the window layout: {}", e);
+                                        }
+                                    }
+                                    "exit" => { self.request_quit(event_loop); }
+                                    // Commands the page implements, e.g. new_chat or toggle_sidebar
+                                    other => self.webview_handle.emit("menu-action", &serde_json::json!({ "action": other })),
                                 }
                             }
                             _ => {}
@@ -173,62 +502,185 @@ impl ApplicationHandler for App {
                 }
             }
         }
-        
-        match event {
-            WindowEvent::CloseRequested => { event_loop.exit(); }
-            WindowEvent::KeyboardInput { event, .. } => {
-                if event.state == winit::event::ElementState::Pressed {
-                    if let winit::keyboard::Key::Named(winit::keyboard::NamedKey::F12) = event.logical_key {
-                        // DevTools handled by wry
-                    }
+    }
+
+    /// Exit, unless downloads or uploads are running and the user would
+    /// rather stay or let them finish with the window hidden
+    fn request_quit(&self, event_loop: &ActiveEventLoop) {
+        use crate::core::shutdown::{self, InFlight, QuitChoice};
+
+        let in_flight = InFlight::current();
+        if in_flight.is_empty() {
+            event_loop.exit();
+            return;
+        }
+        match utils::ask_quit(&in_flight.describe()) {
+            QuitChoice::Quit => {
+                info!("Quitting with {}", in_flight.describe());
+                shutdown::begin_quit();
+                event_loop.exit();
+            }
+            QuitChoice::FinishInBackground => {
+                if let Some(window) = &self.window {
+                    window.set_visible(false);
                 }
+                shutdown::exit_when_idle(|| {
+                    send_app_event(AppEvent::Quit);
+                });
             }
-            _ => {}
+            QuitChoice::Stay => {}
         }
     }
-}
-
-impl App {
-    fn create_webview(&mut self, window: &Arc<Window>) {
-        let mut webview_builder = WebViewBuilder::new();
 
+    /// The window moved to a monitor with another scale. Windows re-measures
+    /// the menu bar on the next non-client repaint, which `DrawMenuBar` forces;
+    /// the page hears about it to swap bitmaps drawn for the old density.
+    fn scale_factor_changed(&self, scale_factor: f64) {
+        info!("Scale factor changed to {}", scale_factor);
         #[cfg(windows)]
-        {
-            #[cfg(not(debug_assertions))]
-            {
-                let app_dir = std::env::current_exe().ok().and_then(|exe| exe.parent().map(|p| p.to_path_buf())).unwrap();
-                if app_dir.join("msedgewebview2.exe").exists() {
-                    std::env::set_var("WEBVIEW2_BROWSER_EXECUTABLE_FOLDER", &app_dir);
-                }
+        if let Some(window) = &self.window {
+            use windows::Win32::Foundation::HWND;
+            use windows::Win32::UI::WindowsAndMessaging::DrawMenuBar;
+            use winit::raw_window_handle::{HasWindowHandle, RawWindowHandle};
+
+            if let Ok(RawWindowHandle::Win32(handle)) = window.window_handle().map(|h| h.as_raw()) {
+                let _ = unsafe { DrawMenuBar(HWND(handle.hwnd.get() as *mut std::ffi::c_void)) };
             }
-            
-            let user_data_dir = std::env::temp_dir().join("MikoWorkspace_WebView2");
-            std::env::set_var("WEBVIEW2_USER_DATA_FOLDER", &user_data_dir);
-            
-            let _ = utils::configure_webview2_permissions();
-            
-            std::env::set_var("WEBVIEW2_DISABLE_PERMISSION_PROMPTS", "1");
-            std::env::set_var("WEBVIEW2_AUTO_GRANT_PERMISSIONS", "1");
-            
-            webview_builder = webview_builder.with_additional_browser_args("--disable-web-security --enable-clipboard-api");
         }
+        self.webview_handle.emit("scale-factor-changed", &serde_json::json!({ "scaleFactor": scale_factor }));
+    }
 
-        #[cfg(debug_assertions)]
-        { webview_builder = webview_builder.with_url(DEV_SERVER_URL); }
+    /// Keep the WebView2 controller covering the client area. The resize that
+    /// follows a scale change would otherwise leave it rendering at the old
+    /// size (blurry) until the user resizes the window.
+    fn fit_webview(&self, size: winit::dpi::PhysicalSize<u32>) {
+        if let Some(webview) = &self.webview {
+            let bounds = wry::Rect {
+                position: wry::dpi::PhysicalPosition::new(0, 0).into(),
+                size: size.into(),
+            };
+            if let Err(e) = webview.set_bounds(bounds) {
+                warn!("Failed to resize the webview: {}", e);
+            }
+        }
+    }
+
+    /// Create the tray icon, removing the current one first. Explorer forgets
+    /// all icons when it restarts; re-adding one it still shows would make two.
+    fn recreate_tray(&mut self, reason: &str) {
+        if let Some(old) = self.tray_icon.take() {
+            info!("Removing tray icon {:?} ({})", old.id(), reason);
+            drop(old);
+        }
+        match tray::create_tray_icon() {
+            Ok(tray) => {
+                self.tray_icon = Some(tray);
+                info!("Tray icon created ({})", reason);
+                self.update_unread_badge(crate::core::unread::total());
+                self.update_connectivity_icon(crate::core::state::runtime().connectivity);
+            }
+            Err(e) => warn!("Failed to create tray icon ({}): {}", reason, e),
+        }
+    }
 
+    /// Reflect the unread total (and running transfers) in the tray tooltip
+    fn update_unread_badge(&self, total: u32) {
+        if let Some(tray) = &self.tray_icon {
+            let details: Vec<String> = crate::core::unread::badge_label(total)
+                .map(|label| format!("{} unread", label))
+                .into_iter()
+                .chain(crate::core::transfer_status::current())
+                .chain(crate::quiet_hours::tooltip())
+                .collect();
+            let tooltip = if details.is_empty() {
+                "Workspace - Desktop Application".to_string()
+            } else {
+                format!("Workspace - {}", details.join(" - "))
+            };
+            let _ = tray.set_tooltip(Some(tooltip));
+        }
+    }
+
+    /// Grey the tray icon out while the network or the ERP is unreachable
+    fn update_connectivity_icon(&self, connectivity: crate::core::state::Connectivity) {
+        let Some(tray) = &self.tray_icon else { return };
+        let icon = crate::icons::load_app_icon(crate::icons::TRAY_ICON_SIZE);
+        let icon = match connectivity {
+            crate::core::state::Connectivity::Online => tray_icon::Icon::from_rgba(icon.rgba.clone(), icon.width, icon.height),
+            _ => {
+                let dimmed = crate::icons::dimmed(&icon);
+                tray_icon::Icon::from_rgba(dimmed.rgba, dimmed.width, dimmed.height)
+            }
+        };
+        match icon {
+            Ok(icon) => {
+                if let Err(e) = tray.set_icon(Some(icon)) {
+                    warn!("Failed to update tray icon: {}", e);
+                }
+            }
+            Err(e) => warn!("Failed to build tray icon: {}", e),
+        }
+    }
+
+    /// Create the webview, offering to install a missing WebView2 runtime and
+    /// to retry after a failure; false once the user gives up
+    fn ensure_webview(&mut self, window: &Arc<Window>) -> bool {
+        // A fixed-version runtime shipped next to the exe takes precedence (also for the check below)
         #[cfg(not(debug_assertions))]
         {
-            use std::borrow::Cow;
-            webview_builder = webview_builder.with_custom_protocol("miko".into(), move |_webview, request| {
-                if request.uri() == "miko://app/" || request.uri() == "miko://app/index.html" {
-                    return http::Response::builder().header("Content-Type", "text/html").body(Cow::Borrowed(INDEX_HTML_BYTES)).unwrap();
+            let app_dir = std::env::current_exe().ok().and_then(|exe| exe.parent().map(|p| p.to_path_buf())).unwrap();
+            if app_dir.join("msedgewebview2.exe").exists() {
+                std::env::set_var("WEBVIEW2_BROWSER_EXECUTABLE_FOLDER", &app_dir);
+            }
+        }
+
+        loop {
+            if utils::webview2_runtime_version().is_none() {
+                warn!("WebView2 runtime not found");
+                if !utils::install_webview2_runtime() {
+                    return false;
                 }
-                http::Response::builder().status(404).body(Cow::Borrowed(&[] as &[u8])).unwrap()
-            });
-            webview_builder = webview_builder.with_url("miko://app/");
+                continue;
+            }
+
+            match self.create_webview(window) {
+                Ok(()) => return true,
+                Err(e) => {
+                    error!("Failed to create WebView: {}", e);
+                    let message = format!("Workspace could not open its window:\n\n{}\n\nTry again?", e);
+                    if !utils::confirm("Workspace", &message) {
+                        return false;
+                    }
+                }
+            }
+        }
+    }
+
+    fn create_webview(&mut self, window: &Arc<Window>) -> Result<(), String> {
+        let mut factory = webview::WebviewFactory::new().with_init_script(crate::core::sync::BOOTSTRAP_SCRIPT);
+        // Safe mode keeps only the bridge the page needs to talk to the host
+        if !crate::safe_mode::active() {
+            factory = factory
+                .with_init_script("console.log('WebView initialized');")
+                .with_init_script(crate::console::INIT_SCRIPT)
+                .with_init_script(DOWNLOAD_PROGRESS_BRIDGE)
+                .with_init_script(crate::spellcheck::init_script());
+            if let Some(script) = crate::media::init_script() {
+                factory = factory.with_init_script(script);
+            }
         }
+        // Serves the bundled page and forwards same-origin requests to the server
+        let mut webview_builder = factory.builder();
 
-        webview_builder = webview_builder.with_initialization_script("console.log('WebView initialized');");
+        #[cfg(debug_assertions)]
+        {
+            let dev_server = self.args.dev_server.as_deref().unwrap_or(DEV_SERVER_URL);
+            crate::ipc::allow_origin(dev_server);
+            webview_builder = webview_builder.with_url(dev_server);
+        }
+
+        #[cfg(not(debug_assertions))]
+        { webview_builder = webview_builder.with_url("miko://app/"); }
 
         #[cfg(windows)]
         let window_handle = {
@@ -242,72 +694,77 @@ impl App {
 
         #[cfg(windows)]
         {
-            let _ = context_menu::init_window_theme(window_handle);
-            let _ = apply_modern_menu_theme(window_handle);
+            utils::set_main_hwnd(window_handle);
+            // A contrast theme draws the frame and menus itself
+            if !crate::accessibility::high_contrast() {
+                let _ = context_menu::init_window_theme(window_handle);
+                let _ = apply_modern_menu_theme(window_handle);
+            }
             let _ = enable_window_animations(window_handle);
         }
 
-        match menubar::create_app_menubar() {
-            Ok(menu) => {
-                if let Ok(_) = menu.attach_to_window(window_handle) {
+        // Already attached if this is a retry
+        if self.native_menubar.is_none() {
+            if let Ok(menu) = menubar::create_app_menubar() {
+                if menu.attach_to_window(window_handle).is_ok() {
+                    menubar::update_spelling_menu(&menu, &crate::spellcheck::current());
+                    menu.set_checked("block_capture", crate::privacy::block_capture());
                     #[cfg(windows)]
-                    let _ = menubar::apply_menu_colors(window_handle);
+                    if !crate::accessibility::high_contrast() {
+                        let _ = menubar::apply_menu_colors(window_handle);
+                    }
                     self.native_menubar = Some(menu);
                     hooks::start_menu_command_handler(window_handle);
                 }
             }
-            _ => {}
         }
 
         let webview = webview_builder
             .with_devtools(true)
+            .with_on_page_load_handler(|event, _url| {
+                if let wry::PageLoadEvent::Finished = event {
+                    send_app_event(AppEvent::PageLoaded);
+                }
+            })
             .with_ipc_handler(|request| {
-                let body = request.body();
-                if let Ok(message) = serde_json::from_str::<serde_json::Value>(body) {
-                    if let Some(msg_type) = message["type"].as_str() {
-                        match msg_type {
-                            "start_download" => {
-                                if let (Some(url), Some(filename)) = (message["url"].as_str(), message["filename"].as_str()) {
-                                    let mut headers = Vec::new();
-                                    if let Some(h) = message["headers"].as_object() {
-                                        for (k, v) in h { if let Some(vs) = v.as_str() { headers.push((k.clone(), vs.to_string())); } }
-                                    }
-                                    let (u, f) = (url.to_string(), filename.to_string());
-                                    std::thread::spawn(move || { download::start_download_process(u, f, headers); });
-                                }
-                            }
-                            "show_in_folder" => {
-                                if let Some(filename) = message["filename"].as_str() {
-                                    let f = filename.to_string();
-                                    std::thread::spawn(move || { download::show_file_in_explorer(&f); });
-                                }
-                            }
-                            "show_notification" => {
-                                if let Ok(noti_data) = serde_json::from_value::<crate::hooks::noti::NotificationData>(message.clone()) {
-                                    std::thread::spawn(move || { let _ = crate::hooks::show_notification(noti_data); });
-                                }
-                            }
-                            _ => {}
-                        }
-                    }
+                // Malformed or oversized messages are answered with `ipc-error` here
+                if let Some(message) = crate::ipc::parse_message(request.uri(), request.body()) {
+                    crate::actions::dispatch(&message, &ACTIONS);
                 }
             })
             .build(&**window)
-            .expect("Failed to create WebView");
+            .map_err(|e| e.to_string())?;
 
+        capture::install_screen_capture_handler(&webview);
+        recovery::install_process_failed_handler(&webview);
+        accelerators::install_accelerator_handler(&webview, window_handle);
         self.webview = Some(webview);
+        crate::core::state::set_webview_ready(true);
+        if let Some(webview) = &self.webview {
+            self.webview_handle.deliver(webview);
+        }
+        Ok(())
     }
 }
 
-impl Drop for App {
-    fn drop(&mut self) {
-        if let Ok(mut created) = TRAY_ICON_CREATED.lock() { *created = false; }
+pub fn main(args: crate::cli::Args) -> Result<(), Box<dyn std::error::Error>> {
+    let event_loop = EventLoop::<AppEvent>::with_user_event().build()?;
+    *EVENT_PROXY.lock().unwrap() = Some(event_loop.create_proxy());
+    if !cfg!(debug_assertions) && args.dev_server.is_some() {
+        warn!("--dev-server is ignored in release builds");
     }
-}
-
-pub fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let event_loop = EventLoop::new()?;
-    let mut app = App::new();
+    let mut app = App::new(args);
+    download::restore_downloads(crate::ipc::handle());
+    // First, so a restored outage is in place before the monitor's first probe
+    crate::core::health::start_poller();
+    crate::core::network::start_monitor();
+    crate::presence::start_monitor(utils::idle_time);
+    crate::quiet_hours::start_monitor();
+    std::thread::spawn(|| utils::check_for_updates(false));
+    std::thread::spawn(utils::cleanup_legacy_webview_profile);
     event_loop.run_app(&mut app)?;
+    crate::clipboard::cleanup();
+    crate::temp_media::cleanup();
+    crate::rendering::relaunch_if_requested();
     Ok(())
 }
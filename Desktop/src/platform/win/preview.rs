@@ -0,0 +1,110 @@
+//! The attachment preview window.
+//!
+//! A plain top-level window with its own webview, created on the event loop or
+//! adopted from the warm standby (see `webview`). It only loads
+//! `miko://downloads/<token>`, has no menu bar and talks to no part of the app
+//! except to close itself; the tray icon and the main page are untouched.
+
+use std::path::Path;
+use std::sync::Arc;
+use winit::dpi::LogicalSize;
+use winit::event_loop::ActiveEventLoop;
+use winit::window::{Window, WindowAttributes, WindowId};
+use tracing::{error, info, warn};
+
+use super::webview::{Standby, WebviewFactory};
+use super::{send_app_event, AppEvent};
+
+pub struct PreviewWindow {
+    window: Arc<Window>,
+    webview: wry::WebView,
+}
+
+fn attributes(title: &str) -> WindowAttributes {
+    Window::default_attributes()
+        .with_title(title)
+        .with_inner_size(LogicalSize::new(900, 700))
+        .with_window_icon(super::utils::load_window_icon())
+}
+
+/// A webview for the preview window, without its URL
+fn builder() -> wry::WebViewBuilder<'static> {
+    WebviewFactory::new()
+        .with_init_script(crate::preview::INIT_SCRIPT)
+        .builder()
+        .with_ipc_handler(|request| {
+            let message: serde_json::Value = serde_json::from_str(request.body()).unwrap_or_default();
+            if message["type"] == "close_preview" {
+                send_app_event(AppEvent::ClosePreview);
+            }
+        })
+}
+
+/// A hidden preview window for the next [`PreviewWindow::open`]
+pub fn standby(event_loop: &ActiveEventLoop) -> Result<Standby, String> {
+    Standby::warm(event_loop, attributes("Preview"), builder())
+}
+
+impl PreviewWindow {
+    /// Show `path` in `standby` if there is one, else in a new window
+    pub fn open(event_loop: &ActiveEventLoop, path: &Path, standby: Option<Standby>) -> Result<Self, String> {
+        let name = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+        let title = format!("{} - Preview", name);
+        let url = crate::preview::register(path);
+
+        let (window, webview) = match standby {
+            Some(standby) => {
+                let (window, webview) = standby.adopt();
+                window.set_title(&title);
+                webview.load_url(&url).map_err(|e| e.to_string())?;
+                window.set_visible(true);
+                (window, webview)
+            }
+            None => {
+                let window = Arc::new(event_loop.create_window(attributes(&title)).map_err(|e| e.to_string())?);
+                let webview = builder().with_url(url).build(&*window).map_err(|e| e.to_string())?;
+                (window, webview)
+            }
+        };
+
+        super::utils::set_capture_excluded(&window, crate::privacy::block_capture());
+        window.focus_window();
+        info!("Previewing {}", path.display());
+        Ok(Self { window, webview })
+    }
+
+    pub fn window(&self) -> &Window {
+        &self.window
+    }
+
+    pub fn id(&self) -> WindowId {
+        self.window.id()
+    }
+
+    pub fn fit(&self, size: winit::dpi::PhysicalSize<u32>) {
+        let bounds = wry::Rect {
+            position: wry::dpi::PhysicalPosition::new(0, 0).into(),
+            size: size.into(),
+        };
+        if let Err(e) = self.webview.set_bounds(bounds) {
+            warn!("Failed to resize the preview: {}", e);
+        }
+    }
+}
+
+/// Files the preview window can't show open in their associated app
+pub fn open_with_default_app(path: &Path) {
+    use windows::core::{w, HSTRING, PCWSTR};
+    use windows::Win32::Foundation::HWND;
+    use windows::Win32::UI::Shell::ShellExecuteW;
+    use windows::Win32::UI::WindowsAndMessaging::SW_SHOWNORMAL;
+
+    let result = unsafe {
+        ShellExecuteW(HWND::default(), w!("open"), &HSTRING::from(path.as_os_str()), PCWSTR::null(), PCWSTR::null(), SW_SHOWNORMAL)
+    };
+    if result.0 as isize > 32 {
+        info!("Opened {}", path.display());
+    } else {
+        error!("Failed to open {} (code {})", path.display(), result.0 as isize);
+    }
+}
@@ -0,0 +1,97 @@
+//! WebView2 printing.
+//!
+//! `ShowPrintUI` needs runtime 1.0.1518; older runtimes get the page as a PDF
+//! in the temp folder, opened in the default viewer to print from there.
+
+use std::path::{Path, PathBuf};
+use webview2_com::Microsoft::Web::WebView2::Win32::{
+    ICoreWebView2PrintSettings, ICoreWebView2_16, ICoreWebView2_7, COREWEBVIEW2_PRINT_DIALOG_KIND_BROWSER,
+};
+use webview2_com::PrintToPdfCompletedHandler;
+use windows_core::{Interface, HSTRING};
+use wry::WebViewExtWindows;
+use tracing::{error, info, warn};
+
+use crate::print::{self, PrintJob};
+
+pub fn run(webview: &wry::WebView, job: PrintJob) {
+    match job {
+        PrintJob::Dialog => {
+            if let Err(e) = show_print_ui(webview) {
+                error!("Printing failed: {}", e);
+                super::utils::show_message("Print", &format!("The page could not be printed.\n\n{}", e));
+            }
+        }
+        PrintJob::Pdf { path, request_id } => {
+            let pending_id = request_id.clone();
+            let finish = move |path: PathBuf, result: Result<(), String>| {
+                if let Err(e) = &result {
+                    super::utils::show_message("Print", &format!("The PDF could not be saved.\n\n{}", e));
+                }
+                print::pdf_finished(pending_id.as_deref(), &path, result);
+            };
+            if let Err(e) = print_to_pdf(webview, &path, finish) {
+                super::utils::show_message("Print", &format!("The PDF could not be saved.\n\n{}", e));
+                print::pdf_finished(request_id.as_deref(), &path, Err(e));
+            }
+        }
+    }
+}
+
+fn show_print_ui(webview: &wry::WebView) -> Result<(), String> {
+    match webview.webview().cast::<ICoreWebView2_16>() {
+        Ok(core) => unsafe { core.ShowPrintUI(COREWEBVIEW2_PRINT_DIALOG_KIND_BROWSER) }.map_err(|e| e.to_string()),
+        Err(_) => {
+            warn!("ShowPrintUI unavailable in this WebView2 runtime, printing through a PDF");
+            let path = crate::paths::temp_dir().join(print::default_pdf_name());
+            print_to_pdf(webview, &path, |path, result| match result {
+                Ok(()) => open_pdf(&path),
+                Err(e) => super::utils::show_message("Print", &format!("The page could not be printed.\n\n{}", e)),
+            })
+        }
+    }
+}
+
+/// Start writing the page to `path`; `done` runs on the UI thread once the
+/// PDF is written. An `Err` here means it never started and `done` won't run.
+fn print_to_pdf(
+    webview: &wry::WebView,
+    path: &Path,
+    done: impl FnOnce(PathBuf, Result<(), String>) + 'static,
+) -> Result<(), String> {
+    let core = webview
+        .webview()
+        .cast::<ICoreWebView2_7>()
+        .map_err(|_| "this WebView2 runtime cannot print to PDF".to_string())?;
+
+    let target = path.to_path_buf();
+    let handler = PrintToPdfCompletedHandler::create(Box::new(move |result, success| {
+        let result = match result {
+            Ok(()) if success => Ok(()),
+            Ok(()) => Err("WebView2 could not write the PDF".to_string()),
+            Err(e) => Err(e.to_string()),
+        };
+        done(target, result);
+        Ok(())
+    }));
+
+    let path = HSTRING::from(path.as_os_str());
+    unsafe { core.PrintToPdf(&path, None::<&ICoreWebView2PrintSettings>, &handler) }.map_err(|e| e.to_string())
+}
+
+fn open_pdf(path: &Path) {
+    use windows::core::{w, HSTRING, PCWSTR};
+    use windows::Win32::Foundation::HWND;
+    use windows::Win32::UI::Shell::ShellExecuteW;
+    use windows::Win32::UI::WindowsAndMessaging::SW_SHOWNORMAL;
+
+    let result = unsafe {
+        ShellExecuteW(HWND::default(), w!("open"), &HSTRING::from(path.as_os_str()), PCWSTR::null(), PCWSTR::null(), SW_SHOWNORMAL)
+    };
+    if result.0 as isize > 32 {
+        info!("Opened {} for printing", path.display());
+    } else {
+        error!("Failed to open {} (code {})", path.display(), result.0 as isize);
+        super::utils::show_message("Print", &format!("The page was saved as a PDF but could not be opened:\n\n{}", path.display()));
+    }
+}
@@ -0,0 +1,74 @@
+//! WebView2 renderer crash detection.
+//!
+//! `ProcessFailed` reports the failure to [`crate::renderer`], which decides
+//! between a delayed reload and giving up with a dialog. GPU process crashes
+//! go to [`crate::rendering`] instead.
+
+use webview2_com::Microsoft::Web::WebView2::Win32::{
+    ICoreWebView2, ICoreWebView2ProcessFailedEventArgs, ICoreWebView2ProcessFailedEventArgs2,
+    COREWEBVIEW2_PROCESS_FAILED_KIND, COREWEBVIEW2_PROCESS_FAILED_KIND_BROWSER_PROCESS_EXITED,
+    COREWEBVIEW2_PROCESS_FAILED_KIND_GPU_PROCESS_EXITED,
+    COREWEBVIEW2_PROCESS_FAILED_KIND_RENDER_PROCESS_EXITED, COREWEBVIEW2_PROCESS_FAILED_KIND_RENDER_PROCESS_UNRESPONSIVE,
+};
+use webview2_com::ProcessFailedEventHandler;
+use windows_core::Interface;
+use wry::WebViewExtWindows;
+use tracing::{error, info, warn};
+
+use crate::renderer::{self, Recovery};
+
+pub fn install_process_failed_handler(webview: &wry::WebView) {
+    let core = webview.webview();
+
+    let handler = ProcessFailedEventHandler::create(Box::new(
+        |_sender: Option<ICoreWebView2>, args: Option<ICoreWebView2ProcessFailedEventArgs>| {
+            let Some(args) = args else { return Ok(()) };
+            let mut kind = COREWEBVIEW2_PROCESS_FAILED_KIND::default();
+            unsafe { args.ProcessFailedKind(&mut kind)? };
+
+            let label = match kind {
+                COREWEBVIEW2_PROCESS_FAILED_KIND_RENDER_PROCESS_EXITED => "renderer exited",
+                COREWEBVIEW2_PROCESS_FAILED_KIND_RENDER_PROCESS_UNRESPONSIVE => "renderer unresponsive",
+                COREWEBVIEW2_PROCESS_FAILED_KIND_BROWSER_PROCESS_EXITED => {
+                    // The controller is gone with it; only a new webview would help
+                    error!("WebView2 browser process exited");
+                    super::utils::show_message("Workspace", "The web view stopped working. Please restart Workspace.");
+                    return Ok(());
+                }
+                // WebView2 restarts it, but a driver that keeps crashing it wants software rendering
+                COREWEBVIEW2_PROCESS_FAILED_KIND_GPU_PROCESS_EXITED => {
+                    crate::rendering::gpu_process_failed();
+                    return Ok(());
+                }
+                // Utility and iframe renderer failures: WebView2 recovers by itself
+                other => {
+                    info!("WebView2 process failure of kind {} recovered by the runtime", other.0);
+                    return Ok(());
+                }
+            };
+            let label = match args.cast::<ICoreWebView2ProcessFailedEventArgs2>() {
+                Ok(args) => {
+                    let mut exit_code = 0i32;
+                    match unsafe { args.ExitCode(&mut exit_code) } {
+                        Ok(()) => format!("{} (exit code {:#x})", label, exit_code),
+                        Err(_) => label.to_string(),
+                    }
+                }
+                Err(_) => label.to_string(),
+            };
+
+            match renderer::failed(&label) {
+                Recovery::Reload => renderer::schedule_reload(|| {
+                    super::send_app_event(super::AppEvent::ReloadPage);
+                }),
+                Recovery::GiveUp(message) => super::utils::show_message("Workspace", &message),
+            }
+            Ok(())
+        },
+    ));
+
+    let mut token = 0i64;
+    if let Err(e) = unsafe { core.add_ProcessFailed(&handler, &mut token) } {
+        warn!("Failed to register ProcessFailed handler: {}", e);
+    }
+}
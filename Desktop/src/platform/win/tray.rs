@@ -1,18 +1,11 @@
 use tray_icon::{TrayIcon, TrayIconBuilder, menu::{Menu, MenuItem, MenuEvent, PredefinedMenuItem}};
 use std::sync::Arc;
 use winit::window::Window;
-use crate::platform::win::{ICON_BYTES, TRAY_ICON_CREATED};
+use crate::platform::tray_menu::RecentAction;
+use tracing::{error, info, warn};
 
-pub fn create_tray_icon(window: Option<Arc<Window>>) -> Result<TrayIcon, Box<dyn std::error::Error>> {
-    // Check global flag to prevent multiple tray icons system-wide
-    {
-        let mut created = TRAY_ICON_CREATED.lock().unwrap();
-        if *created {
-            return Err("Tray icon already exists globally".into());
-        }
-        *created = true;
-    }
-    
+/// The tray menu; rebuilt with [`refresh_menu`] when the download history changes
+fn build_menu() -> Result<Menu, tray_icon::menu::Error> {
     // Create tray menu with explicit IDs to avoid conflicts
     let show_window = MenuItem::with_id("show_window", "Show Window", true, None);
     let hide_window = MenuItem::with_id("hide_window", "Hide Window", true, None);
@@ -32,63 +25,60 @@ pub fn create_tray_icon(window: Option<Arc<Window>>) -> Result<TrayIcon, Box<dyn
     menu.append(&separator1)?;
     menu.append(&open_workspace)?;
     menu.append(&open_downloads)?;
+    crate::platform::tray_menu::append_recent_downloads(&menu)?;
     menu.append(&separator2)?;
     menu.append(&about)?;
     menu.append(&separator3)?;
     menu.append(&exit)?;
-    
-    // Create tray icon using the existing ICON_BYTES
+    Ok(menu)
+}
+
+pub fn refresh_menu(tray: &TrayIcon) {
+    match build_menu() {
+        Ok(menu) => tray.set_menu(Some(Box::new(menu))),
+        Err(e) => warn!("Failed to rebuild the tray menu: {}", e),
+    }
+}
+
+/// A new tray icon. Creating it is all there is to it: the owner drops the
+/// old one first (see `App::recreate_tray`), so there's never two.
+pub fn create_tray_icon() -> Result<TrayIcon, Box<dyn std::error::Error>> {
+    // Create tray icon from the shared app icon
     let tray_icon = TrayIconBuilder::new()
-        .with_menu(Box::new(menu))
+        .with_menu(Box::new(build_menu()?))
         .with_tooltip("Workspace - Desktop Application")
         .with_icon({
-            // Parse the ICO file from ICON_BYTES
-            match ico::IconDir::read(std::io::Cursor::new(ICON_BYTES)) {
-                Ok(icon_dir) => {
-                    if let Some(entry) = icon_dir.entries().iter()
-                        .max_by_key(|entry| (entry.width() as u32, entry.height() as u32, entry.bits_per_pixel())) {
-                        
-                        match entry.decode() {
-                            Ok(image) => {
-                                let rgba_data = image.rgba_data().to_vec();
-                                let width = image.width();
-                                let height = image.height();
-                                
-                                tray_icon::Icon::from_rgba(rgba_data, width, height)?
-                            }
-                            Err(_) => fallback_icon()?
-                        }
-                    } else {
-                        fallback_icon()?
-                    }
-                }
-                Err(_) => fallback_icon()?
-            }
+            let icon = crate::icons::load_app_icon(crate::icons::TRAY_ICON_SIZE);
+            tray_icon::Icon::from_rgba(icon.rgba.clone(), icon.width, icon.height)?
         })
         .build()?;
     
-    println!("✅ Single tray icon created with ID-based menu items (globally unique)");
-    
-    // Handle menu events
+    info!("Tray icon {:?} created", tray_icon.id());
+    Ok(tray_icon)
+}
+
+/// Handle tray menu clicks. Called once: the menu channel is shared by
+/// every icon created after it.
+pub fn handle_menu_events(window: Option<Arc<Window>>) {
     let menu_channel = MenuEvent::receiver();
-    let window_ref = window.clone();
+    let window_ref = window;
     
     std::thread::spawn(move || {
         loop {
             if let Ok(event) = menu_channel.recv() {
-                println!("🔔 Tray menu event received: {}", event.id.0);
+                info!("Tray menu event received: {}", event.id.0);
                 match event.id.0.as_str() {
                     "show_window" => {
                         if let Some(window) = &window_ref {
                             window.set_visible(true);
                             window.focus_window();
-                            println!("✅ Window shown from tray");
+                            info!("Window shown from tray");
                         }
                     }
                     "hide_window" => {
                         if let Some(window) = &window_ref {
                             window.set_visible(false);
-                            println!("✅ Window hidden to tray");
+                            info!("Window hidden to tray");
                         }
                     }
                     "open_workspace" => {
@@ -104,16 +94,13 @@ pub fn create_tray_icon(window: Option<Arc<Window>>) -> Result<TrayIcon, Box<dyn
                         }
                         
                         if let Err(e) = command.spawn() {
-                            println!("❌ Failed to open workspace URL: {}", e);
+                            error!("Failed to open workspace URL: {}", e);
                         } else {
-                            println!("✅ Opened workspace in browser");
+                            info!("Opened workspace in browser");
                         }
                     }
                     "open_downloads" => {
-                        // Open Downloads folder
-                        let downloads_dir = dirs::download_dir().unwrap_or_else(|| {
-                            std::env::current_dir().unwrap().join("Downloads")
-                        });
+                        let downloads_dir = crate::core::downloads::download_dir();
                         
                         let mut command = std::process::Command::new("explorer");
                         command.arg(&downloads_dir);
@@ -126,19 +113,18 @@ pub fn create_tray_icon(window: Option<Arc<Window>>) -> Result<TrayIcon, Box<dyn
                         }
                         
                         if let Err(e) = command.spawn() {
-                            println!("❌ Failed to open Downloads folder: {}", e);
+                            error!("Failed to open Downloads folder: {}", e);
                         } else {
-                            println!("✅ Opened Downloads folder");
+                            info!("Opened Downloads folder");
                         }
                     }
                     "about" => {
                         // Show about dialog
-                        println!("📋 Workspace Desktop Application v0.1.0");
+                        info!("Workspace Desktop Application v0.1.0");
                         
                         #[cfg(windows)]
                         {
-                            use windows::Win32::UI::WindowsAndMessaging::{MessageBoxW, MB_OK, MB_ICONINFORMATION};
-                            use windows::Win32::Foundation::HWND;
+                            use windows::Win32::UI::WindowsAndMessaging::{MessageBoxW, MB_OK, MB_ICONINFORMATION, MB_SETFOREGROUND};
                             use std::ffi::OsStr;
                             use std::os::windows::ffi::OsStrExt;
                             
@@ -157,50 +143,29 @@ Features: File downloads, tray integration, WebView2")
                             
                             unsafe {
                                 MessageBoxW(
-                                    HWND::default(),
+                                    super::utils::main_hwnd(),
                                     windows::core::PCWSTR(message.as_ptr()),
                                     windows::core::PCWSTR(title.as_ptr()),
-                                    MB_OK | MB_ICONINFORMATION
+                                    MB_OK | MB_ICONINFORMATION | MB_SETFOREGROUND
                                 );
                             }
                         }
                     }
                     "exit" => {
-                        println!("👋 Exiting application from tray menu");
-                        
-                        // Reset the global flag when exiting
-                        {
-                            if let Ok(mut created) = TRAY_ICON_CREATED.lock() {
-                                *created = false;
-                            }
+                        info!("Exiting application from tray menu");
+
+                        // The event loop asks first if transfers are running
+                        if !super::send_app_event(super::AppEvent::ExitRequested) {
+                            std::process::exit(0);
                         }
-                        
-                        std::process::exit(0);
-                    }
-                    _ => {
-                        println!("❓ Unknown tray menu action: {}", event.id.0);
                     }
+                    id => match crate::platform::tray_menu::recent_action(id) {
+                        Some(RecentAction::Open(path)) => super::preview::open_with_default_app(&path),
+                        Some(RecentAction::Show(path)) => super::download::show_file_in_explorer(&path.to_string_lossy()),
+                        None => warn!("Unknown tray menu action: {}", event.id.0),
+                    },
                 }
             }
         }
     });
-    
-    Ok(tray_icon)
-}
-
-fn fallback_icon() -> Result<tray_icon::Icon, Box<dyn std::error::Error>> {
-    let size = 32;
-    let mut rgba_data = Vec::with_capacity((size * size * 4) as usize);
-    
-    for y in 0..size {
-        for x in 0..size {
-            let r = ((x as f32 / size as f32) * 255.0) as u8;
-            let g = ((y as f32 / size as f32) * 255.0) as u8;
-            let b = 128;
-            let a = 255;
-            
-            rgba_data.extend_from_slice(&[r, g, b, a]);
-        }
-    }
-    Ok(tray_icon::Icon::from_rgba(rgba_data, size, size)?)
 }
@@ -1,12 +1,13 @@
 use winit::window::Icon;
-use crate::platform::win::ICON_BYTES;
+use std::time::Duration;
+use tracing::{info, warn};
 
 #[cfg(windows)]
 pub fn configure_webview2_permissions() -> Result<(), Box<dyn std::error::Error>> {
     use winreg::enums::*;
     use winreg::RegKey;
     
-    println!("🔧 Configuring WebView2 permissions in registry...");
+    info!("Configuring WebView2 permissions in registry...");
     
     // Try to set permissions for the current user
     let hkcu = RegKey::predef(HKEY_CURRENT_USER);
@@ -18,37 +19,37 @@ pub fn configure_webview2_permissions() -> Result<(), Box<dyn std::error::Error>
         Ok((webview2_key, _)) => {
             // Set clipboard permissions to always allow
             if let Err(e) = webview2_key.set_value("ClipboardReadWritePermission", &1u32) {
-                println!("⚠️ Failed to set clipboard permission: {}", e);
+                warn!("Failed to set clipboard permission: {}", e);
             } else {
-                println!("✅ Clipboard permission set to always allow");
+                info!("Clipboard permission set to always allow");
             }
             
             // Disable permission prompts
             if let Err(e) = webview2_key.set_value("DisablePermissionPrompts", &1u32) {
-                println!("⚠️ Failed to disable permission prompts: {}", e);
+                warn!("Failed to disable permission prompts: {}", e);
             } else {
-                println!("✅ Permission prompts disabled");
+                info!("Permission prompts disabled");
             }
             
             // Set default permissions to granted
             if let Err(e) = webview2_key.set_value("DefaultPermissionState", &"granted") {
-                println!("⚠️ Failed to set default permission state: {}", e);
+                warn!("Failed to set default permission state: {}", e);
             } else {
-                println!("✅ Default permission state set to granted");
+                info!("Default permission state set to granted");
             }
             
             // Disable security warnings
             if let Err(e) = webview2_key.set_value("DisableSecurityWarnings", &1u32) {
-                println!("⚠️ Failed to disable security warnings: {}", e);
+                warn!("Failed to disable security warnings: {}", e);
             } else {
-                println!("✅ Security warnings disabled");
+                info!("Security warnings disabled");
             }
             
-            println!("✅ WebView2 permissions configured successfully");
+            info!("WebView2 permissions configured successfully");
         }
         Err(e) => {
-            println!("⚠️ Failed to create WebView2 registry key: {}", e);
-            println!("   This is normal if running without admin privileges");
+            warn!("Failed to create WebView2 registry key: {}", e);
+            warn!("This is normal if running without admin privileges");
         }
     }
     
@@ -59,23 +60,23 @@ pub fn configure_webview2_permissions() -> Result<(), Box<dyn std::error::Error>
         Ok((app_key, _)) => {
             // Set clipboard permissions for this specific app
             if let Err(e) = app_key.set_value("clipboard", &"allow") {
-                println!("⚠️ Failed to set app-specific clipboard permission: {}", e);
+                warn!("Failed to set app-specific clipboard permission: {}", e);
             } else {
-                println!("✅ App-specific clipboard permission set");
+                info!("App-specific clipboard permission set");
             }
             
             // Set all permissions to allow for this app
             let permissions = ["clipboard-read", "clipboard-write", "clipboard"];
             for permission in &permissions {
                 if let Err(e) = app_key.set_value(permission, &"allow") {
-                    println!("⚠️ Failed to set {} permission: {}", permission, e);
+                    warn!("Failed to set {} permission: {}", permission, e);
                 } else {
-                    println!("✅ {} permission set to allow", permission);
+                    info!("{} permission set to allow", permission);
                 }
             }
         }
         Err(e) => {
-            println!("⚠️ Failed to create app-specific registry key: {}", e);
+            warn!("Failed to create app-specific registry key: {}", e);
         }
     }
     
@@ -84,80 +85,516 @@ pub fn configure_webview2_permissions() -> Result<(), Box<dyn std::error::Error>
 
 #[cfg(not(windows))]
 pub fn configure_webview2_permissions() -> Result<(), Box<dyn std::error::Error>> {
-    println!("⚠️ WebView2 permission configuration only available on Windows");
+    warn!("WebView2 permission configuration only available on Windows");
     Ok(())
 }
 
 pub fn load_window_icon() -> Option<Icon> {
-    // First try to load from embedded bytes
-    println!("Attempting to load window icon from embedded bytes ({} bytes)", ICON_BYTES.len());
-    
-    match ico::IconDir::read(std::io::Cursor::new(ICON_BYTES)) {
-        Ok(icon_dir) => {
-            println!("ICO file parsed successfully, {} entries found", icon_dir.entries().len());
-            
-            // Find the best icon (largest size, highest bit depth)
-            if let Some(entry) = icon_dir.entries().iter()
-                .max_by_key(|entry| (entry.width() as u32, entry.height() as u32, entry.bits_per_pixel())) {
-                
-                println!("Selected icon entry: {}x{} @ {} bpp", entry.width(), entry.height(), entry.bits_per_pixel());
-                
-                match entry.decode() {
-                    Ok(image) => {
-                        let rgba_data = image.rgba_data().to_vec();
-                        let width = image.width();
-                        let height = image.height();
-                        
-                        println!("Icon decoded to RGBA: {}x{}, {} bytes", width, height, rgba_data.len());
-                        
-                        match Icon::from_rgba(rgba_data, width, height) {
-                            Ok(icon) => {
-                                println!("✅ Window icon loaded successfully ({}x{})", width, height);
-                                return Some(icon);
-                            }
-                            Err(e) => {
-                                println!("⚠️ Failed to create winit icon from RGBA data: {}", e);
-                            }
-                        }
-                    }
-                    Err(e) => {
-                        println!("⚠️ Failed to decode icon entry: {}", e);
-                    }
-                }
-            } else {
-                println!("⚠️ No icon entries found in ICO file");
-            }
+    let icon = crate::icons::load_app_icon(crate::icons::WINDOW_ICON_SIZE);
+    match Icon::from_rgba(icon.rgba.clone(), icon.width, icon.height) {
+        Ok(window_icon) => {
+            info!("Window icon loaded successfully ({}x{})", icon.width, icon.height);
+            Some(window_icon)
         }
         Err(e) => {
-            println!("⚠️ Failed to parse embedded ICO file: {}", e);
+            warn!("Failed to create winit icon from RGBA data: {}", e);
+            None
         }
     }
-    
-    // Fallback: try to create a simple colored icon
-    println!("Creating fallback icon...");
-    let size = 32;
-    let mut rgba_data = Vec::with_capacity((size * size * 4) as usize);
-    
-    for y in 0..size {
-        for x in 0..size {
-            // Create a simple gradient icon
-            let r = ((x as f32 / size as f32) * 255.0) as u8;
-            let g = ((y as f32 / size as f32) * 255.0) as u8;
-            let b = 128;
-            let a = 255;
-            
-            rgba_data.extend_from_slice(&[r, g, b, a]);
+}
+
+/// The main window's handle, 0 until it exists
+static MAIN_HWND: std::sync::atomic::AtomicIsize = std::sync::atomic::AtomicIsize::new(0);
+
+/// Remember the main window; it owns the app's message boxes and file
+/// dialogs from then on, so they open focused and screen readers announce them
+pub fn set_main_hwnd(hwnd: windows::Win32::Foundation::HWND) {
+    MAIN_HWND.store(hwnd.0 as isize, std::sync::atomic::Ordering::SeqCst);
+}
+
+/// Owner for dialogs; no owner before the main window exists
+pub fn main_hwnd() -> windows::Win32::Foundation::HWND {
+    windows::Win32::Foundation::HWND(MAIN_HWND.load(std::sync::atomic::Ordering::SeqCst) as *mut std::ffi::c_void)
+}
+
+/// High contrast is on (Settings > Accessibility > Contrast themes)
+pub fn high_contrast() -> bool {
+    use windows::Win32::UI::Accessibility::{HCF_HIGHCONTRASTON, HIGHCONTRASTW};
+    use windows::Win32::UI::WindowsAndMessaging::{SystemParametersInfoW, SPI_GETHIGHCONTRAST, SYSTEM_PARAMETERS_INFO_UPDATE_FLAGS};
+
+    let mut info = HIGHCONTRASTW { cbSize: std::mem::size_of::<HIGHCONTRASTW>() as u32, ..Default::default() };
+    unsafe {
+        SystemParametersInfoW(
+            SPI_GETHIGHCONTRAST,
+            info.cbSize,
+            Some(&mut info as *mut HIGHCONTRASTW as *mut std::ffi::c_void),
+            SYSTEM_PARAMETERS_INFO_UPDATE_FLAGS(0),
+        )
+        .is_ok()
+            && info.dwFlags.0 & HCF_HIGHCONTRASTON.0 != 0
+    }
+}
+
+/// Ask for a destination file with the native "Save As" dialog
+#[cfg(windows)]
+pub fn choose_save_path(default_name: &str, filter_name: &str, extension: &str) -> Option<std::path::PathBuf> {
+    use windows::core::{PCWSTR, PWSTR};
+    use windows::Win32::UI::Controls::Dialogs::{
+        GetSaveFileNameW, OPENFILENAMEW, OFN_NOCHANGEDIR, OFN_OVERWRITEPROMPT, OFN_PATHMUSTEXIST,
+    };
+
+    let mut file_buf = [0u16; 1024];
+    for (slot, c) in file_buf.iter_mut().zip(default_name.encode_utf16().take(1023)) {
+        *slot = c;
+    }
+    // Filter pairs are NUL separated and the list is double-NUL terminated
+    let filter: Vec<u16> = format!("{} (*.{})\0*.{}\0", filter_name, extension, extension)
+        .encode_utf16()
+        .chain(std::iter::once(0))
+        .collect();
+    let def_ext: Vec<u16> = extension.encode_utf16().chain(std::iter::once(0)).collect();
+
+    let mut ofn = OPENFILENAMEW {
+        lStructSize: std::mem::size_of::<OPENFILENAMEW>() as u32,
+        hwndOwner: main_hwnd(),
+        lpstrFilter: PCWSTR(filter.as_ptr()),
+        lpstrFile: PWSTR(file_buf.as_mut_ptr()),
+        nMaxFile: file_buf.len() as u32,
+        lpstrDefExt: PCWSTR(def_ext.as_ptr()),
+        Flags: OFN_OVERWRITEPROMPT | OFN_PATHMUSTEXIST | OFN_NOCHANGEDIR,
+        ..Default::default()
+    };
+
+    unsafe {
+        if !GetSaveFileNameW(&mut ofn).as_bool() {
+            return None;
         }
     }
-    
-    match Icon::from_rgba(rgba_data, size, size) {
-        Ok(icon) => {
-            println!("✅ Fallback icon created successfully ({}x{})", size, size);
-            Some(icon)
+
+    let len = file_buf.iter().position(|&c| c == 0).unwrap_or(file_buf.len());
+    Some(std::path::PathBuf::from(String::from_utf16_lossy(&file_buf[..len])))
+}
+
+/// Ask for a folder with the native folder picker
+#[cfg(windows)]
+pub fn choose_folder(title: &str) -> Option<std::path::PathBuf> {
+    use windows::core::HSTRING;
+    use windows::Win32::System::Com::{CoCreateInstance, CoInitializeEx, CoTaskMemFree, CLSCTX_INPROC_SERVER, COINIT_APARTMENTTHREADED};
+    use windows::Win32::UI::Shell::{FileOpenDialog, IFileOpenDialog, FOS_FORCEFILESYSTEM, FOS_PICKFOLDERS, SIGDN_FILESYSPATH};
+
+    unsafe {
+        // Called on a worker thread; S_FALSE (already initialized) is fine
+        let _ = CoInitializeEx(None, COINIT_APARTMENTTHREADED);
+        let dialog: IFileOpenDialog = CoCreateInstance(&FileOpenDialog, None, CLSCTX_INPROC_SERVER).ok()?;
+        let options = dialog.GetOptions().ok()?;
+        dialog.SetOptions(options | FOS_PICKFOLDERS | FOS_FORCEFILESYSTEM).ok()?;
+        let _ = dialog.SetTitle(&HSTRING::from(title));
+        // Fails with ERROR_CANCELLED when the user closes the dialog
+        dialog.Show(main_hwnd()).ok()?;
+
+        let name = dialog.GetResult().ok()?.GetDisplayName(SIGDN_FILESYSPATH).ok()?;
+        let path = name.to_string().ok();
+        CoTaskMemFree(Some(name.0 as *const _));
+        path.map(std::path::PathBuf::from)
+    }
+}
+
+/// Let the user pick a location and write the diagnostics archive there
+#[cfg(windows)]
+pub fn export_diagnostics() -> serde_json::Value {
+    let Some(path) = choose_save_path(&crate::diagnostics::default_export_name(), "Zip archive", "zip") else {
+        return serde_json::json!({ "success": false, "cancelled": true });
+    };
+
+    match crate::diagnostics::export_logs(&path) {
+        Ok(path) => serde_json::json!({ "success": true, "path": path.to_string_lossy() }),
+        Err(e) => {
+            warn!("Failed to export diagnostic logs: {}", e);
+            serde_json::json!({ "success": false, "error": e.to_string() })
+        }
+    }
+}
+
+/// Let the user pick a location and export the thread there
+#[cfg(windows)]
+pub fn export_thread(request: crate::export::ExportRequest) -> serde_json::Value {
+    let default_name = crate::export::default_file_name(&request.thread_id, request.format);
+    let Some(path) = choose_save_path(&default_name, request.format.filter_name(), request.format.extension()) else {
+        return serde_json::json!({ "success": false, "threadId": request.thread_id, "cancelled": true });
+    };
+    crate::export::export_to(&request, &path)
+}
+
+/// Modal Yes/No question; true when the user picked Yes
+#[cfg(windows)]
+pub fn confirm(title: &str, message: &str) -> bool {
+    use windows::core::HSTRING;
+    use windows::Win32::UI::WindowsAndMessaging::{MessageBoxW, IDYES, MB_ICONQUESTION, MB_SETFOREGROUND, MB_YESNO};
+
+    unsafe {
+        MessageBoxW(
+            main_hwnd(),
+            &HSTRING::from(message),
+            &HSTRING::from(title),
+            MB_YESNO | MB_ICONQUESTION | MB_SETFOREGROUND,
+        ) == IDYES
+    }
+}
+
+/// Ask whether to quit while transfers are running: Yes quits now, No
+/// finishes them in the background, Cancel keeps the window open
+#[cfg(windows)]
+pub fn ask_quit(in_flight: &str) -> crate::core::shutdown::QuitChoice {
+    use crate::core::shutdown::QuitChoice;
+    use windows::core::HSTRING;
+    use windows::Win32::UI::WindowsAndMessaging::{MessageBoxW, IDNO, IDYES, MB_ICONWARNING, MB_SETFOREGROUND, MB_YESNOCANCEL};
+
+    let message = format!(
+        "{} Quit anyway?\n\n\
+         Yes: quit now. Interrupted downloads resume next time.\n\
+         No: finish in the background, then quit.\n\
+         Cancel: keep Workspace open.",
+        in_flight
+    );
+    match unsafe {
+        MessageBoxW(main_hwnd(), &HSTRING::from(message), &HSTRING::from("Workspace"), MB_YESNOCANCEL | MB_ICONWARNING | MB_SETFOREGROUND)
+    } {
+        IDYES => QuitChoice::Quit,
+        IDNO => QuitChoice::FinishInBackground,
+        _ => QuitChoice::Stay,
+    }
+}
+
+/// Start the installer elevated ("runas" shows the UAC prompt)
+#[cfg(windows)]
+pub fn launch_installer(path: &std::path::Path) -> Result<(), String> {
+    use windows::core::{w, HSTRING, PCWSTR};
+    use windows::Win32::Foundation::HWND;
+    use windows::Win32::UI::Shell::ShellExecuteW;
+    use windows::Win32::UI::WindowsAndMessaging::SW_SHOWNORMAL;
+
+    let result = unsafe {
+        ShellExecuteW(
+            HWND::default(),
+            w!("runas"),
+            &HSTRING::from(path.as_os_str()),
+            w!("/UPDATE /SILENT"),
+            PCWSTR::null(),
+            SW_SHOWNORMAL,
+        )
+    };
+    // Values up to 32 are error codes (e.g. the UAC prompt was declined)
+    if result.0 as isize > 32 {
+        info!("Launched installer {}", path.display());
+        Ok(())
+    } else {
+        Err(format!("could not start the installer (code {})", result.0 as isize))
+    }
+}
+
+/// Download and verify the available update, then install it after
+/// confirmation, closing the app. Declining snoozes the update.
+#[cfg(windows)]
+pub fn install_update(webview: Option<crate::ipc::WebviewHandle>) -> serde_json::Value {
+    if !crate::metered::allow("Downloading the update") {
+        crate::metered::when_unmetered(move || {
+            if let Some(error) = install_update(webview)["error"].as_str() {
+                show_message("Update Failed", &format!("The update could not be installed.\n\n{}", error));
+            }
+        });
+        return serde_json::json!({ "success": true, "installed": false, "deferred": true });
+    }
+    let prepared = crate::updates::prepare_installer(|url, path| {
+        let filename = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+        super::download::run_downloader(url, path, &filename, Vec::new(), true, webview, None)
+    });
+    let (manifest, path) = match prepared {
+        Ok(prepared) => prepared,
+        Err(e) => return serde_json::json!({ "success": false, "error": e }),
+    };
+
+    let message = format!(
+        "Workspace {} has been downloaded.\n\nInstall it now? Workspace will close while the update is installed.",
+        manifest.latest_version
+    );
+    if !confirm("Install Update", &message) {
+        crate::updates::remind_later(&manifest.latest_version);
+        return serde_json::json!({ "success": true, "installed": false, "version": manifest.latest_version });
+    }
+
+    match launch_installer(&path) {
+        Ok(()) => {
+            super::send_app_event(super::AppEvent::Quit);
+            serde_json::json!({ "success": true, "installed": true, "version": manifest.latest_version })
         }
         Err(e) => {
-            println!("⚠️ Failed to create fallback icon: {}", e);
-            None
+            warn!("Failed to launch installer: {}", e);
+            serde_json::json!({ "success": false, "error": e })
         }
     }
 }
+
+/// Check for updates and offer to install one. `interactive` (the menu item)
+/// ignores "remind me later" and also reports when nothing is available.
+#[cfg(windows)]
+pub fn check_for_updates(interactive: bool) {
+    let manifest = match crate::updates::check() {
+        Ok(Some(manifest)) => manifest,
+        Ok(None) => {
+            if interactive {
+                let message = format!("You are using the latest version of Workspace ({}).", env!("CARGO_PKG_VERSION"));
+                show_message("Check for Updates", &message);
+            }
+            return;
+        }
+        Err(e) => {
+            warn!("{}", e);
+            if interactive {
+                show_message("Check for Updates", &format!("Could not check for updates.\n\n{}", e));
+            }
+            return;
+        }
+    };
+
+    if !interactive && crate::updates::is_snoozed(&manifest.latest_version) {
+        info!("Update {} postponed by the user", manifest.latest_version);
+        return;
+    }
+
+    let mut message = format!(
+        "Workspace {} is available (you have {}).",
+        manifest.latest_version,
+        env!("CARGO_PKG_VERSION")
+    );
+    if let Some(notes) = &manifest.release_notes {
+        message.push_str(&format!("\n\n{}", notes));
+    }
+    message.push_str("\n\nDownload the update now? Choose No to be reminded later.");
+
+    if confirm("Update Available", &message) {
+        let result = install_update(crate::ipc::handle());
+        if let Some(error) = result["error"].as_str() {
+            show_message("Update Failed", &format!("The update could not be installed.\n\n{}", error));
+        }
+    } else {
+        crate::updates::remind_later(&manifest.latest_version);
+    }
+}
+
+#[cfg(windows)]
+/// Profile location used before it moved to the data directory
+const LEGACY_PROFILE_DIR: &str = "MikoWorkspace_WebView2";
+/// A legacy profile untouched for this long is deleted outright
+const LEGACY_PROFILE_MAX_AGE: Duration = Duration::from_secs(14 * 24 * 60 * 60);
+
+/// Startup housekeeping for the old `%TEMP%` profile: delete it once stale,
+/// otherwise clear the lock files a crashed instance left behind
+pub fn cleanup_legacy_webview_profile() {
+    let legacy = std::env::temp_dir().join(LEGACY_PROFILE_DIR);
+    if !legacy.is_dir() || legacy == crate::paths::webview_user_data_dir(crate::core::settings::get().webview2_user_data_dir.as_deref()) {
+        return;
+    }
+
+    let age = std::fs::metadata(&legacy)
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|modified| modified.elapsed().ok());
+    if age.is_some_and(|age| age >= LEGACY_PROFILE_MAX_AGE) {
+        match std::fs::remove_dir_all(&legacy) {
+            Ok(()) => info!("Removed stale WebView2 profile {}", legacy.display()),
+            Err(e) => warn!("Failed to remove stale WebView2 profile {}: {}", legacy.display(), e),
+        }
+        return;
+    }
+
+    // Held open by a running browser process, so deleting only succeeds when orphaned
+    for lock in [legacy.join("lockfile"), legacy.join("EBWebView").join("lockfile")] {
+        if lock.exists() && std::fs::remove_file(&lock).is_ok() {
+            info!("Removed orphaned WebView2 lock file {}", lock.display());
+        }
+    }
+}
+
+/// Evergreen bootstrapper; it fetches and installs the current runtime
+const WEBVIEW2_BOOTSTRAPPER_URL: &str = "https://go.microsoft.com/fwlink/p/?LinkId=2124703";
+
+/// Version of the installed WebView2 runtime (or the fixed runtime pointed to by
+/// `WEBVIEW2_BROWSER_EXECUTABLE_FOLDER`), if any
+pub fn webview2_runtime_version() -> Option<String> {
+    use webview2_com::Microsoft::Web::WebView2::Win32::GetAvailableCoreWebView2BrowserVersionString;
+    use windows::Win32::System::Com::CoTaskMemFree;
+    use windows_core::{PCWSTR, PWSTR};
+
+    let mut version = PWSTR::null();
+    unsafe {
+        GetAvailableCoreWebView2BrowserVersionString(PCWSTR::null(), &mut version).ok()?;
+        if version.is_null() {
+            return None;
+        }
+        let result = version.to_string().ok();
+        CoTaskMemFree(Some(version.0 as *const _));
+        result
+    }
+}
+
+/// Explain that the WebView2 runtime is missing and offer to install it.
+/// Returns true when webview creation should be attempted again.
+pub fn install_webview2_runtime() -> bool {
+    let accepted = confirm(
+        "Workspace",
+        "Workspace needs the Microsoft Edge WebView2 Runtime, which is not installed on this computer.\n\n\
+         Download and install it now?",
+    );
+    if !accepted {
+        return false;
+    }
+
+    let path = crate::paths::temp_dir().join("MicrosoftEdgeWebview2Setup.exe");
+    let installed = super::download::run_downloader(WEBVIEW2_BOOTSTRAPPER_URL, &path, "MicrosoftEdgeWebview2Setup.exe", Vec::new(), false, None, None)
+        .and_then(|()| {
+            info!("Running WebView2 bootstrapper");
+            std::process::Command::new(&path)
+                .args(["/silent", "/install"])
+                .status()
+                .map_err(|e| format!("could not start the installer: {}", e))
+        })
+        .and_then(|status| if status.success() { Ok(()) } else { Err(format!("the installer failed ({})", status)) });
+    let _ = std::fs::remove_file(&path);
+
+    if let Err(e) = installed {
+        warn!("WebView2 runtime install failed: {}", e);
+        show_message("Workspace", &format!("The WebView2 Runtime could not be installed: {}", e));
+    }
+    // Either way the caller checks again and, if still missing, asks again
+    true
+}
+
+/// Modal message with an OK button
+pub fn show_message(title: &str, message: &str) {
+    use windows::core::HSTRING;
+    use windows::Win32::UI::WindowsAndMessaging::{MessageBoxW, MB_ICONINFORMATION, MB_OK, MB_SETFOREGROUND};
+
+    unsafe {
+        MessageBoxW(main_hwnd(), &HSTRING::from(message), &HSTRING::from(title), MB_OK | MB_ICONINFORMATION | MB_SETFOREGROUND);
+    }
+}
+
+/// Keep `window` out of screenshots, recordings and screen shares
+/// (`privacy.block_capture`). Windows before 10 2004 can't exclude a window,
+/// so it is captured as a black rectangle there instead.
+pub fn set_capture_excluded(window: &winit::window::Window, excluded: bool) {
+    use windows::Win32::Foundation::HWND;
+    use windows::Win32::UI::WindowsAndMessaging::{SetWindowDisplayAffinity, WDA_EXCLUDEFROMCAPTURE, WDA_MONITOR, WDA_NONE};
+    use winit::raw_window_handle::{HasWindowHandle, RawWindowHandle};
+
+    let Ok(RawWindowHandle::Win32(handle)) = window.window_handle().map(|h| h.as_raw()) else { return };
+    let hwnd = HWND(handle.hwnd.get() as *mut std::ffi::c_void);
+    unsafe {
+        let result = if excluded {
+            SetWindowDisplayAffinity(hwnd, WDA_EXCLUDEFROMCAPTURE).or_else(|_| SetWindowDisplayAffinity(hwnd, WDA_MONITOR))
+        } else {
+            SetWindowDisplayAffinity(hwnd, WDA_NONE)
+        };
+        if let Err(e) = result {
+            warn!("Failed to set the capture affinity of a window: {}", e);
+        }
+    }
+}
+
+/// The part of `monitor` not taken by the taskbar, in physical pixels
+pub fn monitor_work_area(monitor: &winit::monitor::MonitorHandle) -> Option<crate::window_layout::Area> {
+    use windows::Win32::Graphics::Gdi::{GetMonitorInfoW, HMONITOR, MONITORINFO};
+    use winit::platform::windows::MonitorHandleExtWindows;
+
+    let hmonitor = HMONITOR(monitor.hmonitor() as isize as *mut std::ffi::c_void);
+    let mut info = MONITORINFO { cbSize: std::mem::size_of::<MONITORINFO>() as u32, ..Default::default() };
+    unsafe {
+        if !GetMonitorInfoW(hmonitor, &mut info).as_bool() {
+            return None;
+        }
+    }
+    let work = info.rcWork;
+    Some(crate::window_layout::Area {
+        x: work.left,
+        y: work.top,
+        width: (work.right - work.left).max(0) as u32,
+        height: (work.bottom - work.top).max(0) as u32,
+    })
+}
+
+/// Time since the last keyboard or mouse input in this session
+pub fn idle_time() -> Option<Duration> {
+    use windows::Win32::System::SystemInformation::GetTickCount;
+    use windows::Win32::UI::Input::KeyboardAndMouse::{GetLastInputInfo, LASTINPUTINFO};
+
+    let mut info = LASTINPUTINFO { cbSize: std::mem::size_of::<LASTINPUTINFO>() as u32, dwTime: 0 };
+    unsafe {
+        if !GetLastInputInfo(&mut info).as_bool() {
+            return None;
+        }
+        // Both tick counts wrap after ~49 days; wrapping_sub keeps the difference right
+        Some(Duration::from_millis(GetTickCount().wrapping_sub(info.dwTime) as u64))
+    }
+}
+
+/// The internet connection is metered (or roaming, or near its data limit),
+/// per Windows' connection cost
+pub fn metered_connection() -> bool {
+    use windows::Networking::Connectivity::{NetworkCostType, NetworkInformation};
+
+    let cost = NetworkInformation::GetInternetConnectionProfile().and_then(|profile| profile.GetConnectionCost());
+    match cost {
+        Ok(cost) => {
+            let limited = cost.NetworkCostType().is_ok_and(|kind| kind == NetworkCostType::Fixed || kind == NetworkCostType::Variable);
+            limited
+                || cost.Roaming().unwrap_or(false)
+                || cost.ApproachingDataLimit().unwrap_or(false)
+                || cost.OverDataLimit().unwrap_or(false)
+        }
+        // No internet profile: nothing to save by skipping
+        Err(_) => false,
+    }
+}
+
+/// Shift is held down right now (safe-mode launch)
+pub fn shift_held() -> bool {
+    use windows::Win32::UI::Input::KeyboardAndMouse::{GetAsyncKeyState, VK_SHIFT};
+
+    unsafe { GetAsyncKeyState(VK_SHIFT.0 as i32) as u16 & 0x8000 != 0 }
+}
+
+/// Add `words` to the Windows user dictionary WebView2 spellchecks against,
+/// for each of `languages` (the user's locale when empty)
+pub fn learn_words(words: &[String], languages: &[String]) -> Result<(), String> {
+    use windows::core::HSTRING;
+    use windows::Win32::Globalization::{GetUserDefaultLocaleName, ISpellCheckerFactory, SpellCheckerFactory};
+    use windows::Win32::System::Com::{CoCreateInstance, CoInitializeEx, CLSCTX_INPROC_SERVER, COINIT_MULTITHREADED};
+
+    let mut languages = languages.to_vec();
+    if languages.is_empty() {
+        let mut buf = [0u16; 85];
+        let len = unsafe { GetUserDefaultLocaleName(&mut buf) };
+        if len <= 1 {
+            return Err("could not determine the user's language".to_string());
+        }
+        languages.push(String::from_utf16_lossy(&buf[..len as usize - 1]));
+    }
+
+    unsafe {
+        let _ = CoInitializeEx(None, COINIT_MULTITHREADED);
+        let factory: ISpellCheckerFactory =
+            CoCreateInstance(&SpellCheckerFactory, None, CLSCTX_INPROC_SERVER).map_err(|e| e.to_string())?;
+        for language in &languages {
+            let tag = HSTRING::from(language.as_str());
+            if !factory.IsSupported(&tag).map(|b| b.as_bool()).unwrap_or(false) {
+                warn!("No Windows spellchecker for {}", language);
+                continue;
+            }
+            let checker = factory.CreateSpellChecker(&tag).map_err(|e| e.to_string())?;
+            for word in words {
+                checker.Add(&HSTRING::from(word.as_str())).map_err(|e| format!("{}: {}", word, e))?;
+            }
+        }
+    }
+    Ok(())
+}
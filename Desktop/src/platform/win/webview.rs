@@ -0,0 +1,156 @@
+//! Building webviews, for the main window and the secondary ones.
+//!
+//! Webviews with the same user data folder and browser arguments share one
+//! WebView2 environment (and browser process); WebView2 refuses to create one
+//! whose arguments differ from a running one's. Every webview therefore starts
+//! from [`WebviewFactory::builder`], which applies them, and brings its own init
+//! scripts and `miko://` handler.
+//!
+//! A webview still takes a second or two to come up, so once the page has
+//! loaded and the app is idle a hidden [`Standby`] is warmed: a window and
+//! webview built as a secondary window will need them, kept on `about:blank`.
+//! The window opening next adopts it and another standby is warmed behind it.
+//! Off with `warm_standby_webview` and in safe mode.
+
+use std::borrow::Cow;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+use winit::event_loop::ActiveEventLoop;
+use winit::window::{Window, WindowAttributes};
+use wry::{WebViewBuilder, WebViewBuilderExtWindows};
+use tracing::{info, warn};
+
+use super::{send_app_event, utils, AppEvent};
+
+/// Wait after startup or an adoption before warming a standby, so it doesn't
+/// compete with what the user is doing
+const STANDBY_DELAY: Duration = Duration::from_secs(5);
+
+/// Serves `miko://` for one webview
+pub type ProtocolHandler = fn(http::Request<Vec<u8>>, wry::RequestAsyncResponder);
+
+static STANDBY_SCHEDULED: AtomicBool = AtomicBool::new(false);
+
+/// Point WebView2 at the app's profile and settle the browser arguments; once
+/// per process, as every webview has to agree on them
+fn browser_args() -> &'static str {
+    static ARGS: OnceLock<String> = OnceLock::new();
+    ARGS.get_or_init(|| {
+        let user_data_dir = crate::paths::webview_user_data_dir(crate::core::settings::get().webview2_user_data_dir.as_deref());
+        // Under %LOCALAPPDATA% the folder inherits the user-only ACL
+        if let Err(e) = std::fs::create_dir_all(&user_data_dir) {
+            warn!("Failed to create WebView2 profile {}: {}", user_data_dir.display(), e);
+        }
+        std::env::set_var("WEBVIEW2_USER_DATA_FOLDER", &user_data_dir);
+
+        let _ = utils::configure_webview2_permissions();
+
+        std::env::set_var("WEBVIEW2_DISABLE_PERMISSION_PROMPTS", "1");
+        std::env::set_var("WEBVIEW2_AUTO_GRANT_PERMISSIONS", "1");
+
+        let settings = crate::core::settings::get();
+        let mut browser_args = String::from("--enable-clipboard-api");
+        if settings.dangerous_disable_web_security {
+            warn!("!!! dangerous_disable_web_security is set: the webview runs WITHOUT the same-origin policy. Debugging only !!!");
+            browser_args.push_str(" --disable-web-security");
+        }
+        // Spellcheck dictionaries follow the accept languages, which are fixed per environment
+        if !settings.spellcheck.languages.is_empty() {
+            browser_args.push_str(&format!(" --accept-lang={}", settings.spellcheck.languages.join(",")));
+        }
+        browser_args.push_str(crate::rendering::browser_args());
+        browser_args
+    })
+}
+
+/// What a kind of webview is built with
+pub struct WebviewFactory {
+    scripts: Vec<Cow<'static, str>>,
+    protocol: ProtocolHandler,
+}
+
+impl WebviewFactory {
+    /// `miko://` served by [`crate::protocol::handle`], no init scripts
+    pub fn new() -> Self {
+        Self { scripts: Vec::new(), protocol: crate::protocol::handle }
+    }
+
+    pub fn with_init_script(mut self, script: impl Into<Cow<'static, str>>) -> Self {
+        self.scripts.push(script.into());
+        self
+    }
+
+    pub fn with_protocol(mut self, protocol: ProtocolHandler) -> Self {
+        self.protocol = protocol;
+        self
+    }
+
+    /// A builder sharing the app's WebView2 environment; the caller adds the
+    /// URL and IPC handler
+    pub fn builder(&self) -> WebViewBuilder<'static> {
+        let protocol = self.protocol;
+        let mut builder = WebViewBuilder::new()
+            .with_additional_browser_args(browser_args())
+            .with_asynchronous_custom_protocol(crate::protocol::SCHEME.into(), move |_webview, request, responder| {
+                protocol(request, responder);
+            });
+        for script in &self.scripts {
+            builder = builder.with_initialization_script(script.as_ref());
+        }
+        builder
+    }
+}
+
+impl Default for WebviewFactory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A hidden, loaded window and webview waiting to become a secondary window
+pub struct Standby {
+    // Dropped before the window it lives in
+    webview: wry::WebView,
+    window: Arc<Window>,
+}
+
+impl Standby {
+    /// Create `attributes`' window hidden, with a webview from `builder` on `about:blank`
+    pub fn warm(event_loop: &ActiveEventLoop, attributes: WindowAttributes, builder: WebViewBuilder) -> Result<Self, String> {
+        let window = Arc::new(event_loop.create_window(attributes.with_visible(false)).map_err(|e| e.to_string())?);
+        let webview = builder.with_url("about:blank").build(&*window).map_err(|e| e.to_string())?;
+        Ok(Self { webview, window })
+    }
+
+    /// Take the window and webview over; both are still hidden and blank
+    pub fn adopt(self) -> (Arc<Window>, wry::WebView) {
+        (self.window, self.webview)
+    }
+}
+
+/// Ask the event loop to warm a standby after [`STANDBY_DELAY`]
+pub fn schedule_standby() {
+    if !crate::core::settings::get().warm_standby_webview || crate::safe_mode::active() {
+        return;
+    }
+    if STANDBY_SCHEDULED.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    std::thread::spawn(|| {
+        std::thread::sleep(STANDBY_DELAY);
+        send_app_event(AppEvent::WarmStandby);
+    });
+}
+
+/// Called for [`AppEvent::WarmStandby`]: whether to warm one now. Not while
+/// a transfer is running; it's tried again later.
+pub fn standby_due() -> bool {
+    STANDBY_SCHEDULED.store(false, Ordering::SeqCst);
+    if !crate::core::shutdown::InFlight::current().is_empty() {
+        info!("App busy, warming the standby webview later");
+        schedule_standby();
+        return false;
+    }
+    true
+}
@@ -0,0 +1,223 @@
+//! Thread list warm-up: the first thing the page asks for once signed in is
+//! `GET /api/chats` and the messages of the thread it opens, so they are
+//! fetched as soon as the page reports the account, before it asks.
+//!
+//! The chat list and the messages of its first `prefetch.threads` threads go
+//! through the same forwarding as the page's own requests (so they show in
+//! the in-flight list and can be cancelled there), one at a time and within
+//! [`MAX_BYTES`] altogether. Each answer waits in a warm store and is handed
+//! to the first matching `GET` from the page, marked `x-prefetched: 1`; a
+//! request arriving while its prefetch is still running waits for it rather
+//! than fetching twice. Entries are served once, only to the account they
+//! were fetched for and only for [`FRESH_FOR`].
+//!
+//! Skipped on metered connections (Windows, unless `metered_transfers` is
+//! `ignore`) and while not online; stopped by signing out and
+//! `cancel_prefetch`. Counters are at `GET miko://app/admin/prefetch`
+//! (`get_prefetch_stats`).
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+use http::{HeaderValue, Response};
+use lazy_static::lazy_static;
+use serde_json::{json, Value};
+use tracing::{debug, info};
+
+use crate::core::state::{self, Connectivity};
+
+pub const ROUTE: &str = "/admin/prefetch";
+const CHATS_PATH: &str = "/api/chats";
+/// Budget for one warm-up, response bodies summed
+const MAX_BYTES: u64 = 4 * 1024 * 1024;
+/// A warmed answer older than this is fetched again
+const FRESH_FOR: Duration = Duration::from_secs(60);
+/// Longest a page request waits for its prefetch to finish
+const MAX_WAIT: Duration = Duration::from_secs(10);
+
+enum Entry {
+    Pending,
+    Ready(Instant, Response<Cow<'static, [u8]>>),
+}
+
+#[derive(Default)]
+struct Store {
+    /// Account the entries were fetched for
+    user_id: Option<String>,
+    /// Path and query -> answer
+    entries: HashMap<String, Entry>,
+}
+
+#[derive(Default)]
+struct Stats {
+    runs: AtomicU64,
+    skipped_metered: AtomicU64,
+    requests: AtomicU64,
+    bytes: AtomicU64,
+    hits: AtomicU64,
+    /// Warmed answers thrown away unused (expired, signed out, cancelled)
+    wasted: AtomicU64,
+}
+
+lazy_static! {
+    static ref STORE: Mutex<Store> = Mutex::new(Store::default());
+    static ref READY: Condvar = Condvar::new();
+}
+
+static STATS: Stats = Stats {
+    runs: AtomicU64::new(0),
+    skipped_metered: AtomicU64::new(0),
+    requests: AtomicU64::new(0),
+    bytes: AtomicU64::new(0),
+    hits: AtomicU64::new(0),
+    wasted: AtomicU64::new(0),
+};
+static CANCELLED: AtomicBool = AtomicBool::new(false);
+
+/// Warm the store for `user_id`, once per account and process
+pub fn signed_in(user_id: &str) {
+    let settings = crate::core::settings::get().prefetch;
+    if !settings.enabled {
+        return;
+    }
+    {
+        let mut store = STORE.lock().unwrap();
+        if store.user_id.as_deref() == Some(user_id) {
+            return;
+        }
+        discard(&mut store);
+        store.user_id = Some(user_id.to_string());
+    }
+    if state::runtime().connectivity != Connectivity::Online {
+        debug!("Skipping prefetch while not online");
+        return;
+    }
+    if crate::metered::restricted() {
+        info!("Skipping prefetch on a metered connection");
+        STATS.skipped_metered.fetch_add(1, Ordering::Relaxed);
+        return;
+    }
+
+    CANCELLED.store(false, Ordering::SeqCst);
+    STATS.runs.fetch_add(1, Ordering::Relaxed);
+    let user_id = user_id.to_string();
+    std::thread::spawn(move || run(&user_id, settings.threads));
+}
+
+/// `cancel_prefetch`: stop the warm-up and drop what it fetched; the
+/// account isn't warmed again in this process
+pub fn cancel() {
+    CANCELLED.store(true, Ordering::SeqCst);
+    discard(&mut STORE.lock().unwrap());
+    info!("Prefetch cancelled");
+}
+
+/// Like [`cancel`], but the next account to sign in is warmed up
+pub fn signed_out() {
+    CANCELLED.store(true, Ordering::SeqCst);
+    let mut store = STORE.lock().unwrap();
+    discard(&mut store);
+    store.user_id = None;
+}
+
+fn discard(store: &mut Store) {
+    let unused = store.entries.drain().filter(|(_, entry)| matches!(entry, Entry::Ready(..))).count();
+    STATS.wasted.fetch_add(unused as u64, Ordering::Relaxed);
+    READY.notify_all();
+}
+
+fn run(user_id: &str, threads: usize) {
+    let started = Instant::now();
+    let mut spent = 0;
+    let Some(chats) = fetch(user_id, CHATS_PATH, &mut spent) else { return };
+    let uuids: Vec<String> = serde_json::from_slice::<Value>(&chats)
+        .ok()
+        .and_then(|doc| doc["data"].as_array().cloned())
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|chat| chat["uuid"].as_str().map(|uuid| uuid.to_string()))
+        .take(threads)
+        .collect();
+    for uuid in uuids {
+        if fetch(user_id, &format!("{}/{}/messages", CHATS_PATH, uuid), &mut spent).is_none() {
+            break;
+        }
+    }
+    info!("Prefetched {} KiB in {:?}", spent / 1024, started.elapsed());
+}
+
+/// Fetch `path` into the store; `None` when the warm-up should stop. Returns the body.
+fn fetch(user_id: &str, path: &str, spent: &mut u64) -> Option<Vec<u8>> {
+    if CANCELLED.load(Ordering::SeqCst) || *spent >= MAX_BYTES {
+        return None;
+    }
+    {
+        let mut store = STORE.lock().unwrap();
+        if store.user_id.as_deref() != Some(user_id) || store.entries.contains_key(path) {
+            return None;
+        }
+        store.entries.insert(path.to_string(), Entry::Pending);
+    }
+
+    let response = crate::protocol::get(path);
+    let body = response.body().to_vec();
+    STATS.requests.fetch_add(1, Ordering::Relaxed);
+    STATS.bytes.fetch_add(body.len() as u64, Ordering::Relaxed);
+    *spent += body.len() as u64;
+
+    let mut store = STORE.lock().unwrap();
+    let keep = response.status().is_success() && !CANCELLED.load(Ordering::SeqCst) && store.user_id.as_deref() == Some(user_id);
+    if keep {
+        store.entries.insert(path.to_string(), Entry::Ready(Instant::now(), response));
+    } else {
+        store.entries.remove(path);
+    }
+    READY.notify_all();
+    keep.then_some(body)
+}
+
+/// The warmed answer to a page `GET` of `path`, if there is one; waits for
+/// a prefetch of it that is still running
+pub fn take(path: &str) -> Option<Response<Cow<'static, [u8]>>> {
+    let current = state::runtime().auth.user_id.clone();
+    let deadline = Instant::now() + MAX_WAIT;
+    let mut store = STORE.lock().unwrap();
+    if store.user_id.is_none() || store.user_id != current {
+        return None;
+    }
+    while let Some(Entry::Pending) = store.entries.get(path) {
+        let left = deadline.checked_duration_since(Instant::now())?;
+        store = READY.wait_timeout(store, left).unwrap().0;
+    }
+    match store.entries.remove(path)? {
+        Entry::Ready(at, response) if at.elapsed() < FRESH_FOR => {
+            STATS.hits.fetch_add(1, Ordering::Relaxed);
+            debug!("Serving {} from the prefetch", path);
+            let (mut parts, body) = response.into_parts();
+            parts.headers.insert("x-prefetched", HeaderValue::from_static("1"));
+            Some(Response::from_parts(parts, body))
+        }
+        Entry::Ready(..) => {
+            STATS.wasted.fetch_add(1, Ordering::Relaxed);
+            None
+        }
+        Entry::Pending => None,
+    }
+}
+
+/// `get_prefetch_stats` / `GET /admin/prefetch`
+pub fn stats() -> Value {
+    let warm = STORE.lock().unwrap().entries.values().filter(|entry| matches!(entry, Entry::Ready(..))).count();
+    json!({
+        "success": true,
+        "runs": STATS.runs.load(Ordering::Relaxed),
+        "skippedMetered": STATS.skipped_metered.load(Ordering::Relaxed),
+        "requests": STATS.requests.load(Ordering::Relaxed),
+        "bytes": STATS.bytes.load(Ordering::Relaxed),
+        "hits": STATS.hits.load(Ordering::Relaxed),
+        "wasted": STATS.wasted.load(Ordering::Relaxed),
+        "warm": warm,
+    })
+}
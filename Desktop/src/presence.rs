@@ -0,0 +1,146 @@
+//! Idle/away detection reported to the ERP presence API.
+//!
+//! The platform layer supplies how long the user has been idle; a background
+//! thread samples it and flips between `active` and `away` when the idle time
+//! crosses `presence.idle_threshold_secs`. Changes are sent as
+//! `PATCH /api/presence` and emitted to the page as `presence-changed`.
+//! A status set manually from the page wins until it is cleared, and nothing
+//! is reported while signed out. The status is also the local user's entry
+//! in the `presence` topic (see [`crate::thread_presence`]).
+
+use std::sync::Mutex;
+use std::time::Duration;
+use lazy_static::lazy_static;
+use serde::Serialize;
+use serde_json::json;
+use tracing::{debug, info, warn};
+
+use crate::core::state;
+
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(30);
+/// Sampled faster while away so returning input is noticed promptly
+const AWAY_SAMPLE_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Presence {
+    pub status: String,
+    /// Set by the user rather than derived from idle time
+    pub manual: bool,
+}
+
+#[derive(Default)]
+struct PresenceState {
+    manual: Option<String>,
+    idle: bool,
+    /// What the server was last told, so signing in again re-reports
+    reported: Option<Presence>,
+}
+
+lazy_static! {
+    static ref STATE: Mutex<PresenceState> = Mutex::new(PresenceState::default());
+    static ref STARTED: Mutex<bool> = Mutex::new(false);
+}
+
+/// Start the sampling thread (once); `idle_time` reports time since the last input
+pub fn start_monitor(idle_time: fn() -> Option<Duration>) {
+    {
+        let mut started = STARTED.lock().unwrap();
+        if *started {
+            return;
+        }
+        *started = true;
+    }
+
+    std::thread::spawn(move || {
+        let client = match reqwest::blocking::Client::builder().timeout(Duration::from_secs(10)).build() {
+            Ok(client) => client,
+            Err(e) => {
+                warn!("Presence reporting disabled: {}", e);
+                return;
+            }
+        };
+
+        loop {
+            let settings = crate::core::settings::get().presence;
+            let idle = settings.enabled
+                && idle_time().is_some_and(|idle| idle >= Duration::from_secs(settings.idle_threshold_secs));
+            let changed = std::mem::replace(&mut STATE.lock().unwrap().idle, idle) != idle;
+            if changed {
+                crate::core::sync::publish(crate::core::sync::TOPIC_PRESENCE);
+            }
+            if settings.enabled {
+                report(&client);
+            }
+            std::thread::sleep(if idle { AWAY_SAMPLE_INTERVAL } else { SAMPLE_INTERVAL });
+        }
+    });
+}
+
+/// Set (or with `None` clear) the status chosen by the user
+pub fn set_manual(status: Option<&str>) {
+    STATE.lock().unwrap().manual = status.map(|s| s.to_string());
+    crate::core::sync::publish(crate::core::sync::TOPIC_PRESENCE);
+    // The change shouldn't wait for the next sample
+    std::thread::spawn(|| {
+        if let Ok(client) = reqwest::blocking::Client::builder().timeout(Duration::from_secs(10)).build() {
+            report(&client);
+        }
+    });
+}
+
+/// Manual status meaning "don't notify me"
+pub const DO_NOT_DISTURB: &str = "dnd";
+
+/// The user set themselves to do-not-disturb; native notifications stay quiet
+pub fn is_do_not_disturb() -> bool {
+    STATE.lock().unwrap().manual.as_deref() == Some(DO_NOT_DISTURB)
+}
+
+/// Forget what was reported so the next sign-in starts fresh
+pub fn signed_out() {
+    STATE.lock().unwrap().reported = None;
+}
+
+/// The local user's status as shown in the app
+pub fn current() -> Presence {
+    let state = STATE.lock().unwrap();
+    match &state.manual {
+        Some(status) => Presence { status: status.clone(), manual: true },
+        None => Presence { status: if state.idle { "away" } else { "active" }.to_string(), manual: false },
+    }
+}
+
+fn report(client: &reqwest::blocking::Client) {
+    let auth = state::runtime().auth.clone();
+    if !auth.signed_in {
+        return;
+    }
+    let Some(base_url) = auth.api_base else {
+        debug!("Presence not reported: no API base from the page");
+        return;
+    };
+
+    let presence = current();
+    if STATE.lock().unwrap().reported.as_ref() == Some(&presence) {
+        return;
+    }
+
+    let mut builder = client
+        .patch(format!("{}/api/presence", base_url.trim_end_matches('/')))
+        .json(&json!({ "status": presence.status, "manual": presence.manual }));
+    if let Some(token) = &auth.token {
+        builder = builder.bearer_auth(token);
+    }
+    match builder.send().and_then(|response| response.error_for_status()) {
+        Ok(_) => {
+            info!("Presence reported: {}{}", presence.status, if presence.manual { " (manual)" } else { "" });
+            if let Some(webview) = crate::ipc::handle() {
+                webview.emit_latest("presence-changed", "", &presence);
+            }
+            STATE.lock().unwrap().reported = Some(presence);
+        }
+        // Retried on the next sample since `reported` is unchanged
+        Err(e) => warn!("Failed to report presence: {}", e),
+    }
+}
@@ -0,0 +1,114 @@
+//! Quick look at a downloaded attachment, requested by `preview_file {path}`.
+//!
+//! Only files the app downloaded can be previewed. On Windows images, PDFs
+//! and text open in a small preview window whose webview loads the file from
+//! `miko://downloads/<token>`; a token is handed out per previewed file, so
+//! the route can't be used to read anything else from disk. Other types open
+//! in their associated app. macOS hands every file to Quick Look.
+
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use http::{Response, StatusCode};
+use lazy_static::lazy_static;
+use tracing::warn;
+
+/// Host of the `miko://` route serving previewed files
+pub const HOST: &str = "downloads";
+/// Tokens kept; older previews stop resolving
+const MAX_TOKENS: usize = 16;
+/// Larger files open in their associated app instead
+const MAX_PREVIEW_BYTES: u64 = 64 * 1024 * 1024;
+
+/// Installed in the preview webview: Escape closes the window
+pub const INIT_SCRIPT: &str = r#"
+window.addEventListener('keydown', (e) => {
+    if (e.key === 'Escape' && window.ipc) window.ipc.postMessage(JSON.stringify({ type: 'close_preview' }));
+});
+"#;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PreviewKind {
+    Image,
+    Pdf,
+    Text,
+}
+
+lazy_static! {
+    static ref TOKENS: Mutex<VecDeque<(String, PathBuf)>> = Mutex::new(VecDeque::new());
+}
+
+/// Resolve `preview_file`'s path to a downloaded file
+pub fn resolve(message: &serde_json::Value) -> Result<PathBuf, String> {
+    let path = message["path"].as_str().ok_or("preview_file needs a path")?;
+    crate::core::downloads::downloaded_file(path)
+}
+
+fn extension(path: &Path) -> String {
+    path.extension().map(|e| e.to_string_lossy().to_lowercase()).unwrap_or_default()
+}
+
+/// What the preview window can show; `None` opens the file in its app
+pub fn kind(path: &Path) -> Option<PreviewKind> {
+    if std::fs::metadata(path).map_or(true, |m| m.len() > MAX_PREVIEW_BYTES) {
+        return None;
+    }
+    match extension(path).as_str() {
+        "png" | "jpg" | "jpeg" | "gif" | "webp" | "bmp" | "svg" | "ico" => Some(PreviewKind::Image),
+        "pdf" => Some(PreviewKind::Pdf),
+        "txt" | "log" | "csv" | "json" | "md" | "xml" => Some(PreviewKind::Text),
+        _ => None,
+    }
+}
+
+fn content_type(path: &Path) -> &'static str {
+    match extension(path).as_str() {
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "bmp" => "image/bmp",
+        "svg" => "image/svg+xml",
+        "ico" => "image/x-icon",
+        "pdf" => "application/pdf",
+        // Shown as plain text rather than rendered or downloaded
+        _ => "text/plain; charset=utf-8",
+    }
+}
+
+/// URL the preview webview loads `path` from
+pub fn register(path: &Path) -> String {
+    let token = uuid::Uuid::new_v4().simple().to_string();
+    let mut tokens = TOKENS.lock().unwrap();
+    tokens.push_back((token.clone(), path.to_path_buf()));
+    while tokens.len() > MAX_TOKENS {
+        tokens.pop_front();
+    }
+    format!("{}://{}/{}", crate::protocol::SCHEME, HOST, token)
+}
+
+/// Answer `miko://downloads/<token>`
+pub fn serve(uri_path: &str) -> Response<Cow<'static, [u8]>> {
+    let token = uri_path.trim_start_matches('/');
+    let path = TOKENS.lock().unwrap().iter().find(|(t, _)| t == token).map(|(_, p)| p.clone());
+    let Some(path) = path else {
+        return status(StatusCode::NOT_FOUND);
+    };
+
+    match std::fs::read(&path) {
+        Ok(bytes) => Response::builder()
+            .header("Content-Type", content_type(&path))
+            .header("Cache-Control", "no-store")
+            .body(Cow::Owned(bytes))
+            .unwrap(),
+        Err(e) => {
+            warn!("Failed to read {} for preview: {}", path.display(), e);
+            status(StatusCode::NOT_FOUND)
+        }
+    }
+}
+
+fn status(code: StatusCode) -> Response<Cow<'static, [u8]>> {
+    Response::builder().status(code).body(Cow::Borrowed(&[][..])).unwrap()
+}
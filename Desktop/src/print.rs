@@ -0,0 +1,67 @@
+//! Printing the page shown in the webview, e.g. a conversation transcript.
+//!
+//! `window.print()` is unreliable in WebView2 (depending on the runtime it
+//! does nothing or opens a broken dialog), so printing goes through the
+//! engines' native APIs. `print_page` and File > Print… open the print
+//! dialog; `print_to_pdf` writes a PDF to `savePath` or to a file picked in the
+//! save dialog and answers with `print-finished`. Both need the webview, so the
+//! platform layers run them on the event loop.
+
+use std::path::PathBuf;
+use serde_json::Value;
+use tracing::{info, warn};
+
+/// What an `AppEvent::Print` should do
+#[derive(Debug)]
+pub enum PrintJob {
+    /// Open the print dialog
+    Dialog,
+    /// Write the page as a PDF to `path`
+    Pdf { path: PathBuf, request_id: Option<String> },
+}
+
+impl PrintJob {
+    /// Give up on the job, answering the page if it asked for a PDF
+    pub fn failed(self, error: &str) {
+        match self {
+            PrintJob::Dialog => warn!("Printing failed: {}", error),
+            PrintJob::Pdf { path, request_id } => pdf_finished(request_id.as_deref(), &path, Err(error.to_string())),
+        }
+    }
+}
+
+/// `savePath` of a `print_to_pdf` message; `Ok(None)` means ask the user
+pub fn save_path(message: &Value) -> Result<Option<PathBuf>, String> {
+    let path = message["savePath"].as_str().filter(|s| !s.is_empty()).map(PathBuf::from);
+    match path.map(|path| crate::core::checked_path(&path)).transpose()? {
+        Some(path) if !path.parent().is_some_and(|dir| dir.is_dir()) => {
+            Err(format!("folder does not exist: {}", path.display()))
+        }
+        path => Ok(path),
+    }
+}
+
+pub fn default_pdf_name() -> String {
+    format!("workspace-{}.pdf", chrono::Local::now().format("%Y%m%d-%H%M%S"))
+}
+
+/// Answer a `print_to_pdf` request
+pub fn pdf_finished(request_id: Option<&str>, path: &std::path::Path, result: Result<(), String>) {
+    let path = path.to_string_lossy();
+    let value = match result {
+        Ok(()) => {
+            info!("Printed page to {}", path);
+            serde_json::json!({ "success": true, "path": path })
+        }
+        Err(e) => {
+            warn!("Printing to {} failed: {}", path, e);
+            serde_json::json!({ "success": false, "path": path, "error": e })
+        }
+    };
+    crate::ipc::respond(request_id, "print-finished", &value);
+}
+
+/// Answer a `print_to_pdf` request that never got to printing
+pub fn pdf_rejected(request_id: Option<&str>, result: Value) {
+    crate::ipc::respond(request_id, "print-finished", &result);
+}
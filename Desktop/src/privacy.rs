@@ -0,0 +1,37 @@
+//! Keeping app windows out of screenshots, recordings and screen shares
+//! (`privacy.block_capture`).
+//!
+//! The platform layers exclude every window they create while the setting is
+//! on (`SetWindowDisplayAffinity` on Windows, `NSWindow.sharingType` on
+//! macOS). A change marks the open windows for an update on the event loop and
+//! is published on the `privacy` state topic, so the page can show that
+//! capture is blocked.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use tracing::info;
+
+use crate::core::settings;
+use crate::core::sync;
+
+/// Set when the setting changes; the event loop takes it to update the windows
+static WINDOWS_DIRTY: AtomicBool = AtomicBool::new(false);
+
+pub fn block_capture() -> bool {
+    settings::get().privacy.block_capture
+}
+
+/// Handle `set_block_capture` and the Tools menu toggle
+pub fn set_block_capture(enabled: bool) -> Result<bool, String> {
+    settings::update(|s| s.privacy.block_capture = enabled)
+        .map_err(|e| format!("failed to save privacy settings: {}", e))?;
+    info!("Screen capture of app windows {}", if enabled { "blocked" } else { "allowed" });
+    WINDOWS_DIRTY.store(true, Ordering::SeqCst);
+    crate::ipc::wake();
+    sync::publish(sync::TOPIC_PRIVACY);
+    Ok(enabled)
+}
+
+/// Returns the setting if it changed since the last call
+pub fn take_window_update() -> Option<bool> {
+    WINDOWS_DIRTY.swap(false, Ordering::SeqCst).then(block_capture)
+}
@@ -0,0 +1,1234 @@
+//! The `miko://` custom protocol the release build loads the app from.
+//!
+//! Besides the bundled `index.html`, the emoji data (see [`crate::emoji`]) and
+//! previewed downloads under `miko://downloads/` (see [`crate::preview`]), it
+//! forwards every other path (`/api/*`, uploaded files, profile pictures) to
+//! the chat server, so the page's requests are same-origin and work with
+//! default browser security instead of needing `--disable-web-security`. Forwarding runs on a worker thread; the
+//! webview is answered through the async responder.
+//!
+//! Session headers are attached here rather than by page script, so fetch,
+//! XHR, EventSource and worker requests all carry them and page code can't
+//! clobber them. Responses are buffered, so an EventSource only sees events
+//! once the server ends the stream.
+//!
+//! Failures the page would otherwise see as ad-hoc text (a transport error,
+//! a reverse proxy's plain 502, raw ERP error pages) are answered with one
+//! JSON envelope, `{"success": false, "error": {"code", "message",
+//! "upstream_status"?, "request_id"}}`, with `code` one of [`ErrorCode`]. JSON
+//! error bodies from the server pass through untouched. The ERP's maintenance
+//! page is answered with the `erp_maintenance` code and `until` when known
+//! (see [`crate::erp_maintenance`]).
+//!
+//! The forwarding client keeps a pool of connections to the API alive (see
+//! `forwarding` in the settings) and speaks HTTP/2 where the server offers it,
+//! so a page load doesn't open a fresh connection per request. The protocol
+//! each answer came over is logged with it.
+//!
+//! Forwarded requests are listed while in flight and can be cancelled (see
+//! [`crate::inflight`]): each one runs as a future on the forwarding runtime
+//! and a cancel drops it wherever it is, closing the connection. A cancelled
+//! request is answered with status 499.
+//!
+//! Sending a message (`POST /api/chats/{uuid}/messages`) is retried up to
+//! [`SEND_ATTEMPTS`] times with jittered backoff when the server can't be
+//! reached or a gateway answers 502/503/504, never on other statuses. Every
+//! attempt carries the same `Idempotency-Key` (the page's, or one generated
+//! here and returned) so a server that honours it stores the message once.
+//! The answer, JSON body and headers, says how many `attempts` it took.
+//!
+//! A `GET` that [`crate::prefetch`] already made is answered from its store.
+//! The thread list and thread messages are kept for offline reading (see
+//! [`crate::core::offline_cache`]); while the ERP is unreachable they are
+//! answered from there and other writes are refused with the `offline` code.
+//! With `forwarding.record_requests` set, what the page had forwarded is kept
+//! for inspection and replay (see [`crate::recordings`]). A 401 on a session
+//! that worked a moment ago is looked into by [`crate::session_takeover`].
+//!
+//! How long a request may take is `forwarding.timeout_secs`, or the
+//! `forwarding.route_timeouts` entry with the longest prefix of its path
+//! (see [`timeout_for`]). One that runs over is answered with the `timeout`
+//! code and `elapsed_ms`/`timeout_ms` in the envelope.
+//!
+//! Answers that came from the API say how long that took in a
+//! `Server-Timing: miko-upstream;dur=<ms>` header, and carry when it went
+//! out and came back as an [`crate::ipc::Upstream`] extension for the IPC
+//! handlers that answer with it.
+//!
+//! Sign-ins (`POST /api/login`) are limited by [`crate::login_guard`];
+//! locked out ones are answered 429 with the `rate_limited` code.
+
+use std::borrow::Cow;
+use std::time::{Duration, Instant};
+use rand::Rng;
+use http::header::{HeaderMap, HeaderValue, AUTHORIZATION, CONTENT_TYPE};
+use http::{Method, Request, Response, StatusCode};
+use futures_util::future::Abortable;
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info, warn};
+use wry::RequestAsyncResponder;
+
+pub const SCHEME: &str = "miko";
+
+/// API base baked in by the frontend build; `api_base_url` in the settings overrides it
+const DEFAULT_API_BASE: &str = match option_env!("VITE_API_URL") {
+    Some(url) => url,
+    None => "http://localhost:5669",
+};
+
+/// Identifies this app instance to the server; fixed for the process lifetime
+const SESSION_HEADER: &str = "x-session-id";
+/// This build's version, for the server to refuse clients it no longer supports
+const CLIENT_VERSION_HEADER: &str = "x-miko-client-version";
+/// Correlates one forwarded request across the page, our log and the server's;
+/// generated unless the page sent one
+const REQUEST_ID_HEADER: &str = "x-request-id";
+/// Longest upstream error text carried in an envelope's `message`
+const MAX_ERROR_MESSAGE: usize = 500;
+/// Typing and read signals; never logged per request and dropped while not
+/// online (see [`crate::ephemeral`] for the batched native path)
+const EPHEMERAL_PREFIX: &str = "/api/ephemeral/";
+/// Lets the server recognize a retried message send; passed through to it
+const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+/// How many tries a message send took
+const ATTEMPTS_HEADER: &str = "x-attempts";
+/// Added to every answer that reached the API; devtools shows it in a request's Timing tab
+const SERVER_TIMING: &str = "server-timing";
+/// Metric in [`SERVER_TIMING`]: time from sending the request to having the whole answer
+const UPSTREAM_TIMING: &str = "miko-upstream";
+const SEND_ATTEMPTS: u32 = 3;
+/// Wait before the second try; doubled for each further one
+const SEND_RETRY_DELAY: Duration = Duration::from_millis(500);
+/// Gateway answers meaning the server never handled the request
+const RETRY_STATUSES: &[StatusCode] = &[StatusCode::BAD_GATEWAY, StatusCode::SERVICE_UNAVAILABLE, StatusCode::GATEWAY_TIMEOUT];
+
+/// Exercises fetch, XHR, EventSource and a worker against `/__test/echo`
+#[cfg(debug_assertions)]
+const REQUEST_TEST_PAGE: &str = include_str!("../../Test/request-headers.html");
+
+/// Hop-by-hop and framing headers that must not be copied between the two legs
+const SKIPPED_HEADERS: &[&str] = &["host", "connection", "content-length", "transfer-encoding", "keep-alive", "upgrade"];
+
+/// `error.code` of the failure envelope
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// The server could not be reached or the connection broke
+    Network,
+    Timeout,
+    /// The server rejected the credentials (401/403)
+    Unauthorized,
+    /// The server or a gateway in front of it failed (5xx)
+    ErpError,
+    /// The server rejected the request itself (other 4xx), or it could not be built
+    BadRequest,
+    /// Something went wrong in the app
+    Internal,
+    /// Stopped from the in-flight list
+    Cancelled,
+    /// Not sent: the ERP is unreachable and the request would change something
+    Offline,
+    /// Not sent: too many rejected sign-ins (see [`crate::login_guard`])
+    RateLimited,
+    /// The ERP is down for scheduled maintenance (see [`crate::erp_maintenance`])
+    ErpMaintenance,
+}
+
+impl ErrorCode {
+    fn from_reqwest(error: &reqwest::Error) -> Self {
+        if error.is_timeout() {
+            Self::Timeout
+        } else if error.is_builder() {
+            Self::BadRequest
+        } else if error.is_connect() || error.is_request() || error.is_body() {
+            Self::Network
+        } else {
+            Self::Internal
+        }
+    }
+
+    fn from_status(status: StatusCode) -> Self {
+        match status {
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => Self::Unauthorized,
+            status if status.is_client_error() => Self::BadRequest,
+            _ => Self::ErpError,
+        }
+    }
+
+    /// Status for envelopes that have no upstream error status to keep
+    fn status(self) -> StatusCode {
+        match self {
+            Self::Network | Self::ErpError => StatusCode::BAD_GATEWAY,
+            Self::Timeout => StatusCode::GATEWAY_TIMEOUT,
+            Self::Unauthorized => StatusCode::UNAUTHORIZED,
+            Self::BadRequest => StatusCode::BAD_REQUEST,
+            Self::Internal => StatusCode::SERVICE_UNAVAILABLE,
+            // "Client closed request", as nginx reports it
+            Self::Cancelled => StatusCode::from_u16(499).unwrap(),
+            Self::Offline => StatusCode::SERVICE_UNAVAILABLE,
+            Self::RateLimited => StatusCode::TOO_MANY_REQUESTS,
+            Self::ErpMaintenance => StatusCode::SERVICE_UNAVAILABLE,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct ErrorEnvelope<'a> {
+    success: bool,
+    error: ErrorBody<'a>,
+}
+
+#[derive(Debug, Serialize)]
+struct ErrorBody<'a> {
+    code: ErrorCode,
+    message: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    upstream_status: Option<u16>,
+    request_id: &'a str,
+    /// How long a timed out request ran, and its limit
+    #[serde(skip_serializing_if = "Option::is_none")]
+    elapsed_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    timeout_ms: Option<u64>,
+    /// Seconds until a rate-limited request may be sent again
+    #[serde(skip_serializing_if = "Option::is_none")]
+    retry_after: Option<u64>,
+    /// When the ERP's maintenance is advertised to end
+    #[serde(skip_serializing_if = "Option::is_none")]
+    until: Option<String>,
+}
+
+lazy_static! {
+    static ref SESSION_ID: String = uuid::Uuid::new_v4().to_string();
+    static ref CLIENT: Option<reqwest::Client> = build_client()
+        .map_err(|e| warn!("API forwarding disabled: {}", e))
+        .ok();
+    /// Drives forwarded requests; `relay` blocks on it until its request is
+    /// answered or cancelled
+    static ref RUNTIME: Option<tokio::runtime::Runtime> = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(2)
+        .thread_name("api-forwarding")
+        .enable_all()
+        .build()
+        .map_err(|e| warn!("API forwarding disabled: {}", e))
+        .ok();
+}
+
+fn build_client() -> reqwest::Result<reqwest::Client> {
+    let settings = crate::core::settings::get().forwarding;
+    let mut builder = reqwest::Client::builder()
+        .connect_timeout(Duration::from_secs(10))
+        .redirect(reqwest::redirect::Policy::none())
+        .pool_max_idle_per_host(settings.pool_max_idle_per_host)
+        .pool_idle_timeout(Duration::from_secs(settings.pool_idle_timeout_secs))
+        .tcp_keepalive(Duration::from_secs(settings.tcp_keepalive_secs))
+        .http2_adaptive_window(true);
+    if settings.http2_prior_knowledge {
+        builder = builder.http2_prior_knowledge();
+    }
+    builder.build()
+}
+
+/// How long a request to `path` may take: the route override with the
+/// longest prefix of `path` (whole segments only), or the default
+pub fn timeout_for(path: &str) -> Duration {
+    route_timeout(&crate::core::settings::get().forwarding, path)
+}
+
+fn route_timeout(settings: &crate::core::settings::ForwardingSettings, path: &str) -> Duration {
+    let secs = settings
+        .route_timeouts
+        .iter()
+        .filter(|(prefix, _)| {
+            let prefix = prefix.trim_end_matches('/');
+            path.strip_prefix(prefix).is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+        })
+        .max_by_key(|(prefix, _)| prefix.trim_end_matches('/').len())
+        .map_or(settings.timeout_secs, |(_, secs)| *secs);
+    Duration::from_secs(secs.max(1))
+}
+
+/// The timeouts in effect, for the diagnostics panel
+pub fn timeout_table() -> serde_json::Value {
+    let settings = crate::core::settings::get().forwarding;
+    let routes: Vec<serde_json::Value> = settings
+        .route_timeouts
+        .iter()
+        .map(|(prefix, secs)| serde_json::json!({ "prefix": prefix, "secs": secs }))
+        .collect();
+    serde_json::json!({ "defaultSecs": settings.timeout_secs, "routes": routes })
+}
+
+pub fn api_base() -> String {
+    crate::core::settings::get()
+        .api_base_url
+        .unwrap_or_else(|| DEFAULT_API_BASE.to_string())
+}
+
+/// Handler for `with_asynchronous_custom_protocol`
+pub fn handle(request: Request<Vec<u8>>, responder: RequestAsyncResponder) {
+    let path = request.uri().path();
+    if request.uri().host() == Some(crate::preview::HOST) {
+        // Previewed files only; never forwarded
+        responder.respond(crate::preview::serve(path));
+    } else if request.uri().host() == Some(crate::temp_media::HOST) {
+        // Staged images only; never forwarded
+        responder.respond(crate::temp_media::serve(path));
+    } else if path == crate::emoji::ROUTE {
+        responder.respond(crate::emoji::serve(&request));
+    } else if path == "/" || path == "/index.html" {
+        // Revalidated by ETag; in safe mode nothing cached by an earlier run is reused
+        let cache_control = if crate::safe_mode::active() { "no-store" } else { "no-cache" };
+        responder.respond(crate::assets::serve(&request, "index.html", "text/html", cache_control));
+    } else if path == crate::prefetch::ROUTE && request.method() == Method::GET {
+        responder.respond(json_response(&crate::prefetch::stats()));
+    } else if path == crate::recordings::HEALTH_ROUTE && request.method() == Method::GET {
+        responder.respond(json_response(&crate::recordings::health()));
+    } else if path == crate::recordings::ROUTE && request.method() == Method::GET {
+        responder.respond(json_response(&crate::recordings::list()));
+    } else if let Some(id) = crate::recordings::replay_route(path).filter(|_| request.method() == Method::POST) {
+        std::thread::spawn(move || responder.respond(json_response(&crate::recordings::replay(id))));
+    } else if path == crate::inflight::ROUTE && request.method() == Method::GET {
+        responder.respond(json_response(&crate::inflight::list()));
+    } else if let Some(id) = crate::inflight::cancel_route(path).filter(|_| request.method() == Method::POST) {
+        responder.respond(json_response(&crate::inflight::cancel(id)));
+    } else if let Some(response) = test_route(&request) {
+        responder.respond(response);
+    } else {
+        std::thread::spawn(move || responder.respond(forward(request)));
+    }
+}
+
+pub fn session_id() -> &'static str {
+    &SESSION_ID
+}
+
+/// Headers sent upstream: the page's minus hop-by-hop ones, plus the session
+/// and client version headers (always ours) and the signed-in user's token
+/// unless the page sent one
+fn outgoing_headers(incoming: &HeaderMap) -> HeaderMap {
+    let mut headers: HeaderMap = incoming
+        .iter()
+        .filter(|(name, _)| !SKIPPED_HEADERS.contains(&name.as_str()))
+        .map(|(name, value)| (name.clone(), value.clone()))
+        .collect();
+    headers.insert(SESSION_HEADER, HeaderValue::from_static(session_id()));
+    headers.insert(CLIENT_VERSION_HEADER, HeaderValue::from_static(env!("CARGO_PKG_VERSION")));
+    if !headers.contains_key(AUTHORIZATION) {
+        let token = crate::core::state::runtime().auth.token.clone();
+        if let Some(value) = token.and_then(|t| HeaderValue::from_str(&format!("Bearer {}", t)).ok()) {
+            headers.insert(AUTHORIZATION, value);
+        }
+    }
+    headers
+}
+
+/// Debug builds serve a page checking that every request type gets the
+/// native headers: `miko://app/__test/requests`
+#[cfg(debug_assertions)]
+fn test_route(request: &Request<Vec<u8>>) -> Option<Response<Cow<'static, [u8]>>> {
+    match request.uri().path() {
+        "/__test/requests" => Some(
+            Response::builder()
+                .header("Content-Type", "text/html")
+                .body(Cow::Borrowed(REQUEST_TEST_PAGE.as_bytes()))
+                .unwrap(),
+        ),
+        "/__test/echo" => {
+            let headers = outgoing_headers(request.headers());
+            let echo = serde_json::json!({
+                "sessionId": headers.get(SESSION_HEADER).and_then(|v| v.to_str().ok()),
+                "authorized": headers.contains_key(AUTHORIZATION),
+            });
+            let sse = request.uri().query().is_some_and(|q| q.contains("sse"));
+            let (content_type, body) = if sse {
+                ("text/event-stream", format!("data: {}\n\n", echo))
+            } else {
+                ("application/json", echo.to_string())
+            };
+            Some(Response::builder().header("Content-Type", content_type).body(Cow::Owned(body.into_bytes())).unwrap())
+        }
+        _ => None,
+    }
+}
+
+#[cfg(not(debug_assertions))]
+fn test_route(_request: &Request<Vec<u8>>) -> Option<Response<Cow<'static, [u8]>>> {
+    None
+}
+
+/// Resolve a server-relative URL from the page (e.g. `/api/files/d/...`) for
+/// native code that talks to the server directly
+pub fn absolute_url(url: &str) -> String {
+    if url.starts_with('/') {
+        format!("{}{}", api_base().trim_end_matches('/'), url)
+    } else {
+        url.to_string()
+    }
+}
+
+/// `POST /api/chats/{uuid}/messages`
+fn is_message_send(method: &Method, path: &str) -> bool {
+    let segments: Vec<&str> = path.trim_end_matches('/').split('/').collect();
+    *method == Method::POST && matches!(segments.as_slice(), ["", "api", "chats", chat, "messages"] if !chat.is_empty())
+}
+
+/// Wait before try `attempt + 1`, with up to half of it added or taken off
+fn send_retry_delay(attempt: u32) -> Duration {
+    SEND_RETRY_DELAY.saturating_mul(1 << (attempt - 1).min(8)).mul_f64(rand::thread_rng().gen_range(0.5..1.5))
+}
+
+/// Forward a `GET` the app makes on its own, as if the page had sent it
+pub fn get(path_and_query: &str) -> Response<Cow<'static, [u8]>> {
+    match Request::get(format!("{}://app{}", SCHEME, path_and_query)).body(Vec::new()) {
+        Ok(request) => relay(request, 1).0,
+        Err(e) => error_response(ErrorCode::BadRequest, None, &e.to_string(), ""),
+    }
+}
+
+/// Forward a request with a JSON body the app makes on its own
+pub fn send_json(method: Method, path_and_query: &str, body: &serde_json::Value) -> Response<Cow<'static, [u8]>> {
+    let request = Request::builder()
+        .method(method)
+        .uri(format!("{}://app{}", SCHEME, path_and_query))
+        .header(CONTENT_TYPE, "application/json")
+        .body(serde_json::to_vec(body).unwrap_or_default());
+    match request {
+        Ok(request) => relay(request, 1).0,
+        Err(e) => error_response(ErrorCode::BadRequest, None, &e.to_string(), ""),
+    }
+}
+
+/// Send a recorded request again (see [`crate::recordings`])
+pub fn replay(request: Request<Vec<u8>>) -> Response<Cow<'static, [u8]>> {
+    relay(request, 1).0
+}
+
+fn forward(request: Request<Vec<u8>>) -> Response<Cow<'static, [u8]>> {
+    let capture = crate::recordings::Capture::start(&request);
+    let response = forward_page_request(request);
+    if let Some(capture) = capture {
+        capture.finish(&response);
+    }
+    response
+}
+
+fn forward_page_request(request: Request<Vec<u8>>) -> Response<Cow<'static, [u8]>> {
+    use crate::core::offline_cache;
+    use crate::core::state::{self, Connectivity};
+
+    let connectivity = state::runtime().connectivity;
+    let online = connectivity == Connectivity::Online;
+    let offline_path = (request.method() == Method::GET && request.uri().query().is_none() && offline_cache::cacheable(request.uri().path()))
+        .then(|| request.uri().path().to_string());
+    if !online {
+        if let Some(response) = offline_path.as_deref().and_then(offline_response) {
+            return response;
+        }
+        if !matches!(*request.method(), Method::GET | Method::HEAD | Method::OPTIONS) {
+            let request_id = request_id_of(request.headers());
+            info!("Refusing {} {} while offline [{}]", request.method(), crate::logging::redact_url(request.uri().path()), request_id);
+            if connectivity == Connectivity::ErpMaintenance {
+                return maintenance_response(crate::erp_maintenance::until(), None, &request_id);
+            }
+            return error_response(ErrorCode::Offline, None, "not sent: the server is unreachable", &request_id);
+        }
+    }
+
+    let response = send_page_request(request);
+    if let Some(path) = offline_path {
+        if response.status().is_success() {
+            offline_cache::store(&path, response.body());
+        } else if is_unreachable(&response) {
+            if let Some(cached) = offline_response(&path) {
+                return cached;
+            }
+        }
+    }
+    response
+}
+
+/// The kept answer to `path`, marked as such
+fn offline_response(path: &str) -> Option<Response<Cow<'static, [u8]>>> {
+    let (body, fetched_at) = crate::core::offline_cache::load(path)?;
+    Some(
+        Response::builder()
+            .header(CONTENT_TYPE, "application/json")
+            .header("x-miko-offline", "true")
+            .header("x-miko-cached-at", fetched_at.to_rfc3339())
+            .body(Cow::Owned(body))
+            .unwrap(),
+    )
+}
+
+/// The failure envelope for a server that couldn't be reached
+fn is_unreachable(response: &Response<Cow<'static, [u8]>>) -> bool {
+    if !matches!(response.status(), StatusCode::BAD_GATEWAY | StatusCode::GATEWAY_TIMEOUT) {
+        return false;
+    }
+    serde_json::from_slice::<serde_json::Value>(response.body())
+        .is_ok_and(|body| matches!(body["error"]["code"].as_str(), Some("network" | "timeout")))
+}
+
+/// The page's `x-request-id`, or a new one
+fn request_id_of(headers: &HeaderMap) -> String {
+    headers
+        .get(REQUEST_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .map(|id| id.to_string())
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string())
+}
+
+fn send_page_request(mut request: Request<Vec<u8>>) -> Response<Cow<'static, [u8]>> {
+    if request.method() == Method::GET {
+        let path_and_query = request.uri().path_and_query().map(|p| p.as_str()).unwrap_or("/");
+        if let Some(response) = crate::prefetch::take(path_and_query) {
+            return response;
+        }
+    }
+    if request.method() == Method::POST && request.uri().path() == crate::login_guard::LOGIN_PATH {
+        return forward_login(request);
+    }
+    if !is_message_send(request.method(), request.uri().path()) {
+        return relay(request, 1).0;
+    }
+    let key = match request.headers().get(IDEMPOTENCY_KEY_HEADER) {
+        Some(key) => key.clone(),
+        None => {
+            let key = HeaderValue::from_str(&uuid::Uuid::new_v4().to_string()).unwrap();
+            request.headers_mut().insert(IDEMPOTENCY_KEY_HEADER, key.clone());
+            key
+        }
+    };
+    let (response, attempts) = relay(request, SEND_ATTEMPTS);
+    with_attempts(response, attempts, key)
+}
+
+/// Send a sign-in unless it is locked out; the answer is held back after
+/// repeated failures and says how many tries are left
+fn forward_login(request: Request<Vec<u8>>) -> Response<Cow<'static, [u8]>> {
+    use crate::login_guard;
+
+    let account = login_guard::account_key(request.body());
+    if let Some(retry_after) = login_guard::locked(&account) {
+        let request_id = request_id_of(request.headers());
+        info!("Refusing sign-in for another {}s after repeated failures [{}]", retry_after, request_id);
+        return rate_limited_response(retry_after, &request_id);
+    }
+    let response = relay(request, 1).0;
+    let outcome = login_guard::record(&account, response.status());
+    std::thread::sleep(outcome.delay);
+    let (parts, body) = response.into_parts();
+    let body = login_guard::annotate(&body, outcome.attempts_remaining).map_or(body, Cow::Owned);
+    Response::from_parts(parts, body)
+}
+
+/// Record `attempts` in the answer to a message send: as a header, and in
+/// the body when that is a JSON object
+fn with_attempts(response: Response<Cow<'static, [u8]>>, attempts: u32, key: HeaderValue) -> Response<Cow<'static, [u8]>> {
+    let (mut parts, body) = response.into_parts();
+    parts.headers.insert(ATTEMPTS_HEADER, HeaderValue::from(attempts));
+    parts.headers.insert(IDEMPOTENCY_KEY_HEADER, key);
+    let is_json = parts.headers.get(CONTENT_TYPE).and_then(|v| v.to_str().ok()).is_some_and(|v| v.contains("json"));
+    let body = match serde_json::from_slice::<serde_json::Value>(&body) {
+        Ok(serde_json::Value::Object(mut object)) if is_json => {
+            object.insert("attempts".to_string(), attempts.into());
+            Cow::Owned(serde_json::to_vec(&object).unwrap_or_default())
+        }
+        _ => body,
+    };
+    Response::from_parts(parts, body)
+}
+
+/// Forward `request`, trying up to `max_attempts` times while the server
+/// can't be reached or a gateway answers one of [`RETRY_STATUSES`]. Returns
+/// the answer and how many tries it took.
+fn relay(request: Request<Vec<u8>>, max_attempts: u32) -> (Response<Cow<'static, [u8]>>, u32) {
+    let request_id = request_id_of(request.headers());
+    let (Some(client), Some(runtime)) = (CLIENT.as_ref(), RUNTIME.as_ref()) else {
+        return (error_response(ErrorCode::Internal, None, "API forwarding unavailable", &request_id), 0);
+    };
+
+    let ephemeral = request.uri().path().starts_with(EPHEMERAL_PREFIX);
+    if ephemeral && crate::core::state::runtime().connectivity != crate::core::state::Connectivity::Online {
+        return (error_response(ErrorCode::Network, None, "dropped while offline", &request_id), 0);
+    }
+
+    let path_and_query = request.uri().path_and_query().map(|p| p.as_str()).unwrap_or("/");
+    let url = format!("{}{}", api_base().trim_end_matches('/'), path_and_query);
+    if !ephemeral {
+        debug!("Forwarding {} {} [{}]", request.method(), crate::logging::redact_url(&url), request_id);
+    }
+
+    let (tracked, registration) = crate::inflight::track(request.method().as_str(), path_and_query, &request_id);
+    let mut attempts = 0;
+    let forwarding = exchange(client, request, &url, &request_id, &tracked, max_attempts, &mut attempts);
+    // Aborting drops the future mid-send or mid-body, and the connection with it
+    let response = match runtime.block_on(Abortable::new(forwarding, registration)) {
+        Ok(response) => response,
+        Err(_) => cancelled_response(&url, &request_id),
+    };
+    (response, attempts)
+}
+
+/// The part of [`relay`] a cancel can interrupt
+async fn exchange(
+    client: &reqwest::Client,
+    request: Request<Vec<u8>>,
+    url: &str,
+    request_id: &str,
+    tracked: &crate::inflight::Tracked,
+    max_attempts: u32,
+    attempts: &mut u32,
+) -> Response<Cow<'static, [u8]>> {
+    let ephemeral = request.uri().path().starts_with(EPHEMERAL_PREFIX);
+    let timeout = timeout_for(request.uri().path());
+    let (parts, mut body) = request.into_parts();
+    let mut headers = outgoing_headers(&parts.headers);
+    if let Ok(value) = HeaderValue::from_str(request_id) {
+        headers.insert(REQUEST_ID_HEADER, value);
+    }
+
+    let mut started;
+    let mut sent_at;
+    let mut upstream = loop {
+        *attempts += 1;
+        let last = *attempts >= max_attempts;
+        // Only the last try may take the body instead of copying it
+        let attempt_body = if last { std::mem::take(&mut body) } else { body.clone() };
+        started = Instant::now();
+        sent_at = crate::ipc::now_ms();
+        let result = client.request(parts.method.clone(), url).headers(headers.clone()).body(attempt_body).timeout(timeout).send().await;
+        let retry = match &result {
+            Ok(upstream) => RETRY_STATUSES.contains(&upstream.status()),
+            Err(e) => e.is_connect(),
+        };
+        if retry && !last {
+            let wait = send_retry_delay(*attempts);
+            let reason = match &result {
+                Ok(upstream) => upstream.status().to_string(),
+                Err(e) => e.to_string(),
+            };
+            warn!(
+                "API request {} failed ({}), retrying in {:?} ({}/{}) [{}]",
+                crate::logging::redact_url(url),
+                reason,
+                wait,
+                attempts,
+                max_attempts,
+                request_id
+            );
+            tokio::time::sleep(wait).await;
+            continue;
+        }
+        match result {
+            Ok(upstream) => break upstream,
+            Err(e) if e.is_timeout() => return timeout_response(url, None, started.elapsed(), timeout, request_id),
+            Err(e) => {
+                warn!("API request {} failed [{}]: {}", crate::logging::redact_url(url), request_id, e);
+                crate::core::network::recheck();
+                return error_response(ErrorCode::from_reqwest(&e), None, &e.to_string(), request_id);
+            }
+        }
+    };
+
+    let status = upstream.status();
+    if !ephemeral {
+        debug!(
+            "API request {} answered {} over {:?} [{}]",
+            crate::logging::redact_url(url),
+            status,
+            upstream.version(),
+            request_id
+        );
+    }
+    let is_json = upstream
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.contains("json"));
+    let mut response = Response::builder().status(status);
+    for (name, value) in upstream.headers() {
+        if !SKIPPED_HEADERS.contains(&name.as_str()) {
+            response = response.header(name, value);
+        }
+    }
+    let mut bytes = Vec::new();
+    loop {
+        match upstream.chunk().await {
+            Ok(Some(chunk)) => {
+                bytes.extend_from_slice(&chunk);
+                tracked.add_bytes(chunk.len());
+            }
+            Ok(None) => break,
+            // `status` may be a 2xx: it is only reported as the envelope's
+            // `upstream_status`, never answered as the HTTP status
+            Err(e) if e.is_timeout() => {
+                return timeout_response(url, Some(status), started.elapsed(), timeout, request_id);
+            }
+            Err(e) => return error_response(ErrorCode::Network, Some(status), &e.to_string(), request_id),
+        }
+    }
+
+    let span = crate::ipc::Upstream { sent_at, received_at: crate::ipc::now_ms() };
+    let upstream_ms = started.elapsed().as_secs_f64() * 1000.0;
+    if let Ok(value) = HeaderValue::from_str(&format!("{};dur={:.1}", UPSTREAM_TIMING, upstream_ms)) {
+        response = response.header(SERVER_TIMING, value);
+    }
+
+    if status == StatusCode::UNAUTHORIZED {
+        crate::session_takeover::rejected(parts.uri.path(), upstream.headers(), &bytes);
+    } else if status.is_success() {
+        crate::session_takeover::authorized();
+    }
+
+    let mut answer = if let Some(until) = crate::erp_maintenance::detect(status, upstream.headers(), &bytes) {
+        crate::erp_maintenance::seen(until);
+        maintenance_response(until, Some(status), request_id)
+    } else if (status.is_client_error() || status.is_server_error()) && !is_json {
+        let text = String::from_utf8_lossy(&bytes);
+        let message: String = match text.trim() {
+            "" => status.to_string(),
+            text => text.chars().take(MAX_ERROR_MESSAGE).collect(),
+        };
+        error_response(ErrorCode::from_status(status), Some(status), &message, request_id)
+    } else {
+        response.body(Cow::Owned(bytes)).unwrap()
+    };
+    answer.extensions_mut().insert(span);
+    answer
+}
+
+fn timeout_response(url: &str, upstream_status: Option<StatusCode>, elapsed: Duration, timeout: Duration, request_id: &str) -> Response<Cow<'static, [u8]>> {
+    warn!("API request {} timed out after {:?} (limit {:?}) [{}]", crate::logging::redact_url(url), elapsed, timeout, request_id);
+    let message = format!("no complete answer within {}s", timeout.as_secs());
+    envelope_response(ErrorBody {
+        code: ErrorCode::Timeout,
+        message: &message,
+        upstream_status: upstream_status.map(|s| s.as_u16()),
+        request_id,
+        elapsed_ms: Some(elapsed.as_millis() as u64),
+        timeout_ms: Some(timeout.as_millis() as u64),
+        retry_after: None,
+        until: None,
+    })
+}
+
+fn rate_limited_response(retry_after: u64, request_id: &str) -> Response<Cow<'static, [u8]>> {
+    let mut response = envelope_response(ErrorBody {
+        code: ErrorCode::RateLimited,
+        message: "too many failed sign-in attempts",
+        upstream_status: None,
+        request_id,
+        elapsed_ms: None,
+        timeout_ms: None,
+        retry_after: Some(retry_after),
+        until: None,
+    });
+    response.headers_mut().insert(http::header::RETRY_AFTER, HeaderValue::from(retry_after));
+    response
+}
+
+fn maintenance_response(until: Option<chrono::DateTime<chrono::Local>>, upstream_status: Option<StatusCode>, request_id: &str) -> Response<Cow<'static, [u8]>> {
+    let message = match until {
+        Some(until) => format!("the server is down for maintenance until {}", until.format("%H:%M")),
+        None => "the server is down for maintenance".to_string(),
+    };
+    let retry_after = until.and_then(|until| (until - chrono::Local::now()).to_std().ok()).map(|wait| wait.as_secs());
+    let mut response = envelope_response(ErrorBody {
+        code: ErrorCode::ErpMaintenance,
+        message: &message,
+        upstream_status: upstream_status.map(|s| s.as_u16()),
+        request_id,
+        elapsed_ms: None,
+        timeout_ms: None,
+        retry_after,
+        until: until.map(|until| until.to_rfc3339()),
+    });
+    if let Some(retry_after) = retry_after {
+        response.headers_mut().insert(http::header::RETRY_AFTER, HeaderValue::from(retry_after));
+    }
+    response
+}
+
+fn cancelled_response(url: &str, request_id: &str) -> Response<Cow<'static, [u8]>> {
+    info!("API request {} cancelled [{}]", crate::logging::redact_url(url), request_id);
+    error_response(ErrorCode::Cancelled, None, "request cancelled", request_id)
+}
+
+fn json_response(value: &serde_json::Value) -> Response<Cow<'static, [u8]>> {
+    Response::builder()
+        .header(CONTENT_TYPE, "application/json")
+        .body(Cow::Owned(serde_json::to_vec(value).unwrap_or_default()))
+        .unwrap()
+}
+
+/// Failure envelope; see [`envelope_response`] for its status
+fn error_response(code: ErrorCode, upstream_status: Option<StatusCode>, message: &str, request_id: &str) -> Response<Cow<'static, [u8]>> {
+    envelope_response(ErrorBody {
+        code,
+        message,
+        upstream_status: upstream_status.map(|s| s.as_u16()),
+        request_id,
+        elapsed_ms: None,
+        timeout_ms: None,
+        retry_after: None,
+        until: None,
+    })
+}
+
+/// The envelope with the upstream status when that was an error one, else
+/// the code's: a transport failure after a 2xx status line must not reach
+/// the page as a success (`fetch().ok`)
+fn envelope_response(error: ErrorBody) -> Response<Cow<'static, [u8]>> {
+    let status = error
+        .upstream_status
+        .and_then(|status| StatusCode::from_u16(status).ok())
+        .filter(|status| status.is_client_error() || status.is_server_error())
+        .unwrap_or_else(|| error.code.status());
+    let request_id = error.request_id.to_string();
+    let envelope = ErrorEnvelope { success: false, error };
+    Response::builder()
+        .status(status)
+        .header(CONTENT_TYPE, "application/json")
+        .header(REQUEST_ID_HEADER, request_id)
+        .body(Cow::Owned(serde_json::to_vec(&envelope).unwrap_or_default()))
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    const CODES: &[(ErrorCode, &str)] = &[
+        (ErrorCode::Network, "network"),
+        (ErrorCode::Timeout, "timeout"),
+        (ErrorCode::Unauthorized, "unauthorized"),
+        (ErrorCode::ErpError, "erp_error"),
+        (ErrorCode::BadRequest, "bad_request"),
+        (ErrorCode::Internal, "internal"),
+        (ErrorCode::Cancelled, "cancelled"),
+        (ErrorCode::Offline, "offline"),
+        (ErrorCode::RateLimited, "rate_limited"),
+        (ErrorCode::ErpMaintenance, "erp_maintenance"),
+    ];
+
+    fn body(response: &Response<Cow<'static, [u8]>>) -> Value {
+        serde_json::from_slice(response.body()).unwrap()
+    }
+
+    #[test]
+    fn error_codes_roundtrip() {
+        for &(code, name) in CODES {
+            let text = serde_json::to_string(&code).unwrap();
+            assert_eq!(text, format!("\"{}\"", name));
+            assert_eq!(serde_json::from_str::<ErrorCode>(&text).unwrap(), code);
+        }
+    }
+
+    #[test]
+    fn upstream_statuses_map_to_codes() {
+        let table = [
+            (400, ErrorCode::BadRequest),
+            (401, ErrorCode::Unauthorized),
+            (403, ErrorCode::Unauthorized),
+            (404, ErrorCode::BadRequest),
+            (422, ErrorCode::BadRequest),
+            (429, ErrorCode::BadRequest),
+            (500, ErrorCode::ErpError),
+            (502, ErrorCode::ErpError),
+            (503, ErrorCode::ErpError),
+            (504, ErrorCode::ErpError),
+        ];
+        for (status, code) in table {
+            assert_eq!(ErrorCode::from_status(StatusCode::from_u16(status).unwrap()), code, "{}", status);
+        }
+    }
+
+    #[test]
+    fn transport_failures_map_to_codes() {
+        let client = reqwest::blocking::Client::builder().timeout(Duration::from_millis(300)).build().unwrap();
+
+        let builder = client.get("not a url").send().unwrap_err();
+        assert_eq!(ErrorCode::from_reqwest(&builder), ErrorCode::BadRequest);
+
+        // Nothing listens on a port just released
+        let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let refused = client.get(format!("http://127.0.0.1:{}/", port)).send().unwrap_err();
+        assert_eq!(ErrorCode::from_reqwest(&refused), ErrorCode::Network);
+
+        // Accepts the connection, never answers
+        let silent = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let timed_out = client.get(format!("http://{}/", silent.local_addr().unwrap())).send().unwrap_err();
+        assert_eq!(ErrorCode::from_reqwest(&timed_out), ErrorCode::Timeout);
+    }
+
+    #[test]
+    fn envelope_shape() {
+        let response = error_response(ErrorCode::BadRequest, Some(StatusCode::UNPROCESSABLE_ENTITY), "invalid thread", "req-1");
+        assert_eq!(response.headers()[CONTENT_TYPE], "application/json");
+        assert_eq!(response.headers()[REQUEST_ID_HEADER], "req-1");
+        assert_eq!(
+            body(&response),
+            json!({
+                "success": false,
+                "error": { "code": "bad_request", "message": "invalid thread", "upstream_status": 422, "request_id": "req-1" },
+            })
+        );
+
+        let response = error_response(ErrorCode::Network, None, "connection reset", "req-2");
+        let error = &body(&response)["error"];
+        assert!(error.get("upstream_status").is_none());
+        assert!(error.get("elapsed_ms").is_none() && error.get("retry_after").is_none() && error.get("until").is_none());
+    }
+
+    #[test]
+    fn envelopes_keep_only_error_statuses() {
+        let table = [
+            (None, ErrorCode::Network, 502),
+            (Some(200), ErrorCode::Network, 502),
+            (Some(206), ErrorCode::Timeout, 504),
+            (Some(304), ErrorCode::Network, 502),
+            (Some(404), ErrorCode::BadRequest, 404),
+            (Some(401), ErrorCode::Unauthorized, 401),
+            (Some(500), ErrorCode::ErpError, 500),
+            (Some(503), ErrorCode::ErpError, 503),
+            (None, ErrorCode::Cancelled, 499),
+        ];
+        for (upstream, code, expected) in table {
+            let upstream = upstream.map(|status| StatusCode::from_u16(status).unwrap());
+            let response = error_response(code, upstream, "failed", "req");
+            assert_eq!(response.status().as_u16(), expected, "{:?} {:?}", upstream, code);
+            assert_eq!(body(&response)["error"]["upstream_status"], json!(upstream.map(|s| s.as_u16())));
+        }
+    }
+
+    #[test]
+    fn body_failures_after_a_success_status_are_not_successes() {
+        let response = timeout_response("http://erp/api/threads", Some(StatusCode::OK), Duration::from_secs(31), Duration::from_secs(30), "req");
+        assert_eq!(response.status(), StatusCode::GATEWAY_TIMEOUT);
+        let error = &body(&response)["error"];
+        assert_eq!(error["code"], "timeout");
+        assert_eq!(error["upstream_status"], 200);
+        assert_eq!(error["timeout_ms"], 30_000);
+
+        let response = error_response(ErrorCode::Network, Some(StatusCode::OK), "connection reset", "req");
+        assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+        assert_eq!(body(&response)["error"]["upstream_status"], 200);
+    }
+
+    fn forwarding(default_secs: u64, routes: &[(&str, u64)]) -> crate::core::settings::ForwardingSettings {
+        crate::core::settings::ForwardingSettings {
+            timeout_secs: default_secs,
+            route_timeouts: routes.iter().map(|(prefix, secs)| (prefix.to_string(), *secs)).collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn route_timeouts_take_the_longest_whole_segment_prefix() {
+        let settings = forwarding(30, &[("/api/files", 300), ("/api/files/thumbs/", 10), ("/api", 60)]);
+        let table = [
+            ("/api/threads", 60),
+            ("/api", 60),
+            ("/apix", 30),
+            ("/api/files", 300),
+            ("/api/files/123", 300),
+            ("/api/filesystem", 60),
+            ("/api/files/thumbs", 10),
+            ("/api/files/thumbs/1.png", 10),
+            ("/other", 30),
+        ];
+        for (path, secs) in table {
+            assert_eq!(route_timeout(&settings, path), Duration::from_secs(secs), "{}", path);
+        }
+    }
+
+    #[test]
+    fn route_timeouts_are_at_least_a_second() {
+        let settings = forwarding(0, &[("/api/fast", 0)]);
+        assert_eq!(route_timeout(&settings, "/api/fast"), Duration::from_secs(1));
+        assert_eq!(route_timeout(&settings, "/api/other"), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn default_route_timeouts() {
+        let settings = crate::core::settings::ForwardingSettings::default();
+        assert_eq!(route_timeout(&settings, "/api/fileupload"), Duration::from_secs(300));
+        assert_eq!(route_timeout(&settings, "/api/threads"), Duration::from_secs(settings.timeout_secs));
+    }
+
+    /// What `relay` made of one scripted upstream answer
+    struct Case {
+        path: &'static str,
+        status: u16,
+        /// `None`: the upstream body passes through untouched
+        code: Option<&'static str>,
+        upstream_status: Option<u16>,
+    }
+
+    fn relayed(path: &str) -> Response<Cow<'static, [u8]>> {
+        relay(Request::get(format!("{}://app{}", SCHEME, path)).body(Vec::new()).unwrap(), 1).0
+    }
+
+    #[test]
+    fn upstream_failures_become_envelopes() {
+        let _session = crate::testing::session(false);
+        crate::testing::upstream();
+        crate::testing::route_timeout("/t/codes/stall", 1);
+        crate::testing::route("/t/codes/", |request| {
+            use crate::testing::Reply;
+            match request.path.trim_start_matches("/t/codes/") {
+                "html-500" => Reply::text(500, "text/html", "<h1>Internal Server Error</h1>"),
+                "plain-502" => Reply::text(502, "text/plain", "Bad Gateway"),
+                "empty-503" => Reply::text(503, "text/plain", ""),
+                "plain-504" => Reply::text(504, "text/plain", "upstream timed out"),
+                "html-404" => Reply::text(404, "text/html", "<h1>Not Found</h1>"),
+                "plain-422" => Reply::text(422, "text/plain", "thread is archived"),
+                "plain-429" => Reply::text(429, "text/plain", "slow down"),
+                "plain-401" => Reply::text(401, "text/plain", "Unauthorized"),
+                "html-403" => Reply::text(403, "text/html", "<h1>Forbidden</h1>"),
+                "json-404" => Reply::json(404, json!({ "success": false, "message": "no such thread" })),
+                "json-500" => Reply::json(500, json!({ "success": false, "message": "database is down" })),
+                "truncated" => Reply::Truncated { status: 200, declared: 4096, sent: 10 },
+                "closed" => Reply::Close,
+                "stall" => Reply::Stall(Duration::from_secs(3)),
+                _ => Reply::json(200, json!({ "success": true })),
+            }
+        });
+
+        let cases = [
+            Case { path: "/t/codes/html-500", status: 500, code: Some("erp_error"), upstream_status: Some(500) },
+            Case { path: "/t/codes/plain-502", status: 502, code: Some("erp_error"), upstream_status: Some(502) },
+            Case { path: "/t/codes/empty-503", status: 503, code: Some("erp_error"), upstream_status: Some(503) },
+            Case { path: "/t/codes/plain-504", status: 504, code: Some("erp_error"), upstream_status: Some(504) },
+            Case { path: "/t/codes/html-404", status: 404, code: Some("bad_request"), upstream_status: Some(404) },
+            Case { path: "/t/codes/plain-422", status: 422, code: Some("bad_request"), upstream_status: Some(422) },
+            Case { path: "/t/codes/plain-429", status: 429, code: Some("bad_request"), upstream_status: Some(429) },
+            Case { path: "/t/codes/plain-401", status: 401, code: Some("unauthorized"), upstream_status: Some(401) },
+            Case { path: "/t/codes/html-403", status: 403, code: Some("unauthorized"), upstream_status: Some(403) },
+            Case { path: "/t/codes/json-404", status: 404, code: None, upstream_status: None },
+            Case { path: "/t/codes/json-500", status: 500, code: None, upstream_status: None },
+            Case { path: "/t/codes/truncated", status: 502, code: Some("network"), upstream_status: Some(200) },
+            Case { path: "/t/codes/closed", status: 502, code: Some("network"), upstream_status: None },
+            Case { path: "/t/codes/stall", status: 504, code: Some("timeout"), upstream_status: None },
+            Case { path: "/t/codes/fine", status: 200, code: None, upstream_status: None },
+        ];
+        for case in cases {
+            let response = relayed(case.path);
+            assert_eq!(response.status().as_u16(), case.status, "{}", case.path);
+            let body = body(&response);
+            match case.code {
+                Some(code) => {
+                    assert_eq!(body["success"], false, "{}", case.path);
+                    assert_eq!(body["error"]["code"], code, "{}", case.path);
+                    assert_eq!(body["error"]["upstream_status"], json!(case.upstream_status), "{}", case.path);
+                    assert!(!body["error"]["message"].as_str().unwrap_or_default().is_empty(), "{}", case.path);
+                }
+                // Not wrapped in an envelope
+                None => assert!(body["error"]["code"].is_null() && body.get("success").is_some(), "{}: {}", case.path, body),
+            }
+        }
+    }
+
+    #[test]
+    fn error_pages_are_kept_as_the_message() {
+        let _session = crate::testing::session(false);
+        crate::testing::upstream();
+        crate::testing::route("/t/messages/", |request| match request.path.as_str() {
+            "/t/messages/long" => crate::testing::Reply::text(500, "text/html", &"x".repeat(MAX_ERROR_MESSAGE * 2)),
+            "/t/messages/blank" => crate::testing::Reply::text(502, "text/html", "  \n "),
+            _ => crate::testing::Reply::text(500, "text/plain", "  database is down\n"),
+        });
+
+        assert_eq!(body(&relayed("/t/messages/plain"))["error"]["message"], "database is down");
+        assert_eq!(body(&relayed("/t/messages/blank"))["error"]["message"], "502 Bad Gateway");
+        let long = body(&relayed("/t/messages/long"));
+        assert_eq!(long["error"]["message"].as_str().unwrap().chars().count(), MAX_ERROR_MESSAGE);
+    }
+
+    #[test]
+    fn timeouts_say_how_long_they_waited() {
+        let _session = crate::testing::session(false);
+        crate::testing::upstream();
+        crate::testing::route_timeout("/t/timeout", 1);
+        crate::testing::route("/t/timeout", |_| crate::testing::Reply::Stall(Duration::from_secs(3)));
+
+        let error = body(&relayed("/t/timeout"))["error"].clone();
+        assert_eq!(error["timeout_ms"], 1000);
+        assert!(error["elapsed_ms"].as_u64().unwrap() >= 1000, "{}", error);
+    }
+
+    #[test]
+    fn unreachable_servers_are_network_failures() {
+        // Nothing listens on a port just released
+        let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let client = reqwest::blocking::Client::new();
+        let refused = client.get(format!("http://127.0.0.1:{}/", port)).send().unwrap_err();
+        let response = error_response(ErrorCode::from_reqwest(&refused), None, &refused.to_string(), "req");
+        assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+        assert_eq!(body(&response)["error"]["code"], "network");
+        assert!(is_unreachable(&response));
+    }
+
+    #[test]
+    fn cursors_and_pagination_headers_pass_through() {
+        let _session = crate::testing::session(false);
+        crate::testing::upstream();
+        crate::testing::route("/t/cursor/", |request| crate::testing::Reply::Respond {
+            status: 200,
+            headers: vec![
+                ("Content-Type", "application/json".to_string()),
+                ("Link", format!("<{}&limit=50>; rel=\"next\"", request.path)),
+                ("X-Next-Cursor", "eyJpZCI6MX0%3D&x=1".to_string()),
+            ],
+            body: json!({ "path": request.path }).to_string().into_bytes(),
+        });
+
+        let queries = [
+            "cursor=eyJpZCI6MX0%3D%3D&limit=50",
+            "cursor=a%26b%3Dc&limit=2",
+            "cursor=%E0%B8%81%E0%B8%B5%E0%B9%88%F0%9F%98%80",
+            "cursor=x+y%20z&empty=&flag",
+            "cursor=%2F..%2Fadmin%3Fq%23frag",
+            "limit=20&cursor=a&cursor=b",
+        ];
+        for query in queries {
+            let path = format!("/t/cursor/api/chats?{}", query);
+            let response = relayed(&path);
+            assert_eq!(response.status(), StatusCode::OK, "{}", query);
+            assert_eq!(body(&response)["path"], path.as_str(), "{}", query);
+            assert_eq!(response.headers()["x-next-cursor"], "eyJpZCI6MX0%3D&x=1");
+            assert_eq!(response.headers()["link"], format!("<{}&limit=50>; rel=\"next\"", path).as_str());
+        }
+    }
+
+    /// The in-flight entry of the request to `path`, once it has read `bytes`
+    fn in_flight(path: &str, bytes: u64) -> u64 {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        loop {
+            let list = crate::inflight::list();
+            let found = list["requests"].as_array().unwrap().iter().find(|r| r["path"] == path && r["bytes"].as_u64() >= Some(bytes));
+            if let Some(request) = found {
+                return request["id"].as_u64().unwrap();
+            }
+            assert!(Instant::now() < deadline, "{} never showed up in flight", path);
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn answers_from_the_api_carry_when_it_was_asked() {
+        let _session = crate::testing::session(false);
+        crate::testing::upstream();
+        crate::testing::route("/t/span/", |request| match request.path.as_str() {
+            "/t/span/html-500" => crate::testing::Reply::text(500, "text/html", "<h1>Internal Server Error</h1>"),
+            "/t/span/closed" => crate::testing::Reply::Close,
+            _ => crate::testing::Reply::json(200, json!({ "success": true })),
+        });
+
+        for path in ["/t/span/ok", "/t/span/html-500"] {
+            let before = crate::ipc::now_ms();
+            let response = relayed(path);
+            let after = crate::ipc::now_ms();
+            let span = crate::ipc::Upstream::of(&response).unwrap_or_else(|| panic!("{} has no span", path));
+            assert!(before <= span.sent_at && span.sent_at <= span.received_at && span.received_at <= after, "{}: {:?}", path, span);
+        }
+        assert_eq!(crate::ipc::Upstream::of(&relayed("/t/span/closed")), None);
+    }
+
+    #[test]
+    fn cancelling_stops_a_request_where_it_is() {
+        let _session = crate::testing::session(false);
+        crate::testing::upstream();
+        crate::testing::route_timeout("/t/cancel", 60);
+        crate::testing::route("/t/cancel/", |request| match request.path.as_str() {
+            "/t/cancel/headers" => crate::testing::Reply::Stall(Duration::from_secs(30)),
+            _ => crate::testing::Reply::Trickle { declared: 1 << 20, pause: Duration::from_millis(20) },
+        });
+
+        // Waiting for the headers, then halfway through the body
+        for (path, bytes) in [("/t/cancel/headers", 0), ("/t/cancel/body", 1)] {
+            let relaying = std::thread::spawn(move || relayed(path));
+            let id = in_flight(path, bytes);
+            let cancelled_at = Instant::now();
+            assert_eq!(crate::inflight::cancel(id)["success"], true);
+            let response = relaying.join().unwrap();
+            assert!(cancelled_at.elapsed() < Duration::from_secs(1), "{} took {:?}", path, cancelled_at.elapsed());
+            assert_eq!(response.status().as_u16(), 499, "{}", path);
+            assert_eq!(body(&response)["error"]["code"], "cancelled", "{}", path);
+            assert!(!crate::inflight::list()["requests"].as_array().unwrap().iter().any(|r| r["id"] == id));
+        }
+    }
+
+    fn page_request(method: Method, path: &str, body: Option<Value>) -> Request<Vec<u8>> {
+        let body = body.map(|body| body.to_string().into_bytes()).unwrap_or_default();
+        Request::builder().method(method).uri(format!("{}://app{}", SCHEME, path)).header(CONTENT_TYPE, "application/json").body(body).unwrap()
+    }
+
+    #[test]
+    fn message_sends_retry_through_gateway_failures() {
+        use crate::testing::mock_erp::{chat_uuid, Faults};
+
+        let session = crate::testing::session(true);
+        let mock = session.use_mock_erp();
+        let chat = chat_uuid(1);
+        let path = format!("/api/chats/{}/messages", chat);
+        let send = |content: &str| forward(page_request(Method::POST, &path, Some(json!({ "content": content }))));
+        let stored = mock.messages(&chat).len();
+
+        mock.set_faults(Faults { path_prefix: Some(path.clone()), status: Some(502), count: Some(2), ..Faults::default() });
+        let response = send("through the outage");
+        assert_eq!(response.status(), StatusCode::CREATED);
+        assert_eq!(response.headers()[ATTEMPTS_HEADER], "3");
+        assert_eq!(body(&response)["attempts"], 3);
+        let key = response.headers()[IDEMPOTENCY_KEY_HEADER].to_str().unwrap().to_string();
+        let requests = mock.take_requests();
+        assert_eq!(requests.len(), 3);
+        assert!(requests.iter().all(|r| r.idempotency_key.as_deref() == Some(key.as_str())), "{:?}", requests);
+        assert_eq!(mock.messages(&chat).len(), stored + 1);
+
+        // Only a gateway failing is worth another try
+        mock.set_faults(Faults { path_prefix: Some(path.clone()), status: Some(500), count: Some(1), ..Faults::default() });
+        let response = send("not retried");
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(response.headers()[ATTEMPTS_HEADER], "1");
+        assert_eq!(mock.take_requests().len(), 1);
+
+        // And only so often
+        mock.set_faults(Faults { path_prefix: Some(path.clone()), status: Some(503), ..Faults::default() });
+        let response = send("given up");
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.headers()[ATTEMPTS_HEADER], SEND_ATTEMPTS.to_string().as_str());
+        assert_eq!(mock.take_requests().len(), SEND_ATTEMPTS as usize);
+        assert_eq!(mock.messages(&chat).len(), stored + 1);
+    }
+
+    #[test]
+    fn kept_answers_stand_in_while_the_erp_is_unreachable() {
+        use crate::testing::mock_erp::{chat_uuid, Faults};
+
+        let session = crate::testing::session(true);
+        let mock = session.use_mock_erp();
+        let live = forward(page_request(Method::GET, "/api/chats", None));
+        assert_eq!(live.status(), StatusCode::OK);
+        assert!(live.headers().get("x-miko-offline").is_none());
+
+        mock.set_faults(Faults { disconnect: true, ..Faults::default() });
+        let kept = forward(page_request(Method::GET, "/api/chats", None));
+        assert_eq!(kept.status(), StatusCode::OK);
+        assert_eq!(kept.headers()["x-miko-offline"], "true");
+        assert_eq!(body(&kept), body(&live));
+        // Nothing was kept for a chat never opened
+        let missing = forward(page_request(Method::GET, &format!("/api/chats/{}/messages", chat_uuid(5)), None));
+        assert_eq!(body(&missing)["error"]["code"], "network");
+
+        // An ERP that answers, even with an error, speaks for itself
+        mock.set_faults(Faults { status: Some(500), ..Faults::default() });
+        let failed = forward(page_request(Method::GET, "/api/chats", None));
+        assert_eq!(failed.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(body(&failed)["error"]["code"], "erp_error");
+    }
+}
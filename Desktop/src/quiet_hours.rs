@@ -0,0 +1,152 @@
+//! Scheduled quiet hours for notifications.
+//!
+//! `notifications.quiet_hours` silences native notifications between `start`
+//! and `end` (local `HH:MM`) on the listed `days`, like do-not-disturb does.
+//! A range ending before it starts runs past midnight and belongs to the day
+//! it started on, so `22:00`–`07:00` on `fri` covers Saturday morning too.
+//! A status the user sets by hand wins over the schedule either way.
+//!
+//! Silenced Windows toasts still go to the Action Center, without popping up
+//! or making a sound; macOS shows them without a sound. The page keeps
+//! counting unread messages. The schedule is checked against the local clock
+//! every time, and a thread samples it so the `quiet-hours` topic and the
+//! tray tooltip follow the clock and OS timezone changes.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use chrono::{Datelike, Local, NaiveDate, NaiveDateTime, NaiveTime, Weekday};
+use serde_json::{json, Value};
+use tracing::info;
+
+use crate::core::settings::{self, QuietHoursSettings};
+use crate::core::sync;
+
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(30);
+
+static STARTED: AtomicBool = AtomicBool::new(false);
+/// Whether the schedule applied at the last sample
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+/// Set when [`ACTIVE`] changed; the event loop takes it to update the tray
+static DIRTY: AtomicBool = AtomicBool::new(false);
+
+fn parse_time(time: &str) -> Option<NaiveTime> {
+    NaiveTime::parse_from_str(time.trim(), "%H:%M").ok()
+}
+
+fn parse_day(day: &str) -> Option<Weekday> {
+    day.trim().parse().ok()
+}
+
+/// The day the quiet hours covering `now` started on, if they do
+fn started_on(start: NaiveTime, end: NaiveTime, now: NaiveDateTime) -> Option<NaiveDate> {
+    let time = now.time();
+    if start <= end {
+        (time >= start && time < end).then(|| now.date())
+    } else if time >= start {
+        Some(now.date())
+    } else if time < end {
+        now.date().pred_opt()
+    } else {
+        None
+    }
+}
+
+/// Whether `hours` silence notifications at local time `now`
+fn quiet_at(hours: &QuietHoursSettings, now: NaiveDateTime) -> bool {
+    if !hours.enabled {
+        return false;
+    }
+    let (Some(start), Some(end)) = (parse_time(&hours.start), parse_time(&hours.end)) else { return false };
+    let Some(day) = started_on(start, end, now) else { return false };
+    // No days listed means every day
+    hours.days.is_empty() || hours.days.iter().filter_map(|d| parse_day(d)).any(|d| d == day.weekday())
+}
+
+/// The schedule applies right now
+pub fn active() -> bool {
+    quiet_at(&settings::get().notifications.quiet_hours, Local::now().naive_local())
+}
+
+/// Notifications should be silent: the user chose do-not-disturb, or the
+/// schedule applies and no status was set by hand
+pub fn silenced() -> bool {
+    if crate::presence::is_do_not_disturb() {
+        return true;
+    }
+    !crate::presence::current().manual && active()
+}
+
+/// Start the sampling thread (once)
+pub fn start_monitor() {
+    if STARTED.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    std::thread::spawn(|| loop {
+        refresh();
+        std::thread::sleep(SAMPLE_INTERVAL);
+    });
+}
+
+/// Publish the schedule's state if it changed
+fn refresh() {
+    let active = active();
+    if ACTIVE.swap(active, Ordering::SeqCst) == active {
+        return;
+    }
+    info!("Quiet hours {}", if active { "started" } else { "ended" });
+    DIRTY.store(true, Ordering::SeqCst);
+    crate::ipc::wake();
+    sync::publish(sync::TOPIC_QUIET_HOURS);
+}
+
+/// Returns true once after quiet hours started or ended
+pub fn take_tray_update() -> bool {
+    DIRTY.swap(false, Ordering::SeqCst)
+}
+
+/// Tray tooltip detail while quiet hours apply
+pub fn tooltip() -> Option<String> {
+    ACTIVE
+        .load(Ordering::SeqCst)
+        .then(|| format!("🌙 Quiet until {}", settings::get().notifications.quiet_hours.end))
+}
+
+/// Handle `set_quiet_hours`: fields left out keep their value
+pub fn set(message: &Value) -> Result<QuietHoursSettings, String> {
+    let mut hours = settings::get().notifications.quiet_hours;
+    if let Some(enabled) = message["enabled"].as_bool() {
+        hours.enabled = enabled;
+    }
+    for (field, value) in [("start", &mut hours.start), ("end", &mut hours.end)] {
+        if let Some(time) = message[field].as_str() {
+            let time = parse_time(time).ok_or_else(|| format!("{} must be HH:MM, got {:?}", field, time))?;
+            *value = time.format("%H:%M").to_string();
+        }
+    }
+    if let Some(days) = message["days"].as_array() {
+        hours.days = days
+            .iter()
+            .map(|day| {
+                let day = day.as_str().unwrap_or_default();
+                let parsed = parse_day(day).ok_or_else(|| format!("unknown day: {:?}", day))?;
+                Ok(parsed.to_string().to_lowercase())
+            })
+            .collect::<Result<_, String>>()?;
+    }
+
+    let saved = hours.clone();
+    settings::update(|s| s.notifications.quiet_hours = saved).map_err(|e| e.to_string())?;
+    sync::publish(sync::TOPIC_QUIET_HOURS);
+    refresh();
+    Ok(hours)
+}
+
+pub fn snapshot() -> Value {
+    let hours = settings::get().notifications.quiet_hours;
+    let active = quiet_at(&hours, Local::now().naive_local());
+    json!({
+        "active": active,
+        "until": active.then(|| hours.end.clone()),
+        "schedule": hours,
+    })
+}
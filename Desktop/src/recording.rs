@@ -0,0 +1,333 @@
+//! Voice memos recorded natively (`start_audio_recording` /
+//! `stop_audio_recording`).
+//!
+//! MediaRecorder in the page produces large webm files and its microphone
+//! prompt is unreliable inside WebView2. Here the default input device is
+//! captured with cpal, mixed down to mono and written as 16-bit WAV to a temp
+//! file, which the page then hands to the upload pipeline. While recording,
+//! `recording-level {rms}` goes out ten times a second for the level meter. A
+//! recording stops by itself after `recording.max_duration_secs`; its result
+//! is then also emitted as `recording-stopped` and kept for the next stop.
+
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use lazy_static::lazy_static;
+use serde::Serialize;
+use serde_json::{json, Value};
+use tracing::{info, warn};
+
+/// How often samples are written out and the level is reported
+const LEVEL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// `code` of a failed recording request
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RecordingErrorCode {
+    /// No input device, or it went away
+    NoDevice,
+    /// The OS refused microphone access
+    PermissionDenied,
+    /// The device offers no sample format we can record
+    UnsupportedFormat,
+    AlreadyRecording,
+    NotRecording,
+    Failed,
+}
+
+#[derive(Debug)]
+pub struct RecordingError {
+    code: RecordingErrorCode,
+    message: String,
+}
+
+impl RecordingError {
+    fn new(code: RecordingErrorCode, message: impl Into<String>) -> Self {
+        Self { code, message: message.into() }
+    }
+
+    /// A backend error: the OS only says "access denied" in its message
+    fn backend(message: String) -> Self {
+        let lower = message.to_lowercase();
+        let code = if lower.contains("denied") || lower.contains("permission") || lower.contains("not authorized") {
+            RecordingErrorCode::PermissionDenied
+        } else {
+            RecordingErrorCode::Failed
+        };
+        Self::new(code, message)
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({ "success": false, "code": self.code, "error": self.message })
+    }
+}
+
+impl From<cpal::DefaultStreamConfigError> for RecordingError {
+    fn from(e: cpal::DefaultStreamConfigError) -> Self {
+        match e {
+            cpal::DefaultStreamConfigError::DeviceNotAvailable => Self::new(RecordingErrorCode::NoDevice, e.to_string()),
+            cpal::DefaultStreamConfigError::StreamTypeNotSupported => {
+                Self::new(RecordingErrorCode::UnsupportedFormat, e.to_string())
+            }
+            e => Self::backend(e.to_string()),
+        }
+    }
+}
+
+impl From<cpal::BuildStreamError> for RecordingError {
+    fn from(e: cpal::BuildStreamError) -> Self {
+        match e {
+            cpal::BuildStreamError::DeviceNotAvailable => Self::new(RecordingErrorCode::NoDevice, e.to_string()),
+            cpal::BuildStreamError::StreamConfigNotSupported => {
+                Self::new(RecordingErrorCode::UnsupportedFormat, e.to_string())
+            }
+            e => Self::backend(e.to_string()),
+        }
+    }
+}
+
+impl From<cpal::PlayStreamError> for RecordingError {
+    fn from(e: cpal::PlayStreamError) -> Self {
+        match e {
+            cpal::PlayStreamError::DeviceNotAvailable => Self::new(RecordingErrorCode::NoDevice, e.to_string()),
+            e => Self::backend(e.to_string()),
+        }
+    }
+}
+
+impl From<std::io::Error> for RecordingError {
+    fn from(e: std::io::Error) -> Self {
+        Self::new(RecordingErrorCode::Failed, format!("failed to write the recording: {}", e))
+    }
+}
+
+struct Recording {
+    stop: Sender<()>,
+    /// Returns the `recording-stopped` payload
+    thread: JoinHandle<Value>,
+}
+
+lazy_static! {
+    static ref RECORDING: Mutex<Option<Recording>> = Mutex::new(None);
+}
+
+/// Handle `start_audio_recording`; returns the IPC result payload
+pub fn start() -> Value {
+    let mut recording = RECORDING.lock().unwrap();
+    // One that stopped at the length cap but was never collected is dropped
+    if recording.as_ref().is_some_and(|r| !r.thread.is_finished()) {
+        return RecordingError::new(RecordingErrorCode::AlreadyRecording, "a recording is already running").to_json();
+    }
+
+    let max_duration = Duration::from_secs(crate::core::settings::get().recording.max_duration_secs.max(1));
+    let (stop_tx, stop_rx) = mpsc::channel();
+    let (started_tx, started_rx) = mpsc::channel();
+    // cpal streams can't move between threads, so this one owns it throughout
+    let thread = std::thread::spawn(move || {
+        let (stream, samples, mut wav) = match open() {
+            Ok(opened) => {
+                let _ = started_tx.send(Ok(opened.2.sample_rate));
+                opened
+            }
+            Err(e) => {
+                let result = e.to_json();
+                let _ = started_tx.send(Err(e));
+                return result;
+            }
+        };
+        let result = run(&stop_rx, &samples, &mut wav, max_duration);
+        drop(stream);
+        finish(wav, result)
+    });
+
+    match started_rx.recv() {
+        Ok(Ok(sample_rate)) => {
+            *recording = Some(Recording { stop: stop_tx, thread });
+            info!("Voice memo recording started ({} Hz)", sample_rate);
+            json!({ "success": true, "sampleRate": sample_rate, "maxDurationSecs": max_duration.as_secs() })
+        }
+        Ok(Err(e)) => {
+            warn!("Voice memo recording failed to start: {}", e.message);
+            e.to_json()
+        }
+        Err(_) => RecordingError::new(RecordingErrorCode::Failed, "the recording thread died").to_json(),
+    }
+}
+
+/// Handle `stop_audio_recording`; returns the file path in the IPC result
+pub fn stop() -> Value {
+    let Some(recording) = RECORDING.lock().unwrap().take() else {
+        return RecordingError::new(RecordingErrorCode::NotRecording, "no recording is running").to_json();
+    };
+    let _ = recording.stop.send(());
+    recording
+        .thread
+        .join()
+        .unwrap_or_else(|_| RecordingError::new(RecordingErrorCode::Failed, "the recording thread panicked").to_json())
+}
+
+/// Samples received by the device callback, mono, not yet written
+type SampleBuffer = Arc<Mutex<Vec<f32>>>;
+
+fn open() -> Result<(cpal::Stream, SampleBuffer, WavWriter), RecordingError> {
+    let device = cpal::default_host()
+        .default_input_device()
+        .ok_or_else(|| RecordingError::new(RecordingErrorCode::NoDevice, "no microphone found"))?;
+    let supported = device.default_input_config()?;
+    let config: cpal::StreamConfig = supported.config();
+    let samples: SampleBuffer = Arc::new(Mutex::new(Vec::new()));
+
+    let stream = match supported.sample_format() {
+        cpal::SampleFormat::F32 => build_stream::<f32>(&device, &config, samples.clone())?,
+        cpal::SampleFormat::I16 => build_stream::<i16>(&device, &config, samples.clone())?,
+        cpal::SampleFormat::U16 => build_stream::<u16>(&device, &config, samples.clone())?,
+        cpal::SampleFormat::I32 => build_stream::<i32>(&device, &config, samples.clone())?,
+        other => {
+            return Err(RecordingError::new(
+                RecordingErrorCode::UnsupportedFormat,
+                format!("unsupported sample format {:?}", other),
+            ))
+        }
+    };
+
+    let path = crate::paths::temp_dir().join(format!("miko-voice-memo-{}.wav", chrono::Local::now().format("%Y%m%d-%H%M%S")));
+    let wav = WavWriter::create(path, config.sample_rate.0)?;
+    stream.play()?;
+    Ok((stream, samples, wav))
+}
+
+fn build_stream<T>(device: &cpal::Device, config: &cpal::StreamConfig, samples: SampleBuffer) -> Result<cpal::Stream, cpal::BuildStreamError>
+where
+    T: cpal::SizedSample,
+    f32: cpal::FromSample<T>,
+{
+    let channels = config.channels.max(1) as usize;
+    device.build_input_stream(
+        config,
+        move |data: &[T], _: &cpal::InputCallbackInfo| {
+            let mono = data.chunks(channels).map(|frame| {
+                frame.iter().map(|&sample| <f32 as cpal::FromSample<T>>::from_sample_(sample)).sum::<f32>() / frame.len() as f32
+            });
+            samples.lock().unwrap().extend(mono);
+        },
+        |e| warn!("Audio input error: {}", e),
+        None,
+    )
+}
+
+/// Write out samples and report the level until stopped or `max_duration`
+/// is reached; returns why it ended
+fn run(stop: &mpsc::Receiver<()>, samples: &SampleBuffer, wav: &mut WavWriter, max_duration: Duration) -> Result<&'static str, RecordingError> {
+    let started = Instant::now();
+    let webview = crate::ipc::handle();
+    loop {
+        let stopped = !matches!(stop.recv_timeout(LEVEL_INTERVAL), Err(RecvTimeoutError::Timeout));
+        let chunk = std::mem::take(&mut *samples.lock().unwrap());
+        wav.write(&chunk)?;
+        if let Some(webview) = &webview {
+            let rms = if chunk.is_empty() { 0.0 } else { (chunk.iter().map(|s| s * s).sum::<f32>() / chunk.len() as f32).sqrt() };
+            webview.emit_latest("recording-level", "", &json!({ "rms": rms }));
+        }
+
+        if stopped {
+            return Ok("stopped");
+        }
+        if started.elapsed() >= max_duration {
+            info!("Voice memo reached the {} s limit", max_duration.as_secs());
+            return Ok("max_duration");
+        }
+    }
+}
+
+fn finish(wav: WavWriter, reason: Result<&'static str, RecordingError>) -> Value {
+    let path = wav.path.clone();
+    let duration_ms = wav.duration_ms();
+    let result = reason.and_then(|reason| {
+        let bytes = wav.finish()?;
+        Ok(json!({
+            "success": true,
+            "path": path,
+            "durationMs": duration_ms,
+            "bytes": bytes,
+            "reason": reason,
+        }))
+    });
+    match result {
+        Ok(result) => {
+            info!("Voice memo saved to {} ({} ms)", path.display(), duration_ms);
+            if result["reason"] == "max_duration" {
+                if let Some(webview) = crate::ipc::handle() {
+                    webview.emit("recording-stopped", &result);
+                }
+            }
+            result
+        }
+        Err(e) => {
+            warn!("Voice memo failed: {}", e.message);
+            let _ = std::fs::remove_file(&path);
+            e.to_json()
+        }
+    }
+}
+
+/// 16-bit mono PCM WAV; the sizes in the header are filled in by `finish`
+struct WavWriter {
+    path: PathBuf,
+    file: BufWriter<File>,
+    sample_rate: u32,
+    samples: u32,
+}
+
+impl WavWriter {
+    fn create(path: PathBuf, sample_rate: u32) -> std::io::Result<Self> {
+        let mut file = BufWriter::new(File::create(&path)?);
+        file.write_all(&wav_header(sample_rate, 0))?;
+        Ok(Self { path, file, sample_rate, samples: 0 })
+    }
+
+    fn write(&mut self, samples: &[f32]) -> std::io::Result<()> {
+        for sample in samples {
+            let value = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+            self.file.write_all(&value.to_le_bytes())?;
+        }
+        self.samples = self.samples.saturating_add(samples.len() as u32);
+        Ok(())
+    }
+
+    fn duration_ms(&self) -> u64 {
+        self.samples as u64 * 1000 / self.sample_rate.max(1) as u64
+    }
+
+    /// Returns the file size
+    fn finish(mut self) -> std::io::Result<u64> {
+        let data_len = self.samples.saturating_mul(2);
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.write_all(&wav_header(self.sample_rate, data_len))?;
+        self.file.flush()?;
+        Ok(44 + data_len as u64)
+    }
+}
+
+fn wav_header(sample_rate: u32, data_len: u32) -> [u8; 44] {
+    let mut header = [0u8; 44];
+    header[0..4].copy_from_slice(b"RIFF");
+    header[4..8].copy_from_slice(&(36 + data_len).to_le_bytes());
+    header[8..12].copy_from_slice(b"WAVE");
+    header[12..16].copy_from_slice(b"fmt ");
+    header[16..20].copy_from_slice(&16u32.to_le_bytes());
+    // PCM, one channel
+    header[20..22].copy_from_slice(&1u16.to_le_bytes());
+    header[22..24].copy_from_slice(&1u16.to_le_bytes());
+    header[24..28].copy_from_slice(&sample_rate.to_le_bytes());
+    header[28..32].copy_from_slice(&(sample_rate * 2).to_le_bytes());
+    header[32..34].copy_from_slice(&2u16.to_le_bytes());
+    header[34..36].copy_from_slice(&16u16.to_le_bytes());
+    header[36..40].copy_from_slice(b"data");
+    header[40..44].copy_from_slice(&data_len.to_le_bytes());
+    header
+}
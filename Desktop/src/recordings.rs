@@ -0,0 +1,349 @@
+//! Request recordings, for "what exactly did the app send upstream?".
+//!
+//! Debugging only and off unless `forwarding.record_requests` is set (read at
+//! startup; `GET miko://app/admin/health` says whether it is on). The last
+//! [`MAX_RECORDINGS`] requests the page had forwarded are kept with their
+//! headers and the first [`MAX_BODY_BYTES`] of both bodies; credentials are
+//! masked in what is listed: credential headers, query parameters and the
+//! [`SECRET_FIELDS`] of JSON bodies. Sign-ins (the route `login_guard`
+//! covers) are listed without either body and can't be replayed. `GET /admin/recordings` (`get_recordings`) lists
+//! them; `POST /admin/recordings/{id}/replay` (`replay_recording {id}`) sends
+//! one again with the current session and answers the new response next to
+//! the recorded one. Replays aren't recorded themselves.
+
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use http::{HeaderMap, Request, Response};
+use lazy_static::lazy_static;
+use serde::Serialize;
+use serde_json::{json, Value};
+use tracing::{info, warn};
+
+pub const ROUTE: &str = "/admin/recordings";
+pub const HEALTH_ROUTE: &str = "/admin/health";
+const MAX_RECORDINGS: usize = 50;
+const MAX_BODY_BYTES: usize = 64 * 1024;
+/// JSON fields masked in listed bodies, compared without case and `_`
+const SECRET_FIELDS: &[&str] = &["password", "token", "accesstoken", "refreshtoken", "idtoken", "secret", "apikey", "sessionid"];
+const MASK: &str = "***";
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Exchange {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    /// The first [`MAX_BODY_BYTES`], as text
+    pub body: String,
+    pub body_truncated: bool,
+    pub duration_ms: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Recording {
+    pub id: u64,
+    pub at: chrono::DateTime<chrono::Utc>,
+    pub method: String,
+    /// Path and query, credentials masked
+    pub path: String,
+    pub request_headers: Vec<(String, String)>,
+    pub request_body: String,
+    pub request_body_truncated: bool,
+    /// A sign-in: neither body was kept
+    pub bodies_omitted: bool,
+    pub response: Exchange,
+    /// What replaying sends; never listed
+    #[serde(skip)]
+    replay: Option<ReplayRequest>,
+}
+
+#[derive(Debug, Clone)]
+struct ReplayRequest {
+    method: http::Method,
+    path_and_query: String,
+    headers: HeaderMap,
+    body: Vec<u8>,
+}
+
+/// A page request on its way upstream, recorded once answered
+pub struct Capture {
+    started: Instant,
+    method: http::Method,
+    path_and_query: String,
+    headers: HeaderMap,
+    body: Vec<u8>,
+    body_truncated: bool,
+    /// A sign-in, whose bodies carry the password and the new token
+    auth: bool,
+}
+
+lazy_static! {
+    static ref ENABLED: bool = {
+        let enabled = crate::core::settings::get().forwarding.record_requests;
+        if enabled {
+            warn!("!!! forwarding.record_requests is set: forwarded requests are kept in memory for replay. Debugging only !!!");
+        }
+        enabled
+    };
+    static ref RECORDINGS: Mutex<VecDeque<Recording>> = Mutex::new(VecDeque::new());
+}
+
+pub fn enabled() -> bool {
+    *ENABLED
+}
+
+impl Capture {
+    /// Note `request` before it is forwarded; `None` while recording is off
+    pub fn start(request: &Request<Vec<u8>>) -> Option<Self> {
+        if !enabled() {
+            return None;
+        }
+        let auth = is_auth_route(request.uri().path());
+        let body: &[u8] = if auth { &[] } else { request.body() };
+        Some(Self {
+            started: Instant::now(),
+            method: request.method().clone(),
+            path_and_query: request.uri().path_and_query().map(|p| p.as_str()).unwrap_or("/").to_string(),
+            headers: request.headers().clone(),
+            body: body[..body.len().min(MAX_BODY_BYTES)].to_vec(),
+            body_truncated: body.len() > MAX_BODY_BYTES,
+            auth,
+        })
+    }
+
+    pub fn finish(self, response: &Response<Cow<'static, [u8]>>) {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+        let recording = Recording {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            at: chrono::Utc::now(),
+            method: self.method.to_string(),
+            path: crate::logging::redact_url(&self.path_and_query),
+            request_headers: listed_headers(&self.headers),
+            request_body: redacted(&self.body),
+            request_body_truncated: self.body_truncated,
+            bodies_omitted: self.auth,
+            response: exchange(response, self.started.elapsed(), self.auth),
+            // A cut-off body would replay as a different request, and a
+            // sign-in must not be sent again
+            replay: (!self.body_truncated && !self.auth).then_some(ReplayRequest {
+                method: self.method,
+                path_and_query: self.path_and_query,
+                headers: self.headers,
+                body: self.body,
+            }),
+        };
+        let mut recordings = RECORDINGS.lock().unwrap();
+        recordings.push_back(recording);
+        while recordings.len() > MAX_RECORDINGS {
+            recordings.pop_front();
+        }
+    }
+}
+
+fn is_auth_route(path: &str) -> bool {
+    path == crate::login_guard::LOGIN_PATH
+}
+
+fn is_secret(field: &str) -> bool {
+    let field: String = field.chars().filter(|&c| c != '_' && c != '-').collect::<String>().to_ascii_lowercase();
+    SECRET_FIELDS.contains(&field.as_str())
+}
+
+/// Mask the [`SECRET_FIELDS`] anywhere in `value`
+fn redact_json(value: &mut Value) {
+    match value {
+        Value::Object(fields) => {
+            for (field, value) in fields.iter_mut() {
+                if is_secret(field) && !value.is_null() {
+                    *value = Value::from(MASK);
+                } else {
+                    redact_json(value);
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(redact_json),
+        _ => {}
+    }
+}
+
+/// Mask `"field": "value"` pairs in text that isn't valid JSON (a body cut
+/// off at [`MAX_BODY_BYTES`]) and `field=value` pairs of a form body
+fn redact_text(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('"') {
+        let (before, after) = rest.split_at(start + 1);
+        out.push_str(before);
+        let Some(end) = after.find('"') else {
+            rest = after;
+            break;
+        };
+        let field = &after[..end];
+        out.push_str(field);
+        out.push('"');
+        rest = &after[end + 1..];
+        let value = rest.trim_start().strip_prefix(':').map(str::trim_start);
+        if let (true, Some(value)) = (is_secret(field), value.and_then(|v| v.strip_prefix('"'))) {
+            out.push_str(&rest[..rest.len() - value.len()]);
+            out.push_str(MASK);
+            // Up to the closing quote, or the end of a cut-off body
+            let mut escaped = false;
+            let close = value.char_indices().find(|&(_, c)| {
+                let closes = c == '"' && !escaped;
+                escaped = c == '\\' && !escaped;
+                closes
+            });
+            rest = match close {
+                Some((at, _)) => {
+                    out.push('"');
+                    &value[at + 1..]
+                }
+                None => "",
+            };
+        }
+    }
+    out.push_str(rest);
+    if !out.contains('{') && out.contains('=') {
+        return out
+            .split('&')
+            .map(|pair| match pair.split_once('=') {
+                Some((field, _)) if is_secret(field) => format!("{}={}", field, MASK),
+                _ => pair.to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join("&");
+    }
+    out
+}
+
+/// The first [`MAX_BODY_BYTES`] as text, secrets masked
+fn redacted(bytes: &[u8]) -> String {
+    let text = String::from_utf8_lossy(&bytes[..bytes.len().min(MAX_BODY_BYTES)]);
+    match serde_json::from_str::<Value>(&text) {
+        Ok(mut value) if value.is_object() || value.is_array() => {
+            redact_json(&mut value);
+            value.to_string()
+        }
+        _ => redact_text(&text),
+    }
+}
+
+fn listed_headers(headers: &HeaderMap) -> Vec<(String, String)> {
+    headers
+        .iter()
+        .map(|(name, value)| {
+            let value = String::from_utf8_lossy(value.as_bytes());
+            (name.to_string(), crate::logging::redact_header(name.as_str(), &value))
+        })
+        .collect()
+}
+
+fn exchange(response: &Response<Cow<'static, [u8]>>, elapsed: Duration, omit_body: bool) -> Exchange {
+    let body: &[u8] = if omit_body { &[] } else { response.body() };
+    Exchange {
+        status: response.status().as_u16(),
+        headers: listed_headers(response.headers()),
+        body: redacted(body),
+        body_truncated: body.len() > MAX_BODY_BYTES,
+        duration_ms: elapsed.as_millis() as u64,
+    }
+}
+
+/// `get_recordings` / `GET /admin/recordings`: oldest first
+pub fn list() -> Value {
+    if !enabled() {
+        return json!({ "success": false, "error": "request recording is off (forwarding.record_requests)" });
+    }
+    json!({ "success": true, "recordings": *RECORDINGS.lock().unwrap() })
+}
+
+/// `replay_recording {id}` / `POST /admin/recordings/{id}/replay`; blocks
+/// until the new response is in
+pub fn replay(id: u64) -> Value {
+    let Some(original) = RECORDINGS.lock().unwrap().iter().find(|r| r.id == id).cloned() else {
+        return json!({ "success": false, "id": id, "error": "no such recording" });
+    };
+    let Some(replay) = original.replay.clone() else {
+        return json!({ "success": false, "id": id, "error": "the request body was too large to record in full" });
+    };
+
+    let mut builder = Request::builder()
+        .method(replay.method)
+        .uri(format!("{}://app{}", crate::protocol::SCHEME, replay.path_and_query));
+    if let Some(headers) = builder.headers_mut() {
+        *headers = replay.headers;
+        // Fresh ids, so the server's log tells the two apart
+        headers.remove("x-request-id");
+        headers.remove("idempotency-key");
+    }
+    let request = match builder.body(replay.body) {
+        Ok(request) => request,
+        Err(e) => return json!({ "success": false, "id": id, "error": e.to_string() }),
+    };
+
+    info!("Replaying recorded request {} {} ({})", original.method, original.path, id);
+    let started = Instant::now();
+    let response = crate::protocol::replay(request);
+    json!({ "success": true, "id": id, "original": original, "replayed": exchange(&response, started.elapsed(), false) })
+}
+
+/// The recording id of a `/admin/recordings/{id}/replay` path
+pub fn replay_route(path: &str) -> Option<u64> {
+    path.strip_prefix(ROUTE)?.strip_prefix('/')?.strip_suffix("/replay")?.parse().ok()
+}
+
+/// `GET /admin/health`
+pub fn health() -> Value {
+    json!({
+        "success": true,
+        "recording": enabled(),
+        "recordings": RECORDINGS.lock().unwrap().len(),
+        "inflight": crate::inflight::count(),
+        "erpHealth": crate::core::health::restored(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_secrets_are_masked_at_any_depth() {
+        let body = br#"{"uid":"anna","password":"hunter2","session":{"access_token":"abc","refreshToken":"def"},"items":[{"apiKey":"k"}]}"#;
+        let listed: Value = serde_json::from_str(&redacted(body)).unwrap();
+        assert_eq!(listed["uid"], "anna");
+        assert_eq!(listed["password"], MASK);
+        assert_eq!(listed["session"]["access_token"], MASK);
+        assert_eq!(listed["session"]["refreshToken"], MASK);
+        assert_eq!(listed["items"][0]["apiKey"], MASK);
+    }
+
+    #[test]
+    fn cut_off_json_is_masked_as_text() {
+        let listed = redacted(br#"{"name":"a \"quoted\" b","token": "abc\"def","next":"x","accessToken":"cut of"#);
+        assert!(!listed.contains("abc") && !listed.contains("cut of"), "{}", listed);
+        assert!(listed.contains(r#""name":"a \"quoted\" b""#), "{}", listed);
+        assert!(listed.contains(r#""next":"x""#), "{}", listed);
+    }
+
+    #[test]
+    fn form_secrets_are_masked() {
+        assert_eq!(redacted(b"uid=anna&password=hunter2"), "uid=anna&password=***");
+    }
+
+    #[test]
+    fn other_bodies_are_kept() {
+        assert_eq!(redacted(b"plain text"), "plain text");
+        assert_eq!(redacted(br#"{"type":"token"}"#), r#"{"type":"token"}"#);
+    }
+
+    #[test]
+    fn only_sign_in_is_an_auth_route() {
+        assert!(is_auth_route(crate::login_guard::LOGIN_PATH));
+        assert!(!is_auth_route("/api/threads"));
+    }
+}
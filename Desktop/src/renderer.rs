@@ -0,0 +1,88 @@
+//! Recovering from renderer crashes.
+//!
+//! When the engine's web content process dies (GPU driver updates, OOM) the
+//! window goes blank. The platform layers report the failure here and reload
+//! the page after [`RELOAD_DELAY`]; the reloaded page gets `renderer-recovered`
+//! with its drafts. More than [`MAX_FAILURES`] failures within
+//! [`FAILURE_WINDOW`] means reloading won't help, so the user gets a dialog
+//! with diagnostics instead of a reload loop. The `renderer` state topic
+//! carries the current [`RendererState`].
+
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use lazy_static::lazy_static;
+use tracing::{error, info, warn};
+
+use crate::core::state::{self, RendererState, RendererStatus};
+
+pub const RELOAD_DELAY: Duration = Duration::from_secs(2);
+pub const MAX_FAILURES: usize = 3;
+pub const FAILURE_WINDOW: Duration = Duration::from_secs(10 * 60);
+
+lazy_static! {
+    static ref FAILURES: Mutex<VecDeque<Instant>> = Mutex::new(VecDeque::new());
+}
+
+/// What the platform layer should do about a failure
+#[derive(Debug)]
+pub enum Recovery {
+    /// Reload the page after [`RELOAD_DELAY`]
+    Reload,
+    /// Stop reloading and show this message
+    GiveUp(String),
+}
+
+/// Record a failure described by `kind` and decide how to recover
+pub fn failed(kind: &str) -> Recovery {
+    let failures = {
+        let mut failures = FAILURES.lock().unwrap();
+        let now = Instant::now();
+        failures.retain(|at| now.duration_since(*at) < FAILURE_WINDOW);
+        failures.push_back(now);
+        failures.len()
+    };
+
+    let give_up = failures > MAX_FAILURES;
+    state::set_renderer_status(RendererStatus {
+        state: if give_up { RendererState::Failed } else { RendererState::Recovering },
+        failures: failures as u32,
+        last_failure: Some(kind.to_string()),
+    });
+
+    if give_up {
+        error!("Renderer failed {} times in {} minutes ({}), not reloading again", failures, FAILURE_WINDOW.as_secs() / 60, kind);
+        return Recovery::GiveUp(format!(
+            "The window's content keeps crashing and was not reloaded again.\n\n\
+             Last failure: {}\nFailures in the last {} minutes: {}\nVersion: {}\n\n\
+             Please restart Workspace. If this keeps happening, export the \
+             diagnostic logs from the Help menu and send them to IT.",
+            kind,
+            FAILURE_WINDOW.as_secs() / 60,
+            failures,
+            env!("CARGO_PKG_VERSION"),
+        ));
+    }
+    warn!("Renderer failed ({}), reloading in {}s", kind, RELOAD_DELAY.as_secs());
+    Recovery::Reload
+}
+
+/// Wait out [`RELOAD_DELAY`] off the UI thread, then ask for the reload
+pub fn schedule_reload(reload: impl FnOnce() + Send + 'static) {
+    std::thread::spawn(move || {
+        std::thread::sleep(RELOAD_DELAY);
+        reload();
+    });
+}
+
+/// Called on every page load; tells a page reloaded after a failure
+pub fn page_loaded() {
+    let mut status = state::runtime().renderer.clone();
+    if status.state != RendererState::Recovering {
+        return;
+    }
+    info!("Page reloaded after a renderer failure");
+    status.state = RendererState::Running;
+    state::set_renderer_status(status);
+    crate::ipc::respond(None, "renderer-recovered", &crate::core::drafts::snapshot());
+}
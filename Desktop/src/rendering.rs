@@ -0,0 +1,116 @@
+//! Software rendering for machines whose GPU drivers break the webview.
+//!
+//! Some old drivers give black video or a corrupted window. With
+//! `rendering.disable_gpu` WebView2 starts with `--disable-gpu
+//! --disable-gpu-compositing` and WKWebView with accelerated drawing off.
+//! Browser arguments are fixed for the life of the browser process, so the
+//! mode is settled once per run and a change applies after a relaunch.
+//!
+//! WebView2 reports a crashed GPU process; after [`GPU_FAILURES_TO_ASK`] of
+//! them in one run the user is offered to switch and relaunch. WebKit has no
+//! such report, so on macOS the setting is only changed by hand. The mode in
+//! effect is part of the `system-info` answer.
+
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::OnceLock;
+use serde_json::{json, Value};
+use tracing::{error, info, warn};
+
+use crate::core::settings;
+
+/// GPU process crashes in one run before software rendering is offered
+pub const GPU_FAILURES_TO_ASK: u32 = 2;
+
+static GPU_FAILURES: AtomicU32 = AtomicU32::new(0);
+static RELAUNCH: AtomicBool = AtomicBool::new(false);
+
+/// Whether this run renders without the GPU; read from the settings the
+/// first time a webview is built
+pub fn gpu_disabled() -> bool {
+    static DISABLED: OnceLock<bool> = OnceLock::new();
+    *DISABLED.get_or_init(|| {
+        let disabled = settings::get().rendering.disable_gpu;
+        if disabled {
+            info!("GPU acceleration is off, the webview renders in software");
+        }
+        disabled
+    })
+}
+
+/// WebView2 arguments for the current mode, with a leading space
+pub fn browser_args() -> &'static str {
+    if gpu_disabled() {
+        " --disable-gpu --disable-gpu-compositing"
+    } else {
+        ""
+    }
+}
+
+/// From the platform layer when the webview's GPU process crashed
+pub fn gpu_process_failed() {
+    let failures = GPU_FAILURES.fetch_add(1, Ordering::SeqCst) + 1;
+    warn!("Webview GPU process failed ({} this run)", failures);
+    if failures != GPU_FAILURES_TO_ASK || gpu_disabled() {
+        return;
+    }
+    // The dialogs block, so not on the UI thread
+    std::thread::spawn(|| {
+        if !ask_fallback() {
+            info!("Software rendering declined");
+            return;
+        }
+        if let Err(e) = settings::update(|s| s.rendering.disable_gpu = true) {
+            error!("Failed to turn GPU acceleration off: {}", e);
+            return;
+        }
+        info!("GPU acceleration turned off after repeated GPU process failures, relaunching");
+        relaunch();
+    });
+}
+
+/// Quit and start again with the same arguments once the event loop is done
+fn relaunch() {
+    RELAUNCH.store(true, Ordering::SeqCst);
+    #[cfg(target_os = "windows")]
+    crate::platform::win::send_app_event(crate::platform::win::AppEvent::Quit);
+    #[cfg(target_os = "macos")]
+    crate::platform::mac::send_app_event(crate::platform::mac::AppEvent::Quit);
+}
+
+/// Called by the platform's `main` after the event loop has exited
+pub fn relaunch_if_requested() {
+    if !RELAUNCH.load(Ordering::SeqCst) {
+        return;
+    }
+    let result = std::env::current_exe()
+        .and_then(|exe| std::process::Command::new(exe).args(std::env::args_os().skip(1)).spawn());
+    if let Err(e) = result {
+        error!("Failed to relaunch: {}", e);
+    }
+}
+
+/// For `system_info`: the mode this run uses, and the one the next run will
+pub fn info() -> Value {
+    let disable_gpu = settings::get().rendering.disable_gpu;
+    json!({
+        "mode": if gpu_disabled() { "software" } else { "gpu" },
+        "disableGpu": disable_gpu,
+        "relaunchPending": disable_gpu != gpu_disabled(),
+        "gpuProcessFailures": GPU_FAILURES.load(Ordering::SeqCst),
+    })
+}
+
+#[cfg(target_os = "windows")]
+fn ask_fallback() -> bool {
+    crate::platform::win::utils::confirm(
+        "Display problems",
+        "The graphics driver keeps crashing the window's content. Switch to software \
+         rendering and restart Workspace?\n\nVideo may play less smoothly.",
+    )
+}
+
+// Only WebView2 reports GPU process failures
+#[cfg(not(target_os = "windows"))]
+fn ask_fallback() -> bool {
+    false
+}
@@ -0,0 +1,274 @@
+//! Safe mode: a launch that ignores the state which may be keeping the app
+//! from starting (`--safe-mode`, or Shift held while it starts).
+//!
+//! While active, settings are the defaults and are never written back, the
+//! bundled page is served uncached, the saved thread isn't restored and the
+//! webview only gets the sync bootstrap script. Before the window opens, a
+//! dialog lists what was skipped and offers the resets below one by one.
+//! Each reset works on the files directly (the in-memory settings are the
+//! defaults by then) and succeeds when there was nothing left to remove.
+
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use serde_json::Value;
+use tracing::{info, warn};
+
+use crate::core::settings;
+
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+
+/// Shown in the startup dialog
+pub const SKIPPED: &[&str] = &[
+    "Saved settings (defaults are used and nothing is saved)",
+    "Cached app files (the page is loaded fresh)",
+    "The thread that was open last time",
+    "Spellcheck, media and download helpers in the page",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reset {
+    ClearWebData,
+    DeleteSessions,
+    RestoreDefaults,
+}
+
+impl Reset {
+    /// In the order they are offered and run: clearing web data still needs
+    /// the profile location from the settings file
+    pub const ALL: [Reset; 3] = [Reset::ClearWebData, Reset::DeleteSessions, Reset::RestoreDefaults];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Reset::ClearWebData => "Clear web data",
+            Reset::DeleteSessions => "Delete sessions",
+            Reset::RestoreDefaults => "Reset settings",
+        }
+    }
+
+    fn description(self) -> &'static str {
+        match self {
+            Reset::ClearWebData => "Clear web data? Cookies, storage and cached files are deleted and you will need to sign in again.",
+            Reset::DeleteSessions => "Delete sessions? The thread and scroll position saved for each account are forgotten.",
+            Reset::RestoreDefaults => "Reset settings? Every setting goes back to its default.",
+        }
+    }
+
+    pub fn run(self) -> std::io::Result<()> {
+        match self {
+            Reset::ClearWebData => clear_web_data(),
+            Reset::DeleteSessions => delete_sessions(),
+            Reset::RestoreDefaults => reset_settings(),
+        }
+    }
+}
+
+/// Turn safe mode on; must happen before the settings are first read
+pub fn enable() {
+    ACTIVE.store(true, Ordering::SeqCst);
+    warn!("Starting in safe mode");
+}
+
+pub fn active() -> bool {
+    ACTIVE.load(Ordering::SeqCst)
+}
+
+/// Shift held at launch asks for safe mode too
+pub fn requested_by_keyboard() -> bool {
+    #[cfg(target_os = "windows")]
+    {
+        crate::platform::win::utils::shift_held()
+    }
+    #[cfg(target_os = "macos")]
+    {
+        crate::platform::mac::utils::shift_held()
+    }
+    #[cfg(not(any(target_os = "windows", target_os = "macos")))]
+    {
+        false
+    }
+}
+
+/// Tell the user what was skipped and run the resets they pick
+pub fn run_startup_dialog() {
+    let skipped = SKIPPED.iter().map(|item| format!("• {}", item)).collect::<Vec<_>>().join("\n");
+    for reset in choose_resets(&format!("Workspace started in safe mode. Skipped:\n\n{}", skipped)) {
+        match reset.run() {
+            Ok(()) => info!("Safe mode: {} done", reset.label()),
+            Err(e) => {
+                warn!("Safe mode: {} failed: {}", reset.label(), e);
+                show_message(&format!("{} failed: {}", reset.label(), e));
+            }
+        }
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn choose_resets(summary: &str) -> Vec<Reset> {
+    use crate::platform::win::utils;
+
+    utils::show_message("Workspace (Safe Mode)", summary);
+    Reset::ALL.into_iter().filter(|reset| utils::confirm("Workspace (Safe Mode)", reset.description())).collect()
+}
+
+#[cfg(target_os = "macos")]
+fn choose_resets(summary: &str) -> Vec<Reset> {
+    let labels = Reset::ALL.map(Reset::label);
+    let prompt = format!("{}\n\nSelect anything to reset:", summary);
+    let picked = crate::platform::mac::utils::choose_from_list("Workspace (Safe Mode)", &prompt, &labels, "Continue", "Skip")
+        .unwrap_or_default();
+    Reset::ALL.into_iter().filter(|reset| picked.iter().any(|label| label == reset.label())).collect()
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+fn choose_resets(summary: &str) -> Vec<Reset> {
+    eprintln!("{}", summary);
+    Vec::new()
+}
+
+fn show_message(text: &str) {
+    #[cfg(target_os = "windows")]
+    crate::platform::win::utils::show_message("Workspace (Safe Mode)", text);
+    #[cfg(target_os = "macos")]
+    crate::platform::mac::utils::show_message("Workspace (Safe Mode)", text);
+    #[cfg(not(any(target_os = "windows", target_os = "macos")))]
+    eprintln!("{}", text);
+}
+
+/// The settings file as stored, if readable
+fn stored_settings(path: &Path) -> Option<Value> {
+    let text = std::fs::read_to_string(path).ok()?;
+    serde_json::from_str::<Value>(&text).ok().filter(|doc| doc.is_object())
+}
+
+fn remove_file(path: &Path) -> std::io::Result<()> {
+    match std::fs::remove_file(path) {
+        Err(e) if e.kind() != ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+fn remove_dir(path: &Path) -> std::io::Result<()> {
+    match std::fs::remove_dir_all(path) {
+        Err(e) if e.kind() != ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+/// Delete `settings.json`; the next normal start writes a fresh one
+pub fn reset_settings() -> std::io::Result<()> {
+    remove_file(&settings::settings_path())
+}
+
+/// Drop every account's saved place (`core::session_state`) from the settings file
+pub fn delete_sessions() -> std::io::Result<()> {
+    delete_sessions_from(&settings::settings_path())
+}
+
+fn delete_sessions_from(path: &Path) -> std::io::Result<()> {
+    let Some(mut doc) = stored_settings(path) else { return Ok(()) };
+    let Some(object) = doc.as_object_mut() else { return Ok(()) };
+    if object.remove("session_state").is_none() {
+        return Ok(());
+    }
+    let bytes = serde_json::to_vec_pretty(&doc).map_err(std::io::Error::other)?;
+    crate::core::write_atomic(path, &bytes)
+}
+
+/// Browser profiles to delete: the WebView2 user data folder on Windows,
+/// WebKit's website data and caches on macOS
+fn web_data_dirs() -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    if cfg!(target_os = "windows") {
+        let configured = stored_settings(&settings::settings_path())
+            .and_then(|doc| doc.get("webview2_user_data_dir").and_then(Value::as_str).map(PathBuf::from));
+        dirs.push(crate::paths::webview_user_data_dir(configured.as_deref()));
+    }
+    if cfg!(target_os = "macos") {
+        if let Some(home) = std::env::var_os("HOME").map(PathBuf::from) {
+            dirs.push(home.join("Library/WebKit/com.workspace.desktop"));
+            dirs.push(home.join("Library/Caches/com.workspace.desktop"));
+        }
+    }
+    dirs
+}
+
+/// Delete the webview's cookies, storage and cache; must run before the webview exists
+pub fn clear_web_data() -> std::io::Result<()> {
+    for dir in web_data_dirs() {
+        remove_dir(&dir)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    struct Fixture(PathBuf);
+
+    impl Fixture {
+        fn new() -> Self {
+            let dir = std::env::temp_dir().join(format!("miko-safe-mode-{}", uuid::Uuid::new_v4().simple()));
+            std::fs::create_dir_all(&dir).unwrap();
+            Self(dir)
+        }
+    }
+
+    impl Drop for Fixture {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn resetting_settings_twice_is_fine() {
+        let fixture = Fixture::new();
+        let path = fixture.0.join("settings.json");
+        std::fs::write(&path, "{}").unwrap();
+        remove_file(&path).unwrap();
+        assert!(!path.exists());
+        remove_file(&path).unwrap();
+        // Anything but "already gone" is still an error
+        assert!(remove_file(&fixture.0).is_err());
+    }
+
+    #[test]
+    fn deleting_sessions_twice_is_fine() {
+        let fixture = Fixture::new();
+        let path = fixture.0.join("settings.json");
+        let stored = json!({
+            "log_level": "debug",
+            "session_state": { "u1": { "thread_id": "t1", "scroll_offset": 120 } },
+        });
+        std::fs::write(&path, serde_json::to_vec(&stored).unwrap()).unwrap();
+
+        delete_sessions_from(&path).unwrap();
+        let after = std::fs::read(&path).unwrap();
+        assert_eq!(serde_json::from_slice::<Value>(&after).unwrap(), json!({ "log_level": "debug" }));
+        delete_sessions_from(&path).unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), after, "nothing left to delete, so nothing is written");
+
+        // Unreadable or missing files are left alone
+        std::fs::write(&path, "{ not json").unwrap();
+        delete_sessions_from(&path).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "{ not json");
+        std::fs::remove_file(&path).unwrap();
+        delete_sessions_from(&path).unwrap();
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn clearing_web_data_twice_is_fine() {
+        let fixture = Fixture::new();
+        let profile = fixture.0.join("EBWebView");
+        std::fs::create_dir_all(profile.join("Default/Local Storage")).unwrap();
+        std::fs::write(profile.join("Default/Cookies"), b"cookies").unwrap();
+
+        remove_dir(&profile).unwrap();
+        assert!(!profile.exists());
+        remove_dir(&profile).unwrap();
+        assert!(fixture.0.exists());
+    }
+}
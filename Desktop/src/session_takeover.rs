@@ -0,0 +1,312 @@
+//! "Signed in on another device" instead of a wall of 401s.
+//!
+//! The ERP drops a user's old token when the same account signs in
+//! elsewhere. When a forwarded request is rejected with 401 although the
+//! session answered fine within the last [`HEALTHY_FOR`], `GET /api/auth/me`
+//! tells a rejected token from a route that refused this one request. If the
+//! token is gone, the ERP's marks decide how: an `X-Session-Ended: replaced`
+//! or `X-Session-Replaced-At` header, or `"reason": "session_replaced"` (and
+//! `replacedAt`) in the JSON body, on the first 401 or on the check.
+//!
+//! A takeover emits `session-takeover {replacedAt?, device?}` and asks
+//! "Reconnect here?" natively; "Sign in again" emits `relogin-requested`
+//! for the page to run its sign-in. Anything else emits `session-expired`.
+//! Either is reported once until a request succeeds again. [`verify`] runs
+//! the same check when the ERP comes back from maintenance.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use http::HeaderMap;
+use lazy_static::lazy_static;
+use serde_json::{json, Value};
+use tracing::{info, warn};
+
+const WHOAMI_PATH: &str = "/api/auth/me";
+const HEALTHY_FOR: Duration = Duration::from_secs(10 * 60);
+const ENDED_HEADER: &str = "x-session-ended";
+const REPLACED_AT_HEADER: &str = "x-session-replaced-at";
+const DEVICE_HEADER: &str = "x-session-replaced-by";
+
+lazy_static! {
+    /// When a request last got through with the session's token
+    static ref LAST_HEALTHY: Mutex<Option<Instant>> = Mutex::new(None);
+}
+
+/// A check (or its dialog) is running
+static CHECKING: AtomicBool = AtomicBool::new(false);
+
+/// What a 401 said about why
+#[derive(Debug, Default, Clone)]
+struct Rejection {
+    replaced: bool,
+    replaced_at: Option<chrono::DateTime<chrono::Utc>>,
+    device: Option<String>,
+}
+
+impl Rejection {
+    fn read(headers: &HeaderMap, body: &[u8]) -> Self {
+        let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok()).map(|v| v.trim().to_string());
+        let body: Value = serde_json::from_slice(body).unwrap_or(Value::Null);
+        // Flat, or nested in an `error` object
+        let field = |name: &str| body[name].as_str().or_else(|| body["error"][name].as_str()).map(|v| v.to_string());
+
+        let replaced_at = header(REPLACED_AT_HEADER)
+            .or_else(|| field("replacedAt"))
+            .and_then(|at| chrono::DateTime::parse_from_rfc3339(&at).ok())
+            .map(|at| at.with_timezone(&chrono::Utc));
+        Self {
+            replaced: header(ENDED_HEADER).is_some_and(|v| v.eq_ignore_ascii_case("replaced"))
+                || field("reason").as_deref() == Some("session_replaced")
+                || replaced_at.is_some(),
+            replaced_at,
+            device: header(DEVICE_HEADER).or_else(|| field("device")),
+        }
+    }
+
+    fn merge(self, other: Self) -> Self {
+        Self {
+            replaced: self.replaced || other.replaced,
+            replaced_at: self.replaced_at.or(other.replaced_at),
+            device: self.device.or(other.device),
+        }
+    }
+}
+
+fn signed_in() -> bool {
+    crate::core::state::runtime().auth.signed_in
+}
+
+/// A forwarded request was answered successfully
+pub fn authorized() {
+    if signed_in() {
+        *LAST_HEALTHY.lock().unwrap() = Some(Instant::now());
+    }
+}
+
+/// A forwarded request to `path` was answered 401 with `headers` and `body`
+pub fn rejected(path: &str, headers: &HeaderMap, body: &[u8]) {
+    if path == WHOAMI_PATH || !signed_in() {
+        return;
+    }
+    // Only a session that worked a moment ago was taken over; report it once
+    let recently_healthy = LAST_HEALTHY.lock().unwrap().take().is_some_and(|at| at.elapsed() < HEALTHY_FOR);
+    if !recently_healthy || CHECKING.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    let first = Rejection::read(headers, body);
+    std::thread::spawn(move || {
+        check(first);
+        CHECKING.store(false, Ordering::SeqCst);
+    });
+}
+
+/// Check the session now, reporting it like a 401 would; false when it
+/// ended (or nobody is signed in)
+pub fn verify() -> bool {
+    if !signed_in() {
+        return false;
+    }
+    if CHECKING.swap(true, Ordering::SeqCst) {
+        return true;
+    }
+    let valid = check(Rejection::default());
+    CHECKING.store(false, Ordering::SeqCst);
+    valid
+}
+
+/// Returns false when the token was rejected
+fn check(first: Rejection) -> bool {
+    let response = crate::protocol::get(WHOAMI_PATH);
+    let status = response.status();
+    if status.is_success() {
+        // The token is fine; that one route refused it
+        authorized();
+        return true;
+    }
+    if status != http::StatusCode::UNAUTHORIZED {
+        warn!("Could not check the session: {}", status);
+        return true;
+    }
+
+    let rejection = first.merge(Rejection::read(response.headers(), response.body()));
+    if !rejection.replaced {
+        info!("Session token expired");
+        emit("session-expired", json!({}));
+        return false;
+    }
+
+    info!("Session taken over by another sign-in at {:?}", rejection.replaced_at);
+    emit(
+        "session-takeover",
+        json!({ "replacedAt": rejection.replaced_at.map(|at| at.to_rfc3339()), "device": rejection.device }),
+    );
+    let when = rejection
+        .replaced_at
+        .map(|at| format!(" at {}", at.with_timezone(&chrono::Local).format("%H:%M")))
+        .unwrap_or_default();
+    let message = format!("Your account signed in on another device{}. Reconnect here?", when);
+    if ask_reconnect(&message) {
+        info!("Signing in again after the takeover");
+        emit("relogin-requested", json!({ "reason": "session_takeover" }));
+    }
+    false
+}
+
+fn emit(event: &str, payload: Value) {
+    if let Some(webview) = crate::ipc::handle() {
+        webview.emit(event, &payload);
+    }
+    crate::ipc::wake();
+}
+
+#[cfg(target_os = "windows")]
+fn ask_reconnect(message: &str) -> bool {
+    // Yes signs in again
+    crate::platform::win::utils::confirm("Signed in elsewhere", message)
+}
+
+#[cfg(target_os = "macos")]
+fn ask_reconnect(message: &str) -> bool {
+    crate::platform::mac::utils::confirm("Signed in elsewhere", message, "Sign in again", "Not now")
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+fn ask_reconnect(_message: &str) -> bool {
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::Arc;
+    use http::HeaderValue;
+    use crate::testing::Reply;
+
+    fn headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+        pairs.iter().map(|(name, value)| (http::HeaderName::from_static(name), HeaderValue::from_str(value).unwrap())).collect()
+    }
+
+    #[test]
+    fn takeover_marks_come_from_headers_or_body() {
+        let at = "2026-10-16T07:02:00Z";
+        let table = [
+            (headers(&[]), json!({ "success": false }), false, None, None),
+            (headers(&[(ENDED_HEADER, " Replaced ")]), Value::Null, true, None, None),
+            (headers(&[(ENDED_HEADER, "expired")]), Value::Null, false, None, None),
+            (headers(&[(REPLACED_AT_HEADER, at), (DEVICE_HEADER, "Laptop")]), Value::Null, true, Some(at), Some("Laptop")),
+            (headers(&[]), json!({ "reason": "session_replaced", "device": "Phone" }), true, None, Some("Phone")),
+            (headers(&[]), json!({ "error": { "reason": "session_replaced", "replacedAt": at } }), true, Some(at), None),
+            (headers(&[(REPLACED_AT_HEADER, "yesterday")]), json!({ "reason": "expired" }), false, None, None),
+        ];
+        for (headers, body, replaced, replaced_at, device) in table {
+            let rejection = Rejection::read(&headers, body.to_string().as_bytes());
+            assert_eq!(rejection.replaced, replaced, "{:?} {}", headers, body);
+            assert_eq!(rejection.replaced_at, replaced_at.map(|at| at.parse().unwrap()), "{:?} {}", headers, body);
+            assert_eq!(rejection.device.as_deref(), device, "{:?} {}", headers, body);
+        }
+    }
+
+    /// Forward a request to `path` and wait for the check it may start
+    fn request(path: &str) {
+        crate::protocol::get(path);
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while CHECKING.load(Ordering::SeqCst) {
+            assert!(Instant::now() < deadline, "the check never finished");
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn a_401_after_a_healthy_session_is_checked_and_reported_once() {
+        let _session = crate::testing::session(true);
+        crate::testing::upstream();
+        crate::testing::emitted("");
+        let checks = Arc::new(AtomicUsize::new(0));
+        let whoami = Arc::new(Mutex::new((200, json!({ "id": "test-user" }))));
+        let (counted, answer) = (checks.clone(), whoami.clone());
+        crate::testing::route(WHOAMI_PATH, move |_| {
+            counted.fetch_add(1, Ordering::SeqCst);
+            let (status, body) = answer.lock().unwrap().clone();
+            Reply::json(status, body)
+        });
+        crate::testing::route("/t/takeover/", |request| match request.path.as_str() {
+            "/t/takeover/ok" => Reply::json(200, json!({ "success": true })),
+            "/t/takeover/replaced" => Reply::Respond {
+                status: 401,
+                headers: vec![
+                    ("Content-Type", "application/json".to_string()),
+                    ("X-Session-Ended", "replaced".to_string()),
+                    ("X-Session-Replaced-By", "Chrome on Windows".to_string()),
+                ],
+                body: br#"{"success":false}"#.to_vec(),
+            },
+            _ => Reply::json(401, json!({ "success": false, "message": "Unauthorized" })),
+        });
+        let set_whoami = |status: u16, body: Value| *whoami.lock().unwrap() = (status, body);
+
+        // Not known to have worked lately: nothing to tell apart
+        *LAST_HEALTHY.lock().unwrap() = None;
+        request("/t/takeover/denied");
+        assert_eq!(checks.load(Ordering::SeqCst), 0);
+
+        // The token works; only that route refused it
+        request("/t/takeover/ok");
+        request("/t/takeover/denied");
+        assert_eq!(checks.load(Ordering::SeqCst), 1);
+        assert!(crate::testing::emitted("session-expired").is_empty());
+
+        // Expired without a mark, reported once
+        set_whoami(401, json!({ "success": false }));
+        request("/t/takeover/denied");
+        request("/t/takeover/denied");
+        assert_eq!(checks.load(Ordering::SeqCst), 2);
+        assert_eq!(crate::testing::emitted("session-expired"), [json!({})]);
+
+        // Taken over: the marks of the 401 and of the check are merged
+        set_whoami(401, json!({ "error": { "reason": "session_replaced", "replacedAt": "2026-10-16T07:02:00Z" } }));
+        request("/t/takeover/ok");
+        request("/t/takeover/replaced");
+        request("/t/takeover/replaced");
+        assert_eq!(checks.load(Ordering::SeqCst), 3);
+        assert_eq!(
+            crate::testing::emitted("session-takeover"),
+            [json!({ "replacedAt": "2026-10-16T07:02:00+00:00", "device": "Chrome on Windows" })]
+        );
+        // Nobody said "Sign in again"
+        assert!(crate::testing::emitted("relogin-requested").is_empty());
+        assert!(crate::testing::emitted("session-expired").is_empty());
+
+        // Back from maintenance
+        assert!(!verify());
+        assert_eq!(crate::testing::emitted("session-takeover").len(), 1);
+        set_whoami(200, json!({ "id": "test-user" }));
+        assert!(verify());
+        assert_eq!(checks.load(Ordering::SeqCst), 5);
+
+        // Nobody signed in, nothing to check
+        crate::core::state::runtime().auth.signed_in = false;
+        assert!(!verify());
+        request("/t/takeover/ok");
+        request("/t/takeover/denied");
+        assert_eq!(checks.load(Ordering::SeqCst), 5);
+    }
+
+    #[test]
+    fn a_token_the_erp_revoked_is_reported_as_expired() {
+        let session = crate::testing::session(true);
+        session.use_mock_erp();
+        crate::testing::emitted("session-expired");
+
+        request("/api/chats");
+        crate::core::state::runtime().auth.token = Some("revoked".to_string());
+        request("/api/chats");
+        assert_eq!(crate::testing::emitted("session-expired"), [json!({})]);
+        assert!(crate::testing::emitted("session-takeover").is_empty());
+
+        // Signed in again
+        crate::core::state::runtime().auth.token = Some(crate::testing::mock_erp::TOKEN.to_string());
+        assert!(verify());
+    }
+}
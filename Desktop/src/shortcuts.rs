@@ -0,0 +1,242 @@
+//! Keyboard shortcuts for the app's own commands.
+//!
+//! [`DEFAULTS`] binds each command to an accelerator; the user's overrides in
+//! `shortcuts` in the settings are merged over them, an empty string unbinding
+//! the command. The platform layers build their native accelerators (the
+//! Win32 accelerator table behind the menu bar) from [`current`] and rebuild
+//! them when [`take_menu_update`] reports a change. Editing, zoom and DevTools
+//! keys are handled by the engine ([`BUILT_IN`]) and can't be rebound.
+//!
+//! Accelerators are written like `Ctrl+Shift+N`: modifiers in the order Ctrl,
+//! Alt, Shift, then a single key.
+
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use serde::Serialize;
+use serde_json::{json, Value};
+use tracing::{info, warn};
+
+use crate::core::settings;
+
+/// Rebindable commands: menu action, label, default accelerator
+pub const DEFAULTS: &[(&str, &str, &str)] = &[
+    ("new_chat", "New Chat", "Ctrl+N"),
+    ("new_window", "New Window", "Ctrl+Shift+N"),
+    ("open_workspace", "Open Workspace", "Ctrl+O"),
+    ("print_page", "Print…", "Ctrl+P"),
+    ("settings", "Settings", "Ctrl+,"),
+    ("toggle_sidebar", "Toggle Sidebar", "Ctrl+B"),
+    ("toggle_chat_list", "Toggle Chat List", "Ctrl+1"),
+    ("fullscreen", "Full Screen", "F11"),
+    ("shortcuts", "Keyboard Shortcuts", "Ctrl+/"),
+    ("snap:left-half", "Left Half", "Alt+Shift+Left"),
+    ("snap:right-half", "Right Half", "Alt+Shift+Right"),
+    ("snap:right-third", "Right Third", "Alt+Shift+End"),
+    ("snap:centered", "Centered", "Alt+Shift+Up"),
+    ("snap:last-saved", "Last Saved Layout", "Alt+Shift+Down"),
+];
+
+/// Keys the engine or the system handles; listed so nothing is bound over them
+pub const BUILT_IN: &[(&str, &str)] = &[
+    ("Ctrl+Z", "Undo"),
+    ("Ctrl+Y", "Redo"),
+    ("Ctrl+X", "Cut"),
+    ("Ctrl+C", "Copy"),
+    ("Ctrl+V", "Paste"),
+    ("Ctrl+A", "Select All"),
+    ("Ctrl+F", "Find"),
+    ("Ctrl++", "Zoom In"),
+    ("Ctrl+-", "Zoom Out"),
+    ("Ctrl+0", "Reset Zoom"),
+    ("F12", "Toggle DevTools"),
+    ("Alt+F4", "Exit"),
+];
+
+const PUNCTUATION: &str = ",-+./;'[]\\`";
+const NAMED_KEYS: &[&str] = &[
+    "Enter", "Tab", "Space", "Backspace", "Delete", "Insert", "Home", "End", "PageUp", "PageDown", "Up", "Down", "Left", "Right",
+];
+
+/// Set when a shortcut changes; the event loop takes it to update the menu
+static MENU_DIRTY: AtomicBool = AtomicBool::new(false);
+
+/// A parsed accelerator; `Display` gives the normalized form
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Accelerator {
+    pub ctrl: bool,
+    pub alt: bool,
+    pub shift: bool,
+    /// Uppercase letter or digit, punctuation, `F1`–`F24` or one of the named keys
+    pub key: String,
+}
+
+impl Accelerator {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let text = text.trim();
+        // `Ctrl++` binds the plus key itself
+        let (modifiers, key) = match text.strip_suffix("++") {
+            Some(rest) => (rest, "+"),
+            None => text.rsplit_once('+').unwrap_or(("", text)),
+        };
+
+        let mut accelerator = Self { ctrl: false, alt: false, shift: false, key: String::new() };
+        for modifier in modifiers.split('+').filter(|m| !m.is_empty()) {
+            match modifier.to_ascii_lowercase().as_str() {
+                "ctrl" | "control" | "cmdorctrl" => accelerator.ctrl = true,
+                "alt" | "option" => accelerator.alt = true,
+                "shift" => accelerator.shift = true,
+                _ => return Err(format!("unknown modifier {:?} in {:?}", modifier, text)),
+            }
+        }
+        accelerator.key = normalize_key(key).ok_or_else(|| format!("unknown key {:?} in {:?}", key, text))?;
+
+        // Anything else would swallow ordinary typing
+        if !accelerator.ctrl && !accelerator.alt && !accelerator.is_function_key() {
+            return Err(format!("{} needs Ctrl or Alt", accelerator));
+        }
+        Ok(accelerator)
+    }
+
+    pub fn is_function_key(&self) -> bool {
+        self.key.len() > 1 && self.key.starts_with('F') && self.key[1..].chars().all(|c| c.is_ascii_digit())
+    }
+}
+
+impl fmt::Display for Accelerator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.ctrl {
+            f.write_str("Ctrl+")?;
+        }
+        if self.alt {
+            f.write_str("Alt+")?;
+        }
+        if self.shift {
+            f.write_str("Shift+")?;
+        }
+        f.write_str(&self.key)
+    }
+}
+
+fn normalize_key(key: &str) -> Option<String> {
+    let mut chars = key.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        return match c {
+            c if c.is_ascii_alphanumeric() => Some(c.to_ascii_uppercase().to_string()),
+            // Same key as `+` on the layouts we ship for
+            '=' => Some("+".to_string()),
+            c if PUNCTUATION.contains(c) => Some(c.to_string()),
+            _ => None,
+        };
+    }
+    if let Some(n) = key.strip_prefix(['F', 'f']).and_then(|n| n.parse::<u8>().ok()) {
+        return (1..=24).contains(&n).then(|| format!("F{}", n));
+    }
+    NAMED_KEYS.iter().find(|k| k.eq_ignore_ascii_case(key)).map(|k| k.to_string())
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Shortcut {
+    pub action: String,
+    pub label: String,
+    /// `None` when the user unbound the command
+    pub accelerator: Option<String>,
+    pub default: String,
+}
+
+/// The defaults merged with the user's overrides; invalid overrides from a
+/// hand-edited settings file fall back to the default
+pub fn current() -> Vec<Shortcut> {
+    let overrides = settings::get().shortcuts;
+    DEFAULTS
+        .iter()
+        .map(|(action, label, default)| {
+            let accelerator = match overrides.get(*action) {
+                Some(text) if text.is_empty() => None,
+                Some(text) => match Accelerator::parse(text) {
+                    Ok(accelerator) => Some(accelerator.to_string()),
+                    Err(e) => {
+                        warn!("Ignoring shortcut for {}: {}", action, e);
+                        Some(default.to_string())
+                    }
+                },
+                None => Some(default.to_string()),
+            };
+            Shortcut { action: action.to_string(), label: label.to_string(), accelerator, default: default.to_string() }
+        })
+        .collect()
+}
+
+/// The command's label with its accelerator, as a Win32 menu item text
+pub fn menu_text(action: &str) -> String {
+    match current().into_iter().find(|s| s.action == action) {
+        Some(Shortcut { label, accelerator: Some(accelerator), .. }) => format!("{}\t{}", label, accelerator),
+        Some(shortcut) => shortcut.label,
+        None => action.to_string(),
+    }
+}
+
+/// Handle `set_shortcut`: `None` restores the default, an empty string
+/// unbinds the command. Fails if the accelerator is built in or taken by
+/// another command.
+pub fn set(action: &str, accelerator: Option<&str>) -> Result<Vec<Shortcut>, String> {
+    let (_, _, default) = DEFAULTS
+        .iter()
+        .find(|(a, _, _)| *a == action)
+        .ok_or_else(|| format!("unknown shortcut action: {}", action))?;
+    let wanted = match accelerator {
+        None => Some(Accelerator::parse(default)?),
+        Some(text) if text.trim().is_empty() => None,
+        Some(text) => Some(Accelerator::parse(text)?),
+    };
+
+    if let Some(wanted) = &wanted {
+        let text = wanted.to_string();
+        if let Some((_, name)) = BUILT_IN.iter().find(|(built_in, _)| *built_in == text) {
+            return Err(format!("{} is reserved for {}", text, name));
+        }
+        if let Some(other) = current().into_iter().find(|s| s.action != action && s.accelerator.as_deref() == Some(text.as_str())) {
+            return Err(format!("{} is already used by {}", text, other.label));
+        }
+    }
+
+    settings::update(|s| match accelerator {
+        None => {
+            s.shortcuts.remove(action);
+        }
+        Some(_) => {
+            s.shortcuts.insert(action.to_string(), wanted.as_ref().map(|a| a.to_string()).unwrap_or_default());
+        }
+    })
+    .map_err(|e| format!("failed to save shortcut: {}", e))?;
+    info!("Shortcut for {} set to {}", action, wanted.as_ref().map(|a| a.to_string()).unwrap_or_else(|| "nothing".to_string()));
+
+    MENU_DIRTY.store(true, Ordering::SeqCst);
+    crate::ipc::wake();
+    Ok(current())
+}
+
+/// Result of `get_shortcuts`
+pub fn snapshot() -> Value {
+    let built_in: Vec<Value> = BUILT_IN.iter().map(|(accelerator, label)| json!({ "accelerator": accelerator, "label": label })).collect();
+    json!({ "success": true, "shortcuts": current(), "builtIn": built_in })
+}
+
+/// The live map as text for the Help > Keyboard Shortcuts dialog
+pub fn describe() -> String {
+    let mut text = String::new();
+    for shortcut in current() {
+        let accelerator = shortcut.accelerator.as_deref().unwrap_or("(none)");
+        text.push_str(&format!("{}\t{}\n", accelerator, shortcut.label));
+    }
+    text.push('\n');
+    for (accelerator, label) in BUILT_IN {
+        text.push_str(&format!("{}\t{}\n", accelerator, label));
+    }
+    text
+}
+
+/// Returns true if a shortcut changed since the last call
+pub fn take_menu_update() -> bool {
+    MENU_DIRTY.swap(false, Ordering::SeqCst)
+}
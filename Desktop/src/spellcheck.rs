@@ -0,0 +1,116 @@
+//! Spellcheck in the message composer.
+//!
+//! Neither engine exposes a spellcheck switch to the host, so `enabled` is
+//! applied in the page through the inherited `spellcheck` attribute on the
+//! root element, at every load and immediately on change. Languages and the
+//! custom dictionary are platform matters: the platform layers pass in how to
+//! add words to the system dictionary the engine checks against.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use tracing::{info, warn};
+
+use crate::core::settings::{self, SpellcheckSettings};
+
+/// Languages offered in the Edit > Spelling menu
+pub const MENU_LANGUAGES: &[(&str, &str)] = &[("en-US", "English (US)"), ("en-GB", "English (UK)"), ("th-TH", "Thai")];
+
+const MAX_WORD_LEN: usize = 64;
+
+/// Set when the settings change; the event loop takes it to update the menu
+static MENU_DIRTY: AtomicBool = AtomicBool::new(false);
+
+/// Adds `words` to the system dictionary for each of `languages`
+pub type LearnWords = fn(&[String], &[String]) -> Result<(), String>;
+
+pub fn current() -> SpellcheckSettings {
+    settings::get().spellcheck
+}
+
+fn apply_script(enabled: bool) -> String {
+    format!(
+        "(function () {{ var apply = function () {{ document.documentElement.spellcheck = {}; }}; \
+         if (document.documentElement) apply(); else document.addEventListener('DOMContentLoaded', apply); }})();",
+        enabled
+    )
+}
+
+/// Initialization script applying the saved `enabled` state on every load
+pub fn init_script() -> String {
+    apply_script(current().enabled)
+}
+
+/// Handle `set_spellcheck`; `None` leaves a field unchanged. Custom words are
+/// re-added with `learn` when the languages change.
+pub fn set(enabled: Option<bool>, languages: Option<Vec<String>>, learn: LearnWords) -> Result<SpellcheckSettings, String> {
+    if let Some(tag) = languages.iter().flatten().find(|tag| !is_language_tag(tag)) {
+        return Err(format!("invalid language tag: {}", tag));
+    }
+
+    let before = current();
+    settings::update(|s| {
+        if let Some(enabled) = enabled {
+            s.spellcheck.enabled = enabled;
+        }
+        if let Some(languages) = languages {
+            s.spellcheck.languages = languages;
+        }
+    })
+    .map_err(|e| format!("failed to save spellcheck settings: {}", e))?;
+
+    let after = current();
+    info!("Spellcheck {} ({:?})", if after.enabled { "enabled" } else { "disabled" }, after.languages);
+    if after.enabled != before.enabled {
+        crate::ipc::queue_script(apply_script(after.enabled));
+    }
+    if after.languages != before.languages && !after.custom_words.is_empty() {
+        if let Err(e) = learn(&after.custom_words, &after.languages) {
+            warn!("Failed to add custom words for {:?}: {}", after.languages, e);
+        }
+    }
+    MENU_DIRTY.store(true, Ordering::SeqCst);
+    crate::ipc::wake();
+    Ok(after)
+}
+
+/// Turn one menu language on or off
+pub fn toggle_language(tag: &str, learn: LearnWords) -> Result<SpellcheckSettings, String> {
+    let mut languages = current().languages;
+    match languages.iter().position(|l| l == tag) {
+        Some(index) => {
+            languages.remove(index);
+        }
+        None => languages.push(tag.to_string()),
+    }
+    set(None, Some(languages), learn)
+}
+
+/// Handle `add_dictionary_word`
+pub fn add_word(word: &str, learn: LearnWords) -> Result<(), String> {
+    let word = word.trim();
+    if word.is_empty() || word.chars().count() > MAX_WORD_LEN || word.chars().any(char::is_whitespace) {
+        return Err(format!("not a single word: {:?}", word));
+    }
+
+    let languages = current().languages;
+    learn(&[word.to_string()], &languages)?;
+    settings::update(|s| {
+        if !s.spellcheck.custom_words.iter().any(|w| w == word) {
+            s.spellcheck.custom_words.push(word.to_string());
+        }
+    })
+    .map_err(|e| format!("failed to save dictionary word: {}", e))?;
+    info!("Added {:?} to the dictionary", word);
+    Ok(())
+}
+
+/// Returns the settings if they changed since the last call
+pub fn take_menu_update() -> Option<SpellcheckSettings> {
+    MENU_DIRTY.swap(false, Ordering::SeqCst).then(current)
+}
+
+/// Loose BCP 47 check: alphanumeric subtags joined by `-`
+fn is_language_tag(tag: &str) -> bool {
+    !tag.is_empty()
+        && tag.len() <= 35
+        && tag.split('-').all(|part| !part.is_empty() && part.len() <= 8 && part.chars().all(|c| c.is_ascii_alphanumeric()))
+}
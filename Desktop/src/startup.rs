@@ -0,0 +1,70 @@
+//! Startup timing.
+//!
+//! Each phase of bringing the window up runs in a `startup` tracing span and
+//! logs its duration; the total from process start to the window appearing is
+//! logged once as `Startup took N ms`, with a warning above [`TARGET`]. The
+//! window is shown when the page has finished loading so users never see a
+//! blank frame, or after [`SHOW_TIMEOUT`] if the page is slow.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+use tracing::{info, info_span, span::EnteredSpan, warn};
+
+pub const TARGET: Duration = Duration::from_millis(1500);
+/// Show the window anyway when the page hasn't loaded by then
+pub const SHOW_TIMEOUT: Duration = Duration::from_secs(3);
+
+static STARTED: OnceLock<Instant> = OnceLock::new();
+static SHOWN: AtomicBool = AtomicBool::new(false);
+
+/// Start the clock; called first thing in `main`
+pub fn begin() {
+    STARTED.get_or_init(Instant::now);
+}
+
+fn elapsed() -> Duration {
+    STARTED.get_or_init(Instant::now).elapsed()
+}
+
+/// Logs the phase's duration when dropped
+pub struct Phase {
+    name: &'static str,
+    at: Instant,
+    _span: EnteredSpan,
+}
+
+impl Drop for Phase {
+    fn drop(&mut self) {
+        info!("Startup phase {} took {} ms", self.name, self.at.elapsed().as_millis());
+    }
+}
+
+pub fn phase(name: &'static str) -> Phase {
+    Phase { name, at: Instant::now(), _span: info_span!("startup", phase = name).entered() }
+}
+
+/// True the first time only: the window should be shown now
+pub fn take_first_show() -> bool {
+    if SHOWN.swap(true, Ordering::SeqCst) {
+        return false;
+    }
+    let total = elapsed();
+    if total > TARGET {
+        warn!("Startup took {} ms (target {} ms)", total.as_millis(), TARGET.as_millis());
+    } else {
+        info!("Startup took {} ms", total.as_millis());
+    }
+    true
+}
+
+/// Call `show` after [`SHOW_TIMEOUT`] unless the window is up by then
+pub fn show_after_timeout(show: impl FnOnce() + Send + 'static) {
+    std::thread::spawn(move || {
+        std::thread::sleep(SHOW_TIMEOUT);
+        if !SHOWN.load(Ordering::SeqCst) {
+            warn!("Page not loaded after {} s, showing the window anyway", SHOW_TIMEOUT.as_secs());
+            show();
+        }
+    });
+}
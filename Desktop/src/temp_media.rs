@@ -0,0 +1,735 @@
+//! Images pasted or dropped into the composer, kept until they are sent.
+//!
+//! `stage_media {source: "clipboard"}` or `{source: "path", path}` copies
+//! the image into `temp-media` in the data directory and answers `{id, url,
+//! path, width, height, bytes, contentType}`. `url` is
+//! `miko://temp-media/<id>`, stable until the image is released, for the
+//! composer's preview; `path` is what `upload_file` takes. `release_media
+//! {id}` deletes it once it was sent or removed from the draft. Whatever
+//! is still staged goes when the app exits, and the maintenance task clears
+//! files no staged image refers to and images staged longer than
+//! [`STALE_AFTER`].
+//!
+//! PNG, JPEG, GIF and WebP are accepted, up to [`MAX_BYTES`] and
+//! [`crate::clipboard::MAX_PIXELS`] each and [`MAX_TOTAL_BYTES`] together.
+//! Metadata that may say where and with what a photo was taken is removed:
+//! PNGs are re-encoded, JPEGs lose their EXIF, XMP, IPTC and comment
+//! segments (keeping only the orientation, so photos stay upright), and
+//! WebP its EXIF and XMP chunks.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use http::{Response, StatusCode};
+use lazy_static::lazy_static;
+use serde_json::{json, Value};
+use tracing::{debug, info, warn};
+
+pub const HOST: &str = "temp-media";
+/// Largest file staged
+const MAX_BYTES: u64 = 25 * 1024 * 1024;
+/// Largest total of everything staged at once
+const MAX_TOTAL_BYTES: u64 = 256 * 1024 * 1024;
+/// Staged images left unreleased this long are removed by the maintenance task
+pub const STALE_AFTER: Duration = Duration::from_secs(24 * 60 * 60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Png,
+    Jpeg,
+    Gif,
+    Webp,
+}
+
+impl Format {
+    fn sniff(bytes: &[u8]) -> Option<Self> {
+        if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+            Some(Self::Png)
+        } else if bytes.starts_with(&[0xFF, 0xD8, 0xFF]) {
+            Some(Self::Jpeg)
+        } else if bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a") {
+            Some(Self::Gif)
+        } else if bytes.len() >= 12 && &bytes[..4] == b"RIFF" && &bytes[8..12] == b"WEBP" {
+            Some(Self::Webp)
+        } else {
+            None
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            Self::Png => "png",
+            Self::Jpeg => "jpg",
+            Self::Gif => "gif",
+            Self::Webp => "webp",
+        }
+    }
+
+    fn content_type(self) -> &'static str {
+        match self {
+            Self::Png => "image/png",
+            Self::Jpeg => "image/jpeg",
+            Self::Gif => "image/gif",
+            Self::Webp => "image/webp",
+        }
+    }
+}
+
+/// An image ready to be written to the store
+struct Normalized {
+    format: Format,
+    bytes: Vec<u8>,
+    width: u32,
+    height: u32,
+}
+
+struct Staged {
+    path: PathBuf,
+    format: Format,
+    bytes: u64,
+    staged_at: Instant,
+}
+
+lazy_static! {
+    static ref STAGED: Mutex<HashMap<String, Staged>> = Mutex::new(HashMap::new());
+}
+
+pub fn dir() -> PathBuf {
+    crate::paths::data_dir().join("temp-media")
+}
+
+/// Handle `stage_media`
+pub fn stage(message: &Value) -> Result<Value, String> {
+    let image = match message["source"].as_str() {
+        Some("clipboard") => from_clipboard()?,
+        Some("path") => {
+            let path = message["path"].as_str().ok_or("path is required")?;
+            from_path(&crate::core::checked_path(Path::new(path))?)?
+        }
+        other => return Err(format!("unknown media source: {:?}", other)),
+    };
+    check_pixels(image.width, image.height)?;
+
+    let size = image.bytes.len() as u64;
+    let staged_total: u64 = STAGED.lock().unwrap().values().map(|staged| staged.bytes).sum();
+    if staged_total + size > MAX_TOTAL_BYTES {
+        return Err("too many images waiting to be sent; send or remove some first".to_string());
+    }
+
+    let id = uuid::Uuid::new_v4().simple().to_string();
+    let path = dir().join(format!("{}.{}", id, image.format.extension()));
+    std::fs::create_dir_all(dir())
+        .and_then(|()| std::fs::write(&path, &image.bytes))
+        .map_err(|e| format!("failed to stage the image: {}", e))?;
+    STAGED.lock().unwrap().insert(id.clone(), Staged { path: path.clone(), format: image.format, bytes: size, staged_at: Instant::now() });
+    debug!("Staged a {}x{} {} as {}", image.width, image.height, image.format.content_type(), id);
+
+    Ok(json!({
+        "id": id,
+        "url": format!("{}://{}/{}", crate::protocol::SCHEME, HOST, id),
+        "path": path,
+        "width": image.width,
+        "height": image.height,
+        "bytes": size,
+        "contentType": image.format.content_type(),
+    }))
+}
+
+/// Handle `release_media`; false if `id` wasn't staged
+pub fn release(id: &str) -> bool {
+    let Some(staged) = STAGED.lock().unwrap().remove(id) else { return false };
+    if let Err(e) = std::fs::remove_file(&staged.path) {
+        warn!("Failed to remove {}: {}", staged.path.display(), e);
+    }
+    true
+}
+
+/// Answer `miko://temp-media/<id>`
+pub fn serve(uri_path: &str) -> Response<Cow<'static, [u8]>> {
+    let id = uri_path.trim_start_matches('/');
+    let staged = STAGED.lock().unwrap().get(id).map(|staged| (staged.path.clone(), staged.format));
+    let Some((path, format)) = staged else {
+        return Response::builder().status(StatusCode::NOT_FOUND).body(Cow::Borrowed(&[][..])).unwrap();
+    };
+    match std::fs::read(&path) {
+        Ok(bytes) => Response::builder()
+            .header("Content-Type", format.content_type())
+            .header("Cache-Control", "no-store")
+            .body(Cow::Owned(bytes))
+            .unwrap(),
+        Err(e) => {
+            warn!("Failed to read staged image {}: {}", path.display(), e);
+            Response::builder().status(StatusCode::NOT_FOUND).body(Cow::Borrowed(&[][..])).unwrap()
+        }
+    }
+}
+
+/// For the maintenance task: release images staged longer than
+/// [`STALE_AFTER`], and return the files of the others
+pub fn in_use() -> Vec<PathBuf> {
+    let stale: Vec<String> = STAGED
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|(_, staged)| staged.staged_at.elapsed() > STALE_AFTER)
+        .map(|(id, _)| id.clone())
+        .collect();
+    for id in &stale {
+        release(id);
+    }
+    if !stale.is_empty() {
+        info!("Released {} image(s) staged over a day ago", stale.len());
+    }
+    STAGED.lock().unwrap().values().map(|staged| staged.path.clone()).collect()
+}
+
+/// Remove everything staged (at exit)
+pub fn cleanup() {
+    STAGED.lock().unwrap().clear();
+    match std::fs::remove_dir_all(dir()) {
+        Ok(()) => info!("Removed staged images"),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => warn!("Failed to remove staged images: {}", e),
+    }
+}
+
+fn check_pixels(width: u32, height: u32) -> Result<(), String> {
+    let max = crate::clipboard::MAX_PIXELS;
+    if width == 0 || height == 0 {
+        return Err("not a readable image".to_string());
+    }
+    if (width as usize).saturating_mul(height as usize) > max {
+        return Err(format!("image is too large ({}x{}, at most {} megapixels)", width, height, max / 1_000_000));
+    }
+    Ok(())
+}
+
+fn from_clipboard() -> Result<Normalized, String> {
+    let mut clipboard = arboard::Clipboard::new().map_err(|e| format!("clipboard unavailable: {}", e))?;
+    let image = match clipboard.get_image() {
+        Ok(image) => image,
+        Err(arboard::Error::ContentNotAvailable) => return Err("no image on the clipboard".to_string()),
+        Err(e) => return Err(e.to_string()),
+    };
+    let (width, height) = (image.width as u32, image.height as u32);
+    check_pixels(width, height)?;
+    let bytes = encode_png(width, height, png::ColorType::Rgba, &image.bytes).map_err(|e| format!("failed to encode the image: {}", e))?;
+    Ok(Normalized { format: Format::Png, bytes, width, height })
+}
+
+fn from_path(path: &Path) -> Result<Normalized, String> {
+    let size = std::fs::metadata(path).map_err(|e| format!("cannot read {}: {}", path.display(), e))?.len();
+    if size > MAX_BYTES {
+        return Err(format!("image is too large ({} MB, at most {} MB)", size / (1024 * 1024), MAX_BYTES / (1024 * 1024)));
+    }
+    let bytes = std::fs::read(path).map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+    normalize(bytes)
+}
+
+/// Check the image and strip its metadata
+fn normalize(bytes: Vec<u8>) -> Result<Normalized, String> {
+    let format = Format::sniff(&bytes).ok_or("not a PNG, JPEG, GIF or WebP image")?;
+    let unreadable = || format!("not a readable {} image", format.extension().to_uppercase());
+    match format {
+        Format::Png => {
+            let (width, height, bytes) = reencode_png(&bytes)?;
+            Ok(Normalized { format, bytes, width, height })
+        }
+        Format::Jpeg => {
+            let (width, height, bytes) = strip_jpeg(&bytes).ok_or_else(unreadable)?;
+            Ok(Normalized { format, bytes, width, height })
+        }
+        Format::Gif => {
+            // GIFs carry no EXIF
+            let (width, height) = bytes
+                .get(6..10)
+                .map(|b| (u16::from_le_bytes([b[0], b[1]]) as u32, u16::from_le_bytes([b[2], b[3]]) as u32))
+                .ok_or_else(unreadable)?;
+            Ok(Normalized { format, bytes, width, height })
+        }
+        Format::Webp => {
+            let (width, height, bytes) = strip_webp(&bytes).ok_or_else(unreadable)?;
+            Ok(Normalized { format, bytes, width, height })
+        }
+    }
+}
+
+fn encode_png(width: u32, height: u32, color: png::ColorType, data: &[u8]) -> Result<Vec<u8>, png::EncodingError> {
+    let mut out = Vec::new();
+    let mut encoder = png::Encoder::new(&mut out, width, height);
+    encoder.set_color(color);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_compression(png::Compression::Fast);
+    encoder.write_header()?.write_image_data(data)?;
+    Ok(out)
+}
+
+/// Decode and encode again: only the pixels survive (an animated PNG keeps
+/// its first frame)
+fn reencode_png(bytes: &[u8]) -> Result<(u32, u32, Vec<u8>), String> {
+    let unreadable = |e: png::DecodingError| format!("not a readable PNG image: {}", e);
+    let mut decoder = png::Decoder::new(Cursor::new(bytes));
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info().map_err(unreadable)?;
+    let (width, height) = (reader.info().width, reader.info().height);
+    // Before allocating the pixels
+    check_pixels(width, height)?;
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let frame = reader.next_frame(&mut buf).map_err(unreadable)?;
+    buf.truncate(frame.buffer_size());
+    let bytes = encode_png(frame.width, frame.height, frame.color_type, &buf).map_err(|e| format!("failed to encode the image: {}", e))?;
+    Ok((frame.width, frame.height, bytes))
+}
+
+/// EXIF orientation (1–8) in an APP1 `Exif` segment's payload
+fn exif_orientation(payload: &[u8]) -> Option<u16> {
+    let tiff = payload.strip_prefix(b"Exif\0\0")?;
+    let big_endian = match tiff.get(..2)? {
+        b"MM" => true,
+        b"II" => false,
+        _ => return None,
+    };
+    let u16_at = |at: usize| tiff.get(at..at + 2).map(|b| if big_endian { u16::from_be_bytes([b[0], b[1]]) } else { u16::from_le_bytes([b[0], b[1]]) });
+    let u32_at = |at: usize| {
+        tiff.get(at..at + 4).map(|b| {
+            let b = [b[0], b[1], b[2], b[3]];
+            if big_endian { u32::from_be_bytes(b) } else { u32::from_le_bytes(b) }
+        })
+    };
+    let ifd = u32_at(4)? as usize;
+    let count = u16_at(ifd)? as usize;
+    (0..count)
+        .map(|n| ifd + 2 + n * 12)
+        .find(|&entry| u16_at(entry) == Some(0x0112))
+        .and_then(|entry| u16_at(entry + 8))
+        .filter(|orientation| (1..=8).contains(orientation))
+}
+
+/// An APP1 segment with nothing but the orientation
+fn orientation_segment(orientation: u16) -> Vec<u8> {
+    let mut payload = b"Exif\0\0MM\0\x2a\0\0\0\x08".to_vec();
+    payload.extend_from_slice(&1u16.to_be_bytes());
+    payload.extend_from_slice(&[0x01, 0x12, 0x00, 0x03, 0, 0, 0, 1]);
+    payload.extend_from_slice(&orientation.to_be_bytes());
+    payload.extend_from_slice(&[0, 0, 0, 0, 0, 0]);
+    let mut segment = vec![0xFF, 0xE1];
+    segment.extend_from_slice(&((payload.len() + 2) as u16).to_be_bytes());
+    segment.extend_from_slice(&payload);
+    segment
+}
+
+/// Drop APP1 (EXIF, XMP), APP13 (IPTC) and comment segments; returns the
+/// size from the frame header and the stripped file
+fn strip_jpeg(bytes: &[u8]) -> Option<(u32, u32, Vec<u8>)> {
+    let mut out = bytes[..2].to_vec();
+    let mut kept = Vec::new();
+    let mut orientation = None;
+    let mut size = None;
+    let mut at = 2;
+    loop {
+        if *bytes.get(at)? != 0xFF {
+            return None;
+        }
+        let marker = *bytes.get(at + 1)?;
+        if marker == 0xFF {
+            // Fill byte
+            at += 1;
+            continue;
+        }
+        // Start of scan: the rest is image data
+        if marker == 0xDA {
+            break;
+        }
+        // The length counts its own two bytes
+        let length = u16::from_be_bytes([*bytes.get(at + 2)?, *bytes.get(at + 3)?]) as usize;
+        if length < 2 {
+            return None;
+        }
+        let segment = bytes.get(at..at + 2 + length)?;
+        let payload = &segment[4..];
+        match marker {
+            0xE1 => orientation = orientation.or_else(|| exif_orientation(payload)),
+            0xED | 0xFE => {}
+            // Frame headers, other than DHT, JPG and DAC
+            0xC0..=0xCF if !matches!(marker, 0xC4 | 0xC8 | 0xCC) => {
+                let height = u16::from_be_bytes([*payload.get(1)?, *payload.get(2)?]) as u32;
+                let width = u16::from_be_bytes([*payload.get(3)?, *payload.get(4)?]) as u32;
+                size = Some((width, height));
+                kept.push(segment);
+            }
+            _ => kept.push(segment),
+        }
+        at += 2 + length;
+    }
+    let (width, height) = size?;
+
+    // JFIF's APP0 stays first
+    let app0 = kept.iter().take_while(|segment| segment[1] == 0xE0).count();
+    for segment in &kept[..app0] {
+        out.extend_from_slice(segment);
+    }
+    if let Some(orientation) = orientation.filter(|&o| o != 1) {
+        out.extend_from_slice(&orientation_segment(orientation));
+    }
+    for segment in &kept[app0..] {
+        out.extend_from_slice(segment);
+    }
+    out.extend_from_slice(&bytes[at..]);
+    Some((width, height, out))
+}
+
+/// Drop the `EXIF` and `XMP ` chunks (and their flags in `VP8X`); returns
+/// the canvas size and the stripped file
+fn strip_webp(bytes: &[u8]) -> Option<(u32, u32, Vec<u8>)> {
+    let mut out = bytes[..12].to_vec();
+    let mut size = None;
+    let mut at = 12;
+    while at + 8 <= bytes.len() {
+        let fourcc = &bytes[at..at + 4];
+        let length = u32::from_le_bytes([bytes[at + 4], bytes[at + 5], bytes[at + 6], bytes[at + 7]]) as usize;
+        // Chunks are padded to an even length
+        let end = at + 8 + length + (length & 1);
+        let chunk = bytes.get(at..end.min(bytes.len()))?;
+        let data = bytes.get(at + 8..at + 8 + length)?;
+        let u24 = |b: &[u8]| b[0] as u32 | (b[1] as u32) << 8 | (b[2] as u32) << 16;
+        match fourcc {
+            b"EXIF" | b"XMP " => {}
+            b"VP8X" => {
+                let mut chunk = chunk.to_vec();
+                // The EXIF and XMP flags
+                if let Some(flags) = chunk.get_mut(8) {
+                    *flags &= !(0x08 | 0x04);
+                }
+                size = size.or(data.get(4..10).map(|b| (u24(&b[..3]) + 1, u24(&b[3..]) + 1)));
+                out.extend_from_slice(&chunk);
+            }
+            b"VP8 " => {
+                size = size.or(data.get(6..10).map(|b| {
+                    (u16::from_le_bytes([b[0], b[1]]) as u32 & 0x3FFF, u16::from_le_bytes([b[2], b[3]]) as u32 & 0x3FFF)
+                }));
+                out.extend_from_slice(chunk);
+            }
+            b"VP8L" => {
+                size = size.or(data.get(1..5).map(|b| {
+                    let bits = u32::from_le_bytes([b[0], b[1], b[2], b[3]]);
+                    ((bits & 0x3FFF) + 1, ((bits >> 14) & 0x3FFF) + 1)
+                }));
+                out.extend_from_slice(chunk);
+            }
+            _ => out.extend_from_slice(chunk),
+        }
+        at = end;
+    }
+    let riff_size = (out.len() - 8) as u32;
+    out[4..8].copy_from_slice(&riff_size.to_le_bytes());
+    let (width, height) = size?;
+    Some((width, height, out))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Staging shares one store and its total; tests take turns
+    fn turn() -> std::sync::MutexGuard<'static, ()> {
+        static TURN: Mutex<()> = Mutex::new(());
+        TURN.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Files to stage from, removed on drop
+    struct Fixture {
+        root: PathBuf,
+    }
+
+    impl Fixture {
+        fn new() -> Self {
+            let root = std::env::temp_dir().join(format!("miko-temp-media-{}", uuid::Uuid::new_v4().simple()));
+            std::fs::create_dir_all(&root).unwrap();
+            Self { root }
+        }
+
+        fn file(&self, name: &str, bytes: &[u8]) -> PathBuf {
+            let path = self.root.join(name);
+            std::fs::write(&path, bytes).unwrap();
+            path
+        }
+    }
+
+    impl Drop for Fixture {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.root);
+        }
+    }
+
+    fn stage_path(path: &Path) -> Result<Value, String> {
+        stage(&json!({ "source": "path", "path": path }))
+    }
+
+    fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+        haystack.windows(needle.len()).any(|window| window == needle)
+    }
+
+    /// A grey `width`x`height` PNG with a text chunk saying where it was made
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let mut out = Vec::new();
+        let mut encoder = png::Encoder::new(&mut out, width, height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.add_text_chunk("Location".to_string(), "Bangkok office".to_string()).unwrap();
+        encoder.write_header().unwrap().write_image_data(&vec![0x80; (width * height * 4) as usize]).unwrap();
+        out
+    }
+
+    fn segment(marker: u8, payload: &[u8]) -> Vec<u8> {
+        let mut segment = vec![0xFF, marker];
+        segment.extend_from_slice(&((payload.len() + 2) as u16).to_be_bytes());
+        segment.extend_from_slice(payload);
+        segment
+    }
+
+    /// An `Exif` payload with one IFD of SHORT `entries`, then the camera's name
+    fn exif(big_endian: bool, entries: &[(u16, u16)]) -> Vec<u8> {
+        let u16_bytes = |v: u16| if big_endian { v.to_be_bytes() } else { v.to_le_bytes() };
+        let mut payload = b"Exif\0\0".to_vec();
+        payload.extend_from_slice(if big_endian { b"MM" } else { b"II" });
+        payload.extend_from_slice(&u16_bytes(42));
+        payload.extend_from_slice(&if big_endian { 8u32.to_be_bytes() } else { 8u32.to_le_bytes() });
+        payload.extend_from_slice(&u16_bytes(entries.len() as u16));
+        for &(tag, value) in entries {
+            payload.extend_from_slice(&u16_bytes(tag));
+            payload.extend_from_slice(&u16_bytes(3));
+            payload.extend_from_slice(&if big_endian { 1u32.to_be_bytes() } else { 1u32.to_le_bytes() });
+            payload.extend_from_slice(&u16_bytes(value));
+            payload.extend_from_slice(&[0, 0]);
+        }
+        payload.extend_from_slice(&[0; 4]);
+        payload.extend_from_slice(b"Canon EOS R5");
+        payload
+    }
+
+    const SOF0: [u8; 9] = [8, 0, 30, 0, 40, 1, 1, 0x11, 0];
+    const SCAN: [u8; 14] = [0xFF, 0xDA, 0, 8, 1, 1, 0, 0, 0x3F, 0, 0x12, 0x34, 0xFF, 0xD9];
+
+    /// A 40x30 JPEG: SOI, JFIF, `segments`, then its frame, tables and scan
+    fn jpeg(segments: &[Vec<u8>]) -> Vec<u8> {
+        let mut out = vec![0xFF, 0xD8];
+        out.extend(segment(0xE0, b"JFIF\0\x01\x01\0\0\x01\0\x01\0\0"));
+        out.extend(segments.concat());
+        out.extend(segment(0xC0, &SOF0));
+        out.extend(segment(0xC4, &[0; 20]));
+        out.extend_from_slice(&SCAN);
+        out
+    }
+
+    fn chunk(fourcc: &[u8; 4], data: &[u8]) -> Vec<u8> {
+        let mut chunk = fourcc.to_vec();
+        chunk.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        chunk.extend_from_slice(data);
+        if data.len() % 2 == 1 {
+            chunk.push(0);
+        }
+        chunk
+    }
+
+    fn webp(chunks: &[Vec<u8>]) -> Vec<u8> {
+        let body = chunks.concat();
+        let mut out = b"RIFF".to_vec();
+        out.extend_from_slice(&((body.len() + 4) as u32).to_le_bytes());
+        out.extend_from_slice(b"WEBP");
+        out.extend(body);
+        out
+    }
+
+    /// A lossless 40x30 bitstream header
+    fn vp8l() -> Vec<u8> {
+        let bits: u32 = 39 | (29 << 14);
+        let mut data = vec![0x2F];
+        data.extend_from_slice(&bits.to_le_bytes());
+        data.extend_from_slice(&[0; 6]);
+        chunk(b"VP8L", &data)
+    }
+
+    /// An extended 40x30 WebP with alpha, EXIF and XMP
+    fn extended_webp() -> Vec<u8> {
+        let vp8x = [0x10 | 0x08 | 0x04, 0, 0, 0, 39, 0, 0, 29, 0, 0];
+        webp(&[chunk(b"VP8X", &vp8x), vp8l(), chunk(b"EXIF", &exif(true, &[(0x0112, 6)])), chunk(b"XMP ", b"<x:xmpmeta>GPS</x:xmpmeta>")])
+    }
+
+    #[test]
+    fn staged_images_go_from_preview_to_upload_to_release() {
+        let _turn = turn();
+        let session = crate::testing::session(true);
+        let mock = session.use_mock_erp();
+        let fixture = Fixture::new();
+
+        let staged = stage_path(&fixture.file("pasted.png", &png(4, 3))).unwrap();
+        let id = staged["id"].as_str().unwrap().to_string();
+        let path = PathBuf::from(staged["path"].as_str().unwrap());
+        assert_eq!(staged["url"], format!("{}://{}/{}", crate::protocol::SCHEME, HOST, id));
+        assert_eq!((staged["width"].as_u64(), staged["height"].as_u64()), (Some(4), Some(3)));
+        assert_eq!(staged["contentType"], "image/png");
+        assert!(path.starts_with(dir()));
+        let bytes = std::fs::read(&path).unwrap();
+        assert_eq!(staged["bytes"].as_u64(), Some(bytes.len() as u64));
+        assert!(!contains(&bytes, b"Bangkok office"));
+
+        let preview = serve(&format!("/{}", id));
+        assert_eq!(preview.status(), StatusCode::OK);
+        assert_eq!(preview.headers()["Content-Type"], "image/png");
+        assert_eq!(preview.headers()["Cache-Control"], "no-store");
+        assert_eq!(preview.body().as_ref(), bytes.as_slice());
+
+        let request = crate::upload::UploadRequest::new(crate::upload::new_upload_id(), crate::testing::mock_erp::chat_uuid(1), path.clone());
+        let uploaded = crate::upload::upload(&request);
+        assert_eq!(uploaded["success"], true, "{}", uploaded);
+        let url = format!("{}{}", mock.base(), uploaded["attachment"]["data"]["url"].as_str().unwrap());
+        let received = reqwest::blocking::get(url).unwrap().bytes().unwrap();
+        assert!(contains(&received, &bytes));
+        assert!(in_use().contains(&path));
+
+        assert!(release(&id));
+        assert!(!path.exists());
+        assert_eq!(serve(&format!("/{}", id)).status(), StatusCode::NOT_FOUND);
+        assert!(!in_use().contains(&path));
+        assert!(!release(&id));
+    }
+
+    #[test]
+    fn jpegs_keep_only_their_orientation() {
+        let metadata = [
+            segment(0xE1, &exif(false, &[(0x010F, 1), (0x0112, 6)])),
+            segment(0xE1, b"http://ns.adobe.com/xap/1.0/\0<x:xmpmeta>GPS 13.7563 N</x:xmpmeta>"),
+            segment(0xED, b"Photoshop 3.0\08BIM Photographer"),
+            segment(0xFE, b"taken at home"),
+        ];
+        let (width, height, stripped) = strip_jpeg(&jpeg(&metadata)).unwrap();
+        assert_eq!((width, height), (40, 30));
+        let mut expected = vec![0xFF, 0xD8];
+        expected.extend(segment(0xE0, b"JFIF\0\x01\x01\0\0\x01\0\x01\0\0"));
+        expected.extend(orientation_segment(6));
+        expected.extend(segment(0xC0, &SOF0));
+        expected.extend(segment(0xC4, &[0; 20]));
+        expected.extend_from_slice(&SCAN);
+        assert_eq!(stripped, expected);
+        for secret in [&b"Canon"[..], b"GPS", b"Photographer", b"taken at home"] {
+            assert!(!contains(&stripped, secret));
+        }
+        assert_eq!(exif_orientation(&orientation_segment(6)[4..]), Some(6));
+
+        // Upright photos need no EXIF at all; fill bytes before a marker are allowed
+        let upright = jpeg(&[segment(0xE1, &exif(true, &[(0x0112, 1)]))]);
+        let (_, _, stripped) = strip_jpeg(&upright).unwrap();
+        assert_eq!(stripped, jpeg(&[]));
+        let mut filled = jpeg(&[]);
+        filled.splice(2..2, [0xFF, 0xFF]);
+        assert_eq!(strip_jpeg(&filled).unwrap().2, jpeg(&[]));
+    }
+
+    #[test]
+    fn orientation_is_read_in_either_byte_order() {
+        let cases = [
+            (exif(true, &[(0x0112, 8)]), Some(8)),
+            (exif(false, &[(0x010F, 2), (0x0112, 3)]), Some(3)),
+            (exif(false, &[(0x010F, 2)]), None),
+            // Out of range
+            (exif(true, &[(0x0112, 9)]), None),
+            (exif(true, &[(0x0112, 0)]), None),
+            (b"Exif\0\0XX\0\x2a\0\0\0\x08".to_vec(), None),
+            (b"XMP\0\0\0MM".to_vec(), None),
+        ];
+        for (payload, expected) in cases {
+            assert_eq!(exif_orientation(&payload), expected, "{:?}", payload);
+        }
+        // Cut anywhere, it never reads past the end
+        let full = exif(true, &[(0x0112, 6)]);
+        for cut in 0..full.len() {
+            let _ = exif_orientation(&full[..cut]);
+        }
+        let mut far = exif(false, &[(0x0112, 6)]);
+        far[10..14].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert_eq!(exif_orientation(&far), None);
+    }
+
+    #[test]
+    fn webps_lose_their_exif_and_xmp_chunks() {
+        let (width, height, stripped) = strip_webp(&extended_webp()).unwrap();
+        assert_eq!((width, height), (40, 30));
+        let vp8x = [0x10, 0, 0, 0, 39, 0, 0, 29, 0, 0];
+        assert_eq!(stripped, webp(&[chunk(b"VP8X", &vp8x), vp8l()]));
+        assert_eq!(u32::from_le_bytes(stripped[4..8].try_into().unwrap()) as usize, stripped.len() - 8);
+
+        let simple = webp(&[vp8l()]);
+        assert_eq!(strip_webp(&simple), Some((40, 30, simple.clone())));
+        let lossy = webp(&[chunk(b"VP8 ", &[0x10, 0, 0, 0x9D, 0x01, 0x2A, 40, 0, 30, 0])]);
+        assert_eq!(strip_webp(&lossy).map(|(w, h, _)| (w, h)), Some((40, 30)));
+    }
+
+    #[test]
+    fn truncated_or_corrupt_images_are_refused() {
+        let samples = [png(4, 3), jpeg(&[segment(0xE1, &exif(false, &[(0x0112, 6)]))]), extended_webp(), b"GIF89a\x28\0\x1e\0\0\0\0;".to_vec()];
+        for sample in &samples {
+            assert!(normalize(sample.clone()).is_ok());
+            // Cut anywhere or with any byte flipped, it is refused or read, never a panic
+            for cut in 0..sample.len() {
+                let _ = normalize(sample[..cut].to_vec());
+            }
+            for at in 0..sample.len() {
+                let mut corrupt = sample.clone();
+                corrupt[at] ^= 0xFF;
+                let _ = normalize(corrupt);
+            }
+        }
+
+        let jpeg = jpeg(&[]);
+        let before_frame = jpeg.len() - SCAN.len() - 22 - 11;
+        let cases = [
+            (b"hello, world".to_vec(), "not a PNG, JPEG, GIF or WebP image"),
+            (jpeg[..before_frame].to_vec(), "not a readable JPG image"),
+            // A segment length shorter than the length field
+            ([&jpeg[..2], &[0xFF, 0xE1, 0, 0]].concat(), "not a readable JPG image"),
+            ([&jpeg[..2], &[0xFF, 0xE1, 0, 1, 0]].concat(), "not a readable JPG image"),
+            (webp(&[chunk(b"VP8X", &[])]), "not a readable WEBP image"),
+            (webp(&[chunk(b"EXIF", b"only metadata")]), "not a readable WEBP image"),
+            (b"GIF89a\x28\0".to_vec(), "not a readable GIF image"),
+        ];
+        for (bytes, error) in cases {
+            assert_eq!(normalize(bytes.clone()).err().as_deref(), Some(error), "{:?}", bytes);
+        }
+        let mut no_pixels = png(4, 3);
+        let idat = no_pixels.windows(4).position(|w| w == b"IDAT").unwrap();
+        no_pixels[idat + 6] ^= 0xFF;
+        assert!(normalize(no_pixels).err().unwrap().starts_with("not a readable PNG image"));
+    }
+
+    #[test]
+    fn size_caps_are_enforced() {
+        let _turn = turn();
+        let fixture = Fixture::new();
+
+        let huge = fixture.file("huge.png", b"\x89PNG\r\n\x1a\n");
+        std::fs::File::options().write(true).open(&huge).unwrap().set_len(MAX_BYTES + 1).unwrap();
+        assert_eq!(stage_path(&huge).unwrap_err(), "image is too large (25 MB, at most 25 MB)");
+
+        let mut header = Vec::new();
+        let mut encoder = png::Encoder::new(&mut header, 20_000, 20_000);
+        encoder.set_color(png::ColorType::Rgba);
+        // Its pixels would take 1.6 GB; the header is all there is
+        encoder.write_header().unwrap().write_chunk(png::chunk::IDAT, &[]).unwrap();
+        assert!(stage_path(&fixture.file("wide.png", &header)).unwrap_err().starts_with("image is too large (20000x20000"));
+        assert_eq!(check_pixels(0, 10).unwrap_err(), "not a readable image");
+
+        // Whatever else is waiting counts against the total
+        let small = fixture.file("small.png", &png(4, 3));
+        let size = normalize(png(4, 3)).unwrap().bytes.len() as u64;
+        let filler = |bytes: u64| Staged { path: fixture.root.join("filler.png"), format: Format::Png, bytes, staged_at: Instant::now() };
+        STAGED.lock().unwrap().insert("filler".to_string(), filler(MAX_TOTAL_BYTES - size + 1));
+        assert_eq!(stage_path(&small).unwrap_err(), "too many images waiting to be sent; send or remove some first");
+        STAGED.lock().unwrap().insert("filler".to_string(), filler(MAX_TOTAL_BYTES - size));
+        let staged = stage_path(&small).unwrap();
+        assert!(release(staged["id"].as_str().unwrap()));
+        STAGED.lock().unwrap().remove("filler");
+    }
+}
@@ -0,0 +1,287 @@
+//! Shared by the unit tests: a fake ERP for code that talks to the API, and
+//! an allocator that counts what each thread holds.
+//!
+//! [`upstream`] starts the fake ERP once per test binary and points the API
+//! base at it. Each test claims a path prefix of its own with [`route`] and
+//! scripts the answers there, so tests run side by side against the one
+//! server. Every answer closes its connection.
+//!
+//! Tests of whole flows (retries, paging, the offline fallback) run against
+//! the mock ERP of `test/mock_erp` instead, through [`Session::use_mock_erp`].
+//!
+//! Whether someone is signed in is shared by every request the app forwards,
+//! so tests that sign in or forward requests hold a [`session`] and take
+//! turns. [`emitted`] collects what is sent to the webview.
+//!
+//! [`live_bytes`] and [`peak_bytes`] count the calling thread's allocations
+//! only, so soak tests aren't thrown off by the ones running beside them.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock};
+use std::time::Duration;
+use serde_json::Value;
+
+use crate::core::state::{self, AuthSnapshot};
+use crate::ipc::{EmitEvent, WebviewHandle};
+
+/// [`System`], keeping count per thread
+struct Counting;
+
+thread_local! {
+    static LIVE: Cell<isize> = const { Cell::new(0) };
+    static PEAK: Cell<isize> = const { Cell::new(0) };
+}
+
+fn count(change: isize) {
+    // Gone while the thread shuts down
+    let _ = LIVE.try_with(|live| {
+        live.set(live.get() + change);
+        let _ = PEAK.try_with(|peak| peak.set(peak.get().max(live.get())));
+    });
+}
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        count(layout.size() as isize);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        count(-(layout.size() as isize));
+        unsafe { System.dealloc(ptr, layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        count(new_size as isize - layout.size() as isize);
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+/// Bytes allocated and not yet freed by this thread; frees of memory another
+/// thread allocated count against it
+pub fn live_bytes() -> isize {
+    LIVE.with(Cell::get)
+}
+
+/// The most [`live_bytes`] reached since the last [`reset_peak`]
+pub fn peak_bytes() -> isize {
+    PEAK.with(Cell::get)
+}
+
+pub fn reset_peak() {
+    PEAK.with(|peak| peak.set(live_bytes()));
+}
+
+/// The turn of a test that depends on who is signed in; signs out again
+/// when dropped
+#[allow(dead_code)]
+#[path = "../test/mock_erp/server.rs"]
+pub mod mock_erp;
+
+pub struct Session {
+    _turn: MutexGuard<'static, ()>,
+    on_mock_erp: Cell<bool>,
+}
+
+impl Session {
+    /// Forward to the mock ERP, with its token and no faults, until the
+    /// turn ends
+    pub fn use_mock_erp(&self) -> &'static mock_erp::MockErp {
+        static MOCK: OnceLock<mock_erp::MockErp> = OnceLock::new();
+        let mock = MOCK.get_or_init(mock_erp::MockErp::start);
+        mock.clear_faults();
+        mock.take_requests();
+        crate::core::settings::update(|s| s.api_base_url = Some(mock.base())).unwrap();
+        state::runtime().auth.token = Some(mock_erp::TOKEN.to_string());
+        self.on_mock_erp.set(true);
+        mock
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        state::runtime().auth = AuthSnapshot::default();
+        if self.on_mock_erp.get() {
+            // Starting the fake ERP updates the settings too, so not inside `update`
+            let base = upstream().to_string();
+            crate::core::settings::update(|s| s.api_base_url = Some(base)).unwrap();
+        }
+    }
+}
+
+/// Wait for the turn, then sign in (or stay signed out) without the side
+/// effects of a real sign-in
+pub fn session(signed_in: bool) -> Session {
+    static TURN: Mutex<()> = Mutex::new(());
+    let turn = TURN.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    state::runtime().auth = AuthSnapshot {
+        signed_in,
+        user_id: signed_in.then(|| "test-user".to_string()),
+        token: signed_in.then(|| "test-token".to_string()),
+        ..Default::default()
+    };
+    Session { _turn: turn, on_mock_erp: Cell::new(false) }
+}
+
+/// Payloads of the `name` events emitted since the last call asking for
+/// them; the first call starts collecting
+pub fn emitted(name: &str) -> Vec<Value> {
+    static HANDLE: OnceLock<WebviewHandle> = OnceLock::new();
+    static EVENTS: Mutex<Vec<(String, Value)>> = Mutex::new(Vec::new());
+    let handle = HANDLE.get_or_init(|| crate::ipc::install(|| {}));
+    let mut events = EVENTS.lock().unwrap();
+    events.extend(handle.drain().into_iter().filter_map(|item| match item {
+        EmitEvent::Event { name, payload } => Some((name, payload)),
+        _ => None,
+    }));
+    let (matching, rest) = std::mem::take(&mut *events).into_iter().partition(|(event, _)| event == name);
+    *events = rest;
+    matching.into_iter().map(|(_, payload)| payload).collect()
+}
+
+/// A request the fake ERP received
+#[derive(Debug, Clone)]
+pub struct Received {
+    /// Path and query
+    pub path: String,
+    pub body: Vec<u8>,
+}
+
+/// How the fake ERP answers
+pub enum Reply {
+    Respond { status: u16, headers: Vec<(&'static str, String)>, body: Vec<u8> },
+    /// Say nothing for this long, then hang up
+    Stall(Duration),
+    /// Hang up without answering
+    Close,
+    /// Promise `declared` bytes of body, send `sent` of them and hang up
+    Truncated { status: u16, declared: usize, sent: usize },
+    /// Promise `declared` bytes of body and send one every `pause` until done
+    /// or the client hangs up
+    Trickle { declared: usize, pause: Duration },
+}
+
+impl Reply {
+    pub fn json(status: u16, body: serde_json::Value) -> Self {
+        Self::text(status, "application/json", &body.to_string())
+    }
+
+    pub fn text(status: u16, content_type: &str, body: &str) -> Self {
+        Self::Respond { status, headers: vec![("Content-Type", content_type.to_string())], body: body.as_bytes().to_vec() }
+    }
+}
+
+type Handler = Arc<dyn Fn(&Received) -> Reply + Send + Sync>;
+
+struct Server {
+    base: String,
+    routes: Mutex<Vec<(String, Handler)>>,
+}
+
+fn server() -> &'static Server {
+    static SERVER: OnceLock<Server> = OnceLock::new();
+    SERVER.get_or_init(|| {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                std::thread::spawn(move || serve(stream));
+            }
+        });
+        crate::core::settings::update(|s| s.api_base_url = Some(base.clone())).unwrap();
+        Server { base, routes: Mutex::new(Vec::new()) }
+    })
+}
+
+/// The fake ERP's base URL; the API base points there from the first call
+pub fn upstream() -> &'static str {
+    &server().base
+}
+
+/// Answer requests whose path starts with `prefix` with `handler`
+pub fn route(prefix: &str, handler: impl Fn(&Received) -> Reply + Send + Sync + 'static) {
+    server().routes.lock().unwrap().push((prefix.to_string(), Arc::new(handler)));
+}
+
+/// Let requests under `prefix` take `secs` (see [`crate::protocol::timeout_for`])
+pub fn route_timeout(prefix: &str, secs: u64) {
+    crate::core::settings::update(|s| {
+        s.forwarding.route_timeouts.insert(prefix.to_string(), secs);
+    })
+    .unwrap();
+}
+
+fn serve(stream: TcpStream) {
+    let Some(request) = read_request(&stream) else { return };
+    let handler = server()
+        .routes
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|(prefix, _)| request.path.starts_with(prefix.as_str()))
+        .max_by_key(|(prefix, _)| prefix.len())
+        .map(|(_, handler)| handler.clone());
+    let reply = match handler {
+        Some(handler) => handler(&request),
+        None => Reply::json(404, serde_json::json!({ "success": false, "error": "no such route" })),
+    };
+
+    let mut stream = stream;
+    let _ = match reply {
+        Reply::Respond { status, headers, body } => {
+            let mut head = format!("HTTP/1.1 {} X\r\nContent-Length: {}\r\nConnection: close\r\n", status, body.len());
+            for (name, value) in headers {
+                head.push_str(&format!("{}: {}\r\n", name, value));
+            }
+            head.push_str("\r\n");
+            stream.write_all(head.as_bytes()).and_then(|()| stream.write_all(&body))
+        }
+        Reply::Stall(wait) => {
+            std::thread::sleep(wait);
+            Ok(())
+        }
+        Reply::Close => Ok(()),
+        Reply::Truncated { status, declared, sent } => {
+            let head = format!("HTTP/1.1 {} X\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n", status, declared);
+            stream.write_all(head.as_bytes()).and_then(|()| stream.write_all(&vec![b' '; sent]))
+        }
+        Reply::Trickle { declared, pause } => {
+            let head = format!("HTTP/1.1 200 X\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n", declared);
+            stream.write_all(head.as_bytes()).and_then(|()| {
+                (0..declared).try_for_each(|_| {
+                    std::thread::sleep(pause);
+                    stream.write_all(b" ").and_then(|()| stream.flush())
+                })
+            })
+        }
+    };
+    let _ = stream.flush();
+}
+
+fn read_request(stream: &TcpStream) -> Option<Received> {
+    let _ = stream.set_read_timeout(Some(Duration::from_secs(10)));
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    reader.read_line(&mut line).ok()?;
+    let path = line.split_whitespace().nth(1)?.to_string();
+
+    let mut length = 0;
+    loop {
+        line.clear();
+        reader.read_line(&mut line).ok()?;
+        let Some((name, value)) = line.trim_end().split_once(':') else { break };
+        if name.trim().eq_ignore_ascii_case("content-length") {
+            length = value.trim().parse().ok()?;
+        }
+    }
+    // Read so the client isn't cut off mid-send
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).ok()?;
+    Some(Received { path, body })
+}
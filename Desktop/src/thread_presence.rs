@@ -0,0 +1,245 @@
+//! Who is in a thread and who is typing, as the `presence` sync topic.
+//!
+//! The page's `/ws` socket hands the frames it receives to the native side
+//! (`socket_event {data}`), which keeps typing (`typing_start`/`typing_stop`,
+//! or `typing` with `isTyping`) and participants (`user_joined`/`user_left`)
+//! per thread for the threads the page is showing (`watch_presence
+//! {threadIds}`), seeded from `GET /api/chats/{id}/members`. The local user's
+//! entry carries the idle/away status from [`crate::presence`]. The page
+//! subscribes to the topic instead of polling the thread:
+//!
+//! `{connected, self: {status, manual}, threads: {id: {participants: {userId: {name, role, status?}}, typing: [userId]}}}`
+//!
+//! Typing without a stop expires after [`TYPING_TTL`]. When the socket drops
+//! (`socket_state {connected}`) every typing indicator is cleared; when it
+//! reconnects the members are fetched again and the page gets a full
+//! snapshot instead of a patch, so nothing stale survives a network blip.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use lazy_static::lazy_static;
+use serde_json::{json, Map, Value};
+use tracing::{debug, info, warn};
+
+use crate::core::sync;
+
+const TYPING_TTL: Duration = Duration::from_secs(6);
+const EXPIRY_INTERVAL: Duration = Duration::from_secs(1);
+/// Threads followed at once; the page shows one or two
+const MAX_THREADS: usize = 16;
+
+#[derive(Default)]
+struct Thread {
+    /// User id -> `{name, role}`
+    participants: BTreeMap<String, Value>,
+    /// User id -> when the indicator lapses
+    typing: BTreeMap<String, Instant>,
+}
+
+#[derive(Default)]
+struct PresenceState {
+    connected: bool,
+    threads: BTreeMap<String, Thread>,
+}
+
+lazy_static! {
+    static ref STATE: Mutex<PresenceState> = Mutex::new(PresenceState::default());
+}
+
+static EXPIRING: AtomicBool = AtomicBool::new(false);
+
+fn local_user() -> Option<String> {
+    let auth = &crate::core::state::runtime().auth;
+    auth.user_id.clone().filter(|_| auth.signed_in)
+}
+
+/// A string or number field as a string id
+fn id(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) if !s.is_empty() => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        _ => None,
+    }
+}
+
+/// Follow exactly `thread_ids`, fetching the members of the new ones
+pub fn watch(thread_ids: &[String]) {
+    let mut added = Vec::new();
+    {
+        let mut state = STATE.lock().unwrap();
+        state.threads.retain(|id, _| thread_ids.contains(id));
+        for id in thread_ids.iter().take(MAX_THREADS) {
+            if !state.threads.contains_key(id) {
+                state.threads.insert(id.clone(), Thread::default());
+                added.push(id.clone());
+            }
+        }
+    }
+    sync::publish(sync::TOPIC_PRESENCE);
+    if !added.is_empty() {
+        std::thread::spawn(move || added.iter().for_each(|id| load_members(id)));
+    }
+}
+
+fn load_members(thread_id: &str) {
+    let response = crate::protocol::get(&format!("/api/chats/{}/members", thread_id));
+    if !response.status().is_success() {
+        warn!("Could not load the members of thread {}: {}", thread_id, response.status());
+        return;
+    }
+    let body: Value = serde_json::from_slice(response.body()).unwrap_or(Value::Null);
+    let members = body["data"].as_array().cloned().unwrap_or_default();
+    {
+        let mut state = STATE.lock().unwrap();
+        let Some(thread) = state.threads.get_mut(thread_id) else { return };
+        thread.participants = members
+            .iter()
+            .filter_map(|m| Some((id(&m["userId"])?, json!({ "name": m["userName"], "role": m["userRole"] }))))
+            .collect();
+    }
+    sync::publish(sync::TOPIC_PRESENCE);
+}
+
+/// A frame the page's socket received (text or already parsed)
+pub fn socket_event(data: &Value) {
+    let event = match data {
+        Value::String(text) => serde_json::from_str(text).unwrap_or(Value::Null),
+        other => other.clone(),
+    };
+    let payload = &event["data"];
+    let Some(thread_id) = ["channel", "threadId", "chatId"]
+        .iter()
+        .find_map(|field| id(&event[*field]).or_else(|| id(&payload[*field])))
+    else {
+        return;
+    };
+    let Some(user_id) = id(&event["userId"]).or_else(|| id(&payload["userId"])) else { return };
+    let local_user = local_user();
+
+    let mut state = STATE.lock().unwrap();
+    let Some(thread) = state.threads.get_mut(&thread_id) else { return };
+    let changed = match event["type"].as_str().unwrap_or_default() {
+        "typing_start" | "typing" if payload["isTyping"].as_bool() != Some(false) => {
+            // The page shows its own typing itself
+            if local_user.as_deref() == Some(user_id.as_str()) {
+                return;
+            }
+            let fresh = !thread.typing.contains_key(&user_id);
+            thread.typing.insert(user_id, Instant::now() + TYPING_TTL);
+            drop(state);
+            expire_typing();
+            fresh
+        }
+        "typing_stop" | "typing" => thread.typing.remove(&user_id).is_some(),
+        "user_joined" | "participant_joined" => {
+            let name = ["userName", "user", "name"].iter().find_map(|field| payload[*field].as_str());
+            let entry = json!({ "name": name, "role": payload["userRole"] });
+            thread.participants.insert(user_id, entry.clone()) != Some(entry)
+        }
+        "user_left" | "participant_left" => {
+            thread.typing.remove(&user_id);
+            thread.participants.remove(&user_id).is_some()
+        }
+        _ => false,
+    };
+    if changed {
+        sync::publish(sync::TOPIC_PRESENCE);
+    }
+}
+
+/// Drop lapsed typing indicators once a second while any is shown
+fn expire_typing() {
+    if EXPIRING.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    std::thread::spawn(|| loop {
+        std::thread::sleep(EXPIRY_INTERVAL);
+        let now = Instant::now();
+        let (expired, remaining) = {
+            let mut state = STATE.lock().unwrap();
+            let mut expired = false;
+            for thread in state.threads.values_mut() {
+                let before = thread.typing.len();
+                thread.typing.retain(|_, until| *until > now);
+                expired |= thread.typing.len() != before;
+            }
+            (expired, state.threads.values().any(|thread| !thread.typing.is_empty()))
+        };
+        if expired {
+            sync::publish(sync::TOPIC_PRESENCE);
+        }
+        if !remaining {
+            EXPIRING.store(false, Ordering::SeqCst);
+            // One may have started after the check
+            if !STATE.lock().unwrap().threads.values().any(|thread| !thread.typing.is_empty())
+                || EXPIRING.swap(true, Ordering::SeqCst)
+            {
+                return;
+            }
+        }
+    });
+}
+
+/// The page's socket connected or dropped
+pub fn socket_state(connected: bool) {
+    let reload: Vec<String> = {
+        let mut state = STATE.lock().unwrap();
+        let was_connected = std::mem::replace(&mut state.connected, connected);
+        // Stops sent while the socket was down never arrive
+        state.threads.values_mut().for_each(|thread| thread.typing.clear());
+        if !connected || was_connected {
+            Vec::new()
+        } else {
+            state.threads.keys().cloned().collect()
+        }
+    };
+    if !connected {
+        debug!("Socket down; typing indicators cleared");
+        sync::publish(sync::TOPIC_PRESENCE);
+        return;
+    }
+    if reload.is_empty() {
+        sync::resend(sync::TOPIC_PRESENCE);
+        return;
+    }
+    info!("Socket reconnected; reloading presence of {} thread(s)", reload.len());
+    std::thread::spawn(move || {
+        reload.iter().for_each(|id| load_members(id));
+        // Joins and leaves during the gap were missed; replace the page's copy
+        sync::resend(sync::TOPIC_PRESENCE);
+    });
+}
+
+/// Forget everything on sign-out
+pub fn clear() {
+    *STATE.lock().unwrap() = PresenceState::default();
+    sync::publish(sync::TOPIC_PRESENCE);
+}
+
+pub fn snapshot() -> Value {
+    let me = crate::presence::current();
+    let local_user = local_user();
+    let state = STATE.lock().unwrap();
+    let threads: Map<String, Value> = state
+        .threads
+        .iter()
+        .map(|(thread_id, thread)| {
+            let participants: Map<String, Value> = thread
+                .participants
+                .iter()
+                .map(|(user_id, entry)| {
+                    let mut entry = entry.clone();
+                    if local_user.as_deref() == Some(user_id.as_str()) {
+                        entry["status"] = Value::from(me.status.as_str());
+                    }
+                    (user_id.clone(), entry)
+                })
+                .collect();
+            let typing: BTreeSet<&String> = thread.typing.keys().collect();
+            (thread_id.clone(), json!({ "participants": participants, "typing": typing }))
+        })
+        .collect();
+    json!({ "connected": state.connected, "self": me, "threads": threads })
+}
@@ -0,0 +1,186 @@
+//! Update checks against the update API, installer download and verification.
+//!
+//! The platform layers drive the flow: [`check`] finds out whether a newer
+//! version exists, [`prepare_installer`] fetches the installer through the
+//! downloader service (resuming a partial file from an earlier attempt) and
+//! verifies its SHA-256, then the platform asks for confirmation and launches
+//! it. Declining stores a snooze in the settings so the prompt doesn't return
+//! on every launch.
+
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::{info, warn};
+
+use crate::core::settings::{self, UpdateSnooze};
+use crate::core::state::{self, UpdateStatus};
+
+const UPDATE_CHECK_URL: &str = "https://workspace.wmt.in.th/api/updates/check";
+/// How long "Remind me later" keeps a version from being offered again
+const SNOOZE_DAYS: i64 = 3;
+
+#[cfg(windows)]
+const PLATFORM: &str = "windows";
+#[cfg(target_os = "macos")]
+const PLATFORM: &str = "macos";
+#[cfg(not(any(windows, target_os = "macos")))]
+const PLATFORM: &str = "linux";
+
+/// Response of the update API
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdateManifest {
+    #[serde(default)]
+    pub update_available: bool,
+    pub latest_version: String,
+    pub download_url: Option<String>,
+    /// Hex SHA-256 of the installer
+    pub checksum: Option<String>,
+    pub release_notes: Option<String>,
+    pub file_size: Option<u64>,
+}
+
+lazy_static! {
+    /// Last manifest that offered an update
+    static ref AVAILABLE: Mutex<Option<UpdateManifest>> = Mutex::new(None);
+}
+
+fn set_status(change: impl FnOnce(&mut UpdateStatus)) {
+    let mut status = state::runtime().updates.clone();
+    change(&mut status);
+    state::set_update_status(status);
+}
+
+/// Ask the update API whether a newer version exists
+pub fn check() -> Result<Option<UpdateManifest>, String> {
+    set_status(|s| s.checking = true);
+    let result = fetch_manifest();
+    set_status(|s| {
+        s.checking = false;
+        if let Ok(Some(manifest)) = &result {
+            s.available_version = Some(manifest.latest_version.clone());
+        }
+    });
+
+    let manifest = result?;
+    *AVAILABLE.lock().unwrap() = manifest.clone();
+    Ok(manifest)
+}
+
+fn fetch_manifest() -> Result<Option<UpdateManifest>, String> {
+    let client = reqwest::blocking::Client::builder()
+        .timeout(Duration::from_secs(15))
+        .user_agent(concat!("MikoWorkspace/", env!("CARGO_PKG_VERSION")))
+        .build()
+        .map_err(|e| e.to_string())?;
+    let manifest: UpdateManifest = client
+        .get(UPDATE_CHECK_URL)
+        .query(&[("version", env!("CARGO_PKG_VERSION")), ("platform", PLATFORM)])
+        .send()
+        .and_then(|response| response.error_for_status())
+        .and_then(|response| response.json())
+        .map_err(|e| format!("update check failed: {}", e))?;
+
+    let newer = is_newer(&manifest.latest_version, env!("CARGO_PKG_VERSION"));
+    info!("Update check: latest {} (current {})", manifest.latest_version, env!("CARGO_PKG_VERSION"));
+    Ok((manifest.update_available && newer).then_some(manifest))
+}
+
+/// Dotted numeric comparison; a leading `v` and pre-release suffixes are ignored
+fn is_newer(candidate: &str, current: &str) -> bool {
+    let parse = |version: &str| -> Vec<u64> {
+        version
+            .trim_start_matches('v')
+            .split(['.', '-', '+'])
+            .map_while(|part| part.parse().ok())
+            .collect()
+    };
+    parse(candidate) > parse(current)
+}
+
+/// The update found by the last [`check`], if any
+pub fn available() -> Option<UpdateManifest> {
+    AVAILABLE.lock().unwrap().clone()
+}
+
+/// Whether the user asked to be reminded later about `version`
+pub fn is_snoozed(version: &str) -> bool {
+    matches!(
+        settings::get().update_snooze,
+        Some(UpdateSnooze { version: snoozed, until }) if snoozed == version && until > chrono::Utc::now()
+    )
+}
+
+pub fn remind_later(version: &str) {
+    let snooze = UpdateSnooze {
+        version: version.to_string(),
+        until: chrono::Utc::now() + chrono::Duration::days(SNOOZE_DAYS),
+    };
+    if let Err(e) = settings::update(|s| s.update_snooze = Some(snooze)) {
+        warn!("Failed to save update reminder: {}", e);
+    }
+}
+
+/// Where the installer for `manifest` is kept while downloading
+pub fn installer_path(manifest: &UpdateManifest) -> PathBuf {
+    let name = manifest
+        .download_url
+        .as_deref()
+        .and_then(|url| url::Url::parse(url).ok())
+        .and_then(|url| url.path_segments().and_then(|mut s| s.next_back()).map(|s| s.to_string()))
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| format!("MikoWorkspace-{}", manifest.latest_version));
+    crate::paths::data_dir().join("updates").join(name)
+}
+
+/// Download the installer of the available update with `download(url, path)`
+/// and verify its checksum. A corrupt file is deleted so the next attempt
+/// starts over instead of resuming it.
+pub fn prepare_installer(
+    download: impl FnOnce(&str, &Path) -> Result<(), String>,
+) -> Result<(UpdateManifest, PathBuf), String> {
+    let manifest = available().ok_or("no update available")?;
+    let url = manifest.download_url.clone().ok_or("update has no download URL")?;
+    let checksum = manifest.checksum.clone().ok_or("update has no checksum")?;
+    let path = installer_path(&manifest);
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+
+    set_status(|s| s.downloading = true);
+    let result = download(&url, &path).and_then(|()| verify_checksum(&path, &checksum));
+    set_status(|s| s.downloading = false);
+
+    if let Err(e) = &result {
+        warn!("Update {} not ready: {}", manifest.latest_version, e);
+        if e.starts_with("checksum mismatch") {
+            let _ = std::fs::remove_file(&path);
+        }
+    }
+    result.map(|()| (manifest, path))
+}
+
+fn verify_checksum(path: &Path, expected: &str) -> Result<(), String> {
+    let mut file = std::fs::File::open(path).map_err(|e| e.to_string())?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        let n = file.read(&mut buf).map_err(|e| e.to_string())?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+
+    let actual: String = hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect();
+    if actual.eq_ignore_ascii_case(expected.trim()) {
+        info!("Installer checksum verified: {}", path.display());
+        Ok(())
+    } else {
+        Err(format!("checksum mismatch (expected {}, got {})", expected.trim(), actual))
+    }
+}
@@ -0,0 +1,608 @@
+//! Native attachment upload, so large files neither stall the renderer nor
+//! lose progress reporting.
+//!
+//! The file is streamed as `multipart/form-data` to `/api/fileupload`. The
+//! request body is a reader chaining the part header, the file and the closing
+//! boundary, wrapped so every chunk handed to the socket is counted and
+//! reported as `upload-progress {id, sent, total}`.
+//!
+//! Files from `uploads.chunked_threshold_mb` on use the ERP's chunked upload
+//! (`/api/chunked-upload/*`) instead, since one request that size rarely
+//! survives the VPN: one request per chunk, each retried on its own, with
+//! the server's upload id and the finished chunks saved to
+//! `uploads/{id}.json` in the data directory after every chunk. A failed
+//! upload, or one a quit interrupted (listed as paused after the restart),
+//! continues from the first missing chunk with `resume_upload {id}`;
+//! `cancel_upload {id}` drops it here and on the server.
+
+use std::collections::{BTreeSet, HashMap};
+use std::fs::File;
+use std::io::{Cursor, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tracing::{debug, info, warn};
+
+use crate::core::transfers::{self, TransferControl, TransferKind, TransferState, TransferUnit};
+
+const MAX_ATTEMPTS: u32 = 3;
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+const MIB: u64 = 1024 * 1024;
+/// The server buffers 32 MiB of a chunk in memory
+const MAX_CHUNK_MB: u64 = 32;
+
+#[derive(Clone)]
+pub struct UploadRequest {
+    /// Identifies the upload in progress events and for `cancel_upload`
+    pub id: String,
+    pub thread_id: String,
+    pub path: PathBuf,
+    /// ERP API base; never taken from the page (see [`UploadRequest::new`])
+    base_url: String,
+    token: Option<String>,
+}
+
+impl UploadRequest {
+    /// Upload `path` to `thread_id` with the app's API base and the signed-in
+    /// user's token, whatever the page sent along
+    pub fn new(id: String, thread_id: String, path: PathBuf) -> Self {
+        Self {
+            id,
+            thread_id,
+            path,
+            base_url: crate::protocol::api_base(),
+            token: crate::core::state::runtime().auth.token.clone(),
+        }
+    }
+}
+
+enum UploadError {
+    Cancelled,
+    /// Worth retrying: connection problems and gateway errors
+    Transient(String),
+    Failed(String),
+    /// The server no longer knows the chunked upload session (it restarted)
+    Expired,
+}
+
+/// Where a chunked upload got to, saved after every chunk
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ChunkedState {
+    id: String,
+    thread_id: String,
+    path: PathBuf,
+    base_url: String,
+    /// The server's id for the upload session
+    upload_id: String,
+    size: u64,
+    chunk_size: u64,
+    /// The file's modification time when the upload began; an edited file starts over
+    modified: Option<SystemTime>,
+    completed: BTreeSet<u64>,
+}
+
+impl ChunkedState {
+    fn chunks(&self) -> u64 {
+        self.size.div_ceil(self.chunk_size)
+    }
+
+    fn chunk_len(&self, index: u64) -> u64 {
+        self.chunk_size.min(self.size - index * self.chunk_size)
+    }
+
+    fn sent(&self) -> u64 {
+        self.completed.iter().map(|index| self.chunk_len(*index)).sum()
+    }
+}
+
+lazy_static! {
+    static ref RUNNING: Mutex<HashMap<String, Arc<AtomicBool>>> = Mutex::new(HashMap::new());
+    /// Failed and cancelled uploads, kept for `retry_transfer`
+    static ref STOPPED: Mutex<HashMap<String, UploadRequest>> = Mutex::new(HashMap::new());
+}
+
+/// Uploads as listed in [`crate::core::transfers`]; the transfer id is the upload id
+pub struct Uploads;
+
+impl TransferControl for Uploads {
+    fn resume(&self, id: &str) -> Result<(), String> {
+        resume(id, None)
+    }
+
+    fn cancel(&self, id: &str) -> Result<(), String> {
+        (cancel(id) || discard(id)).then_some(()).ok_or_else(|| format!("upload {} is not running", id))
+    }
+
+    fn retry(&self, id: &str) -> Result<(), String> {
+        let request = STOPPED.lock().unwrap().remove(id).ok_or_else(|| format!("upload {} can't be retried", id))?;
+        std::thread::spawn(move || {
+            let result = upload(&request);
+            crate::ipc::respond(None, "upload-finished", &result);
+        });
+        Ok(())
+    }
+}
+
+pub fn new_upload_id() -> String {
+    uuid::Uuid::new_v4().to_string()
+}
+
+/// Number of uploads currently running
+pub fn active_count() -> usize {
+    RUNNING.lock().unwrap().len()
+}
+
+/// Stop the upload `id`; returns false if it isn't running
+pub fn cancel(id: &str) -> bool {
+    match RUNNING.lock().unwrap().get(id) {
+        Some(flag) => {
+            flag.store(true, Ordering::SeqCst);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Continue the failed or interrupted upload `id` (`resume_upload`); a
+/// chunked one picks up at its first missing chunk
+pub fn resume(id: &str, request_id: Option<String>) -> Result<(), String> {
+    if RUNNING.lock().unwrap().contains_key(id) {
+        return Err(format!("upload {} is already running", id));
+    }
+    let stopped = STOPPED.lock().unwrap().remove(id);
+    let request = match stopped {
+        Some(request) => request,
+        // Interrupted by a quit
+        None => load_state(id)
+            .map(|state| UploadRequest::new(state.id, state.thread_id, state.path))
+            .ok_or_else(|| format!("upload {} can't be resumed", id))?,
+    };
+    // The token it started with may have been replaced since
+    let request = UploadRequest::new(request.id, request.thread_id, request.path);
+    std::thread::spawn(move || {
+        let result = upload(&request);
+        crate::ipc::respond(request_id.as_deref(), "upload-finished", &result);
+    });
+    Ok(())
+}
+
+/// Drop the stopped chunked upload `id` here and on the server; false if there is none
+pub fn discard(id: &str) -> bool {
+    if !drop_chunked(id) {
+        return false;
+    }
+    info!("Upload {} discarded", id);
+    transfers::stopped(id, TransferState::Cancelled, None);
+    true
+}
+
+fn drop_chunked(id: &str) -> bool {
+    let Some(state) = load_state(id) else { return false };
+    remove_state(id);
+    std::thread::spawn(move || {
+        let Ok(mut url) = reqwest::Url::parse(&crate::protocol::api_base()) else { return };
+        if let Ok(mut segments) = url.path_segments_mut() {
+            segments.pop_if_empty().extend(["api", "chunked-upload", &state.upload_id]);
+        }
+        let mut builder = reqwest::blocking::Client::new().delete(url);
+        if let Some(token) = crate::core::state::runtime().auth.token.clone() {
+            builder = builder.bearer_auth(token);
+        }
+        if let Err(e) = builder.send().and_then(|response| response.error_for_status()) {
+            debug!("Could not drop upload session {}: {}", state.upload_id, e);
+        }
+    });
+    true
+}
+
+/// List the chunked uploads an earlier run left unfinished, as paused transfers
+pub fn restore_interrupted() {
+    let Ok(entries) = std::fs::read_dir(state_dir()) else { return };
+    for path in entries.flatten().map(|entry| entry.path()) {
+        let state = std::fs::read(&path).ok().and_then(|bytes| serde_json::from_slice::<ChunkedState>(&bytes).ok());
+        let Some(state) = state.filter(|state| state.path.is_file()) else {
+            debug!("Dropping upload state {}", path.display());
+            let _ = std::fs::remove_file(&path);
+            continue;
+        };
+        info!("Upload {} of {} was interrupted at {}/{} chunks", state.id, state.path.display(), state.completed.len(), state.chunks());
+        transfers::started(&state.id, TransferKind::Upload, &file_name(&state.path), TransferUnit::Bytes);
+        transfers::set_path(&state.id, &state.path);
+        transfers::progress(&state.id, state.sent(), Some(state.size));
+        transfers::stopped(&state.id, TransferState::Paused, Some("Interrupted".to_string()));
+    }
+}
+
+fn state_dir() -> PathBuf {
+    crate::paths::data_dir().join("uploads")
+}
+
+fn state_path(id: &str) -> Option<PathBuf> {
+    // Ids come from the page; only plain ones name a file
+    let plain = !id.is_empty() && id.len() <= 64 && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    plain.then(|| state_dir().join(format!("{}.json", id)))
+}
+
+fn load_state(id: &str) -> Option<ChunkedState> {
+    serde_json::from_slice(&std::fs::read(state_path(id)?).ok()?).ok()
+}
+
+fn save_state(state: &ChunkedState) {
+    let Some(path) = state_path(&state.id) else { return };
+    let result = serde_json::to_vec(state)
+        .map_err(std::io::Error::other)
+        .and_then(|bytes| crate::core::write_atomic(&path, &bytes));
+    if let Err(e) = result {
+        warn!("Failed to save the state of upload {}: {}", state.id, e);
+    }
+}
+
+fn remove_state(id: &str) {
+    if let Some(path) = state_path(id) {
+        let _ = std::fs::remove_file(path);
+    }
+}
+
+/// Upload the file and return the IPC result payload, which carries the
+/// server's attachment descriptor on success
+pub fn upload(request: &UploadRequest) -> Value {
+    let cancelled = Arc::new(AtomicBool::new(false));
+    RUNNING.lock().unwrap().insert(request.id.clone(), cancelled.clone());
+    transfers::started(&request.id, TransferKind::Upload, &file_name(&request.path), TransferUnit::Bytes);
+    transfers::set_path(&request.id, &request.path);
+    let result = upload_with_retries(request, &cancelled);
+    RUNNING.lock().unwrap().remove(&request.id);
+
+    match result {
+        Ok(attachment) => {
+            info!("Uploaded {} ({})", request.path.display(), request.id);
+            crate::clipboard::uploaded(&request.path);
+            transfers::stopped(&request.id, TransferState::Completed, None);
+            json!({ "success": true, "id": request.id, "threadId": request.thread_id, "attachment": attachment })
+        }
+        Err(UploadError::Cancelled) => {
+            info!("Upload {} cancelled", request.id);
+            drop_chunked(&request.id);
+            STOPPED.lock().unwrap().insert(request.id.clone(), request.clone());
+            transfers::stopped(&request.id, TransferState::Cancelled, None);
+            json!({ "success": false, "id": request.id, "threadId": request.thread_id, "cancelled": true })
+        }
+        Err(UploadError::Expired) => {
+            let e = "the server dropped the upload session".to_string();
+            warn!("Upload of {} failed: {}", request.path.display(), e);
+            STOPPED.lock().unwrap().insert(request.id.clone(), request.clone());
+            transfers::stopped(&request.id, TransferState::Failed, Some(e.clone()));
+            json!({ "success": false, "id": request.id, "threadId": request.thread_id, "error": e })
+        }
+        Err(UploadError::Transient(e)) | Err(UploadError::Failed(e)) => {
+            warn!("Upload of {} failed: {}", request.path.display(), e);
+            STOPPED.lock().unwrap().insert(request.id.clone(), request.clone());
+            transfers::stopped(&request.id, TransferState::Failed, Some(e.clone()));
+            json!({ "success": false, "id": request.id, "threadId": request.thread_id, "error": e })
+        }
+    }
+}
+
+fn upload_with_retries(request: &UploadRequest, cancelled: &Arc<AtomicBool>) -> Result<Value, UploadError> {
+    crate::core::checked_path(&request.path).map_err(UploadError::Failed)?;
+    let size = std::fs::metadata(&request.path)
+        .map_err(|e| UploadError::Failed(format!("cannot read {}: {}", request.path.display(), e)))?
+        .len();
+    let limit_mb = crate::core::settings::get().max_upload_mb;
+    if size > limit_mb.saturating_mul(1024 * 1024) {
+        return Err(UploadError::Failed(format!("file is larger than the {} MB upload limit", limit_mb)));
+    }
+
+    let client = reqwest::blocking::Client::builder()
+        .connect_timeout(Duration::from_secs(15))
+        // Large files take as long as they take; stalls surface as connection errors
+        .timeout(None)
+        .build()
+        .map_err(|e| UploadError::Failed(e.to_string()))?;
+
+    if size >= crate::core::settings::get().uploads.chunked_threshold_mb.saturating_mul(MIB) {
+        return send_chunked(&client, request, size, cancelled);
+    }
+    with_retries(&request.id, || send(&client, request, size, cancelled))
+}
+
+/// Run `attempt` again after transient failures, backing off
+fn with_retries<T>(what: &str, mut attempt: impl FnMut() -> Result<T, UploadError>) -> Result<T, UploadError> {
+    let mut number = 1;
+    loop {
+        match attempt() {
+            Err(UploadError::Transient(e)) if number < MAX_ATTEMPTS => {
+                warn!("Upload {} attempt {} failed, retrying: {}", what, number, e);
+                std::thread::sleep(Duration::from_secs(2u64.pow(number)));
+                number += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+fn send_chunked(client: &reqwest::blocking::Client, request: &UploadRequest, size: u64, cancelled: &Arc<AtomicBool>) -> Result<Value, UploadError> {
+    let modified = std::fs::metadata(&request.path).and_then(|m| m.modified()).ok();
+    let saved = load_state(&request.id).filter(|state| state.path == request.path && state.size == size && state.modified == modified);
+    let mut state = match saved {
+        Some(state) => {
+            info!("Resuming upload {} at {}/{} chunks", request.id, state.completed.len(), state.chunks());
+            state
+        }
+        None => {
+            let chunk_size = crate::core::settings::get().uploads.chunk_size_mb.clamp(1, MAX_CHUNK_MB) * MIB;
+            let state = ChunkedState {
+                id: request.id.clone(),
+                thread_id: request.thread_id.clone(),
+                path: request.path.clone(),
+                base_url: request.base_url.clone(),
+                upload_id: init_chunked(client, request, size, chunk_size, cancelled)?,
+                size,
+                chunk_size,
+                modified,
+                completed: BTreeSet::new(),
+            };
+            save_state(&state);
+            state
+        }
+    };
+
+    let mut restarted = false;
+    let mut index = 0;
+    while index < state.chunks() {
+        if state.completed.contains(&index) {
+            index += 1;
+            continue;
+        }
+        let what = format!("{} chunk {}", request.id, index);
+        match with_retries(&what, || send_chunk(client, request, &state, index, cancelled)) {
+            Ok(_) => {
+                state.completed.insert(index);
+                save_state(&state);
+                index += 1;
+            }
+            // Its chunks went with it; start over once
+            Err(UploadError::Expired) if !restarted => {
+                warn!("Upload session of {} expired; starting over", request.id);
+                restarted = true;
+                state.upload_id = init_chunked(client, request, size, state.chunk_size, cancelled)?;
+                state.completed.clear();
+                save_state(&state);
+                index = 0;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+
+    let body = with_retries(&request.id, || {
+        post_json(client, request, "complete", &json!({ "uploadId": state.upload_id }), cancelled)
+    })?;
+    remove_state(&request.id);
+    // Shaped like the answer of `/api/fileupload`
+    let url = body["finalUrl"].as_str().unwrap_or_default();
+    Ok(json!({
+        "success": true,
+        "url": url,
+        "filename": url.rsplit('/').next(),
+        "size": size,
+        "type": content_type(&request.path),
+    }))
+}
+
+fn init_chunked(
+    client: &reqwest::blocking::Client,
+    request: &UploadRequest,
+    size: u64,
+    chunk_size: u64,
+    cancelled: &Arc<AtomicBool>,
+) -> Result<String, UploadError> {
+    let body = json!({ "filename": file_name(&request.path), "totalSize": size, "chunkSize": chunk_size });
+    let answer = with_retries(&request.id, || post_json(client, request, "init", &body, cancelled))?;
+    answer["uploadId"]
+        .as_str()
+        .map(|id| id.to_string())
+        .ok_or_else(|| UploadError::Failed("the server returned no upload id".to_string()))
+}
+
+fn post_json(
+    client: &reqwest::blocking::Client,
+    request: &UploadRequest,
+    action: &str,
+    body: &Value,
+    cancelled: &Arc<AtomicBool>,
+) -> Result<Value, UploadError> {
+    if cancelled.load(Ordering::SeqCst) {
+        return Err(UploadError::Cancelled);
+    }
+    let url = format!("{}/api/chunked-upload/{}", request.base_url.trim_end_matches('/'), action);
+    let mut builder = client.post(&url).json(body);
+    if let Some(token) = &request.token {
+        builder = builder.bearer_auth(token);
+    }
+    answer(&request.id, builder.send(), cancelled, true)
+}
+
+fn send_chunk(
+    client: &reqwest::blocking::Client,
+    request: &UploadRequest,
+    state: &ChunkedState,
+    index: u64,
+    cancelled: &Arc<AtomicBool>,
+) -> Result<Value, UploadError> {
+    if cancelled.load(Ordering::SeqCst) {
+        return Err(UploadError::Cancelled);
+    }
+
+    let length = state.chunk_len(index);
+    let mut file = File::open(&request.path).map_err(|e| UploadError::Failed(e.to_string()))?;
+    file.seek(SeekFrom::Start(index * state.chunk_size)).map_err(|e| UploadError::Failed(e.to_string()))?;
+    let boundary = format!("----MikoUpload{}", uuid::Uuid::new_v4().simple());
+    let head = format!(
+        "--{b}\r\nContent-Disposition: form-data; name=\"uploadId\"\r\n\r\n{upload}\r\n\
+         --{b}\r\nContent-Disposition: form-data; name=\"chunkIndex\"\r\n\r\n{index}\r\n\
+         --{b}\r\nContent-Disposition: form-data; name=\"chunk\"; filename=\"chunk_{index}\"\r\nContent-Type: application/octet-stream\r\n\r\n",
+        b = boundary,
+        upload = state.upload_id.replace(['\r', '\n'], ""),
+    )
+    .into_bytes();
+    let tail = format!("\r\n--{}--\r\n", boundary).into_bytes();
+    let total = head.len() as u64 + length + tail.len() as u64;
+
+    // Progress counts the whole file
+    let reader = ProgressReader {
+        inner: Cursor::new(head).chain(file.take(length)).chain(Cursor::new(tail)),
+        id: request.id.clone(),
+        sent: state.sent(),
+        total: state.size,
+        last_emit: None,
+        cancelled: cancelled.clone(),
+        webview: crate::ipc::handle(),
+    };
+
+    let url = format!("{}/api/chunked-upload/chunk", request.base_url.trim_end_matches('/'));
+    let mut builder = client
+        .post(&url)
+        .header(reqwest::header::CONTENT_TYPE, format!("multipart/form-data; boundary={}", boundary))
+        .body(reqwest::blocking::Body::sized(reader, total));
+    if let Some(token) = &request.token {
+        builder = builder.bearer_auth(token);
+    }
+    answer(&request.id, builder.send(), cancelled, true)
+}
+
+fn send(client: &reqwest::blocking::Client, request: &UploadRequest, size: u64, cancelled: &Arc<AtomicBool>) -> Result<Value, UploadError> {
+    if cancelled.load(Ordering::SeqCst) {
+        return Err(UploadError::Cancelled);
+    }
+
+    let file = File::open(&request.path).map_err(|e| UploadError::Failed(e.to_string()))?;
+    let boundary = format!("----MikoUpload{}", uuid::Uuid::new_v4().simple());
+    let head = part_header(&boundary, &request.thread_id, &request.path);
+    let tail = format!("\r\n--{}--\r\n", boundary).into_bytes();
+    let total = head.len() as u64 + size + tail.len() as u64;
+
+    let reader = ProgressReader {
+        inner: Cursor::new(head).chain(file).chain(Cursor::new(tail)),
+        id: request.id.clone(),
+        sent: 0,
+        total,
+        last_emit: None,
+        cancelled: cancelled.clone(),
+        webview: crate::ipc::handle(),
+    };
+
+    let url = format!("{}/api/fileupload", request.base_url.trim_end_matches('/'));
+    let mut builder = client
+        .post(&url)
+        .header(reqwest::header::CONTENT_TYPE, format!("multipart/form-data; boundary={}", boundary))
+        .body(reqwest::blocking::Body::sized(reader, total));
+    if let Some(token) = &request.token {
+        builder = builder.bearer_auth(token);
+    }
+    answer(&request.id, builder.send(), cancelled, false)
+}
+
+/// The server's JSON answer to a request of upload `id`, or why there is
+/// none; with `session`, 404 means the chunked upload session is gone
+fn answer(
+    id: &str,
+    result: reqwest::Result<reqwest::blocking::Response>,
+    cancelled: &Arc<AtomicBool>,
+    session: bool,
+) -> Result<Value, UploadError> {
+    let response = match result {
+        Ok(response) => response,
+        Err(_) if cancelled.load(Ordering::SeqCst) => return Err(UploadError::Cancelled),
+        Err(e) if e.is_connect() || e.is_timeout() || e.is_request() => return Err(UploadError::Transient(e.to_string())),
+        Err(e) => return Err(UploadError::Failed(e.to_string())),
+    };
+
+    let status = response.status();
+    let body: Value = response.json().unwrap_or(Value::Null);
+    debug!("Upload {} request finished with {}", id, status);
+    if status.is_success() && body["success"].as_bool() != Some(false) {
+        return Ok(body);
+    }
+
+    let message = body["error"].as_str().map(|s| s.to_string()).unwrap_or_else(|| format!("server returned {}", status));
+    match status.as_u16() {
+        404 if session => Err(UploadError::Expired),
+        502..=504 => Err(UploadError::Transient(message)),
+        _ => Err(UploadError::Failed(message)),
+    }
+}
+
+/// The file's name as sent to the server
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "file".to_string())
+        .replace(['"', '\r', '\n'], "_")
+}
+
+fn part_header(boundary: &str, thread_id: &str, path: &Path) -> Vec<u8> {
+    format!(
+        "--{b}\r\nContent-Disposition: form-data; name=\"threadId\"\r\n\r\n{thread}\r\n\
+         --{b}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"{name}\"\r\nContent-Type: {mime}\r\n\r\n",
+        b = boundary,
+        thread = thread_id.replace(['\r', '\n'], ""),
+        name = file_name(path),
+        mime = content_type(path),
+    )
+    .into_bytes()
+}
+
+fn content_type(path: &Path) -> &'static str {
+    let extension = path.extension().map(|e| e.to_string_lossy().to_ascii_lowercase()).unwrap_or_default();
+    match extension.as_str() {
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "pdf" => "application/pdf",
+        "zip" => "application/zip",
+        "txt" => "text/plain",
+        "mp4" => "video/mp4",
+        "ai" | "eps" => "application/postscript",
+        "psd" => "image/vnd.adobe.photoshop",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Counts bytes as the HTTP client pulls them and aborts once cancelled
+struct ProgressReader<R> {
+    inner: R,
+    id: String,
+    sent: u64,
+    total: u64,
+    last_emit: Option<Instant>,
+    cancelled: Arc<AtomicBool>,
+    webview: Option<crate::ipc::WebviewHandle>,
+}
+
+impl<R: Read> Read for ProgressReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.cancelled.load(Ordering::SeqCst) {
+            return Err(std::io::Error::other("upload cancelled"));
+        }
+
+        let n = self.inner.read(buf)?;
+        self.sent += n as u64;
+        // A chunk's form fields count too, so the last one may run past the file's size
+        let sent = self.sent.min(self.total);
+        let due = self.last_emit.is_none_or(|at| at.elapsed() >= PROGRESS_INTERVAL);
+        if due || sent == self.total {
+            self.last_emit = Some(Instant::now());
+            if let Some(webview) = &self.webview {
+                webview.emit_latest("upload-progress", &self.id, &json!({ "id": self.id, "sent": sent, "total": self.total }));
+            }
+            transfers::progress(&self.id, sent, Some(self.total));
+        }
+        Ok(n)
+    }
+}
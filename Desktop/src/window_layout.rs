@@ -0,0 +1,173 @@
+//! Window position presets, for keeping the chat beside the ERP.
+//!
+//! `snap_window {preset}` (and View → Window Layout) moves the main window to
+//! `left-half`, `right-half`, `right-third` or `centered` on the monitor it's
+//! on, sized to the monitor's work area so the taskbar or Dock and menu bar
+//! stay clear. `save_window_layout {name?}` keeps the current geometry under a
+//! name in `window_layouts`, as fractions of the work area plus the monitor's
+//! name, so it survives resolution changes; `snap_window` with that name, or
+//! `last-saved` for the newest, brings it back, on the same monitor if it's
+//! still connected.
+
+use serde_json::{json, Value};
+use tracing::info;
+use winit::dpi::{PhysicalPosition, PhysicalSize};
+use winit::monitor::MonitorHandle;
+use winit::window::Window;
+
+use crate::core::settings::{self, SavedLayout};
+
+/// Preset, label; the labels are the menu's, the presets double as its
+/// `snap:` commands
+pub const PRESETS: &[(&str, &str)] = &[
+    ("left-half", "Left Half"),
+    ("right-half", "Right Half"),
+    ("right-third", "Right Third"),
+    ("centered", "Centered"),
+    ("last-saved", "Last Saved Layout"),
+];
+
+/// Name `save_window_layout` uses when given none
+pub const DEFAULT_NAME: &str = "Saved layout";
+
+/// A rectangle in physical pixels
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Area {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Area {
+    fn part(self, x: f64, y: f64, width: f64, height: f64) -> Self {
+        let (w, h) = (self.width as f64, self.height as f64);
+        Self {
+            x: self.x + (x * w).round() as i32,
+            y: self.y + (y * h).round() as i32,
+            width: (width * w).round() as u32,
+            height: (height * h).round() as u32,
+        }
+    }
+}
+
+/// Where a built-in preset puts the window within `work`
+fn preset_area(preset: &str, work: Area) -> Option<Area> {
+    Some(match preset {
+        "left-half" => work.part(0.0, 0.0, 0.5, 1.0),
+        "right-half" => work.part(0.5, 0.0, 0.5, 1.0),
+        "right-third" => work.part(2.0 / 3.0, 0.0, 1.0 / 3.0, 1.0),
+        "centered" => work.part(1.0 / 6.0, 0.1, 2.0 / 3.0, 0.8),
+        _ => return None,
+    })
+}
+
+/// `layout` mapped back onto `work`
+fn saved_area(layout: &SavedLayout, work: Area) -> Area {
+    let clamp = |v: f64| v.clamp(0.0, 1.0);
+    let (x, y) = (clamp(layout.x), clamp(layout.y));
+    work.part(x, y, clamp(layout.width).min(1.0 - x), clamp(layout.height).min(1.0 - y))
+}
+
+fn monitor_name(monitor: &MonitorHandle) -> Option<String> {
+    monitor.name()
+}
+
+/// The window's monitor, else the primary one
+fn current_monitor(window: &Window) -> Result<MonitorHandle, String> {
+    window
+        .current_monitor()
+        .or_else(|| window.primary_monitor())
+        .ok_or_else(|| "no monitor found".to_string())
+}
+
+/// Handle `snap_window` and the View → Window Layout items
+pub fn snap(window: &Window, preset: &str) -> Result<(), String> {
+    let monitor = current_monitor(window)?;
+    let (monitor, target) = match preset_area(preset, work_area(&monitor)) {
+        Some(target) => (monitor, target),
+        None => {
+            let layouts = settings::get().window_layouts;
+            let name = match preset {
+                "last-saved" => layouts.last_saved.clone().ok_or("no layout was saved yet")?,
+                name => name.to_string(),
+            };
+            let layout = layouts.layouts.get(&name).ok_or_else(|| format!("unknown window layout: {}", name))?;
+            // Back on the monitor it was saved on, if that's still there
+            let monitor = window
+                .available_monitors()
+                .find(|m| layout.monitor.is_some() && monitor_name(m) == layout.monitor)
+                .unwrap_or(monitor);
+            let target = saved_area(layout, work_area(&monitor));
+            (monitor, target)
+        }
+    };
+
+    window.set_fullscreen(None);
+    window.set_maximized(false);
+    // The frame (title bar, borders) is outside the inner size
+    let (outer, inner) = (window.outer_size(), window.inner_size());
+    let frame = PhysicalSize::new(outer.width.saturating_sub(inner.width), outer.height.saturating_sub(inner.height));
+    let inner = PhysicalSize::new(target.width.saturating_sub(frame.width), target.height.saturating_sub(frame.height));
+    window.set_outer_position(PhysicalPosition::new(target.x, target.y));
+    let _ = window.request_inner_size(inner);
+    info!("Window snapped to {} on {:?}: {:?}", preset, monitor_name(&monitor), target);
+    Ok(())
+}
+
+/// Handle `save_window_layout`: keep the window's geometry as `name`
+pub fn save(window: &Window, name: Option<&str>) -> Result<String, String> {
+    let name = name.map(str::trim).filter(|n| !n.is_empty()).unwrap_or(DEFAULT_NAME).to_string();
+    if PRESETS.iter().any(|(preset, _)| *preset == name) {
+        return Err(format!("{} is a built-in preset", name));
+    }
+    let monitor = current_monitor(window)?;
+    let work = work_area(&monitor);
+    let position = window.outer_position().map_err(|e| e.to_string())?;
+    let size = window.outer_size();
+    let (w, h) = (work.width.max(1) as f64, work.height.max(1) as f64);
+    let layout = SavedLayout {
+        monitor: monitor_name(&monitor),
+        x: (position.x - work.x) as f64 / w,
+        y: (position.y - work.y) as f64 / h,
+        width: size.width as f64 / w,
+        height: size.height as f64 / h,
+    };
+    let saved = name.clone();
+    settings::update(|s| {
+        s.window_layouts.layouts.insert(saved.clone(), layout);
+        s.window_layouts.last_saved = Some(saved);
+    })
+    .map_err(|e| e.to_string())?;
+    info!("Window layout {:?} saved", name);
+    Ok(name)
+}
+
+/// Handle `list_window_layouts`
+pub fn list() -> Value {
+    let layouts = settings::get().window_layouts;
+    let presets: Vec<Value> = PRESETS.iter().map(|(preset, label)| json!({ "preset": preset, "label": label })).collect();
+    json!({ "presets": presets, "saved": layouts.layouts, "lastSaved": layouts.last_saved })
+}
+
+/// The part of `monitor` not taken by the taskbar
+#[cfg(target_os = "windows")]
+fn work_area(monitor: &MonitorHandle) -> Area {
+    crate::platform::win::utils::monitor_work_area(monitor).unwrap_or_else(|| full_area(monitor))
+}
+
+/// The part of `monitor` not taken by the Dock and menu bar
+#[cfg(target_os = "macos")]
+fn work_area(monitor: &MonitorHandle) -> Area {
+    crate::platform::mac::utils::monitor_work_area(monitor).unwrap_or_else(|| full_area(monitor))
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+fn work_area(monitor: &MonitorHandle) -> Area {
+    full_area(monitor)
+}
+
+fn full_area(monitor: &MonitorHandle) -> Area {
+    let (position, size) = (monitor.position(), monitor.size());
+    Area { x: position.x, y: position.y, width: size.width, height: size.height }
+}
@@ -0,0 +1,49 @@
+// Mock ERP - a stand-in for the chat server with scriptable failures
+//
+// Serves the endpoints the desktop app uses (login, auth/me, chats,
+// messages, fileupload, health) from memory, so the forwarding, retry,
+// offline and sign-in paths can be exercised without the real server:
+//
+//   cargo run --bin mock-erp -- --port 5669
+//
+// and point `api_base_url` in the settings at the printed address. Faults
+// apply to every request, or are changed while running:
+//
+//   curl -X POST localhost:5669/__mock/faults -d '{"status":502,"count":2}'
+//   curl -X POST localhost:5669/__mock/faults -d '{"pathPrefix":"/api/chats","latencyMs":15000}'
+//   curl -X POST localhost:5669/__mock/faults -d '{"disconnect":true}'
+//   curl -X DELETE localhost:5669/__mock/faults
+//   curl localhost:5669/__mock/requests
+//
+// The server lives in mock_erp/server.rs; `cargo test` starts it in-process
+// (see tests/mock_erp.rs and the unit tests of the forwarding).
+use clap::Parser;
+
+#[allow(dead_code)]
+#[path = "mock_erp/server.rs"]
+mod server;
+
+#[derive(Parser, Debug)]
+#[command(name = "mock-erp", about = "Stand-in chat server with scriptable failures")]
+struct Args {
+    /// Port to listen on; 0 picks a free one
+    #[arg(long, default_value_t = 5669)]
+    port: u16,
+    /// Delay every answer by this many milliseconds
+    #[arg(long, default_value_t = 0)]
+    latency_ms: u64,
+    /// Answer every request with this status instead
+    #[arg(long)]
+    fail_status: Option<u16>,
+}
+
+fn main() {
+    let args = Args::parse();
+    let runtime = tokio::runtime::Runtime::new().expect("Failed to start the runtime");
+    runtime.block_on(async {
+        let listener = tokio::net::TcpListener::bind(("127.0.0.1", args.port)).await.expect("Failed to bind");
+        println!("Mock ERP listening on http://{}", listener.local_addr().expect("bound address"));
+        let faults = server::Faults { latency_ms: args.latency_ms, status: args.fail_status, ..server::Faults::default() };
+        server::serve(listener, faults).await;
+    });
+}
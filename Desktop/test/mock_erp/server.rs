@@ -0,0 +1,381 @@
+// The mock ERP itself, shared by the `mock-erp` tool and the tests
+//
+// Everything is kept in memory. Faults apply to every request outside
+// `/__mock/`, or only to those under `pathPrefix`, and only to the next
+// `count` of them when set. Tests start one on a free port with
+// `MockErp::start` and script it directly; the tool serves the same
+// router on a fixed port and is scripted over HTTP.
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use axum::body::{Body, Bytes};
+use axum::extract::{Path, Query, Request, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{delete, get, post};
+use axum::{Json, Router};
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+pub const TOKEN: &str = "mock-token";
+pub const PASSWORD: &str = "password";
+/// How many chats there are; each starts with three messages
+pub const CHATS: usize = 5;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Faults {
+    /// Only requests whose path starts with this are affected
+    pub path_prefix: Option<String>,
+    pub latency_ms: u64,
+    /// Answer with this status instead (401, 502, ...)
+    pub status: Option<u16>,
+    /// Break the connection in the middle of the answer
+    pub disconnect: bool,
+    /// Only the next this many matching requests; all of them when unset
+    pub count: Option<u32>,
+}
+
+/// A request the mock received
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Logged {
+    pub method: String,
+    /// Path and query
+    pub path: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub idempotency_key: Option<String>,
+}
+
+#[derive(Default)]
+struct Mock {
+    faults: Faults,
+    /// Chat uuid -> messages, oldest first
+    messages: HashMap<String, Vec<Value>>,
+    /// Stored name -> contents
+    uploads: HashMap<String, Bytes>,
+    /// Every request, oldest first
+    requests: Vec<Logged>,
+}
+
+type Shared = Arc<Mutex<Mock>>;
+
+/// The uuid of chat `n` (1 to [`CHATS`])
+pub fn chat_uuid(n: usize) -> String {
+    format!("00000000-0000-4000-8000-{:012}", n)
+}
+
+fn shared(faults: Faults) -> Shared {
+    let mut mock = Mock { faults, ..Mock::default() };
+    for n in 1..=CHATS {
+        let uuid = chat_uuid(n);
+        mock.messages.insert(uuid.clone(), (1..=3).map(|id| message(&uuid, id, &format!("Message {}", id))).collect());
+    }
+    Arc::new(Mutex::new(mock))
+}
+
+fn router(state: Shared) -> Router {
+    Router::new()
+        .route("/api/login", post(login))
+        .route("/api/auth/me", get(me))
+        .route("/api/chats", get(list_chats))
+        .route("/api/chats/:uuid/messages", get(list_messages).post(send_message))
+        .route("/api/messages/:id", delete(delete_message))
+        .route("/api/fileupload", post(upload))
+        .route("/uploads/:name", get(download))
+        .route("/health", get(|| async { Json(json!({ "status": "ok" })) }))
+        .route("/__mock/faults", get(show_faults).post(set_faults).delete(clear_faults))
+        .route("/__mock/requests", get(list_requests))
+        .layer(middleware::from_fn_with_state(state.clone(), inject_faults))
+        .with_state(state)
+}
+
+/// Serve on `listener` until the process ends, starting with `faults`
+pub async fn serve(listener: tokio::net::TcpListener, faults: Faults) {
+    axum::serve(listener, router(shared(faults))).await.expect("Server failed");
+}
+
+/// A mock ERP on a free port, served from a thread of its own
+pub struct MockErp {
+    address: SocketAddr,
+    state: Shared,
+}
+
+impl MockErp {
+    pub fn start() -> Self {
+        let state = shared(Faults::default());
+        let served = state.clone();
+        let (bound, address) = std::sync::mpsc::channel();
+        std::thread::spawn(move || {
+            let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().expect("Failed to start the runtime");
+            runtime.block_on(async move {
+                let listener = tokio::net::TcpListener::bind(("127.0.0.1", 0)).await.expect("Failed to bind");
+                bound.send(listener.local_addr().expect("bound address")).unwrap();
+                axum::serve(listener, router(served)).await.expect("Server failed");
+            });
+        });
+        Self { address: address.recv().expect("The mock ERP didn't start"), state }
+    }
+
+    /// `http://127.0.0.1:{port}`
+    pub fn base(&self) -> String {
+        format!("http://{}", self.address)
+    }
+
+    pub fn set_faults(&self, faults: Faults) {
+        self.state.lock().unwrap().faults = faults;
+    }
+
+    pub fn clear_faults(&self) {
+        self.set_faults(Faults::default());
+    }
+
+    /// The requests received since the last call
+    pub fn take_requests(&self) -> Vec<Logged> {
+        std::mem::take(&mut self.state.lock().unwrap().requests)
+    }
+
+    /// Add `count` messages to chat `chat`, numbered on from its last one
+    pub fn add_messages(&self, chat: &str, count: usize) {
+        let mut mock = self.state.lock().unwrap();
+        let messages = mock.messages.entry(chat.to_string()).or_default();
+        let first = messages.len() + 1;
+        messages.extend((first..first + count).map(|id| message(chat, id, &format!("Message {}", id))));
+    }
+
+    /// The messages chat `chat` has, oldest first
+    pub fn messages(&self, chat: &str) -> Vec<Value> {
+        self.state.lock().unwrap().messages.get(chat).cloned().unwrap_or_default()
+    }
+
+    /// Store `contents` as an upload, for `/uploads/{name}`
+    pub fn add_upload(&self, name: &str, contents: &[u8]) {
+        self.state.lock().unwrap().uploads.insert(name.to_string(), Bytes::copy_from_slice(contents));
+    }
+}
+
+/// Log the request, then apply the faults that match it
+async fn inject_faults(State(state): State<Shared>, request: Request, next: Next) -> Response {
+    let path = request.uri().path().to_string();
+    let faults = {
+        let mut mock = state.lock().unwrap();
+        mock.requests.push(Logged {
+            method: request.method().to_string(),
+            path: request.uri().path_and_query().map_or(path.clone(), |p| p.to_string()),
+            idempotency_key: request.headers().get("idempotency-key").and_then(|v| v.to_str().ok()).map(|v| v.to_string()),
+        });
+        let faults = &mut mock.faults;
+        let matches = !path.starts_with("/__mock/")
+            && faults.path_prefix.as_deref().is_none_or(|prefix| path.starts_with(prefix))
+            && faults.count != Some(0);
+        if matches {
+            if let Some(count) = faults.count.as_mut() {
+                *count -= 1;
+            }
+        }
+        matches.then(|| faults.clone())
+    };
+    let Some(faults) = faults else { return next.run(request).await };
+
+    if faults.latency_ms > 0 {
+        tokio::time::sleep(Duration::from_millis(faults.latency_ms)).await;
+    }
+    if faults.disconnect {
+        // Half an answer, then the connection drops; the pause lets the
+        // half go out first
+        let half = futures_util::stream::once(async { Ok(Bytes::from_static(b"{\"success\": true, \"data\": [")) });
+        let drop = futures_util::stream::once(async {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            Err(std::io::Error::new(std::io::ErrorKind::ConnectionReset, "mock disconnect"))
+        });
+        return Response::builder()
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from_stream(half.chain(drop)))
+            .unwrap();
+    }
+    match faults.status.and_then(|status| StatusCode::from_u16(status).ok()) {
+        Some(status) => go_error(status, &format!("mock failure {}", status.as_u16())),
+        None => next.run(request).await,
+    }
+}
+
+/// Errors the way the Go server's `http.Error` sends them: JSON text labelled as plain text
+fn go_error(status: StatusCode, error: &str) -> Response {
+    let body = json!({ "success": false, "error": error }).to_string();
+    (status, [(header::CONTENT_TYPE, "text/plain; charset=utf-8")], body).into_response()
+}
+
+fn authorized(headers: &HeaderMap) -> bool {
+    headers.get(header::AUTHORIZATION).and_then(|v| v.to_str().ok()) == Some(&format!("Bearer {}", TOKEN))
+}
+
+fn user() -> Value {
+    json!({ "id": 1, "uid": "mock", "name": "Mock User", "role": "admin" })
+}
+
+fn chats() -> Vec<Value> {
+    (1..=CHATS)
+        .map(|n| {
+            json!({
+                "id": n,
+                "uuid": chat_uuid(n),
+                "channelName": format!("Mock chat {}", n),
+                "status": "PENDING",
+                "createdAt": chrono::Utc::now().to_rfc3339(),
+                "updatedAt": chrono::Utc::now().to_rfc3339(),
+            })
+        })
+        .collect()
+}
+
+fn message(chat: &str, id: usize, content: &str) -> Value {
+    json!({
+        "id": id,
+        "messageId": uuid::Uuid::new_v4().to_string(),
+        "channelId": chat,
+        "content": content,
+        "userId": 1,
+        "userName": "Mock User",
+        "attachments": [],
+        "createdAt": chrono::Utc::now().to_rfc3339(),
+    })
+}
+
+async fn login(Json(body): Json<Value>) -> Response {
+    if body["password"].as_str() != Some(PASSWORD) {
+        return go_error(StatusCode::UNAUTHORIZED, "Invalid credentials");
+    }
+    Json(json!({ "success": true, "data": { "token": TOKEN, "user": user() } })).into_response()
+}
+
+async fn me(headers: HeaderMap) -> Response {
+    if !authorized(&headers) {
+        return go_error(StatusCode::UNAUTHORIZED, "Invalid or expired token");
+    }
+    Json(json!({ "success": true, "data": user() })).into_response()
+}
+
+async fn list_chats(headers: HeaderMap) -> Response {
+    if !authorized(&headers) {
+        return go_error(StatusCode::UNAUTHORIZED, "Invalid or expired token");
+    }
+    Json(json!({ "success": true, "data": chats() })).into_response()
+}
+
+#[derive(Deserialize)]
+struct Page {
+    limit: Option<usize>,
+    /// The `id` of the last message of the previous page
+    cursor: Option<u64>,
+}
+
+/// All of a chat's messages, or `limit` of them after `cursor` with a
+/// `nextCursor` while more are left
+async fn list_messages(State(state): State<Shared>, Path(uuid): Path<String>, Query(page): Query<Page>, headers: HeaderMap) -> Response {
+    if !authorized(&headers) {
+        return go_error(StatusCode::UNAUTHORIZED, "Invalid or expired token");
+    }
+    let mock = state.lock().unwrap();
+    let Some(messages) = mock.messages.get(&uuid) else {
+        return go_error(StatusCode::NOT_FOUND, "Chat not found");
+    };
+    let after: Vec<&Value> = messages.iter().filter(|m| page.cursor.is_none_or(|cursor| m["id"].as_u64() > Some(cursor))).collect();
+    let limit = page.limit.unwrap_or(usize::MAX).max(1);
+    let data = &after[..after.len().min(limit)];
+    let next_cursor = (after.len() > limit).then(|| data.last().map(|m| m["id"].to_string())).flatten();
+    Json(json!({ "success": true, "data": data, "nextCursor": next_cursor })).into_response()
+}
+
+async fn send_message(State(state): State<Shared>, Path(uuid): Path<String>, headers: HeaderMap, Json(body): Json<Value>) -> Response {
+    if !authorized(&headers) {
+        return go_error(StatusCode::UNAUTHORIZED, "Invalid or expired token");
+    }
+    let mut mock = state.lock().unwrap();
+    let Some(messages) = mock.messages.get_mut(&uuid) else {
+        return go_error(StatusCode::NOT_FOUND, "Chat not found");
+    };
+    let id = messages.last().and_then(|m| m["id"].as_u64()).unwrap_or(0) as usize + 1;
+    let sent = message(&uuid, id, body["content"].as_str().unwrap_or_default());
+    messages.push(sent.clone());
+    (StatusCode::CREATED, Json(json!({ "success": true, "data": sent }))).into_response()
+}
+
+/// By `messageId`, or by the numeric `id`
+async fn delete_message(State(state): State<Shared>, Path(id): Path<String>, headers: HeaderMap) -> Response {
+    if !authorized(&headers) {
+        return go_error(StatusCode::UNAUTHORIZED, "Invalid or expired token");
+    }
+    let numeric = id.parse::<u64>().ok();
+    let mut mock = state.lock().unwrap();
+    for messages in mock.messages.values_mut() {
+        let before = messages.len();
+        messages.retain(|m| m["messageId"].as_str() != Some(id.as_str()) && (numeric.is_none() || m["id"].as_u64() != numeric));
+        if messages.len() < before {
+            return Json(json!({ "success": true })).into_response();
+        }
+    }
+    go_error(StatusCode::NOT_FOUND, "Message not found")
+}
+
+async fn upload(State(state): State<Shared>, headers: HeaderMap, body: Bytes) -> Response {
+    if !authorized(&headers) {
+        return go_error(StatusCode::UNAUTHORIZED, "Invalid or expired token");
+    }
+    let name = format!("{}.bin", uuid::Uuid::new_v4().simple());
+    let size = body.len();
+    state.lock().unwrap().uploads.insert(name.clone(), body);
+    Json(json!({ "success": true, "data": { "filename": name, "url": format!("/uploads/{}", name), "size": size } })).into_response()
+}
+
+/// An uploaded file; `Range: bytes={start}-` answers the rest of it
+async fn download(State(state): State<Shared>, Path(name): Path<String>, headers: HeaderMap) -> Response {
+    let Some(contents) = state.lock().unwrap().uploads.get(&name).cloned() else {
+        return go_error(StatusCode::NOT_FOUND, "File not found");
+    };
+    let start = headers
+        .get(header::RANGE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("bytes=")?.strip_suffix('-')?.parse::<usize>().ok());
+    match start {
+        None => ([(header::CONTENT_TYPE, "application/octet-stream")], contents).into_response(),
+        Some(start) if start >= contents.len() => {
+            (StatusCode::RANGE_NOT_SATISFIABLE, [(header::CONTENT_RANGE, format!("bytes */{}", contents.len()))]).into_response()
+        }
+        Some(start) => (
+            StatusCode::PARTIAL_CONTENT,
+            [
+                (header::CONTENT_TYPE, "application/octet-stream".to_string()),
+                (header::CONTENT_RANGE, format!("bytes {}-{}/{}", start, contents.len() - 1, contents.len())),
+            ],
+            contents.slice(start..),
+        )
+            .into_response(),
+    }
+}
+
+async fn show_faults(State(state): State<Shared>) -> Json<Faults> {
+    Json(state.lock().unwrap().faults.clone())
+}
+
+async fn set_faults(State(state): State<Shared>, body: Bytes) -> Response {
+    match serde_json::from_slice::<Faults>(&body) {
+        Ok(faults) => {
+            println!("Faults: {:?}", faults);
+            state.lock().unwrap().faults = faults.clone();
+            Json(faults).into_response()
+        }
+        Err(e) => (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    }
+}
+
+async fn clear_faults(State(state): State<Shared>) -> StatusCode {
+    state.lock().unwrap().faults = Faults::default();
+    StatusCode::NO_CONTENT
+}
+
+async fn list_requests(State(state): State<Shared>) -> Json<Vec<Logged>> {
+    Json(state.lock().unwrap().requests.clone())
+}
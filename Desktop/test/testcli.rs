@@ -1,8 +1,14 @@
-use std::io;
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::time::Duration;
 use serde_json::{json, Value};
 use tokio;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+use clap::{Parser, Subcommand};
 use crossterm::{
-    event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEventKind},
+    event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEvent, KeyEventKind, MouseEventKind},
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
@@ -11,15 +17,85 @@ use ratatui::{
     layout::{Constraint, Direction, Layout},
     style::{Color, Modifier, Style},
     text::{Line, Span},
-    widgets::{Block, Borders, List, ListItem, Paragraph, Wrap},
+    widgets::{Block, Borders, Paragraph, Wrap},
     Frame, Terminal,
 };
 
+const DEFAULT_ERP_URL: &str = "http://10.10.60.8:1669";
+
+/// Miko Workspace CLI - interactive chat TUI, or one-shot commands for scripting
+#[derive(Parser, Debug)]
+#[command(name = "miko-cli", version, about)]
+struct Cli {
+    /// ERP server base URL
+    #[arg(long, env = "MIKO_SERVER", default_value = DEFAULT_ERP_URL, global = true)]
+    server: String,
+
+    /// Login identifier (email/username)
+    #[arg(long, env = "MIKO_IDENTIFIER", global = true)]
+    identifier: Option<String>,
+
+    /// Login password
+    #[arg(long, env = "MIKO_PASSWORD", hide_env_values = true, global = true)]
+    password: Option<String>,
+
+    /// Existing auth token; skips the login request
+    #[arg(long, env = "MIKO_TOKEN", hide_env_values = true, global = true)]
+    token: Option<String>,
+
+    /// Runs the interactive TUI when omitted
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Send a message to a thread and print the created message as JSON
+    Send {
+        /// Thread uuid, as printed by `threads`
+        #[arg(long)]
+        thread: String,
+        /// Message text, or "-" to read it from stdin
+        #[arg(long)]
+        message: String,
+    },
+    /// List threads: uuid and name
+    Threads {
+        #[arg(long, default_value_t = 50)]
+        limit: u32,
+        /// Print the raw JSON response
+        #[arg(long)]
+        json: bool,
+    },
+    /// List the most recent messages of a thread
+    Messages {
+        /// Thread uuid, as printed by `threads`
+        #[arg(long)]
+        thread: String,
+        #[arg(long, default_value_t = 50)]
+        limit: u32,
+        /// Print the raw JSON response
+        #[arg(long)]
+        json: bool,
+    },
+}
+
 #[derive(Debug, Clone)]
 struct ChatMessage {
     sender: String,
     content: String,
     timestamp: String,
+    attachments: Vec<String>,
+}
+
+/// Updates sent from a downloaderservice reader thread back to the UI loop
+#[derive(Debug)]
+enum DownloadEvent {
+    Progress(Value),
+    Finished {
+        output_path: PathBuf,
+        result: Result<(), String>,
+    },
 }
 
 #[derive(Debug, Clone)]
@@ -56,16 +132,21 @@ struct App {
     auth_token: Option<String>,
     cursor_position: usize,
     scroll_offset: usize,
+    follow_bottom: bool,
+    max_scroll: usize,
+    viewport_height: usize,
     login_error: Option<String>,
     should_login: bool,
+    pending_thread: Option<String>,
+    status: Option<String>,
+    download_tx: Sender<DownloadEvent>,
+    download_rx: Receiver<DownloadEvent>,
 }
 
 impl App {
-    fn new() -> Self {
-        let client = reqwest::Client::builder()
-            .timeout(std::time::Duration::from_secs(30))
-            .build()
-            .expect("Failed to create HTTP client");
+    fn new(erp_base_url: String) -> Self {
+        let client = build_client();
+        let (download_tx, download_rx) = channel();
 
         Self {
             state: AppState::Login,
@@ -76,45 +157,30 @@ impl App {
             messages: Vec::new(),
             user: None,
             client,
-            erp_base_url: "http://10.10.60.8:1669".to_string(),
+            erp_base_url,
             auth_token: None,
             cursor_position: 0,
             scroll_offset: 0,
+            follow_bottom: true,
+            max_scroll: 0,
+            viewport_height: 0,
             login_error: None,
             should_login: false,
+            pending_thread: None,
+            status: None,
+            download_tx,
+            download_rx,
         }
     }
 
     async fn login(&mut self, identifier: &str, password: Option<&str>) -> Result<bool, Box<dyn std::error::Error>> {
-        let login_url = format!("{}/api/auth/login", self.erp_base_url);
-        
-        let payload = json!({
-            "identifier": identifier,
-            "password": password,
-            "createPassword": password.is_none()
-        });
-
-        println!("Attempting login to: {}", login_url);
-        println!("Payload: {}", serde_json::to_string_pretty(&payload)?);
-
-        let response = self.client
-            .post(&login_url)
-            .json(&payload)
-            .send()
-            .await?;
-
-        let status = response.status();
-        let response_text = response.text().await?;
-
-        println!("Response status: {}", status);
-        println!("Response body: {}", response_text);
-
-        if !status.is_success() {
-            self.login_error = Some(format!("Login failed: {} - {}", status, response_text));
-            return Ok(false);
-        }
-
-        let login_response: Value = serde_json::from_str(&response_text)?;
+        let login_response = match request_login(&self.client, &self.erp_base_url, identifier, password).await {
+            Ok(response) => response,
+            Err(e) => {
+                self.login_error = Some(e);
+                return Ok(false);
+            }
+        };
 
         if let (Some(success), Some(token), Some(user_data)) = (
             login_response.get("success").and_then(|v| v.as_bool()),
@@ -139,6 +205,7 @@ impl App {
                     sender: "System".to_string(),
                     content: format!("Welcome {}! You are now logged in.", self.user.as_ref().unwrap().name),
                     timestamp: chrono::Utc::now().format("%H:%M:%S").to_string(),
+                    attachments: Vec::new(),
                 });
                 
                 return Ok(true);
@@ -183,6 +250,7 @@ impl App {
                 sender: user_name,
                 content: content.to_string(),
                 timestamp: chrono::Utc::now().format("%H:%M:%S").to_string(),
+                attachments: Vec::new(),
             });
 
             // For demo purposes, add a simple echo response
@@ -193,6 +261,7 @@ impl App {
                 sender: "Echo Bot".to_string(),
                 content: format!("Echo: {}", content),
                 timestamp: chrono::Utc::now().format("%H:%M:%S").to_string(),
+                attachments: Vec::new(),
             });
         }
         
@@ -234,6 +303,49 @@ impl App {
         self.cursor_position = 0;
     }
 
+    fn scroll_up(&mut self, lines: usize) {
+        // Start from the offset actually on screen, not a stale value from before the last resize
+        self.scroll_offset = self.scroll_offset.min(self.max_scroll).saturating_sub(lines);
+        self.follow_bottom = self.max_scroll == 0;
+    }
+
+    fn scroll_down(&mut self, lines: usize) {
+        self.scroll_offset = self.scroll_offset.saturating_add(lines).min(self.max_scroll);
+        self.follow_bottom = self.scroll_offset >= self.max_scroll;
+    }
+
+    fn scroll_to_top(&mut self) {
+        self.scroll_offset = 0;
+        self.follow_bottom = self.max_scroll == 0;
+    }
+
+    fn scroll_to_bottom(&mut self) {
+        self.scroll_offset = self.max_scroll;
+        self.follow_bottom = true;
+    }
+
+    fn page_size(&self) -> usize {
+        self.viewport_height.saturating_sub(1).max(1)
+    }
+
+    /// Handles message-pane scrolling keys; returns true if the key was consumed.
+    fn handle_scroll_key(&mut self, key: &KeyEvent) -> bool {
+        let ctrl = key.modifiers.contains(event::KeyModifiers::CONTROL);
+        let half_page = (self.viewport_height / 2).max(1);
+
+        match key.code {
+            KeyCode::PageUp => self.scroll_up(self.page_size()),
+            KeyCode::PageDown => self.scroll_down(self.page_size()),
+            KeyCode::Char('u') if ctrl => self.scroll_up(half_page),
+            KeyCode::Char('d') if ctrl => self.scroll_down(half_page),
+            // Home/End move the input cursor while editing
+            KeyCode::Home if self.input_mode == InputMode::Normal => self.scroll_to_top(),
+            KeyCode::End if self.input_mode == InputMode::Normal => self.scroll_to_bottom(),
+            _ => return false,
+        }
+        true
+    }
+
     fn submit_message(&mut self) {
         let message = self.input.clone();
         self.input.clear();
@@ -257,13 +369,16 @@ impl App {
                 }
             }
             AppState::Chat => {
-                if !message.is_empty() {
+                if let Some(command) = message.strip_prefix(':') {
+                    self.run_input_command(command);
+                } else if !message.is_empty() {
                     // Add user message to chat immediately
                     let user_name = self.user.as_ref().map(|u| u.name.clone()).unwrap_or("You".to_string());
                     self.messages.push(ChatMessage {
                         sender: user_name,
                         content: message.clone(),
                         timestamp: chrono::Utc::now().format("%H:%M:%S").to_string(),
+                        attachments: Vec::new(),
                     });
 
                     // Add echo response
@@ -271,6 +386,7 @@ impl App {
                         sender: "Echo Bot".to_string(),
                         content: format!("Echo: {}", message),
                         timestamp: chrono::Utc::now().format("%H:%M:%S").to_string(),
+                        attachments: Vec::new(),
                     });
                 }
             }
@@ -279,7 +395,236 @@ impl App {
     }
 }
 
-fn ui(f: &mut Frame, app: &App) {
+impl App {
+    fn run_input_command(&mut self, command: &str) {
+        let mut parts = command.split_whitespace();
+        match (parts.next(), parts.next()) {
+            (Some("thread"), Some(thread_id)) => {
+                self.pending_thread = Some(thread_id.to_string());
+            }
+            (Some("download"), Some(number)) => match number.trim_start_matches('#').parse::<usize>() {
+                Ok(number) if number > 0 => self.start_download(number),
+                _ => self.status = Some(format!("Invalid attachment number: {}", number)),
+            },
+            _ => {
+                self.status = Some("Unknown command. Use :thread <uuid> or :download <n>".to_string());
+            }
+        }
+    }
+
+    fn attachment_count(&self) -> usize {
+        self.messages.iter().map(|m| m.attachments.len()).sum()
+    }
+
+    async fn load_thread(&mut self, thread_id: &str) {
+        let Some(token) = self.auth_token.clone() else {
+            self.status = Some("Not logged in".to_string());
+            return;
+        };
+
+        let result = match api_url(&self.erp_base_url, &["api", "chats", thread_id, "messages"]) {
+            Ok(url) => api_request(self.client.get(url).query(&[("limit", 50)]), &token).await,
+            Err(e) => Err(e),
+        };
+
+        match result {
+            Ok(response) => {
+                self.messages = response_list(&response, "messages").map(parse_api_message).collect();
+                self.scroll_to_bottom();
+                self.status = Some(format!(
+                    "Thread {}: {} messages, {} attachments",
+                    thread_id,
+                    self.messages.len(),
+                    self.attachment_count()
+                ));
+            }
+            Err(e) => self.status = Some(format!("Failed to load thread {}: {}", thread_id, e)),
+        }
+    }
+
+    /// Downloads attachment number `number` (1-based, as shown in the message pane).
+    fn start_download(&mut self, number: usize) {
+        let Some(attachment) = self.messages
+            .iter()
+            .flat_map(|m| m.attachments.iter())
+            .nth(number - 1)
+            .cloned() else {
+            self.status = Some(format!("No attachment #{}", number));
+            return;
+        };
+        let Some(token) = self.auth_token.clone() else {
+            self.status = Some("Not logged in".to_string());
+            return;
+        };
+
+        let url = match attachment_url(&self.erp_base_url, &attachment, &token) {
+            Ok(url) => url,
+            Err(e) => {
+                self.status = Some(e);
+                return;
+            }
+        };
+
+        let downloads_dir = dirs::download_dir().unwrap_or_else(|| {
+            std::env::current_dir().unwrap().join("Downloads")
+        });
+        let output_path = unique_output_path(&downloads_dir, &attachment_filename(&attachment));
+
+        self.status = Some(format!("Starting download: {}", output_path.display()));
+        spawn_downloader(url, output_path, token, self.download_tx.clone());
+    }
+
+    fn download_latest_attachment(&mut self) {
+        match self.attachment_count() {
+            0 => self.status = Some("No attachments in this thread".to_string()),
+            count => self.start_download(count),
+        }
+    }
+
+    fn handle_download_event(&mut self, event: DownloadEvent) {
+        match event {
+            DownloadEvent::Progress(progress) => {
+                if progress["status"].as_str() == Some("downloading") {
+                    self.status = Some(format!(
+                        "Downloading {} {:.0}% @ {} (ETA {})",
+                        progress["filename"].as_str().unwrap_or(""),
+                        progress["progress_percent"].as_f64().unwrap_or(0.0),
+                        progress["download_speed_human"].as_str().unwrap_or("N/A"),
+                        progress["eta_human"].as_str().unwrap_or("-"),
+                    ));
+                }
+            }
+            DownloadEvent::Finished { output_path, result } => {
+                let content = match result {
+                    Ok(()) => format!("Downloaded to {}", output_path.display()),
+                    Err(e) => format!("Download of {} failed: {}", output_path.display(), e),
+                };
+                self.status = Some(content.clone());
+                self.messages.push(ChatMessage {
+                    sender: "System".to_string(),
+                    content,
+                    timestamp: chrono::Local::now().format("%H:%M:%S").to_string(),
+                    attachments: Vec::new(),
+                });
+            }
+        }
+    }
+}
+
+fn parse_api_message(message: &Value) -> ChatMessage {
+    let created_at = message["createdAt"].as_str().unwrap_or("");
+    let timestamp = chrono::DateTime::parse_from_rfc3339(created_at)
+        .map(|t| t.with_timezone(&chrono::Local).format("%H:%M:%S").to_string())
+        .unwrap_or_else(|_| created_at.to_string());
+
+    ChatMessage {
+        sender: message["userName"].as_str().unwrap_or("Unknown").to_string(),
+        content: message["content"].as_str().unwrap_or("").to_string(),
+        timestamp,
+        attachments: message["attachments"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|a| a.as_str().map(str::to_string))
+            .collect(),
+    }
+}
+
+/// Resolves an attachment reference the same way the web client's FileAttachmentCard does.
+fn attachment_url(base_url: &str, attachment: &str, token: &str) -> Result<String, String> {
+    if attachment.starts_with("http://") || attachment.starts_with("https://") {
+        return Ok(attachment.to_string());
+    }
+    if attachment.starts_with('/') {
+        return Ok(format!("{}{}", base_url.trim_end_matches('/'), attachment));
+    }
+
+    let mut segments = vec!["api", "files", "d"];
+    segments.extend(attachment.split('/').filter(|s| !s.is_empty()));
+    let mut url = api_url(base_url, &segments)?;
+    url.query_pairs_mut().append_pair("token", token);
+    Ok(url.to_string())
+}
+
+fn attachment_filename(attachment: &str) -> String {
+    let without_query = attachment.split(['?', '#']).next().unwrap_or("");
+    match without_query.rsplit(['/', '\\']).next() {
+        Some(name) if !name.is_empty() && name != "." && name != ".." => name.to_string(),
+        _ => "download".to_string(),
+    }
+}
+
+/// Picks `dir/name`, or `dir/name (1).ext`, `dir/name (2).ext`, ... if the file already exists.
+fn unique_output_path(dir: &Path, filename: &str) -> PathBuf {
+    let candidate = dir.join(filename);
+    if !candidate.exists() {
+        return candidate;
+    }
+
+    let path = Path::new(filename);
+    let stem = path.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+    let extension = path.extension().map(|e| format!(".{}", e.to_string_lossy())).unwrap_or_default();
+
+    (1..)
+        .map(|n| dir.join(format!("{} ({}){}", stem, n, extension)))
+        .find(|p| !p.exists())
+        .unwrap()
+}
+
+fn downloader_path() -> PathBuf {
+    let name = format!("downloaderservice{}", std::env::consts::EXE_SUFFIX);
+    std::env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(|p| p.join(&name)))
+        .filter(|p| p.exists())
+        .unwrap_or_else(|| PathBuf::from(name))
+}
+
+/// Runs downloaderservice on a background thread, forwarding its JSON progress lines.
+fn spawn_downloader(url: String, output_path: PathBuf, token: String, events: Sender<DownloadEvent>) {
+    std::thread::spawn(move || {
+        let child = std::process::Command::new(downloader_path())
+            .arg(&url)
+            .arg(&output_path)
+            .arg("--header")
+            .arg(format!("Authorization: Bearer {}", token))
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn();
+
+        let mut child = match child {
+            Ok(child) => child,
+            Err(e) => {
+                let _ = events.send(DownloadEvent::Finished {
+                    output_path,
+                    result: Err(format!("Failed to start downloaderservice: {}", e)),
+                });
+                return;
+            }
+        };
+
+        let mut last_error = None;
+        if let Some(stdout) = child.stdout.take() {
+            for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+                if let Ok(progress) = serde_json::from_str::<Value>(&line) {
+                    if progress["status"].as_str() == Some("error") {
+                        last_error = progress["error"].as_str().map(str::to_string);
+                    }
+                    let _ = events.send(DownloadEvent::Progress(progress));
+                }
+            }
+        }
+
+        let result = match child.wait() {
+            Ok(status) if status.success() => Ok(()),
+            Ok(status) => Err(last_error.unwrap_or_else(|| format!("downloader exited with {}", status))),
+            Err(e) => Err(e.to_string()),
+        };
+        let _ = events.send(DownloadEvent::Finished { output_path, result });
+    });
+}
+
+fn ui(f: &mut Frame, app: &mut App) {
     match app.state {
         AppState::Login => draw_login_screen(f, app),
         AppState::Password => draw_password_screen(f, app),
@@ -398,13 +743,118 @@ fn draw_password_screen(f: &mut Frame, app: &App) {
     }
 }
 
-fn draw_chat_screen(f: &mut Frame, app: &App) {
+/// Word-wraps `text` so the first line fits `first_width` columns and the rest fit `rest_width`.
+/// Columns are display columns: CJK characters take two, Thai vowel and tone marks none.
+fn wrap_text(text: &str, first_width: usize, rest_width: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut current = String::new();
+    let mut current_width = 0;
+
+    for paragraph in text.split('\n') {
+        for mut word in paragraph.split_whitespace() {
+            loop {
+                let width = if lines.is_empty() { first_width } else { rest_width }.max(1);
+                let word_width = word.width();
+                let needed = if current_width == 0 { word_width } else { current_width + 1 + word_width };
+                if needed <= width {
+                    if current_width > 0 {
+                        current.push(' ');
+                        current_width += 1;
+                    }
+                    current.push_str(word);
+                    current_width += word_width;
+                    break;
+                }
+                if current_width > 0 {
+                    lines.push(std::mem::take(&mut current));
+                    current_width = 0;
+                    continue;
+                }
+                // Word longer than a whole line: hard-break it
+                let (head, rest) = word.split_at(split_at_width(word, width));
+                if rest.is_empty() {
+                    // One character wider than the line
+                    current.push_str(head);
+                    current_width = word_width;
+                    break;
+                }
+                lines.push(head.to_string());
+                word = rest;
+            }
+        }
+        lines.push(std::mem::take(&mut current));
+        current_width = 0;
+    }
+
+    lines
+}
+
+/// Byte offset where `text` stops fitting in `width` columns. Keeps at least
+/// one character, and combining marks with the character they belong to.
+fn split_at_width(text: &str, width: usize) -> usize {
+    let mut used = 0;
+    for (i, c) in text.char_indices() {
+        let c_width = c.width().unwrap_or(0);
+        if i > 0 && c_width > 0 && used + c_width > width {
+            return i;
+        }
+        used += c_width;
+    }
+    text.len()
+}
+
+/// `first_attachment` is the display number of the message's first attachment.
+fn message_lines(message: &ChatMessage, width: usize, first_attachment: usize) -> Vec<Line<'static>> {
+    let timestamp = format!("[{}] ", message.timestamp);
+    let sender = format!("{}: ", message.sender);
+    let prefix_len = timestamp.width() + sender.width();
+    let indent = 2;
+
+    let wrapped = wrap_text(
+        &message.content,
+        width.saturating_sub(prefix_len),
+        width.saturating_sub(indent),
+    );
+
+    let attachment_lines = message.attachments.iter().enumerate().map(|(i, attachment)| {
+        Line::from(vec![
+            Span::raw(" ".repeat(indent)),
+            Span::styled(
+                format!("📎 #{} {}", first_attachment + i, attachment_filename(attachment)),
+                Style::default().fg(Color::Yellow),
+            ),
+        ])
+    });
+
+    wrapped
+        .into_iter()
+        .enumerate()
+        .map(|(i, text)| {
+            if i == 0 {
+                Line::from(vec![
+                    Span::styled(timestamp.clone(), Style::default().fg(Color::Gray)),
+                    Span::styled(
+                        sender.clone(),
+                        Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD),
+                    ),
+                    Span::raw(text),
+                ])
+            } else {
+                Line::from(vec![Span::raw(" ".repeat(indent)), Span::raw(text)])
+            }
+        })
+        .chain(attachment_lines)
+        .collect()
+}
+
+fn draw_chat_screen(f: &mut Frame, app: &mut App) {
     let chunks = Layout::default()
         .direction(Direction::Vertical)
         .margin(1)
         .constraints([
             Constraint::Length(3),
             Constraint::Min(1),
+            Constraint::Length(1),
             Constraint::Length(3),
         ])
         .split(f.area());
@@ -422,30 +872,54 @@ fn draw_chat_screen(f: &mut Frame, app: &App) {
     f.render_widget(header, chunks[0]);
 
     // Messages
-    let messages: Vec<ListItem> = app
+    let inner_width = chunks[1].width.saturating_sub(2) as usize;
+    let viewport_height = chunks[1].height.saturating_sub(2) as usize;
+    let mut next_attachment = 1;
+    let lines: Vec<Line> = app
         .messages
         .iter()
-        .skip(app.scroll_offset)
-        .map(|m| {
-            let content = Line::from(vec![
-                Span::styled(
-                    format!("[{}] ", m.timestamp),
-                    Style::default().fg(Color::Gray),
-                ),
-                Span::styled(
-                    format!("{}: ", m.sender),
-                    Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD),
-                ),
-                Span::raw(&m.content),
-            ]);
-            ListItem::new(content)
+        .flat_map(|m| {
+            let lines = message_lines(m, inner_width, next_attachment);
+            next_attachment += m.attachments.len();
+            lines
         })
         .collect();
 
-    let messages_list = List::new(messages)
-        .block(Block::default().borders(Borders::ALL).title("Messages"));
+    let total_lines = lines.len();
+    app.viewport_height = viewport_height;
+    app.max_scroll = total_lines.saturating_sub(viewport_height);
+    if app.follow_bottom {
+        app.scroll_offset = app.max_scroll;
+    } else {
+        app.scroll_offset = app.scroll_offset.min(app.max_scroll);
+    }
+
+    let visible: Vec<Line> = lines
+        .into_iter()
+        .skip(app.scroll_offset)
+        .take(viewport_height)
+        .collect();
+
+    let messages_title = if total_lines == 0 {
+        "Messages".to_string()
+    } else {
+        let first = app.scroll_offset + 1;
+        let last = (app.scroll_offset + viewport_height).min(total_lines);
+        let position = if app.follow_bottom { "bottom" } else { "scrolled - End to follow" };
+        format!("Messages [{}-{}/{}] ({})", first, last, total_lines, position)
+    };
+
+    let messages_list = Paragraph::new(visible)
+        .block(Block::default().borders(Borders::ALL).title(messages_title));
     f.render_widget(messages_list, chunks[1]);
 
+    // Status bar
+    let status_text = app.status.clone().unwrap_or_else(|| {
+        ":thread <uuid> to open a thread, :download <n> or 'd' (normal mode) for the latest attachment".to_string()
+    });
+    let status = Paragraph::new(status_text).style(Style::default().fg(Color::Magenta));
+    f.render_widget(status, chunks[2]);
+
     // Input
     let input_title = match app.input_mode {
         InputMode::Normal => "Type your message - Press Enter to edit (Esc for normal mode, 'q' or Ctrl+C to quit)",
@@ -458,17 +932,179 @@ fn draw_chat_screen(f: &mut Frame, app: &App) {
             InputMode::Editing => Style::default().fg(Color::Yellow),
         })
         .block(Block::default().borders(Borders::ALL).title(input_title));
-    f.render_widget(input, chunks[2]);
+    f.render_widget(input, chunks[3]);
 
     if app.input_mode == InputMode::Editing {
         f.set_cursor_position((
-            chunks[2].x + app.cursor_position as u16 + 1,
-            chunks[2].y + 1,
+            chunks[3].x + app.cursor_position as u16 + 1,
+            chunks[3].y + 1,
         ));
     }
 }
 
+fn build_client() -> reqwest::Client {
+    reqwest::Client::builder()
+        .timeout(std::time::Duration::from_secs(30))
+        .build()
+        .expect("Failed to create HTTP client")
+}
+
+/// Posts credentials to the ERP login endpoint and returns the parsed response body.
+async fn request_login(
+    client: &reqwest::Client,
+    base_url: &str,
+    identifier: &str,
+    password: Option<&str>,
+) -> Result<Value, String> {
+    let login_url = format!("{}/api/auth/login", base_url.trim_end_matches('/'));
+
+    let payload = json!({
+        "identifier": identifier,
+        "password": password,
+        "createPassword": password.is_none()
+    });
+
+    let response = client
+        .post(&login_url)
+        .json(&payload)
+        .send()
+        .await
+        .map_err(|e| format!("Login request failed: {}", e))?;
+
+    let status = response.status();
+    let response_text = response.text().await.map_err(|e| format!("Login request failed: {}", e))?;
+
+    if !status.is_success() {
+        return Err(format!("Login failed: {} - {}", status, response_text));
+    }
+
+    serde_json::from_str(&response_text).map_err(|e| format!("Login failed: invalid response: {}", e))
+}
+
+fn api_url(base_url: &str, segments: &[&str]) -> Result<reqwest::Url, String> {
+    let mut url = reqwest::Url::parse(base_url).map_err(|e| format!("Invalid server URL '{}': {}", base_url, e))?;
+    url.path_segments_mut()
+        .map_err(|_| format!("Invalid server URL '{}'", base_url))?
+        .pop_if_empty()
+        .extend(segments);
+    Ok(url)
+}
+
+/// Sends an authenticated API request and returns the JSON body, or an error for non-2xx responses.
+async fn api_request(request: reqwest::RequestBuilder, token: &str) -> Result<Value, String> {
+    let response = request
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await
+        .map_err(|e| format!("Request failed: {}", e))?;
+
+    let status = response.status();
+    let body = response.text().await.map_err(|e| format!("Request failed: {}", e))?;
+
+    if !status.is_success() {
+        return Err(format!("HTTP {}: {}", status, body));
+    }
+
+    serde_json::from_str(&body).map_err(|e| format!("Invalid JSON response: {}", e))
+}
+
+/// The list in an API response: under `key`, or under `data` as the Go server sends it.
+fn response_list<'a>(response: &'a Value, key: &str) -> impl Iterator<Item = &'a Value> {
+    response.get(key).or_else(|| response.get("data")).and_then(Value::as_array).into_iter().flatten()
+}
+
+async fn resolve_token(client: &reqwest::Client, cli: &Cli) -> Result<String, String> {
+    if let Some(token) = &cli.token {
+        return Ok(token.clone());
+    }
+
+    let identifier = cli.identifier.as_deref()
+        .ok_or("No credentials: pass --token or --identifier/--password (or MIKO_TOKEN / MIKO_IDENTIFIER / MIKO_PASSWORD)")?;
+    // Without a password the login would create one on the account
+    let password = cli.password.as_deref()
+        .ok_or("--identifier needs --password (or MIKO_PASSWORD)")?;
+
+    let response = request_login(client, &cli.server, identifier, Some(password)).await?;
+    match (response["success"].as_bool(), response["token"].as_str()) {
+        (Some(true), Some(token)) => Ok(token.to_string()),
+        _ => Err("Login failed: Invalid response format".to_string()),
+    }
+}
+
+/// Runs a one-shot subcommand, printing results to stdout.
+async fn run_command(cli: &Cli, command: &Command) -> Result<(), String> {
+    let client = build_client();
+    let token = resolve_token(&client, cli).await?;
+
+    match command {
+        Command::Send { thread, message } => {
+            let content = if message == "-" {
+                let mut buffer = String::new();
+                io::stdin().read_to_string(&mut buffer).map_err(|e| format!("Failed to read stdin: {}", e))?;
+                buffer.trim_end().to_string()
+            } else {
+                message.clone()
+            };
+            if content.is_empty() {
+                return Err("Message is empty".to_string());
+            }
+
+            let url = api_url(&cli.server, &["api", "chats", thread, "messages"])?;
+            let response = api_request(client.post(url).json(&json!({ "content": content })), &token).await?;
+            println!("{}", json!({ "success": true, "thread": thread, "response": response }));
+        }
+        Command::Threads { limit, json } => {
+            let url = api_url(&cli.server, &["api", "chats"])?;
+            let response = api_request(client.get(url).query(&[("limit", limit)]), &token).await?;
+
+            if *json {
+                println!("{}", response);
+            } else {
+                for chat in response_list(&response, "chats") {
+                    // Messages are addressed by the chat's uuid
+                    let id = chat.get("uuid").unwrap_or(&chat["id"]);
+                    println!(
+                        "{}\t{}",
+                        id.to_string().trim_matches('"'),
+                        chat["channelName"].as_str().unwrap_or("")
+                    );
+                }
+            }
+        }
+        Command::Messages { thread, limit, json } => {
+            let url = api_url(&cli.server, &["api", "chats", thread, "messages"])?;
+            let response = api_request(client.get(url).query(&[("limit", limit)]), &token).await?;
+
+            if *json {
+                println!("{}", response);
+            } else {
+                for message in response_list(&response, "messages") {
+                    println!(
+                        "[{}] {}: {}",
+                        message["createdAt"].as_str().unwrap_or(""),
+                        message["userName"].as_str().unwrap_or("Unknown"),
+                        message["content"].as_str().unwrap_or("")
+                    );
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let cli = Cli::parse();
+
+    if let Some(command) = &cli.command {
+        let rt = tokio::runtime::Runtime::new()?;
+        if let Err(e) = rt.block_on(run_command(&cli, command)) {
+            println!("{}", json!({ "success": false, "error": e }));
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
     // Setup terminal
     enable_raw_mode()?;
     let mut stdout = io::stdout();
@@ -477,7 +1113,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let mut terminal = Terminal::new(backend)?;
 
     // Create app and run it
-    let mut app = App::new();
+    let mut app = App::new(cli.server.clone());
     let res = {
         let rt = tokio::runtime::Runtime::new()?;
         rt.block_on(run_app(&mut terminal, &mut app))
@@ -506,8 +1142,21 @@ async fn run_app<B: Backend>(
     loop {
         terminal.draw(|f| ui(f, app))?;
 
-        if let Event::Key(key) = event::read()? {
-            if key.kind == KeyEventKind::Press {
+        while let Ok(download_event) = app.download_rx.try_recv() {
+            app.handle_download_event(download_event);
+        }
+
+        // Poll so download progress keeps redrawing without key presses
+        if !event::poll(Duration::from_millis(200))? {
+            continue;
+        }
+
+        match event::read()? {
+            Event::Key(key) if key.kind == KeyEventKind::Press => {
+                if app.state == AppState::Chat && app.handle_scroll_key(&key) {
+                    continue;
+                }
+
                 match app.input_mode {
                     InputMode::Normal => match key.code {
                         KeyCode::Enter | KeyCode::Char('e') => {
@@ -516,6 +1165,9 @@ async fn run_app<B: Backend>(
                         KeyCode::Char('q') => {
                             return Ok(());
                         }
+                        KeyCode::Char('d') if app.state == AppState::Chat => {
+                            app.download_latest_attachment();
+                        }
                         KeyCode::Char('c') if key.modifiers.contains(event::KeyModifiers::CONTROL) => {
                             return Ok(());
                         }
@@ -540,6 +1192,12 @@ async fn run_app<B: Backend>(
                         KeyCode::Right => {
                             app.move_cursor_right();
                         }
+                        KeyCode::Home => {
+                            app.cursor_position = 0;
+                        }
+                        KeyCode::End => {
+                            app.cursor_position = app.input.len();
+                        }
                         KeyCode::Esc => {
                             app.input_mode = InputMode::Normal;
                         }
@@ -547,6 +1205,16 @@ async fn run_app<B: Backend>(
                     },
                 }
             }
+            Event::Mouse(mouse) if app.state == AppState::Chat => match mouse.kind {
+                MouseEventKind::ScrollUp => app.scroll_up(3),
+                MouseEventKind::ScrollDown => app.scroll_down(3),
+                _ => {}
+            },
+            _ => {}
+        }
+
+        if let Some(thread_id) = app.pending_thread.take() {
+            app.load_thread(&thread_id).await;
         }
 
         // Handle login attempt
@@ -569,4 +1237,42 @@ async fn run_app<B: Backend>(
             return Ok(());
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn widths(lines: &[String]) -> Vec<usize> {
+        lines.iter().map(|line| line.width()).collect()
+    }
+
+    #[test]
+    fn wraps_by_display_width() {
+        // Tone marks and vowels above/below take no column
+        let thai = "สวัสดีครับ ยินดีต้อนรับ";
+        assert_eq!(thai.width(), 16);
+        assert_eq!(wrap_text(thai, 12, 12), vec!["สวัสดีครับ", "ยินดีต้อนรับ"]);
+
+        // Each of these takes two columns
+        let lines = wrap_text("你好世界 こんにちは", 8, 8);
+        assert_eq!(lines, vec!["你好世界", "こんにち", "は"]);
+        assert!(widths(&lines).iter().all(|&width| width <= 8));
+    }
+
+    #[test]
+    fn hard_breaks_keep_marks_with_their_letter() {
+        let lines = wrap_text("กี่กี่กี่", 2, 2);
+        assert_eq!(lines, vec!["กี่กี่", "กี่"]);
+        assert_eq!(lines.concat(), "กี่กี่กี่");
+
+        // A wide character never gets split, even when the line is narrower
+        assert_eq!(wrap_text("漢字", 1, 1), vec!["漢", "字"]);
+    }
+
+    #[test]
+    fn first_line_leaves_room_for_the_prefix() {
+        let lines = wrap_text("aaa bbb ccc ddd", 3, 7);
+        assert_eq!(lines, vec!["aaa", "bbb ccc", "ddd"]);
+        assert_eq!(wrap_text("one\n\ntwo", 10, 10), vec!["one", "", "two"]);
+    }
+}
@@ -0,0 +1,214 @@
+//! The mock ERP (test/mock_erp) and the downloader service run against it.
+//!
+//! Each test starts a mock of its own on a free port, so they run side by
+//! side. The downloader runs as the built binary, in portable mode so its
+//! logs stay in the target directory.
+
+use std::path::PathBuf;
+use std::process::Command;
+use std::time::{Duration, Instant};
+use reqwest::blocking::Client;
+use reqwest::StatusCode;
+use serde_json::{json, Value};
+
+#[allow(dead_code)]
+#[path = "../test/mock_erp/server.rs"]
+mod server;
+
+use server::{Faults, MockErp};
+
+fn client() -> Client {
+    Client::builder().timeout(Duration::from_secs(10)).build().unwrap()
+}
+
+fn authorized(client: &Client, method: reqwest::Method, url: String) -> reqwest::blocking::RequestBuilder {
+    client.request(method, url).bearer_auth(server::TOKEN)
+}
+
+/// A scratch directory, removed on drop
+struct Scratch(PathBuf);
+
+impl Scratch {
+    fn new() -> Self {
+        let dir = std::env::temp_dir().join(format!("miko-mock-erp-{}", uuid::Uuid::new_v4().simple()));
+        std::fs::create_dir_all(&dir).unwrap();
+        Self(dir)
+    }
+}
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+#[test]
+fn serves_the_endpoints_the_app_uses() {
+    let mock = MockErp::start();
+    let (client, base) = (client(), mock.base());
+    let chat = server::chat_uuid(1);
+
+    let login = client.post(format!("{}/api/login", base)).json(&json!({ "uid": "mock", "password": "wrong" })).send().unwrap();
+    assert_eq!(login.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(login.headers()["content-type"], "text/plain; charset=utf-8", "errors are labelled like the Go server's");
+    let login: Value = client.post(format!("{}/api/login", base)).json(&json!({ "uid": "mock", "password": server::PASSWORD })).send().unwrap().json().unwrap();
+    assert_eq!(login["data"]["token"], server::TOKEN);
+
+    assert_eq!(client.get(format!("{}/api/auth/me", base)).send().unwrap().status(), StatusCode::UNAUTHORIZED);
+    let me: Value = authorized(&client, reqwest::Method::GET, format!("{}/api/auth/me", base)).send().unwrap().json().unwrap();
+    assert_eq!(me["data"]["uid"], "mock");
+    let chats: Value = authorized(&client, reqwest::Method::GET, format!("{}/api/chats", base)).send().unwrap().json().unwrap();
+    assert_eq!(chats["data"].as_array().unwrap().len(), server::CHATS);
+
+    let sent = authorized(&client, reqwest::Method::POST, format!("{}/api/chats/{}/messages", base, chat)).json(&json!({ "content": "hello" })).send().unwrap();
+    assert_eq!(sent.status(), StatusCode::CREATED);
+    let messages: Value = authorized(&client, reqwest::Method::GET, format!("{}/api/chats/{}/messages", base, chat)).send().unwrap().json().unwrap();
+    assert_eq!(messages["data"].as_array().unwrap().len(), 4);
+    assert_eq!(messages["data"][3]["content"], "hello");
+    assert!(messages["nextCursor"].is_null());
+    let missing = authorized(&client, reqwest::Method::GET, format!("{}/api/chats/nope/messages", base)).send().unwrap();
+    assert_eq!(missing.status(), StatusCode::NOT_FOUND);
+
+    let deleted = authorized(&client, reqwest::Method::DELETE, format!("{}/api/messages/{}", base, messages["data"][0]["messageId"].as_str().unwrap())).send().unwrap();
+    assert_eq!(deleted.status(), StatusCode::OK);
+    let deleted_again = authorized(&client, reqwest::Method::DELETE, format!("{}/api/messages/{}", base, messages["data"][0]["messageId"].as_str().unwrap())).send().unwrap();
+    assert_eq!(deleted_again.status(), StatusCode::NOT_FOUND);
+    assert_eq!(mock.messages(&chat).len(), 3);
+
+    let uploaded: Value = authorized(&client, reqwest::Method::POST, format!("{}/api/fileupload", base)).body(vec![7u8; 1000]).send().unwrap().json().unwrap();
+    assert_eq!(uploaded["data"]["size"], 1000);
+    let file = client.get(format!("{}{}", base, uploaded["data"]["url"].as_str().unwrap())).send().unwrap().bytes().unwrap();
+    assert_eq!(&file[..], &[7u8; 1000][..]);
+
+    let health: Value = client.get(format!("{}/health", base)).send().unwrap().json().unwrap();
+    assert_eq!(health["status"], "ok");
+}
+
+#[test]
+fn pages_messages_by_cursor() {
+    let mock = MockErp::start();
+    let client = client();
+    let chat = server::chat_uuid(2);
+    mock.add_messages(&chat, 9);
+
+    let mut ids = Vec::new();
+    let mut cursor: Option<String> = None;
+    loop {
+        let mut request = authorized(&client, reqwest::Method::GET, format!("{}/api/chats/{}/messages", mock.base(), chat)).query(&[("limit", "5")]);
+        if let Some(cursor) = &cursor {
+            request = request.query(&[("cursor", cursor)]);
+        }
+        let page: Value = request.send().unwrap().json().unwrap();
+        ids.extend(page["data"].as_array().unwrap().iter().map(|m| m["id"].as_u64().unwrap()));
+        match page["nextCursor"].as_str() {
+            Some(next) => cursor = Some(next.to_string()),
+            None => break,
+        }
+    }
+    assert_eq!(ids, (1..=12).collect::<Vec<_>>());
+    let paths: Vec<String> = mock.take_requests().into_iter().map(|r| r.path).collect();
+    assert_eq!(paths.len(), 3);
+    assert!(paths[2].ends_with("limit=5&cursor=10"), "{:?}", paths);
+}
+
+#[test]
+fn faults_are_scripted_by_path_and_count() {
+    let mock = MockErp::start();
+    let (client, base) = (client(), mock.base());
+    let health = || client.get(format!("{}/health", base)).send().unwrap().status();
+
+    mock.set_faults(Faults { status: Some(503), count: Some(2), ..Faults::default() });
+    assert_eq!([health(), health(), health()], [StatusCode::SERVICE_UNAVAILABLE, StatusCode::SERVICE_UNAVAILABLE, StatusCode::OK]);
+
+    mock.set_faults(Faults { path_prefix: Some("/api/".to_string()), status: Some(401), ..Faults::default() });
+    assert_eq!(health(), StatusCode::OK);
+    assert_eq!(client.get(format!("{}/api/chats", base)).bearer_auth(server::TOKEN).send().unwrap().status(), StatusCode::UNAUTHORIZED);
+
+    mock.set_faults(Faults { latency_ms: 300, ..Faults::default() });
+    let started = Instant::now();
+    assert_eq!(health(), StatusCode::OK);
+    assert!(started.elapsed() >= Duration::from_millis(300));
+
+    mock.set_faults(Faults { disconnect: true, ..Faults::default() });
+    let response = client.get(format!("{}/health", base)).send().unwrap();
+    assert!(response.bytes().is_err(), "the answer breaks off");
+
+    // The same over HTTP, as the tool is scripted
+    let set = client.post(format!("{}/__mock/faults", base)).body(r#"{"status":502,"count":1}"#).send().unwrap();
+    assert_eq!(set.status(), StatusCode::OK);
+    let shown: Value = client.get(format!("{}/__mock/faults", base)).send().unwrap().json().unwrap();
+    assert_eq!((shown["status"].clone(), shown["count"].clone()), (json!(502), json!(1)));
+    assert_eq!(health(), StatusCode::BAD_GATEWAY);
+    assert_eq!(health(), StatusCode::OK);
+    assert_eq!(client.delete(format!("{}/__mock/faults", base)).send().unwrap().status(), StatusCode::NO_CONTENT);
+
+    let logged: Value = client.get(format!("{}/__mock/requests", base)).send().unwrap().json().unwrap();
+    let logged: Vec<&Value> = logged.as_array().unwrap().iter().filter(|r| r["path"] == "/health").collect();
+    assert_eq!(logged.len(), 8);
+}
+
+/// Run the downloader; its last line of output, and whether it succeeded
+fn download(url: &str, output: &std::path::Path, extra: &[&str]) -> (Value, bool) {
+    let output = Command::new(env!("CARGO_BIN_EXE_downloaderservice"))
+        .arg(url)
+        .arg(output)
+        .args(["-H", &format!("Authorization: Bearer {}", server::TOKEN), "--download-id", "dl-1"])
+        .args(extra)
+        .env("MIKO_PORTABLE", "1")
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let last = stdout.lines().last().unwrap_or_default();
+    (serde_json::from_str(last).unwrap_or_else(|e| panic!("{}: {:?}", e, stdout)), output.status.success())
+}
+
+#[test]
+fn the_downloader_fetches_and_resumes_from_the_mock() {
+    let mock = MockErp::start();
+    let scratch = Scratch::new();
+    let contents: Vec<u8> = (0..200_000u32).map(|n| (n % 251) as u8).collect();
+    mock.add_upload("report.pdf", &contents);
+    let url = format!("{}/uploads/report.pdf", mock.base());
+
+    let target = scratch.0.join("report.pdf");
+    let (last, ok) = download(&url, &target, &[]);
+    assert!(ok, "{}", last);
+    assert_eq!((last["status"].clone(), last["download_id"].clone()), (json!("success"), json!("dl-1")));
+    assert_eq!(std::fs::read(&target).unwrap(), contents);
+
+    // Half a file left by an interrupted run is continued with a Range request
+    std::fs::write(&target, &contents[..75_000]).unwrap();
+    mock.take_requests();
+    let (last, ok) = download(&url, &target, &["--resume"]);
+    assert!(ok, "{}", last);
+    assert_eq!(std::fs::read(&target).unwrap(), contents);
+    assert_eq!(mock.take_requests().len(), 1);
+
+    // Nothing left to fetch
+    let (last, ok) = download(&url, &target, &["--resume"]);
+    assert!(ok, "{}", last);
+    assert_eq!(std::fs::read(&target).unwrap().len(), contents.len());
+}
+
+#[test]
+fn the_downloader_reports_server_failures() {
+    let mock = MockErp::start();
+    let scratch = Scratch::new();
+    mock.add_upload("big.zip", &[1u8; 50_000]);
+    let url = format!("{}/uploads/big.zip", mock.base());
+
+    mock.set_faults(Faults { status: Some(503), count: Some(1), ..Faults::default() });
+    let (last, ok) = download(&url, &scratch.0.join("a.zip"), &[]);
+    assert!(!ok);
+    assert_eq!(last["status"], "error");
+    assert!(last["error"].as_str().unwrap().contains("503"), "{}", last);
+
+    mock.set_faults(Faults { disconnect: true, count: Some(1), ..Faults::default() });
+    let (last, ok) = download(&url, &scratch.0.join("b.zip"), &[]);
+    assert!(!ok);
+    assert!(last["error"].as_str().unwrap().contains("Stream error"), "{}", last);
+
+    let (last, ok) = download(&format!("{}/uploads/missing.zip", mock.base()), &scratch.0.join("c.zip"), &[]);
+    assert!(!ok);
+    assert!(last["error"].as_str().unwrap().contains("404"), "{}", last);
+}
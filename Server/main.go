@@ -46,6 +46,7 @@ func main() {
 
 	// Public routes
 	r.Post("/api/login", loginHandler)
+	r.Get("/api/version", versionHandler)
 	
 	// Download routes with flexible auth (supports header OR query param)
 	r.With(flexibleAuthMiddleware).Get("/api/files/download", downloadFileHandler)
@@ -343,6 +344,21 @@ func createQueueHandler(w http.ResponseWriter, r *http.Request) {
 		Data:    insertedQueue,
 	})
 }
+// APIVersion is the version of the HTTP API. Bump the minor for additions
+// and the major for changes that break existing clients; desktops compare it
+// against the range they were built for.
+const APIVersion = "1.0.0"
+
+func versionHandler(w http.ResponseWriter, r *http.Request) {
+	w.Header().Set("Content-Type", "application/json")
+	json.NewEncoder(w).Encode(APIResponse{
+		Success: true,
+		Data: map[string]interface{}{
+			"apiVersion": APIVersion,
+		},
+	})
+}
+
 func wsStatusHandler(w http.ResponseWriter, r *http.Request) {
 	connectedClients := GetConnectedClients()
 	
@@ -15,6 +15,8 @@ interface SubprocessProgress {
   eta_human: string;
   status: 'downloading' | 'completed' | 'error';
   error: string | null;
+  // Given out when the download was accepted; tells concurrent downloads apart
+  download_id?: string;
 }
 
 export interface DownloadProgress {
@@ -30,6 +32,8 @@ export interface DownloadProgress {
 // Global download state - shared across all hook instances
 const globalDownloads = new Map<string, DownloadProgress>();
 const listeners = new Set<() => void>();
+// Download id -> URL the download was started for
+const downloadUrls = new Map<string, string>();
 
 // Notify all listeners of state change
 function notifyListeners() {
@@ -60,8 +64,9 @@ if (typeof window !== 'undefined' && !window.downloadProgressCallback) {
       status: progress.status
     });
     
+    const url = (progress.download_id && downloadUrls.get(progress.download_id)) || progress.url;
     const downloadProgress: DownloadProgress = {
-      url: progress.url,
+      url,
       filename: progress.filename,
       progress: Math.round(progress.progress_percent),
       status: progress.status === 'downloading' ? 'downloading' :
@@ -72,7 +77,7 @@ if (typeof window !== 'undefined' && !window.downloadProgressCallback) {
       eta: progress.eta_human
     };
     
-    updateDownload(progress.url, downloadProgress);
+    updateDownload(url, downloadProgress);
   };
 }
 
@@ -115,6 +120,9 @@ export function useDownload() {
         
         if (result.success) {
           console.log('✅ Download IPC sent:', result.message);
+          if (result.downloadId) {
+            downloadUrls.set(result.downloadId, url);
+          }
         } else {
           throw new Error(result.error || 'Failed to start download');
         }
@@ -184,6 +192,7 @@ declare global {
         success: boolean;
         message?: string;
         error?: string;
+        downloadId?: string;
       }>;
       showInFolder: (filename: string) => Promise<{
         success: boolean;
@@ -192,5 +201,6 @@ declare global {
       }>;
     };
     downloadProgressCallback?: (progress: SubprocessProgress) => void;
+    downloadProgressCallbacks?: Record<string, (progress: SubprocessProgress) => void>;
   }
 }
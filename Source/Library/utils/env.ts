@@ -1,13 +1,27 @@
 // Environment configuration utility
 // Centralized place for all environment variables
 
+/**
+ * Whether the page is served by the desktop app's `miko://` protocol
+ * (WebView2 exposes it as http://miko.app/)
+ */
+export const isDesktopProtocol = (): boolean => {
+  if (typeof window === 'undefined') return false;
+  return window.location.protocol === 'miko:' || window.location.host === 'miko.app';
+};
+
+const getServerUrl = (): string => {
+  return import.meta.env.VITE_API_URL || 'http://localhost:5669';
+};
+
 /**
  * Get the API base URL from environment variables
- * Falls back to localhost:5669 if not set
+ * Falls back to localhost:5669 if not set. Inside the desktop app API calls
+ * stay same-origin; the app forwards `/api/*` to the server.
  */
 export const getApiUrl = (): string => {
-  const apiUrl = import.meta.env.VITE_API_URL || 'http://localhost:5669';
-  return apiUrl;
+  if (isDesktopProtocol()) return '';
+  return getServerUrl();
 };
 
 /**
@@ -15,7 +29,8 @@ export const getApiUrl = (): string => {
  * Converts http:// to ws:// and https:// to wss://
  */
 export const getWebSocketUrl = (): string => {
-  const apiUrl = getApiUrl();
+  // WebSockets aren't subject to CORS and can't go through the custom protocol
+  const apiUrl = getServerUrl();
   const wsUrl = apiUrl.replace(/^https/, 'wss');
   return `${wsUrl}/ws`;
 };